@@ -0,0 +1,31 @@
+//! Build script: with the `capi` feature, regenerate the C header of the exported
+//! functions, `include/projzst.h`, from `src/capi.rs`
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+/// Write `include/projzst.h` with cbindgen, leaving it untouched when nothing changed
+#[cfg(feature = "capi")]
+fn generate_header() {
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").expect("set by cargo"));
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("PROJZST_H".to_string()),
+        header: Some("/* Generated by cbindgen from src/capi.rs, do not edit */".to_string()),
+        cpp_compat: true,
+        documentation_style: cbindgen::DocumentationStyle::C99,
+        ..cbindgen::Config::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/capi.rs"))
+        .generate()
+        .expect("src/capi.rs declares C functions cbindgen understands")
+        .write_to_file(crate_dir.join("include/projzst.h"));
+}
@@ -0,0 +1,90 @@
+/* Generated by cbindgen from src/capi.rs, do not edit */
+
+#ifndef PROJZST_H
+#define PROJZST_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The call succeeded
+#define PROJZST_OK 0
+
+// Any other failure, panics included
+#define PROJZST_ERROR_OTHER 1
+
+// The input is not a valid archive
+#define PROJZST_ERROR_INVALID_FORMAT 2
+
+// File system failure
+#define PROJZST_ERROR_IO 3
+
+// Unknown metadata fields were refused
+#define PROJZST_ERROR_UNKNOWN_FIELDS 4
+
+// A check on the archive or its output failed
+#define PROJZST_ERROR_VERIFICATION 5
+
+// An argument is invalid, a null pointer or malformed JSON included
+#define PROJZST_ERROR_INVALID_INPUT 64
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Pack a directory into a .pjz file
+//
+// # Arguments
+// * `source_dir` - Directory to pack
+// * `output_file` - Path of the .pjz file to write
+// * `metadata_json` - Metadata as a JSON object (`name`, `auth`, `fmt`, `ed`, `ver`,
+//   `desc`, `extra`, ...), or null for empty metadata
+// * `level` - Zstd compression level, 1-22 (0 stores the payload uncompressed)
+//
+// # Safety
+// Non-null pointers must point to NUL-terminated strings.
+int projzst_pack(const char *source_dir,
+                 const char *output_file,
+                 const char *metadata_json,
+                 int level);
+
+// Unpack a .pjz file into a directory, writing `metadata.json` next to it
+// Unknown metadata fields are ignored.
+//
+// # Arguments
+// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+// * `output_dir` - Directory to extract to, created if needed
+//
+// # Safety
+// Non-null pointers must point to NUL-terminated strings.
+int projzst_unpack(const char *input_file, const char *output_dir);
+
+// Read the metadata of a .pjz file as a JSON object
+// Returns a string to release with [`projzst_string_free`], or null on error.
+//
+// # Arguments
+// * `input_file` - Path to the .pjz file
+//
+// # Safety
+// `input_file`, when not null, must point to a NUL-terminated string.
+char *projzst_read_metadata_json(const char *input_file);
+
+// Release a string returned by this library; null is ignored
+//
+// # Safety
+// `s` must come from this library and not have been released before.
+void projzst_string_free(char *s);
+
+// Code of the last call on this thread, [`PROJZST_OK`] when it succeeded
+int projzst_last_error_code(void);
+
+// Message of the last error on this thread, empty when the last call succeeded
+// The string belongs to the library and is valid until the next call on this thread.
+const char *projzst_last_error_message(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PROJZST_H */
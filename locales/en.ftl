@@ -0,0 +1,160 @@
+# Messages of the projzst command-line tool, English (the fallback of every locale)
+
+## Prefixes
+
+# $kind is the translated `error-kind-*` message of the error, which English leaves out
+error = Error: { $message }
+warning = Warning: { $message }
+
+## Kinds of errors
+
+error-kind-other = error
+error-kind-invalid-format = not a valid archive
+error-kind-io = file system error
+error-kind-unknown-fields = unknown metadata fields
+error-kind-verification = check failed
+error-kind-invalid-input = invalid argument
+
+## Warnings of pack and unpack reports
+
+warning-oversized-file = skipped { $path } ({ $size } bytes): over the size limit
+warning-special-entry = skipped { $entry } { $path }
+warning-possible-secret = possible secret in { $location }: { $secret }
+warning-unknown-field = unknown metadata field { $field } ignored
+warning-sanitized-path = { $path } extracted as { $sanitized }
+warning-clamped-mtime = { $path } has a modification time in the future, set to now
+warning-nested-left-packed = { $path } left packed: { $reason }
+warning-compat-dropped = { $option } is not supported by --compat { $compat }, dropped
+warning-watch-failed = pack failed, waiting for changes: { $error }
+
+## Other warnings
+
+warning-io-uring-unavailable = --io-uring ignored: projzst was built without the `io-uring` feature
+warning-path-collision = { $first } and { $second } are the same file here ({ $kind }), the latter wins
+warning-truncated-frame = frame at offset { $offset } is cut short or malformed
+warning-damaged-entry = { $entry } does not decode, nor any entry after it
+warning-lost-entries = lost { $entry } and any entry after it: { $error }
+warning-payload-damaged = payload damaged: { $error }
+warning-catalog-skipped = skipped { $path }: { $error }
+warning-prune-kept = kept { $path }: { $error }
+warning-confine-no-landlock = --confine: this kernel has no Landlock, file system access is not restricted
+warning-confine-no-seccomp = --confine: no seccomp filter for { $arch }, system calls are not restricted
+no-signature = No signature: { $path }
+no-timestamp = No timestamp: { $path }
+no-attachment = No attachment named { $name }
+
+## Status lines
+
+packed = Successfully packed: { $path }
+packed-stdout = Successfully packed to stdout
+watching = Watching { $path } for changes
+repacked = Repacked { $path } after { $changes } change(s): { $files } files, { $size }
+unpacked = Successfully unpacked: { $path }
+unpacked-nested = Unpacked { $count } nested archives
+unpacked-partially = Partially unpacked: { $path } ({ $count } entries extracted)
+post-verified = Written files match the archive
+no-metadata = No metadata: input is a plain tar.zst
+package = Package: { $name } v{ $version }
+synced = Synced { $path }: { $added } added, { $updated } updated, { $removed } removed, { $unchanged } unchanged
+converted = Successfully converted: { $path }
+name-matches = Name matches { $template }
+verified = Verified: { $path } ({ $count } entries)
+merged = Successfully merged { $count } archives: { $path }
+estimated = Would pack { $files } files ({ $size }) into { $path }: about { $estimated } ({ $ratio }x, { $sampled } sampled)
+signed = Signed { $path }
+signed-with-key = Signed { $path } with key { $key }
+timestamped = Timestamped { $path } at { $time }
+wrote = Wrote { $path }
+checksum-matches = Checksum matches { $path }
+signed-by-key = Signed by key { $key }
+timestamped-at = Timestamped at { $time }
+recovered = Recovered { $count } entries ({ $size }) into { $path }
+lint-summary = { $errors } error(s), { $warnings } warning(s)
+migrated = Migrated: { $input } -> { $output }
+migrate-copied = Already in the current layout, copied: { $input } -> { $output }
+recompressed = Repacked { $path }: { $before } -> { $after }
+optimized = Optimized { $path }: { $before } -> { $after } ({ $change }%)
+packed-set = Packed { $count } archives sharing { $chunks } chunks ({ $size }): { $path }
+bundled = Bundled { $count } archives ({ $size }): { $path }
+extracted-member = Extracted { $member } to { $path }
+binary-match = Binary file { $path } matches
+bench-sample = Sample: { $size } in { $files } files
+wrote-sbom = Wrote { $format } SBOM of { $input } to { $path }
+mounted = Mounted { $path } on { $mountpoint } (unmount or press Ctrl-C to stop)
+pushed = Pushed { $path } to { $url }
+pulled = Pulled { $package } to { $path }
+cataloged = Cataloged { $count } archives into { $path } ({ $read } read, { $unchanged } unchanged, { $removed } removed)
+trained-dictionary = Trained dictionary { $id } ({ $size } bytes, { $samples } samples) into { $path }
+would-remove = Would remove { $path }
+removed = Removed { $path }
+would-prune = Would remove { $count } archives ({ $size }), kept { $kept }
+pruned = Removed { $count } archives ({ $size }), kept { $kept }
+serving = Serving { $path } on { $url } (press Ctrl-C to stop)
+removed-foreign-frames = Removed { $count } foreign frames
+no-provenance = No provenance: { $path }
+generated-key = Generated key { $name } ({ $key })
+trusted-key = Trusted key { $name } ({ $key })
+man-pages-written = Man pages written to: { $path }
+no-comment = No comment: { $path }
+comment-set = Comment set: { $path }
+comment-removed = Comment removed: { $path }
+no-icon = No icon: { $path }
+icon-saved = Icon saved: { $path } ({ $media_type })
+unknown-media-type = unknown type
+icon-set = Icon set: { $path }
+icon-removed = Icon removed: { $path }
+attached = Attached { $name } ({ $size } bytes): { $path }
+attachment-saved = Attachment saved: { $path } ({ $size } bytes)
+attachment-removed = Attachment removed: { $name }
+metadata-identical = Metadata is identical
+appended-revision = Appended metadata revision { $revision }: { $path }
+bumped = Bumped { $path } from { $previous } to { $version }
+metadata-dumped = Metadata dumped: { $path } ({ $size } bytes in { $frames } frame(s))
+diff-summary = { $added } added, { $removed } removed, { $modified } modified
+metadata-saved = Metadata saved to: { $path }
+
+## Summaries of info, stat and stats
+
+field-name = Name: { $value }
+field-author = Author: { $value }
+field-version = Version: { $value }
+field-format = Format: { $value }
+field-format-edition = Format: { $value } ({ $edition })
+field-description = Description: { $value }
+field-license = License: { $value }
+field-homepage = Homepage: { $value }
+field-repository = Repository: { $value }
+field-keywords = Keywords: { $value }
+field-dependencies = Dependencies: { $value }
+field-extra = Extra: { $value }
+stat-file-size = File size: { $size }
+stat-metadata-size = Metadata size: { $size }
+stat-skippable-frames = Skippable frames: { $count }
+stat-payload-size = Payload size: { $size }
+stat-zstd-frames = Zstd frames: { $count } (window up to { $window }, { $checksums } with checksum)
+stat-entries = Entries: { $count }
+stat-uncompressed-size = Uncompressed size: { $size }
+stat-largest = Largest files by compressed size:
+stat-largest-estimated = Largest files by compressed size (estimated):
+stats =
+    Files: { $files }
+    Original size: { $original }
+    Compressed size: { $compressed }
+    Ratio: { $ratio }x
+stats-deduplicated = Deduplicated: { $size }
+stats-resumed = Resumed: { $count } entries from the checkpoint
+
+
+## Prompts of pack --interactive
+
+wizard-name = Package name
+wizard-author = Author
+wizard-version = Version
+wizard-format = Format identifier
+wizard-edition = Format edition
+wizard-description = Description
+wizard-output = Output file
+wizard-preview = { $count } files ({ $size }), about { $estimated } packed:
+wizard-more = ... and { $count } more
+wizard-confirm = Write { $path }? [Y/n]
+wizard-cancelled = Nothing written
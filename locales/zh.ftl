@@ -0,0 +1,159 @@
+# Messages of the projzst command-line tool, Simplified Chinese
+
+## Prefixes
+
+error = 错误（{ $kind }）：{ $message }
+warning = 警告：{ $message }
+
+## Kinds of errors
+
+error-kind-other = 错误
+error-kind-invalid-format = 不是有效的归档
+error-kind-io = 文件系统错误
+error-kind-unknown-fields = 未知的元数据字段
+error-kind-verification = 校验失败
+error-kind-invalid-input = 参数无效
+
+## Warnings of pack and unpack reports
+
+warning-oversized-file = 已跳过 { $path }（{ $size } 字节）：超出大小限制
+warning-special-entry = 已跳过{ $entry } { $path }
+warning-possible-secret = { $location } 中可能含有密钥：{ $secret }
+warning-unknown-field = 已忽略未知的元数据字段 { $field }
+warning-sanitized-path = { $path } 已解包为 { $sanitized }
+warning-clamped-mtime = { $path } 的修改时间在未来，已设为当前时间
+warning-nested-left-packed = { $path } 保持未解包：{ $reason }
+warning-compat-dropped = --compat { $compat } 不支持 { $option }，已舍弃
+warning-watch-failed = 打包失败，等待更改：{ $error }
+
+## Other warnings
+
+warning-io-uring-unavailable = 已忽略 --io-uring：projzst 构建时未启用 `io-uring` 特性
+warning-path-collision = { $first } 与 { $second } 在此处是同一个文件（{ $kind }），以后者为准
+warning-truncated-frame = 偏移 { $offset } 处的帧被截断或格式有误
+warning-damaged-entry = { $entry } 及其后的条目均无法解码
+warning-lost-entries = 丢失了 { $entry } 及其后的条目：{ $error }
+warning-payload-damaged = 数据已损坏：{ $error }
+warning-catalog-skipped = 已跳过 { $path }：{ $error }
+warning-prune-kept = 已保留 { $path }：{ $error }
+warning-confine-no-landlock = --confine：此内核不支持 Landlock，文件系统访问不受限制
+warning-confine-no-seccomp = --confine：{ $arch } 没有 seccomp 过滤器，系统调用不受限制
+no-signature = 没有签名：{ $path }
+no-timestamp = 没有时间戳：{ $path }
+no-attachment = 没有名为 { $name } 的附件
+
+## Status lines
+
+packed = 打包成功：{ $path }
+packed-stdout = 已打包到标准输出
+watching = 正在监视 { $path } 的更改
+repacked = 已重新打包 { $path }（{ $changes } 处更改）：{ $files } 个文件，{ $size }
+unpacked = 解包成功：{ $path }
+unpacked-nested = 已解包 { $count } 个嵌套归档
+unpacked-partially = 部分解包：{ $path }（已解出 { $count } 个条目）
+post-verified = 写入的文件与归档一致
+no-metadata = 没有元数据：输入是普通的 tar.zst
+package = 包：{ $name } v{ $version }
+synced = 已同步 { $path }：新增 { $added }，更新 { $updated }，删除 { $removed }，未变 { $unchanged }
+converted = 转换成功：{ $path }
+name-matches = 名称符合 { $template }
+verified = 校验通过：{ $path }（{ $count } 个条目）
+merged = 已合并 { $count } 个归档：{ $path }
+estimated = 将把 { $files } 个文件（{ $size }）打包到 { $path }：约 { $estimated }（{ $ratio } 倍，抽样 { $sampled }）
+signed = 已签名 { $path }
+signed-with-key = 已用密钥 { $key } 签名 { $path }
+timestamped = 已为 { $path } 加盖时间戳 { $time }
+wrote = 已写入 { $path }
+checksum-matches = 校验和与 { $path } 一致
+signed-by-key = 签名密钥 { $key }
+timestamped-at = 时间戳 { $time }
+recovered = 已恢复 { $count } 个条目（{ $size }）到 { $path }
+lint-summary = { $errors } 个错误，{ $warnings } 个警告
+migrated = 已迁移：{ $input } -> { $output }
+migrate-copied = 已是当前布局，已复制：{ $input } -> { $output }
+recompressed = 已重新压缩 { $path }：{ $before } -> { $after }
+optimized = 已优化 { $path }：{ $before } -> { $after }（{ $change }%）
+packed-set = 已打包 { $count } 个归档，共享 { $chunks } 个块（{ $size }）：{ $path }
+bundled = 已捆绑 { $count } 个归档（{ $size }）：{ $path }
+extracted-member = 已将 { $member } 解出到 { $path }
+binary-match = 二进制文件 { $path } 匹配
+bench-sample = 样本：{ $files } 个文件，{ $size }
+wrote-sbom = 已将 { $input } 的 { $format } SBOM 写入 { $path }
+mounted = 已将 { $path } 挂载到 { $mountpoint }（卸载或按 Ctrl-C 停止）
+pushed = 已将 { $path } 推送到 { $url }
+pulled = 已将 { $package } 拉取到 { $path }
+cataloged = 已将 { $count } 个归档编入 { $path }（读取 { $read }，未变 { $unchanged }，删除 { $removed }）
+trained-dictionary = 已训练字典 { $id }（{ $size } 字节，{ $samples } 个样本）到 { $path }
+would-remove = 将删除 { $path }
+removed = 已删除 { $path }
+would-prune = 将删除 { $count } 个归档（{ $size }），保留 { $kept } 个
+pruned = 已删除 { $count } 个归档（{ $size }），保留 { $kept } 个
+serving = 正在 { $url } 上提供 { $path }（按 Ctrl-C 停止）
+removed-foreign-frames = 已删除 { $count } 个外来帧
+no-provenance = 没有来源证明：{ $path }
+generated-key = 已生成密钥 { $name }（{ $key }）
+trusted-key = 已信任密钥 { $name }（{ $key }）
+man-pages-written = 手册页已写入：{ $path }
+no-comment = 没有注释：{ $path }
+comment-set = 已设置注释：{ $path }
+comment-removed = 已删除注释：{ $path }
+no-icon = 没有图标：{ $path }
+icon-saved = 图标已保存：{ $path }（{ $media_type }）
+unknown-media-type = 未知类型
+icon-set = 已设置图标：{ $path }
+icon-removed = 已删除图标：{ $path }
+attached = 已附加 { $name }（{ $size } 字节）：{ $path }
+attachment-saved = 附件已保存：{ $path }（{ $size } 字节）
+attachment-removed = 已删除附件：{ $name }
+metadata-identical = 元数据相同
+appended-revision = 已追加元数据修订 { $revision }：{ $path }
+bumped = 已将 { $path } 从 { $previous } 升级到 { $version }
+metadata-dumped = 元数据已导出：{ $path }（{ $size } 字节，{ $frames } 个帧）
+diff-summary = 新增 { $added }，删除 { $removed }，修改 { $modified }
+metadata-saved = 元数据已保存到：{ $path }
+
+## Summaries of info, stat and stats
+
+field-name = 名称：{ $value }
+field-author = 作者：{ $value }
+field-version = 版本：{ $value }
+field-format = 格式：{ $value }
+field-format-edition = 格式：{ $value }（{ $edition }）
+field-description = 描述：{ $value }
+field-license = 许可证：{ $value }
+field-homepage = 主页：{ $value }
+field-repository = 仓库：{ $value }
+field-keywords = 关键词：{ $value }
+field-dependencies = 依赖：{ $value }
+field-extra = 其他：{ $value }
+stat-file-size = 文件大小：{ $size }
+stat-metadata-size = 元数据大小：{ $size }
+stat-skippable-frames = 可跳过帧：{ $count }
+stat-payload-size = 数据大小：{ $size }
+stat-zstd-frames = Zstd 帧：{ $count }（窗口最大 { $window }，{ $checksums } 个带校验和）
+stat-entries = 条目：{ $count }
+stat-uncompressed-size = 解压后大小：{ $size }
+stat-largest = 压缩后最大的文件：
+stat-largest-estimated = 压缩后最大的文件（估算）：
+stats =
+    文件：{ $files }
+    原始大小：{ $original }
+    压缩后大小：{ $compressed }
+    压缩比：{ $ratio } 倍
+stats-deduplicated = 去重：{ $size }
+stats-resumed = 已从检查点恢复 { $count } 个条目
+
+
+## Prompts of pack --interactive
+
+wizard-name = 包名
+wizard-author = 作者
+wizard-version = 版本
+wizard-format = 格式标识
+wizard-edition = 格式版本
+wizard-description = 描述
+wizard-output = 输出文件
+wizard-preview = { $count } 个文件（{ $size }），打包后约 { $estimated }：
+wizard-more = ……另有 { $count } 个
+wizard-confirm = 写入 { $path }？[Y/n]
+wizard-cancelled = 未写入任何文件
@@ -0,0 +1,188 @@
+//! Public API facade
+//!
+//! Everything meant for library users is listed here, grouped by role; the modules that
+//! implement it are private to the crate. The crate root re-exports all of it, so
+//! `projzst::pack` and `projzst::api::operations::pack` name the same function.
+//!
+//! Options, reports and errors are `#[non_exhaustive]`: new variants and fields can be
+//! added without a breaking release. Build them through their constructors or `Default`
+//! and keep a wildcard arm when matching on them.
+
+/// Pack, unpack, inspect and transform archives
+pub mod operations {
+    pub use crate::attach::{add_attachment, attachment, attachments, remove_attachment};
+    pub use crate::bench::bench;
+    pub use crate::builder::{info, pack, pack_to_writer};
+    pub use crate::builder::{read_metadata, read_metadata_as, read_metadata_from};
+    pub use crate::builder::{unpack, unpack_from_reader};
+    pub use crate::bump::bump_version;
+    pub use crate::bundle::{bundle_members, extract_member};
+    pub use crate::catalog::{build_catalog, catalog_dictionaries, train_catalog_dictionary};
+    pub use crate::checksum::{checksum_path, verify_checksum_file, write_checksum_file};
+    pub use crate::collision::path_collisions;
+    pub use crate::convert::{export_archive, import_archive, sidecar_path};
+    pub use crate::deps::resolve_dependencies;
+    pub use crate::diff::{diff, diff_archive_metadata, diff_metadata, entry_digests};
+    pub use crate::entries::entries;
+    pub use crate::frames::{
+        comment, frames, metadata_frames, set_comment, strip_foreign_frames, write_frame,
+    };
+    pub use crate::grep::grep;
+    pub use crate::icon::{icon, set_icon};
+    pub use crate::incremental::unpack_incremental;
+    pub use crate::legacy::{detect_layout, migrate};
+    pub use crate::lint::lint;
+    pub use crate::listing::list_entries;
+    pub use crate::memory::{pack_to_vec, unpack_from_slice};
+    pub use crate::merge::merge;
+    pub use crate::naming::{auto_file_name, expected_file_name, verify_naming};
+    pub use crate::packset::pack_set;
+    pub use crate::preview::{preview_entry, write_entry};
+    pub use crate::provenance::provenance;
+    pub use crate::prune::prune;
+    pub use crate::repair::repair;
+    pub use crate::revisions::trailing_metadata;
+    pub use crate::revisions::{append_metadata, metadata_history, read_metadata_revision};
+    pub use crate::sbom::sbom;
+    pub use crate::search::search;
+    pub use crate::signing::{sign_archive, signature, signed_digest};
+    pub use crate::stat::stat;
+    pub use crate::sync::{sync, sync_from_reader};
+    pub use crate::timestamp::{timestamp, verify_timestamp};
+    pub use crate::usage::disk_usage;
+    pub use crate::validate::validate;
+
+    #[cfg(feature = "crypto")]
+    pub use crate::signing::{sign_archive_with_key, verify_signature};
+
+    #[cfg(feature = "http")]
+    pub use crate::http::{preview_entry_url, read_metadata_url, sync_url, write_entry_url};
+    #[cfg(feature = "http")]
+    pub use crate::registry::parse_package_ref;
+    #[cfg(feature = "http")]
+    pub use crate::timestamp::timestamp_archive;
+
+    #[cfg(all(feature = "fuse", target_os = "linux"))]
+    pub use crate::mount::mount;
+
+    #[cfg(feature = "s3")]
+    pub use crate::s3::{pack_from_s3, pack_from_s3_to_writer};
+    #[cfg(feature = "s3")]
+    pub use crate::s3::{unpack_from_reader_to_s3, unpack_to_s3};
+
+    #[cfg(feature = "serve")]
+    pub use crate::serve::serve;
+}
+
+/// Builders, settings and the parsers turning CLI-style strings into them
+pub mod options {
+    pub use crate::builder::{Packer, ReadOptions};
+    pub use crate::checksum::ChecksumAlgorithm;
+    pub use crate::codec::PayloadCodec;
+    pub use crate::concurrency::Concurrency;
+    pub use crate::convert::ArchiveFormat;
+    pub use crate::dictionary::{Dictionary, DEFAULT_DICTIONARY_SIZE};
+    pub use crate::frames::parse_metadata_magic;
+    pub use crate::grep::GrepQuery;
+    pub use crate::lint::{LintConfig, LintPlugin, Severity};
+    pub use crate::merge::ConflictPolicy;
+    pub use crate::metadata::validate::MetadataPolicy;
+    pub use crate::metadata::{parse_extra_pair, CompatProfile, ExtraFormat};
+    pub use crate::metadata::{IgnoreUnknown, MetadataEncoding, MetadataFormat};
+    pub use crate::mtime::MtimePolicy;
+    pub use crate::packset::SetMember;
+    pub use crate::profile::CompressionProfile;
+    pub use crate::prune::RetentionPolicy;
+    pub use crate::remap::{EntryAction, PathTransform, WindowsNamePolicy};
+    pub use crate::revisions::MetadataPlacement;
+    pub use crate::sbom::SbomFormat;
+    pub use crate::schema::{ExtraSchema, SchemaRegistry};
+    pub use crate::search::MetadataQuery;
+    pub use crate::secrets::SecretPolicy;
+    pub use crate::string_utils::{convert, parse_size, IntoOpStr};
+    pub use crate::tar_format::TarFormat;
+    pub use crate::template::TemplateVars;
+    pub use crate::walk::{EntryOrder, OversizePolicy, SpecialPolicy};
+    pub use crate::watch::SourceWatcher;
+    pub use crate::{DEFAULT_BUFFER_SIZE, DEFAULT_ZSTD_LEVEL};
+
+    #[cfg(feature = "crypto")]
+    pub use crate::signing::{PublicKey, SecretKey};
+}
+
+/// The metadata model, its typed `extra` namespaces and header attachments
+pub mod metadata {
+    pub use crate::attach::{Attachment, MAX_ATTACHMENT_SIZE};
+    pub use crate::builder::{FORMAT_VERSION, SUPPORTED_FORMAT_VERSIONS};
+    pub use crate::builder::{MAX_DECOMPRESSED_METADATA_SIZE, MAX_METADATA_SIZE};
+    pub use crate::bundle::{BundleManifest, BundleMember};
+    pub use crate::deps::{Dependency, Version, VersionPart, VersionReq};
+    pub use crate::frames::MAX_FRAME_SIZE;
+    pub use crate::icon::{Icon, MAX_ICON_SIZE};
+    pub use crate::metadata::ext::{BuildInfo, CiInfo, ExtraNamespace, VcsInfo};
+    pub use crate::metadata::validate::{FieldError, MetadataBuilder};
+    pub use crate::metadata::Metadata;
+    pub use crate::provenance::Provenance;
+}
+
+/// Results returned by comparisons, lint runs, plugins and frame reads
+pub mod reports {
+    pub use crate::bench::{BenchReport, BenchResult};
+    pub use crate::catalog::{Catalog, CatalogDictionary, CatalogEntry, CatalogReport};
+    pub use crate::collision::{CollisionKind, PathCollision};
+    pub use crate::diff::{ArchiveDiff, EntryDigest, FieldChange, MetadataDiff, ModifiedEntry};
+    pub use crate::encoding::TextEncoding;
+    pub use crate::entries::{Entries, Entry};
+    pub use crate::estimate::PackEstimate;
+    pub use crate::frames::{FrameKind, RawFrame};
+    pub use crate::grep::{GrepMatch, GrepReport};
+    pub use crate::legacy::ArchiveLayout;
+    pub use crate::lint::{LintFinding, LintManifest, LintReport, ManifestEntry};
+    pub use crate::listing::{ArchiveEntry, EntryKind};
+    pub use crate::memory::MemoryFile;
+    pub use crate::packset::PackSetReport;
+    pub use crate::preview::{ContentKind, EntryPreview};
+    pub use crate::prune::PruneReport;
+    pub use crate::repack::RepackReport;
+    pub use crate::repair::RepairReport;
+    pub use crate::report::{ExtensionStats, PackReport, SkippedFile, SpecialEntry, SpecialKind};
+    pub use crate::report::{UnpackReport, Warning};
+    pub use crate::revisions::MetadataRevision;
+    pub use crate::schema::SchemaViolation;
+    pub use crate::search::SearchHit;
+    pub use crate::secrets::{SecretFinding, SecretKind};
+    pub use crate::stat::{ArchiveStats, EntryCompression, ZstdFrameStats};
+    pub use crate::sync::SyncReport;
+    pub use crate::timestamp::Timestamp;
+    pub use crate::usage::DirectoryUsage;
+    pub use crate::validate::ValidationReport;
+
+    /// Ids of the built-in lint rules
+    pub mod rules {
+        pub use crate::lint::{ABSOLUTE_SYMLINK, DEVICE_NODE, JUNK_FILE, LARGE_FILE};
+        pub use crate::lint::{INCOMPLETE_METADATA, INVALID_VERSION, LARGE_EXTRA};
+        pub use crate::lint::{MISSING_LICENSE, MISSING_NAME, MISSING_VERSION};
+        pub use crate::lint::{UNSAFE_PATH, WORLD_WRITABLE};
+    }
+}
+
+/// Where archives are read from and written to
+pub mod storage {
+    pub use crate::storage::{LocalStorage, Location, Place, Storage, StorageEntry, StoragePath};
+
+    #[cfg(feature = "http")]
+    pub use crate::http::HttpStorage;
+    #[cfg(feature = "http")]
+    pub use crate::registry::Registry;
+
+    #[cfg(feature = "mmap")]
+    pub use crate::mapped::{FrameSlice, MappedArchive};
+
+    #[cfg(feature = "s3")]
+    pub use crate::s3::{S3Client, S3Location, S3Object, S3Storage};
+}
+
+/// The error type shared by every operation
+pub mod errors {
+    pub use crate::errors::{ErrorKind, ProjzstError, Result};
+}
@@ -0,0 +1,144 @@
+//! Named file attachments
+//!
+//! Small files that travel with a package but are not part of its tree (a changelog, the
+//! license, a signature bundle) can each be stored in an `attachment` frame next to the
+//! metadata, and read back without decompressing the payload. The frame payload is the
+//! file name length as a little-endian `u16`, the UTF-8 name, then the file content.
+
+use std::path::Path;
+
+use crate::errors::{ProjzstError, Result};
+use crate::frames::{frames, rewrite_frames, FrameKind, RawFrame};
+
+/// Largest attachment accepted, in bytes
+pub const MAX_ATTACHMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Longest attachment name, in bytes
+const MAX_ATTACHMENT_NAME: usize = 255;
+
+/// A named file attached to an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Attachment {
+    /// File name, without directories
+    pub name: String,
+    /// File content
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Check that `name` is a plain file name and `data` at most [`MAX_ATTACHMENT_SIZE`]
+    /// bytes
+    ///
+    /// # Arguments
+    /// * `name` - File name, e.g. `CHANGELOG.md`
+    /// * `data` - File content
+    pub fn new(name: impl Into<String>, data: Vec<u8>) -> Result<Self> {
+        let name = name.into();
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.len() > MAX_ATTACHMENT_NAME
+            || name.contains(['/', '\\', '\0'])
+        {
+            return Err(ProjzstError::InvalidAttachment(format!(
+                "{name:?} is not a file name of at most {MAX_ATTACHMENT_NAME} bytes"
+            )));
+        }
+        if data.len() > MAX_ATTACHMENT_SIZE {
+            return Err(ProjzstError::InvalidAttachment(format!(
+                "{} bytes exceeds the {MAX_ATTACHMENT_SIZE} byte limit",
+                data.len()
+            )));
+        }
+        Ok(Self { name, data })
+    }
+
+    /// Internal helper: the payload of the frame holding the attachment
+    fn to_payload(&self) -> Vec<u8> {
+        let mut payload = (self.name.len() as u16).to_le_bytes().to_vec();
+        payload.extend_from_slice(self.name.as_bytes());
+        payload.extend_from_slice(&self.data);
+        payload
+    }
+
+    /// Internal helper: parse a frame payload, `None` when it is not an attachment
+    fn from_payload(payload: &[u8]) -> Option<Self> {
+        let len = usize::from(u16::from_le_bytes(payload.get(..2)?.try_into().ok()?));
+        let name = std::str::from_utf8(payload.get(2..2 + len)?).ok()?;
+        Self::new(name, payload[2 + len..].to_vec()).ok()
+    }
+}
+
+/// Read every file attached to a .pjz file, in frame order
+/// Attachment frames that do not parse are skipped
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn attachments<P: AsRef<Path>>(input_file: P) -> Result<Vec<Attachment>> {
+    Ok(frames(input_file)?
+        .iter()
+        .filter(|frame| frame.kind() == FrameKind::Attachment)
+        .filter_map(|frame| Attachment::from_payload(&frame.payload))
+        .collect())
+}
+
+/// Read one file attached to a .pjz file, if there is one of that name
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `name` - File name the attachment was added under
+pub fn attachment<P: AsRef<Path>>(input_file: P, name: &str) -> Result<Option<Attachment>> {
+    Ok(attachments(input_file)?
+        .into_iter()
+        .find(|attachment| attachment.name == name))
+}
+
+/// Attach a file to a .pjz file in place, replacing any attachment of the same name
+/// The metadata and payload are left untouched
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `attachment` - File to attach
+pub fn add_attachment<P: AsRef<Path>>(input_file: P, attachment: &Attachment) -> Result<()> {
+    rewrite_frames(input_file.as_ref(), |frames| {
+        let position = frames
+            .iter()
+            .position(|frame| is_named(frame, &attachment.name))
+            .or_else(|| {
+                frames
+                    .iter()
+                    .rposition(|frame| frame.kind() == FrameKind::Attachment)
+                    .map(|last| last + 1)
+            })
+            .unwrap_or(frames.len());
+        frames.retain(|frame| !is_named(frame, &attachment.name));
+        let frame = RawFrame {
+            magic: FrameKind::Attachment.magic(),
+            payload: attachment.to_payload(),
+        };
+        frames.insert(position.min(frames.len()), frame);
+    })
+}
+
+/// Remove a file attached to a .pjz file in place
+/// Returns whether an attachment of that name was there
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `name` - File name the attachment was added under
+pub fn remove_attachment<P: AsRef<Path>>(input_file: P, name: &str) -> Result<bool> {
+    if attachment(input_file.as_ref(), name)?.is_none() {
+        return Ok(false);
+    }
+    rewrite_frames(input_file.as_ref(), |frames| {
+        frames.retain(|frame| !is_named(frame, name));
+    })?;
+    Ok(true)
+}
+
+/// Internal helper: whether a frame holds the attachment called `name`
+fn is_named(frame: &RawFrame, name: &str) -> bool {
+    frame.kind() == FrameKind::Attachment
+        && Attachment::from_payload(&frame.payload).is_some_and(|a| a.name == name)
+}
@@ -0,0 +1,137 @@
+//! Compression level benchmark
+//!
+//! [`bench`] reads a sample of a source directory as the tar stream a pack would write,
+//! then compresses it at each of the given levels with each of the given thread counts,
+//! measuring time, ratio and the memory taken by the compressor. The numbers only hold
+//! for content like the sample, which is why it is taken from the directory to pack.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use zstd::zstd_safe::{self, CCtx, CParameter};
+
+use crate::concurrency::Concurrency;
+use crate::errors::{ProjzstError, Result};
+use crate::payload::{check_compression_level, STORE_LEVEL};
+use crate::walk::{walk_source, WalkOptions};
+
+/// Outcome of compressing the sample with one level and thread count
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BenchResult {
+    /// Zstd compression level
+    pub level: i32,
+    /// Threads compressing, as given to [`Concurrency::threads`]
+    pub threads: usize,
+    /// Compressed size of the sample in bytes
+    pub compressed_size: u64,
+    /// Wall-clock time taken to compress the sample
+    pub duration: Duration,
+    /// Memory held by the compressor, in bytes
+    pub memory: usize,
+}
+
+/// Sample and results of a benchmark
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BenchReport {
+    /// Size of the sample tar stream in bytes
+    pub sample_size: u64,
+    /// Number of files in the sample (the last one possibly cut short)
+    pub files: usize,
+    /// One result per level and thread count, levels first
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchResult {
+    /// Sample size divided by compressed size
+    pub fn ratio(&self, sample_size: u64) -> f64 {
+        sample_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+/// Compress a sample of a source directory at every level of `levels` with every
+/// thread count of `threads`
+///
+/// # Arguments
+/// * `source_dir` - Directory to take the sample from, in pack order
+/// * `levels` - Zstd levels, 1-22 or negative for the fast levels
+/// * `threads` - Thread counts, `1` compressing on the calling thread
+/// * `sample_size` - Largest sample taken, in bytes
+pub fn bench<P: AsRef<Path>>(
+    source_dir: P,
+    levels: &[i32],
+    threads: &[usize],
+    sample_size: u64,
+) -> Result<BenchReport> {
+    for &level in levels {
+        if level == STORE_LEVEL {
+            return Err(ProjzstError::InvalidCompressionLevel(level));
+        }
+        check_compression_level(level)?;
+    }
+    let (sample, files) = read_sample(source_dir.as_ref(), sample_size)?;
+
+    let mut results = Vec::new();
+    for &level in levels {
+        for &thread_count in threads {
+            let workers = Concurrency::threads(thread_count).compression_workers();
+            let mut cctx = CCtx::create();
+            cctx.set_parameter(CParameter::CompressionLevel(level))
+                .map_err(zstd_error)?;
+            cctx.set_parameter(CParameter::NbWorkers(workers))
+                .map_err(zstd_error)?;
+            let mut compressed = Vec::with_capacity(zstd_safe::compress_bound(sample.len()));
+
+            let started = Instant::now();
+            let compressed_size = cctx
+                .compress2(&mut compressed, &sample)
+                .map_err(zstd_error)?;
+            results.push(BenchResult {
+                level,
+                threads: thread_count.max(1),
+                compressed_size: compressed_size as u64,
+                duration: started.elapsed(),
+                memory: cctx.sizeof(),
+            });
+        }
+    }
+    Ok(BenchReport {
+        sample_size: sample.len() as u64,
+        files,
+        results,
+    })
+}
+
+/// Internal helper: the tar stream of the first files of `source_dir` in pack order, up
+/// to `limit` bytes of content, and the number of files in it
+fn read_sample(source_dir: &Path, limit: u64) -> Result<(Vec<u8>, usize)> {
+    let mut tar_builder = tar::Builder::new(Vec::new());
+    let mut taken = 0;
+    let mut files = 0;
+    for (relative, path, info) in walk_source(source_dir, None, &WalkOptions::default())?.entries {
+        if !info.is_file() {
+            continue;
+        }
+        if taken >= limit {
+            break;
+        }
+        // The last file is cut to the limit
+        let size = info.len().min(limit - taken);
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&info);
+        header.set_size(size);
+        tar_builder.append_data(&mut header, &relative, File::open(&path)?.take(size))?;
+        taken += size;
+        files += 1;
+    }
+    Ok((tar_builder.into_inner()?, files))
+}
+
+/// Internal helper: the error for a zstd error code
+fn zstd_error(code: zstd_safe::ErrorCode) -> ProjzstError {
+    ProjzstError::Io(io::Error::other(zstd_safe::get_error_name(code)))
+}
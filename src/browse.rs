@@ -0,0 +1,298 @@
+//! Terminal archive browser of the command-line tool (`browse`, `tui` feature)
+//!
+//! The entries of an archive are shown one directory at a time, with the metadata and a
+//! preview of any file a key away. Marked files and directories are extracted with the
+//! same path filtering `unpack --transform` uses, so nothing else is written.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use projzst::{
+    list_entries, preview_entry, read_metadata, ArchiveEntry, EntryKind, IgnoreUnknown, Metadata,
+    ProjzstError, ReadOptions,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::format_size;
+
+/// Largest part of a file shown in its preview
+const PREVIEW_BYTES: usize = 64 * 1024;
+
+/// Key help shown in the status line of the entry list
+const HELP: &str = "enter open  backspace up  space mark  x extract  m metadata  q quit";
+
+/// One row of the directory shown
+struct Row {
+    name: String,
+    path: String,
+    directory: bool,
+    /// Size of the file, or of every file below the directory
+    size: u64,
+}
+
+/// What the main area shows
+enum View {
+    Entries,
+    /// Scrollable text under a title: the metadata or a file preview
+    Text {
+        title: String,
+        text: String,
+    },
+}
+
+/// State of the browser between key presses
+struct Browser {
+    input: PathBuf,
+    output: PathBuf,
+    entries: Vec<ArchiveEntry>,
+    metadata: Metadata,
+    /// Directory shown, `""` for the archive root
+    directory: String,
+    rows: Vec<Row>,
+    list: ListState,
+    /// Paths marked for extraction; a directory stands for everything below it
+    marked: BTreeSet<String>,
+    view: View,
+    scroll: u16,
+    status: String,
+    /// Entries extracted so far
+    extracted: usize,
+}
+
+/// Browse `input` until the user quits, extracting marked entries into `output`
+/// Returns the number of entries extracted
+pub fn run(input: &Path, output: &Path) -> Result<usize, ProjzstError> {
+    let mut browser = Browser {
+        input: input.to_path_buf(),
+        output: output.to_path_buf(),
+        entries: list_entries(input)?,
+        metadata: read_metadata(input, IgnoreUnknown::On)?,
+        directory: String::new(),
+        rows: Vec::new(),
+        list: ListState::default(),
+        marked: BTreeSet::new(),
+        view: View::Entries,
+        scroll: 0,
+        status: HELP.to_string(),
+        extracted: 0,
+    };
+    browser.load_rows();
+
+    let mut terminal = ratatui::init();
+    let result = browser.event_loop(&mut terminal);
+    ratatui::restore();
+    result.map(|()| browser.extracted)
+}
+
+impl Browser {
+    /// Internal helper: draw and handle keys until `q`
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), ProjzstError> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let View::Text { .. } = self.view {
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        self.scroll = self.scroll.saturating_add(1)
+                    }
+                    KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(20),
+                    KeyCode::PageDown => self.scroll = self.scroll.saturating_add(20),
+                    _ => self.view = View::Entries,
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Home => self.list.select_first(),
+                KeyCode::End => self.list.select_last(),
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.open()?,
+                KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.up(),
+                KeyCode::Char(' ') => self.toggle_mark(),
+                KeyCode::Char('m') => self.show_metadata()?,
+                KeyCode::Char('x') => self.extract()?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Internal helper: fill `rows` with the children of `directory`, directories first
+    fn load_rows(&mut self) {
+        let prefix = if self.directory.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.directory)
+        };
+        // Keyed by (file?, name) for the order; directories need no entry of their own
+        let mut rows: BTreeMap<(bool, String), Row> = BTreeMap::new();
+        for entry in &self.entries {
+            let Some(rest) = entry.path.strip_prefix(&prefix) else {
+                continue;
+            };
+            let (name, below) = match rest.split_once('/') {
+                Some((name, _)) => (name, true),
+                None => (rest, false),
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let directory = below || entry.kind == EntryKind::Directory;
+            let row = rows
+                .entry((!directory, name.to_string()))
+                .or_insert_with(|| Row {
+                    name: name.to_string(),
+                    path: format!("{prefix}{name}"),
+                    directory,
+                    size: 0,
+                });
+            row.size += entry.size;
+        }
+        self.rows = rows.into_values().collect();
+        self.list.select((!self.rows.is_empty()).then_some(0));
+    }
+
+    /// Internal helper: enter the selected directory, or preview the selected file
+    fn open(&mut self) -> Result<(), ProjzstError> {
+        let Some(row) = self.list.selected().and_then(|i| self.rows.get(i)) else {
+            return Ok(());
+        };
+        if row.directory {
+            self.directory = row.path.clone();
+            self.load_rows();
+            return Ok(());
+        }
+        let preview = preview_entry(&self.input, &row.path, PREVIEW_BYTES)?;
+        let text = match preview.text() {
+            Some(text) if preview.truncated => {
+                format!("{text}\n... ({} bytes in all)", preview.size)
+            }
+            Some(text) => text,
+            None => format!("(binary, {} bytes)", preview.size),
+        };
+        self.show(row.path.clone(), text);
+        Ok(())
+    }
+
+    /// Internal helper: go to the parent directory, selecting the one left
+    fn up(&mut self) {
+        if self.directory.is_empty() {
+            return;
+        }
+        let left = std::mem::take(&mut self.directory);
+        self.directory = left
+            .rsplit_once('/')
+            .map(|(parent, _)| parent.to_string())
+            .unwrap_or_default();
+        self.load_rows();
+        let position = self.rows.iter().position(|row| row.path == left);
+        self.list.select(position.or(Some(0)));
+    }
+
+    /// Internal helper: mark or unmark the selected row, then move to the next one
+    fn toggle_mark(&mut self) {
+        let Some(row) = self.list.selected().and_then(|i| self.rows.get(i)) else {
+            return;
+        };
+        if !self.marked.remove(&row.path) {
+            self.marked.insert(row.path.clone());
+        }
+        self.status = format!("{} marked", self.marked.len());
+        self.list.select_next();
+    }
+
+    /// Internal helper: show the metadata as JSON
+    fn show_metadata(&mut self) -> Result<(), ProjzstError> {
+        let text = serde_json::to_string_pretty(&self.metadata)?;
+        self.show("metadata".to_string(), text);
+        Ok(())
+    }
+
+    /// Internal helper: switch to a text view
+    fn show(&mut self, title: String, text: String) {
+        self.view = View::Text { title, text };
+        self.scroll = 0;
+    }
+
+    /// Internal helper: extract the marked entries into `output`
+    fn extract(&mut self) -> Result<(), ProjzstError> {
+        if self.marked.is_empty() {
+            self.status = "Nothing marked: mark entries with space first".to_string();
+            return Ok(());
+        }
+        let marked = std::mem::take(&mut self.marked);
+        let count = self
+            .entries
+            .iter()
+            .filter(|entry| is_marked(&marked, &entry.path))
+            .count();
+        ReadOptions::new(IgnoreUnknown::On)
+            .transform(move |path| is_marked(&marked, path).then(|| path.to_string()))
+            .unpack(&self.input, &self.output)?;
+        self.extracted += count;
+        self.status = format!("Extracted {count} entries to {}", self.output.display());
+        Ok(())
+    }
+
+    /// Internal helper: draw the main area and the status line
+    fn draw(&mut self, frame: &mut Frame<'_>) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        match &self.view {
+            View::Entries => {
+                let items: Vec<ListItem<'_>> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let mark = if is_marked(&self.marked, &row.path) {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        let name = if row.directory {
+                            format!("{}/", row.name)
+                        } else {
+                            row.name.clone()
+                        };
+                        ListItem::new(format!("{mark} {:>10}  {name}", format_size(row.size)))
+                    })
+                    .collect();
+                let title = format!(" {} /{} ", self.input.display(), self.directory);
+                let list = List::new(items)
+                    .block(Block::bordered().title(title))
+                    .highlight_style(Style::new().reversed());
+                frame.render_stateful_widget(list, main, &mut self.list);
+            }
+            View::Text { title, text } => {
+                let paragraph = Paragraph::new(text.as_str())
+                    .block(Block::bordered().title(format!(" {title} ")))
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.scroll, 0));
+                frame.render_widget(paragraph, main);
+            }
+        }
+        let status_text = match self.view {
+            View::Entries => self.status.as_str(),
+            View::Text { .. } => "up/down scroll  any other key back",
+        };
+        frame.render_widget(Paragraph::new(status_text).dim(), status);
+    }
+}
+
+/// Internal helper: whether `path` is marked, itself or through a directory above it
+fn is_marked(marked: &BTreeSet<String>, path: &str) -> bool {
+    marked.contains(path)
+        || path
+            .match_indices('/')
+            .any(|(i, _)| marked.contains(&path[..i]))
+}
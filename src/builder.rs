@@ -1,63 +1,1672 @@
 //! I don't know what I should write there.
 
-use std::fs::{self, File};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+use crate::checkpoint::Checkpoint;
+use crate::chunking::store_digests;
+use crate::codec::{zstd_decoder, PayloadCodec, PayloadDecoder, PayloadHead};
+use crate::collision::{find_collisions, PathCollision};
+use crate::concurrency::Concurrency;
+use crate::dictionary::Dictionary;
 use crate::errors::{ProjzstError, Result};
-use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::frames::{
+    check_metadata_magic, foreign_frames, padding_frame, write_frame_unchecked, FrameKind,
+    RawFrame, MAX_FRAME_SIZE,
+};
+use crate::incremental::{archive_sha256, plan_incremental, IncrementalPlan, INCREMENTAL_KEY};
+use crate::listing::ArchiveEntry;
+use crate::metadata::validate::MetadataPolicy;
+use crate::metadata::{CompatProfile, ExtraFormat, IgnoreUnknown, Metadata, MetadataEncoding};
+use crate::mtime::MtimePolicy;
+use crate::nested::{unpack_nested, DEFAULT_NESTED_SIZE_LIMIT};
+use crate::packset::CHUNK_STORE_KEY;
+use crate::payload::{check_compression_level, check_window_log, PayloadEncoder, STORE_LEVEL};
+use crate::profile::CompressionProfile;
+use crate::provenance::Provenance;
+use crate::reflink::FramedPayload;
+use crate::remap::{unpack_framed, unpack_mapped, EntryAction, PathMap, WindowsNamePolicy};
+use crate::report::{PackReport, UnpackReport};
+use crate::revisions::{latest_revision, write_trailer, MetadataPlacement};
+#[cfg(feature = "sandbox")]
+use crate::sandbox::unpack_sandboxed;
+use crate::schema::SchemaRegistry;
+use crate::secrets::{scan_sources, SecretFinding, SecretPolicy};
+use crate::selfcheck::unpack_verified;
+use crate::sfx::{read_stub, stub};
+use crate::space::{blocks_for, ensure_free_space, SPACE_BLOCK};
+use crate::storage::{info_backend, normalize_path, unpack_backend, with_backend, Location, Place};
+use crate::string_utils::to_hex;
+use crate::tar_format::TarFormat;
+use crate::temp::{sync_parent_dir, temp_file, temp_file_for, TempPath};
+use crate::template::TemplateVars;
+use crate::volume::{open_input, volume_path, VolumeWriter};
+use crate::walk::{
+    append_generated, append_source, Appended, ContentRewrite, EntryOrder, OversizePolicy,
+    SpecialPolicy, WalkOptions,
+};
+
+/// Format version written into the metadata of new archives
+/// Archives from before the version field was introduced count as version 1
+pub const FORMAT_VERSION: u32 = 1;
+/// Format versions this build can read
+pub const SUPPORTED_FORMAT_VERSIONS: RangeInclusive<u32> = 1..=FORMAT_VERSION;
 
-/// Maximum allowed metadata size (10 MB) to prevent malicious files
-const MAX_METADATA_SIZE: usize = 10 * 1024 * 1024;
+/// Largest metadata stored in an archive, over all its frames (64 MiB)
+/// Readers reject bigger metadata before allocating, to withstand malicious files
+pub const MAX_METADATA_SIZE: usize = 64 * 1024 * 1024;
+/// Largest compressed metadata once decompressed (256 MiB)
+pub const MAX_DECOMPRESSED_METADATA_SIZE: usize = 256 * 1024 * 1024;
 
 /// Minimum value of ZStd skippable frame magic number (inclusive)
-const SKIPPABLE_FRAME_MAGIC_MIN: u32 = 0x184D2A50;
+pub(crate) const SKIPPABLE_FRAME_MAGIC_MIN: u32 = 0x184D2A50;
 /// Maximum value of ZStd skippable frame magic number (inclusive)
-const SKIPPABLE_FRAME_MAGIC_MAX: u32 = 0x184D2A5F;
-/// Fixed magic number used for metadata frames (any value in the range works)
-const METADATA_FRAME_MAGIC: u32 = 0x184D2A50;
+pub(crate) const SKIPPABLE_FRAME_MAGIC_MAX: u32 = 0x184D2A5F;
+/// Magic number of metadata frames; the rest of the range is left to applications
+pub(crate) const METADATA_FRAME_MAGIC: u32 = 0x184D2A50;
+/// Largest metadata payload written into one skippable frame; bigger metadata spans
+/// several consecutive frames, which the reader concatenates
+const METADATA_FRAME_CHUNK_SIZE: usize = 1024 * 1024;
+const _: () = assert!(METADATA_FRAME_CHUNK_SIZE <= MAX_FRAME_SIZE);
+/// Magic number of a regular ZStd frame, which starts a plain `.tar.zst` file
+pub(crate) const ZSTD_FRAME_MAGIC: u32 = 0xFD2FB528;
+/// Leading bytes of CBOR metadata: the CBOR self-describe tag (55799), which MessagePack
+/// encoded metadata never starts with
+const CBOR_METADATA_TAG: [u8; 3] = [0xD9, 0xD9, 0xF7];
+/// Leading byte of JSON metadata (the object opening brace, a bare integer in MessagePack)
+const JSON_METADATA_TAG: u8 = b'{';
+/// Leading bytes of a compressed metadata payload (the ZStd frame magic); MessagePack
+/// encoded metadata never starts with them, so they double as the compression flag
+const COMPRESSED_METADATA_FLAG: [u8; 4] = ZSTD_FRAME_MAGIC.to_le_bytes();
+
+/// Builder for pack operations
+/// Collects metadata and options, then packs one or more sources with them
+///
+/// ```no_run
+/// use projzst::{Metadata, Packer};
+///
+/// Packer::new(Metadata::default())
+///     .extra_file("extra.json")
+///     .compression_level(9)
+///     .pack("./source", "output.pjz")?;
+/// # Ok::<(), projzst::ProjzstError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Packer {
+    pub(crate) metadata: Metadata,
+    pub(crate) extra_source: Option<ExtraSource>,
+    pub(crate) extra_format: Option<ExtraFormat>,
+    pub(crate) compression_level: i32,
+    pub(crate) codec: PayloadCodec,
+    pub(crate) dictionary: Option<Dictionary>,
+    pub(crate) window_log: Option<u32>,
+    pub(crate) long_distance: bool,
+    pub(crate) allow_non_object_extra: bool,
+    pub(crate) extra_pairs: Vec<(String, serde_json::Value)>,
+    pub(crate) base_archive: Option<PathBuf>,
+    pub(crate) chunk_store: Option<PathBuf>,
+    pub(crate) split_size: Option<u64>,
+    pub(crate) compress_metadata: bool,
+    pub(crate) metadata_encoding: MetadataEncoding,
+    pub(crate) payload_alignment: Option<u64>,
+    pub(crate) compat: CompatProfile,
+    pub(crate) schemas: Option<SchemaRegistry>,
+    pub(crate) template_vars: Option<TemplateVars>,
+    pub(crate) sync: bool,
+    pub(crate) overwrite: bool,
+    pub(crate) prefix: Option<String>,
+    pub(crate) sources: Vec<(PathBuf, String)>,
+    pub(crate) walk: WalkOptions,
+    pub(crate) adaptive: bool,
+    pub(crate) per_entry: bool,
+    pub(crate) content_checksum: bool,
+    pub(crate) metadata_magic: u32,
+    pub(crate) foreign_frames: Vec<RawFrame>,
+    pub(crate) keep_foreign_frames: bool,
+    pub(crate) metadata_placement: MetadataPlacement,
+    pub(crate) self_extracting: bool,
+    pub(crate) concurrency: Option<Concurrency>,
+    pub(crate) provenance: Option<Provenance>,
+    pub(crate) buffer_size: usize,
+    pub(crate) generated: Vec<(String, Vec<u8>)>,
+    pub(crate) secret_policy: SecretPolicy,
+    pub(crate) checkpoint: Option<PathBuf>,
+}
+
+impl Default for Packer {
+    fn default() -> Self {
+        Self::new(Metadata::default())
+    }
+}
+
+impl Packer {
+    /// Create a packer embedding `metadata` at the default compression level
+    pub fn new(metadata: Metadata) -> Self {
+        Self {
+            metadata,
+            extra_source: None,
+            extra_format: None,
+            compression_level: crate::DEFAULT_ZSTD_LEVEL,
+            codec: PayloadCodec::Zstd,
+            dictionary: None,
+            window_log: None,
+            long_distance: false,
+            allow_non_object_extra: false,
+            extra_pairs: Vec::new(),
+            base_archive: None,
+            chunk_store: None,
+            split_size: None,
+            compress_metadata: false,
+            metadata_encoding: MetadataEncoding::MessagePack,
+            payload_alignment: None,
+            compat: CompatProfile::Latest,
+            schemas: None,
+            template_vars: None,
+            sync: false,
+            overwrite: true,
+            prefix: None,
+            sources: Vec::new(),
+            walk: WalkOptions::default(),
+            adaptive: false,
+            per_entry: false,
+            content_checksum: false,
+            metadata_magic: METADATA_FRAME_MAGIC,
+            foreign_frames: Vec::new(),
+            keep_foreign_frames: true,
+            metadata_placement: MetadataPlacement::Header,
+            self_extracting: false,
+            concurrency: None,
+            provenance: None,
+            buffer_size: crate::DEFAULT_BUFFER_SIZE,
+            generated: Vec::new(),
+            secret_policy: SecretPolicy::Off,
+            checkpoint: None,
+        }
+    }
+
+    /// Load `extra` from a JSON, TOML or YAML file, replacing the metadata's own `extra`
+    pub fn extra_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.extra_source = Some(ExtraSource::File(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Load `extra` from JSON text already in memory (e.g. read from stdin)
+    /// `origin` names the source in error messages
+    pub fn extra_json(mut self, origin: impl Into<String>, content: impl Into<String>) -> Self {
+        self.extra_source = Some(ExtraSource::Json {
+            origin: origin.into(),
+            content: content.into(),
+        });
+        self
+    }
+
+    /// Load `extra` from JSON held in an environment variable, read when packing
+    pub fn extra_env(mut self, var: impl Into<String>) -> Self {
+        self.extra_source = Some(ExtraSource::Env(var.into()));
+        self
+    }
+
+    /// Parse the `extra` source as `format` instead of JSON
+    /// Files are otherwise parsed by extension (`.toml`, `.yaml`, `.yml`)
+    pub fn extra_format(mut self, format: ExtraFormat) -> Self {
+        self.extra_format = Some(format);
+        self
+    }
+
+    /// Set a single extra value by dotted path, applied over the extra file contents
+    /// Pairs are applied in the order they were added
+    pub fn extra_value(mut self, path: impl Into<String>, value: serde_json::Value) -> Self {
+        self.extra_pairs.push((path.into(), value));
+        self
+    }
+
+    /// Pack incrementally against a previous archive
+    /// Files whose size and SHA-256 match an entry of `base` are left out and listed
+    /// in `extra.incremental` instead; see [`crate::unpack_incremental`]
+    pub fn base_archive<P: AsRef<Path>>(mut self, base: P) -> Self {
+        self.base_archive = Some(base.as_ref().to_path_buf());
+        self
+    }
+
+    /// Split the output file into volumes of at most `bytes` bytes each
+    /// ([`Packer::pack`] writes `out.pjz.001`, `out.pjz.002`, ... instead of `out.pjz`);
+    /// reading functions join the volumes back transparently
+    pub fn split_size(mut self, bytes: u64) -> Self {
+        self.split_size = Some(bytes).filter(|&bytes| bytes > 0);
+        self
+    }
+
+    /// Set the zstd compression level: 1-22 or a negative fast level, `0` storing the
+    /// payload uncompressed
+    /// Ultra levels (20-22) compress best but take up to about 1 GiB of memory per
+    /// compression thread, and unpacking needs their window of up to 128 MiB
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Use the level, window and compression threads of a preset
+    /// Later calls to [`Packer::compression_level`], [`Packer::window_log`],
+    /// [`Packer::long_distance`] and [`Packer::concurrency`] override its settings
+    pub fn profile(mut self, profile: CompressionProfile) -> Self {
+        self.compression_level = profile.level();
+        self.window_log = profile.window_log();
+        self.long_distance = profile.long_distance();
+        if let Some(workers) = profile.compression_workers() {
+            self.concurrency = Some(self.effective_concurrency().compression(workers));
+        }
+        self
+    }
+
+    /// Compress with a window of `2^window_log` bytes (10-27, up to the 128 MiB zstd
+    /// decoders accept by default) instead of the default of the level
+    pub fn window_log(mut self, window_log: u32) -> Self {
+        self.window_log = Some(window_log);
+        self
+    }
+
+    /// Find repeats across the whole window with zstd long-distance matching, which pays
+    /// off on large trees holding similar files far apart
+    pub fn long_distance(mut self, long_distance: bool) -> Self {
+        self.long_distance = long_distance;
+        self
+    }
+
+    /// Compress the payload with `codec` instead of zstd (needs the feature of the same
+    /// name), at the compression level mapped onto the levels of the codec
+    /// The codec is recorded in the metadata for readers; checkpoints and the 1.0
+    /// compatibility profile need zstd, and adaptive compression and per-entry frames are
+    /// left out with other codecs. See [`PayloadCodec`]
+    pub fn codec(mut self, codec: PayloadCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Compress the payload with a zstd dictionary, see [`Dictionary`]
+    /// Its ID is recorded in the metadata; the dictionary itself is not stored, and readers
+    /// need it to unpack the archive (see [`ReadOptions::dictionary`]). Needs the zstd codec
+    pub fn dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Store the payload in an uncompressed zstd frame, same as compression level `0`
+    /// For already-compressed content, where compressing only costs CPU time
+    pub fn store_only(self) -> Self {
+        self.compression_level(STORE_LEVEL)
+    }
+
+    /// Store files that look already compressed (media, archives) without compressing
+    /// them, and compress the rest at the packer's level. Files are recognized by their
+    /// magic number or by sampling; applies to directory packs
+    pub fn adaptive_compression(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Compress every file in a zstd frame of its own and index the frames after the
+    /// payload, so single entries are read without decompressing the ones before them
+    /// Costs some ratio, as files no longer share a compression window; applies to
+    /// directory packs
+    pub fn per_entry_frames(mut self, per_entry: bool) -> Self {
+        self.per_entry = per_entry;
+        self
+    }
+
+    /// End every compressed zstd frame of the payload with a checksum of its content
+    /// Readers then tell a damaged payload from a decoding problem and fail with
+    /// [`ProjzstError::ChecksumMismatch`]; stored frames (level 0, adaptive) carry none
+    pub fn content_checksum(mut self, checksum: bool) -> Self {
+        self.content_checksum = checksum;
+        self
+    }
+
+    /// Store the metadata in skippable frames of another magic than `0x184D2A50`, for
+    /// files shared with tools that use that magic themselves; see
+    /// [`parse_metadata_magic`](crate::parse_metadata_magic) for the magics accepted
+    /// Such archives are read with [`ReadOptions::metadata_magic`]. Metadata revisions and
+    /// in-place edits still use the default magic
+    pub fn metadata_magic(mut self, magic: u32) -> Self {
+        self.metadata_magic = magic;
+        self
+    }
+
+    /// Write frames left to other tools (vendor frames, signatures, ...) after the
+    /// metadata, as they are; see [`RawFrame::is_foreign`]
+    pub fn foreign_frames(mut self, frames: Vec<RawFrame>) -> Self {
+        self.foreign_frames = frames;
+        self
+    }
+
+    /// Carry the foreign frames of the archive being replaced over to the new one
+    /// (default: on), so repacking keeps what other tools attached to it; off, they are
+    /// dropped. See [`strip_foreign_frames`](crate::strip_foreign_frames) for existing files
+    pub fn keep_foreign_frames(mut self, keep: bool) -> Self {
+        self.keep_foreign_frames = keep;
+        self
+    }
+
+    /// Split file content into content-defined chunks, each distinct chunk stored once
+    /// Archives of trees with many identical or near-identical files get much smaller;
+    /// only projzst reassembles the files, other tar readers see the chunks. Needs a PAX
+    /// or GNU [`TarFormat`]; see [`PackReport::deduplicated_size`] for the bytes saved
+    pub fn chunked(mut self, chunked: bool) -> Self {
+        self.walk.chunked = chunked;
+        self
+    }
+
+    /// Leave out the chunks the chunk store archive `store` already holds, as the members
+    /// of a package set do (see [`crate::pack_set`]); implies [`Packer::chunked`]
+    /// The store is recorded in `extra.chunk_store`, and the archive unpacks only with
+    /// [`ReadOptions::chunk_store`] pointing at it
+    pub fn chunk_store<P: AsRef<Path>>(mut self, store: P) -> Self {
+        self.chunk_store = Some(store.as_ref().to_path_buf());
+        self.walk.chunked = true;
+        self
+    }
+
+    /// Thread counts for compression and hashing (default: [`Concurrency::current_default`])
+    pub fn concurrency(mut self, concurrency: Concurrency) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Zstd-compress the metadata inside its frames, at the packer's compression level
+    /// Worth it for large `extra` blobs; readers decompress it transparently
+    pub fn compress_metadata(mut self, compress: bool) -> Self {
+        self.compress_metadata = compress;
+        self
+    }
+
+    /// Serialize the metadata as MessagePack (default), CBOR or JSON
+    /// Readers detect the encoding, so JSON lets any JSON parser read the header
+    pub fn metadata_encoding(mut self, encoding: MetadataEncoding) -> Self {
+        self.metadata_encoding = encoding;
+        self
+    }
+
+    /// Pad the frames so the payload starts at a multiple of `bytes` (e.g. 4096)
+    /// A `padding` frame records the alignment; `0` or `1` leaves the payload unaligned
+    pub fn payload_alignment(mut self, bytes: u64) -> Self {
+        self.payload_alignment = Some(bytes).filter(|&bytes| bytes > 1);
+        self
+    }
+
+    /// Check `extra` against the schema registered for the metadata's `(fmt, ed)` pair
+    /// Packing fails with [`ProjzstError::SchemaViolations`] before anything is written
+    pub fn schemas(mut self, schemas: SchemaRegistry) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
+    /// Whether packing may replace an existing output file (default: `true`)
+    /// When `false`, packing fails with [`ProjzstError::OutputExists`] before writing
+    /// anything if the output file, or the first volume of a split output, exists
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Store every entry under the top-level directory `prefix` (e.g. `myapp-1.2.0/`)
+    /// Applies to directory, storage and S3 sources; an empty prefix stores entries as-is
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Pack another directory or file alongside the main source, stored under `prefix`
+    /// The prefix starts at the archive root: [`Packer::prefix`] only applies to the main
+    /// source. Lets directory packs combine several roots without a staging directory;
+    /// a file with an empty prefix is stored under its file name
+    pub fn source<P: AsRef<Path>>(mut self, path: P, prefix: impl Into<String>) -> Self {
+        self.sources
+            .push((path.as_ref().to_path_buf(), prefix.into()));
+        self
+    }
+
+    /// Check every file for credentials (AWS keys, GitHub tokens, private keys, `.env`
+    /// files) before anything is written (default: [`SecretPolicy::Off`])
+    /// Files are checked as they would be stored, after [`Packer::rewrite`]
+    pub fn secret_scan(mut self, policy: SecretPolicy) -> Self {
+        self.secret_policy = policy;
+        self
+    }
+
+    /// Rewrite the content of files as they are packed, e.g. to redact secrets or
+    /// normalize line endings, without staging a modified tree
+    /// `rewrite` gets the archive path and content of every regular file read from disk
+    /// and returns the content to store, `None` to store it as it is. Files are read into
+    /// memory whole while a rewrite is set; a new rewrite replaces the previous one
+    pub fn rewrite<F>(mut self, rewrite: F) -> Self
+    where
+        F: Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.walk.rewrite = Some(ContentRewrite(Arc::new(rewrite)));
+        self
+    }
+
+    /// Store a file generated in memory at `path` from the archive root, after the sources
+    /// It is readable by everyone and dated from `SOURCE_DATE_EPOCH`, or the pack time;
+    /// packing fails with [`ProjzstError::UnsafeEntryPath`] for an absolute path or one
+    /// with `..`
+    pub fn generated_file(mut self, path: impl Into<String>, content: impl Into<Vec<u8>>) -> Self {
+        self.generated.push((path.into(), content.into()));
+        self
+    }
+
+    /// Do not descend into directories on another file system than the source's
+    /// Mount points are stored as empty directories, so bind mounts are not swept in
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.walk.one_file_system = one_file_system;
+        self
+    }
+
+    /// Store entries at most `depth` levels below a source directory
+    /// `1` keeps only the entries directly inside it; deeper directories are not read
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.walk.max_depth = Some(depth);
+        self
+    }
+
+    /// Leave out version control directories (`.git`, `.hg`, `.svn`, `.bzr`, `CVS`,
+    /// `_darcs`) found while walking a source directory
+    pub fn exclude_vcs(mut self, exclude: bool) -> Self {
+        self.walk.exclude_vcs = exclude;
+        self
+    }
+
+    /// Leave out dotfiles and dot-directories found while walking a source directory
+    pub fn exclude_hidden(mut self, exclude: bool) -> Self {
+        self.walk.exclude_hidden = exclude;
+        self
+    }
+
+    /// Leave out files and directories whose name matches `glob`, where `*` is any run of
+    /// characters and `?` any one, found while walking a source directory
+    /// Call it once per pattern; a directory left out takes everything inside it along
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.walk.exclude.push(glob.into());
+        self
+    }
+
+    /// Leave out source files larger than `limit` bytes, listing them in
+    /// [`PackReport::oversized`]; see [`Packer::oversize_policy`] to fail instead
+    pub fn max_file_size(mut self, limit: u64) -> Self {
+        self.walk.max_file_size = Some(limit);
+        self
+    }
+
+    /// What to do with files over [`Packer::max_file_size`] (default: skip them)
+    pub fn oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.walk.oversize_policy = policy;
+        self
+    }
+
+    /// Order in which the entries of a source directory are stored (default: by path)
+    /// Grouping files by extension or size can improve the compression ratio
+    pub fn order(mut self, order: EntryOrder) -> Self {
+        self.walk.order = order;
+        self
+    }
+
+    /// What to do with device nodes, FIFOs and sockets in the source (default: leave them
+    /// out and list them in [`PackReport::special`])
+    pub fn special_entries(mut self, policy: SpecialPolicy) -> Self {
+        self.walk.special = policy;
+        self
+    }
+
+    /// Header format of the stored entries (default: GNU)
+    /// [`TarFormat::Pax`] suits readers that only know POSIX formats; [`TarFormat::Ustar`]
+    /// fails the pack on paths over 255 bytes and files of 8 GiB or more
+    pub fn tar_format(mut self, format: TarFormat) -> Self {
+        self.walk.format = format;
+        self
+    }
+
+    /// Store each path of a hard-linked file with its own copy of the content
+    /// By default, a file linked more than once is stored once and its other paths as
+    /// hard links to it, which unpacking restores
+    pub fn hard_dereference(mut self, dereference: bool) -> Self {
+        self.walk.hard_dereference = dereference;
+        self
+    }
+
+    /// Store modification times to the nanosecond in PAX `mtime` records (default: whole
+    /// seconds only, in the tar headers); ignored for [`TarFormat::Ustar`]
+    pub fn subsecond_mtimes(mut self, subsecond: bool) -> Self {
+        self.walk.subsecond_mtimes = subsecond;
+        self
+    }
+
+    /// Flush the output file and its directory to disk before a pack returns
+    /// For storage where a crash must not lose or truncate an archive reported as written
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Make packs to a file resumable: the archive is written to `<output>.part` in
+    /// per-entry frames, each complete frame is recorded in the checkpoint file at `path`,
+    /// and a pack interrupted before its end picks up after the last recorded frame when
+    /// run again with the same settings and sources. Both files are removed once the
+    /// archive is complete; split volumes are written without checkpoints
+    pub fn checkpoint<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.checkpoint = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Size in bytes of the write buffer in front of the output (default:
+    /// [`DEFAULT_BUFFER_SIZE`](crate::DEFAULT_BUFFER_SIZE))
+    /// Larger buffers mean fewer, larger writes, which network file systems reward
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Expand `${...}` placeholders in the metadata and `extra` with `vars`
+    /// Without it, placeholders are stored as written
+    pub fn template_vars(mut self, vars: TemplateVars) -> Self {
+        self.template_vars = Some(vars);
+        self
+    }
+
+    /// Record the build environment in a `provenance` frame, see [`Provenance::collect`]
+    /// Off by default, as it makes archives differ from one machine or commit to another
+    pub fn provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Keep the archive readable by an older projzst release
+    /// Options that release does not understand are left out when packing; see
+    /// [`Packer::dropped_options`] to report them
+    pub fn compat(mut self, profile: CompatProfile) -> Self {
+        self.compat = profile;
+        self
+    }
+
+    /// Write the metadata at the start of the file (default), after the payload as well, or
+    /// only after it, see [`MetadataPlacement`]
+    /// Readers given a path find a trailer-only metadata on their own, streams do not; it
+    /// cannot be combined with split volumes, payload alignment, provenance, foreign frames
+    /// or another metadata magic, which all need the header
+    pub fn metadata_placement(mut self, placement: MetadataPlacement) -> Self {
+        self.metadata_placement = placement;
+        self
+    }
+
+    /// Start the archive with a POSIX shell script unpacking it, so `sh app.pjz DIR` works
+    /// without projzst installed (with zstd and tar instead, for unchunked zstd payloads)
+    /// Packs to a file make it executable; readers skip the script
+    pub fn self_extracting(mut self, self_extracting: bool) -> Self {
+        self.self_extracting = self_extracting;
+        self
+    }
+
+    /// Options set on this packer that its compatibility profile leaves out
+    pub fn dropped_options(&self) -> Vec<&'static str> {
+        if self.compat == CompatProfile::Latest {
+            return Vec::new();
+        }
+        let mut dropped = Vec::new();
+        if self.compress_metadata {
+            dropped.push("metadata compression");
+        }
+        if self.metadata_encoding != MetadataEncoding::MessagePack {
+            dropped.push("metadata encoding other than MessagePack");
+        }
+        if self.payload_alignment.is_some() {
+            dropped.push("payload alignment");
+        }
+        if self.split_size.is_some() {
+            dropped.push("split volumes");
+        }
+        if self.provenance.is_some() {
+            dropped.push("provenance frame");
+        }
+        if self.walk.chunked {
+            dropped.push("chunked payload");
+        }
+        if self.metadata_placement != MetadataPlacement::Header {
+            dropped.push("metadata trailer");
+        }
+        if self.self_extracting {
+            dropped.push("self-extracting stub");
+        }
+        if self.dictionary.is_some() {
+            dropped.push("compression dictionary");
+        }
+        if self.per_entry {
+            dropped.push("per-entry frames and their index");
+        }
+        if self.metadata_magic != METADATA_FRAME_MAGIC {
+            dropped.push("metadata magic other than 0x184D2A50");
+        }
+        if !self.foreign_frames.is_empty() {
+            dropped.push("foreign frames");
+        }
+        let metadata = &self.metadata;
+        if metadata.license.is_some()
+            || metadata.homepage.is_some()
+            || metadata.repository.is_some()
+            || !metadata.keywords.is_empty()
+        {
+            dropped.push("license, homepage, repository and keywords fields");
+        }
+        if !metadata.deps.is_empty() {
+            dropped.push("dependencies");
+        }
+        dropped
+    }
+
+    /// Accept extra metadata files holding arrays or scalars instead of an object
+    pub fn allow_non_object_extra(mut self, allow: bool) -> Self {
+        self.allow_non_object_extra = allow;
+        self
+    }
+
+    /// Pack a directory into a .pjz file
+    /// Creates archive with MessagePack metadata stored in ZStd skippable frames,
+    /// followed by tar.zst compressed content; returns what was stored and left out.
+    /// Either side may be on a storage backend
+    /// ([`Storage::at`](crate::Storage::at)), in which case only the regular files are
+    /// packed, and the payload is streamed to the output
+    pub fn pack<L1, L2>(&self, source_dir: L1, output_file: L2) -> Result<PackReport>
+    where
+        L1: Location,
+        L2: Location,
+    {
+        match (source_dir.place(), output_file.place()) {
+            (Place::Local(source_dir), Place::Local(output_file)) => {
+                let roots = self.source_roots(source_dir)?;
+                self.pack_roots(&roots, output_file)
+            }
+            (source, output) => with_backend(source, |source, source_prefix| {
+                with_backend(output, |output, output_path| {
+                    self.pack_backend(source, source_prefix, output, output_path)
+                })
+            }),
+        }
+    }
+
+    /// Internal helper: pack the given roots into a .pjz file
+    pub(crate) fn pack_roots(
+        &self,
+        roots: &[SourceRoot],
+        output_file: &Path,
+    ) -> Result<PackReport> {
+        self.check_overwrite(output_file)?;
+        if let Some(packer) = self.carrying_foreign_frames(output_file) {
+            return packer.pack_roots(roots, output_file);
+        }
+        let secrets = self.scan_secrets(roots)?;
+        let (metadata, plan) = self.prepare(roots)?;
+
+        // Create parent directories if needed
+        if let Some(parent) = output_file.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        // Write final .pjz file: [skippable frame][tar.zst data]
+        if let Some(split_size) = self.split_size.filter(|_| !self.is_v1_0()) {
+            let output = VolumeWriter::new(output_file, split_size).sync(self.sync);
+            let mut output = BufWriter::with_capacity(self.buffer_size, output);
+            let mut report = self.write_archive(
+                roots,
+                &mut output,
+                metadata,
+                plan.as_ref(),
+                Some(output_file),
+            )?;
+            unbuffer(output)?.finish()?;
+            report.set_secrets(secrets);
+            return Ok(report);
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            // A resumed pack keeps the header written by the first run
+            let metadata_bytes = self.finish_metadata(metadata, plan.as_ref())?;
+            let mut report = self.pack_resumable(
+                roots,
+                output_file,
+                checkpoint,
+                &metadata_bytes,
+                plan.as_ref(),
+            )?;
+            report.set_secrets(secrets);
+            return Ok(report);
+        }
+
+        // Only a complete archive replaces the output file
+        let (output, temp) = temp_file_for(output_file)?;
+        let mut output = BufWriter::with_capacity(self.buffer_size, output);
+        let mut report = self.write_archive(
+            roots,
+            &mut output,
+            metadata,
+            plan.as_ref(),
+            Some(output_file),
+        )?;
+        self.persist(unbuffer(output)?, temp, output_file)?;
+        report.set_secrets(secrets);
+        Ok(report)
+    }
+
+    /// Internal helper: a copy of this packer also writing the foreign frames of the
+    /// archive at `output_file`, or `None` when there are none to carry over
+    pub(crate) fn carrying_foreign_frames(&self, output_file: &Path) -> Option<Self> {
+        if !self.keep_foreign_frames || !self.metadata_placement.in_header() {
+            return None;
+        }
+        let carried: Vec<RawFrame> = foreign_frames(output_file, self.metadata_magic)
+            .into_iter()
+            .filter(|frame| !self.foreign_frames.contains(frame))
+            .collect();
+        if carried.is_empty() {
+            return None;
+        }
+        log::info!(
+            "{}: carrying over {} foreign frames",
+            output_file.display(),
+            carried.len()
+        );
+        let mut packer = self.clone().keep_foreign_frames(false);
+        packer.foreign_frames.extend(carried);
+        Some(packer)
+    }
+
+    /// Internal helper: pack the given roots into `<output_file>.part`, resuming from the
+    /// checkpoint at `checkpoint_path` when it records an earlier run of this pack
+    fn pack_resumable(
+        &self,
+        roots: &[SourceRoot],
+        output_file: &Path,
+        checkpoint_path: &Path,
+        metadata_bytes: &[u8],
+        plan: Option<&IncrementalPlan>,
+    ) -> Result<PackReport> {
+        let mut part = output_file.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let fingerprint = to_hex(&Sha256::digest(format!("{self:?}{roots:?}")));
+        let available = fs::metadata(&part).map_or(0, |info| info.len());
+
+        let (output, checkpoint) =
+            match Checkpoint::resume(checkpoint_path, &fingerprint, available)? {
+                Some(checkpoint) => {
+                    log::info!(
+                        "resuming {} after {} bytes of payload",
+                        part.display(),
+                        checkpoint.payload_size
+                    );
+                    let mut output = OpenOptions::new().write(true).open(&part)?;
+                    output.set_len(checkpoint.header_size + checkpoint.payload_size)?;
+                    output.seek(SeekFrom::End(0))?;
+                    (output, checkpoint)
+                }
+                None => {
+                    let mut header = Vec::new();
+                    self.write_header(&mut header, metadata_bytes)?;
+                    // Not truncated: what an earlier run left past the new data is cut
+                    // off once this pack completes
+                    let mut output = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(&part)?;
+                    output.write_all(&header)?;
+                    let checkpoint =
+                        Checkpoint::create(checkpoint_path, &fingerprint, header.len() as u64)?;
+                    (output, checkpoint)
+                }
+            };
+
+        let mut output = BufWriter::with_capacity(self.buffer_size, output);
+        let report = self.write_payload(roots, &mut output, plan, Some(checkpoint))?;
+        self.write_trailer(&mut output, metadata_bytes)?;
+        let mut output = unbuffer(output)?;
+        // Left over from a longer earlier run that did not match the checkpoint
+        let end = output.stream_position()?;
+        output.set_len(end)?;
+        self.set_executable(&output)?;
+        if self.sync {
+            output.sync_all()?;
+        }
+        drop(output);
+        fs::rename(&part, output_file)?;
+        if self.sync {
+            sync_parent_dir(output_file)?;
+        }
+        fs::remove_file(checkpoint_path)?;
+        Ok(report)
+    }
+
+    /// Pack a directory into any writer (e.g. stdout) instead of a file
+    /// The split size does not apply: the whole archive goes to `writer`
+    pub fn pack_to_writer<P, W>(&self, source_dir: P, writer: W) -> Result<PackReport>
+    where
+        P: AsRef<Path>,
+        W: Write,
+    {
+        let roots = self.source_roots(source_dir.as_ref())?;
+        self.pack_roots_to_writer(&roots, writer)
+    }
+
+    /// Internal helper: pack the given roots into any writer
+    fn pack_roots_to_writer<W: Write>(
+        &self,
+        roots: &[SourceRoot],
+        writer: W,
+    ) -> Result<PackReport> {
+        let secrets = self.scan_secrets(roots)?;
+        let (metadata, plan) = self.prepare(roots)?;
+        let mut writer = BufWriter::with_capacity(self.buffer_size, writer);
+        let mut report = self.write_archive(roots, &mut writer, metadata, plan.as_ref(), None)?;
+        writer.flush()?;
+        report.set_secrets(secrets);
+        Ok(report)
+    }
+
+    /// Pack an explicit list of files and directories into a .pjz file
+    /// Each path is stored under itself (relative paths) or its file name (paths outside
+    /// the current directory), below [`Packer::prefix`]; use [`Packer::source`] to store a
+    /// path elsewhere
+    pub fn pack_files<I, P1, P2>(&self, files: I, output_file: P2) -> Result<PackReport>
+    where
+        I: IntoIterator<Item = P1>,
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let roots = self.file_roots(files)?;
+        self.pack_roots(&roots, output_file.as_ref())
+    }
+
+    /// Pack an explicit list of files and directories into any writer (e.g. stdout)
+    /// See [`Packer::pack_files`] for where entries are stored
+    pub fn pack_files_to_writer<I, P, W>(&self, files: I, writer: W) -> Result<PackReport>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+        W: Write,
+    {
+        let roots = self.file_roots(files)?;
+        self.pack_roots_to_writer(&roots, writer)
+    }
+
+    /// The metadata as it will be stored, with `extra` loaded from its sources
+    /// Fails when `extra` does not match the schema set with [`Packer::schemas`]
+    pub fn resolved_metadata(&self) -> Result<Metadata> {
+        let mut metadata = self.metadata.clone();
+        if let Some(source) = &self.extra_source {
+            metadata.extra = source.load(self.extra_format, self.allow_non_object_extra)?;
+        }
+        for (path, value) in &self.extra_pairs {
+            metadata.set_extra_path(path, value.clone());
+        }
+        if let Some(vars) = &self.template_vars {
+            vars.expand_metadata(&mut metadata)?;
+        }
+        if let Some(schemas) = &self.schemas {
+            schemas.validate(&metadata)?;
+        }
+        Ok(metadata)
+    }
+
+    /// Internal helper: the thread counts of this pack
+    pub(crate) fn effective_concurrency(&self) -> Concurrency {
+        self.concurrency
+            .unwrap_or_else(Concurrency::current_default)
+    }
+
+    /// Internal helper: a payload encoder at the configured level and worker count
+    pub(crate) fn payload_encoder<W: Write>(&self, output: W) -> io::Result<PayloadEncoder<W>> {
+        let workers = self.effective_concurrency().compression_workers();
+        match self.active_dictionary() {
+            Some(dictionary) => PayloadEncoder::with_dictionary(
+                output,
+                self.compression_level,
+                workers,
+                dictionary.clone(),
+            )?,
+            None => {
+                PayloadEncoder::with_codec(output, self.codec, self.compression_level, workers)?
+            }
+        }
+        .content_checksum(self.content_checksum)?
+        .window(self.window_log, self.long_distance)
+    }
+
+    /// Internal helper: write the [tar.zst data] following the skippable frames to a writer
+    /// Entries are stored under the prefix of their root; those `plan` finds unchanged are
+    /// left out (incremental packs), as are those a resumed `checkpoint` records
+    pub(crate) fn write_payload<W: Write>(
+        &self,
+        roots: &[SourceRoot],
+        output: &mut W,
+        plan: Option<&IncrementalPlan>,
+        mut checkpoint: Option<Checkpoint>,
+    ) -> Result<PackReport> {
+        let mut report = PackReport::default();
+        // The payload goes on after the frames recorded, in frames of their own
+        let encoder = match &mut checkpoint {
+            Some(checkpoint) => {
+                checkpoint.restore(&mut report);
+                PayloadEncoder::resume(
+                    output,
+                    self.compression_level,
+                    self.effective_concurrency().compression_workers(),
+                    checkpoint.payload_size,
+                    std::mem::take(&mut checkpoint.index),
+                    self.active_dictionary().cloned(),
+                )?
+                .content_checksum(self.content_checksum)?
+                .window(self.window_log, self.long_distance)?
+            }
+            None => self.payload_encoder(output)?,
+        };
+        let per_entry = (self.per_entry && !self.is_v1_0()) || checkpoint.is_some();
+        // Projzst 1.0 would extract the chunks instead of the files
+        let walk = if self.is_v1_0() && self.walk.chunked {
+            Cow::Owned(WalkOptions {
+                chunked: false,
+                ..self.walk.clone()
+            })
+        } else {
+            Cow::Borrowed(&self.walk)
+        };
+        // Chunks of the store count as stored already
+        let chunks = match &self.chunk_store {
+            Some(store) if walk.chunked => store_digests(store)?,
+            _ => HashSet::new(),
+        };
+        let mut appended = Appended {
+            checkpoint,
+            chunks,
+            ..Appended::default()
+        };
+        // Append tar.zst compressed data as a standard ZStd frame
+        let mut zst_encoder = encoder.adaptive(self.adaptive).per_entry(per_entry);
+        {
+            let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+            for root in roots {
+                append_source(
+                    &mut tar_builder,
+                    &root.path,
+                    root.prefix.as_deref(),
+                    &walk,
+                    plan,
+                    &mut appended,
+                    &mut report,
+                )?;
+            }
+            if !self.generated.is_empty() {
+                let mtime = TemplateVars::new()
+                    .get("timestamp")
+                    .and_then(|secs| secs.parse().ok())
+                    .unwrap_or_default();
+                for (path, content) in &self.generated {
+                    let path = archive_prefix(path)?
+                        .ok_or_else(|| ProjzstError::UnsafeEntryPath(path.clone()))?;
+                    append_generated(
+                        &mut tar_builder,
+                        self.walk.format,
+                        &path,
+                        content,
+                        mtime,
+                        &mut report,
+                    )?;
+                }
+            }
+        }
+        // Finalize zstd stream
+        let (output, compressed_size) = zst_encoder.finish_counted()?;
+        output.flush()?;
+        report.finish(compressed_size);
+
+        Ok(report)
+    }
+
+    /// Internal helper: resolve metadata for directory sources
+    /// For incremental packs, also starts comparing the sources with the base archive;
+    /// [`Packer::finish_metadata`] serializes the metadata once the comparison is done
+    pub(crate) fn prepare(
+        &self,
+        roots: &[SourceRoot],
+    ) -> Result<(Metadata, Option<IncrementalPlan>)> {
+        let mut metadata = self.resolved_metadata()?;
+        let plan = match &self.base_archive {
+            Some(base) => Some(plan_incremental(
+                roots,
+                &self.walk,
+                base,
+                self.effective_concurrency().hashing_threads(),
+            )?),
+            None => None,
+        };
+        if let Some(store) = self.chunk_store.as_ref().filter(|_| !self.is_v1_0()) {
+            let file = store.file_name().map(|name| name.to_string_lossy());
+            metadata.set_extra_path(
+                CHUNK_STORE_KEY,
+                serde_json::json!({ "file": file, "sha256": archive_sha256(store)? }),
+            );
+        }
+        Ok((metadata, plan))
+    }
+
+    /// Internal helper: serialize prepared metadata, with the incremental reference list
+    /// of `plan`, waiting for the files still being compared
+    pub(crate) fn finish_metadata(
+        &self,
+        mut metadata: Metadata,
+        plan: Option<&IncrementalPlan>,
+    ) -> Result<Vec<u8>> {
+        if let Some(plan) = plan {
+            metadata.set_extra_path(INCREMENTAL_KEY, plan.to_json()?);
+        }
+        self.encode(&metadata)
+    }
+
+    /// Internal helper: write the header, the payload and the trailer to `output`
+    /// The header of an incremental pack lists the files left out, known once they are
+    /// compared while the payload is written, so the payload is spooled meanwhile to a
+    /// temporary file next to `spool_target`, or in the system temporary directory for `None`
+    fn write_archive<W: Write>(
+        &self,
+        roots: &[SourceRoot],
+        output: &mut W,
+        metadata: Metadata,
+        plan: Option<&IncrementalPlan>,
+        spool_target: Option<&Path>,
+    ) -> Result<PackReport> {
+        if plan.is_none() {
+            let metadata_bytes = self.encode(&metadata)?;
+            self.write_header(output, &metadata_bytes)?;
+            let report = self.write_payload(roots, output, None, None)?;
+            self.write_trailer(output, &metadata_bytes)?;
+            return Ok(report);
+        }
+        // Settings that cannot be written fail before the payload is compressed
+        self.encode(&metadata)?;
+        let (spool, temp) = match spool_target {
+            Some(target) => temp_file_for(target)?,
+            None => temp_file()?,
+        };
+        let mut spool = BufWriter::with_capacity(self.buffer_size, spool);
+        let report = self.write_payload(roots, &mut spool, plan, None)?;
+        drop(unbuffer(spool)?);
+        let metadata_bytes = self.finish_metadata(metadata, plan)?;
+        self.write_header(output, &metadata_bytes)?;
+        io::copy(&mut File::open(temp.path())?, output)?;
+        self.write_trailer(output, &metadata_bytes)?;
+        Ok(report)
+    }
+
+    /// Internal helper: files of the roots that look like they contain credentials, as
+    /// [`Packer::secret_scan`] says; fails under [`SecretPolicy::Error`] if there are any
+    pub(crate) fn scan_secrets(&self, roots: &[SourceRoot]) -> Result<Vec<SecretFinding>> {
+        if self.secret_policy == SecretPolicy::Off {
+            return Ok(Vec::new());
+        }
+        let threads = self.effective_concurrency().hashing_threads();
+        let findings = scan_sources(roots, &self.walk, threads)?;
+        if self.secret_policy == SecretPolicy::Error && !findings.is_empty() {
+            let list: Vec<String> = findings.iter().map(SecretFinding::describe).collect();
+            return Err(ProjzstError::SecretsFound(list.join(", ")));
+        }
+        Ok(findings)
+    }
+
+    /// Internal helper: the normalized [`Packer::prefix`], `None` when entries are not prefixed
+    /// Fails with [`ProjzstError::UnsafeEntryPath`] for an absolute prefix or one with `..`
+    pub(crate) fn entry_prefix(&self) -> Result<Option<String>> {
+        self.prefix.as_deref().map_or(Ok(None), archive_prefix)
+    }
+
+    /// Internal helper: `source_dir` followed by the roots added with [`Packer::source`],
+    /// each with the prefix of its entries
+    pub(crate) fn source_roots(&self, source_dir: &Path) -> Result<Vec<SourceRoot>> {
+        let main = SourceRoot {
+            path: source_dir.to_path_buf(),
+            prefix: self.entry_prefix()?,
+        };
+        self.with_sources(vec![main])
+    }
+
+    /// Internal helper: one root per listed path, stored under its own path below
+    /// [`Packer::prefix`], followed by the roots added with [`Packer::source`]
+    pub(crate) fn file_roots<I, P>(&self, files: I) -> Result<Vec<SourceRoot>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let prefix = self.entry_prefix()?;
+        let roots = files
+            .into_iter()
+            .map(|file| {
+                let path = file.as_ref();
+                let name = normalize_path(path)
+                    .or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()));
+                let prefix = match (&prefix, name) {
+                    (Some(prefix), Some(name)) => Some(format!("{prefix}/{name}")),
+                    (prefix, name) => name.or_else(|| prefix.clone()),
+                };
+                SourceRoot {
+                    path: path.to_path_buf(),
+                    prefix,
+                }
+            })
+            .collect();
+        self.with_sources(roots)
+    }
+
+    /// Internal helper: append the roots added with [`Packer::source`] and check that
+    /// every root exists
+    fn with_sources(&self, mut roots: Vec<SourceRoot>) -> Result<Vec<SourceRoot>> {
+        for (path, prefix) in &self.sources {
+            roots.push(SourceRoot {
+                path: path.clone(),
+                prefix: archive_prefix(prefix)?,
+            });
+        }
+        for root in &mut roots {
+            check_source(&root.path)?;
+            if root.prefix.is_none() && !root.path.is_dir() {
+                // A file needs a name of its own in the archive
+                root.prefix = root
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+            }
+        }
+        Ok(roots)
+    }
+
+    /// Internal helper: resolve metadata and serialize it for the metadata frame
+    pub(crate) fn encoded_metadata(&self) -> Result<Vec<u8>> {
+        self.encode(&self.resolved_metadata()?)
+    }
+
+    /// Internal helper: write the metadata frames, the provenance frame and the foreign
+    /// frames, followed by a padding frame when the payload is to be aligned
+    pub(crate) fn write_header<W: Write>(
+        &self,
+        output: &mut W,
+        metadata_bytes: &[u8],
+    ) -> Result<()> {
+        check_metadata_magic(self.metadata_magic)?;
+        if self.is_v1_0() {
+            // 1.0 readers expect the whole metadata in a single frame of the default magic
+            return write_frame_unchecked(output, METADATA_FRAME_MAGIC, metadata_bytes);
+        }
+        let mut header_len = 0;
+        if self.self_extracting {
+            let stub = stub();
+            output.write_all(&stub)?;
+            header_len += stub.len() as u64;
+        }
+        if !self.metadata_placement.in_header() {
+            return Ok(());
+        }
+        write_metadata_frame(output, self.metadata_magic, metadata_bytes)?;
+        let chunks = metadata_bytes.len().div_ceil(METADATA_FRAME_CHUNK_SIZE);
+        header_len += (metadata_bytes.len() + chunks * 8) as u64;
+        if let Some(provenance) = &self.provenance {
+            let payload = serde_json::to_vec(provenance)?;
+            write_frame_unchecked(output, FrameKind::Provenance.magic(), &payload)?;
+            header_len += 8 + payload.len() as u64;
+        }
+        for frame in &self.foreign_frames {
+            if !frame.is_foreign(self.metadata_magic) {
+                return Err(ProjzstError::InvalidFrameMagic(frame.magic));
+            }
+            frame.write_to(&mut *output)?;
+            header_len += 8 + frame.payload.len() as u64;
+        }
+        if let Some(alignment) = self.payload_alignment {
+            padding_frame(header_len, alignment).write_to(output)?;
+        }
+        Ok(())
+    }
+
+    /// Internal helper: write the metadata trailer after the payload, if one was asked for
+    pub(crate) fn write_trailer<W: Write>(
+        &self,
+        output: &mut W,
+        metadata_bytes: &[u8],
+    ) -> Result<()> {
+        if self.is_v1_0() || !self.metadata_placement.in_trailer() {
+            return Ok(());
+        }
+        write_trailer(output, metadata_bytes)
+    }
+
+    /// Internal helper: serialize metadata, compressed if requested
+    /// The payload codec is recorded here, so packs with a level, window or codec that
+    /// cannot be written fail before anything is
+    fn encode(&self, metadata: &Metadata) -> Result<Vec<u8>> {
+        check_compression_level(self.compression_level)?;
+        if let Some(window_log) = self.window_log {
+            check_window_log(window_log)?;
+        }
+        self.check_codec()?;
+        self.check_placement()?;
+        if self.is_v1_0() {
+            // The positional array 1.0 readers expect, without the fields added since
+            let metadata_bytes = rmp_serde::to_vec(&(
+                &metadata.name,
+                &metadata.auth,
+                &metadata.fmt,
+                &metadata.ed,
+                &metadata.ver,
+                &metadata.desc,
+                &metadata.extra,
+            ))?;
+            if metadata_bytes.len() > MAX_METADATA_SIZE {
+                return Err(ProjzstError::InvalidMetadataLength(metadata_bytes.len()));
+            }
+            return Ok(metadata_bytes);
+        }
+        let metadata = &Metadata {
+            codec: self.codec.recorded(),
+            dictionary: self.active_dictionary().map(Dictionary::id),
+            ..metadata.clone()
+        };
+        let metadata_bytes = encode_metadata(metadata, self.metadata_encoding)?;
+        if !self.compress_metadata {
+            return Ok(metadata_bytes);
+        }
+        compress_metadata(&metadata_bytes, self.compression_level)
+    }
+
+    /// Internal helper: fail if `output_file` exists and may not be overwritten
+    pub(crate) fn check_overwrite(&self, output_file: &Path) -> Result<()> {
+        self.check_overwrite_with(output_file, |path| path.exists())
+    }
+
+    /// Internal helper: [`Packer::check_overwrite`] with `exists` telling whether a path is
+    /// taken, for outputs on a storage backend
+    pub(crate) fn check_overwrite_with(
+        &self,
+        output_file: &Path,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Result<()> {
+        if self.overwrite {
+            return Ok(());
+        }
+        for path in [output_file.to_path_buf(), volume_path(output_file, 1)] {
+            if exists(&path) {
+                return Err(ProjzstError::OutputExists(path.display().to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Internal helper: move a complete archive into place at `output_file`, flushing it
+    /// and its directory to disk first if [`Packer::sync`] asked for it
+    pub(crate) fn persist(&self, output: File, temp: TempPath, output_file: &Path) -> Result<()> {
+        self.set_executable(&output)?;
+        if self.sync {
+            output.sync_all()?;
+        }
+        drop(output);
+        temp.persist(output_file)?;
+        if self.sync {
+            sync_parent_dir(output_file)?;
+        }
+        Ok(())
+    }
+
+    /// Internal helper: make a self-extracting archive file executable
+    fn set_executable(&self, output: &File) -> Result<()> {
+        #[cfg(unix)]
+        if self.self_extracting && !self.is_v1_0() {
+            use std::os::unix::fs::PermissionsExt;
+            output.set_permissions(fs::Permissions::from_mode(0o755))?;
+        }
+        #[cfg(not(unix))]
+        let _ = output;
+        Ok(())
+    }
+
+    /// Internal helper: the dictionary the payload is compressed with, none under the 1.0
+    /// compatibility profile
+    pub(crate) fn active_dictionary(&self) -> Option<&Dictionary> {
+        self.dictionary.as_ref().filter(|_| !self.is_v1_0())
+    }
+
+    /// Internal helper: whether archives must stay readable by projzst 1.0
+    pub(crate) fn is_v1_0(&self) -> bool {
+        self.compat == CompatProfile::V1_0
+    }
+
+    /// Internal helper: fail when the metadata is to be written only after the payload but
+    /// an option needs it at the start of the file
+    fn check_placement(&self) -> Result<()> {
+        if self.is_v1_0() || self.metadata_placement.in_header() {
+            return Ok(());
+        }
+        let option = if self.split_size.is_some() {
+            "split volumes"
+        } else if self.payload_alignment.is_some() {
+            "payload alignment"
+        } else if self.provenance.is_some() {
+            "a provenance frame"
+        } else if !self.foreign_frames.is_empty() {
+            "foreign frames"
+        } else if self.metadata_magic != METADATA_FRAME_MAGIC {
+            "another metadata magic"
+        } else {
+            return Ok(());
+        };
+        Err(ProjzstError::PlacementConflict(option.to_string()))
+    }
+
+    /// Internal helper: fail unless the payload codec is built in and the options set
+    /// work with it
+    fn check_codec(&self) -> Result<()> {
+        if self.codec.check_available()? == PayloadCodec::Zstd {
+            return Ok(());
+        }
+        let option = if self.is_v1_0() {
+            "the 1.0 compatibility profile"
+        } else if self.checkpoint.is_some() {
+            "checkpoints"
+        } else if self.dictionary.is_some() {
+            "a dictionary"
+        } else {
+            return Ok(());
+        };
+        Err(ProjzstError::CodecConflict {
+            codec: self.codec.name().to_string(),
+            option: option.to_string(),
+        })
+    }
+}
+
+/// Pack a directory into a .pjz file
+/// Shorthand for [`Packer`] with an optional extra file and compression level; either
+/// side may be on a storage backend ([`Storage::at`](crate::Storage::at))
+///
+/// # Arguments
+/// * `source_dir` - Directory to pack
+/// * `output_file` - Output .pjz file path
+/// * `metadata` - Metadata to embed
+/// * `extra_file` - Optional extra metadata JSON file
+/// * `compression_level` - Zstd compression level
+pub fn pack<L1, L2, P>(
+    source_dir: L1,
+    output_file: L2,
+    metadata: Metadata,
+    extra_file: Option<P>,
+    compression_level: i32,
+) -> Result<()>
+where
+    L1: Location,
+    L2: Location,
+    P: AsRef<Path>,
+{
+    packer_for(metadata, extra_file, compression_level)
+        .pack(source_dir, output_file)
+        .map(drop)
+}
+
+/// Pack a directory into any writer (e.g. stdout) instead of a file
+/// Behaves exactly like [`pack`], but streams the archive to `writer`
+///
+/// # Arguments
+/// * `source_dir` - Directory to pack
+/// * `writer` - Destination of the .pjz stream
+/// * `metadata` - Metadata to embed
+/// * `extra_file` - Optional extra metadata JSON file
+/// * `compression_level` - Zstd compression level
+pub fn pack_to_writer<P1, P2, W>(
+    source_dir: P1,
+    writer: W,
+    metadata: Metadata,
+    extra_file: Option<P2>,
+    compression_level: i32,
+) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    W: Write,
+{
+    packer_for(metadata, extra_file, compression_level)
+        .pack_to_writer(source_dir, writer)
+        .map(drop)
+}
+
+/// Internal helper: the [`Packer`] equivalent of the positional pack arguments
+pub(crate) fn packer_for<P: AsRef<Path>>(
+    metadata: Metadata,
+    extra_file: Option<P>,
+    compression_level: i32,
+) -> Packer {
+    let packer = Packer::new(metadata).compression_level(compression_level);
+    match extra_file {
+        Some(path) => packer.extra_file(path),
+        None => packer,
+    }
+}
+
+/// Internal helper: validate the source directory exists
+fn check_source(source_dir: &Path) -> Result<()> {
+    if !source_dir.exists() {
+        return Err(ProjzstError::SourceNotFound(
+            source_dir.display().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A directory or file packed by a [`Packer`] and the prefix of its entries in the archive
+/// (for a file, its own name in the archive)
+#[derive(Debug, Clone)]
+pub(crate) struct SourceRoot {
+    pub path: PathBuf,
+    pub prefix: Option<String>,
+}
+
+/// Internal helper: `prefix` as a `/`-separated archive path, `None` when empty
+/// Fails with [`ProjzstError::UnsafeEntryPath`] for an absolute prefix or one with `..`
+fn archive_prefix(prefix: &str) -> Result<Option<String>> {
+    if prefix.trim_matches('/').is_empty() {
+        return Ok(None);
+    }
+    if Path::new(prefix).is_absolute() {
+        return Err(ProjzstError::UnsafeEntryPath(prefix.to_string()));
+    }
+    normalize_path(Path::new(prefix))
+        .map(Some)
+        .ok_or_else(|| ProjzstError::UnsafeEntryPath(prefix.to_string()))
+}
+
+/// Where a [`Packer`] takes its extra metadata JSON from
+#[derive(Debug, Clone)]
+pub(crate) enum ExtraSource {
+    /// JSON file on disk
+    File(PathBuf),
+    /// JSON text supplied directly, with a name for error messages
+    Json { origin: String, content: String },
+    /// Environment variable holding JSON text
+    Env(String),
+}
+
+impl ExtraSource {
+    /// Read and parse the extra metadata
+    /// Parse errors carry the source name and position; non-object values are rejected
+    /// unless `allow_non_object` is set. Without an explicit `format`, files are parsed by
+    /// extension and other sources as JSON
+    fn load(
+        &self,
+        format: Option<ExtraFormat>,
+        allow_non_object: bool,
+    ) -> Result<serde_json::Value> {
+        match self {
+            ExtraSource::File(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|_| ProjzstError::ExtraFileNotFound(path.display().to_string()))?;
+                let format = format.unwrap_or_else(|| ExtraFormat::from_path(path));
+                parse_extra(
+                    &path.display().to_string(),
+                    &content,
+                    format,
+                    allow_non_object,
+                )
+            }
+            ExtraSource::Json { origin, content } => parse_extra(
+                origin,
+                content,
+                format.unwrap_or_default(),
+                allow_non_object,
+            ),
+            ExtraSource::Env(var) => {
+                let content =
+                    std::env::var(var).map_err(|_| ProjzstError::ExtraEnvNotSet(var.clone()))?;
+                let origin = format!("${var}");
+                parse_extra(
+                    &origin,
+                    &content,
+                    format.unwrap_or_default(),
+                    allow_non_object,
+                )
+            }
+        }
+    }
+}
+
+/// Internal helper: parse extra metadata text in `format` coming from `origin`
+fn parse_extra(
+    origin: &str,
+    content: &str,
+    format: ExtraFormat,
+    allow_non_object: bool,
+) -> Result<serde_json::Value> {
+    let invalid = |(line, column), message| ProjzstError::InvalidExtraFile {
+        path: origin.to_string(),
+        line,
+        column,
+        message,
+    };
+    let value: serde_json::Value = match format {
+        ExtraFormat::Json => serde_json::from_str(content)
+            .map_err(|e| invalid((e.line(), e.column()), json_error_message(&e)))?,
+        #[cfg(feature = "toml")]
+        ExtraFormat::Toml => {
+            let table: toml::Table = toml::from_str(content).map_err(|e| {
+                let offset = e.span().map_or(0, |span| span.start);
+                invalid(line_column(content, offset), e.message().to_string())
+            })?;
+            toml_to_json(toml::Value::Table(table))
+        }
+        #[cfg(feature = "yaml")]
+        ExtraFormat::Yaml => serde_yaml::from_str(content).map_err(|e| {
+            let position = e
+                .location()
+                .map_or((1, 1), |location| (location.line(), location.column()));
+            let message = e.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((head, _)) => head.to_string(),
+                None => message,
+            };
+            invalid(position, message)
+        })?,
+        #[cfg(not(all(feature = "toml", feature = "yaml")))]
+        _ => {
+            return Err(ProjzstError::FeatureUnavailable {
+                what: format!("{format:?} extra metadata"),
+                feature: format!("{format:?}").to_lowercase(),
+            })
+        }
+    };
+
+    if !allow_non_object && !value.is_object() {
+        return Err(ProjzstError::ExtraNotObject {
+            path: origin.to_string(),
+            found: json_kind(&value).to_string(),
+        });
+    }
 
-/// Pack a directory into a .pjz file
-/// Creates archive with MessagePack metadata stored in ZStd skippable frames,
-/// followed by tar.zst compressed content
-///
-///
-/// the Pack Builder for pack operations.
-#[derive(Debug, Clone)]
-pub struct Packer {}
+    Ok(value)
+}
 
-pub fn pack<P1, P2, P3>(
-    source_dir: P1,
-    output_file: P2,
-    mut metadata: Metadata,
-    extra_file: Option<P3>,
-    compression_level: i32,
-) -> Result<()>
-where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
-    P3: AsRef<Path>,
-{
-    let source_dir = source_dir.as_ref();
-    let output_file = output_file.as_ref();
+/// Internal helper: 1-based line and column of a byte offset in `content`
+#[cfg(feature = "toml")]
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
 
-    // Validate source directory exists
-    if !source_dir.exists() {
-        return Err(ProjzstError::SourceNotFound(
-            source_dir.display().to_string(),
-        ));
+/// Internal helper: convert a TOML value to JSON, datetimes becoming RFC 3339 strings
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(dt) => serde_json::Value::String(dt.to_string()),
+        toml::Value::Array(items) => items.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect(),
+    }
+}
+
+/// Internal helper: a serde_json error message without its trailing position
+fn json_error_message(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    match message.rsplit_once(" at line ") {
+        Some((head, _)) => head.to_string(),
+        None => message,
     }
+}
 
-    // Load extra metadata from JSON file if provided
-    if let Some(extra_path) = extra_file {
-        let extra_path = extra_path.as_ref();
-        let extra_content = fs::read_to_string(extra_path)
-            .map_err(|_| ProjzstError::ExtraFileNotFound(extra_path.display().to_string()))?;
-        metadata.extra = serde_json::from_str(&extra_content)?;
+/// Internal helper: human-readable name of a JSON value's type
+pub(crate) fn json_kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
     }
+}
 
-    // Serialize metadata to MessagePack bytes
-    let metadata_bytes = rmp_serde::to_vec(&metadata)?;
+/// Internal helper: serialize metadata in the given encoding and check its size
+/// Returns the bytes to store in the metadata frame
+pub(crate) fn encode_metadata(metadata: &Metadata, encoding: MetadataEncoding) -> Result<Vec<u8>> {
+    let metadata = &Metadata {
+        format_version: Some(FORMAT_VERSION),
+        ..metadata.clone()
+    };
+    let metadata_bytes = match encoding {
+        // Named fields, so optional ones can be left out; readers accept the positional
+        // arrays of older archives too
+        MetadataEncoding::MessagePack => rmp_serde::to_vec_named(metadata)?,
+        MetadataEncoding::Cbor => {
+            let mut bytes = CBOR_METADATA_TAG.to_vec();
+            ciborium::into_writer(metadata, &mut bytes)?;
+            bytes
+        }
+        MetadataEncoding::Json => serde_json::to_vec(metadata)?,
+    };
     let metadata_len = metadata_bytes.len();
 
     // Validate metadata size
@@ -65,43 +1674,133 @@ where
         return Err(ProjzstError::InvalidMetadataLength(metadata_len));
     }
 
-    // Create parent directories if needed
-    if let Some(parent) = output_file.parent() {
-        if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent)?;
-        }
+    Ok(metadata_bytes)
+}
+
+/// Internal helper: zstd-compress encoded metadata into a flagged frame payload
+/// The size limit then applies to the compressed bytes, so metadata up to
+/// [`MAX_DECOMPRESSED_METADATA_SIZE`] fits if it compresses well
+fn compress_metadata(metadata_bytes: &[u8], level: i32) -> Result<Vec<u8>> {
+    let compressed = zstd::stream::encode_all(metadata_bytes, level)?;
+    if compressed.len() > MAX_METADATA_SIZE {
+        return Err(ProjzstError::InvalidMetadataLength(compressed.len()));
     }
+    Ok(compressed)
+}
 
-    // Write final .pjz file: [skippable frame][tar.zst data]
-    let mut output = File::create(output_file)?;
+/// Internal helper: undo [`compress_metadata`] if the payload carries the flag
+/// Decompression stops past [`MAX_DECOMPRESSED_METADATA_SIZE`] to guard against bombs
+pub(crate) fn decompress_metadata(metadata_bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if !metadata_bytes.starts_with(&COMPRESSED_METADATA_FLAG) {
+        return Ok(metadata_bytes);
+    }
+    let decoder = zstd_decoder(metadata_bytes.as_slice())?;
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_METADATA_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_DECOMPRESSED_METADATA_SIZE {
+        return Err(ProjzstError::InvalidMetadataLength(decompressed.len()));
+    }
+    Ok(decompressed)
+}
 
-    // Write skippable frame header (magic + size)
-    output.write_all(&METADATA_FRAME_MAGIC.to_le_bytes())?;
-    output.write_all(&(metadata_len as u32).to_le_bytes())?;
-    // Write metadata bytes as frame data
-    output.write_all(&metadata_bytes)?;
+/// Internal helper: write the metadata skippable frames (magic + size + data each)
+/// Metadata larger than [`METADATA_FRAME_CHUNK_SIZE`] is split over consecutive frames
+pub(crate) fn write_metadata_frame<W: Write>(
+    output: &mut W,
+    magic: u32,
+    metadata_bytes: &[u8],
+) -> Result<()> {
+    if metadata_bytes.len() > MAX_METADATA_SIZE {
+        return Err(ProjzstError::InvalidMetadataLength(metadata_bytes.len()));
+    }
+    for chunk in metadata_bytes.chunks(METADATA_FRAME_CHUNK_SIZE) {
+        write_frame_unchecked(output, magic, chunk)?;
+    }
+    Ok(())
+}
 
-    // Append tar.zst compressed data as a standard ZStd frame
-    let mut zst_encoder = zstd::stream::Encoder::new(&mut output, compression_level)?;
-    {
-        let mut tar_builder = tar::Builder::new(&mut zst_encoder);
-        // Add all files from source directory
-        tar_builder.append_dir_all(".", source_dir)?;
+/// Internal helper: replace the metadata frames at the start of a .pjz file in place,
+/// keeping the encoding and compression they were written with
+/// The payload and the other frames are left untouched
+pub(crate) fn rewrite_metadata(input_file: &Path, metadata: &Metadata) -> Result<()> {
+    let current: Vec<u8> = crate::frames::frames(input_file)?
+        .into_iter()
+        .filter(|frame| frame.kind() == FrameKind::Metadata)
+        .flat_map(|frame| frame.payload)
+        .collect();
+    let compressed = current.starts_with(&COMPRESSED_METADATA_FLAG);
+    let current = decompress_metadata(current)?;
+    // The payload stays as it is, and so does the codec it was written with
+    let (_, codec, dictionary, _) = decode_header_metadata(&current, IgnoreUnknown::On)?;
+    let metadata = &Metadata {
+        codec: codec.recorded(),
+        dictionary,
+        ..metadata.clone()
+    };
+    let encoding = if current.starts_with(&CBOR_METADATA_TAG) {
+        MetadataEncoding::Cbor
+    } else if current.first() == Some(&JSON_METADATA_TAG) {
+        MetadataEncoding::Json
+    } else {
+        MetadataEncoding::MessagePack
+    };
+    let mut metadata_bytes = encode_metadata(metadata, encoding)?;
+    if compressed {
+        metadata_bytes = compress_metadata(&metadata_bytes, crate::DEFAULT_ZSTD_LEVEL)?;
     }
-    // Finalize zstd stream
-    zst_encoder.finish()?;
 
-    Ok(())
+    crate::frames::rewrite_frames(input_file, |frames| {
+        let position = frames
+            .iter()
+            .position(|frame| frame.kind() == FrameKind::Metadata)
+            .unwrap_or(0);
+        frames.retain(|frame| frame.kind() != FrameKind::Metadata);
+        let chunks = metadata_bytes
+            .chunks(METADATA_FRAME_CHUNK_SIZE)
+            .map(|chunk| RawFrame {
+                magic: METADATA_FRAME_MAGIC,
+                payload: chunk.to_vec(),
+            });
+        frames.splice(position..position, chunks);
+    })
+}
+
+/// Internal helper: read metadata from a reader with ignore_unknown parameter
+/// Returns metadata plus the bytes already consumed from the first payload frame, which
+/// must be replayed in front of the reader before decoding the payload (the reader may be
+/// a non-seekable stream such as stdin), and the codec to decode it with
+pub(crate) fn read_metadata_from_reader<R: Read>(
+    reader: &mut R,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, PayloadHead)> {
+    let (metadata, payload_head) =
+        read_header(reader, METADATA_FRAME_MAGIC, ignore_unknown, false)?;
+    Ok((
+        metadata.ok_or(ProjzstError::InvalidFileHeader)?,
+        payload_head,
+    ))
 }
 
-/// Internal helper: read metadata from a file with ignore_unknown parameter
-/// Returns metadata and leaves file cursor at the start of the first ZStd frame
-fn read_metadata_from_file(file: &mut File, ignore_unknown: IgnoreUnknown) -> Result<Metadata> {
+/// Internal helper: read the metadata frames like [`read_metadata_from_reader`]
+/// Metadata is read from the frames of `metadata_magic`; with `allow_missing_metadata`, a
+/// stream starting right away with a regular ZStd frame gives `None` instead of failing
+fn read_header<R: Read>(
+    reader: &mut R,
+    metadata_magic: u32,
+    ignore_unknown: IgnoreUnknown,
+    allow_missing_metadata: bool,
+) -> Result<(Option<Metadata>, PayloadHead)> {
     let mut metadata_bytes = Vec::new();
+    let mut payload_head = Vec::new();
+    // Streams of self-extracting archives still start with the script
+    let (_, head) = read_stub(reader)?;
+    let mut reader = io::Cursor::new(head).chain(reader);
 
     loop {
         let mut magic_buf = [0u8; 4];
-        match file.read_exact(&mut magic_buf) {
+        match reader.read_exact(&mut magic_buf) {
             Ok(()) => {}
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                 // EOF while reading magic: if we already have metadata, accept it;
@@ -121,41 +1820,275 @@ fn read_metadata_from_file(file: &mut File, ignore_unknown: IgnoreUnknown) -> Re
         if (SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&magic) {
             // Read frame size (little-endian)
             let mut size_buf = [0u8; 4];
-            file.read_exact(&mut size_buf)?;
-            let frame_size = u32::from_le_bytes(size_buf) as usize;
+            reader.read_exact(&mut size_buf)?;
+            let frame_size = u32::from_le_bytes(size_buf);
+
+            // Auxiliary frames of other magics are not part of the metadata
+            if magic != metadata_magic {
+                let skipped = io::copy(
+                    &mut reader.by_ref().take(u64::from(frame_size)),
+                    &mut io::sink(),
+                )?;
+                if skipped != u64::from(frame_size) {
+                    return Err(ProjzstError::InvalidFileHeader);
+                }
+                continue;
+            }
+            let frame_size = frame_size as usize;
 
-            // Validate total metadata size
-            if metadata_bytes.len() + frame_size > MAX_METADATA_SIZE {
-                return Err(ProjzstError::InvalidMetadataLength(frame_size));
+            // Validate total metadata size over all frames before allocating
+            let total_size = metadata_bytes.len() + frame_size;
+            if total_size > MAX_METADATA_SIZE {
+                return Err(ProjzstError::InvalidMetadataLength(total_size));
             }
 
             // Read frame data
             let mut frame_data = vec![0u8; frame_size];
-            file.read_exact(&mut frame_data)?;
+            reader.read_exact(&mut frame_data)?;
             metadata_bytes.extend_from_slice(&frame_data);
+        } else if metadata_bytes.is_empty() {
+            // No metadata at all: only a plain tar.zst is accepted, and only if asked for
+            if allow_missing_metadata && magic == ZSTD_FRAME_MAGIC {
+                return Ok((None, PayloadHead::zstd(magic_buf.to_vec())));
+            }
+            return Err(ProjzstError::InvalidFileHeader);
         } else {
             // Not a skippable frame - assume it's the start of ZStd compressed data
-            // Rewind so the ZStd decoder can read the magic again
-            file.seek(SeekFrom::Current(-4))?;
+            // Hand the magic back so the ZStd decoder can read it again
+            payload_head.extend_from_slice(&magic_buf);
             break;
         }
     }
 
-    // Ensure we actually read some metadata
-    if metadata_bytes.is_empty() {
-        return Err(ProjzstError::InvalidFileHeader);
+    let metadata_bytes = decompress_metadata(metadata_bytes)?;
+    let (metadata, codec, dictionary, ignored_fields) =
+        decode_header_metadata(&metadata_bytes, ignore_unknown)?;
+    Ok((
+        Some(metadata),
+        PayloadHead {
+            bytes: payload_head,
+            codec,
+            dictionary,
+            ignored_fields,
+        },
+    ))
+}
+
+/// Internal helper: read metadata and open the tar payload that follows it
+/// Works on non-seekable streams by replaying the bytes consumed while probing frames
+pub(crate) fn open_archive<R: Read>(
+    mut reader: R,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, tar::Archive<impl Read>)> {
+    let (metadata, payload_head) = read_metadata_from_reader(&mut reader, ignore_unknown)?;
+    Ok((metadata, open_payload(payload_head, reader)?))
+}
+
+/// Internal helper: open the tar payload of an archive whose metadata is not needed
+/// Plain tar.zst streams are accepted, and frames of other metadata magics are skipped
+pub(crate) fn open_payload_only<R: Read>(mut reader: R) -> Result<tar::Archive<impl Read>> {
+    let (_, payload_head) =
+        read_header(&mut reader, METADATA_FRAME_MAGIC, IgnoreUnknown::On, true)?;
+    open_payload(payload_head, reader)
+}
+
+/// Internal helper: open the tar payload whose first bytes were already consumed
+pub(crate) fn open_payload<R: Read>(
+    payload_head: PayloadHead,
+    reader: R,
+) -> Result<tar::Archive<impl Read>> {
+    open_payload_with(payload_head, reader, &[])
+}
+
+/// Internal helper: open the tar payload like [`open_payload`], decoding it with the one
+/// of `dictionaries` it was compressed with, if any
+pub(crate) fn open_payload_with<R: Read>(
+    payload_head: PayloadHead,
+    reader: R,
+    dictionaries: &[Dictionary],
+) -> Result<tar::Archive<impl Read>> {
+    Ok(tar::Archive::new(decompress_payload(
+        payload_head,
+        reader,
+        crate::DEFAULT_BUFFER_SIZE,
+        dictionaries,
+    )?))
+}
+
+/// Internal helper: decompress the payload into the plain tar stream with the codec
+/// recorded in its metadata, reading the compressed stream through a buffer of
+/// `buffer_size` bytes
+/// Fails with [`ProjzstError::DictionaryRequired`] when the metadata records a dictionary
+/// not among `dictionaries`
+fn decompress_payload<R: Read>(
+    payload_head: PayloadHead,
+    reader: R,
+    buffer_size: usize,
+    dictionaries: &[Dictionary],
+) -> Result<impl Read> {
+    let dictionary = crate::dictionary::required(payload_head.dictionary, dictionaries)?;
+    let input = io::Cursor::new(payload_head.bytes).chain(reader);
+    PayloadDecoder::new(
+        payload_head.codec,
+        BufReader::with_capacity(buffer_size.max(1), input),
+        dictionary,
+    )
+}
+
+/// Internal helper: the writer behind a buffer, once everything buffered is written
+fn unbuffer<W: Write>(output: BufWriter<W>) -> Result<W> {
+    Ok(output
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?)
+}
+
+/// Internal helper: deserialize metadata in whichever encoding it was written in
+/// and check its format version
+pub(crate) fn decode_metadata(
+    metadata_bytes: &[u8],
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let (mut metadata, _) = decode_versioned_metadata(metadata_bytes, ignore_unknown)?;
+    metadata.codec = None;
+    metadata.dictionary = None;
+    Ok(metadata)
+}
+
+/// Internal helper: deserialize the metadata at the start of an archive like
+/// [`decode_metadata`], also returning the payload codec and dictionary ID it records and
+/// the unknown fields ignored under [`IgnoreUnknown::On`]
+pub(crate) fn decode_header_metadata(
+    metadata_bytes: &[u8],
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, PayloadCodec, Option<u32>, Vec<String>)> {
+    let (mut metadata, ignored) = decode_versioned_metadata(metadata_bytes, ignore_unknown)?;
+    let codec = PayloadCodec::from_recorded(metadata.codec.take().as_deref())?;
+    let dictionary = metadata.dictionary.take();
+    Ok((metadata, codec, dictionary, ignored))
+}
+
+/// Internal helper: deserialize metadata and check its format version
+/// Also returns the unknown fields ignored under [`IgnoreUnknown::On`]
+fn decode_versioned_metadata(
+    metadata_bytes: &[u8],
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, Vec<String>)> {
+    let (mut metadata, ignored) =
+        if let Some(cbor) = metadata_bytes.strip_prefix(&CBOR_METADATA_TAG) {
+            let value: serde_json::Value = ciborium::from_reader(cbor)?;
+            decode_metadata_value(value, ignore_unknown)?
+        } else if metadata_bytes.first() == Some(&JSON_METADATA_TAG) {
+            let value: serde_json::Value = serde_json::from_slice(metadata_bytes)?;
+            decode_metadata_value(value, ignore_unknown)?
+        } else {
+            decode_msgpack_metadata(metadata_bytes, ignore_unknown)?
+        };
+
+    let found = metadata.format_version.take().unwrap_or(1);
+    if !SUPPORTED_FORMAT_VERSIONS.contains(&found) {
+        return Err(ProjzstError::UnsupportedVersion {
+            found,
+            supported: SUPPORTED_FORMAT_VERSIONS,
+        });
+    }
+    Ok((metadata, ignored))
+}
+
+/// Internal helper: deserialize metadata already parsed into a JSON value (CBOR, JSON)
+/// with ignore_unknown handling, also returning the unknown fields ignored
+fn decode_metadata_value(
+    value: serde_json::Value,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, Vec<String>)> {
+    match ignore_unknown {
+        IgnoreUnknown::On => {
+            let mut unknown_fields = Vec::new();
+            let metadata: Metadata = serde_ignored::deserialize(value, |path| {
+                unknown_fields.push(path.to_string());
+            })?;
+            Ok((metadata, unknown_fields))
+        }
+        IgnoreUnknown::Off => {
+            let mut unknown_fields = Vec::new();
+            let metadata: Metadata = serde_ignored::deserialize(value, |path| {
+                unknown_fields.push(path.to_string());
+            })?;
+            if !unknown_fields.is_empty() {
+                return Err(ProjzstError::UnknownFields(unknown_fields.join(", ")));
+            }
+            Ok((metadata, Vec::new()))
+        }
+        IgnoreUnknown::Export => match value {
+            serde_json::Value::Object(map) => Ok((split_unknown_fields(map)?, Vec::new())),
+            value => Ok((serde_json::from_value(value)?, Vec::new())),
+        },
+    }
+}
+
+/// Internal helper: deserialize the known fields of a metadata object and move the
+/// others into extra.ignored
+fn split_unknown_fields(map: serde_json::Map<String, serde_json::Value>) -> Result<Metadata> {
+    // Known fields we want to extract
+    let known_fields = [
+        "name",
+        "auth",
+        "fmt",
+        "ed",
+        "ver",
+        "desc",
+        "extra",
+        "license",
+        "homepage",
+        "repository",
+        "keywords",
+        "deps",
+        "pjz_version",
+        "pjz_codec",
+        "pjz_dict",
+    ];
+
+    // Build a map of known fields
+    let mut known_map = serde_json::Map::new();
+    let mut unknown_map = serde_json::Map::new();
+
+    for (key, value) in map {
+        if known_fields.contains(&key.as_str()) {
+            known_map.insert(key, value);
+        } else {
+            unknown_map.insert(key, value);
+        }
+    }
+
+    // Deserialize known fields into Metadata
+    let known_value = serde_json::Value::Object(known_map);
+    let mut metadata: Metadata = serde_json::from_value(known_value)?;
+
+    // Merge unknown fields into extra.ignored
+    if !unknown_map.is_empty() {
+        metadata.merge_unknown_fields(serde_json::Value::Object(unknown_map));
     }
 
-    // Deserialize MessagePack to Metadata struct with ignore_unknown handling
+    Ok(metadata)
+}
+
+/// Internal helper: deserialize MessagePack metadata with ignore_unknown handling
+fn decode_msgpack_metadata(
+    metadata_bytes: &[u8],
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, Vec<String>)> {
     match ignore_unknown {
         IgnoreUnknown::On => {
-            // Silently ignore unknown fields
-            let metadata: Metadata = rmp_serde::from_slice(&metadata_bytes)?;
-            Ok(metadata)
+            // Ignore unknown fields, only listing them
+            let mut deserializer = rmp_serde::Deserializer::new(metadata_bytes);
+            let mut unknown_fields = Vec::new();
+            let metadata: Metadata = serde_ignored::deserialize(&mut deserializer, |path| {
+                unknown_fields.push(path.to_string());
+            })?;
+            Ok((metadata, unknown_fields))
         }
         IgnoreUnknown::Off => {
             // Check for unknown fields using serde_ignored
-            let mut deserializer = rmp_serde::Deserializer::new(&metadata_bytes[..]);
+            let mut deserializer = rmp_serde::Deserializer::new(metadata_bytes);
             let mut unknown_fields = Vec::new();
 
             let metadata: Metadata = serde_ignored::deserialize(&mut deserializer, |path| {
@@ -166,43 +2099,575 @@ fn read_metadata_from_file(file: &mut File, ignore_unknown: IgnoreUnknown) -> Re
                 return Err(ProjzstError::UnknownFields(unknown_fields.join(", ")));
             }
 
-            Ok(metadata)
+            Ok((metadata, Vec::new()))
         }
         IgnoreUnknown::Export => {
             // Deserialize into a generic Value first
-            let full_value: serde_json::Value = rmp_serde::from_slice(&metadata_bytes)?;
+            let full_value: serde_json::Value = rmp_serde::from_slice(metadata_bytes)?;
 
             if let serde_json::Value::Object(map) = full_value {
-                // Known fields we want to extract
-                let known_fields = ["name", "auth", "fmt", "ed", "ver", "desc", "extra"];
-
-                // Build a map of known fields
-                let mut known_map = serde_json::Map::new();
-                let mut unknown_map = serde_json::Map::new();
-
-                for (key, value) in map {
-                    if known_fields.contains(&key.as_str()) {
-                        known_map.insert(key, value);
-                    } else {
-                        unknown_map.insert(key, value);
-                    }
-                }
+                Ok((split_unknown_fields(map)?, Vec::new()))
+            } else {
+                // Not an object - just try normal deserialization
+                Ok((rmp_serde::from_slice(metadata_bytes)?, Vec::new()))
+            }
+        }
+    }
+}
+
+/// Options for reading and unpacking .pjz files
+/// Every method also returns whether the input carried metadata, which is always the case
+/// unless [`allow_missing_metadata`](Self::allow_missing_metadata) is set
+///
+/// ```no_run
+/// use projzst::{IgnoreUnknown, ReadOptions};
+///
+/// let (metadata, has_metadata) = ReadOptions::new(IgnoreUnknown::On)
+///     .allow_missing_metadata(true)
+///     .unpack("backup.tar.zst", "./output")?;
+/// # Ok::<(), projzst::ProjzstError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub(crate) ignore_unknown: IgnoreUnknown,
+    pub(crate) allow_missing_metadata: bool,
+    pub(crate) post_verify: bool,
+    pub(crate) schemas: Option<SchemaRegistry>,
+    pub(crate) policy: Option<MetadataPolicy>,
+    pub(crate) path_map: PathMap,
+    pub(crate) reject_collisions: bool,
+    pub(crate) check_free_space: bool,
+    pub(crate) concurrency: Option<Concurrency>,
+    pub(crate) keep_going: bool,
+    pub(crate) buffer_size: usize,
+    pub(crate) recursive_depth: usize,
+    pub(crate) nested_size_limit: u64,
+    pub(crate) metadata_magic: Option<u32>,
+    pub(crate) dictionaries: Vec<Dictionary>,
+    #[cfg(feature = "sandbox")]
+    pub(crate) sandboxed: bool,
+}
 
-                // Deserialize known fields into Metadata
-                let known_value = serde_json::Value::Object(known_map);
-                let mut metadata: Metadata = serde_json::from_value(known_value)?;
+impl ReadOptions {
+    /// Create options handling unknown metadata fields as `ignore_unknown` says
+    pub fn new(ignore_unknown: IgnoreUnknown) -> Self {
+        Self {
+            ignore_unknown,
+            allow_missing_metadata: false,
+            post_verify: false,
+            schemas: None,
+            policy: None,
+            path_map: PathMap::default(),
+            reject_collisions: false,
+            check_free_space: false,
+            concurrency: None,
+            keep_going: false,
+            buffer_size: crate::DEFAULT_BUFFER_SIZE,
+            recursive_depth: 0,
+            nested_size_limit: DEFAULT_NESTED_SIZE_LIMIT,
+            metadata_magic: None,
+            dictionaries: Vec::new(),
+            #[cfg(feature = "sandbox")]
+            sandboxed: false,
+        }
+    }
 
-                // Merge unknown fields into extra.ignored
-                if !unknown_map.is_empty() {
-                    metadata.merge_unknown_fields(serde_json::Value::Object(unknown_map));
-                }
+    /// Drop the first `count` components of every entry path when unpacking, like
+    /// `tar --strip-components`; entries with no more components than that are skipped
+    pub fn strip_components(mut self, count: usize) -> Self {
+        self.path_map.strip_components = count;
+        self
+    }
 
-                Ok(metadata)
-            } else {
-                // Not an object - just try normal deserialization
-                Ok(rmp_serde::from_slice(&metadata_bytes)?)
+    /// What to do with device nodes and FIFOs in the archive (default: leave them out)
+    /// [`SpecialPolicy::Include`] recreates them, which for devices takes privileges
+    pub fn special_entries(mut self, policy: SpecialPolicy) -> Self {
+        self.path_map.special = policy;
+        self
+    }
+
+    /// What to do with entry names Windows cannot create, such as `CON` or `notes.`
+    /// (default: [`WindowsNamePolicy::Sanitize`] on Windows, [`WindowsNamePolicy::Keep`]
+    /// elsewhere); applies after [`ReadOptions::transform`]
+    pub fn windows_names(mut self, policy: WindowsNamePolicy) -> Self {
+        self.path_map.windows_names = policy;
+        self
+    }
+
+    /// What to do with the modification times stored in the archive (default: restore
+    /// them, with sub-second precision when packed with [`Packer::subsecond_mtimes`])
+    pub fn mtimes(mut self, policy: MtimePolicy) -> Self {
+        self.path_map.mtime = policy;
+        self
+    }
+
+    /// Thread counts for hashing when verifying written files (default:
+    /// [`Concurrency::current_default`])
+    pub fn concurrency(mut self, concurrency: Concurrency) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Refuse to unpack a file with entries that collide on case-insensitive or
+    /// normalizing file systems (`README` and `readme`), with
+    /// [`ProjzstError::PathCollision`]; stream inputs are not checked
+    pub fn reject_collisions(mut self, reject: bool) -> Self {
+        self.reject_collisions = reject;
+        self
+    }
+
+    /// Refuse to unpack a file whose entries need more space than the file system of the
+    /// output directory has left, with [`ProjzstError::InsufficientSpace`], before anything
+    /// is written; see [`ReadOptions::required_space`]. Stream inputs are not checked
+    pub fn check_free_space(mut self, check: bool) -> Self {
+        self.check_free_space = check;
+        self
+    }
+
+    /// Extract what can be when some entries cannot be: an entry failing to extract (corrupt
+    /// content, unsafe path, file system error) is logged as a warning and skipped, and a
+    /// payload that cannot be read to its end stops extraction without failing
+    /// Failures are listed in the [`UnpackReport`] of [`ReadOptions::unpack_with_report`];
+    /// an entry that failed while being written may be left partly written
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Rewrite entry paths when unpacking, after [`ReadOptions::strip_components`]
+    /// `transform` gets the `/`-separated path and returns the path to extract to, or
+    /// `None` (or an empty path) to skip the entry. Transforms run in the order added,
+    /// and unpacking fails with [`ProjzstError::UnsafeEntryPath`] for a path leaving
+    /// the output directory
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.path_map.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Decide for each entry, before its path is mapped, whether to extract it, skip it or
+    /// stop unpacking there, replacing any filter set before
+    /// `filter` gets the entry as [`list_entries`](crate::list_entries) describes it, with
+    /// its path in the archive. Directories extracted before a stop still get their
+    /// permissions and times
+    pub fn entry_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ArchiveEntry) -> EntryAction + Send + Sync + 'static,
+    {
+        self.path_map.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// After unpacking, also unpack the .pjz files found in the output directory (by
+    /// their leading metadata frame), in place: `name.pjz` becomes the directory `name`,
+    /// with its metadata in `name.metadata.json`. Archives found in those are unpacked
+    /// too, down to `max_depth` levels (`0`, the default, unpacks nothing nested)
+    /// An archive nested in a copy of itself, or beyond the depth or the
+    /// [`nested_size_limit`](Self::nested_size_limit), is left packed and listed in
+    /// [`UnpackReport::nested_skipped`]
+    pub fn recursive(mut self, max_depth: usize) -> Self {
+        self.recursive_depth = max_depth;
+        self
+    }
+
+    /// Largest total size of the files unpacked from nested archives with
+    /// [`ReadOptions::recursive`] (default: 4 GiB); an archive that would go over it is
+    /// left packed. Sizes are read from the entry headers before anything is written
+    pub fn nested_size_limit(mut self, bytes: u64) -> Self {
+        self.nested_size_limit = bytes;
+        self
+    }
+
+    /// Read the metadata from skippable frames of another magic than `0x184D2A50`, as
+    /// written with [`Packer::metadata_magic`]; frames of the default magic are then
+    /// skipped like other foreign frames
+    pub fn metadata_magic(mut self, magic: u32) -> Self {
+        self.metadata_magic = Some(magic);
+        self
+    }
+
+    /// Decode payloads compressed with `dictionary` (see [`Packer::dictionary`]); may be
+    /// given several times. Archives recording a dictionary not given fail with
+    /// [`ProjzstError::DictionaryRequired`]
+    pub fn dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionaries.push(dictionary);
+        self
+    }
+
+    /// Take the chunks a payload leaves out from the chunk store archive `store`, for
+    /// archives of a package set (see [`crate::pack_set`]); they fail with
+    /// [`ProjzstError::InvalidChunk`] without it, and with
+    /// [`ProjzstError::ChunkStoreMismatch`] before anything is extracted when `store` is
+    /// not the one recorded in `extra.chunk_store`
+    pub fn chunk_store<P: AsRef<Path>>(mut self, store: P) -> Self {
+        self.path_map.chunk_store = Some(store.as_ref().to_path_buf());
+        self
+    }
+
+    /// Accept plain `.tar.zst` files as archives with [`Metadata::default`] instead of
+    /// failing with [`ProjzstError::InvalidFileHeader`]
+    pub fn allow_missing_metadata(mut self, allow: bool) -> Self {
+        self.allow_missing_metadata = allow;
+        self
+    }
+
+    /// Flush every extracted file to disk before unpacking returns (default: leave it to
+    /// the operating system)
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.path_map.sync = sync;
+        self
+    }
+
+    /// Size in bytes of the read buffer feeding the zstd decoder (default:
+    /// [`DEFAULT_BUFFER_SIZE`](crate::DEFAULT_BUFFER_SIZE))
+    /// Larger buffers mean fewer, larger reads, which network file systems reward
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = bytes;
+        self
+    }
+
+    /// Write small files in batches through io_uring, opening, writing and syncing a
+    /// batch in a few system calls (default: off)
+    /// Falls back to regular writes when the kernel does not offer io_uring; not used
+    /// with [`ReadOptions::post_verify`]
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn io_uring(mut self, io_uring: bool) -> Self {
+        self.path_map.io_uring = io_uring;
+        self
+    }
+
+    /// Create every entry relative to a handle on the output directory, through which no
+    /// path resolves outside of it, not even by way of `..` or an extracted symlink
+    /// (default: off); on Linux the kernel enforces this with `openat2(RESOLVE_BENEATH)`
+    /// Entries that would leave the output fail to extract. Neither
+    /// [`ReadOptions::post_verify`] nor io_uring batching applies to sandboxed unpacks
+    #[cfg(feature = "sandbox")]
+    pub fn sandboxed(mut self, sandboxed: bool) -> Self {
+        self.sandboxed = sandboxed;
+        self
+    }
+
+    /// Internal helper: whether entries are created through a handle on the output
+    #[cfg(feature = "sandbox")]
+    fn is_sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    /// Internal helper: sandboxed unpacks need the `sandbox` feature
+    #[cfg(not(feature = "sandbox"))]
+    fn is_sandboxed(&self) -> bool {
+        false
+    }
+
+    /// After unpacking, read every written file and symlink back and compare it with the
+    /// archive content, failing with [`ProjzstError::ExtractionMismatch`] on a difference
+    /// Digests are taken while extracting, so streams are still read only once
+    pub fn post_verify(mut self, verify: bool) -> Self {
+        self.post_verify = verify;
+        self
+    }
+
+    /// Check `extra` against the schema registered for its `(fmt, ed)` pair, failing
+    /// with [`ProjzstError::SchemaViolations`] before anything is extracted
+    pub fn schemas(mut self, schemas: SchemaRegistry) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
+    /// Reject archives whose metadata does not meet `policy`, failing with
+    /// [`ProjzstError::PolicyViolations`] before anything is extracted
+    /// A plain `.tar.zst` let through by [`allow_missing_metadata`](Self::allow_missing_metadata)
+    /// is checked as empty metadata
+    pub fn policy(mut self, policy: MetadataPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Read only metadata from a .pjz file, see [`read_metadata`]
+    /// The latest revision appended with [`append_metadata`](crate::append_metadata) wins
+    pub fn read_metadata<P: AsRef<Path>>(&self, input_file: P) -> Result<(Metadata, bool)> {
+        let input_file = input_file.as_ref();
+        let (metadata, _) = self.read_header(&mut open_input(input_file)?)?;
+        let metadata = latest_revision(input_file, self.ignore_unknown)?.or(metadata);
+        self.check_metadata(metadata.as_ref())?;
+        let has_metadata = metadata.is_some();
+        Ok((metadata.unwrap_or_default(), has_metadata))
+    }
+
+    /// Read only metadata from a .pjz stream, see [`read_metadata_from`]
+    pub fn read_metadata_from<R: Read>(&self, mut reader: R) -> Result<(Metadata, bool)> {
+        let (metadata, _) = self.read_header(&mut reader)?;
+        self.check_metadata(metadata.as_ref())?;
+        let has_metadata = metadata.is_some();
+        Ok((metadata.unwrap_or_default(), has_metadata))
+    }
+
+    /// List the entries of a .pjz file that collide on case-insensitive or normalizing file
+    /// systems once mapped by these options, see [`crate::path_collisions`]
+    pub fn path_collisions<P: AsRef<Path>>(&self, input_file: P) -> Result<Vec<PathCollision>> {
+        let (_, mut tar_archive) =
+            open_archive(open_input(input_file.as_ref())?, IgnoreUnknown::On)?;
+        let mut paths = Vec::new();
+        for entry in tar_archive.entries()? {
+            let entry = entry?;
+            let path = self.path_map.map(&entry.path()?)?;
+            if let Some(path) = path.filter(|_| !entry.header().entry_type().is_dir()) {
+                paths.push(path);
+            }
+        }
+        Ok(find_collisions(paths))
+    }
+
+    /// Bytes unpacking a .pjz file would take on disk once mapped by these options, from
+    /// its tar headers: regular files rounded up to whole blocks and a block per directory
+    /// Hard links and other special entries take no space of their own
+    pub fn required_space<P: AsRef<Path>>(&self, input_file: P) -> Result<u64> {
+        let (_, mut tar_archive) =
+            open_archive(open_input(input_file.as_ref())?, IgnoreUnknown::On)?;
+        let mut needed = 0u64;
+        for entry in tar_archive.entries()? {
+            let entry = entry?;
+            if self.path_map.map(&entry.path()?)?.is_none() {
+                continue;
+            }
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_file() {
+                needed = needed.saturating_add(blocks_for(entry.size()));
+            } else if entry_type.is_dir() {
+                needed = needed.saturating_add(SPACE_BLOCK);
+            }
+        }
+        Ok(needed)
+    }
+
+    /// Unpack a .pjz file to target directory, see [`unpack`]
+    /// The latest appended metadata revision is the one written to metadata.json; with
+    /// [`ReadOptions::reject_collisions`] or [`WindowsNamePolicy::Reject`], every entry path
+    /// is checked before anything is written, as is the free space with
+    /// [`ReadOptions::check_free_space`]
+    pub fn unpack<P1, P2>(&self, input_file: P1, output_dir: P2) -> Result<(Metadata, bool)>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let (metadata, has_metadata, _) = self.unpack_with_report(input_file, output_dir)?;
+        Ok((metadata, has_metadata))
+    }
+
+    /// Unpack a .pjz file like [`ReadOptions::unpack`], also returning the entries
+    /// extracted and, with [`ReadOptions::keep_going`], those that failed
+    pub fn unpack_with_report<P1, P2>(
+        &self,
+        input_file: P1,
+        output_dir: P2,
+    ) -> Result<(Metadata, bool, UnpackReport)>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let input_file = input_file.as_ref();
+        let latest = latest_revision(input_file, self.ignore_unknown)?;
+        if self.reject_collisions || self.path_map.windows_names == WindowsNamePolicy::Reject {
+            // Fail on the first unportable name or collision before extracting anything
+            if let Some(collision) = self.path_collisions(input_file)?.into_iter().next() {
+                if self.reject_collisions {
+                    return Err(ProjzstError::PathCollision {
+                        first: collision.first,
+                        second: collision.second,
+                        kind: collision.kind.name().to_string(),
+                    });
+                }
             }
         }
+        let output_dir = output_dir.as_ref();
+        if self.check_free_space {
+            ensure_free_space(output_dir, self.required_space(input_file)?)?;
+        }
+        // Stored files of per-entry payloads are copied from the file, not decoded
+        let framed = if self.post_verify || self.is_sandboxed() {
+            None
+        } else {
+            FramedPayload::open(input_file)?
+        };
+        let extracted = self.extract(open_input(input_file)?, output_dir, latest, framed)?;
+        self.finish(output_dir, extracted)
+    }
+
+    /// Unpack a .pjz stream to target directory, see [`unpack_from_reader`]
+    /// No metadata.json is written for an input without metadata
+    pub fn unpack_from_reader<R, P>(&self, reader: R, output_dir: P) -> Result<(Metadata, bool)>
+    where
+        R: Read,
+        P: AsRef<Path>,
+    {
+        let (metadata, has_metadata, _) =
+            self.unpack_from_reader_with_report(reader, output_dir)?;
+        Ok((metadata, has_metadata))
+    }
+
+    /// Unpack a .pjz stream like [`ReadOptions::unpack_from_reader`], also returning the
+    /// entries extracted and, with [`ReadOptions::keep_going`], those that failed
+    pub fn unpack_from_reader_with_report<R, P>(
+        &self,
+        reader: R,
+        output_dir: P,
+    ) -> Result<(Metadata, bool, UnpackReport)>
+    where
+        R: Read,
+        P: AsRef<Path>,
+    {
+        let output_dir = output_dir.as_ref();
+        let extracted = self.extract(reader, output_dir, None, None)?;
+        self.finish(output_dir, extracted)
+    }
+
+    /// Internal helper: unpack a stream, with `latest` superseding its own metadata and
+    /// the payload read from `framed` when given
+    fn extract<R: Read>(
+        &self,
+        mut reader: R,
+        output_dir: &Path,
+        latest: Option<Metadata>,
+        framed: Option<FramedPayload>,
+    ) -> Result<(Metadata, bool, UnpackReport)> {
+        // Read metadata, then decompress zstd and open the tar archive
+        let (metadata, mut payload_head) = self.read_header(&mut reader)?;
+        self.check_metadata(latest.as_ref().or(metadata.as_ref()))?;
+        self.check_chunk_store(latest.as_ref().or(metadata.as_ref()))?;
+        let mut report = UnpackReport::default();
+        report.ignored_fields(std::mem::take(&mut payload_head.ignored_fields));
+        // Frames are decoded one by one without the dictionary, so read them in order
+        let framed = framed.filter(|_| payload_head.dictionary.is_none());
+        let payload =
+            decompress_payload(payload_head, reader, self.buffer_size, &self.dictionaries)?;
+
+        // Create output directory and extract files
+        fs::create_dir_all(output_dir)?;
+        if self.is_sandboxed() {
+            #[cfg(feature = "sandbox")]
+            unpack_sandboxed(
+                payload,
+                output_dir,
+                &self.path_map,
+                self.keep_going,
+                &mut report,
+            )?;
+        } else if let Some(framed) = &framed {
+            unpack_framed(
+                framed,
+                output_dir,
+                &self.path_map,
+                self.keep_going,
+                &mut report,
+            )?;
+        } else if self.post_verify {
+            let hashing = self
+                .concurrency
+                .unwrap_or_else(Concurrency::current_default)
+                .hashing_threads();
+            unpack_verified(
+                payload,
+                output_dir,
+                &self.path_map,
+                hashing,
+                self.keep_going,
+                &mut report,
+            )?;
+        } else {
+            // Also with identity paths: the tar crate has no say over special entries
+            unpack_mapped(
+                payload,
+                output_dir,
+                &self.path_map,
+                self.keep_going,
+                &mut report,
+            )?;
+        }
+
+        match latest.or(metadata) {
+            Some(metadata) => Ok((metadata, true, report)),
+            None => Ok((Metadata::default(), false, report)),
+        }
+    }
+
+    /// Internal helper: write metadata.json to the parent directory of `output_dir`, then
+    /// unpack the nested archives with [`ReadOptions::recursive`]
+    fn finish(
+        &self,
+        output_dir: &Path,
+        (metadata, has_metadata, mut report): (Metadata, bool, UnpackReport),
+    ) -> Result<(Metadata, bool, UnpackReport)> {
+        if has_metadata {
+            let metadata_json_path = output_dir
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join("metadata.json");
+            let json_content = serde_json::to_string_pretty(&metadata)?;
+            fs::write(metadata_json_path, json_content)?;
+        }
+        if self.recursive_depth > 0 {
+            unpack_nested(self, output_dir, &mut report)?;
+        }
+        Ok((metadata, has_metadata, report))
+    }
+
+    /// Internal helper: unpack a .pjz file like [`ReadOptions::unpack_with_report`],
+    /// without writing metadata.json or unpacking nested archives
+    pub(crate) fn unpack_nested_file(
+        &self,
+        input_file: &Path,
+        output_dir: &Path,
+    ) -> Result<(Metadata, bool, UnpackReport)> {
+        let latest = latest_revision(input_file, self.ignore_unknown)?;
+        let framed = FramedPayload::open(input_file)?.filter(|_| !self.post_verify);
+        self.extract(open_input(input_file)?, output_dir, latest, framed)
+    }
+
+    /// Internal helper: read the metadata frames with these options
+    pub(crate) fn read_header<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<(Option<Metadata>, PayloadHead)> {
+        let magic = self.metadata_magic.unwrap_or(METADATA_FRAME_MAGIC);
+        check_metadata_magic(magic)?;
+        read_header(
+            reader,
+            magic,
+            self.ignore_unknown,
+            self.allow_missing_metadata,
+        )
+    }
+
+    /// Internal helper: fail when the chunk store set with [`ReadOptions::chunk_store`] is
+    /// not the one `extra.chunk_store` of the metadata records
+    fn check_chunk_store(&self, metadata: Option<&Metadata>) -> Result<()> {
+        let (Some(store), Some(metadata)) = (&self.path_map.chunk_store, metadata) else {
+            return Ok(());
+        };
+        let expected = metadata
+            .extra
+            .get(CHUNK_STORE_KEY)
+            .and_then(|reference| reference.get("sha256"))
+            .and_then(|sha256| sha256.as_str());
+        match expected {
+            Some(expected) if archive_sha256(store)? != expected => Err(
+                ProjzstError::ChunkStoreMismatch(store.display().to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Internal helper: enforce the policy and the registered schemas, if any
+    pub(crate) fn check_metadata(&self, metadata: Option<&Metadata>) -> Result<()> {
+        if let Some(policy) = &self.policy {
+            policy.check(metadata.unwrap_or(&Metadata::default()))?;
+        }
+        match (&self.schemas, metadata) {
+            (Some(schemas), Some(metadata)) => schemas.validate(metadata),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -210,80 +2675,117 @@ fn read_metadata_from_file(file: &mut File, ignore_unknown: IgnoreUnknown) -> Re
 /// Returns the metadata found in the skippable frames
 ///
 /// # Arguments
-/// * `input_file` - Path to the .pjz file
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
 /// * `ignore_unknown` - How to handle unknown fields in metadata
 pub fn read_metadata<P: AsRef<Path>>(
     input_file: P,
     ignore_unknown: IgnoreUnknown,
 ) -> Result<Metadata> {
-    let mut file = File::open(input_file.as_ref())?;
-    read_metadata_from_file(&mut file, ignore_unknown)
+    let (metadata, _) = ReadOptions::new(ignore_unknown).read_metadata(input_file)?;
+    Ok(metadata)
+}
+
+/// Read the extra metadata of a .pjz file into a caller-provided type
+/// Shorthand for [`read_metadata`] followed by [`Metadata::read_extra_as`]
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn read_metadata_as<T: DeserializeOwned>(input_file: impl AsRef<Path>) -> Result<T> {
+    read_metadata(input_file, IgnoreUnknown::On)?.read_extra_as()
+}
+
+/// Read only metadata from a .pjz stream (e.g. stdin)
+/// Consumes the skippable frames and the first bytes of the payload
+///
+/// # Arguments
+/// * `reader` - Source of the .pjz stream
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn read_metadata_from<R: Read>(reader: R, ignore_unknown: IgnoreUnknown) -> Result<Metadata> {
+    let (metadata, _) = ReadOptions::new(ignore_unknown).read_metadata_from(reader)?;
+    Ok(metadata)
 }
 
 /// Unpack a .pjz file to target directory
 /// Extracts content, writes metadata.json to parent directory of output,
-/// and returns the metadata
+/// and returns the metadata. Either side may be on a storage backend
+/// ([`Storage::at`](crate::Storage::at)), in which case only the regular files are
+/// extracted
 ///
 /// # Arguments
-/// * `input_file` - Path to the .pjz file
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
 /// * `output_dir` - Directory to extract contents to
 /// * `ignore_unknown` - How to handle unknown fields in metadata
-pub fn unpack<P1, P2>(
-    input_file: P1,
-    output_dir: P2,
+pub fn unpack<L1, L2>(
+    input_file: L1,
+    output_dir: L2,
     ignore_unknown: IgnoreUnknown,
 ) -> Result<Metadata>
 where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
+    L1: Location,
+    L2: Location,
 {
-    let input_file = input_file.as_ref();
-    let output_dir = output_dir.as_ref();
-
-    let mut file = File::open(input_file)?;
-    // Read metadata and position cursor at start of ZStd frame
-    let metadata = read_metadata_from_file(&mut file, ignore_unknown)?;
-
-    // Decompress zstd and extract tar archive
-    // File cursor is now at the start of the ZStd compressed data
-    let zst_decoder = zstd::stream::Decoder::new(&mut file)?;
-    let mut tar_archive = tar::Archive::new(zst_decoder);
-
-    // Create output directory and extract files
-    fs::create_dir_all(output_dir)?;
-    tar_archive.unpack(output_dir)?;
-
-    // Write metadata.json to parent directory of output_dir
-    let metadata_json_path = output_dir
-        .parent()
-        .unwrap_or(Path::new("."))
-        .join("metadata.json");
-    let json_content = serde_json::to_string_pretty(&metadata)?;
-    fs::write(metadata_json_path, json_content)?;
+    match (input_file.place(), output_dir.place()) {
+        (Place::Local(input_file), Place::Local(output_dir)) => {
+            let (metadata, _) = ReadOptions::new(ignore_unknown).unpack(input_file, output_dir)?;
+            Ok(metadata)
+        }
+        (input, output) => with_backend(input, |input, input_path| {
+            with_backend(output, |output, output_prefix| {
+                unpack_backend(input, input_path, output, output_prefix, ignore_unknown)
+            })
+        }),
+    }
+}
 
+/// Unpack a .pjz stream (e.g. stdin) to target directory
+/// Behaves exactly like [`unpack`], but reads the archive from `reader`
+///
+/// # Arguments
+/// * `reader` - Source of the .pjz stream
+/// * `output_dir` - Directory to extract contents to
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn unpack_from_reader<R, P>(
+    reader: R,
+    output_dir: P,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata>
+where
+    R: Read,
+    P: AsRef<Path>,
+{
+    let (metadata, _) = ReadOptions::new(ignore_unknown).unpack_from_reader(reader, output_dir)?;
     Ok(metadata)
 }
 
 /// Extract metadata from .pjz file and save as JSON
-/// Returns the metadata and writes it to the specified JSON file
+/// Returns the metadata and writes it to the specified JSON file; either side may be on
+/// a storage backend ([`Storage::at`](crate::Storage::at))
 ///
 /// # Arguments
 /// * `input_file` - Path to the .pjz file
 /// * `output_json` - Path where to save the JSON file
 /// * `ignore_unknown` - How to handle unknown fields in metadata
-pub fn info<P1, P2>(
-    input_file: P1,
-    output_json: P2,
+pub fn info<L1, L2>(
+    input_file: L1,
+    output_json: L2,
     ignore_unknown: IgnoreUnknown,
 ) -> Result<Metadata>
 where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
+    L1: Location,
+    L2: Location,
 {
+    let (Place::Local(input_file), Place::Local(output_json)) =
+        (input_file.place(), output_json.place())
+    else {
+        return with_backend(input_file.place(), |input, input_path| {
+            with_backend(output_json.place(), |output, output_path| {
+                info_backend(input, input_path, output, output_path, ignore_unknown)
+            })
+        });
+    };
     let metadata = read_metadata(input_file, ignore_unknown)?;
 
     // Create parent directory if needed
-    let output_json = output_json.as_ref();
     if let Some(parent) = output_json.parent() {
         if !parent.as_os_str().is_empty() {
             fs::create_dir_all(parent)?;
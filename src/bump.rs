@@ -0,0 +1,45 @@
+//! Version bumps of packed archives
+//!
+//! [`bump_version`] parses the `ver` of an archive as a semantic version, increments it
+//! with [`Version::bump`] and writes the metadata back without repacking: the metadata
+//! frames at the start of the file are replaced, in the encoding they were written in,
+//! and the payload is copied over unchanged. An archive whose metadata was already edited
+//! with [`append_metadata`] gets a new revision instead, as that is what readers see.
+
+use std::path::Path;
+
+use crate::builder::{read_metadata, rewrite_metadata};
+use crate::deps::{Version, VersionPart};
+use crate::errors::Result;
+use crate::metadata::IgnoreUnknown;
+use crate::revisions::{append_metadata, latest_revision};
+
+/// Increment the version in the metadata of a .pjz file, in place
+/// Returns the previous and the new version; fails with
+/// [`InvalidVersion`](crate::ProjzstError::InvalidVersion) when `ver` is missing or not a
+/// semantic version, and with [`UnknownFields`](crate::ProjzstError::UnknownFields) rather
+/// than drop metadata fields it does not know
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `part` - Number to increment, or `None` to only change the pre-release
+/// * `pre` - Pre-release identifiers of the new version (`rc.1`)
+pub fn bump_version<P: AsRef<Path>>(
+    input_file: P,
+    part: Option<VersionPart>,
+    pre: Option<&str>,
+) -> Result<(Version, Version)> {
+    let input_file = input_file.as_ref();
+    let mut metadata = read_metadata(input_file, IgnoreUnknown::Off)?;
+    let current = metadata.version()?;
+    let next = current.bump(part, pre)?;
+    metadata.ver = Some(next.to_string());
+
+    if latest_revision(input_file, IgnoreUnknown::On)?.is_some() {
+        append_metadata(input_file, &metadata)?;
+    } else {
+        rewrite_metadata(input_file, &metadata)?;
+    }
+    log::info!("{}: {current} -> {next}", input_file.display());
+    Ok((current, next))
+}
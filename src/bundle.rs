@@ -0,0 +1,158 @@
+//! Bundles: archives made of other archives
+//!
+//! A bundle ships a product made of several packages as one .pjz file. Its entries are
+//! member .pjz files, stored as they are (they are compressed already) in frames of their
+//! own, and its metadata carries a manifest under `extra.bundle` listing every member with
+//! its name, version, digest and size. The manifest is read without touching the payload,
+//! and the frame index lets [`extract_member`] decode just the frames of the member asked
+//! for, so one package comes out of a large bundle without unpacking the rest.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builder::{read_metadata, Packer, SourceRoot};
+use crate::catalog::file_digest;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::ext::ExtraNamespace;
+use crate::metadata::IgnoreUnknown;
+use crate::preview::write_entry;
+use crate::report::PackReport;
+use crate::temp::temp_file_for;
+
+/// Manifest of a bundle, stored under `extra.bundle`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BundleManifest {
+    /// Member archives, in the order they were given
+    pub members: Vec<BundleMember>,
+}
+
+impl ExtraNamespace for BundleManifest {
+    const KEY: &'static str = "bundle";
+}
+
+/// One archive of a bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BundleMember {
+    /// Path of the member inside the bundle (its file name)
+    pub path: String,
+    /// Package name from the member's metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Version from the member's metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ver: Option<String>,
+    /// Digest of the member file, `sha256:<hex>`
+    pub digest: String,
+    /// Size of the member file in bytes
+    pub size: u64,
+}
+
+impl BundleMember {
+    /// Whether `member` names this member: its path, its package name, or `name@ver`
+    pub fn is_named(&self, member: &str) -> bool {
+        let name = self.name.as_deref();
+        match member.split_once('@') {
+            Some((wanted, ver)) => name == Some(wanted) && self.ver.as_deref() == Some(ver),
+            None => self.path == member || name == Some(member),
+        }
+    }
+}
+
+impl Packer {
+    /// Pack .pjz files into a bundle written to `output_file`
+    /// Members are stored under their file names, uncompressed and in frames of their
+    /// own, and listed in a [`BundleManifest`] added to the packer's metadata; the
+    /// compression level and the other sources of the packer do not apply
+    ///
+    /// # Arguments
+    /// * `members` - Member .pjz files; their file names must differ
+    /// * `output_file` - Path of the bundle to write
+    pub fn pack_bundle<I, P1, P2>(&self, members: I, output_file: P2) -> Result<PackReport>
+    where
+        I: IntoIterator<Item = P1>,
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let mut manifest = BundleManifest::default();
+        let mut roots = Vec::new();
+        for member in members {
+            let file = member.as_ref();
+            let metadata = read_metadata(file, IgnoreUnknown::On)?;
+            let path = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| ProjzstError::SourceNotFound(file.display().to_string()))?;
+            if manifest.members.iter().any(|member| member.path == path) {
+                return Err(ProjzstError::DuplicateBundleMember(path));
+            }
+            manifest.members.push(BundleMember {
+                path: path.clone(),
+                name: metadata.name,
+                ver: metadata.ver,
+                digest: file_digest(file)?,
+                size: fs::metadata(file)?.len(),
+            });
+            roots.push(SourceRoot {
+                path: file.to_path_buf(),
+                prefix: Some(path),
+            });
+        }
+
+        let packer = self
+            .clone()
+            .store_only()
+            .per_entry_frames(true)
+            .extra_value(BundleManifest::KEY, serde_json::to_value(&manifest)?);
+        packer.pack_roots(&roots, output_file.as_ref())
+    }
+}
+
+/// List the members of a bundle from its manifest
+/// Fails with [`ProjzstError::NotABundle`] when the archive has no bundle manifest
+///
+/// # Arguments
+/// * `input_file` - Path to the bundle (or the first volume of a split bundle)
+pub fn bundle_members<P: AsRef<Path>>(input_file: P) -> Result<Vec<BundleMember>> {
+    let metadata = read_metadata(input_file.as_ref(), IgnoreUnknown::On)?;
+    match metadata.namespace::<BundleManifest>()? {
+        Some(manifest) => Ok(manifest.members),
+        None => Err(ProjzstError::NotABundle),
+    }
+}
+
+/// Write one member of a bundle to a file, decoding only the frames it is stored in
+/// The copy is checked against the digest in the manifest before it replaces
+/// `output_file`; returns the member written
+///
+/// # Arguments
+/// * `input_file` - Path to the bundle (or the first volume of a split bundle)
+/// * `member` - Member path, package name, or `name@ver`
+/// * `output_file` - Path of the member file to write
+pub fn extract_member<P1, P2>(input_file: P1, member: &str, output_file: P2) -> Result<BundleMember>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let input_file = input_file.as_ref();
+    let output_file = output_file.as_ref();
+    let found = bundle_members(input_file)?
+        .into_iter()
+        .find(|candidate| candidate.is_named(member))
+        .ok_or_else(|| ProjzstError::BundleMemberNotFound(member.to_string()))?;
+
+    let (mut output, temp) = temp_file_for(output_file)?;
+    write_entry(input_file, &found.path, &mut output)?;
+    drop(output);
+    if file_digest(temp.path())? != found.digest {
+        return Err(ProjzstError::ExtractionMismatch(format!(
+            "{} does not match its digest in the bundle manifest",
+            found.path
+        )));
+    }
+    temp.persist(output_file)?;
+    Ok(found)
+}
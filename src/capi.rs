@@ -0,0 +1,232 @@
+//! C API (`capi` feature)
+//!
+//! Functions exported with C linkage from the `cdylib` build, declared in
+//! `include/projzst.h`, which the build script regenerates with cbindgen. Paths and JSON
+//! are NUL-terminated strings, UTF-8 except for paths on Unix, which are taken as bytes.
+//!
+//! Functions return [`PROJZST_OK`] or the [`ErrorKind`](crate::ErrorKind) code of the
+//! error, the same as the exit codes of the command-line tool; strings returned are
+//! released with [`projzst_string_free`]. The code and message of the last error are kept
+//! per thread for [`projzst_last_error_code`] and [`projzst_last_error_message`]. Panics
+//! do not cross the boundary: they are reported as [`PROJZST_ERROR_OTHER`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::errors::{ErrorKind, ProjzstError};
+use crate::metadata::{IgnoreUnknown, Metadata};
+
+/// The call succeeded
+pub const PROJZST_OK: c_int = 0;
+/// Any other failure, panics included
+pub const PROJZST_ERROR_OTHER: c_int = 1;
+/// The input is not a valid archive
+pub const PROJZST_ERROR_INVALID_FORMAT: c_int = 2;
+/// File system failure
+pub const PROJZST_ERROR_IO: c_int = 3;
+/// Unknown metadata fields were refused
+pub const PROJZST_ERROR_UNKNOWN_FIELDS: c_int = 4;
+/// A check on the archive or its output failed
+pub const PROJZST_ERROR_VERIFICATION: c_int = 5;
+/// An argument is invalid, a null pointer or malformed JSON included
+pub const PROJZST_ERROR_INVALID_INPUT: c_int = 64;
+
+thread_local! {
+    /// Code and message of the last error on this thread
+    static LAST_ERROR: RefCell<(c_int, CString)> = RefCell::new((PROJZST_OK, CString::default()));
+}
+
+/// Pack a directory into a .pjz file
+///
+/// # Arguments
+/// * `source_dir` - Directory to pack
+/// * `output_file` - Path of the .pjz file to write
+/// * `metadata_json` - Metadata as a JSON object (`name`, `auth`, `fmt`, `ed`, `ver`,
+///   `desc`, `extra`, ...), or null for empty metadata
+/// * `level` - Zstd compression level, 1-22 (0 stores the payload uncompressed)
+///
+/// # Safety
+/// Non-null pointers must point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn projzst_pack(
+    source_dir: *const c_char,
+    output_file: *const c_char,
+    metadata_json: *const c_char,
+    level: c_int,
+) -> c_int {
+    status(guard(|| {
+        let source_dir = path_arg(source_dir, "source_dir")?;
+        let output_file = path_arg(output_file, "output_file")?;
+        let metadata: Metadata = if metadata_json.is_null() {
+            Metadata::default()
+        } else {
+            serde_json::from_str(str_arg(metadata_json, "metadata_json")?)
+                .map_err(ProjzstError::from)?
+        };
+        crate::pack(source_dir, output_file, metadata, None::<&str>, level)?;
+        Ok(())
+    }))
+}
+
+/// Unpack a .pjz file into a directory, writing `metadata.json` next to it
+/// Unknown metadata fields are ignored.
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `output_dir` - Directory to extract to, created if needed
+///
+/// # Safety
+/// Non-null pointers must point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn projzst_unpack(
+    input_file: *const c_char,
+    output_dir: *const c_char,
+) -> c_int {
+    status(guard(|| {
+        let input_file = path_arg(input_file, "input_file")?;
+        let output_dir = path_arg(output_dir, "output_dir")?;
+        crate::unpack(input_file, output_dir, IgnoreUnknown::On)?;
+        Ok(())
+    }))
+}
+
+/// Read the metadata of a .pjz file as a JSON object
+/// Returns a string to release with [`projzst_string_free`], or null on error.
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+///
+/// # Safety
+/// `input_file`, when not null, must point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn projzst_read_metadata_json(input_file: *const c_char) -> *mut c_char {
+    let json = guard(|| {
+        let input_file = path_arg(input_file, "input_file")?;
+        let metadata = crate::read_metadata(input_file, IgnoreUnknown::On)?;
+        let json = serde_json::to_string(&metadata).map_err(ProjzstError::from)?;
+        // JSON escapes control characters, NUL included
+        Ok(CString::new(json).expect("JSON has no NUL byte"))
+    });
+    match json {
+        Ok(json) => {
+            status(Ok(()));
+            json.into_raw()
+        }
+        Err(e) => {
+            status(Err(e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a string returned by this library; null is ignored
+///
+/// # Safety
+/// `s` must come from this library and not have been released before.
+#[no_mangle]
+pub unsafe extern "C" fn projzst_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Code of the last call on this thread, [`PROJZST_OK`] when it succeeded
+#[no_mangle]
+pub extern "C" fn projzst_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+/// Message of the last error on this thread, empty when the last call succeeded
+/// The string belongs to the library and is valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn projzst_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().1.as_ptr())
+}
+
+/// Internal helper: why a call failed, with the code it returns
+struct Failure {
+    code: c_int,
+    message: String,
+}
+
+impl From<ProjzstError> for Failure {
+    fn from(e: ProjzstError) -> Self {
+        let code = match e.kind() {
+            ErrorKind::Other => PROJZST_ERROR_OTHER,
+            ErrorKind::InvalidFormat => PROJZST_ERROR_INVALID_FORMAT,
+            ErrorKind::Io => PROJZST_ERROR_IO,
+            ErrorKind::UnknownFields => PROJZST_ERROR_UNKNOWN_FIELDS,
+            ErrorKind::Verification => PROJZST_ERROR_VERIFICATION,
+            ErrorKind::InvalidInput => PROJZST_ERROR_INVALID_INPUT,
+        };
+        Self {
+            code,
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Internal helper: run `f`, turning a panic into a failure
+fn guard<T>(f: impl FnOnce() -> Result<T, Failure>) -> Result<T, Failure> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(Failure {
+            code: PROJZST_ERROR_OTHER,
+            message: format!("panic: {message}"),
+        })
+    })
+}
+
+/// Internal helper: record the outcome of a call as the last error, returning its code
+fn status(result: Result<(), Failure>) -> c_int {
+    let (code, message) = match result {
+        Ok(()) => (PROJZST_OK, String::new()),
+        Err(failure) => (failure.code, failure.message),
+    };
+    let message = CString::new(message.replace('\0', "\\0")).expect("NUL bytes replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = (code, message));
+    code
+}
+
+/// Internal helper: a string argument, failing on null or invalid UTF-8
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if s.is_null() {
+        return Err(invalid_input(format!("{name} is null")));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| invalid_input(format!("{name} is not valid UTF-8")))
+}
+
+/// Internal helper: a path argument, taken as bytes on Unix
+unsafe fn path_arg(s: *const c_char, name: &str) -> Result<PathBuf, Failure> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        if s.is_null() {
+            return Err(invalid_input(format!("{name} is null")));
+        }
+        let bytes = CStr::from_ptr(s).to_bytes();
+        Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        str_arg(s, name).map(PathBuf::from)
+    }
+}
+
+/// Internal helper: a failure for a bad argument
+fn invalid_input(message: String) -> Failure {
+    Failure {
+        code: PROJZST_ERROR_INVALID_INPUT,
+        message,
+    }
+}
@@ -0,0 +1,390 @@
+//! Catalog of the archives in a directory tree
+//!
+//! [`build_catalog`] decodes only the metadata frames of every .pjz file below a
+//! directory (the payload is hashed for the digest, never decompressed) and writes one
+//! JSON file listing them. Runs are incremental: an archive whose size and modification
+//! time match the catalog already on disk keeps its entry without being read again. The
+//! catalog is saved every 64 archives read, so an interrupted run picks up where it
+//! stopped.
+//!
+//! [`train_catalog_dictionary`] trains a zstd [`Dictionary`] on the files of the cataloged
+//! archives and stores it next to the catalog file, for packing more archives of the kind
+//! (see [`catalog_dictionaries`]). Training again adds a dictionary: archives packed with
+//! the earlier ones still need them, so they are kept.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::builder::{open_payload_with, read_metadata, read_metadata_from_reader};
+use crate::concurrency::{parallel_map, Concurrency};
+use crate::dictionary::Dictionary;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::string_utils::to_hex;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+
+/// Archives read between two saves of the catalog
+const CHECKPOINT_INTERVAL: usize = 64;
+
+/// Largest sample taken from one file when training a dictionary: dictionaries help with
+/// the start of small files, not with the rest of large ones
+const MAX_SAMPLE_SIZE: u64 = 128 * 1024;
+
+/// Samples gathered for training, as a multiple of the dictionary size (zstd's advice)
+const SAMPLES_PER_DICTIONARY_BYTE: usize = 100;
+
+/// Catalog file content: every archive found, sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Catalog {
+    /// One entry per archive
+    pub archives: Vec<CatalogEntry>,
+    /// Dictionaries trained for the archives, oldest first; packing uses the last one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dictionaries: Vec<CatalogDictionary>,
+}
+
+/// A dictionary of a [`Catalog`], see [`train_catalog_dictionary`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CatalogDictionary {
+    /// Dictionary file, relative to the directory of the catalog file
+    pub file: String,
+    /// ID of the dictionary, recorded in the archives packed with it
+    pub id: u32,
+    /// Size of the dictionary in bytes
+    pub size: u64,
+    /// Files of the cataloged archives it was trained on
+    pub samples: usize,
+}
+
+/// One archive of a [`Catalog`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CatalogEntry {
+    /// Path relative to the cataloged directory, with `/` separators
+    pub path: String,
+    /// Package name from the metadata
+    pub name: Option<String>,
+    /// Package version from the metadata
+    pub ver: Option<String>,
+    /// Digest of the whole archive file (`sha256:<hex>`), as pinned by dependencies
+    pub digest: String,
+    /// Size of the archive file in bytes
+    pub size: u64,
+    /// Modification time of the archive file in seconds since the Unix epoch
+    pub mtime: u64,
+    /// Latest metadata of the archive
+    pub metadata: Metadata,
+}
+
+/// Outcome of a [`build_catalog`] run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct CatalogReport {
+    /// Archives in the catalog written
+    pub archives: usize,
+    /// Archives read in this run
+    pub indexed: usize,
+    /// Archives whose entry was kept from the previous catalog
+    pub reused: usize,
+    /// Entries of the previous catalog whose archive is gone
+    pub removed: usize,
+    /// Archives left out because they could not be read, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+impl Catalog {
+    /// Load a catalog written by [`build_catalog`]
+    pub fn load<P: AsRef<Path>>(catalog_file: P) -> Result<Self> {
+        let file = File::open(catalog_file.as_ref())?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+
+    /// Internal helper: write the catalog atomically, replacing the file
+    fn save(&self, catalog_file: &Path) -> Result<()> {
+        let (file, temp) = temp_file_for(catalog_file)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        temp.persist(catalog_file)
+    }
+}
+
+/// Write a catalog of the .pjz files below a directory, reusing the entries of an
+/// existing catalog file for archives that have not changed
+/// Unreadable archives are left out and listed in [`CatalogReport::failed`]
+///
+/// # Arguments
+/// * `root` - Directory searched for .pjz files at any depth (symlinks are not followed)
+/// * `catalog_file` - JSON file to write; read first when it exists
+pub fn build_catalog<P1, P2>(root: P1, catalog_file: P2) -> Result<CatalogReport>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let root = root.as_ref();
+    let catalog_file = catalog_file.as_ref();
+    let (mut previous, dictionaries): (HashMap<String, CatalogEntry>, _) =
+        match Catalog::load(catalog_file) {
+            Ok(catalog) => (
+                catalog
+                    .archives
+                    .into_iter()
+                    .map(|entry| (entry.path.clone(), entry))
+                    .collect(),
+                catalog.dictionaries,
+            ),
+            Err(ProjzstError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                (HashMap::new(), Vec::new())
+            }
+            Err(e) => return Err(e),
+        };
+
+    let mut files = Vec::new();
+    find_archives(root, &mut files)?;
+    files.sort();
+
+    let mut report = CatalogReport::default();
+    let mut archives = Vec::new();
+    let mut changed = Vec::new();
+    for file in files {
+        let path = relative_path(root, &file);
+        let stat = fs::metadata(&file)?;
+        let size = stat.len();
+        let mtime = stat
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        match previous.remove(&path) {
+            Some(entry) if entry.size == size && entry.mtime == mtime => {
+                log::debug!("{path}: unchanged");
+                report.reused += 1;
+                archives.push(entry);
+            }
+            _ => changed.push((file, path, size, mtime)),
+        }
+    }
+
+    // Changed archives are hashed on the hashing threads, a checkpoint at a time
+    let threads = Concurrency::current_default().hashing_threads();
+    let batches = changed.chunks(CHECKPOINT_INTERVAL);
+    let batch_count = batches.len();
+    for (index, batch) in batches.enumerate() {
+        let entries = parallel_map(batch, threads, |(file, path, size, mtime)| {
+            catalog_entry(file, path.clone(), *size, *mtime)
+        });
+        for ((_, path, _, _), entry) in batch.iter().zip(entries) {
+            match entry {
+                Ok(entry) => {
+                    log::info!("{path}");
+                    report.indexed += 1;
+                    archives.push(entry);
+                }
+                Err(e) => report.failed.push((path.clone(), e.to_string())),
+            }
+        }
+        if index + 1 < batch_count {
+            // Entries of archives gone from the root are only dropped by the final save
+            let mut checkpoint = archives.clone();
+            checkpoint.extend(previous.values().cloned());
+            checkpoint.sort_by(|a, b| a.path.cmp(&b.path));
+            Catalog {
+                archives: checkpoint,
+                dictionaries: dictionaries.clone(),
+            }
+            .save(catalog_file)?;
+        }
+    }
+
+    archives.sort_by(|a, b| a.path.cmp(&b.path));
+    report.removed = previous.len();
+    report.archives = archives.len();
+    Catalog {
+        archives,
+        dictionaries,
+    }
+    .save(catalog_file)?;
+    Ok(report)
+}
+
+/// Train a dictionary on the files of the archives of a catalog and add it to the catalog
+/// The dictionary is written next to the catalog file, as `<catalog name>-<id>.dict`, and
+/// becomes the one packing uses (see [`catalog_dictionaries`]); earlier ones are kept for
+/// the archives packed with them. Archives that cannot be read are skipped. Fails with
+/// [`ProjzstError::InvalidDictionary`] when the archives hold too little to train on
+///
+/// # Arguments
+/// * `root` - Directory the catalog was built from, see [`build_catalog`]
+/// * `catalog_file` - Catalog written by [`build_catalog`]
+/// * `max_size` - Largest dictionary size in bytes, see
+///   [`DEFAULT_DICTIONARY_SIZE`](crate::DEFAULT_DICTIONARY_SIZE)
+pub fn train_catalog_dictionary<P1, P2>(
+    root: P1,
+    catalog_file: P2,
+    max_size: usize,
+) -> Result<CatalogDictionary>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let root = root.as_ref();
+    let catalog_file = catalog_file.as_ref();
+    let mut catalog = Catalog::load(catalog_file)?;
+    let known = catalog_dictionaries(catalog_file)?;
+
+    let budget = max_size.saturating_mul(SAMPLES_PER_DICTIONARY_BYTE);
+    let mut samples = Vec::new();
+    let mut sampled = 0;
+    for entry in &catalog.archives {
+        if sampled >= budget {
+            break;
+        }
+        let file = root.join(&entry.path);
+        if let Err(e) = sample_archive(&file, &known, &mut samples, &mut sampled) {
+            log::warn!("{}: {e}", entry.path);
+        }
+    }
+    let dictionary = Dictionary::train(&samples, max_size)?;
+
+    let name = catalog_file
+        .file_stem()
+        .map_or("catalog".into(), |stem| stem.to_string_lossy());
+    let file = format!("{name}-{:08x}.dict", dictionary.id());
+    let path = catalog_dir(catalog_file).join(&file);
+    let (mut output, temp) = temp_file_for(&path)?;
+    output.write_all(dictionary.as_bytes())?;
+    output.sync_all()?;
+    temp.persist(&path)?;
+
+    let trained = CatalogDictionary {
+        file,
+        id: dictionary.id(),
+        size: dictionary.as_bytes().len() as u64,
+        samples: samples.len(),
+    };
+    catalog.dictionaries.retain(|known| known.id != trained.id);
+    catalog.dictionaries.push(trained.clone());
+    catalog.save(catalog_file)?;
+    Ok(trained)
+}
+
+/// Load the dictionaries of a catalog, oldest first
+/// Pack with the last one ([`Packer::dictionary`](crate::Packer::dictionary)) and read
+/// archives of the catalog with all of them
+/// ([`ReadOptions::dictionary`](crate::ReadOptions::dictionary)); empty when none was
+/// trained. Fails with [`ProjzstError::InvalidDictionary`] when a file does not hold the
+/// dictionary the catalog lists
+///
+/// # Arguments
+/// * `catalog_file` - Catalog written by [`build_catalog`]
+pub fn catalog_dictionaries<P: AsRef<Path>>(catalog_file: P) -> Result<Vec<Dictionary>> {
+    let catalog_file = catalog_file.as_ref();
+    let dir = catalog_dir(catalog_file);
+    Catalog::load(catalog_file)?
+        .dictionaries
+        .iter()
+        .map(|listed| {
+            let dictionary = Dictionary::load(dir.join(&listed.file))?;
+            if dictionary.id() != listed.id {
+                return Err(ProjzstError::InvalidDictionary(format!(
+                    "{} is not dictionary {:08x}",
+                    listed.file, listed.id
+                )));
+            }
+            Ok(dictionary)
+        })
+        .collect()
+}
+
+/// Internal helper: add the start of every regular file of an archive to `samples`, until
+/// `sampled` bytes reach the training budget
+fn sample_archive(
+    file: &Path,
+    dictionaries: &[Dictionary],
+    samples: &mut Vec<Vec<u8>>,
+    sampled: &mut usize,
+) -> Result<()> {
+    let mut reader = open_input(file)?;
+    let (_, payload_head) = read_metadata_from_reader(&mut reader, IgnoreUnknown::On)?;
+    let mut archive = open_payload_with(payload_head, reader, dictionaries)?;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let mut sample = Vec::new();
+        entry.take(MAX_SAMPLE_SIZE).read_to_end(&mut sample)?;
+        if !sample.is_empty() {
+            *sampled += sample.len();
+            samples.push(sample);
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: directory holding a catalog file and its dictionaries
+fn catalog_dir(catalog_file: &Path) -> &Path {
+    catalog_file
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Internal helper: read the metadata and digest of one archive
+fn catalog_entry(file: &Path, path: String, size: u64, mtime: u64) -> Result<CatalogEntry> {
+    let metadata = read_metadata(file, IgnoreUnknown::On)?;
+    Ok(CatalogEntry {
+        path,
+        name: metadata.name.clone(),
+        ver: metadata.ver.clone(),
+        digest: file_digest(file)?,
+        size,
+        mtime,
+        metadata,
+    })
+}
+
+/// Internal helper: digest of a whole file, `sha256:<hex>`
+pub(crate) fn file_digest(file: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file)?, &mut hasher)?;
+    Ok(format!("sha256:{}", to_hex(&hasher.finalize())))
+}
+
+/// Internal helper: collect the .pjz files below `dir`, without following symlinks
+pub(crate) fn find_archives(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            find_archives(&path, files)?;
+        } else if file_type.is_file() && path.extension().is_some_and(|e| e == "pjz") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: `file` relative to `root`, with `/` separators
+pub(crate) fn relative_path(root: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,201 @@
+//! Resumable packs
+//!
+//! With [`Packer::checkpoint`](crate::Packer::checkpoint), a pack to a file writes the
+//! archive to `<output>.part` in per-entry frames and, whenever a frame is complete and
+//! flushed, appends a line to the checkpoint listing the entries of that frame and where it
+//! ends. Run again after an interruption, the same pack cuts the partial archive back to
+//! the end of the last recorded frame and carries on from there, leaving out the entries
+//! already stored. A checkpoint written by a packer with other settings or sources does
+//! not match and the pack starts over.
+//!
+//! The checkpoint holds JSON lines: a [`CheckpointHeader`], then one [`FrameRecord`] per
+//! complete frame. A line cut short by the interruption ends the checkpoint.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Result;
+use crate::index::IndexedEntry;
+use crate::payload::PayloadEncoder;
+use crate::report::PackReport;
+
+/// Version of the checkpoint layout
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// First line of a checkpoint
+#[derive(Serialize, Deserialize)]
+struct CheckpointHeader {
+    version: u32,
+    /// Digest of the packer settings and sources the checkpoint belongs to
+    fingerprint: String,
+    /// Bytes of skippable frames before the payload
+    header_size: u64,
+}
+
+/// A complete payload frame
+#[derive(Serialize, Deserialize)]
+struct FrameRecord {
+    /// Payload offset where the frame ends
+    end: u64,
+    /// File starting the frame, as indexed; `None` for a frame starting a pack or a resume
+    file: Option<String>,
+    /// Archive paths stored in the frame, with the size of regular files
+    entries: Vec<(String, Option<u64>)>,
+}
+
+/// Progress of a resumable pack, recorded in its checkpoint file
+#[derive(Debug)]
+pub(crate) struct Checkpoint {
+    file: File,
+    /// Bytes of skippable frames before the payload
+    pub header_size: u64,
+    /// Payload offset where the recorded frames end, `0` for a new pack
+    pub payload_size: u64,
+    /// Indexed frames recorded before the interruption being resumed
+    pub index: Vec<IndexedEntry>,
+    /// Entries stored before the interruption, with the size of regular files
+    done: HashMap<String, Option<u64>>,
+    /// File starting the frame being written
+    current: Option<String>,
+    /// Entries stored in the frame being written
+    pending: Vec<(String, Option<u64>)>,
+}
+
+impl Checkpoint {
+    /// Start a checkpoint at `path` for a new pack, replacing any file there
+    ///
+    /// # Arguments
+    /// * `path` - Path of the checkpoint file
+    /// * `fingerprint` - Digest of the packer settings and sources
+    /// * `header_size` - Bytes of skippable frames before the payload
+    pub(crate) fn create(path: &Path, fingerprint: &str, header_size: u64) -> Result<Self> {
+        let header = CheckpointHeader {
+            version: CHECKPOINT_VERSION,
+            fingerprint: fingerprint.to_string(),
+            header_size,
+        };
+        let mut file = File::create(path)?;
+        write_line(&mut file, &header)?;
+        Ok(Self::new(file, header_size))
+    }
+
+    /// Read the checkpoint at `path` to resume the pack it records
+    /// Frames past the `available` bytes of the partial archive are dropped; `None` when
+    /// there is no checkpoint or it belongs to another pack
+    ///
+    /// # Arguments
+    /// * `path` - Path of the checkpoint file
+    /// * `fingerprint` - Digest of the packer settings and sources
+    /// * `available` - Size of the partial archive
+    pub(crate) fn resume(path: &Path, fingerprint: &str, available: u64) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next().transpose()? {
+            Some(line) => serde_json::from_str::<CheckpointHeader>(&line).ok(),
+            None => None,
+        };
+        let Some(header) = header.filter(|header| {
+            header.version == CHECKPOINT_VERSION
+                && header.fingerprint == fingerprint
+                && header.header_size <= available
+        }) else {
+            log::info!("checkpoint {} does not match this pack", path.display());
+            return Ok(None);
+        };
+
+        let mut records = Vec::new();
+        for line in lines {
+            let Ok(record) = serde_json::from_str::<FrameRecord>(&line?) else {
+                break;
+            };
+            if header.header_size + record.end > available {
+                break;
+            }
+            records.push(record);
+        }
+
+        // Rewritten without what the interruption cut short, so new lines start clean
+        let mut file = File::create(path)?;
+        write_line(&mut file, &header)?;
+        let mut checkpoint = Self::new(
+            OpenOptions::new().append(true).open(path)?,
+            header.header_size,
+        );
+        for record in records {
+            write_line(&mut file, &record)?;
+            if let Some(path) = record.file {
+                checkpoint.index.push(IndexedEntry {
+                    path,
+                    offset: checkpoint.payload_size,
+                    length: 0,
+                });
+            }
+            checkpoint.done.extend(record.entries);
+            checkpoint.payload_size = record.end;
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Internal helper: a checkpoint appending to `file`, with nothing recorded yet
+    fn new(file: File, header_size: u64) -> Self {
+        Self {
+            file,
+            header_size,
+            payload_size: 0,
+            index: Vec::new(),
+            done: HashMap::new(),
+            current: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Whether `path` was stored before the interruption being resumed
+    pub(crate) fn is_done(&self, path: &str) -> bool {
+        self.done.contains_key(path)
+    }
+
+    /// Count the files stored before the interruption in `report`
+    pub(crate) fn restore(&self, report: &mut PackReport) {
+        for (path, size) in &self.done {
+            if let Some(size) = size {
+                report.add_file(path, *size);
+            }
+        }
+        report.resumed = self.done.len();
+    }
+
+    /// Record an entry stored in the frame being written, with the size of regular files
+    pub(crate) fn stored(&mut self, path: &str, size: Option<u64>) {
+        self.pending.push((path.to_string(), size));
+    }
+
+    /// Record the frame completed by starting one for `file`, once it is flushed
+    pub(crate) fn frame_started<W: Write>(
+        &mut self,
+        encoder: &mut PayloadEncoder<W>,
+        file: &str,
+    ) -> Result<()> {
+        let record = FrameRecord {
+            end: encoder.flush_finished()?,
+            file: self.current.replace(file.to_string()),
+            entries: std::mem::take(&mut self.pending),
+        };
+        write_line(&mut self.file, &record)
+    }
+}
+
+/// Internal helper: append `value` as a line of JSON
+fn write_line<T: Serialize>(file: &mut File, value: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
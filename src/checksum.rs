@@ -0,0 +1,251 @@
+//! Checksum files next to archives
+//!
+//! Release pipelines publish `app.pjz.sha256` next to `app.pjz`, in the format of
+//! coreutils `sha256sum`: one `<hex digest>  <file name>` line per file, so
+//! `sha256sum -c app.pjz.sha256` checks a download without projzst. The digest is over
+//! the file as it is on disk; a split archive gets one line per volume. Reading accepts
+//! the binary-mode `<hex> *<name>` lines and the BSD `SHA256 (<name>) = <hex>` tags too.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::concurrency::{parallel_map, Concurrency};
+use crate::errors::{ProjzstError, Result};
+use crate::string_utils::{to_hex, IntoOpStr};
+use crate::volume::{base_path, volume_files};
+
+/// Digest of a checksum file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// SHA-256, as written by `sha256sum` (default)
+    #[default]
+    Sha256,
+    /// SHA-512, as written by `sha512sum`
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(ChecksumAlgorithm::Sha256),
+            "sha512" | "sha-512" => Ok(ChecksumAlgorithm::Sha512),
+            _ => Err(ProjzstError::InvalidChecksumAlgorithm(s.to_string())),
+        }
+    }
+
+    /// Name of the algorithm, as accepted by [`ChecksumAlgorithm::from_str_tmp`] and used
+    /// as the extension of the checksum file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Internal helper: the algorithm writing digests of `len` hex digits
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            64 => Some(ChecksumAlgorithm::Sha256),
+            128 => Some(ChecksumAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Internal helper: hex digest of a whole file
+    fn file_digest(self, path: &Path) -> Result<String> {
+        let mut file = File::open(path)?;
+        Ok(match self {
+            ChecksumAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file, &mut hasher)?;
+                to_hex(&hasher.finalize())
+            }
+            ChecksumAlgorithm::Sha512 => {
+                let mut hasher = Sha512::new();
+                io::copy(&mut file, &mut hasher)?;
+                to_hex(&hasher.finalize())
+            }
+        })
+    }
+}
+
+/// Path of the checksum file of an archive: `app.pjz.sha256` for `app.pjz`
+///
+/// # Arguments
+/// * `archive` - Path to the .pjz file (or the first volume of a split archive)
+/// * `algorithm` - Digest the file holds, naming its extension
+pub fn checksum_path<P: AsRef<Path>>(archive: P, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut path = base_path(archive.as_ref()).into_os_string();
+    path.push(".");
+    path.push(algorithm.name());
+    PathBuf::from(path)
+}
+
+/// Write the checksum file of an archive next to it, replacing any previous one
+/// Returns the path written, see [`checksum_path`]
+///
+/// # Arguments
+/// * `archive` - Path to the .pjz file (or the first volume of a split archive)
+/// * `algorithm` - Digest to write
+pub fn write_checksum_file<P: AsRef<Path>>(
+    archive: P,
+    algorithm: ChecksumAlgorithm,
+) -> Result<PathBuf> {
+    let archive = archive.as_ref();
+    let files = volume_files(archive);
+    let threads = Concurrency::current_default().hashing_threads();
+    let digests = parallel_map(&files, threads, |file| algorithm.file_digest(file));
+    let mut content = String::new();
+    for (file, digest) in files.iter().zip(digests) {
+        let digest = digest?;
+        let name = file_name(file);
+        if name.contains(['\\', '\n']) {
+            // coreutils escapes such names and flags the line with a leading backslash
+            let name = name.replace('\\', "\\\\").replace('\n', "\\n");
+            content.push_str(&format!("\\{digest}  {name}\n"));
+        } else {
+            content.push_str(&format!("{digest}  {name}\n"));
+        }
+    }
+    let path = checksum_path(archive, algorithm);
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Check an archive against a checksum file
+/// Every file of the archive (each volume when it was split) must be listed under its
+/// file name, with the digest it has on disk; a checksum file with a single line also
+/// matches an archive of a single file that was renamed since. Fails with
+/// [`ProjzstError::ChecksumFileMismatch`] on a digest that differs and with
+/// [`ProjzstError::InvalidChecksumFile`] on a file that does not parse or does not list
+/// the archive
+///
+/// # Arguments
+/// * `archive` - Path to the .pjz file (or the first volume of a split archive)
+/// * `checksum_file` - File in the format of `sha256sum` or `sha512sum`
+pub fn verify_checksum_file<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive: P1,
+    checksum_file: P2,
+) -> Result<()> {
+    let checksum_file = checksum_file.as_ref();
+    let lines = parse_checksum_file(&fs::read_to_string(checksum_file)?).map_err(|line| {
+        ProjzstError::InvalidChecksumFile(format!(
+            "{}: line {line} is not `<hex digest>  <file name>`",
+            checksum_file.display()
+        ))
+    })?;
+    let files = volume_files(archive.as_ref());
+    let mut expected_digests = Vec::with_capacity(files.len());
+    for file in &files {
+        let name = file_name(file);
+        let line = match lines.iter().find(|(_, listed)| *listed == name) {
+            Some(line) => line,
+            None if lines.len() == 1 && files.len() == 1 => &lines[0],
+            None => {
+                return Err(ProjzstError::InvalidChecksumFile(format!(
+                    "{} does not list {name}",
+                    checksum_file.display()
+                )))
+            }
+        };
+        let expected = line.0.to_lowercase();
+        let algorithm = ChecksumAlgorithm::from_hex_len(expected.len()).ok_or_else(|| {
+            ProjzstError::InvalidChecksumFile(format!(
+                "{}: the digest of {name} is neither SHA-256 nor SHA-512",
+                checksum_file.display()
+            ))
+        })?;
+        expected_digests.push((file, algorithm, expected));
+    }
+    // The volumes are hashed together, then checked in order
+    let threads = Concurrency::current_default().hashing_threads();
+    let actual_digests = parallel_map(&expected_digests, threads, |(file, algorithm, _)| {
+        algorithm.file_digest(file)
+    });
+    for ((file, _, expected), actual) in expected_digests.into_iter().zip(actual_digests) {
+        let actual = actual?;
+        if actual != expected {
+            return Err(ProjzstError::ChecksumFileMismatch {
+                path: file.display().to_string(),
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: the digest and file name of every line of a checksum file
+/// Fails with the number of the first line that does not parse
+fn parse_checksum_file(content: &str) -> std::result::Result<Vec<(String, String)>, usize> {
+    let mut lines = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        lines.push(parse_line(line).ok_or(number + 1)?);
+    }
+    Ok(lines)
+}
+
+/// Internal helper: parse one `<hex>  <name>`, `<hex> *<name>` or `SHA256 (<name>) = <hex>`
+/// line, unescaping the name of a line starting with a backslash
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let tagged = line
+        .split_once(" (")
+        .filter(|(tag, _)| ChecksumAlgorithm::from_str_tmp(*tag).is_ok())
+        .and_then(|(_, rest)| rest.rsplit_once(") = "));
+    let (digest, name) = match tagged {
+        Some((name, digest)) => (digest, name),
+        None => {
+            let (digest, rest) = line.split_once(' ')?;
+            (digest, rest.strip_prefix([' ', '*'])?)
+        }
+    };
+    if digest.is_empty() || !digest.bytes().all(|b| b.is_ascii_hexdigit()) || name.is_empty() {
+        return None;
+    }
+    let name = if escaped {
+        unescape(name)?
+    } else {
+        name.to_string()
+    };
+    Some((digest.to_string(), name))
+}
+
+/// Internal helper: undo the `\\` and `\n` escapes of a coreutils file name
+fn unescape(name: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => unescaped.push('\\'),
+                'n' => unescaped.push('\n'),
+                _ => return None,
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+/// Internal helper: the file name of a path, as listed in checksum files
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
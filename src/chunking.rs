@@ -0,0 +1,402 @@
+//! Content-defined chunking of file content
+//!
+//! With [`Packer::chunked`](crate::Packer::chunked), file content is cut into chunks at
+//! positions picked by a rolling hash of the bytes themselves (FastCDC with a gear hash),
+//! so an edit only changes the chunks around it and the rest of the file splits exactly as
+//! before. Each distinct chunk is stored once, as an entry under `.pjz-chunks/` named
+//! after its SHA-256, right before the first file made of it; files are stored empty, with
+//! the list of their chunks in a `PROJZST.chunks` PAX record. Trees holding many copies or
+//! near-copies of the same files (vendored dependencies, successive nightly builds) shrink
+//! accordingly.
+//!
+//! The payload stays a valid tar stream, but only projzst puts the files back together:
+//! other tar readers extract the chunks and empty files. When unpacking, chunks are spooled
+//! to a temporary file in the output directory and copied into the files made of them.
+//!
+//! With [`Packer::chunk_store`](crate::Packer::chunk_store), the chunks already held by a
+//! chunk store archive (see [`pack_set`](crate::pack_set)) are left out of the payload,
+//! which then unpacks only with the store given to
+//! [`ReadOptions::chunk_store`](crate::ReadOptions::chunk_store).
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::builder::open_payload_only;
+use crate::errors::{ProjzstError, Result};
+use crate::reflink::{copy_range, set_mode};
+use crate::string_utils::to_hex;
+use crate::tar_format::{append_entry, append_entry_with, TarFormat};
+use crate::temp::{temp_file_for, TempPath};
+use crate::volume::open_input;
+
+/// Directory holding the chunk entries of a chunked payload
+pub(crate) const CHUNK_DIR: &str = ".pjz-chunks";
+
+/// PAX record listing the chunks of a file, as comma-separated hex SHA-256 digests
+pub(crate) const CHUNKS_RECORD: &str = "PROJZST.chunks";
+
+/// Smallest chunk cut, except for the last one of a file
+const MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Size past which cuts get easier, so most chunks end up close to it
+const AVERAGE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk, cut whatever the hash says
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Hash bits that must be zero for a cut below the average size
+const MASK_SMALL: u64 = !0 << (64 - 18);
+
+/// Hash bits that must be zero for a cut above the average size
+const MASK_LARGE: u64 = !0 << (64 - 14);
+
+/// Random value of each byte for the gear hash; fixed, so that cut points never change
+/// from one release to another
+const GEAR: [u64; 256] = gear_table();
+
+/// Internal helper: the gear table, drawn from a splitmix64 sequence
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Internal helper: length of the first chunk of `data`, which must hold the rest of the
+/// content or at least [`MAX_CHUNK_SIZE`] bytes of it
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let average = data.len().min(AVERAGE_CHUNK_SIZE);
+    let max = data.len().min(MAX_CHUNK_SIZE);
+    let mut hash = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(max).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let mask = if i < average { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    max
+}
+
+/// Reader split into content-defined chunks
+struct Chunker<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(MAX_CHUNK_SIZE),
+            eof: false,
+        }
+    }
+
+    /// Next chunk of the content, `None` at its end
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while !self.eof && self.buffer.len() < MAX_CHUNK_SIZE {
+            let filled = self.buffer.len();
+            self.buffer.resize(MAX_CHUNK_SIZE, 0);
+            let read = loop {
+                match self.inner.read(&mut self.buffer[filled..]) {
+                    Ok(read) => break read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            };
+            self.buffer.truncate(filled + read);
+            self.eof = read == 0;
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let rest = self.buffer.split_off(cut_point(&self.buffer));
+        Ok(Some(std::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+/// Internal helper: store `content` as chunks, those not in `stored` yet appended first,
+/// then an empty entry at `path` listing them
+/// `header` (made by [`TarFormat::header`]) and `mtime` describe the file as in
+/// [`append_entry`]. Returns how many bytes of the content were in chunks stored before
+pub(crate) fn append_chunked<W: Write, R: Read>(
+    tar_builder: &mut tar::Builder<W>,
+    format: TarFormat,
+    mut header: tar::Header,
+    path: &str,
+    mtime: Option<&str>,
+    content: R,
+    stored: &mut HashSet<String>,
+) -> Result<u64> {
+    if format == TarFormat::Ustar {
+        return Err(ProjzstError::TarFormatLimit {
+            path: path.to_string(),
+            format: format.name().to_string(),
+            reason: "chunked files need PAX records".to_string(),
+        });
+    }
+    let mut digests = Vec::new();
+    let mut deduplicated = 0;
+    each_chunk(content, |digest, chunk| {
+        if stored.contains(&digest) {
+            deduplicated += chunk.len() as u64;
+        } else {
+            append_chunk(tar_builder, format, &digest, chunk)?;
+            stored.insert(digest.clone());
+        }
+        digests.push(digest);
+        Ok(())
+    })?;
+
+    let list = digests.join(",");
+    let mut records: Vec<(&str, &str)> = mtime.map(|mtime| ("mtime", mtime)).into_iter().collect();
+    if !digests.is_empty() {
+        records.push((CHUNKS_RECORD, &list));
+    }
+    header.set_size(0);
+    append_entry_with(
+        tar_builder,
+        format,
+        header,
+        path,
+        None,
+        &records,
+        io::empty(),
+    )?;
+    Ok(deduplicated)
+}
+
+/// Internal helper: cut `content` into chunks as [`append_chunked`] does, calling `f` with
+/// the hex SHA-256 and bytes of each
+pub(crate) fn each_chunk<R, F>(content: R, mut f: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(String, &[u8]) -> Result<()>,
+{
+    let mut chunker = Chunker::new(content);
+    while let Some(chunk) = chunker.next_chunk()? {
+        f(to_hex(&Sha256::digest(&chunk)), &chunk)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: store one chunk as an entry under [`CHUNK_DIR`] named after `digest`
+pub(crate) fn append_chunk<W: Write>(
+    tar_builder: &mut tar::Builder<W>,
+    format: TarFormat,
+    digest: &str,
+    chunk: &[u8],
+) -> Result<()> {
+    let mut header = format.header();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(chunk.len() as u64);
+    let path = format!("{CHUNK_DIR}/{digest}");
+    append_entry(tar_builder, format, header, &path, None, None, chunk)
+}
+
+/// Internal helper: the digests of the chunks held by the chunk store archive `store`,
+/// see [`crate::pack_set`]
+pub(crate) fn store_digests(store: &Path) -> Result<HashSet<String>> {
+    let mut digests = HashSet::new();
+    let mut archive = open_payload_only(open_input(store)?)?;
+    for entry in archive.entries()? {
+        if let Some(digest) = chunk_digest(&entry?) {
+            digests.insert(digest);
+        }
+    }
+    Ok(digests)
+}
+
+/// Internal helper: the digest of the chunk an entry holds, `None` for other entries
+pub(crate) fn chunk_digest<R: Read>(entry: &tar::Entry<'_, R>) -> Option<String> {
+    if entry.header().entry_type() != tar::EntryType::Regular {
+        return None;
+    }
+    let path = entry.path_bytes();
+    let digest = path.strip_prefix(format!("{CHUNK_DIR}/").as_bytes())?;
+    (digest.len() == 64 && digest.iter().all(u8::is_ascii_hexdigit))
+        .then(|| String::from_utf8_lossy(digest).into_owned())
+}
+
+/// Internal helper: the chunks a file entry is made of, `None` for files stored whole
+pub(crate) fn chunk_list<R: Read>(entry: &mut tar::Entry<'_, R>) -> Result<Option<Vec<String>>> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(None);
+    };
+    for extension in extensions {
+        let extension = extension?;
+        if extension.key() != Ok(CHUNKS_RECORD) {
+            continue;
+        }
+        let value = extension.value().map_err(|_| {
+            ProjzstError::InvalidChunk(String::from_utf8_lossy(extension.value_bytes()).into())
+        })?;
+        return Ok(Some(value.split(',').map(str::to_string).collect()));
+    }
+    Ok(None)
+}
+
+/// Chunks met so far while unpacking a chunked payload
+pub(crate) struct ChunkStore {
+    /// Directory the spool file is created in
+    dir: PathBuf,
+    spool: RefCell<Option<Spool>>,
+}
+
+/// Temporary file holding the chunks one after another
+struct Spool {
+    writer: File,
+    reader: File,
+    /// Offset and length of each chunk in the file, by digest
+    chunks: HashMap<String, (u64, u64)>,
+    size: u64,
+    /// Dropped last, once both handles are closed
+    _temp: TempPath,
+}
+
+impl ChunkStore {
+    /// Create a store spooling to `dir`; nothing is written before the first chunk
+    pub(crate) fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            spool: RefCell::new(None),
+        }
+    }
+
+    /// Create a store spooling to `dir`, loaded with the chunks of the chunk store archive
+    /// `store` when there is one, so payloads packed against it can be put back together
+    pub(crate) fn with_store(dir: &Path, store: Option<&Path>) -> Result<Self> {
+        let chunks = Self::new(dir);
+        if let Some(store) = store {
+            let mut archive = open_payload_only(open_input(store)?)?;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if let Some(digest) = chunk_digest(&entry) {
+                    chunks.insert(digest, &mut entry)?;
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Keep the chunk named `digest`, read from `content`
+    /// Fails with [`ProjzstError::InvalidChunk`] when the content does not match the name
+    pub(crate) fn insert<R: Read>(&self, digest: String, content: R) -> Result<()> {
+        let mut chunk = Vec::new();
+        content
+            .take(MAX_CHUNK_SIZE as u64 + 1)
+            .read_to_end(&mut chunk)?;
+        if chunk.len() > MAX_CHUNK_SIZE || to_hex(&Sha256::digest(&chunk)) != digest {
+            return Err(ProjzstError::InvalidChunk(digest));
+        }
+        let mut spool = self.spool.borrow_mut();
+        if spool.is_none() {
+            let (writer, temp) = temp_file_for(&self.dir.join(CHUNK_DIR))?;
+            *spool = Some(Spool {
+                writer,
+                reader: File::open(temp.path())?,
+                chunks: HashMap::new(),
+                size: 0,
+                _temp: temp,
+            });
+        }
+        let spool = spool.as_mut().expect("spool created above");
+        spool.writer.write_all(&chunk)?;
+        spool
+            .chunks
+            .insert(digest, (spool.size, chunk.len() as u64));
+        spool.size += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Write the file made of `chunks` to a new file at `target` with permissions `mode`
+    pub(crate) fn assemble(&self, chunks: &[String], target: &Path, mode: u32) -> Result<()> {
+        if target.symlink_metadata().is_ok() {
+            std::fs::remove_file(target)?;
+        }
+        let mut output = File::options().write(true).create_new(true).open(target)?;
+        self.write_into(chunks, &mut output)?;
+        set_mode(&output, mode)?;
+        Ok(())
+    }
+
+    /// Write the content made of `chunks` to `output`, an open file
+    pub(crate) fn write_into(&self, chunks: &[String], output: &mut File) -> Result<()> {
+        let spool = self.spool.borrow();
+        let ranges = ranges(spool.as_ref(), chunks)?;
+        let reader = &spool.as_ref().expect("chunks were found").reader;
+        for (offset, len) in ranges {
+            copy_range(reader, offset, output, len)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the file at `path` holds exactly the content made of `chunks`
+    pub(crate) fn matches(&self, chunks: &[String], path: &Path) -> Result<bool> {
+        let spool = self.spool.borrow();
+        let ranges = ranges(spool.as_ref(), chunks)?;
+        let mut reader = &spool.as_ref().expect("chunks were found").reader;
+        let mut local = File::open(path)?;
+        if local.metadata()?.len() != ranges.iter().map(|(_, len)| len).sum::<u64>() {
+            return Ok(false);
+        }
+        let (mut stored, mut current) = (Vec::new(), Vec::new());
+        for (offset, len) in ranges {
+            reader.seek(SeekFrom::Start(offset))?;
+            stored.clear();
+            reader.take(len).read_to_end(&mut stored)?;
+            current.resize(stored.len(), 0);
+            local.read_exact(&mut current)?;
+            if stored != current {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Size and hex SHA-256 of the file made of `chunks`
+    pub(crate) fn digest(&self, chunks: &[String]) -> Result<(u64, String)> {
+        let spool = self.spool.borrow();
+        let ranges = ranges(spool.as_ref(), chunks)?;
+        let mut reader = &spool.as_ref().expect("chunks were found").reader;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        for (offset, len) in ranges {
+            reader.seek(SeekFrom::Start(offset))?;
+            size += io::copy(&mut reader.take(len), &mut hasher)?;
+        }
+        Ok((size, to_hex(&hasher.finalize())))
+    }
+}
+
+/// Internal helper: where each of `chunks` is in the spool, failing with
+/// [`ProjzstError::InvalidChunk`] for the first one missing
+fn ranges(spool: Option<&Spool>, chunks: &[String]) -> Result<Vec<(u64, u64)>> {
+    chunks
+        .iter()
+        .map(|digest| {
+            spool
+                .and_then(|spool| spool.chunks.get(digest).copied())
+                .ok_or_else(|| ProjzstError::InvalidChunk(digest.clone()))
+        })
+        .collect()
+}
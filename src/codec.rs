@@ -0,0 +1,257 @@
+//! Payload codecs
+//!
+//! The payload is a zstd stream unless [`Packer::codec`](crate::Packer::codec) picks
+//! another codec, each behind the feature of the same name: `xz` for the smallest
+//! archives, `lz4` where decoding speed matters most, `brotli` for text-heavy trees. The
+//! codec is recorded in the metadata (`pjz_codec`) and readers pick the decoder from it;
+//! zstd payloads record nothing, so their archives stay readable by older releases and
+//! plain zstd decoders.
+//!
+//! Compression levels map onto the range of the codec: clamped to `0..=9` for xz and
+//! `0..=11` for brotli, ignored by lz4. Only zstd payloads are made of frames projzst
+//! controls, so adaptive compression, per-entry frames and checkpoints apply to zstd
+//! alone, as do the frame-level tools (`stat`, `repair`, indexed reads).
+
+use std::io::{self, BufRead, BufReader, Read};
+
+use zstd::stream::raw::{InBuffer, Operation, OutBuffer, WriteBuf};
+use zstd::stream::zio;
+use zstd::zstd_safe::{self, zstd_sys, DCtx, ResetDirective};
+
+use crate::dictionary::Dictionary;
+use crate::errors::{ChecksumFailure, ProjzstError, Result};
+use crate::string_utils::IntoOpStr;
+
+/// Compression codec of the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum PayloadCodec {
+    /// Zstandard (default)
+    #[default]
+    Zstd,
+    /// xz (LZMA2), `xz` feature
+    Xz,
+    /// LZ4 frames, `lz4` feature
+    Lz4,
+    /// Brotli, `brotli` feature
+    Brotli,
+}
+
+impl PayloadCodec {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "zstd" | "zst" => Ok(PayloadCodec::Zstd),
+            "xz" | "lzma" => Ok(PayloadCodec::Xz),
+            "lz4" => Ok(PayloadCodec::Lz4),
+            "brotli" | "br" => Ok(PayloadCodec::Brotli),
+            _ => Err(ProjzstError::InvalidCodec(s.to_string())),
+        }
+    }
+
+    /// Lowercase name of the codec, as recorded in the metadata
+    pub fn name(self) -> &'static str {
+        match self {
+            PayloadCodec::Zstd => "zstd",
+            PayloadCodec::Xz => "xz",
+            PayloadCodec::Lz4 => "lz4",
+            PayloadCodec::Brotli => "brotli",
+        }
+    }
+
+    /// Whether this build can write and read payloads of the codec
+    pub fn is_available(self) -> bool {
+        match self {
+            PayloadCodec::Zstd => true,
+            PayloadCodec::Xz => cfg!(feature = "xz"),
+            PayloadCodec::Lz4 => cfg!(feature = "lz4"),
+            PayloadCodec::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+
+    /// Internal helper: fail with [`ProjzstError::CodecUnavailable`] unless this build
+    /// supports the codec
+    pub(crate) fn check_available(self) -> Result<Self> {
+        if !self.is_available() {
+            return Err(ProjzstError::CodecUnavailable(self.name().to_string()));
+        }
+        Ok(self)
+    }
+
+    /// Internal helper: the value recorded in the metadata, `None` for zstd
+    pub(crate) fn recorded(self) -> Option<String> {
+        (self != PayloadCodec::Zstd).then(|| self.name().to_string())
+    }
+
+    /// Internal helper: the codec recorded in the metadata of an archive, zstd when none is
+    /// Codecs this build does not know or support fail with
+    /// [`ProjzstError::CodecUnavailable`]
+    pub(crate) fn from_recorded(recorded: Option<&str>) -> Result<Self> {
+        let Some(name) = recorded else {
+            return Ok(PayloadCodec::Zstd);
+        };
+        Self::from_str_tmp(name)
+            .map_err(|_| ProjzstError::CodecUnavailable(name.to_string()))?
+            .check_available()
+    }
+}
+
+/// First bytes of a payload, consumed while probing the frames before it
+pub(crate) struct PayloadHead {
+    /// Bytes to replay in front of the rest of the payload
+    pub(crate) bytes: Vec<u8>,
+    /// Codec recorded in the metadata
+    pub(crate) codec: PayloadCodec,
+    /// ID of the dictionary recorded in the metadata
+    pub(crate) dictionary: Option<u32>,
+    /// Unknown metadata fields ignored while decoding the metadata
+    pub(crate) ignored_fields: Vec<String>,
+}
+
+impl PayloadHead {
+    /// Internal helper: the head of a zstd payload
+    pub(crate) fn zstd(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            codec: PayloadCodec::Zstd,
+            dictionary: None,
+            ignored_fields: Vec::new(),
+        }
+    }
+}
+
+/// Decoder of the payload, whichever its codec
+pub(crate) enum PayloadDecoder<R: BufRead> {
+    Zstd(ZstdDecoder<R>),
+    #[cfg(feature = "xz")]
+    Xz(xz2::bufread::XzDecoder<R>),
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::Decompressor<R>>),
+}
+
+impl<R: BufRead> PayloadDecoder<R> {
+    /// Internal helper: decode `input`, a payload compressed with `codec` and, for zstd,
+    /// with `dictionary` when given
+    pub(crate) fn new(
+        codec: PayloadCodec,
+        input: R,
+        dictionary: Option<&Dictionary>,
+    ) -> Result<Self> {
+        #[allow(unreachable_patterns)]
+        Ok(match codec.check_available()? {
+            PayloadCodec::Zstd => Self::Zstd(zstd_decoder_with(
+                input,
+                dictionary.map_or(&[][..], Dictionary::as_bytes),
+            )?),
+            #[cfg(feature = "xz")]
+            PayloadCodec::Xz => Self::Xz(xz2::bufread::XzDecoder::new_multi_decoder(input)),
+            #[cfg(feature = "lz4")]
+            PayloadCodec::Lz4 => Self::Lz4(lz4_flex::frame::FrameDecoder::new(input)),
+            #[cfg(feature = "brotli")]
+            PayloadCodec::Brotli => Self::Brotli(Box::new(brotli::Decompressor::new(
+                input,
+                BROTLI_BUFFER_SIZE,
+            ))),
+            codec => return Err(ProjzstError::CodecUnavailable(codec.name().to_string())),
+        })
+    }
+}
+
+impl<R: BufRead> Read for PayloadDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Zstd(decoder) => decoder.read(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(decoder) => decoder.read(buf),
+            #[cfg(feature = "lz4")]
+            Self::Lz4(decoder) => decoder.read(buf),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Zstd decoder whose errors carry [`ChecksumFailure`] when a frame does not match its
+/// content checksum, as told by the zstd error code
+pub(crate) type ZstdDecoder<R> = zio::Reader<R, ZstdDecoding>;
+
+/// Internal helper: a [`ZstdDecoder`] of `input`, buffered like `zstd::stream::Decoder`
+pub(crate) fn zstd_decoder<R: Read>(input: R) -> io::Result<ZstdDecoder<BufReader<R>>> {
+    let input = BufReader::with_capacity(DCtx::in_size(), input);
+    zstd_decoder_with(input, &[])
+}
+
+/// Internal helper: a [`ZstdDecoder`] of `input`, compressed with `dictionary` unless
+/// it is empty
+pub(crate) fn zstd_decoder_with<R: BufRead>(
+    input: R,
+    dictionary: &[u8],
+) -> io::Result<ZstdDecoder<R>> {
+    let mut context = DCtx::create();
+    context.init().map_err(zstd_error)?;
+    context.load_dictionary(dictionary).map_err(zstd_error)?;
+    Ok(zio::Reader::new(input, ZstdDecoding { context }))
+}
+
+/// Decoding step of a [`ZstdDecoder`]
+pub(crate) struct ZstdDecoding {
+    context: DCtx<'static>,
+}
+
+impl Operation for ZstdDecoding {
+    fn run<C: WriteBuf + ?Sized>(
+        &mut self,
+        input: &mut InBuffer<'_>,
+        output: &mut OutBuffer<'_, C>,
+    ) -> io::Result<usize> {
+        self.context
+            .decompress_stream(output, input)
+            .map_err(zstd_error)
+    }
+
+    fn reinit(&mut self) -> io::Result<()> {
+        self.context
+            .reset(ResetDirective::SessionOnly)
+            .map_err(zstd_error)?;
+        Ok(())
+    }
+
+    fn finish<C: WriteBuf + ?Sized>(
+        &mut self,
+        _output: &mut OutBuffer<'_, C>,
+        finished_frame: bool,
+    ) -> io::Result<usize> {
+        if finished_frame {
+            Ok(0)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "incomplete frame",
+            ))
+        }
+    }
+}
+
+/// Internal helper: the I/O error of a zstd error code
+fn zstd_error(code: usize) -> io::Error {
+    let message = zstd_safe::get_error_name(code);
+    // SAFETY: ZSTD_getErrorCode only reads the value it is given
+    let kind = unsafe { zstd_sys::ZSTD_getErrorCode(code) };
+    if kind == zstd_sys::ZSTD_ErrorCode::ZSTD_error_checksum_wrong {
+        io::Error::new(io::ErrorKind::InvalidData, ChecksumFailure(message))
+    } else {
+        io::Error::other(message)
+    }
+}
+
+/// Buffer of the brotli encoder and decoder
+#[cfg(feature = "brotli")]
+pub(crate) const BROTLI_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Window of the brotli encoder, as a power of two (4 MiB)
+#[cfg(feature = "brotli")]
+pub(crate) const BROTLI_WINDOW: u32 = 22;
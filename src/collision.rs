@@ -0,0 +1,106 @@
+//! Entry paths that collide on case-insensitive or normalizing file systems
+//!
+//! `README` and `readme` are two entries in an archive but one file on the default macOS
+//! and Windows file systems, and so are `café` spelled with a precomposed `é` (NFC) and
+//! with `e` plus a combining accent (NFD). Extracting both silently keeps only the last;
+//! [`path_collisions`] lists such pairs beforehand and
+//! [`ReadOptions::reject_collisions`](crate::ReadOptions::reject_collisions) refuses them.
+//! Normalization collisions need the `unicode` feature; without it only case is compared.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+#[cfg(feature = "unicode")]
+use icu_normalizer::ComposingNormalizerBorrowed;
+use serde::Serialize;
+
+use crate::builder::ReadOptions;
+use crate::errors::Result;
+use crate::metadata::IgnoreUnknown;
+
+/// Why two entry paths land on the same file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum CollisionKind {
+    /// The paths differ only in letter case
+    Case,
+    /// The paths differ only in Unicode normalization (and possibly case)
+    Normalization,
+}
+
+impl CollisionKind {
+    /// Lowercase name of the kind
+    pub fn name(self) -> &'static str {
+        match self {
+            CollisionKind::Case => "case",
+            CollisionKind::Normalization => "normalization",
+        }
+    }
+}
+
+/// Two entries extracted to the same file on some file systems
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PathCollision {
+    /// Path of the entry met first
+    pub first: String,
+    /// Path of the later entry, which would overwrite it
+    pub second: String,
+    /// How the paths differ
+    pub kind: CollisionKind,
+}
+
+/// List the entries of a .pjz file that collide on case-insensitive or Unicode-normalizing
+/// file systems, in archive order
+/// Directories are left out: two spellings of a directory merge rather than overwrite
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn path_collisions<P: AsRef<Path>>(input_file: P) -> Result<Vec<PathCollision>> {
+    ReadOptions::new(IgnoreUnknown::On).path_collisions(input_file)
+}
+
+/// Internal helper: the pairs of colliding paths among `paths`
+pub(crate) fn find_collisions<I: IntoIterator<Item = String>>(paths: I) -> Vec<PathCollision> {
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut collisions = Vec::new();
+    for path in paths {
+        let key = collision_key(&path);
+        match seen.get(&key) {
+            // The same path twice is a duplicate entry, not a collision
+            Some(first) if *first == path => {}
+            Some(first) => {
+                // Same key, so what lowercasing alone does not explain is normalization
+                let kind = if first.to_lowercase() == path.to_lowercase() {
+                    CollisionKind::Case
+                } else {
+                    CollisionKind::Normalization
+                };
+                collisions.push(PathCollision {
+                    first: first.clone(),
+                    second: path,
+                    kind,
+                });
+            }
+            None => {
+                seen.insert(key, path);
+            }
+        }
+    }
+    collisions
+}
+
+/// Internal helper: the name `path` lands on, lowercased and in NFC
+#[cfg(feature = "unicode")]
+fn collision_key(path: &str) -> String {
+    let nfc = ComposingNormalizerBorrowed::new_nfc();
+    nfc.normalize(&nfc.normalize(path).to_lowercase())
+        .into_owned()
+}
+
+/// Internal helper: the name `path` lands on, lowercased (without the `unicode` feature)
+#[cfg(not(feature = "unicode"))]
+fn collision_key(path: &str) -> String {
+    path.to_lowercase()
+}
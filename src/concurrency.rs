@@ -0,0 +1,201 @@
+//! Thread counts of the parallel parts of packing and unpacking
+//!
+//! A [`Concurrency`] sets how many zstd workers compress the payload, how many uploads
+//! write the entries of an archive unpacked to S3, and how many threads hash: files for
+//! incremental packs and post-unpack verification, volumes for checksum files, archives
+//! for catalogs and entries for SBOMs, all through the helpers at the end of this module.
+//! It is given per call with [`Packer::concurrency`](crate::Packer::concurrency) and
+//! [`ReadOptions::concurrency`](crate::ReadOptions::concurrency), or for the whole process
+//! with [`Concurrency::set_default`]; counts left unset keep each subsystem's own default.
+
+use std::sync::{mpsc, Mutex, RwLock};
+use std::thread;
+
+use crate::errors::Result;
+
+/// Uploads in flight at once when unpacking to S3, unless configured
+const DEFAULT_EXTRACTION_WRITERS: usize = 8;
+
+/// Crate-level default, used by calls given no [`Concurrency`] of their own
+static DEFAULT: RwLock<Concurrency> = RwLock::new(Concurrency::new());
+
+/// Thread counts of the parallel subsystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Concurrency {
+    compression: Option<u32>,
+    extraction: Option<usize>,
+    hashing: Option<usize>,
+}
+
+impl Concurrency {
+    /// Create a configuration leaving every subsystem at its default
+    pub const fn new() -> Self {
+        Self {
+            compression: None,
+            extraction: None,
+            hashing: None,
+        }
+    }
+
+    /// Use `threads` threads everywhere: `1` compresses on the calling thread, hashes
+    /// one file at a time and uploads one object at a time
+    pub fn threads(threads: usize) -> Self {
+        let threads = threads.max(1);
+        Self::new()
+            .compression(if threads > 1 { threads as u32 } else { 0 })
+            .extraction(threads)
+            .hashing(threads)
+    }
+
+    /// Compress with `workers` zstd worker threads, `0` compressing on the calling
+    /// thread (default)
+    pub fn compression(mut self, workers: u32) -> Self {
+        self.compression = Some(workers);
+        self
+    }
+
+    /// Keep up to `writers` uploads in flight when unpacking to S3 (default: 8)
+    pub fn extraction(mut self, writers: usize) -> Self {
+        self.extraction = Some(writers.max(1));
+        self
+    }
+
+    /// Hash up to `threads` files at once (default: one per available CPU)
+    pub fn hashing(mut self, threads: usize) -> Self {
+        self.hashing = Some(threads.max(1));
+        self
+    }
+
+    /// Make this the configuration of every call not given one of its own
+    pub fn set_default(self) {
+        *DEFAULT.write().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    /// The configuration of calls not given one of their own
+    pub fn current_default() -> Self {
+        *DEFAULT.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Number of zstd compression workers, `0` for none
+    pub fn compression_workers(&self) -> u32 {
+        self.compression.unwrap_or(0)
+    }
+
+    /// Number of concurrent writes when unpacking to S3
+    pub fn extraction_writers(&self) -> usize {
+        self.extraction.unwrap_or(DEFAULT_EXTRACTION_WRITERS)
+    }
+
+    /// Number of threads hashing files
+    pub fn hashing_threads(&self) -> usize {
+        self.hashing
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+/// Internal helper: `f` applied to every item on up to `threads` threads, results in the
+/// order of `items`
+pub(crate) fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("hashing thread panicked"))
+            .collect()
+    })
+}
+
+/// Internal helper: `f` applied on up to `threads` threads to the items `produce` hands
+/// to its callback, while it is still producing them; results in the order of the items
+/// At most two items per thread wait for a thread, so a producer reading ahead of the
+/// hashing holds little in memory. A `produce` failure is returned once the threads are
+/// done with the items handed so far
+pub(crate) fn pipelined_map<T, R, P, F>(threads: usize, produce: P, f: F) -> Result<Vec<R>>
+where
+    T: Send,
+    R: Send,
+    P: FnOnce(&mut dyn FnMut(T)) -> Result<()>,
+    F: Fn(T) -> R + Sync,
+{
+    if threads <= 1 {
+        let mut results = Vec::new();
+        produce(&mut |item| results.push(f(item)))?;
+        return Ok(results);
+    }
+    let (item_tx, item_rx) = mpsc::sync_channel::<(usize, T)>(threads * 2);
+    let item_rx = Mutex::new(item_rx);
+    let (result_tx, result_rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let (item_rx, result_tx, f) = (&item_rx, result_tx.clone(), &f);
+            scope.spawn(move || loop {
+                let item = item_rx.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                let Ok((index, item)) = item else {
+                    break;
+                };
+                // The receiver is alive until every thread is done
+                let _ = result_tx.send((index, f(item)));
+            });
+        }
+        drop(result_tx);
+        let mut count = 0;
+        let produced = produce(&mut |item| {
+            // A thread only stops once the sender is gone
+            let _ = item_tx.send((count, item));
+            count += 1;
+        });
+        drop(item_tx);
+        let mut results: Vec<(usize, R)> = result_rx.iter().collect();
+        results.sort_by_key(|(index, _)| *index);
+        produced.map(|()| results.into_iter().map(|(_, result)| result).collect())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ProjzstError;
+
+    #[test]
+    fn test_pipelined_map() {
+        let produce = |hand: &mut dyn FnMut(u64)| {
+            (0..100).for_each(&mut *hand);
+            Ok(())
+        };
+        let expected: Vec<u64> = (0..100).map(|item| item * item).collect();
+        for threads in [1, 4] {
+            assert_eq!(
+                pipelined_map(threads, produce, |item| item * item).unwrap(),
+                expected
+            );
+        }
+
+        // Items handed before a failure are still mapped, then the failure is returned
+        let mapped = std::sync::atomic::AtomicUsize::new(0);
+        let result = pipelined_map(
+            4,
+            |hand: &mut dyn FnMut(u64)| {
+                (0..10).for_each(&mut *hand);
+                Err(ProjzstError::Io(std::io::Error::other("truncated")))
+            },
+            |item| {
+                mapped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                item
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(mapped.into_inner(), 10);
+    }
+}
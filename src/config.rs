@@ -0,0 +1,145 @@
+//! User configuration of the command-line tool
+//!
+//! `config.toml` in the `projzst` configuration directory (`$XDG_CONFIG_HOME/projzst`,
+//! else `~/.config/projzst`; `%APPDATA%\projzst` on Windows), or the file given with
+//! `--config`, sets defaults for options most invocations repeat. `PROJZST_*`
+//! environment variables override it, so CI can change a setting without a file, and
+//! flags given on the command line win over both; exclude patterns are added to those
+//! of `--exclude`. `PROJZST_LEVEL` and `PROJZST_THREADS` are only parsed when a command
+//! needs the setting and its flag is not given, so a bad value fails no other command.
+//!
+//! ```toml
+//! level = 12
+//! threads = 4
+//! exclude = ["*.tmp", "node_modules"]
+//! author = "Jane Doe"
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use projzst::ProjzstError;
+use serde::Deserialize;
+
+/// Defaults read from the configuration file and the environment
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Compression level of `pack`, `convert` and `merge`, see [`UserConfig::level`]
+    pub level: Option<i32>,
+    /// Threads for compression, hashing and S3 uploads, see [`UserConfig::threads`]
+    pub threads: Option<usize>,
+    /// Globs of the names `pack` leaves out (`PROJZST_EXCLUDE`, comma-separated)
+    pub exclude: Vec<String>,
+    /// Author recorded by `pack` (`PROJZST_AUTHOR`)
+    pub author: Option<String>,
+}
+
+impl UserConfig {
+    /// Read the configuration file, then the environment variables overriding it
+    /// (but for the level and thread count, resolved with their flags); no file at the default location is an empty configuration, while a missing
+    /// `--config` file is an error
+    ///
+    /// # Arguments
+    /// * `path` - File given with `--config`, read instead of the default location
+    pub fn load(path: Option<&Path>) -> Result<Self, ProjzstError> {
+        let mut config = match path {
+            Some(path) => Self::read(path, true)?,
+            None => match default_path() {
+                Some(path) => Self::read(&path, false)?,
+                None => Self::default(),
+            },
+        };
+        if let Some(exclude) = env_string("PROJZST_EXCLUDE") {
+            config.exclude = exclude
+                .split(',')
+                .map(str::trim)
+                .filter(|glob| !glob.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(author) = env_string("PROJZST_AUTHOR") {
+            config.author = Some(author);
+        }
+        Ok(config)
+    }
+
+    /// Compression level: `flag` when given, else `PROJZST_LEVEL`, else the file's
+    /// Fails with [`ProjzstError::InvalidEnvVar`] when the variable is read and does not
+    /// parse
+    pub fn level(&self, flag: Option<i32>) -> Result<Option<i32>, ProjzstError> {
+        match flag {
+            Some(level) => Ok(Some(level)),
+            None => Ok(env_value("PROJZST_LEVEL")?.or(self.level)),
+        }
+    }
+
+    /// Thread count: `flag` when given, else `PROJZST_THREADS`, else the file's
+    /// Fails with [`ProjzstError::InvalidEnvVar`] when the variable is read and does not
+    /// parse
+    pub fn threads(&self, flag: Option<usize>) -> Result<Option<usize>, ProjzstError> {
+        match flag {
+            Some(threads) => Ok(Some(threads)),
+            None => Ok(env_value("PROJZST_THREADS")?.or(self.threads)),
+        }
+    }
+
+    /// Internal helper: parse a configuration file, empty when it does not exist and is
+    /// not `required`
+    fn read(path: &Path, required: bool) -> Result<Self, ProjzstError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        toml::from_str(&content).map_err(|e| ProjzstError::InvalidConfig {
+            path: path.display().to_string(),
+            message: e.message().to_string(),
+        })
+    }
+}
+
+/// The `projzst` configuration directory, if a home or configuration directory is known
+/// Holds `config.toml` and the signing keys of `projzst key`
+pub fn config_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let dir = if cfg!(windows) {
+        PathBuf::from(var("APPDATA")?)
+    } else {
+        match var("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(var("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join("projzst"))
+}
+
+/// Internal helper: the configuration file read without `--config`
+fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Internal helper: an environment variable, unset when empty
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Internal helper: an environment variable parsed as `T`, failing with
+/// [`ProjzstError::InvalidEnvVar`] on a value that does not parse
+fn env_value<T: FromStr>(name: &str) -> Result<Option<T>, ProjzstError> {
+    env_string(name)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| ProjzstError::InvalidEnvVar {
+                    name: name.to_string(),
+                    value,
+                })
+        })
+        .transpose()
+}
@@ -0,0 +1,421 @@
+//! Process confinement of the command-line tool (`unpack --confine`)
+//!
+//! Before a possibly hostile archive is decoded, the process gives up what unpacking does
+//! not need, for good. On Linux, `no_new_privs` is set, Landlock limits the file system to
+//! reading the input's directory and writing the output directory (and its `metadata.json`),
+//! and a seccomp filter makes the system calls an unpack never makes (processes, sockets,
+//! tracing, mounts, io_uring, kernel modules) fail with `EPERM`. On OpenBSD, `unveil` and
+//! `pledge` do the same. A bug in a decoder then cannot reach beyond those paths.
+//!
+//! Kernels without Landlock still get the seccomp filter, with a warning; other systems
+//! refuse `--confine` rather than run unconfined.
+
+use std::path::Path;
+
+use projzst::ProjzstError;
+
+use crate::i18n::tr;
+use crate::Output;
+
+/// Paths an unpack may touch once confined
+pub(crate) struct Confinement<'a> {
+    /// Archive read, `None` for stdin; the directory holding it is readable, for volumes
+    pub(crate) input: Option<&'a Path>,
+    /// Output directory, created beforehand
+    pub(crate) output: &'a Path,
+    /// `metadata.json` written next to the output, created beforehand when missing
+    pub(crate) metadata_json: Option<&'a Path>,
+}
+
+impl Confinement<'_> {
+    /// Confine the process to these paths for the rest of its life
+    #[cfg(target_os = "linux")]
+    pub(crate) fn apply(&self, out: &mut Output) -> Result<(), ProjzstError> {
+        linux::no_new_privs()?;
+        match linux::landlock(self) {
+            Ok(true) => {}
+            Ok(false) => out.warn(tr!("warning-confine-no-landlock")),
+            Err(e) => return Err(e.into()),
+        }
+        if !linux::seccomp()? {
+            out.warn(tr!(
+                "warning-confine-no-seccomp",
+                arch = std::env::consts::ARCH
+            ));
+        }
+        Ok(())
+    }
+
+    /// Confine the process to these paths for the rest of its life
+    #[cfg(target_os = "openbsd")]
+    pub(crate) fn apply(&self, _out: &mut Output) -> Result<(), ProjzstError> {
+        openbsd::unveil_and_pledge(self).map_err(ProjzstError::from)
+    }
+
+    /// Confinement is not available on this system: refused rather than ignored
+    #[cfg(not(any(target_os = "linux", target_os = "openbsd")))]
+    pub(crate) fn apply(&self, _out: &mut Output) -> Result<(), ProjzstError> {
+        Err(ProjzstError::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "--confine is only available on Linux and OpenBSD",
+        )))
+    }
+
+    /// Internal helper: the directory whose files may be read, that of the input
+    #[cfg(any(target_os = "linux", target_os = "openbsd"))]
+    fn input_dir(&self) -> Option<&Path> {
+        self.input.map(|input| match input.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::Path;
+
+    use super::Confinement;
+
+    // Landlock file system rights (linux/landlock.h), those of ABI 1 first
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    const ACCESS_FS_ABI_1: u64 = (1 << 13) - 1;
+    /// Linking and renaming across directories, denied altogether under ABI 1
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+    const CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// Internal helper: never gain privileges again, which seccomp and Landlock require
+    /// of unprivileged processes
+    pub(super) fn no_new_privs() -> io::Result<()> {
+        // SAFETY: plain prctl call without pointers
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Internal helper: restrict the file system to the paths of `confinement`
+    /// Returns `false` when the kernel has no Landlock
+    pub(super) fn landlock(confinement: &Confinement<'_>) -> io::Result<bool> {
+        // SAFETY: asking for the ABI version takes no attributes
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 0 {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::ENOSYS | libc::EOPNOTSUPP) => Ok(false),
+                _ => Err(error),
+            };
+        }
+        let mut handled = ACCESS_FS_ABI_1;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_FS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: `attr` is a valid ruleset attribute of the size given
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the ruleset file descriptor was just created and is owned here
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        if let Some(dir) = confinement.input_dir() {
+            let read = ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+            add_rule(&ruleset, dir, read)?;
+        }
+        add_rule(&ruleset, confinement.output, handled & !ACCESS_FS_EXECUTE)?;
+        if let Some(metadata_json) = confinement.metadata_json {
+            let write = (ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE) & handled;
+            add_rule(&ruleset, metadata_json, write)?;
+        }
+        // SAFETY: `ruleset` is a Landlock ruleset file descriptor
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+
+    /// Internal helper: allow `access` beneath `path`
+    fn add_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> io::Result<()> {
+        let file = File::options()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)?;
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: file.as_raw_fd(),
+        };
+        // SAFETY: `attr` is a valid path-beneath attribute holding an open descriptor
+        let added = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr,
+                0,
+            )
+        };
+        if added != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// System calls an unpack never makes, failing with `EPERM` once confined
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED: &[libc::c_long] = &[
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_io_uring_setup,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_personality,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    // Classic BPF opcodes (linux/bpf_common.h) and seccomp_data offsets
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    mod bpf {
+        /// `BPF_LD | BPF_W | BPF_ABS`
+        pub(super) const LD_W_ABS: u16 = 0x20;
+        /// `BPF_JMP | BPF_JEQ | BPF_K`
+        pub(super) const JMP_JEQ_K: u16 = 0x15;
+        /// `BPF_JMP | BPF_JGE | BPF_K`
+        pub(super) const JMP_JGE_K: u16 = 0x35;
+        /// `BPF_RET | BPF_K`
+        pub(super) const RET_K: u16 = 0x06;
+        pub(super) const NR_OFFSET: u32 = 0;
+        pub(super) const ARCH_OFFSET: u32 = 4;
+        pub(super) const RET_KILL_PROCESS: u32 = 0x8000_0000;
+        pub(super) const RET_ALLOW: u32 = 0x7fff_0000;
+        /// System call numbers of the x32 ABI, also reachable from x86-64 processes
+        pub(super) const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+    }
+
+    /// Internal helper: install the seccomp filter on every thread of the process
+    /// Returns `false` on architectures the filter does not know
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub(super) fn seccomp() -> io::Result<bool> {
+        let statement = |code: u16, k: u32| libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |code: u16, k: u32, jt: u8| libc::sock_filter { code, jt, jf: 0, k };
+        let denied = DENIED.len() as u8;
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let mut program = vec![
+            statement(bpf::LD_W_ABS, bpf::ARCH_OFFSET),
+            jump(bpf::JMP_JEQ_K, AUDIT_ARCH, 1),
+            statement(bpf::RET_K, bpf::RET_KILL_PROCESS),
+            statement(bpf::LD_W_ABS, bpf::NR_OFFSET),
+        ];
+        // Past the checks of each call and the allowing return, to the denying one
+        if cfg!(target_arch = "x86_64") {
+            program.push(jump(bpf::JMP_JGE_K, bpf::X32_SYSCALL_BIT, denied + 1));
+        }
+        for (i, &nr) in DENIED.iter().enumerate() {
+            program.push(jump(bpf::JMP_JEQ_K, nr as u32, denied - i as u8));
+        }
+        program.push(statement(bpf::RET_K, bpf::RET_ALLOW));
+        program.push(statement(bpf::RET_K, deny));
+
+        let filter = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_mut_ptr(),
+        };
+        // SAFETY: `filter` points at `program`, which outlives the call
+        let installed = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_TSYNC,
+                &filter,
+            )
+        };
+        if installed != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+
+    /// Internal helper: no seccomp filter for this architecture
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub(super) fn seccomp() -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(target_os = "openbsd")]
+mod openbsd {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::Confinement;
+
+    /// Internal helper: unveil the paths of `confinement`, then pledge to file I/O
+    pub(super) fn unveil_and_pledge(confinement: &Confinement<'_>) -> io::Result<()> {
+        if let Some(dir) = confinement.input_dir() {
+            unveil(Some(dir), "r")?;
+        }
+        unveil(Some(confinement.output), "rwc")?;
+        if let Some(metadata_json) = confinement.metadata_json {
+            unveil(Some(metadata_json), "w")?;
+        }
+        unveil(None, "")?;
+        let promises = CString::new("stdio rpath wpath cpath fattr").expect("no NUL");
+        // SAFETY: `promises` is NUL-terminated; no exec promises are given
+        if unsafe { libc::pledge(promises.as_ptr(), std::ptr::null()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Internal helper: unveil `path` with `permissions`, or lock unveiling with `None`
+    fn unveil(path: Option<&Path>, permissions: &str) -> io::Result<()> {
+        let path = path
+            .map(|path| CString::new(path.as_os_str().as_bytes()))
+            .transpose()?;
+        let permissions = CString::new(permissions)?;
+        let (path, permissions) = match &path {
+            Some(path) => (path.as_ptr(), permissions.as_ptr()),
+            None => (std::ptr::null(), std::ptr::null()),
+        };
+        // SAFETY: both pointers are NUL-terminated strings or both are null
+        if unsafe { libc::unveil(path, permissions) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::*;
+
+    /// Set to the scratch directory of a re-run of the test that confines itself
+    const CONFINED_DIR: &str = "PROJZST_TEST_CONFINED_DIR";
+
+    /// Exit status of the confined run when the kernel has no Landlock
+    const NO_LANDLOCK: i32 = 77;
+
+    /// Confinement is for good, so it is tried in a re-run of this test in a child process
+    #[test]
+    fn test_writes_outside_output_are_denied() {
+        if let Some(dir) = std::env::var_os(CONFINED_DIR) {
+            confined_run(&PathBuf::from(dir));
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("out")).unwrap();
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "confine::tests::test_writes_outside_output_are_denied",
+            ])
+            .args(["--test-threads", "1", "--nocapture"])
+            .env(CONFINED_DIR, dir.path())
+            .status()
+            .unwrap();
+        if status.code() == Some(NO_LANDLOCK) {
+            eprintln!("skipped: no Landlock on this kernel");
+            return;
+        }
+        assert!(status.success(), "confined run failed: {status}");
+        assert!(dir.path().join("out/inside.txt").exists());
+        assert!(!dir.path().join("outside.txt").exists());
+    }
+
+    /// Internal helper: confine this process to `dir/out`, then write inside and outside
+    fn confined_run(dir: &Path) -> ! {
+        let output = dir.join("out");
+        let confinement = Confinement {
+            input: None,
+            output: &output,
+            metadata_json: None,
+        };
+        linux::no_new_privs().unwrap();
+        if !matches!(linux::landlock(&confinement), Ok(true)) {
+            std::process::exit(NO_LANDLOCK);
+        }
+        std::fs::write(output.join("inside.txt"), "inside").unwrap();
+        let denied = std::fs::write(dir.join("outside.txt"), "outside").unwrap_err();
+        assert_eq!(denied.kind(), ErrorKind::PermissionDenied);
+        std::process::exit(0);
+    }
+}
@@ -0,0 +1,359 @@
+//! Conversion between .pjz and standard archive formats
+//!
+//! Exporting turns a .pjz file into a plain `.tar`, `.tar.zst`, `.tar.gz` or `.zip`,
+//! dropping the metadata or writing it to a JSON sidecar next to the output. Importing
+//! wraps such an archive into a .pjz file with metadata attached. The .pjz payload is
+//! itself a tar.zst stream, so `.tar.zst` is converted in both directions without
+//! recompressing. `.tar.gz` needs the `gzip` feature and `.zip` the `zip` feature.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+#[cfg(feature = "zip")]
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+
+use crate::builder::{open_archive, open_payload, read_metadata_from_reader, Packer};
+use crate::codec::{zstd_decoder, PayloadCodec};
+#[cfg(feature = "zip")]
+use crate::date::{civil_from_days, days_from_civil};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::revisions::latest_revision;
+#[cfg(feature = "zip")]
+use crate::storage::normalize_path;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+
+/// Standard archive format a .pjz file can be converted to or from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    /// Uncompressed tarball (`.tar`)
+    Tar,
+    /// Zstd-compressed tarball (`.tar.zst`, `.tzst`)
+    TarZst,
+    /// Gzip-compressed tarball (`.tar.gz`, `.tgz`)
+    TarGz,
+    /// Zip archive (`.zip`)
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format from a file name extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this build can read and write archives of the format
+    pub fn is_available(self) -> bool {
+        match self {
+            ArchiveFormat::Tar | ArchiveFormat::TarZst => true,
+            ArchiveFormat::TarGz => cfg!(feature = "gzip"),
+            ArchiveFormat::Zip => cfg!(feature = "zip"),
+        }
+    }
+
+    /// Internal helper: fail with [`ProjzstError::FeatureUnavailable`] unless this build
+    /// supports the format
+    fn check_available(self) -> Result<()> {
+        let feature = match self {
+            ArchiveFormat::TarGz if !self.is_available() => "gzip",
+            ArchiveFormat::Zip if !self.is_available() => "zip",
+            _ => return Ok(()),
+        };
+        Err(ProjzstError::FeatureUnavailable {
+            what: format!("{self:?} conversion"),
+            feature: feature.to_string(),
+        })
+    }
+}
+
+/// Path of the JSON sidecar holding the metadata of a converted archive
+/// (`out.tar.gz` gives `out.tar.gz.metadata.json`)
+pub fn sidecar_path<P: AsRef<Path>>(archive: P) -> PathBuf {
+    let mut path = archive.as_ref().as_os_str().to_owned();
+    path.push(".metadata.json");
+    PathBuf::from(path)
+}
+
+/// Convert a .pjz file into a standard archive
+/// Returns the metadata of the input, which the output itself does not carry
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+/// * `output_file` - Path of the archive to create
+/// * `format` - Format of the output archive
+/// * `sidecar` - Also write the metadata as JSON to [`sidecar_path`] of the output
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn export_archive<P1, P2>(
+    input_file: P1,
+    output_file: P2,
+    format: ArchiveFormat,
+    sidecar: bool,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let input_file = input_file.as_ref();
+    let output_file = output_file.as_ref();
+    format.check_available()?;
+    let mut reader = open_input(input_file)?;
+
+    // Only a complete archive replaces the output file
+    let (mut output, temp) = temp_file_for(output_file)?;
+    let metadata = match format {
+        ArchiveFormat::TarZst => {
+            let (metadata, payload_head) = read_metadata_from_reader(&mut reader, ignore_unknown)?;
+            if payload_head.codec == PayloadCodec::Zstd && payload_head.dictionary.is_none() {
+                // The payload already is a tar.zst stream: copy it past the metadata frames
+                output.write_all(&payload_head.bytes)?;
+                io::copy(&mut reader, &mut output)?;
+                drop(output);
+            } else {
+                let mut tar_stream = open_payload(payload_head, reader)?.into_inner();
+                let mut zst_encoder =
+                    zstd::stream::Encoder::new(output, crate::DEFAULT_ZSTD_LEVEL)?;
+                io::copy(&mut tar_stream, &mut zst_encoder)?;
+                zst_encoder.finish()?;
+            }
+            metadata
+        }
+        ArchiveFormat::Tar => {
+            let (metadata, tar_archive) = open_archive(reader, ignore_unknown)?;
+            io::copy(&mut tar_archive.into_inner(), &mut { output })?;
+            metadata
+        }
+        #[cfg(feature = "gzip")]
+        ArchiveFormat::TarGz => {
+            let (metadata, tar_archive) = open_archive(reader, ignore_unknown)?;
+            let mut gz_encoder = GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut tar_archive.into_inner(), &mut gz_encoder)?;
+            gz_encoder.finish()?;
+            metadata
+        }
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => {
+            let (metadata, mut tar_archive) = open_archive(reader, ignore_unknown)?;
+            write_zip(&mut tar_archive, output)?;
+            metadata
+        }
+        #[cfg(not(all(feature = "gzip", feature = "zip")))]
+        _ => unreachable!("{format:?} is checked to be available above"),
+    };
+    temp.persist(output_file)?;
+    let metadata = latest_revision(input_file, ignore_unknown)?.unwrap_or(metadata);
+
+    if sidecar {
+        let json_content = serde_json::to_string_pretty(&metadata)?;
+        fs::write(sidecar_path(output_file), json_content)?;
+    }
+
+    Ok(metadata)
+}
+
+/// Wrap a standard archive into a .pjz file
+/// Shorthand for [`Packer::pack_archive`] with the given metadata and compression level
+///
+/// # Arguments
+/// * `input_file` - Path of the archive to wrap
+/// * `format` - Format of the input archive
+/// * `output_file` - Output .pjz file path
+/// * `metadata` - Metadata to embed
+/// * `compression_level` - Zstd compression level (unused for `.tar.zst` inputs)
+pub fn import_archive<P1, P2>(
+    input_file: P1,
+    format: ArchiveFormat,
+    output_file: P2,
+    metadata: Metadata,
+    compression_level: i32,
+) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    Packer::new(metadata)
+        .compression_level(compression_level)
+        .pack_archive(input_file, format, output_file)
+}
+
+impl Packer {
+    /// Wrap a standard archive into a .pjz file instead of packing a directory
+    /// `.tar.zst` inputs are copied as they are; other formats, and every format packed with
+    /// another [`Packer::codec`], are re-encoded
+    pub fn pack_archive<P1, P2>(
+        &self,
+        input_file: P1,
+        format: ArchiveFormat,
+        output_file: P2,
+    ) -> Result<()>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let input_file = input_file.as_ref();
+        if !input_file.is_file() {
+            return Err(ProjzstError::SourceNotFound(
+                input_file.display().to_string(),
+            ));
+        }
+        let output_file = output_file.as_ref();
+        self.check_overwrite(output_file)?;
+        format.check_available()?;
+        if let Some(packer) = self.carrying_foreign_frames(output_file) {
+            return packer.pack_archive(input_file, format, output_file);
+        }
+        let metadata_bytes = self.encoded_metadata()?;
+
+        // Only a complete archive replaces the output file
+        let (mut output, temp) = temp_file_for(output_file)?;
+        self.write_header(&mut output, &metadata_bytes)?;
+
+        let mut input = File::open(input_file)?;
+        if format == ArchiveFormat::TarZst
+            && self.codec == PayloadCodec::Zstd
+            && self.active_dictionary().is_none()
+        {
+            io::copy(&mut input, &mut output)?;
+        } else {
+            let mut zst_encoder = self.payload_encoder(&mut output)?;
+            match format {
+                ArchiveFormat::Tar => {
+                    io::copy(&mut input, &mut zst_encoder)?;
+                }
+                #[cfg(feature = "gzip")]
+                ArchiveFormat::TarGz => {
+                    io::copy(&mut GzDecoder::new(input), &mut zst_encoder)?;
+                }
+                #[cfg(feature = "zip")]
+                ArchiveFormat::Zip => {
+                    let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+                    read_zip(input, &mut tar_builder)?;
+                    tar_builder.finish()?;
+                }
+                ArchiveFormat::TarZst => {
+                    io::copy(&mut zstd_decoder(input)?, &mut zst_encoder)?;
+                }
+                #[cfg(not(all(feature = "gzip", feature = "zip")))]
+                _ => unreachable!("{format:?} is checked to be available above"),
+            }
+            zst_encoder.finish()?.flush()?;
+        }
+        self.write_trailer(&mut output, &metadata_bytes)?;
+        self.persist(output, temp, output_file)
+    }
+}
+
+/// Internal helper: write every tar entry into a new zip archive
+#[cfg(feature = "zip")]
+fn write_zip<R: Read, W: Write + Seek>(tar_archive: &mut tar::Archive<R>, output: W) -> Result<()> {
+    let mut zip_writer = zip::ZipWriter::new(output);
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        let header = entry.header();
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(header.mode()? & 0o7777)
+            .large_file(header.size()? >= u64::from(u32::MAX));
+        if let Some(time) = zip_time(header.mtime()?) {
+            options = options.last_modified_time(time);
+        }
+
+        let entry_type = header.entry_type();
+        if entry_type.is_dir() {
+            zip_writer.add_directory(format!("{path}/"), options)?;
+        } else if entry_type.is_symlink() {
+            // A zip symlink: S_IFLNK in the Unix mode, the target as content
+            let target = entry.link_name()?.unwrap_or_default();
+            zip_writer.add_symlink(path, target.to_string_lossy(), options)?;
+        } else if entry_type.is_file() {
+            zip_writer.start_file(path, options)?;
+            io::copy(&mut entry, &mut zip_writer)?;
+        }
+    }
+    zip_writer.finish()?;
+    Ok(())
+}
+
+/// Internal helper: append every zip entry to a tar stream
+#[cfg(feature = "zip")]
+fn read_zip<R: Read + Seek, W: Write>(input: R, tar_builder: &mut tar::Builder<W>) -> Result<()> {
+    let mut zip_archive = zip::ZipArchive::new(input)?;
+    for index in 0..zip_archive.len() {
+        let mut file = zip_archive.by_index(index)?;
+        // Entries escaping the archive root are left out, as on unpack
+        let Some(path) = file.enclosed_name().as_deref().and_then(normalize_path) else {
+            continue;
+        };
+
+        let mut header = tar::Header::new_gnu();
+        let default_mode = if file.is_dir() { 0o755 } else { 0o644 };
+        header.set_mode(file.unix_mode().map_or(default_mode, |mode| mode & 0o7777));
+        header.set_mtime(file.last_modified().map_or(0, unix_time));
+
+        if file.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            tar_builder.append_data(&mut header, &path, io::empty())?;
+        } else if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            tar_builder.append_link(&mut header, &path, target)?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size());
+            tar_builder.append_data(&mut header, &path, &mut file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: Unix seconds as a zip (MS-DOS) timestamp, `None` outside 1980..=2107
+#[cfg(feature = "zip")]
+fn zip_time(secs: u64) -> Option<zip::DateTime> {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month,
+        day,
+        (rem / 3600) as u8,
+        (rem % 3600 / 60) as u8,
+        (rem % 60) as u8,
+    )
+    .ok()
+}
+
+/// Internal helper: zip timestamp as Unix seconds (zip times carry no zone; read as UTC)
+#[cfg(feature = "zip")]
+fn unix_time(time: zip::DateTime) -> u64 {
+    let days = days_from_civil(i64::from(time.year()), time.month(), time.day());
+    let secs = days * 86_400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    secs.max(0) as u64
+}
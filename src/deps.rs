@@ -0,0 +1,527 @@
+//! Dependency declarations between packages
+//!
+//! A package lists what it needs in `deps`: a package name, a version requirement and
+//! optionally the digest of the exact archive it was built against. Requirements use the
+//! Cargo syntax (`^1.2`, `~1.2.3`, `>=1.0, <2.0`, `=1.4.0`, `1.*`, `*`); a bare version is
+//! a caret requirement. Pre-releases only match a requirement naming a pre-release of the
+//! same `major.minor.patch`, as in Cargo.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::Metadata;
+use crate::string_utils::to_hex;
+
+/// A semantic version (`1.2.3`, `2.0.0-rc.1+build.5`)
+/// Versions differing only in their build metadata are equal, as semver precedence has it
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Version {
+    /// Incremented for incompatible changes
+    pub major: u64,
+    /// Incremented for compatible additions
+    pub minor: u64,
+    /// Incremented for compatible fixes
+    pub patch: u64,
+    /// Pre-release identifiers (`rc.1`), empty for a release
+    pub pre: String,
+    /// Build metadata (`build.5`), ignored when comparing
+    pub build: String,
+}
+
+impl Version {
+    /// Parse a version following Semantic Versioning 2.0.0
+    /// Fails with [`ProjzstError::InvalidVersion`] otherwise
+    pub fn parse(version: &str) -> Result<Self> {
+        let invalid = || ProjzstError::InvalidVersion(version.to_string());
+        let (rest, build) = match version.split_once('+') {
+            Some((rest, build)) if build.split('.').all(is_identifier) => (rest, build),
+            Some(_) => return Err(invalid()),
+            None => (version, ""),
+        };
+        let (core, pre) = match rest.split_once('-') {
+            Some((core, pre)) if pre.split('.').all(is_pre_identifier) => (core, pre),
+            Some(_) => return Err(invalid()),
+            None => (rest, ""),
+        };
+        let numbers: Vec<u64> = core
+            .split('.')
+            .map(|part| parse_number(part).ok_or_else(invalid))
+            .collect::<Result<_>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre: pre.to_string(),
+            build: build.to_string(),
+        })
+    }
+}
+
+/// Part of a version incremented by [`Version::bump`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VersionPart {
+    /// `1.2.3` to `2.0.0`
+    Major,
+    /// `1.2.3` to `1.3.0`
+    Minor,
+    /// `1.2.3` to `1.2.4`
+    Patch,
+}
+
+impl Version {
+    /// The next version: `part` incremented and the numbers after it reset, then `pre`
+    /// set as the pre-release; build metadata is dropped
+    /// A pre-release already at the version `part` leads to is released instead
+    /// (`2.0.0-rc.1` to `2.0.0` for a major bump), and `pre` alone on a release bumps
+    /// the patch first (`1.2.3` to `1.2.4-rc.1`). Fails with
+    /// [`ProjzstError::InvalidVersion`] when `pre` is not a valid pre-release and
+    /// [`ProjzstError::VersionNotBumped`] when the result would not come after this version
+    ///
+    /// # Arguments
+    /// * `part` - Number to increment, or `None` to only change the pre-release
+    /// * `pre` - Pre-release identifiers of the new version (`rc.1`)
+    pub fn bump(&self, part: Option<VersionPart>, pre: Option<&str>) -> Result<Self> {
+        let released = self.pre.is_empty();
+        let (major, minor, patch) = (self.major, self.minor, self.patch);
+        let (major, minor, patch) = match part {
+            Some(VersionPart::Major) if released || minor != 0 || patch != 0 => (major + 1, 0, 0),
+            Some(VersionPart::Minor) if released || patch != 0 => (major, minor + 1, 0),
+            Some(VersionPart::Patch) | None if released => (major, minor, patch + 1),
+            _ => (major, minor, patch),
+        };
+        let mut next = format!("{major}.{minor}.{patch}");
+        if let Some(pre) = pre {
+            next = format!("{next}-{pre}");
+        }
+        let next = Self::parse(&next)?;
+        if next <= *self {
+            return Err(ProjzstError::VersionNotBumped {
+                from: self.to_string(),
+                to: next.to_string(),
+            });
+        }
+        Ok(next)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre)?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| cmp_pre(&self.pre, &other.pre))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.major, self.minor, self.patch, &self.pre).hash(state);
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Comparison operator of one requirement clause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+}
+
+/// One clause of a requirement; missing minor or patch numbers are wildcards
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: String,
+}
+
+/// A version requirement (`^1.2`, `>=1.0, <2.0`, ...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+    /// Parse a requirement in the Cargo syntax
+    /// Fails with [`ProjzstError::InvalidVersionReq`] otherwise
+    pub fn parse(req: &str) -> Result<Self> {
+        let invalid = || ProjzstError::InvalidVersionReq(req.to_string());
+        if req.trim() == "*" {
+            return Ok(Self {
+                comparators: Vec::new(),
+            });
+        }
+        let comparators = req
+            .split(',')
+            .map(|clause| parse_comparator(clause.trim()).ok_or_else(invalid))
+            .collect::<Result<_>>()?;
+        Ok(Self { comparators })
+    }
+
+    /// Whether `version` satisfies every clause of the requirement
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+            && (version.pre.is_empty()
+                || self.comparators.iter().any(|c| {
+                    c.major == version.major
+                        && c.minor == Some(version.minor)
+                        && c.patch == Some(version.patch)
+                        && !c.pre.is_empty()
+                }))
+    }
+}
+
+impl Comparator {
+    /// Internal helper: whether `v` satisfies this clause
+    fn matches(&self, v: &Version) -> bool {
+        match self.op {
+            Op::Exact => self.matches_exact(v),
+            Op::Greater => self.matches_greater(v),
+            Op::GreaterEq => self.matches_exact(v) || self.matches_greater(v),
+            Op::Less => self.matches_less(v),
+            Op::LessEq => self.matches_exact(v) || self.matches_less(v),
+            Op::Tilde => self.matches_tilde(v),
+            Op::Caret => self.matches_caret(v),
+        }
+    }
+
+    fn matches_exact(&self, v: &Version) -> bool {
+        v.major == self.major
+            && self.minor.is_none_or(|minor| v.minor == minor)
+            && self.patch.is_none_or(|patch| v.patch == patch)
+            && v.pre == self.pre
+    }
+
+    fn matches_greater(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return v.major > self.major;
+        }
+        let Some(minor) = self.minor else {
+            return false;
+        };
+        if v.minor != minor {
+            return v.minor > minor;
+        }
+        let Some(patch) = self.patch else {
+            return false;
+        };
+        if v.patch != patch {
+            return v.patch > patch;
+        }
+        cmp_pre(&v.pre, &self.pre) == Ordering::Greater
+    }
+
+    fn matches_less(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return v.major < self.major;
+        }
+        let Some(minor) = self.minor else {
+            return false;
+        };
+        if v.minor != minor {
+            return v.minor < minor;
+        }
+        let Some(patch) = self.patch else {
+            return false;
+        };
+        if v.patch != patch {
+            return v.patch < patch;
+        }
+        cmp_pre(&v.pre, &self.pre) == Ordering::Less
+    }
+
+    fn matches_tilde(&self, v: &Version) -> bool {
+        if v.major != self.major || self.minor.is_some_and(|minor| v.minor != minor) {
+            return false;
+        }
+        if let Some(patch) = self.patch {
+            if v.patch != patch {
+                return v.patch > patch;
+            }
+        }
+        cmp_pre(&v.pre, &self.pre) != Ordering::Less
+    }
+
+    fn matches_caret(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return false;
+        }
+        let Some(minor) = self.minor else {
+            return true;
+        };
+        let Some(patch) = self.patch else {
+            return if self.major > 0 {
+                v.minor >= minor
+            } else {
+                v.minor == minor
+            };
+        };
+        if self.major > 0 {
+            if v.minor != minor {
+                return v.minor > minor;
+            }
+            if v.patch != patch {
+                return v.patch > patch;
+            }
+        } else if minor > 0 {
+            if v.minor != minor {
+                return false;
+            }
+            if v.patch != patch {
+                return v.patch > patch;
+            }
+        } else if v.minor != minor || v.patch != patch {
+            return false;
+        }
+        cmp_pre(&v.pre, &self.pre) != Ordering::Less
+    }
+}
+
+/// Another package a package needs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Dependency {
+    /// Name of the package needed
+    pub name: String,
+    /// Version requirement, see [`VersionReq`]
+    pub req: String,
+    /// Digest of the exact archive built against (`sha256:<hex>`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+impl Dependency {
+    /// Declare a dependency, checking the requirement and digest
+    ///
+    /// # Arguments
+    /// * `name` - Name of the package needed
+    /// * `req` - Version requirement, e.g. `^1.2`
+    /// * `digest` - Optional `sha256:<hex>` digest of the archive
+    pub fn new<I: crate::IntoOpStr>(
+        name: impl Into<String>,
+        req: impl Into<String>,
+        digest: I,
+    ) -> Result<Self> {
+        let dependency = Self {
+            name: name.into(),
+            req: req.into(),
+            digest: digest.into_op_str(),
+        };
+        dependency.version_req()?;
+        if let Some(digest) = &dependency.digest {
+            let hex = digest.strip_prefix("sha256:").unwrap_or_default();
+            if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(ProjzstError::InvalidDigest(digest.clone()));
+            }
+        }
+        Ok(dependency)
+    }
+
+    /// The parsed version requirement
+    pub fn version_req(&self) -> Result<VersionReq> {
+        VersionReq::parse(&self.req)
+    }
+
+    /// Whether a package satisfies this dependency: same name, and a version meeting the
+    /// requirement
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        if metadata.name.as_deref() != Some(self.name.as_str()) {
+            return false;
+        }
+        match (self.version_req(), metadata.version()) {
+            (Ok(req), Ok(ver)) => req.matches(&ver),
+            _ => false,
+        }
+    }
+
+    /// Check an archive file against the pinned digest
+    /// Returns `true` when no digest is pinned
+    pub fn verify_digest<P: AsRef<Path>>(&self, archive: P) -> Result<bool> {
+        let Some(digest) = &self.digest else {
+            return Ok(true);
+        };
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(archive.as_ref())?, &mut hasher)?;
+        Ok(digest.strip_prefix("sha256:") == Some(to_hex(&hasher.finalize()).as_str()))
+    }
+}
+
+/// Pick the package satisfying each dependency of `metadata` among `available`
+/// The highest matching version wins. Fails with
+/// [`ProjzstError::UnresolvedDependencies`] listing every dependency nothing satisfies
+///
+/// # Arguments
+/// * `metadata` - Metadata declaring the dependencies
+/// * `available` - Metadata of the packages to choose from
+pub fn resolve_dependencies<'a>(
+    metadata: &Metadata,
+    available: &'a [Metadata],
+) -> Result<Vec<&'a Metadata>> {
+    let mut resolved = Vec::new();
+    let mut unresolved = Vec::new();
+    for dependency in &metadata.deps {
+        let best = available
+            .iter()
+            .filter(|candidate| dependency.matches(candidate))
+            .filter_map(|candidate| Some((candidate.version().ok()?, candidate)))
+            .max_by(|a, b| a.0.cmp(&b.0));
+        match best {
+            Some((_, candidate)) => resolved.push(candidate),
+            None => unresolved.push(format!("{} {}", dependency.name, dependency.req)),
+        }
+    }
+    if !unresolved.is_empty() {
+        return Err(ProjzstError::UnresolvedDependencies(unresolved));
+    }
+    Ok(resolved)
+}
+
+/// Internal helper: parse one requirement clause (`>=1.2`, `~1.2.3`, `1.*`, `1.2`)
+fn parse_comparator(clause: &str) -> Option<Comparator> {
+    let (op, rest) = [
+        (">=", Op::GreaterEq),
+        ("<=", Op::LessEq),
+        (">", Op::Greater),
+        ("<", Op::Less),
+        ("=", Op::Exact),
+        ("~", Op::Tilde),
+        ("^", Op::Caret),
+    ]
+    .into_iter()
+    .find_map(|(prefix, op)| clause.strip_prefix(prefix).map(|rest| (op, rest)))
+    .unwrap_or((Op::Caret, clause));
+    let rest = rest.trim();
+
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) if pre.split('.').all(is_pre_identifier) => (core, pre),
+        Some(_) => return None,
+        None => (rest, ""),
+    };
+    let mut parts = core.split('.');
+    let major = parse_number(parts.next()?)?;
+    let mut wildcard = false;
+    let mut next = |parts: &mut std::str::Split<'_, char>| -> Option<Option<u64>> {
+        match parts.next() {
+            None => Some(None),
+            Some("*" | "x" | "X") => {
+                wildcard = true;
+                Some(None)
+            }
+            Some(_) if wildcard => None,
+            Some(part) => parse_number(part).map(Some),
+        }
+    };
+    let minor = next(&mut parts)?;
+    let patch = next(&mut parts)?;
+    if parts.next().is_some() || (minor.is_none() && patch.is_some()) {
+        return None;
+    }
+    // Pre-releases need a full version; wildcards only make sense as exact matches
+    if !pre.is_empty() && patch.is_none() {
+        return None;
+    }
+    let op = if wildcard && op == Op::Caret {
+        Op::Exact
+    } else {
+        op
+    };
+    Some(Comparator {
+        op,
+        major,
+        minor,
+        patch,
+        pre: pre.to_string(),
+    })
+}
+
+/// Internal helper: order pre-release strings; a release (empty) sorts after any
+/// pre-release, numeric identifiers compare as numbers and before alphanumeric ones
+fn cmp_pre(a: &str, b: &str) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        _ => {}
+    }
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let ordering = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Internal helper: a number without leading zeros
+fn parse_number(part: &str) -> Option<u64> {
+    let valid = !part.is_empty()
+        && part.bytes().all(|b| b.is_ascii_digit())
+        && (part == "0" || !part.starts_with('0'));
+    valid.then(|| part.parse().ok()).flatten()
+}
+
+/// Internal helper: a non-empty run of alphanumerics and hyphens
+fn is_identifier(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// Internal helper: a pre-release identifier (numeric ones without leading zeros)
+fn is_pre_identifier(id: &str) -> bool {
+    is_identifier(id) && (!id.bytes().all(|b| b.is_ascii_digit()) || parse_number(id).is_some())
+}
@@ -0,0 +1,106 @@
+//! Zstd compression dictionaries
+//!
+//! Small archives of similar content (the packages of one project, release after
+//! release) compress poorly one by one: every payload starts from an empty window. A
+//! dictionary trained on samples of that content primes the window, see
+//! [`Dictionary::train`]. [`Packer::dictionary`](crate::Packer::dictionary) compresses the
+//! payload with one and records its ID in the metadata (`pjz_dict`). The dictionary is not
+//! stored in the archive: readers are given the same one with
+//! [`ReadOptions::dictionary`](crate::ReadOptions::dictionary), and fail with
+//! [`ProjzstError::DictionaryRequired`] without it. Catalogs keep their dictionaries next to
+//! the catalog file, see [`train_catalog_dictionary`](crate::train_catalog_dictionary).
+//!
+//! Only zstd payloads use dictionaries, and the frame-level tools (`stat`, `repair`,
+//! `validate`, indexed reads) do not decode them.
+
+use std::fs;
+use std::path::Path;
+
+use crate::errors::{ProjzstError, Result};
+
+/// Magic number starting a zstd dictionary (little-endian on disk)
+const DICTIONARY_MAGIC: u32 = 0xEC30A437;
+
+/// Default size of a trained dictionary (112 KiB, the size the zstd CLI trains)
+pub const DEFAULT_DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// A zstd dictionary, as written by `zstd --train` or [`Dictionary::train`]
+#[derive(Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    bytes: Vec<u8>,
+}
+
+impl Dictionary {
+    /// Parse a zstd dictionary
+    /// Fails with [`ProjzstError::InvalidDictionary`] on raw content without the zstd
+    /// dictionary header, which carries no ID to record in archives
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let magic = bytes
+            .get(..4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        let id = bytes
+            .get(4..8)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        match (magic, id) {
+            (Some(DICTIONARY_MAGIC), Some(id)) if id != 0 => Ok(Self { id, bytes }),
+            _ => Err(ProjzstError::InvalidDictionary(
+                "not a zstd dictionary with an ID".to_string(),
+            )),
+        }
+    }
+
+    /// Read a dictionary file, see [`Dictionary::from_bytes`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(fs::read(path.as_ref())?)
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on samples of the content to compress
+    /// zstd wants about a hundred times `max_size` of samples, in many small pieces (one
+    /// per file); fails with [`ProjzstError::InvalidDictionary`] when they are too few
+    ///
+    /// # Arguments
+    /// * `samples` - Contents of files like those to be packed
+    /// * `max_size` - Largest dictionary size in bytes, see [`DEFAULT_DICTIONARY_SIZE`]
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self> {
+        let bytes = zstd::dict::from_samples(samples, max_size).map_err(|e| {
+            ProjzstError::InvalidDictionary(format!(
+                "training on {} samples failed: {e}",
+                samples.len()
+            ))
+        })?;
+        Self::from_bytes(bytes)
+    }
+
+    /// ID of the dictionary, recorded in the archives compressed with it
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The dictionary as stored in a file
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dictionary")
+            .field("id", &format_args!("{:08x}", self.id))
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// Internal helper: the dictionary among `given` that an archive recording `recorded`
+/// needs, failing with [`ProjzstError::DictionaryRequired`] when it is not there
+pub(crate) fn required(recorded: Option<u32>, given: &[Dictionary]) -> Result<Option<&Dictionary>> {
+    let Some(id) = recorded else {
+        return Ok(None);
+    };
+    given
+        .iter()
+        .find(|dictionary| dictionary.id == id)
+        .map(Some)
+        .ok_or(ProjzstError::DictionaryRequired(id))
+}
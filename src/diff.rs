@@ -0,0 +1,275 @@
+//! Content and metadata comparison between two archives
+//!
+//! Entries are matched by path and compared by SHA-256 of their content,
+//! so timestamps and permission changes alone do not count as modifications.
+//! Metadata is compared field by field, with `extra` broken down recursively by key.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::builder::{open_archive, read_metadata};
+use crate::errors::Result;
+use crate::metadata::{IgnoreUnknown, Metadata, FIELDS};
+use crate::storage::normalize_path;
+use crate::string_utils::to_hex;
+use crate::volume::open_input;
+
+/// Path, size and content hash of one archive entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct EntryDigest {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Content size in bytes
+    pub size: u64,
+    /// Hex SHA-256 of the content (of the link target for symlinks)
+    pub sha256: String,
+}
+
+/// An entry present in both archives with different content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ModifiedEntry {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Digest in the old archive
+    pub old: EntryDigest,
+    /// Digest in the new archive
+    pub new: EntryDigest,
+}
+
+/// Differences between two archives, each list sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ArchiveDiff {
+    /// Entries only in the new archive
+    pub added: Vec<EntryDigest>,
+    /// Entries only in the old archive
+    pub removed: Vec<EntryDigest>,
+    /// Entries in both archives whose content differs
+    pub modified: Vec<ModifiedEntry>,
+}
+
+impl ArchiveDiff {
+    /// Whether the two archives have identical content
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compute the digest of every file and symlink entry in a .pjz file, sorted by path
+/// Hard links get the digest of the file they name; directories carry no content and are
+/// left out
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+pub fn entry_digests<P: AsRef<Path>>(input_file: P) -> Result<Vec<EntryDigest>> {
+    entry_digests_with(input_file.as_ref(), |_| {})
+}
+
+/// Internal helper: compute the digests like [`entry_digests`], handing each one to
+/// `on_digest` as soon as it is known, in archive order
+pub(crate) fn entry_digests_with<F>(input_file: &Path, mut on_digest: F) -> Result<Vec<EntryDigest>>
+where
+    F: FnMut(&EntryDigest),
+{
+    let file = open_input(input_file)?;
+    let (_, mut tar_archive) = open_archive(file, IgnoreUnknown::On)?;
+
+    let mut digests: Vec<EntryDigest> = Vec::new();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+
+        let mut hasher = Sha256::new();
+        let size = if entry_type.is_symlink() {
+            let target = entry.link_name()?.unwrap_or_default();
+            let target = target.to_string_lossy();
+            hasher.update(target.as_bytes());
+            target.len() as u64
+        } else if entry_type.is_file() {
+            io::copy(&mut entry, &mut hasher)?
+        } else if entry_type.is_hard_link() {
+            // A hard link has the content of the file it names, stored earlier
+            let target = entry.link_name()?.unwrap_or_default();
+            let target = normalize_path(&target).unwrap_or_default();
+            let Some(linked) = digests.iter().find(|digest| digest.path == target) else {
+                continue;
+            };
+            let digest = EntryDigest {
+                path,
+                ..linked.clone()
+            };
+            on_digest(&digest);
+            digests.push(digest);
+            continue;
+        } else {
+            continue;
+        };
+
+        let digest = EntryDigest {
+            path,
+            size,
+            sha256: to_hex(&hasher.finalize()),
+        };
+        on_digest(&digest);
+        digests.push(digest);
+    }
+    // To the end of the last frame, so its content checksum is checked
+    io::copy(&mut tar_archive.into_inner(), &mut io::sink())?;
+
+    digests.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(digests)
+}
+
+/// Compare the content of two .pjz files
+/// Returns the entries added, removed and modified going from `old_file` to `new_file`
+///
+/// # Arguments
+/// * `old_file` - Path to the baseline .pjz file
+/// * `new_file` - Path to the .pjz file to compare against it
+pub fn diff<P1, P2>(old_file: P1, new_file: P2) -> Result<ArchiveDiff>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let old: BTreeMap<String, EntryDigest> = entry_digests(old_file)?
+        .into_iter()
+        .map(|d| (d.path.clone(), d))
+        .collect();
+    let mut new: BTreeMap<String, EntryDigest> = entry_digests(new_file)?
+        .into_iter()
+        .map(|d| (d.path.clone(), d))
+        .collect();
+
+    let mut result = ArchiveDiff::default();
+    for (path, old_digest) in old {
+        match new.remove(&path) {
+            None => result.removed.push(old_digest),
+            Some(new_digest) if new_digest.sha256 != old_digest.sha256 => {
+                result.modified.push(ModifiedEntry {
+                    path,
+                    old: old_digest,
+                    new: new_digest,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    result.added = new.into_values().collect();
+
+    Ok(result)
+}
+
+/// One metadata field that differs between two archives
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct FieldChange {
+    /// Field name; values inside `extra` are reported by dotted path (`extra.build.commit`)
+    pub field: String,
+    /// Value in the old metadata (`None` when absent)
+    pub old: Option<serde_json::Value>,
+    /// Value in the new metadata (`None` when absent)
+    pub new: Option<serde_json::Value>,
+}
+
+/// Field-by-field differences between two metadata records
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct MetadataDiff {
+    /// Changed fields, in declaration order followed by `extra` paths sorted by key
+    pub changes: Vec<FieldChange>,
+}
+
+impl MetadataDiff {
+    /// Whether the two records are identical
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compare two metadata records field by field
+/// `extra` is compared recursively: nested objects are broken down key by key and any
+/// other value (arrays included) is reported as a whole
+pub fn diff_metadata(old: &Metadata, new: &Metadata) -> MetadataDiff {
+    let mut result = MetadataDiff::default();
+
+    for field in FIELDS {
+        let old_value = old.field_value(field);
+        let new_value = new.field_value(field);
+        if old_value != new_value {
+            result.changes.push(FieldChange {
+                field: field.to_string(),
+                old: old_value,
+                new: new_value,
+            });
+        }
+    }
+
+    diff_values("extra", &old.extra, &new.extra, &mut result.changes);
+
+    result
+}
+
+/// Compare the metadata of two .pjz files field by field
+/// Only the metadata frames are read; see [`diff_metadata`] for how fields are compared
+///
+/// # Arguments
+/// * `old_file` - Path to the baseline .pjz file
+/// * `new_file` - Path to the .pjz file to compare against it
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn diff_archive_metadata<P1, P2>(
+    old_file: P1,
+    new_file: P2,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<MetadataDiff>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let old = read_metadata(old_file, ignore_unknown)?;
+    let new = read_metadata(new_file, ignore_unknown)?;
+    Ok(diff_metadata(&old, &new))
+}
+
+/// Internal helper: record the differences between two JSON values under `path`,
+/// descending into objects present on both sides
+fn diff_values(
+    path: &str,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (old, new) {
+        (serde_json::Value::Object(old_map), serde_json::Value::Object(new_map)) => {
+            let keys: std::collections::BTreeSet<&String> =
+                old_map.keys().chain(new_map.keys()).collect();
+            for key in keys {
+                let child = format!("{path}.{key}");
+                match (old_map.get(key), new_map.get(key)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_values(&child, old_value, new_value, changes)
+                    }
+                    (old_value, new_value) => changes.push(FieldChange {
+                        field: child,
+                        old: old_value.cloned(),
+                        new: new_value.cloned(),
+                    }),
+                }
+            }
+        }
+        _ if old != new => changes.push(FieldChange {
+            field: path.to_string(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
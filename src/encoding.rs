@@ -0,0 +1,111 @@
+//! Character encoding detection for archive content
+//!
+//! Text inside archives is not always UTF-8. [`TextEncoding::detect`] recognizes UTF-8,
+//! UTF-16 (with or without byte order mark) and Latin-1 from the content alone, so text is
+//! shown and searched correctly instead of being garbled or taken for binary data.
+
+use serde::Serialize;
+
+/// UTF-8 byte order mark
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// UTF-16 little-endian byte order mark
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+/// UTF-16 big-endian byte order mark
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Character encoding of text content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum TextEncoding {
+    /// UTF-8 (including plain ASCII)
+    Utf8,
+    /// UTF-16, little-endian
+    Utf16Le,
+    /// UTF-16, big-endian
+    Utf16Be,
+    /// ISO-8859-1
+    Latin1,
+}
+
+impl TextEncoding {
+    /// Guess the encoding of `data`, `None` when it looks like binary content
+    /// With `truncated`, a character cut at the end of `data` is not held against it
+    pub fn detect(data: &[u8], truncated: bool) -> Option<Self> {
+        if data.starts_with(UTF8_BOM) {
+            return Some(TextEncoding::Utf8);
+        }
+        if data.starts_with(UTF16_LE_BOM) {
+            return Some(TextEncoding::Utf16Le);
+        }
+        if data.starts_with(UTF16_BE_BOM) {
+            return Some(TextEncoding::Utf16Be);
+        }
+
+        if !data.contains(&0) {
+            match std::str::from_utf8(data) {
+                Ok(_) => return Some(TextEncoding::Utf8),
+                // `error_len` is `None` when the input merely ends inside a character
+                Err(e) if truncated && e.error_len().is_none() => return Some(TextEncoding::Utf8),
+                Err(_) => {}
+            }
+            return data
+                .iter()
+                .all(|&b| !is_control(b))
+                .then_some(TextEncoding::Latin1);
+        }
+
+        // Mostly-ASCII UTF-16 has a zero in every other byte
+        let (even_zeros, odd_zeros) = data.chunks_exact(2).fold((0, 0), |(even, odd), pair| {
+            (
+                even + usize::from(pair[0] == 0),
+                odd + usize::from(pair[1] == 0),
+            )
+        });
+        let units = data.len() / 2;
+        let encoding = if even_zeros == 0 && odd_zeros * 2 >= units {
+            TextEncoding::Utf16Le
+        } else if odd_zeros == 0 && even_zeros * 2 >= units {
+            TextEncoding::Utf16Be
+        } else {
+            return None;
+        };
+        let text = encoding.decode(data);
+        text.chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0C' | '\u{1B}'))
+            .then_some(encoding)
+    }
+
+    /// Decode `data` in this encoding, dropping a byte order mark
+    /// Invalid sequences become U+FFFD
+    pub fn decode(self, data: &[u8]) -> String {
+        match self {
+            TextEncoding::Utf8 => {
+                String::from_utf8_lossy(data.strip_prefix(UTF8_BOM).unwrap_or(data)).into_owned()
+            }
+            TextEncoding::Utf16Le => decode_utf16(
+                data.strip_prefix(UTF16_LE_BOM).unwrap_or(data),
+                u16::from_le_bytes,
+            ),
+            TextEncoding::Utf16Be => decode_utf16(
+                data.strip_prefix(UTF16_BE_BOM).unwrap_or(data),
+                u16::from_be_bytes,
+            ),
+            TextEncoding::Latin1 => data.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+}
+
+/// Internal helper: decode UTF-16 code units; an odd trailing byte is dropped
+fn decode_utf16(data: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = data.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Internal helper: C0 control bytes that do not occur in text (tab, newlines, form feed
+/// and escape do)
+fn is_control(b: u8) -> bool {
+    b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)
+}
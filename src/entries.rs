@@ -0,0 +1,205 @@
+//! Streaming access to the tar entries of an archive
+//!
+//! [`entries`] yields the entries of a payload one by one, each readable for its content,
+//! for consumers doing their own extraction, indexing or processing. The payload is
+//! decoded on a thread of its own, which hands the content over in pieces as it is read,
+//! so nothing is buffered beyond a few pieces and the iterator owns everything it needs.
+//!
+//! Entries come in archive order and, as with any tar stream, their content can only be
+//! read until the next entry is taken: what is left unread is skipped, and reading
+//! further fails. Paths are normalized like [`list_entries`](crate::list_entries) does,
+//! entries whose path normalizes to nothing are left out, and the entries of chunked
+//! archives are given as stored (chunks and empty files).
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::builder::{open_payload_with, ReadOptions};
+use crate::errors::{ProjzstError, Result};
+use crate::listing::ArchiveEntry;
+use crate::metadata::IgnoreUnknown;
+use crate::volume::open_input;
+
+/// Pieces of content the reading thread may get ahead of the reader by
+pub(crate) const PIECES_AHEAD: usize = 4;
+
+/// A piece of the content of an entry, or the error reading it
+pub(crate) type Piece = io::Result<Vec<u8>>;
+
+/// The pieces of an entry still to be read, dropped once the next entry is taken
+type PieceSlot = Arc<Mutex<Option<Receiver<Piece>>>>;
+
+/// One entry of an archive, see [`entries`]
+/// Reading it gives the content of the entry (nothing for directories and links)
+#[derive(Debug)]
+pub struct Entry {
+    header: ArchiveEntry,
+    pax_records: BTreeMap<String, Vec<u8>>,
+    pieces: PieceSlot,
+    piece: Vec<u8>,
+    position: usize,
+}
+
+impl Entry {
+    /// Path, type, size, mode, modification time and link target of the entry
+    pub fn header(&self) -> &ArchiveEntry {
+        &self.header
+    }
+
+    /// Entry path relative to the archive root, without a trailing `/`
+    pub fn path(&self) -> &str {
+        &self.header.path
+    }
+
+    /// PAX records of the entry (`mtime` to the nanosecond, `PROJZST.chunks`, ...), by key
+    pub fn pax_records(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.pax_records
+    }
+}
+
+impl Read for Entry {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let slot = self.pieces.lock().expect("entry pieces lock poisoned");
+        let Some(pieces) = slot.as_ref() else {
+            return Err(io::Error::other(format!(
+                "{}: read after the next entry was taken",
+                self.header.path
+            )));
+        };
+        while self.position == self.piece.len() {
+            match pieces.recv() {
+                Ok(piece) => {
+                    self.piece = piece?;
+                    self.position = 0;
+                }
+                // The reading thread is done with the entry
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.piece.len() - self.position);
+        buf[..len].copy_from_slice(&self.piece[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Iterator over the entries of an archive, see [`entries`]
+#[derive(Debug)]
+pub struct Entries {
+    headers: Receiver<Result<Entry>>,
+    current: Option<PieceSlot>,
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Lets the reading thread skip whatever is left of the previous entry
+        if let Some(current) = self.current.take() {
+            current.lock().expect("entry pieces lock poisoned").take();
+        }
+        let entry = self.headers.recv().ok()?;
+        if let Ok(entry) = &entry {
+            self.current = Some(Arc::clone(&entry.pieces));
+        }
+        Some(entry)
+    }
+}
+
+/// Iterate over the entries of a .pjz file, each readable for its content
+/// Errors, opening the file included, come as items; the iteration ends after one
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn entries<P: AsRef<Path>>(input_file: P) -> Entries {
+    ReadOptions::new(IgnoreUnknown::On).entries(input_file)
+}
+
+impl ReadOptions {
+    /// Iterate over the entries of a .pjz file, see [`entries`]
+    /// Uses the metadata magic and dictionaries of these options; the other options
+    /// (path mapping, filters, policies) apply to unpacking only
+    pub fn entries<P: AsRef<Path>>(&self, input_file: P) -> Entries {
+        let mut options = ReadOptions::new(self.ignore_unknown);
+        options.allow_missing_metadata = self.allow_missing_metadata;
+        options.metadata_magic = self.metadata_magic;
+        options.dictionaries = self.dictionaries.clone();
+        let input_file = input_file.as_ref().to_path_buf();
+        let (headers, receiver) = mpsc::sync_channel(1);
+        thread::spawn(move || {
+            if let Err(e) = read_entries(&options, &input_file, &headers) {
+                let _ = headers.send(Err(e));
+            }
+        });
+        Entries {
+            headers: receiver,
+            current: None,
+        }
+    }
+}
+
+/// Internal helper: decode the payload of `input_file` and send its entries, then their
+/// content piece by piece, until the iterator or an entry stops listening
+fn read_entries(
+    options: &ReadOptions,
+    input_file: &Path,
+    headers: &SyncSender<Result<Entry>>,
+) -> Result<()> {
+    let mut reader = open_input(input_file)?;
+    let (_, payload_head) = options.read_header(&mut reader)?;
+    let mut tar_archive = open_payload_with(payload_head, reader, &options.dictionaries)?;
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let Some(header) = ArchiveEntry::of(&entry)? else {
+            continue;
+        };
+        let mut pax_records = BTreeMap::new();
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension?;
+                let key = extension
+                    .key()
+                    .map_err(|e| ProjzstError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+                pax_records.insert(key.to_string(), extension.value_bytes().to_vec());
+            }
+        }
+        let (sender, pieces) = mpsc::sync_channel(PIECES_AHEAD);
+        let item = Entry {
+            header,
+            pax_records,
+            pieces: Arc::new(Mutex::new(Some(pieces))),
+            piece: Vec::new(),
+            position: 0,
+        };
+        if headers.send(Ok(item)).is_err() {
+            return Ok(());
+        }
+        send_content(&mut entry, &sender);
+    }
+    Ok(())
+}
+
+/// Internal helper: send the content of an entry in pieces, until it ends, fails or the
+/// entry is no longer read
+pub(crate) fn send_content<R: Read + ?Sized>(entry: &mut R, sender: &SyncSender<Piece>) {
+    loop {
+        let mut piece = vec![0; crate::DEFAULT_BUFFER_SIZE];
+        let piece = match entry.read(&mut piece) {
+            Ok(0) => return,
+            Ok(len) => {
+                piece.truncate(len);
+                Ok(piece)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+        let failed = piece.is_err();
+        if sender.send(piece).is_err() || failed {
+            return;
+        }
+    }
+}
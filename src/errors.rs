@@ -1,11 +1,19 @@
+use std::error::Error as _;
+use std::fmt::Display;
+use std::path::Path;
+
 use thiserror::Error;
 
+use crate::metadata::validate::FieldError;
+use crate::schema::SchemaViolation;
+
 /// Custom error types for projzst operations
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ProjzstError {
     /// IO operation failed (file read/write, directory creation, etc.)
     #[error("IO operation failed: {0}")]
-    Io(#[from] std::io::Error),
+    Io(#[source] std::io::Error),
 
     /// JSON serialization/deserialization failed
     #[error("JSON parsing failed: {0}")]
@@ -19,14 +27,289 @@ pub enum ProjzstError {
     #[error("MessagePack decoding failed: {0}")]
     MsgPackDecode(#[from] rmp_serde::decode::Error),
 
-    /// Metadata size is invalid (zero or exceeds MAX_METADATA_SIZE)
+    /// CBOR encoding failed during metadata serialization
+    #[error("CBOR encoding failed: {0}")]
+    CborEncode(#[from] ciborium::ser::Error<std::io::Error>),
+
+    /// CBOR decoding failed during metadata deserialization
+    #[error("CBOR decoding failed: {0}")]
+    CborDecode(#[from] ciborium::de::Error<std::io::Error>),
+
+    /// Zip archive could not be read or written during a conversion
+    #[cfg(feature = "zip")]
+    #[error("Zip archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    /// Metadata size is invalid (exceeds MAX_METADATA_SIZE, or
+    /// MAX_DECOMPRESSED_METADATA_SIZE once decompressed)
     #[error("Invalid metadata length: got {0} bytes")]
     InvalidMetadataLength(usize),
 
+    /// Frame payload does not fit the 32-bit size field of a skippable frame
+    #[error("Frame payload of {0} bytes exceeds the {max} byte frame limit", max = u32::MAX)]
+    FrameTooLarge(usize),
+
+    /// Frame magic is not a skippable frame magic, or not a vendor one where only vendor
+    /// frames may be written
+    #[error("Invalid frame magic {0:#010X}")]
+    InvalidFrameMagic(u32),
+
+    /// Archive was written in a format version this build cannot read
+    #[error(
+        "Unsupported format version {found}: this build reads versions {}..={}",
+        supported.start(),
+        supported.end()
+    )]
+    UnsupportedVersion {
+        found: u32,
+        supported: std::ops::RangeInclusive<u32>,
+    },
+
     /// Extra metadata file specified but not found
     #[error("Extra metadata file not found: {0}")]
     ExtraFileNotFound(String),
 
+    /// Environment variable named as the extra metadata source is not set
+    #[error("Extra metadata environment variable not set: {0}")]
+    ExtraEnvNotSet(String),
+
+    /// Extra metadata file is not valid JSON
+    #[error("Invalid extra metadata file {path} (line {line}, column {column}): {message}")]
+    InvalidExtraFile {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// Extra metadata file holds something other than a JSON object
+    #[error("Extra metadata file {path} must contain a JSON object, found {found}")]
+    ExtraNotObject { path: String, found: String },
+
+    /// Extra metadata pair is not of the form `key=value`
+    #[error("Invalid extra metadata pair (expected key=value): {0}")]
+    InvalidExtraPair(String),
+
+    /// Base archive given for an incremental unpack is not the one it was packed against
+    #[error("Base archive does not match the one this archive was packed against: {0}")]
+    BaseMismatch(String),
+
+    /// Chunk store given for an unpack is not the one the archive was packed against
+    #[error("Chunk store does not match the one this archive was packed against: {0}")]
+    ChunkStoreMismatch(String),
+
+    /// Several archives being merged contain a file at the same path
+    #[error("Entry exists in more than one merged archive: {0}")]
+    MergeConflict(String),
+
+    /// Archive given to merge is incremental and depends on a base archive
+    #[error("Cannot merge incremental archive: {0}")]
+    IncrementalMerge(String),
+
+    /// Invalid merge conflict policy
+    #[error(
+        "Invalid conflict policy {0:?}: must be 'error', 'prefer-last' or 'prefix-per-source'"
+    )]
+    InvalidConflictPolicy(String),
+
+    /// Invalid entry order
+    #[error("Invalid entry order {0:?}: must be 'path', 'extension' or 'size'")]
+    InvalidEntryOrder(String),
+
+    /// Invalid oversize policy
+    #[error("Invalid oversize policy {0:?}: must be 'skip' or 'error'")]
+    InvalidOversizePolicy(String),
+
+    /// A source file exceeds the size limit set for packing
+    #[error("File {path} is {size} bytes, over the {limit}-byte limit")]
+    FileTooLarge { path: String, size: u64, limit: u64 },
+
+    /// Invalid secret scan policy
+    #[error("Invalid secret scan policy {0:?}: must be 'off', 'warn' or 'error'")]
+    InvalidSecretPolicy(String),
+
+    /// Files about to be packed look like they contain credentials
+    #[error("Possible secrets in the source: {0}")]
+    SecretsFound(String),
+
+    /// Invalid special entry policy
+    #[error("Invalid special entry policy {0:?}: must be 'skip', 'include' or 'error'")]
+    InvalidSpecialPolicy(String),
+
+    /// Invalid Windows name policy
+    #[error("Invalid Windows name policy {0:?}: must be 'keep', 'sanitize' or 'reject'")]
+    InvalidWindowsNamePolicy(String),
+
+    /// Invalid modification time policy
+    #[error("Invalid mtime policy {0:?}: must be 'restore', 'clamp' or 'skip'")]
+    InvalidMtimePolicy(String),
+
+    /// An entry name cannot be created on Windows (reserved device name, trailing dot or
+    /// space, or invalid character)
+    #[error("Entry path {0} is not a valid Windows path")]
+    UnportableEntryPath(String),
+
+    /// Two entries would be extracted to the same file on case-insensitive or
+    /// normalizing file systems, see [`crate::path_collisions`]
+    #[error("Entries {first} and {second} collide on {kind}-insensitive file systems")]
+    PathCollision {
+        first: String,
+        second: String,
+        kind: String,
+    },
+
+    /// Invalid tar header format
+    #[error("Invalid tar format {0:?}: must be 'gnu', 'pax' or 'ustar'")]
+    InvalidTarFormat(String),
+
+    /// An entry cannot be written in the tar header format chosen
+    #[error("Cannot store {path} as {format}: {reason}")]
+    TarFormatLimit {
+        path: String,
+        format: String,
+        reason: String,
+    },
+
+    /// A device node, FIFO or socket met under [`crate::SpecialPolicy::Error`]
+    #[error("{path} is a {kind}")]
+    SpecialEntry { path: String, kind: String },
+
+    /// Archive file name differs from the one its metadata and naming template give
+    #[error("File name {actual:?} does not match {expected:?} expected from its metadata")]
+    NamingMismatch { actual: String, expected: String },
+
+    /// Naming template uses a metadata field that is not set
+    #[error("Metadata field {field} used by naming template {template:?} is not set")]
+    NamingFieldMissing { field: String, template: String },
+
+    /// Registry operation on an archive whose metadata lacks its name or version
+    #[error("Metadata field {0} is not set: registries address archives by name and version")]
+    RegistryFieldMissing(String),
+
+    /// Package reference not in `name@version` form
+    #[error("Invalid package reference {0:?}: expected name@version")]
+    InvalidPackageRef(String),
+
+    /// Invalid bill of materials format
+    #[error("Invalid SBOM format {0:?}: must be 'cyclonedx' or 'spdx'")]
+    InvalidSbomFormat(String),
+
+    /// Metadata search query cannot be parsed
+    #[error("Invalid metadata query {query:?}: {reason}")]
+    InvalidQuery { query: String, reason: String },
+
+    /// Naming template has an unmatched brace
+    #[error("Invalid naming template {0:?}: unmatched brace")]
+    InvalidNamingTemplate(String),
+
+    /// Conversion between the two given paths is not supported
+    #[error("Cannot convert {0}: expected a .pjz file on one side and .tar, .tar.zst, .tar.gz or .zip on the other")]
+    UnsupportedConversion(String),
+
+    /// Lint configuration names a rule that does not exist
+    #[error("Unknown lint rule: {0}")]
+    UnknownLintRule(String),
+
+    /// External lint rule plugin could not be run or gave an invalid answer
+    #[error("Lint plugin {plugin} failed: {message}")]
+    LintPluginFailed { plugin: String, message: String },
+
+    /// Lint found problems at error severity
+    #[error("Lint failed with {0} error(s)")]
+    LintFailed(usize),
+
+    /// External subcommand executable not found on PATH
+    #[error("No such subcommand: {0} is not on PATH")]
+    UnknownSubcommand(String),
+
+    /// Icon is too large or not a supported image format
+    #[error("Invalid icon: {0}")]
+    InvalidIcon(String),
+
+    /// Attachment name is not a plain file name, or its content is too large
+    #[error("Invalid attachment: {0}")]
+    InvalidAttachment(String),
+
+    /// Metadata placement name not recognized
+    #[error("Invalid metadata placement {0:?}: must be 'header', 'both' or 'trailer'")]
+    InvalidMetadataPlacement(String),
+
+    /// A pack option needs the metadata at the start of the file
+    #[error("Metadata written only after the payload cannot be combined with {0}")]
+    PlacementConflict(String),
+
+    /// Archive holds no regular file at the requested path
+    #[error("No such file in archive: {0}")]
+    EntryNotFound(String),
+
+    /// Archive metadata has no bundle manifest (`extra.bundle`)
+    #[error("Archive is not a bundle: its metadata has no extra.bundle manifest")]
+    NotABundle,
+
+    /// Bundle has no member with the requested path or name
+    #[error("No such bundle member: {0}")]
+    BundleMemberNotFound(String),
+
+    /// Two bundle members would be stored under the same file name
+    #[error("Bundle members share the file name {0:?}")]
+    DuplicateBundleMember(String),
+
+    /// Files read back after unpacking differ from the archive content
+    #[error("Extracted files do not match the archive: {0}")]
+    ExtractionMismatch(String),
+
+    /// Entries were skipped by a best-effort unpack
+    #[error("Unpack incomplete: {0} failure(s), the rest was extracted")]
+    IncompleteExtraction(usize),
+
+    /// A source file could not be read, or its entry written, while packing
+    #[error("Failed to pack {path}: {}", chain(.source))]
+    PackEntry {
+        path: String,
+        source: std::io::Error,
+    },
+
+    /// An entry could not be written to the output directory while unpacking
+    #[error("Failed to extract {path}: {}", chain(.source))]
+    ExtractEntry {
+        path: String,
+        source: std::io::Error,
+    },
+
+    /// Archive has no metadata revision with the requested number
+    #[error("No such metadata revision: {0}")]
+    RevisionNotFound(usize),
+
+    /// An extracted entry would be written outside the output directory
+    #[error("Entry path leaves the output directory: {0}")]
+    UnsafeEntryPath(String),
+
+    /// A file packed from memory does not have a relative path
+    #[error("Not a relative file path: {0}")]
+    InvalidMemoryPath(String),
+
+    /// A path transform expression cannot be parsed
+    #[error("Invalid path transform {0:?}: expected s/PATTERN/REPLACEMENT/[g]")]
+    InvalidTransform(String),
+
+    /// Output file exists and overwriting it was not allowed
+    #[error("Output file already exists: {0}")]
+    OutputExists(String),
+
+    /// The file system of the output directory has less space left than the unpack
+    /// needs, see [`crate::ReadOptions::check_free_space`]
+    #[error("Not enough space in {dir}: unpacking needs {needed} bytes, {available} available")]
+    InsufficientSpace {
+        dir: String,
+        needed: u64,
+        available: u64,
+    },
+
+    /// A file of a storage backend or bucket holds more or fewer bytes than it was listed
+    /// with, having changed between listing and packing
+    #[error("Source {path} does not hold the {expected} bytes it was listed with")]
+    SourceSizeChanged { path: String, expected: u64 },
+
     /// Source directory to pack does not exist
     #[error("Source directory does not exist: {0}")]
     SourceNotFound(String),
@@ -35,14 +318,429 @@ pub enum ProjzstError {
     #[error("Failed to read file header or invalid file format")]
     InvalidFileHeader,
 
+    /// A chunked file refers to a chunk missing from the payload, or one whose content
+    /// does not match its digest
+    #[error("Missing or damaged chunk {0} in the payload")]
+    InvalidChunk(String),
+
     /// Unknown fields detected in metadata when ignore_unknown is false
     #[error("Unknown fields detected in metadata: {0}")]
     UnknownFields(String),
 
+    /// Invalid metadata encoding name
+    #[error("Invalid metadata encoding {0:?}: must be 'msgpack', 'cbor' or 'json'")]
+    InvalidMetadataEncoding(String),
+
+    /// `extra` does not deserialize into the type asked for
+    #[error("Extra metadata does not match {type_name}: {source}")]
+    ExtraMismatch {
+        /// Name of the requested type
+        type_name: &'static str,
+        /// What did not match, such as a missing or mistyped field; `extra` is read as a
+        /// JSON value, so there is no line or column to point at
+        source: serde_json::Error,
+    },
+
+    /// Metadata fields failed validation, see [`crate::MetadataBuilder`]
+    #[error("Invalid metadata: {}", join(.0))]
+    InvalidMetadata(Vec<FieldError>),
+
+    /// Metadata read from an archive does not meet the required policy,
+    /// see [`crate::MetadataPolicy`]
+    #[error("Metadata does not meet the policy: {}", join(.0))]
+    PolicyViolations(Vec<FieldError>),
+
+    /// `extra` does not match the JSON Schema registered for its format
+    #[error("Extra metadata does not match its schema: {}", join(.0))]
+    SchemaViolations(Vec<SchemaViolation>),
+
+    /// JSON Schema could not be loaded
+    #[error("Invalid JSON Schema: {0}")]
+    InvalidSchema(String),
+
+    /// A project manifest cannot be imported
+    #[error("Invalid manifest {path}: {message}")]
+    InvalidManifest { path: String, message: String },
+
+    /// A metadata template names a variable that is not defined
+    #[error("Undefined template variable ${{{0}}}")]
+    UndefinedTemplateVariable(String),
+
+    /// A metadata template has an unterminated placeholder
+    #[error("Invalid template {0:?}: unterminated ${{")]
+    InvalidTemplate(String),
+
+    /// Invalid extra metadata format name
+    #[error("Invalid extra metadata format {0:?}: must be 'json', 'toml' or 'yaml'")]
+    InvalidExtraFormat(String),
+
+    /// Invalid metadata export format name
+    #[error("Invalid metadata format {0:?}: must be 'json', 'yaml', 'toml' or 'msgpack'")]
+    InvalidMetadataFormat(String),
+
+    /// Metadata holds a value the export format cannot represent
+    #[error("Metadata cannot be exported as {format}: {message}")]
+    UnrepresentableMetadata { format: String, message: String },
+
+    /// Invalid compatibility profile name
+    #[error("Invalid compatibility profile {0:?}: must be '1.0' or 'latest'")]
+    InvalidCompatProfile(String),
+
+    /// A version string is not a semantic version
+    #[error("Invalid version {0:?}: expected a semantic version like 1.2.3")]
+    InvalidVersion(String),
+
+    /// A version bump would not lead to a greater version
+    #[error("Version {to} does not come after {from}")]
+    VersionNotBumped { from: String, to: String },
+
+    /// A dependency version requirement cannot be parsed
+    #[error("Invalid version requirement {0:?}")]
+    InvalidVersionReq(String),
+
+    /// A dependency digest is not `sha256:<hex>`
+    #[error("Invalid digest {0:?}: expected sha256:<64 hex digits>")]
+    InvalidDigest(String),
+
+    /// Some dependencies are not satisfied by any available package
+    #[error("Unresolved dependencies: {}", .0.join(", "))]
+    UnresolvedDependencies(Vec<String>),
+
     /// Invalid ignore_unknown parameter value
     #[error("Invalid ignore_unknown parameter: must be 'on', 'off', or 'export'")]
     InvalidIgnoreUnknownParam,
+
+    /// Size argument is not a positive byte count (e.g. `100M`)
+    #[error("Invalid size {0:?}: expected a positive byte count such as 4096, 512K or 100M")]
+    InvalidSize(String),
+
+    /// Compression level outside the zstd range, or `0` where only real levels apply
+    #[error(
+        "Invalid compression level {0}: expected 1-22 or a negative fast level (levels \
+         20-22 take up to about 1 GiB of memory per compression thread and 128 MiB to unpack)"
+    )]
+    InvalidCompressionLevel(i32),
+
+    /// Compression window outside what readers accept
+    #[error("Invalid window log {0}: expected 10-27 (1 KiB to 128 MiB)")]
+    InvalidWindowLog(u32),
+
+    /// A magic cannot hold metadata, see [`crate::parse_metadata_magic`]
+    #[error(
+        "Invalid metadata frame magic {0:?}: expected 0x184D2A50, 0x184D2A52, 0x184D2A53 \
+         or 0x184D2A58-0x184D2A5E"
+    )]
+    InvalidMetadataMagic(String),
+
+    /// Payload codec name not recognized
+    #[error("Invalid payload codec {0:?}: must be 'zstd', 'xz', 'lz4' or 'brotli'")]
+    InvalidCodec(String),
+
+    /// An archive or a pack needs a payload codec this build does not support
+    #[error(
+        "Payload codec {0} is not supported by this build (see the xz, lz4 and brotli features)"
+    )]
+    CodecUnavailable(String),
+
+    /// An operation needs a Cargo feature this build was compiled without
+    #[error("{what} is not supported by this build (see the {feature} feature)")]
+    FeatureUnavailable { what: String, feature: String },
+
+    /// A pack option only works with zstd payloads
+    #[error("The {codec} payload codec cannot be combined with {option}")]
+    CodecConflict { codec: String, option: String },
+
+    /// Compression preset name not recognized
+    #[error("Invalid compression preset {0:?}: must be 'fast', 'balanced', 'max' or 'archive'")]
+    InvalidCompressionProfile(String),
+
+    /// The configuration file of the command-line tool cannot be read
+    #[error("Invalid configuration file {path}: {message}")]
+    InvalidConfig { path: String, message: String },
+
+    /// An environment variable read by the command-line tool has an invalid value
+    #[error("Invalid value {value:?} for environment variable {name}")]
+    InvalidEnvVar { name: String, value: String },
+
+    /// An argument, or a combination of options, cannot be used as given
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// A zstd frame of the payload does not match the content checksum it ends with: the
+    /// archive was damaged after it was written
+    #[error("Payload checksum mismatch, the archive is damaged: {0}")]
+    ChecksumMismatch(String),
+
+    /// An archive file does not have the digest a checksum file lists for it
+    #[error(
+        "Checksum of {path} does not match the checksum file: expected {expected}, got {actual}"
+    )]
+    ChecksumFileMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// A checksum file does not parse, or does not list the archive checked against it
+    #[error("Invalid checksum file: {0}")]
+    InvalidChecksumFile(String),
+
+    /// An external signing command could not be run, failed or printed no signature
+    #[error("Signing failed: {0}")]
+    SigningFailed(String),
+
+    /// A signing key does not parse
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    /// An archive signature is missing, made by a key not trusted, or does not match
+    #[error("Signature check failed: {0}")]
+    SignatureInvalid(String),
+
+    /// An archive timestamp is missing, does not parse or no longer covers the archive
+    #[error("Timestamp check failed: {0}")]
+    TimestampInvalid(String),
+
+    /// Checksum algorithm name not recognized
+    #[error("Invalid checksum algorithm {0:?}: must be 'sha256' or 'sha512'")]
+    InvalidChecksumAlgorithm(String),
+
+    /// A compression dictionary does not parse, or cannot be trained from the samples
+    #[error("Invalid dictionary: {0}")]
+    InvalidDictionary(String),
+
+    /// An archive was compressed with a dictionary that was not given, see
+    /// [`ReadOptions::dictionary`](crate::ReadOptions::dictionary)
+    #[error("Archive needs compression dictionary {0:08x}: pass the catalog it was packed for")]
+    DictionaryRequired(u32),
+
+    /// An archive failed [`crate::validate`]: a frame is cut short or malformed, or the
+    /// payload does not decode to the end
+    #[error("Archive is damaged: {0}")]
+    Damaged(String),
+
+    /// Remote storage request failed (object storage, HTTP)
+    #[error("Remote request failed: {0}")]
+    Remote(String),
+}
+
+impl ProjzstError {
+    /// Category of the error, stable across releases: a variant never moves to another
+    /// kind, so matching on kinds keeps working as variants are added
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ProjzstError::MsgPackDecode(_)
+            | ProjzstError::CborDecode(_)
+            | ProjzstError::InvalidMetadataLength(_)
+            | ProjzstError::InvalidFrameMagic(_)
+            | ProjzstError::UnsupportedVersion { .. }
+            | ProjzstError::InvalidFileHeader
+            | ProjzstError::InvalidChunk(_)
+            | ProjzstError::UnsafeEntryPath(_) => ErrorKind::InvalidFormat,
+            #[cfg(feature = "zip")]
+            ProjzstError::Zip(_) => ErrorKind::InvalidFormat,
+            ProjzstError::Io(_)
+            | ProjzstError::PackEntry { .. }
+            | ProjzstError::ExtractEntry { .. }
+            | ProjzstError::Remote(_)
+            | ProjzstError::ExtraFileNotFound(_)
+            | ProjzstError::SourceNotFound(_)
+            | ProjzstError::SourceSizeChanged { .. }
+            | ProjzstError::OutputExists(_)
+            | ProjzstError::InsufficientSpace { .. } => ErrorKind::Io,
+            ProjzstError::UnknownFields(_) => ErrorKind::UnknownFields,
+            ProjzstError::LintFailed(_)
+            | ProjzstError::NamingMismatch { .. }
+            | ProjzstError::NamingFieldMissing { .. }
+            | ProjzstError::RegistryFieldMissing(_)
+            | ProjzstError::ExtractionMismatch(_)
+            | ProjzstError::BaseMismatch(_)
+            | ProjzstError::ChunkStoreMismatch(_)
+            | ProjzstError::PolicyViolations(_)
+            | ProjzstError::SchemaViolations(_)
+            | ProjzstError::PathCollision { .. }
+            | ProjzstError::SecretsFound(_)
+            | ProjzstError::UnresolvedDependencies(_)
+            | ProjzstError::ChecksumMismatch(_)
+            | ProjzstError::ChecksumFileMismatch { .. }
+            | ProjzstError::SignatureInvalid(_)
+            | ProjzstError::TimestampInvalid(_)
+            | ProjzstError::Damaged(_) => ErrorKind::Verification,
+            ProjzstError::InvalidExtraPair(_)
+            | ProjzstError::InvalidConflictPolicy(_)
+            | ProjzstError::InvalidMetadataPlacement(_)
+            | ProjzstError::InvalidChecksumFile(_)
+            | ProjzstError::InvalidChecksumAlgorithm(_)
+            | ProjzstError::InvalidDictionary(_)
+            | ProjzstError::DictionaryRequired(_)
+            | ProjzstError::InvalidKey(_)
+            | ProjzstError::PlacementConflict(_)
+            | ProjzstError::InvalidEntryOrder(_)
+            | ProjzstError::InvalidOversizePolicy(_)
+            | ProjzstError::InvalidSpecialPolicy(_)
+            | ProjzstError::InvalidSecretPolicy(_)
+            | ProjzstError::InvalidWindowsNamePolicy(_)
+            | ProjzstError::InvalidMtimePolicy(_)
+            | ProjzstError::InvalidTarFormat(_)
+            | ProjzstError::InvalidNamingTemplate(_)
+            | ProjzstError::InvalidPackageRef(_)
+            | ProjzstError::InvalidQuery { .. }
+            | ProjzstError::InvalidSbomFormat(_)
+            | ProjzstError::UnsupportedConversion(_)
+            | ProjzstError::InvalidTransform(_)
+            | ProjzstError::InvalidMetadataEncoding(_)
+            | ProjzstError::InvalidExtraFormat(_)
+            | ProjzstError::InvalidMetadataFormat(_)
+            | ProjzstError::InvalidCompatProfile(_)
+            | ProjzstError::VersionNotBumped { .. }
+            | ProjzstError::DuplicateBundleMember(_)
+            | ProjzstError::InvalidMetadataMagic(_)
+            | ProjzstError::InvalidIgnoreUnknownParam
+            | ProjzstError::InvalidSize(_)
+            | ProjzstError::InvalidMemoryPath(_)
+            | ProjzstError::InvalidCodec(_)
+            | ProjzstError::CodecConflict { .. }
+            | ProjzstError::InvalidCompressionProfile(_)
+            | ProjzstError::InvalidConfig { .. }
+            | ProjzstError::InvalidEnvVar { .. }
+            | ProjzstError::InvalidArgument(_)
+            | ProjzstError::InvalidWindowLog(_)
+            | ProjzstError::InvalidCompressionLevel(_) => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Process exit code of the error category, see [`ErrorKind::code`]
+    pub fn exit_code(&self) -> u8 {
+        self.kind().code()
+    }
+}
+
+/// Category of a [`ProjzstError`], for applications branching on what went wrong
+/// without matching every variant
+///
+/// | Kind | Code | Category |
+/// |------|------|----------|
+/// | `Other` | 1 | Any other failure |
+/// | `InvalidFormat` | 2 | Invalid archive format (bad header, frame, metadata encoding, unsafe entry) |
+/// | `Io` | 3 | I/O failure (file system, missing source or output already there, remote) |
+/// | `UnknownFields` | 4 | Unknown metadata fields refused by `ignore_unknown` |
+/// | `Verification` | 5 | Verification failure (lint, naming, policy, schema, extracted files, checksums, damage) |
+/// | `InvalidInput` | 64 | Invalid option value |
+///
+/// Codes and names are stable across releases; new kinds get new codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Any other failure
+    Other,
+    /// The input is not a valid archive
+    InvalidFormat,
+    /// File system or network failure
+    Io,
+    /// Unknown metadata fields refused by [`IgnoreUnknown::Off`](crate::IgnoreUnknown::Off)
+    UnknownFields,
+    /// A check on the archive or its output failed
+    Verification,
+    /// An option or argument value is invalid
+    InvalidInput,
+}
+
+impl ErrorKind {
+    /// Numeric code of the kind, also the process exit code of the command-line tool
+    pub fn code(self) -> u8 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::InvalidFormat => 2,
+            ErrorKind::Io => 3,
+            ErrorKind::UnknownFields => 4,
+            ErrorKind::Verification => 5,
+            ErrorKind::InvalidInput => 64,
+        }
+    }
+
+    /// Lowercase name of the kind, e.g. `invalid-format`
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::InvalidFormat => "invalid-format",
+            ErrorKind::Io => "io",
+            ErrorKind::UnknownFields => "unknown-fields",
+            ErrorKind::Verification => "verification",
+            ErrorKind::InvalidInput => "invalid-input",
+        }
+    }
+}
+
+/// A zstd frame not matching its content checksum, the error the payload decoders
+/// (see [`crate::codec::ZstdDecoder`]) wrap in an I/O error
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub(crate) struct ChecksumFailure(pub(crate) &'static str);
+
+impl From<std::io::Error> for ProjzstError {
+    /// I/O errors become [`ProjzstError::Io`], except checksum failures of the zstd
+    /// decoder, found among the causes as well since the tar crate wraps them
+    fn from(error: std::io::Error) -> Self {
+        let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(&error);
+        while let Some(e) = cause {
+            let inner = e
+                .downcast_ref::<std::io::Error>()
+                .and_then(std::io::Error::get_ref);
+            if e.is::<ChecksumFailure>() || inner.is_some_and(|inner| inner.is::<ChecksumFailure>())
+            {
+                return ProjzstError::ChecksumMismatch(chain(&error));
+            }
+            cause = e.source();
+        }
+        ProjzstError::Io(error)
+    }
 }
 
 /// Result type alias for projzst operations
 pub type Result<T> = std::result::Result<T, ProjzstError>;
+
+impl ProjzstError {
+    /// Internal helper: attach the source path of the entry being packed to an I/O failure
+    pub(crate) fn packing(self, path: &Path) -> Self {
+        match self {
+            ProjzstError::Io(source) => ProjzstError::PackEntry {
+                path: path.display().to_string(),
+                source,
+            },
+            e => e,
+        }
+    }
+
+    /// Internal helper: attach the path of the entry being extracted to an I/O failure
+    pub(crate) fn extracting(self, path: impl Into<String>) -> Self {
+        match self {
+            ProjzstError::Io(source) => ProjzstError::ExtractEntry {
+                path: path.into(),
+                source,
+            },
+            e => e,
+        }
+    }
+}
+
+/// Internal helper: an error followed by its causes, which wrappers such as the tar
+/// crate's errors leave out of their own message
+fn chain(error: &std::io::Error) -> String {
+    let mut message = error.to_string();
+    let mut cause = error.source();
+    while let Some(e) = cause {
+        message.push_str(&format!(": {e}"));
+        cause = e.source();
+    }
+    message
+}
+
+/// Internal helper: list displayable items separated by semicolons
+fn join<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,192 @@
+//! Dry-run packs
+//!
+//! [`Packer::dry_run`] goes through a pack without writing a file: the sources are walked
+//! with every filter of the packer, the tar stream is built as it would be, and only the
+//! compression is left out. The compressed size is estimated from a sample of the stream:
+//! the first blocks and every [`SAMPLE_STRIDE`]th block after them are compressed on
+//! their own at the packer's level, and the ratio found is applied to the whole stream.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{Packer, SourceRoot};
+use crate::errors::Result;
+use crate::incremental::IncrementalPlan;
+use crate::payload::STORE_LEVEL;
+use crate::report::PackReport;
+use crate::walk::walk_source;
+
+/// Bytes of the tar stream compressed as one sample block, large enough for blocks to
+/// compress about as well as the whole stream
+const SAMPLE_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Blocks at the start of the stream that are all sampled, so small packs are measured
+/// rather than estimated
+const SAMPLE_HEAD_BLOCKS: u64 = 4;
+
+/// One block in this many is sampled past the first [`SAMPLE_HEAD_BLOCKS`]
+const SAMPLE_STRIDE: u64 = 8;
+
+/// What a pack would store, from [`Packer::dry_run`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct PackEstimate {
+    /// The pack as it would go, files left out included; its `compressed_size` is the
+    /// estimate for the payload
+    pub report: PackReport,
+    /// Size of the payload stored uncompressed, tar headers included
+    pub stored_size: u64,
+    /// Bytes of the tar stream compressed to make the estimate
+    pub sampled_size: u64,
+    /// Estimated size of the .pjz file, metadata and other frames included
+    pub estimated_size: u64,
+    /// Archive paths of the files that would be stored, in archive order, generated
+    /// files last
+    pub paths: Vec<String>,
+}
+
+impl Packer {
+    /// Walk a source directory as [`Packer::pack`] would and estimate the archive size,
+    /// without writing anything
+    ///
+    /// # Arguments
+    /// * `source_dir` - Directory to pack
+    pub fn dry_run<P: AsRef<Path>>(&self, source_dir: P) -> Result<PackEstimate> {
+        let roots = self.source_roots(source_dir.as_ref())?;
+        self.dry_run_roots(&roots)
+    }
+
+    /// Walk a list of files and directories as [`Packer::pack_files`] would and estimate
+    /// the archive size, without writing anything
+    ///
+    /// # Arguments
+    /// * `files` - Files and directories to pack
+    pub fn dry_run_files<I, P>(&self, files: I) -> Result<PackEstimate>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let roots = self.file_roots(files)?;
+        self.dry_run_roots(&roots)
+    }
+
+    /// Internal helper: build the tar stream of the roots into a sampler
+    fn dry_run_roots(&self, roots: &[SourceRoot]) -> Result<PackEstimate> {
+        let secrets = self.scan_secrets(roots)?;
+        let (metadata, plan) = self.prepare(roots)?;
+
+        // Stored frames hand the tar stream to the sampler nearly as it is
+        let mut sampler = Sampler::new(self.compression_level);
+        let stored = self
+            .clone()
+            .compression_level(STORE_LEVEL)
+            .adaptive_compression(false)
+            .per_entry_frames(false)
+            .content_checksum(false);
+        let mut report = stored.write_payload(roots, &mut sampler, plan.as_ref(), None)?;
+        sampler.flush_block()?;
+        let metadata_bytes = self.finish_metadata(metadata, plan.as_ref())?;
+        let mut header = Vec::new();
+        self.write_header(&mut header, &metadata_bytes)?;
+        self.write_trailer(&mut header, &metadata_bytes)?;
+        let unchanged = plan.as_ref().map(IncrementalPlan::unchanged).transpose()?;
+        report.set_secrets(secrets);
+        report.compressed_size = sampler.estimate();
+        let mut paths = Vec::new();
+        for root in roots {
+            let walk = walk_source(&root.path, root.prefix.as_deref(), &self.walk)?;
+            paths.extend(
+                walk.entries
+                    .into_iter()
+                    .filter(|(path, _, info)| {
+                        !info.is_dir() && !unchanged.as_ref().is_some_and(|u| u.contains(path))
+                    })
+                    .map(|(path, ..)| path),
+            );
+        }
+        paths.extend(self.generated.iter().map(|(path, _)| path.clone()));
+        log::info!(
+            "dry run: {} files, {} bytes sampled of {}",
+            report.files,
+            sampler.sampled,
+            sampler.total
+        );
+        Ok(PackEstimate {
+            stored_size: sampler.total,
+            sampled_size: sampler.sampled,
+            estimated_size: header.len() as u64 + report.compressed_size,
+            report,
+            paths,
+        })
+    }
+}
+
+/// Writer compressing a sample of the blocks written to it
+struct Sampler {
+    level: i32,
+    block: Vec<u8>,
+    blocks: u64,
+    total: u64,
+    sampled: u64,
+    compressed: u64,
+}
+
+impl Sampler {
+    fn new(level: i32) -> Self {
+        Self {
+            level,
+            block: Vec::with_capacity(SAMPLE_BLOCK_SIZE),
+            blocks: 0,
+            total: 0,
+            sampled: 0,
+            compressed: 0,
+        }
+    }
+
+    /// Internal helper: compress the current block if it is part of the sample
+    /// Blocks that do not shrink count at their size, as adaptive packs store them
+    fn flush_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        if self.blocks < SAMPLE_HEAD_BLOCKS || self.blocks.is_multiple_of(SAMPLE_STRIDE) {
+            let size = self.block.len() as u64;
+            let compressed = if self.level == STORE_LEVEL {
+                size
+            } else {
+                zstd::bulk::compress(&self.block, self.level)?.len() as u64
+            };
+            self.sampled += size;
+            self.compressed += compressed.min(size);
+        }
+        self.blocks += 1;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Internal helper: the compressed size of the whole stream, from the sample ratio
+    fn estimate(&self) -> u64 {
+        if self.sampled == 0 {
+            return 0;
+        }
+        (self.total as f64 * self.compressed as f64 / self.sampled as f64).round() as u64
+    }
+}
+
+impl Write for Sampler {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(SAMPLE_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..taken]);
+        self.total += taken as u64;
+        if self.block.len() == SAMPLE_BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+//! Raw access to skippable frames
+//!
+//! The metadata lives in skippable frames with magic `0x184D2A50`. The rest of the
+//! skippable range, `0x184D2A51..=0x184D2A5F`, holds auxiliary frames (indexes, signatures,
+//! build logs, provenance blobs, ...) ahead of the payload. Such frames are skipped when
+//! reading metadata and ignored by every zstd decoder.
+//!
+//! [`FrameKind`] names each magic in the range:
+//!
+//! | Magic                       | Kind         |
+//! |-----------------------------|--------------|
+//! | `0x184D2A50`                | `metadata`   |
+//! | `0x184D2A51`                | `index`      |
+//! | `0x184D2A52`                | `signature`  |
+//! | `0x184D2A53`                | `dictionary` |
+//! | `0x184D2A54`                | `comment`    |
+//! | `0x184D2A55`                | `icon`       |
+//! | `0x184D2A56`                | `padding`    |
+//! | `0x184D2A57`                | `provenance` |
+//! | `0x184D2A58..=0x184D2A5E`   | `vendor`     |
+//! | `0x184D2A5F`                | `attachment` |
+//!
+//! Readers skip frames of kinds they do not know, and [`RawFrame::write_to`] writes a frame
+//! back byte for byte, so tools rewriting an archive preserve frames added by newer
+//! versions or other applications.
+//!
+//! Where another tool already claims `0x184D2A50`, the metadata can move to a magic projzst
+//! writes nothing else with, see [`Packer::metadata_magic`](crate::Packer::metadata_magic)
+//! and [`ReadOptions::metadata_magic`](crate::ReadOptions::metadata_magic). Frames with the
+//! default magic are then foreign to projzst and skipped like any other.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::builder::{METADATA_FRAME_MAGIC, SKIPPABLE_FRAME_MAGIC_MAX, SKIPPABLE_FRAME_MAGIC_MIN};
+use crate::errors::{ProjzstError, Result};
+use crate::sfx::read_stub;
+use crate::temp::temp_file_for;
+use crate::volume::{open_input, volume_path};
+
+/// First magic left to applications
+const VENDOR_FRAME_MAGIC_MIN: u32 = 0x184D2A58;
+
+/// Magic of attachment frames, the last of the skippable range
+const ATTACHMENT_FRAME_MAGIC: u32 = 0x184D2A5F;
+
+/// Largest payload of one skippable frame (4 GiB - 1)
+/// The frame header stores the payload size as a 32-bit little-endian integer
+pub const MAX_FRAME_SIZE: usize = u32::MAX as usize;
+
+/// What a skippable frame holds, by magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FrameKind {
+    /// Archive metadata (MessagePack, possibly over several frames)
+    Metadata,
+    /// Entry index
+    Index,
+    /// Archive signature, see [`sign_archive`](crate::sign_archive)
+    Signature,
+    /// Compression dictionary
+    Dictionary,
+    /// Human-readable comment (UTF-8 text)
+    Comment,
+    /// Icon or thumbnail image
+    Icon,
+    /// Filler aligning the payload: the alignment as a little-endian `u64`, then zeros
+    Padding,
+    /// Build environment the archive was packed in (JSON), see [`Provenance`](crate::Provenance)
+    Provenance,
+    /// Application-defined data; the value is the offset into the vendor range (0-6)
+    Vendor(u8),
+    /// Named file attached to the archive, see [`Attachment`](crate::Attachment)
+    Attachment,
+    /// Magic kept for a future kind; every magic in the range has a kind since
+    /// `0x184D2A57` became [`FrameKind::Provenance`]
+    Reserved(u32),
+}
+
+impl FrameKind {
+    /// Kind of a skippable frame magic, `None` outside `0x184D2A50..=0x184D2A5F`
+    pub fn from_magic(magic: u32) -> Option<Self> {
+        if !(SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&magic) {
+            return None;
+        }
+        Some(match magic - SKIPPABLE_FRAME_MAGIC_MIN {
+            0 => FrameKind::Metadata,
+            1 => FrameKind::Index,
+            2 => FrameKind::Signature,
+            3 => FrameKind::Dictionary,
+            4 => FrameKind::Comment,
+            5 => FrameKind::Icon,
+            6 => FrameKind::Padding,
+            7 => FrameKind::Provenance,
+            _ if magic == ATTACHMENT_FRAME_MAGIC => FrameKind::Attachment,
+            _ if magic >= VENDOR_FRAME_MAGIC_MIN => {
+                FrameKind::Vendor((magic - VENDOR_FRAME_MAGIC_MIN) as u8)
+            }
+            _ => FrameKind::Reserved(magic),
+        })
+    }
+
+    /// Magic number frames of this kind are written with
+    pub fn magic(self) -> u32 {
+        match self {
+            FrameKind::Metadata => METADATA_FRAME_MAGIC,
+            FrameKind::Index => SKIPPABLE_FRAME_MAGIC_MIN + 1,
+            FrameKind::Signature => SKIPPABLE_FRAME_MAGIC_MIN + 2,
+            FrameKind::Dictionary => SKIPPABLE_FRAME_MAGIC_MIN + 3,
+            FrameKind::Comment => SKIPPABLE_FRAME_MAGIC_MIN + 4,
+            FrameKind::Icon => SKIPPABLE_FRAME_MAGIC_MIN + 5,
+            FrameKind::Padding => SKIPPABLE_FRAME_MAGIC_MIN + 6,
+            FrameKind::Provenance => SKIPPABLE_FRAME_MAGIC_MIN + 7,
+            FrameKind::Vendor(offset) => VENDOR_FRAME_MAGIC_MIN + u32::from(offset),
+            FrameKind::Attachment => ATTACHMENT_FRAME_MAGIC,
+            FrameKind::Reserved(magic) => magic,
+        }
+    }
+
+    /// Lowercase name of the kind, as listed by `projzst frames`
+    pub fn name(self) -> &'static str {
+        match self {
+            FrameKind::Metadata => "metadata",
+            FrameKind::Index => "index",
+            FrameKind::Signature => "signature",
+            FrameKind::Dictionary => "dictionary",
+            FrameKind::Comment => "comment",
+            FrameKind::Icon => "icon",
+            FrameKind::Padding => "padding",
+            FrameKind::Provenance => "provenance",
+            FrameKind::Vendor(_) => "vendor",
+            FrameKind::Attachment => "attachment",
+            FrameKind::Reserved(_) => "reserved",
+        }
+    }
+}
+
+/// One skippable frame as stored in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    /// Frame magic number, `0x184D2A50` for metadata
+    pub magic: u32,
+    /// Frame content
+    pub payload: Vec<u8>,
+}
+
+impl RawFrame {
+    /// Kind of the frame according to its magic
+    pub fn kind(&self) -> FrameKind {
+        FrameKind::from_magic(self.magic).expect("frames are read from the skippable range")
+    }
+
+    /// Whether projzst leaves the frame to other tools: signature, dictionary and vendor
+    /// frames, and metadata frames of another magic than the metadata is read from
+    /// Foreign frames are carried over when an archive is rewritten, repacked or merged
+    ///
+    /// # Arguments
+    /// * `metadata_magic` - Magic of the frames holding the archive's metadata
+    pub fn is_foreign(&self, metadata_magic: u32) -> bool {
+        self.magic != metadata_magic
+            && matches!(
+                self.kind(),
+                FrameKind::Metadata
+                    | FrameKind::Signature
+                    | FrameKind::Dictionary
+                    | FrameKind::Vendor(_)
+                    | FrameKind::Reserved(_)
+            )
+    }
+
+    /// Write the frame back exactly as it was read, whatever its kind
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        if FrameKind::from_magic(self.magic).is_none() {
+            return Err(ProjzstError::InvalidFrameMagic(self.magic));
+        }
+        write_frame_unchecked(&mut writer, self.magic, &self.payload)
+    }
+}
+
+/// Read every skippable frame before the payload of a .pjz file, in file order
+/// Metadata frames are included as they are stored (MessagePack, possibly in several chunks)
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn frames<P: AsRef<Path>>(input_file: P) -> Result<Vec<RawFrame>> {
+    let (frames, _) = read_frames(&mut open_input(input_file.as_ref())?)?;
+    Ok(frames)
+}
+
+/// Read the metadata frames at the start of a .pjz file, undecoded
+/// Their payloads joined are the metadata exactly as stored: encoded in MessagePack, CBOR
+/// or JSON, zstd-compressed when packed so, and holding fields this version may not know.
+/// Revisions appended by [`append_metadata`](crate::append_metadata) are not included;
+/// a file without metadata frames of `metadata_magic` fails as an invalid header
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `metadata_magic` - Magic of the metadata frames, [`FrameKind::Metadata`] unless packed
+///   with [`Packer::metadata_magic`](crate::Packer::metadata_magic)
+pub fn metadata_frames<P: AsRef<Path>>(
+    input_file: P,
+    metadata_magic: u32,
+) -> Result<Vec<RawFrame>> {
+    let metadata: Vec<RawFrame> = frames(input_file)?
+        .into_iter()
+        .filter(|frame| frame.magic == metadata_magic)
+        .collect();
+    if metadata.is_empty() {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    Ok(metadata)
+}
+
+/// Remove the foreign frames of a .pjz file in place, see [`RawFrame::is_foreign`]
+/// Returns the number of frames removed; the metadata and payload are left untouched, and
+/// a file without metadata frames of `metadata_magic` is refused as an invalid header
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `metadata_magic` - Magic of the metadata frames, [`FrameKind::Metadata`] unless packed
+///   with [`Packer::metadata_magic`](crate::Packer::metadata_magic)
+pub fn strip_foreign_frames<P: AsRef<Path>>(input_file: P, metadata_magic: u32) -> Result<usize> {
+    // A wrong magic would take the metadata for a foreign frame
+    if !frames(input_file.as_ref())?
+        .iter()
+        .any(|frame| frame.magic == metadata_magic)
+    {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    let mut removed = 0;
+    rewrite_frames(input_file.as_ref(), |frames| {
+        let before = frames.len();
+        frames.retain(|frame| !frame.is_foreign(metadata_magic));
+        removed = before - frames.len();
+    })?;
+    Ok(removed)
+}
+
+/// Internal helper: the foreign frames ahead of the payload of a .pjz file, deduplicated
+/// An input that cannot be read as an archive has none
+pub(crate) fn foreign_frames(input_file: &Path, metadata_magic: u32) -> Vec<RawFrame> {
+    let mut foreign: Vec<RawFrame> = Vec::new();
+    for frame in frames(input_file).unwrap_or_default() {
+        if frame.is_foreign(metadata_magic) && !foreign.contains(&frame) {
+            foreign.push(frame);
+        }
+    }
+    foreign
+}
+
+/// Read the comment attached to a .pjz file, if any
+/// Several comment frames are joined with newlines
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn comment<P: AsRef<Path>>(input_file: P) -> Result<Option<String>> {
+    let comments: Vec<String> = frames(input_file)?
+        .into_iter()
+        .filter(|frame| frame.kind() == FrameKind::Comment)
+        .map(|frame| String::from_utf8_lossy(&frame.payload).into_owned())
+        .collect();
+    Ok((!comments.is_empty()).then(|| comments.join("\n")))
+}
+
+/// Attach a human-readable comment (e.g. release notes) to a .pjz file in place
+/// The metadata and payload are left untouched; `None` removes the comment
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `comment` - UTF-8 text of the comment, or `None` to remove it
+pub fn set_comment<P: AsRef<Path>>(input_file: P, comment: Option<&str>) -> Result<()> {
+    replace_frame(
+        input_file.as_ref(),
+        FrameKind::Comment,
+        comment.map(|comment| comment.as_bytes().to_vec()),
+    )
+}
+
+/// Internal helper: replace every frame of one kind by a single frame, or remove them
+/// The new frame takes the place of the first old one, or goes after the last frame
+pub(crate) fn replace_frame(
+    input_file: &Path,
+    kind: FrameKind,
+    payload: Option<Vec<u8>>,
+) -> Result<()> {
+    rewrite_frames(input_file, |frames| {
+        let position = frames
+            .iter()
+            .position(|frame| frame.kind() == kind)
+            .unwrap_or(frames.len());
+        frames.retain(|frame| frame.kind() != kind);
+        if let Some(payload) = payload {
+            let magic = kind.magic();
+            frames.insert(position, RawFrame { magic, payload });
+        }
+    })
+}
+
+/// Internal helper: padding frame making the payload start at a multiple of `alignment`
+/// `header_len` is the size of all frames written before it
+pub(crate) fn padding_frame(header_len: u64, alignment: u64) -> RawFrame {
+    let unpadded = header_len + 16;
+    let zeros = (alignment - unpadded % alignment) % alignment;
+    let mut payload = alignment.to_le_bytes().to_vec();
+    payload.resize(8 + zeros as usize, 0);
+    RawFrame {
+        magic: FrameKind::Padding.magic(),
+        payload,
+    }
+}
+
+/// Internal helper: move the padding frame last and resize it for the frames before it,
+/// which start `offset` bytes into the file, so edits keep the payload aligned
+fn realign(frames: &mut Vec<RawFrame>, offset: u64) {
+    let alignment = frames
+        .iter()
+        .find(|frame| frame.kind() == FrameKind::Padding)
+        .and_then(|frame| frame.payload.get(..8))
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("8 bytes")));
+    let Some(alignment) = alignment.filter(|&alignment| alignment > 1) else {
+        return;
+    };
+    frames.retain(|frame| frame.kind() != FrameKind::Padding);
+    let header_len = offset
+        + frames
+            .iter()
+            .map(|frame| 8 + frame.payload.len() as u64)
+            .sum::<u64>();
+    frames.push(padding_frame(header_len, alignment));
+}
+
+/// Internal helper: replace the leading frames of an archive file, keeping the payload
+/// Split archives are edited in their first volume, which holds the frames; a padding
+/// frame is resized to keep the payload alignment
+pub(crate) fn rewrite_frames<F>(input_file: &Path, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut Vec<RawFrame>),
+{
+    let target = if input_file.exists() {
+        input_file.to_path_buf()
+    } else {
+        volume_path(input_file, 1)
+    };
+    let mut reader = File::open(&target)?;
+    let (stub, head) = read_stub(&mut reader)?;
+    let (mut frames, payload_head) = read_frames(&mut io::Cursor::new(head).chain(&mut reader))?;
+    edit(&mut frames);
+    realign(&mut frames, stub.len() as u64);
+
+    let (mut output, temp) = temp_file_for(&target)?;
+    output.set_permissions(reader.metadata()?.permissions())?;
+    output.write_all(&stub)?;
+    for frame in &frames {
+        frame.write_to(&mut output)?;
+    }
+    output.write_all(&payload_head)?;
+    io::copy(&mut reader, &mut output)?;
+    drop(output);
+    temp.persist(&target)
+}
+
+/// Internal helper: read the leading skippable frames of a stream
+/// Also returns the bytes consumed past them (the payload magic, empty at end of file)
+pub(crate) fn read_frames<R: Read>(reader: &mut R) -> Result<(Vec<RawFrame>, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut payload_head = Vec::new();
+
+    loop {
+        let mut magic_buf = [0u8; 4];
+        match reader.read_exact(&mut magic_buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let magic = u32::from_le_bytes(magic_buf);
+        if FrameKind::from_magic(magic).is_none() {
+            payload_head.extend_from_slice(&magic_buf);
+            break;
+        }
+
+        let mut size_buf = [0u8; 4];
+        reader.read_exact(&mut size_buf)?;
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(u64::from(u32::from_le_bytes(size_buf)))
+            .read_to_end(&mut payload)?;
+        if payload.len() != u32::from_le_bytes(size_buf) as usize {
+            return Err(ProjzstError::InvalidFileHeader);
+        }
+        frames.push(RawFrame { magic, payload });
+    }
+
+    if frames.is_empty() {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    Ok((frames, payload_head))
+}
+
+/// Parse the magic of the frames holding metadata, in hexadecimal with or without `0x`
+/// Accepted are the default `0x184D2A50` and the magics projzst writes nothing else with:
+/// `0x184D2A52`, `0x184D2A53` and the vendor range `0x184D2A58..=0x184D2A5E`
+///
+/// # Examples
+///
+/// ```
+/// use projzst::parse_metadata_magic;
+///
+/// assert_eq!(parse_metadata_magic("0x184D2A58").unwrap(), 0x184D2A58);
+/// assert!(parse_metadata_magic("0x184D2A51").is_err()); // the entry index
+/// ```
+pub fn parse_metadata_magic(s: &str) -> Result<u32> {
+    let digits = s.trim();
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(digits);
+    let magic = u32::from_str_radix(digits, 16)
+        .map_err(|_| ProjzstError::InvalidMetadataMagic(s.to_string()))?;
+    check_metadata_magic(magic)?;
+    Ok(magic)
+}
+
+/// Internal helper: fail unless metadata may be stored under `magic`, see
+/// [`parse_metadata_magic`]
+pub(crate) fn check_metadata_magic(magic: u32) -> Result<()> {
+    match FrameKind::from_magic(magic) {
+        Some(
+            FrameKind::Metadata
+            | FrameKind::Signature
+            | FrameKind::Dictionary
+            | FrameKind::Vendor(_),
+        ) => Ok(()),
+        _ => Err(ProjzstError::InvalidMetadataMagic(format!("{magic:#X}"))),
+    }
+}
+
+/// Write a vendor skippable frame
+/// Frames written before an archive (e.g. ahead of [`Packer::pack_to_writer`](crate::Packer::pack_to_writer))
+/// travel with it without affecting its metadata or content. Only the vendor range is
+/// open to applications: the other kinds mean something to projzst (an index, a signature,
+/// a dictionary, ...) and are written by their own functions, such as [`set_comment`]
+///
+/// ```no_run
+/// use std::fs::File;
+/// use projzst::{write_frame, FrameKind, Metadata, Packer};
+///
+/// let mut output = File::create("output.pjz")?;
+/// write_frame(&mut output, FrameKind::Vendor(0).magic(), b"build log")?;
+/// Packer::new(Metadata::default()).pack_to_writer("./source", &mut output)?;
+/// # Ok::<(), projzst::ProjzstError>(())
+/// ```
+///
+/// # Arguments
+/// * `writer` - Destination of the frame
+/// * `magic` - Vendor frame magic in `0x184D2A58..=0x184D2A5E`, see [`FrameKind::Vendor`]
+/// * `payload` - Frame content, at most [`MAX_FRAME_SIZE`] bytes
+pub fn write_frame<W: Write>(mut writer: W, magic: u32, payload: &[u8]) -> Result<()> {
+    match FrameKind::from_magic(magic) {
+        Some(FrameKind::Vendor(_)) => write_frame_unchecked(&mut writer, magic, payload),
+        _ => Err(ProjzstError::InvalidFrameMagic(magic)),
+    }
+}
+
+/// Internal helper: write a skippable frame with a magic already checked
+/// Fails with [`ProjzstError::FrameTooLarge`] rather than writing a wrapped size field
+pub(crate) fn write_frame_unchecked<W: Write>(
+    writer: &mut W,
+    magic: u32,
+    payload: &[u8],
+) -> Result<()> {
+    let size =
+        u32::try_from(payload.len()).map_err(|_| ProjzstError::FrameTooLarge(payload.len()))?;
+
+    writer.write_all(&magic.to_le_bytes())?;
+    writer.write_all(&size.to_le_bytes())?;
+    writer.write_all(payload)?;
+    Ok(())
+}
@@ -0,0 +1,257 @@
+//! Text search inside archives
+//!
+//! [`grep`] streams the payload through the decompressor once and searches every regular
+//! file in it, so nothing has to be extracted first. The start of each file decides how it
+//! is read, as in [`preview_entry`](crate::preview_entry): text in any encoding
+//! [`TextEncoding::detect`] knows is decoded and searched line by line, while binary files
+//! are only reported as matching or not. The pattern is literal text, like the patterns
+//! of [`PathTransform`](crate::PathTransform).
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::open_archive;
+use crate::encoding::TextEncoding;
+use crate::errors::Result;
+use crate::metadata::IgnoreUnknown;
+use crate::storage::normalize_path;
+use crate::volume::open_input;
+
+/// Bytes read from the start of a file to tell text from binary content
+const SNIFF_SIZE: usize = 8192;
+
+/// What [`grep`] looks for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GrepQuery {
+    /// Literal text searched for
+    pub pattern: String,
+    /// Glob the entry paths must match; `*` matches any run of characters, `/`
+    /// included, and `?` any one character
+    pub paths: Option<String>,
+    /// Match regardless of case
+    pub ignore_case: bool,
+}
+
+impl GrepQuery {
+    /// Create a query for literal text, searched in every file
+    ///
+    /// # Arguments
+    /// * `pattern` - Text searched for
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Only search entries whose path matches a glob, e.g. `src/*.rs`
+    ///
+    /// # Arguments
+    /// * `glob` - Pattern matched against the whole entry path
+    pub fn paths(mut self, glob: impl Into<String>) -> Self {
+        self.paths = Some(glob.into());
+        self
+    }
+
+    /// Match regardless of case
+    ///
+    /// # Arguments
+    /// * `ignore_case` - Whether case is ignored
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Internal helper: whether `path` is among the entries searched
+    fn includes(&self, path: &str) -> bool {
+        match &self.paths {
+            Some(glob) => glob_matches(
+                &glob.chars().collect::<Vec<_>>(),
+                &path.chars().collect::<Vec<_>>(),
+            ),
+            None => true,
+        }
+    }
+
+    /// Internal helper: whether a line of text contains the pattern
+    fn matches_text(&self, line: &str) -> bool {
+        if self.ignore_case {
+            line.to_lowercase().contains(&self.pattern.to_lowercase())
+        } else {
+            line.contains(&self.pattern)
+        }
+    }
+}
+
+/// A line of text containing the pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct GrepMatch {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Line number, starting at 1
+    pub line: u64,
+    /// The line, decoded and without its line ending
+    pub text: String,
+}
+
+/// Everything [`grep`] found, in archive order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct GrepReport {
+    /// Matching lines of text files
+    pub matches: Vec<GrepMatch>,
+    /// Binary files containing the pattern
+    pub binary_matches: Vec<String>,
+    /// Number of files searched
+    pub searched: usize,
+}
+
+/// Search the regular files of a .pjz file for literal text
+/// Hard links, symlinks and other special entries are not searched
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `query` - Pattern, paths to search and case sensitivity
+pub fn grep<P: AsRef<Path>>(input_file: P, query: &GrepQuery) -> Result<GrepReport> {
+    let (_, mut tar_archive) = open_archive(open_input(input_file.as_ref())?, IgnoreUnknown::On)?;
+    let mut report = GrepReport::default();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        if !query.includes(&path) {
+            continue;
+        }
+        report.searched += 1;
+
+        let mut head = Vec::new();
+        (&mut entry)
+            .take(SNIFF_SIZE as u64)
+            .read_to_end(&mut head)?;
+        let truncated = (head.len() as u64) < entry.header().size()?;
+        let content = io::Cursor::new(head.as_slice()).chain(&mut entry);
+        match TextEncoding::detect(&head, truncated) {
+            Some(encoding) => grep_text(content, encoding, &path, query, &mut report.matches)?,
+            None => {
+                if contains_bytes(content, query)? {
+                    report.binary_matches.push(path);
+                }
+            }
+        }
+    }
+    log::info!(
+        "{} matching lines and {} matching binary files in {} files",
+        report.matches.len(),
+        report.binary_matches.len(),
+        report.searched
+    );
+    Ok(report)
+}
+
+/// Internal helper: search text content line by line
+fn grep_text<R: Read>(
+    content: R,
+    encoding: TextEncoding,
+    path: &str,
+    query: &GrepQuery,
+    matches: &mut Vec<GrepMatch>,
+) -> Result<()> {
+    let mut reader = BufReader::new(content);
+    let mut line = Vec::new();
+    let mut number = 0;
+    while read_line(&mut reader, encoding, &mut line)? {
+        number += 1;
+        let text = encoding.decode(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if query.matches_text(text) {
+            matches.push(GrepMatch {
+                path: path.to_string(),
+                line: number,
+                text: text.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: read the bytes of one line, line ending included, into `line`
+/// Returns `false` at the end of the content
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    encoding: TextEncoding,
+    line: &mut Vec<u8>,
+) -> Result<bool> {
+    line.clear();
+    loop {
+        if reader.read_until(b'\n', line)? == 0 {
+            return Ok(!line.is_empty());
+        }
+        // Lines start on a unit boundary, so the parity of the offset tells which byte of
+        // a UTF-16 unit the 0A is
+        let end = line.len() - 1;
+        match encoding {
+            TextEncoding::Utf8 | TextEncoding::Latin1 => return Ok(true),
+            TextEncoding::Utf16Le if end.is_multiple_of(2) => {
+                let mut high = [0u8; 1];
+                let read = reader.read(&mut high)?;
+                line.extend_from_slice(&high[..read]);
+                if read == 0 || high[0] == 0 {
+                    return Ok(true);
+                }
+            }
+            TextEncoding::Utf16Be if !end.is_multiple_of(2) && line[end - 1] == 0 => {
+                return Ok(true)
+            }
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => {}
+        }
+    }
+}
+
+/// Internal helper: whether binary content contains the pattern, read in blocks
+/// Case is ignored for ASCII letters only
+fn contains_bytes<R: Read>(mut content: R, query: &GrepQuery) -> Result<bool> {
+    let fold = |bytes: &[u8]| -> Vec<u8> {
+        if query.ignore_case {
+            bytes.to_ascii_lowercase()
+        } else {
+            bytes.to_vec()
+        }
+    };
+    let pattern = fold(query.pattern.as_bytes());
+    if pattern.is_empty() {
+        return Ok(true);
+    }
+    // The tail of the previous block, so a match across blocks is found
+    let mut window = Vec::new();
+    let mut block = vec![0u8; 64 * 1024];
+    loop {
+        let read = content.read(&mut block)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        window.extend_from_slice(&fold(&block[..read]));
+        if window.windows(pattern.len()).any(|bytes| bytes == pattern) {
+            return Ok(true);
+        }
+        let keep = window.len().min(pattern.len() - 1);
+        window.drain(..window.len() - keep);
+    }
+}
+
+/// Internal helper: match a glob where `*` is any run of characters and `?` any one
+pub(crate) fn glob_matches(glob: &[char], text: &[char]) -> bool {
+    match glob.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && glob_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
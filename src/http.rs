@@ -0,0 +1,353 @@
+//! Remote metadata reads over HTTP (feature `http`)
+//!
+//! Only the leading skippable frames are needed to decode the metadata, so the
+//! archive is fetched lazily with `Range` requests instead of being downloaded.
+//! [`sync_url`] streams whole archives the same way, in growing ranges.
+//! [`write_entry_url`] reads the entry index at the end of archives packed with per-entry
+//! frames and downloads only the frame of the file it wants.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::builder::{open_archive, read_metadata_from_reader};
+use crate::codec::zstd_decoder;
+use crate::errors::{ProjzstError, Result};
+use crate::index::EntryIndex;
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::preview::{read_entry, wanted_path, EntryPreview};
+use crate::storage::{Storage, StorageEntry};
+use crate::sync::{sync_from_reader, SyncReport};
+
+/// Bytes fetched by the first range request (most metadata fits in it)
+const RANGE_CHUNK_SIZE: u64 = 64 * 1024;
+/// Upper bound for range requests; chunks double up to this while reading sequentially
+const MAX_RANGE_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Read only metadata from a .pjz file served over HTTP(S)
+/// Issues range requests for the skippable frames instead of downloading the payload;
+/// servers that ignore `Range` are read as a stream and dropped once metadata is decoded
+///
+/// # Arguments
+/// * `url` - `http://` or `https://` URL of the .pjz file
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn read_metadata_url(url: &str, ignore_unknown: IgnoreUnknown) -> Result<Metadata> {
+    let mut reader = RangeReader::new(url);
+    let (metadata, _) = read_metadata_from_reader(&mut reader, ignore_unknown)?;
+    Ok(metadata)
+}
+
+/// Update a directory to match a .pjz file served over HTTP(S), see [`crate::sync`]
+/// The archive is streamed with range requests and never stored whole
+///
+/// # Arguments
+/// * `url` - `http://` or `https://` URL of the .pjz file
+/// * `output_dir` - Directory to update, created if missing
+/// * `delete` - Also remove files and directories the archive does not have
+pub fn sync_url<P: AsRef<Path>>(url: &str, output_dir: P, delete: bool) -> Result<SyncReport> {
+    sync_from_reader(RangeReader::new(url), output_dir, delete)
+}
+
+/// Copy the whole content of one file in a .pjz file served over HTTP(S) to a writer
+/// For archives packed with per-entry frames, only the entry index and the frame of the
+/// file are downloaded; other archives are streamed up to the file
+/// Returns the number of bytes written
+///
+/// # Arguments
+/// * `url` - `http://` or `https://` URL of the .pjz file
+/// * `entry_path` - Path of the file inside the archive
+/// * `writer` - Destination of the content (e.g. stdout)
+pub fn write_entry_url<W: Write>(url: &str, entry_path: &str, mut writer: W) -> Result<u64> {
+    let (_, size) = with_remote_entry(url, entry_path, |entry, _| {
+        io::copy(entry, &mut writer)?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(size)
+}
+
+/// Read the beginning of one file in a .pjz file served over HTTP(S), see
+/// [`crate::preview_entry`] and [`write_entry_url`]
+///
+/// # Arguments
+/// * `url` - `http://` or `https://` URL of the .pjz file
+/// * `entry_path` - Path of the file inside the archive
+/// * `max_bytes` - Largest number of bytes to read from the entry
+pub fn preview_entry_url(url: &str, entry_path: &str, max_bytes: usize) -> Result<EntryPreview> {
+    let mut data = Vec::new();
+    let (path, size) = with_remote_entry(url, entry_path, |entry, _| {
+        entry.take(max_bytes as u64).read_to_end(&mut data)?;
+        Ok(())
+    })?;
+    Ok(EntryPreview::new(path, size, data))
+}
+
+/// Internal helper: find a regular file entry of a remote archive and hand its content
+/// and size to `read`, fetching only its frame when the archive is indexed
+/// Returns the normalized path and the size of the entry
+fn with_remote_entry<F>(url: &str, entry_path: &str, read: F) -> Result<(String, u64)>
+where
+    F: FnOnce(&mut dyn Read, u64) -> Result<()>,
+{
+    let wanted = wanted_path(entry_path)?;
+    let frame = match RemoteFile::open(url)? {
+        Some(mut remote) => match EntryIndex::read_from(&mut remote)? {
+            Some(index) => match index.find(&wanted) {
+                Some(entry) => Some(remote.range(entry.offset, entry.length)?),
+                None => None,
+            },
+            None => None,
+        },
+        None => None,
+    };
+
+    let size = match frame {
+        Some(frame) => {
+            let mut tar_archive = tar::Archive::new(zstd_decoder(frame)?);
+            read_entry(tar_archive.entries()?.take(1), &wanted, 0, read)?
+        }
+        None => {
+            // Read in a single pass so the payload is fetched once: of a path stored twice,
+            // this is the first entry
+            let (_, mut tar_archive) = open_archive(RangeReader::new(url), IgnoreUnknown::On)?;
+            read_entry(tar_archive.entries()?, &wanted, 0, read)?
+        }
+    };
+    size.map(|size| (wanted, size))
+        .ok_or_else(|| ProjzstError::EntryNotFound(entry_path.to_string()))
+}
+
+/// Random-access reader backed by HTTP range requests, for the index at the end of archives
+/// The last bytes of the file are fetched once; reads before them issue a request each
+struct RemoteFile {
+    agent: ureq::Agent,
+    url: String,
+    /// Size of the whole file
+    size: u64,
+    /// Absolute offset of the next byte to read
+    pos: u64,
+    /// The last bytes of the file
+    tail: Vec<u8>,
+}
+
+impl RemoteFile {
+    /// Fetch the end of the file, `None` when the server does not honor `Range`
+    fn open(url: &str) -> Result<Option<Self>> {
+        let agent = ureq::Agent::new();
+        let range = format!("bytes=-{RANGE_CHUNK_SIZE}");
+        let response = match agent.get(url).set("Range", &range).call() {
+            Ok(response) => response,
+            // Empty file
+            Err(ureq::Error::Status(416, _)) => return Ok(None),
+            Err(e) => return Err(remote_error(url, e)),
+        };
+        let size = response
+            .header("Content-Range")
+            .and_then(|value| value.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse().ok());
+        let Some(size) = size.filter(|_| response.status() == 206) else {
+            return Ok(None);
+        };
+
+        let mut tail = Vec::new();
+        response
+            .into_reader()
+            .take(RANGE_CHUNK_SIZE)
+            .read_to_end(&mut tail)?;
+        Ok(Some(Self {
+            agent,
+            url: url.to_string(),
+            size,
+            pos: size,
+            tail,
+        }))
+    }
+
+    /// Stream `length` bytes starting at `offset` with a single request
+    fn range(&self, offset: u64, length: u64) -> Result<impl Read> {
+        let range = format!("bytes={offset}-{}", offset + length.max(1) - 1);
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &range)
+            .call()
+            .map_err(|e| remote_error(&self.url, e))?;
+        if response.status() != 206 {
+            return Err(ProjzstError::Remote(format!(
+                "GET {} ignored the range {range}",
+                self.url
+            )));
+        }
+        Ok(response.into_reader().take(length))
+    }
+}
+
+impl Read for RemoteFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let tail_start = self.size - self.tail.len() as u64;
+        let n = if self.pos >= self.size || out.is_empty() {
+            0
+        } else if self.pos >= tail_start {
+            let start = (self.pos - tail_start) as usize;
+            let n = out.len().min(self.tail.len() - start);
+            out[..n].copy_from_slice(&self.tail[start..start + n]);
+            n
+        } else {
+            let n = out.len().min((tail_start - self.pos) as usize);
+            self.range(self.pos, n as u64)
+                .map_err(io::Error::other)?
+                .read_exact(&mut out[..n])?;
+            n
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+/// Sequential reader backed by HTTP range requests
+struct RangeReader {
+    agent: ureq::Agent,
+    url: String,
+    /// Absolute offset of the next byte to fetch
+    pos: u64,
+    /// Size of the next range request
+    chunk_size: u64,
+    /// Bytes fetched but not yet consumed
+    buf: Vec<u8>,
+    buf_pos: usize,
+    /// Full-body stream, once the server has answered without honoring `Range`
+    stream: Option<Box<dyn Read + Send + Sync>>,
+    eof: bool,
+}
+
+impl RangeReader {
+    fn new(url: &str) -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+            pos: 0,
+            chunk_size: RANGE_CHUNK_SIZE,
+            buf: Vec::new(),
+            buf_pos: 0,
+            stream: None,
+            eof: false,
+        }
+    }
+
+    /// Fetch the next chunk into `buf`, or switch to streaming mode
+    fn fetch(&mut self) -> io::Result<()> {
+        let range = format!("bytes={}-{}", self.pos, self.pos + self.chunk_size - 1);
+        let response = match self.agent.get(&self.url).set("Range", &range).call() {
+            Ok(response) => response,
+            // Asked past the end of the file
+            Err(ureq::Error::Status(416, _)) => {
+                self.eof = true;
+                return Ok(());
+            }
+            Err(e) => return Err(io::Error::other(remote_error(&self.url, e))),
+        };
+
+        if response.status() == 206 {
+            self.buf.clear();
+            self.buf_pos = 0;
+            response
+                .into_reader()
+                .take(self.chunk_size)
+                .read_to_end(&mut self.buf)?;
+            if self.buf.is_empty() {
+                self.eof = true;
+            }
+            self.pos += self.buf.len() as u64;
+            self.chunk_size = (self.chunk_size * 2).min(MAX_RANGE_CHUNK_SIZE);
+        } else {
+            // Range not supported: stream the whole body, skipping what was already read
+            let mut stream = response.into_reader();
+            io::copy(&mut (&mut stream).take(self.pos), &mut io::sink())?;
+            self.stream = Some(stream);
+        }
+        Ok(())
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(stream) = &mut self.stream {
+                return stream.read(out);
+            }
+            if self.buf_pos < self.buf.len() {
+                let n = out.len().min(self.buf.len() - self.buf_pos);
+                out[..n].copy_from_slice(&self.buf[self.buf_pos..self.buf_pos + n]);
+                self.buf_pos += n;
+                return Ok(n);
+            }
+            if self.eof {
+                return Ok(0);
+            }
+            self.fetch()?;
+        }
+    }
+}
+
+/// Read-only [`Storage`] backend over an HTTP(S) base URL
+/// Files are fetched with range requests; `write` and `list` are not supported
+#[derive(Debug, Clone)]
+pub struct HttpStorage {
+    base_url: String,
+}
+
+impl HttpStorage {
+    /// Create a backend resolving paths against `base_url`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Internal helper: full URL of a backend path
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+}
+
+impl Storage for HttpStorage {
+    fn open(&self, path: &str) -> Result<Box<dyn Read + Send + '_>> {
+        Ok(Box::new(RangeReader::new(&self.url(path))))
+    }
+
+    fn write(&self, path: &str, _data: &mut dyn Read) -> Result<()> {
+        Err(ProjzstError::Remote(format!(
+            "cannot write {}: HTTP storage is read-only",
+            self.url(path)
+        )))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<StorageEntry>> {
+        Err(ProjzstError::Remote(format!(
+            "cannot list {}: HTTP storage does not support listing",
+            self.url(prefix)
+        )))
+    }
+}
+
+/// Internal helper: describe a failed HTTP request
+fn remote_error(url: &str, error: ureq::Error) -> ProjzstError {
+    match error {
+        ureq::Error::Status(code, _) => {
+            ProjzstError::Remote(format!("GET {url} returned HTTP {code}"))
+        }
+        e => ProjzstError::Remote(format!("GET {url} failed: {e}")),
+    }
+}
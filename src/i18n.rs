@@ -0,0 +1,166 @@
+//! Localized messages of the command-line tool
+//!
+//! Status lines, warnings, the error line and the summaries of `info`, `stat` and pack
+//! statistics are looked up by id in Fluent files under `locales/`, built into the
+//! binary. Listings keep their fixed layout, which scripts parse. The language comes
+//! from `--lang`, else from `LC_ALL`, `LC_MESSAGES` or `LANG`; English is used for any
+//! language without a file and for any message a file lacks. `--json` output stays English, so scripts parsing
+//! it do not depend on the locale of the machine.
+//!
+//! Messages of library errors are not translated: the error line names the kind of
+//! the error in the language picked, followed by the message itself.
+
+use std::sync::OnceLock;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use projzst::{ProjzstError, Warning};
+use unic_langid::LanguageIdentifier;
+
+/// Fluent files built into the binary, by language
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("zh", include_str!("../locales/zh.ftl")),
+];
+
+/// Bundles messages are looked up in, the language picked first and English last
+static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// Pick the language of the messages; only the first call has an effect
+///
+/// # Arguments
+/// * `lang` - Language given with `--lang`, e.g. `zh` or `zh_CN.UTF-8`, before the
+///   environment
+pub fn init(lang: Option<&str>) {
+    BUNDLES.get_or_init(|| {
+        let lang = lang.map(str::to_string).or_else(env_lang);
+        let language = lang.as_deref().and_then(parse_language);
+        let mut bundles = Vec::new();
+        if let Some((language, source)) = LOCALES
+            .iter()
+            .find(|(name, _)| Some(*name) == language.as_deref() && *name != "en")
+        {
+            bundles.push(bundle(language, source));
+        }
+        bundles.push(bundle(LOCALES[0].0, LOCALES[0].1));
+        bundles
+    });
+}
+
+/// Look up a message, formatted with `args`; the id itself when no bundle has it
+///
+/// # Arguments
+/// * `id` - Id of the message in the Fluent files
+/// * `args` - Values of the variables of the message
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    init(None);
+    let bundles = BUNDLES.get().expect("initialized above");
+    for bundle in bundles {
+        let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) else {
+            continue;
+        };
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, args, &mut errors);
+        if errors.is_empty() {
+            return text.into_owned();
+        }
+    }
+    id.to_string()
+}
+
+/// Look up a message, with its variables given as `name = value` pairs
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+
+pub(crate) use tr;
+
+/// The error line printed when a command fails, e.g. `Error: ...`
+///
+/// # Arguments
+/// * `e` - Error of the command
+pub fn error_line(e: &ProjzstError) -> String {
+    let kind = message(&format!("error-kind-{}", e.kind().name()), None);
+    tr!("error", kind = kind, message = e.to_string())
+}
+
+/// A warning of a pack or unpack report, in the language picked
+///
+/// # Arguments
+/// * `warning` - Warning to describe
+pub fn warning(warning: &Warning) -> String {
+    match warning {
+        Warning::OversizedFile { path, size } => {
+            tr!("warning-oversized-file", path = path.as_str(), size = *size)
+        }
+        Warning::SpecialEntry { path, entry } => {
+            tr!(
+                "warning-special-entry",
+                path = path.as_str(),
+                entry = entry.name()
+            )
+        }
+        Warning::PossibleSecret { path, line, secret } => {
+            let location = match line {
+                Some(line) => format!("{path}:{line}"),
+                None => path.clone(),
+            };
+            tr!(
+                "warning-possible-secret",
+                location = location,
+                secret = secret.name()
+            )
+        }
+        Warning::UnknownField { field } => {
+            tr!("warning-unknown-field", field = format!("{field:?}"))
+        }
+        Warning::SanitizedPath { path, sanitized } => tr!(
+            "warning-sanitized-path",
+            path = path.as_str(),
+            sanitized = sanitized.as_str()
+        ),
+        Warning::ClampedMtime { path } => tr!("warning-clamped-mtime", path = path.as_str()),
+        Warning::NestedLeftPacked { path, reason } => tr!(
+            "warning-nested-left-packed",
+            path = path.as_str(),
+            reason = reason.as_str()
+        ),
+        _ => warning.to_string(),
+    }
+}
+
+/// Internal helper: the language named by the locale environment variables
+fn env_lang() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Internal helper: the language subtag of a locale such as `zh_CN.UTF-8`
+fn parse_language(locale: &str) -> Option<String> {
+    // POSIX locales add the encoding and a modifier, which language tags do not have
+    let tag = locale.split(['.', '@']).next()?;
+    let id: LanguageIdentifier = tag.replace('_', "-").parse().ok()?;
+    Some(id.language.as_str().to_string())
+}
+
+/// Internal helper: a bundle of the messages of one language
+fn bundle(language: &str, source: &str) -> FluentBundle<FluentResource> {
+    let id: LanguageIdentifier = language.parse().expect("valid language tag");
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Isolation marks would end up around every path printed to the terminal
+    bundle.set_use_isolating(false);
+    let resource =
+        FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, _)| resource);
+    // Fails on duplicate ids only, and the first of them is kept then
+    let _ = bundle.add_resource(resource);
+    bundle
+}
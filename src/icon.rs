@@ -0,0 +1,94 @@
+//! Icon and thumbnail attachments
+//!
+//! A small image (application icon, screenshot) can be stored in an `icon` frame next to
+//! the metadata, so package browsers show artwork by reading the header only.
+
+use std::path::Path;
+
+use crate::errors::{ProjzstError, Result};
+use crate::frames::{frames, replace_frame, FrameKind};
+
+/// Largest icon accepted, in bytes
+pub const MAX_ICON_SIZE: usize = 1024 * 1024;
+
+/// Image formats accepted as icons: media type and leading signature
+const ICON_SIGNATURES: [(&str, &[u8]); 5] = [
+    ("image/png", b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", b"\xFF\xD8\xFF"),
+    ("image/gif", b"GIF8"),
+    ("image/x-icon", b"\x00\x00\x01\x00"),
+    ("image/svg+xml", b"<svg"),
+];
+
+/// An image attached to an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icon {
+    /// Image file content
+    pub data: Vec<u8>,
+}
+
+impl Icon {
+    /// Check that `data` is a supported image of at most [`MAX_ICON_SIZE`] bytes
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() > MAX_ICON_SIZE {
+            return Err(ProjzstError::InvalidIcon(format!(
+                "{} bytes exceeds the {MAX_ICON_SIZE} byte limit",
+                data.len()
+            )));
+        }
+        let icon = Self { data };
+        if icon.media_type().is_none() {
+            return Err(ProjzstError::InvalidIcon(
+                "not a PNG, JPEG, GIF, WebP, ICO or SVG image".to_string(),
+            ));
+        }
+        Ok(icon)
+    }
+
+    /// Media type guessed from the image signature, e.g. `image/png`
+    pub fn media_type(&self) -> Option<&'static str> {
+        let data = self.data.as_slice();
+        if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            return Some("image/webp");
+        }
+        // SVG files may start with an XML declaration or whitespace
+        let trimmed = match data.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(start) => &data[start..],
+            None => data,
+        };
+        if trimmed.starts_with(b"<?xml") && trimmed.windows(4).any(|w| w == b"<svg") {
+            return Some("image/svg+xml");
+        }
+        ICON_SIGNATURES
+            .iter()
+            .find(|(_, signature)| trimmed.starts_with(signature))
+            .map(|(media_type, _)| *media_type)
+    }
+}
+
+/// Read the icon attached to a .pjz file, if any
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn icon<P: AsRef<Path>>(input_file: P) -> Result<Option<Icon>> {
+    Ok(frames(input_file)?
+        .into_iter()
+        .find(|frame| frame.kind() == FrameKind::Icon)
+        .map(|frame| Icon {
+            data: frame.payload,
+        }))
+}
+
+/// Attach an icon to a .pjz file in place, replacing any previous one
+/// The metadata and payload are left untouched; `None` removes the icon
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `icon` - Image to attach, or `None` to remove it
+pub fn set_icon<P: AsRef<Path>>(input_file: P, icon: Option<&Icon>) -> Result<()> {
+    replace_frame(
+        input_file.as_ref(),
+        FrameKind::Icon,
+        icon.map(|icon| icon.data.clone()),
+    )
+}
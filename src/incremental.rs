@@ -0,0 +1,246 @@
+//! Incremental packing against a previous archive
+//!
+//! An incremental archive stores only new and modified files. Files identical to an
+//! entry of the base archive (same size and SHA-256) are listed by path under
+//! `extra.incremental.unchanged`, together with the SHA-256 of the base archive itself,
+//! and are restored from the base by [`unpack_incremental`].
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+
+use sha2::{Digest, Sha256};
+
+use crate::builder::{open_archive, unpack, SourceRoot};
+use crate::diff::entry_digests_with;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::storage::normalize_path;
+use crate::string_utils::to_hex;
+use crate::volume::{open_input, open_raw_input};
+use crate::walk::{walk_source, WalkOptions};
+
+/// Key under `extra` holding the incremental reference list
+pub(crate) const INCREMENTAL_KEY: &str = "incremental";
+
+/// Comparison of the source files with a base archive, finished by hashing threads while
+/// the payload is written
+/// Dropping the plan stops the hashing
+pub(crate) struct IncrementalPlan {
+    /// Hex SHA-256 of the base archive file
+    base_sha256: String,
+    /// Paths whose size matches their base entry, compared by content on the threads
+    candidates: BTreeSet<String>,
+    verdicts: Arc<Verdicts>,
+}
+
+/// Outcomes of the content comparisons, shared with the hashing threads
+#[derive(Default)]
+struct Verdicts {
+    /// Whether each candidate compared so far is identical to its base entry
+    done: Mutex<HashMap<String, Result<bool>>>,
+    /// Notified whenever a comparison is done
+    ready: Condvar,
+    /// Set once the plan is dropped, so the threads stop
+    cancelled: AtomicBool,
+}
+
+impl IncrementalPlan {
+    /// Whether the file at `relative` is identical to its base entry, waiting for its
+    /// comparison if it is still running
+    pub fn is_unchanged(&self, relative: &str) -> Result<bool> {
+        if !self.candidates.contains(relative) {
+            return Ok(false);
+        }
+        let mut done = self.verdicts.done.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match done.get(relative) {
+                Some(Ok(same)) => return Ok(*same),
+                Some(Err(_)) => {
+                    let error = done.remove(relative).and_then(|verdict| verdict.err());
+                    return Err(error.expect("failed comparison"));
+                }
+                None => {
+                    done = self
+                        .verdicts
+                        .ready
+                        .wait(done)
+                        .unwrap_or_else(|e| e.into_inner())
+                }
+            }
+        }
+    }
+
+    /// Paths identical in the source and the base, once every comparison is done
+    pub fn unchanged(&self) -> Result<BTreeSet<String>> {
+        let mut unchanged = BTreeSet::new();
+        for relative in &self.candidates {
+            if self.is_unchanged(relative)? {
+                unchanged.insert(relative.clone());
+            }
+        }
+        Ok(unchanged)
+    }
+
+    /// The reference list as stored in `extra.incremental`, once every comparison is done
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "base_sha256": self.base_sha256,
+            "unchanged": self.unchanged()?,
+        }))
+    }
+}
+
+impl Drop for IncrementalPlan {
+    fn drop(&mut self) {
+        self.verdicts.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Compare the files of every source root, stored under its prefix, against the entries
+/// of `base_archive`, the last one of each path being the one the base unpacks to
+/// The base is decoded and hashed first; the files whose size matches their base entry
+/// are then hashed on up to `threads` threads of their own, in walk order, while the
+/// caller writes the payload and asks [`IncrementalPlan::is_unchanged`] as it goes
+pub(crate) fn plan_incremental(
+    roots: &[SourceRoot],
+    options: &WalkOptions,
+    base_archive: &Path,
+    threads: usize,
+) -> Result<IncrementalPlan> {
+    let (base_sha256, base) = thread::scope(|scope| {
+        let base_sha256 = scope.spawn(|| archive_sha256(base_archive));
+        let mut base = HashMap::new();
+        entry_digests_with(base_archive, |digest| {
+            base.insert(digest.path.clone(), (digest.size, digest.sha256.clone()));
+        })?;
+        let base_sha256 = base_sha256.join().expect("hashing thread panicked")?;
+        Ok::<_, ProjzstError>((base_sha256, base))
+    })?;
+
+    // The first root holding a path is the one it is stored from
+    let mut seen = BTreeSet::new();
+    let mut work: Vec<(String, PathBuf, String)> = Vec::new();
+    for root in roots {
+        for (relative, path, info) in
+            walk_source(&root.path, root.prefix.as_deref(), options)?.entries
+        {
+            if !seen.insert(relative.clone()) || !info.is_file() {
+                continue;
+            }
+            if let Some((size, sha256)) = base.get(&relative) {
+                if *size == info.len() {
+                    work.push((relative, path, sha256.clone()));
+                }
+            }
+        }
+    }
+
+    let candidates = work.iter().map(|(relative, ..)| relative.clone()).collect();
+    let verdicts = Arc::new(Verdicts::default());
+    let (task_tx, task_rx) = mpsc::channel();
+    for task in work {
+        // The receiver is alive until the threads below are done
+        let _ = task_tx.send(task);
+    }
+    drop(task_tx);
+    let task_rx = Arc::new(Mutex::new(task_rx));
+    for _ in 0..threads.max(1) {
+        let (task_rx, verdicts) = (Arc::clone(&task_rx), Arc::clone(&verdicts));
+        thread::spawn(move || loop {
+            let task = task_rx.lock().unwrap_or_else(|e| e.into_inner()).recv();
+            let Ok((relative, path, sha256)) = task else {
+                break;
+            };
+            if verdicts.cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            let same = file_sha256(&path).map(|hash| hash == sha256);
+            let mut done = verdicts.done.lock().unwrap_or_else(|e| e.into_inner());
+            done.insert(relative, same);
+            verdicts.ready.notify_all();
+        });
+    }
+
+    Ok(IncrementalPlan {
+        base_sha256,
+        candidates,
+        verdicts,
+    })
+}
+
+/// Unpack an incremental .pjz file on top of the base archive it was packed against
+/// Unchanged files are extracted from `base_file` first, then the archive's own entries;
+/// archives that are not incremental are unpacked as usual
+///
+/// # Arguments
+/// * `input_file` - Path to the incremental .pjz file
+/// * `base_file` - Path to the base .pjz file it references
+/// * `output_dir` - Directory to extract contents to
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn unpack_incremental<P1, P2, P3>(
+    input_file: P1,
+    base_file: P2,
+    output_dir: P3,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let base_file = base_file.as_ref();
+    let output_dir = output_dir.as_ref();
+
+    let (metadata, _) = open_archive(open_input(input_file.as_ref())?, ignore_unknown)?;
+    if let Some(reference) = metadata.extra.get(INCREMENTAL_KEY) {
+        let expected = reference["base_sha256"].as_str().unwrap_or_default();
+        if archive_sha256(base_file)? != expected {
+            return Err(ProjzstError::BaseMismatch(base_file.display().to_string()));
+        }
+
+        let unchanged: BTreeSet<&str> = reference["unchanged"]
+            .as_array()
+            .map(|paths| paths.iter().filter_map(|p| p.as_str()).collect())
+            .unwrap_or_default();
+
+        fs::create_dir_all(output_dir)?;
+        let (_, mut base_archive) = open_archive(open_input(base_file)?, IgnoreUnknown::On)?;
+        for entry in base_archive.entries()? {
+            let mut entry = entry?;
+            let keep = normalize_path(&entry.path()?)
+                .is_some_and(|path| unchanged.contains(path.as_str()));
+            if keep {
+                log::info!(
+                    "{} ({} bytes, from the base archive)",
+                    entry.path()?.display(),
+                    entry.size()
+                );
+                entry.unpack_in(output_dir)?;
+            }
+        }
+    }
+
+    unpack(input_file, output_dir, ignore_unknown)
+}
+
+/// Internal helper: hex SHA-256 of a file's content
+fn file_sha256(path: &Path) -> Result<String> {
+    reader_sha256(File::open(path)?)
+}
+
+/// Internal helper: hex SHA-256 of an archive, with the volumes of a split archive joined
+pub(crate) fn archive_sha256(path: &Path) -> Result<String> {
+    reader_sha256(open_raw_input(path)?)
+}
+
+/// Internal helper: hex SHA-256 of everything `reader` yields
+fn reader_sha256(mut reader: impl io::Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
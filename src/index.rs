@@ -0,0 +1,107 @@
+//! Entry index of per-entry payloads
+//!
+//! With [`Packer::per_entry_frames`](crate::Packer::per_entry_frames), every file starts a
+//! zstd frame of its own and an index frame listing where each one is goes right after the
+//! payload. Readers by path decode only the frame of the entry they want; everything else
+//! sees a regular tar.zst stream, since zstd decoders skip the index like any skippable frame.
+//!
+//! The payload of the index frame is the MessagePack-encoded [`EntryIndex`] followed by the
+//! payload size as a little-endian `u32`, like metadata revisions, so it is found walking
+//! backwards from the end of the file past any revision appended later.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::builder::METADATA_FRAME_MAGIC;
+use crate::errors::Result;
+use crate::frames::{FrameKind, MAX_FRAME_SIZE};
+
+/// Where the frames of a per-entry payload are
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EntryIndex {
+    /// Bytes of zstd frames between the end of the header frames and the index frame
+    pub payload_size: u64,
+    /// Offset of the payload in the file, set by [`EntryIndex::read_from`]
+    #[serde(skip)]
+    pub payload_start: u64,
+    /// Files in archive order
+    pub entries: Vec<IndexedEntry>,
+}
+
+/// The zstd frame holding one file, starting with its tar header
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct IndexedEntry {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Offset of the frame from the start of the payload
+    pub offset: u64,
+    /// Size of the frame in bytes
+    pub length: u64,
+}
+
+impl EntryIndex {
+    /// Internal helper: write the index frame
+    pub(crate) fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mut payload = rmp_serde::to_vec_named(self).map_err(io::Error::other)?;
+        let size = u32::try_from(payload.len() + 4)
+            .ok()
+            .filter(|&size| size as usize <= MAX_FRAME_SIZE)
+            .ok_or_else(|| io::Error::other("entry index too large for a frame"))?;
+        payload.extend_from_slice(&size.to_le_bytes());
+        output.write_all(&FrameKind::Index.magic().to_le_bytes())?;
+        output.write_all(&size.to_le_bytes())?;
+        output.write_all(&payload)
+    }
+
+    /// Internal helper: read the index at the end of an archive file, past any metadata
+    /// revision; `None` for archives packed as a single stream
+    /// Each entry comes back with its absolute offset in the file
+    pub(crate) fn read_from<R: Read + Seek>(file: &mut R) -> Result<Option<Self>> {
+        let mut end = file.seek(SeekFrom::End(0))?;
+        while end >= 12 {
+            let mut size_buf = [0u8; 4];
+            file.seek(SeekFrom::Start(end - 4))?;
+            file.read_exact(&mut size_buf)?;
+            let size = u64::from(u32::from_le_bytes(size_buf));
+            if size < 4 || size + 8 > end {
+                return Ok(None);
+            }
+
+            let start = end - 8 - size;
+            let mut header = [0u8; 8];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut header)?;
+            if header[4..] != size_buf {
+                return Ok(None);
+            }
+            let magic = u32::from_le_bytes(header[..4].try_into().expect("4 bytes"));
+            if magic == METADATA_FRAME_MAGIC {
+                end = start;
+                continue;
+            }
+            if magic != FrameKind::Index.magic() {
+                return Ok(None);
+            }
+
+            let mut payload = vec![0u8; size as usize - 4];
+            file.read_exact(&mut payload)?;
+            let mut index: Self = rmp_serde::from_slice(&payload)?;
+            let Some(payload_start) = start.checked_sub(index.payload_size) else {
+                return Ok(None);
+            };
+            for entry in &mut index.entries {
+                entry.offset += payload_start;
+            }
+            index.payload_start = payload_start;
+            return Ok(Some(index));
+        }
+        Ok(None)
+    }
+
+    /// Internal helper: the frame of the file at `path`, if indexed
+    /// The last one when the path was stored twice, as unpacking keeps that one
+    pub(crate) fn find(&self, path: &str) -> Option<&IndexedEntry> {
+        self.entries.iter().rfind(|entry| entry.path == path)
+    }
+}
@@ -0,0 +1,221 @@
+//! Signing keys of the command-line tool (`projzst key`)
+//!
+//! Key pairs live in `keys/` of the configuration directory (see [`config_dir`]):
+//! `NAME.key` holds the secret key, readable by its owner only, and `NAME.pub` the public
+//! half handed out to those checking signatures. Public keys of others are trusted by
+//! copying them to `trusted/NAME.pub`. `pack --sign-key NAME` signs with a key pair, and
+//! `verify --check-signature` accepts signatures of trusted keys and of the user's own.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use projzst::{ProjzstError, PublicKey, SecretKey};
+use serde::Serialize;
+
+use crate::config::config_dir;
+
+/// A key of the store, as listed by `projzst key list`
+#[derive(Debug, Serialize)]
+pub struct StoredKey {
+    /// Name the key is stored under
+    pub name: String,
+    /// Identifier recorded with signatures, see [`PublicKey::id`]
+    pub id: String,
+    /// Whether the key is a key pair of the user (`keys/`) rather than a trusted public key
+    pub own: bool,
+}
+
+/// Key pairs and trusted public keys in the configuration directory
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    /// The store of the configuration directory, created on first write
+    pub fn open() -> Result<Self, ProjzstError> {
+        let dir = config_dir().ok_or_else(|| {
+            ProjzstError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no configuration directory to keep keys in (HOME is not set)",
+            ))
+        })?;
+        Ok(Self { dir })
+    }
+
+    /// Create a key pair from the system random source
+    /// Fails when `name` is taken, unless `force` replaces it
+    ///
+    /// # Arguments
+    /// * `name` - Name to store the pair under
+    /// * `force` - Replace a key pair of the same name
+    pub fn generate(&self, name: &str, force: bool) -> Result<PublicKey, ProjzstError> {
+        let secret_path = self.secret_path(name)?;
+        if secret_path.exists() && !force {
+            return Err(ProjzstError::OutputExists(
+                secret_path.display().to_string(),
+            ));
+        }
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed)
+            .map_err(|e| ProjzstError::Io(io::Error::other(e.to_string())))?;
+        let key = SecretKey::from_seed(seed);
+        fs::create_dir_all(self.dir.join("keys"))?;
+        write_private(&secret_path, &key.to_text())?;
+        fs::write(self.public_path(name)?, key.public_key().to_text() + "\n")?;
+        Ok(key.public_key())
+    }
+
+    /// Secret key of a key pair
+    ///
+    /// # Arguments
+    /// * `name` - Name the pair is stored under
+    pub fn secret_key(&self, name: &str) -> Result<SecretKey, ProjzstError> {
+        SecretKey::from_text(&read_key(&self.secret_path(name)?)?)
+    }
+
+    /// Public key of a key pair
+    ///
+    /// # Arguments
+    /// * `name` - Name the pair is stored under
+    pub fn public_key(&self, name: &str) -> Result<PublicKey, ProjzstError> {
+        PublicKey::from_text(&read_key(&self.public_path(name)?)?)
+    }
+
+    /// Trust the public key in `file`, replacing a trusted key of the same name
+    ///
+    /// # Arguments
+    /// * `file` - Public key file, as written by `projzst key export`
+    /// * `name` - Name to trust the key under
+    pub fn trust(&self, file: &Path, name: &str) -> Result<PublicKey, ProjzstError> {
+        let key = PublicKey::from_text(&read_key(file)?)?;
+        let path = self.trusted_path(name)?;
+        fs::create_dir_all(self.dir.join("trusted"))?;
+        fs::write(path, key.to_text() + "\n")?;
+        Ok(key)
+    }
+
+    /// Every key pair and trusted key, sorted by name; files that do not parse are skipped
+    pub fn list(&self) -> Result<Vec<StoredKey>, ProjzstError> {
+        let mut keys = Vec::new();
+        for (dir, own) in [("keys", true), ("trusted", false)] {
+            for (name, key) in public_keys(&self.dir.join(dir))? {
+                keys.push(StoredKey {
+                    name,
+                    id: key.id(),
+                    own,
+                });
+            }
+        }
+        keys.sort_by(|a, b| (!a.own, &a.name).cmp(&(!b.own, &b.name)));
+        Ok(keys)
+    }
+
+    /// Keys whose signatures `verify --check-signature` accepts: trusted keys and the
+    /// public halves of the user's key pairs
+    pub fn verifying_keys(&self) -> Result<Vec<PublicKey>, ProjzstError> {
+        let mut keys = Vec::new();
+        for dir in ["keys", "trusted"] {
+            keys.extend(
+                public_keys(&self.dir.join(dir))?
+                    .into_iter()
+                    .map(|(_, key)| key),
+            );
+        }
+        Ok(keys)
+    }
+
+    /// Internal helper: file of the secret key of a pair
+    fn secret_path(&self, name: &str) -> Result<PathBuf, ProjzstError> {
+        self.key_path("keys", name, "key")
+    }
+
+    /// Internal helper: file of the public key of a pair
+    fn public_path(&self, name: &str) -> Result<PathBuf, ProjzstError> {
+        self.key_path("keys", name, "pub")
+    }
+
+    /// Internal helper: file of a trusted public key
+    fn trusted_path(&self, name: &str) -> Result<PathBuf, ProjzstError> {
+        self.key_path("trusted", name, "pub")
+    }
+
+    /// Internal helper: `DIR/NAME.EXT` in the store, for a valid key name
+    fn key_path(&self, dir: &str, name: &str, ext: &str) -> Result<PathBuf, ProjzstError> {
+        Ok(self
+            .dir
+            .join(dir)
+            .join(format!("{}.{ext}", check_name(name)?)))
+    }
+}
+
+/// Internal helper: fail unless a key name is letters, digits, `.`, `_` and `-`, not
+/// starting with a dot
+fn check_name(name: &str) -> Result<&str, ProjzstError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(name)
+    } else {
+        Err(ProjzstError::InvalidKey(format!(
+            "{name:?} is not a key name (letters, digits, '.', '_' and '-')"
+        )))
+    }
+}
+
+/// Internal helper: read a key file, failing with [`ProjzstError::InvalidKey`] when it is
+/// missing
+fn read_key(path: &Path) -> Result<String, ProjzstError> {
+    fs::read_to_string(path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            ProjzstError::InvalidKey(format!("no key file {}", path.display()))
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Internal helper: the `.pub` files of a directory that parse, by name; none when the
+/// directory does not exist
+fn public_keys(dir: &Path) -> Result<Vec<(String, PublicKey)>, ProjzstError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut keys = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".pub"))
+        else {
+            continue;
+        };
+        if let Ok(key) = fs::read_to_string(&path)
+            .map_err(ProjzstError::from)
+            .and_then(|text| PublicKey::from_text(&text))
+        {
+            keys.push((name.to_string(), key));
+        }
+    }
+    Ok(keys)
+}
+
+/// Internal helper: write a file only its owner can read
+fn write_private(path: &Path, content: &str) -> Result<(), ProjzstError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(file, "{content}")?;
+    Ok(())
+}
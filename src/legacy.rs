@@ -0,0 +1,112 @@
+//! Migration from the legacy layout
+//!
+//! Early archives stored the metadata behind a plain length prefix instead of in skippable
+//! frames: `[4-byte big-endian length][MessagePack metadata][tar.zst data]`. Such files are
+//! not valid zstd streams, so generic tools cannot decompress them. [`migrate`] moves the
+//! metadata into a metadata frame and keeps the payload byte for byte.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{
+    decode_metadata, write_metadata_frame, MAX_METADATA_SIZE, METADATA_FRAME_MAGIC,
+    SKIPPABLE_FRAME_MAGIC_MAX, SKIPPABLE_FRAME_MAGIC_MIN, ZSTD_FRAME_MAGIC,
+};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::IgnoreUnknown;
+use crate::temp::temp_file_for;
+
+/// Header layout of an archive file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ArchiveLayout {
+    /// Metadata in leading skippable frames
+    Current,
+    /// Metadata behind a 4-byte big-endian length prefix
+    Legacy,
+}
+
+/// Tell which layout an archive file uses
+/// Fails with [`ProjzstError::InvalidFileHeader`] when the file is neither
+///
+/// # Arguments
+/// * `input_file` - Path to the archive file
+pub fn detect_layout<P: AsRef<Path>>(input_file: P) -> Result<ArchiveLayout> {
+    let (layout, _) = read_layout(&mut File::open(input_file.as_ref())?)?;
+    Ok(layout)
+}
+
+/// Rewrite a legacy archive into the current layout
+/// The metadata is stored unchanged in a metadata frame and the payload is copied as is.
+/// An archive already in the current layout is copied unchanged. Returns the layout the
+/// input had; `output_file` may be `input_file` to migrate in place.
+///
+/// # Arguments
+/// * `input_file` - Path to the archive to migrate
+/// * `output_file` - Path of the migrated .pjz file
+pub fn migrate<P1, P2>(input_file: P1, output_file: P2) -> Result<ArchiveLayout>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut input = File::open(input_file.as_ref())?;
+    let (layout, header) = read_layout(&mut input)?;
+
+    let output_file = output_file.as_ref();
+    let (mut output, temp) = temp_file_for(output_file)?;
+    match layout {
+        ArchiveLayout::Current => output.write_all(&header)?,
+        ArchiveLayout::Legacy => {
+            // `header` is the metadata followed by the payload magic
+            let (metadata_bytes, payload_head) = header.split_at(header.len() - 4);
+            write_metadata_frame(&mut output, METADATA_FRAME_MAGIC, metadata_bytes)?;
+            output.write_all(payload_head)?;
+        }
+    }
+    io::copy(&mut input, &mut output)?;
+    output.flush()?;
+    drop(output);
+    temp.persist(output_file)?;
+    Ok(layout)
+}
+
+/// Internal helper: detect the layout from the start of a file
+/// Returns the bytes consumed: the first 4 bytes for the current layout, the metadata
+/// and the payload magic for the legacy one
+fn read_layout<R: Read>(reader: &mut R) -> Result<(ArchiveLayout, Vec<u8>)> {
+    let mut prefix = [0u8; 4];
+    read_header_bytes(reader, &mut prefix)?;
+    if (SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&u32::from_le_bytes(prefix))
+    {
+        return Ok((ArchiveLayout::Current, prefix.to_vec()));
+    }
+
+    let metadata_len = u32::from_be_bytes(prefix) as usize;
+    if metadata_len == 0 || metadata_len > MAX_METADATA_SIZE {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    let mut header = vec![0u8; metadata_len + 4];
+    read_header_bytes(reader, &mut header)?;
+
+    // The payload must follow right away, and the metadata must decode
+    let (metadata_bytes, payload_magic) = header.split_at(metadata_len);
+    if payload_magic != ZSTD_FRAME_MAGIC.to_le_bytes() {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    decode_metadata(metadata_bytes, IgnoreUnknown::On)
+        .map_err(|_| ProjzstError::InvalidFileHeader)?;
+    Ok((ArchiveLayout::Legacy, header))
+}
+
+/// Internal helper: fill `buf`, reporting a file too short as an invalid header
+fn read_header_bytes<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(ProjzstError::InvalidFileHeader),
+        Err(e) => Err(e.into()),
+    }
+}
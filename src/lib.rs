@@ -1,26 +1,189 @@
 //! Core library for projzst - pack and unpack .pjz files
 //!
-//! File format specification (new):
+//! File format specification (new, see `migrate` for the legacy one):
 //! [Skippable Frame (metadata)] + [tar.zst data]
 //! Skippable Frame: [4-byte magic (0x184D2A50..0x184D2A5F)] + [4-byte little-endian size] + [MessagePack metadata]
 //! The metadata is stored in one or more ZStd skippable frames at the beginning of the file,
 //! followed by a standard ZStd compressed frame containing the tar archive.
+//!
+//! The public API is listed in [`api`] and re-exported here.
+
+pub mod api;
+pub use crate::api::errors::*;
+pub use crate::api::metadata::*;
+pub use crate::api::operations::*;
+pub use crate::api::options::*;
+pub use crate::api::reports::*;
+pub use crate::api::storage::*;
 
 mod string_utils;
-pub use crate::string_utils::convert;
-pub use crate::string_utils::IntoOpStr;
+
+mod bench;
 
 mod builder;
-pub use crate::builder::Packer;
-pub use crate::builder::{info, pack, read_metadata, unpack};
+
+mod bump;
+
+mod bundle;
+
+#[cfg(feature = "capi")]
+mod capi;
+
+mod catalog;
+
+mod checkpoint;
+
+mod checksum;
+
+mod chunking;
+
+mod codec;
+
+mod collision;
+
+mod concurrency;
+
+mod frames;
+
+mod storage;
+
+mod diff;
+
+mod dictionary;
+
+mod entries;
+
+mod incremental;
+
+mod index;
+
+mod volume;
+
+mod temp;
+
+mod selfcheck;
+
+mod merge;
+
+mod convert;
+
+mod date;
+
+mod deps;
+
+mod grep;
+
+mod legacy;
+
+mod lint;
+
+mod listing;
+
+#[cfg(feature = "mmap")]
+mod mapped;
+
+mod attach;
+
+mod icon;
+
+mod memory;
+
+mod mtime;
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
+
+mod naming;
+
+mod nested;
+
+mod packset;
+
+mod payload;
+
+mod preview;
+
+mod profile;
+
+mod provenance;
+
+mod prune;
+
+#[cfg(feature = "python")]
+mod python;
+
+mod reflink;
+
+mod remap;
+
+mod repack;
+
+mod repair;
+
+#[cfg(feature = "http")]
+mod registry;
+
+mod report;
+
+mod revisions;
+
+#[cfg(feature = "sandbox")]
+mod sandbox;
+
+mod sbom;
+
+mod sfx;
+
+mod signing;
+
+mod schema;
+
+mod search;
+
+mod secrets;
+
+#[cfg(feature = "serve")]
+mod serve;
+
+mod space;
+
+mod stat;
+
+mod sync;
+
+mod tar_format;
+
+mod template;
+
+mod timestamp;
+
+mod usage;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+mod validate;
+
+mod walk;
+
+mod watch;
+
+mod encoding;
+
+mod estimate;
 
 mod errors;
-pub use crate::errors::ProjzstError;
-pub use crate::errors::Result;
 
 mod metadata;
-pub use crate::metadata::IgnoreUnknown;
-pub use crate::metadata::Metadata;
+
+#[cfg(feature = "http")]
+mod http;
+
+#[cfg(feature = "s3")]
+mod s3;
 
 /// Default zstd compression level for pack operation
 pub const DEFAULT_ZSTD_LEVEL: i32 = 6;
+
+/// Default size in bytes of the buffers between archive files and the zstd streams
+pub const DEFAULT_BUFFER_SIZE: usize = 128 * 1024;
@@ -0,0 +1,438 @@
+//! Best-practice checks on archives
+//!
+//! Each rule has an id and a default severity; a [`LintConfig`] can raise, lower or turn
+//! off any of them. Organizations can add their own rules as [`LintPlugin`]s. The
+//! resulting [`LintReport`] serializes to JSON for CI gates.
+
+mod plugin;
+pub use plugin::{LintManifest, LintPlugin, ManifestEntry};
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::builder::open_archive;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::validate::is_semver;
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::revisions::latest_revision;
+use crate::storage::normalize_path;
+use crate::volume::open_input;
+use plugin::run_plugin;
+
+/// Metadata has no version
+pub const MISSING_VERSION: &str = "missing-version";
+/// A file or directory is writable by everyone
+pub const WORLD_WRITABLE: &str = "world-writable";
+/// A symlink points to an absolute path
+pub const ABSOLUTE_SYMLINK: &str = "absolute-symlink";
+/// A single file exceeds [`LintConfig::max_file_size`]
+pub const LARGE_FILE: &str = "large-file";
+/// OS or editor leftovers such as `.DS_Store`
+pub const JUNK_FILE: &str = "junk-file";
+/// No license file at the archive root and no `license` (or `extra.license`) in metadata
+pub const MISSING_LICENSE: &str = "missing-license";
+/// Metadata has no name
+pub const MISSING_NAME: &str = "missing-name";
+/// Metadata has no author, format or description
+pub const INCOMPLETE_METADATA: &str = "incomplete-metadata";
+/// The version is not a semantic version
+pub const INVALID_VERSION: &str = "invalid-version";
+/// An entry path is absolute or climbs out of the archive with `..`
+pub const UNSAFE_PATH: &str = "unsafe-path";
+/// An entry is a device node or a named pipe
+pub const DEVICE_NODE: &str = "device-node";
+/// `extra` exceeds [`LintConfig::max_extra_size`] once serialized
+pub const LARGE_EXTRA: &str = "large-extra";
+
+/// Every built-in rule with its default severity
+const RULES: [(&str, Severity); 12] = [
+    (MISSING_NAME, Severity::Error),
+    (MISSING_VERSION, Severity::Error),
+    (INVALID_VERSION, Severity::Warning),
+    (INCOMPLETE_METADATA, Severity::Warning),
+    (LARGE_EXTRA, Severity::Warning),
+    (UNSAFE_PATH, Severity::Error),
+    (DEVICE_NODE, Severity::Error),
+    (WORLD_WRITABLE, Severity::Warning),
+    (ABSOLUTE_SYMLINK, Severity::Warning),
+    (LARGE_FILE, Severity::Warning),
+    (JUNK_FILE, Severity::Warning),
+    (MISSING_LICENSE, Severity::Warning),
+];
+
+/// File names that are never meant to be shipped
+const JUNK_NAMES: [&str; 5] = [
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    "__MACOSX",
+    ".directory",
+];
+
+/// How serious a finding is; `Off` disables a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Severity {
+    /// Rule disabled
+    Off,
+    /// Informational only
+    Info,
+    /// Worth fixing, does not fail the lint
+    Warning,
+    /// Fails the lint
+    Error,
+}
+
+/// Rule selection and thresholds for [`lint`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct LintConfig {
+    /// Largest single file accepted by the `large-file` rule, in bytes
+    pub max_file_size: u64,
+    /// Largest serialized `extra` accepted by the `large-extra` rule, in bytes
+    pub max_extra_size: u64,
+    /// Severity overrides by rule id; rules not listed keep their default
+    pub severities: BTreeMap<String, Severity>,
+    /// Rule ids reported by plugins, which `severities` may name besides the built-in rules
+    pub plugin_rules: Vec<String>,
+    /// External rule plugins, run after the built-in rules
+    pub plugins: Vec<LintPlugin>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: 100 * 1024 * 1024,
+            max_extra_size: 64 * 1024,
+            severities: BTreeMap::new(),
+            plugin_rules: Vec::new(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+impl LintConfig {
+    /// Configuration running only the given plugins, with every built-in rule off
+    pub fn plugins_only(plugins: Vec<LintPlugin>) -> Self {
+        Self {
+            severities: RULES
+                .iter()
+                .map(|(id, _)| (id.to_string(), Severity::Off))
+                .collect(),
+            plugins,
+            ..Default::default()
+        }
+    }
+
+    /// Check that every severity override names a built-in rule or one of `plugin_rules`
+    pub fn check(&self) -> Result<()> {
+        let unknown = self.severities.keys().find(|rule| {
+            !RULES.iter().any(|(id, _)| id == rule) && !self.plugin_rules.contains(rule)
+        });
+        match unknown {
+            Some(rule) => Err(ProjzstError::UnknownLintRule(rule.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Effective severity of a rule
+    pub fn severity(&self, rule: &str) -> Severity {
+        self.severities.get(rule).copied().unwrap_or_else(|| {
+            RULES
+                .iter()
+                .find(|(id, _)| *id == rule)
+                .map_or(Severity::Off, |(_, severity)| *severity)
+        })
+    }
+}
+
+/// One rule violation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct LintFinding {
+    /// Id of the rule that fired
+    pub rule: String,
+    /// Severity the rule was configured with
+    pub severity: Severity,
+    /// Archive entry concerned, if the finding is about one
+    pub path: Option<String>,
+    /// Human-readable explanation
+    pub message: String,
+}
+
+/// Findings of a [`lint`] run, in archive order after metadata findings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct LintReport {
+    /// Every finding of enabled rules
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    /// Number of findings at the given severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+
+    /// Whether any finding fails the lint
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+}
+
+/// Check a .pjz file against best-practice rules and any configured plugins
+/// Severity overrides must name built-in rules or declared plugin rules, see
+/// [`LintConfig::check`]
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+/// * `config` - Rule severities and thresholds
+pub fn lint<P: AsRef<Path>>(input_file: P, config: &LintConfig) -> Result<LintReport> {
+    config.check()?;
+
+    let input_file = input_file.as_ref();
+    let (metadata, mut tar_archive) = open_archive(open_input(input_file)?, IgnoreUnknown::On)?;
+    let metadata = latest_revision(input_file, IgnoreUnknown::On)?.unwrap_or(metadata);
+    let mut linter = Linter {
+        config,
+        report: LintReport::default(),
+    };
+
+    lint_metadata(&mut linter, &metadata)?;
+
+    let mut has_license = has_license_metadata(&metadata);
+    let mut entries = Vec::new();
+    for entry in tar_archive.entries()? {
+        let entry = entry?;
+        let raw_path = entry.path()?;
+        if raw_path.has_root() || raw_path.components().any(|c| c == Component::ParentDir) {
+            let raw_path = raw_path.to_string_lossy();
+            linter.add(
+                UNSAFE_PATH,
+                Some(&raw_path),
+                "path would be extracted outside the output directory".to_string(),
+            );
+            continue;
+        }
+        let Some(path) = normalize_path(&raw_path) else {
+            continue;
+        };
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let special = match entry_type {
+            tar::EntryType::Block => Some("block device"),
+            tar::EntryType::Char => Some("character device"),
+            tar::EntryType::Fifo => Some("named pipe"),
+            _ => None,
+        };
+        if let Some(special) = special {
+            linter.add(DEVICE_NODE, Some(&path), format!("{special} in a package"));
+        }
+        let name = path.rsplit('/').next().unwrap_or(&path);
+
+        if !path.contains('/') && is_license_name(name) {
+            has_license = true;
+        }
+        if !entry_type.is_symlink() && header.mode()? & 0o002 != 0 {
+            linter.add(
+                WORLD_WRITABLE,
+                Some(&path),
+                format!("mode {:o} is writable by everyone", header.mode()? & 0o7777),
+            );
+        }
+        if entry_type.is_symlink() {
+            if let Some(target) = entry.link_name()? {
+                if target.has_root() {
+                    linter.add(
+                        ABSOLUTE_SYMLINK,
+                        Some(&path),
+                        format!("symlink points to absolute path {}", target.display()),
+                    );
+                }
+            }
+        }
+        if entry_type.is_file() && header.size()? > config.max_file_size {
+            linter.add(
+                LARGE_FILE,
+                Some(&path),
+                format!(
+                    "{} bytes exceeds the {} byte limit",
+                    header.size()?,
+                    config.max_file_size
+                ),
+            );
+        }
+        if is_junk_name(name) {
+            linter.add(
+                JUNK_FILE,
+                Some(&path),
+                format!("{name} should not be shipped"),
+            );
+        }
+
+        if !config.plugins.is_empty() {
+            entries.push(ManifestEntry {
+                kind: entry_kind(entry_type).to_string(),
+                size: header.size()?,
+                mode: header.mode()? & 0o7777,
+                link: entry
+                    .link_name()?
+                    .map(|target| target.to_string_lossy().into_owned()),
+                path,
+            });
+        }
+    }
+
+    if !has_license {
+        linter.add(
+            MISSING_LICENSE,
+            None,
+            "no LICENSE or COPYING file at the root and no license in metadata".to_string(),
+        );
+    }
+
+    if !config.plugins.is_empty() {
+        let manifest = LintManifest { metadata, entries };
+        for plugin in &config.plugins {
+            for finding in run_plugin(plugin, &manifest)? {
+                let severity = config
+                    .severities
+                    .get(&finding.rule)
+                    .copied()
+                    .or(finding.severity)
+                    .unwrap_or(Severity::Warning);
+                linter.push(&finding.rule, severity, finding.path, finding.message);
+            }
+        }
+    }
+
+    Ok(linter.report)
+}
+
+/// Internal helper: findings about the metadata itself
+fn lint_metadata(linter: &mut Linter, metadata: &Metadata) -> Result<()> {
+    let is_unset = |field: &Option<String>| field.as_deref().is_none_or(str::is_empty);
+    if is_unset(&metadata.name) {
+        linter.add(MISSING_NAME, None, "metadata has no name".to_string());
+    }
+    match metadata.ver.as_deref() {
+        None | Some("") => {
+            linter.add(MISSING_VERSION, None, "metadata has no version".to_string());
+        }
+        Some(ver) if !is_semver(ver) => {
+            linter.add(
+                INVALID_VERSION,
+                None,
+                format!("version {ver:?} is not a semantic version"),
+            );
+        }
+        Some(_) => {}
+    }
+
+    let missing: Vec<&str> = [
+        ("auth", &metadata.auth),
+        ("fmt", &metadata.fmt),
+        ("desc", &metadata.desc),
+    ]
+    .into_iter()
+    .filter(|(_, value)| is_unset(value))
+    .map(|(field, _)| field)
+    .collect();
+    if !missing.is_empty() {
+        linter.add(
+            INCOMPLETE_METADATA,
+            None,
+            format!("metadata has no {}", missing.join(", ")),
+        );
+    }
+
+    let extra_size = serde_json::to_vec(&metadata.extra)?.len() as u64;
+    if extra_size > linter.config.max_extra_size {
+        linter.add(
+            LARGE_EXTRA,
+            None,
+            format!(
+                "extra is {extra_size} bytes, over the {} byte limit",
+                linter.config.max_extra_size
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Internal helper: collects findings, dropping those of disabled rules
+struct Linter<'a> {
+    config: &'a LintConfig,
+    report: LintReport,
+}
+
+impl Linter<'_> {
+    /// Record a finding of a built-in rule
+    fn add(&mut self, rule: &str, path: Option<&str>, message: String) {
+        let severity = self.config.severity(rule);
+        self.push(rule, severity, path.map(str::to_string), message);
+    }
+
+    /// Record a finding at the given severity
+    fn push(&mut self, rule: &str, severity: Severity, path: Option<String>, message: String) {
+        if severity != Severity::Off {
+            self.report.findings.push(LintFinding {
+                rule: rule.to_string(),
+                severity,
+                path,
+                message,
+            });
+        }
+    }
+}
+
+/// Internal helper: entry type as named in the plugin manifest
+fn entry_kind(entry_type: tar::EntryType) -> &'static str {
+    if entry_type.is_file() {
+        "file"
+    } else if entry_type.is_dir() {
+        "dir"
+    } else if entry_type.is_symlink() {
+        "symlink"
+    } else if entry_type.is_hard_link() {
+        "hardlink"
+    } else {
+        "other"
+    }
+}
+
+/// Internal helper: whether the metadata names a license
+fn has_license_metadata(metadata: &Metadata) -> bool {
+    metadata
+        .license
+        .as_deref()
+        .is_some_and(|license| !license.is_empty())
+        || metadata
+            .extra
+            .get("license")
+            .and_then(|license| license.as_str())
+            .is_some_and(|license| !license.is_empty())
+}
+
+/// Internal helper: whether a root file name looks like a license (`LICENSE`, `COPYING.md`, ...)
+fn is_license_name(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    ["LICENSE", "LICENCE", "COPYING"]
+        .iter()
+        .any(|stem| upper.starts_with(stem))
+}
+
+/// Internal helper: whether a file name is an OS or editor leftover
+fn is_junk_name(name: &str) -> bool {
+    JUNK_NAMES.contains(&name)
+        || name.starts_with("._")
+        || name.ends_with('~')
+        || name.ends_with(".swp")
+}
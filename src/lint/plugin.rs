@@ -0,0 +1,196 @@
+//! External lint rule plugins
+//!
+//! A plugin receives the archive [`LintManifest`] as JSON and answers with a JSON array
+//! of findings, each `{"rule": ..., "message": ..., "path": ..., "severity": ...}` where
+//! `path` and `severity` are optional (severity defaults to `warning` and can be
+//! overridden in [`LintConfig::severities`](super::LintConfig)).
+//!
+//! - Command plugins read the manifest on stdin and print the findings on stdout; a
+//!   non-zero exit status fails the lint run.
+//! - WebAssembly plugins (feature `wasm`) export `memory`, `alloc(len: i32) -> i32` and
+//!   `lint(ptr: i32, len: i32) -> i64`. The manifest is written at the pointer returned
+//!   by `alloc`, and `lint` returns the findings location packed as `ptr << 32 | len`.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use super::Severity;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::Metadata;
+
+/// An external rule plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum LintPlugin {
+    /// Executable and its arguments
+    Command(Vec<String>),
+    /// Path to a WebAssembly module
+    Wasm(PathBuf),
+}
+
+impl LintPlugin {
+    /// Name of the plugin in error messages
+    fn name(&self) -> String {
+        match self {
+            LintPlugin::Command(argv) => argv.join(" "),
+            LintPlugin::Wasm(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// What plugins are shown of an archive: its metadata and every entry header
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct LintManifest {
+    /// Archive metadata
+    pub metadata: Metadata,
+    /// Entries in archive order
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// One archive entry as shown to plugins
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// `file`, `dir`, `symlink`, `hardlink` or `other`
+    pub kind: String,
+    /// Content size in bytes
+    pub size: u64,
+    /// Unix permission bits
+    pub mode: u32,
+    /// Link target of symlinks and hard links
+    pub link: Option<String>,
+}
+
+/// A finding as reported by a plugin
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct PluginFinding {
+    pub rule: String,
+    pub message: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub severity: Option<Severity>,
+}
+
+/// Run a plugin on a manifest and collect its findings
+pub(crate) fn run_plugin(
+    plugin: &LintPlugin,
+    manifest: &LintManifest,
+) -> Result<Vec<PluginFinding>> {
+    let input = serde_json::to_vec(manifest)?;
+    let output = match plugin {
+        LintPlugin::Command(argv) => run_command(plugin, argv, input)?,
+        LintPlugin::Wasm(path) => run_wasm(plugin, path, &input)?,
+    };
+    serde_json::from_slice(&output)
+        .map_err(|e| plugin_error(plugin, format!("invalid findings: {e}")))
+}
+
+/// Internal helper: run a command plugin, feeding the manifest on stdin
+fn run_command(plugin: &LintPlugin, argv: &[String], input: Vec<u8>) -> Result<Vec<u8>> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| plugin_error(plugin, "empty command".to_string()))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| plugin_error(plugin, e.to_string()))?;
+
+    // Feed stdin from another thread so a plugin writing before it reads cannot deadlock
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let feeder = thread::spawn(move || {
+        // A plugin may exit without reading everything; its status tells what happened
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output()?;
+    feeder.join().expect("plugin stdin writer panicked");
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => format!("{}: {stderr}", output.status),
+        };
+        return Err(plugin_error(plugin, message));
+    }
+    Ok(output.stdout)
+}
+
+/// Instructions a WebAssembly plugin may execute before it is stopped
+#[cfg(feature = "wasm")]
+const WASM_FUEL: u64 = 10_000_000_000;
+
+/// Internal helper: run a WebAssembly plugin in a sandbox with no imports
+#[cfg(feature = "wasm")]
+fn run_wasm(plugin: &LintPlugin, path: &std::path::Path, input: &[u8]) -> Result<Vec<u8>> {
+    let fail = |e: &dyn std::fmt::Display| plugin_error(plugin, e.to_string());
+
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    let engine = wasmi::Engine::new(&config);
+    let module = wasmi::Module::new(&engine, &std::fs::read(path)?).map_err(|e| fail(&e))?;
+    let mut store = wasmi::Store::new(&engine, ());
+    store.set_fuel(WASM_FUEL).map_err(|e| fail(&e))?;
+
+    let instance = wasmi::Linker::<()>::new(&engine)
+        .instantiate(&mut store, &module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(|e| fail(&e))?;
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or_else(|| plugin_error(plugin, "missing `memory` export".to_string()))?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&store, "alloc")
+        .map_err(|e| fail(&e))?;
+    let lint = instance
+        .get_typed_func::<(i32, i32), i64>(&store, "lint")
+        .map_err(|e| fail(&e))?;
+
+    let len = i32::try_from(input.len()).map_err(|e| fail(&e))?;
+    let ptr = alloc.call(&mut store, len).map_err(|e| fail(&e))?;
+    memory
+        .write(&mut store, ptr as u32 as usize, input)
+        .map_err(|e| fail(&e))?;
+    let packed = lint.call(&mut store, (ptr, len)).map_err(|e| fail(&e))?;
+
+    // The location comes from the plugin, so it is checked before anything is copied
+    let (ptr, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+    memory
+        .data(&store)
+        .get(ptr..ptr + len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| {
+            plugin_error(
+                plugin,
+                format!("findings at {ptr}..{} are outside its memory", ptr + len),
+            )
+        })
+}
+
+/// Internal helper: WebAssembly plugins need the `wasm` feature
+#[cfg(not(feature = "wasm"))]
+fn run_wasm(plugin: &LintPlugin, _path: &std::path::Path, _input: &[u8]) -> Result<Vec<u8>> {
+    Err(plugin_error(
+        plugin,
+        "built without the `wasm` feature".to_string(),
+    ))
+}
+
+/// Internal helper: describe a failed plugin run
+fn plugin_error(plugin: &LintPlugin, message: String) -> ProjzstError {
+    ProjzstError::LintPluginFailed {
+        plugin: plugin.name(),
+        message,
+    }
+}
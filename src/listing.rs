@@ -0,0 +1,99 @@
+//! Entry listing without extraction
+//!
+//! [`list_entries`] reads the tar headers of the payload, skipping over the content, and
+//! returns one [`ArchiveEntry`] per entry in archive order. Browsers and selective
+//! extraction (see [`ReadOptions::transform`](crate::ReadOptions::transform)) start here.
+
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::open_payload_only;
+use crate::errors::Result;
+use crate::storage::normalize_path;
+use crate::volume::open_input;
+
+/// Type of an archive entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum EntryKind {
+    /// Regular file
+    File,
+    /// Directory
+    Directory,
+    /// Symbolic link
+    Symlink,
+    /// Hard link to an earlier entry
+    HardLink,
+    /// Device node, FIFO or anything else
+    Other,
+}
+
+/// Path, type and size of one archive entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ArchiveEntry {
+    /// Entry path relative to the archive root, without a trailing `/`
+    pub path: String,
+    /// Type of the entry
+    pub kind: EntryKind,
+    /// Uncompressed size in bytes, `0` for anything but files
+    pub size: u64,
+    /// Permission bits, e.g. `0o644`
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: u64,
+    /// Target of a symbolic or hard link
+    pub link: Option<String>,
+}
+
+impl ArchiveEntry {
+    /// Internal helper: describe a tar entry, `None` for paths that normalize to nothing
+    pub(crate) fn of<R: Read>(entry: &tar::Entry<'_, R>) -> Result<Option<Self>> {
+        let Some(path) = normalize_path(&entry.path()?) else {
+            return Ok(None);
+        };
+        let entry_type = entry.header().entry_type();
+        let kind = if entry_type.is_file() {
+            EntryKind::File
+        } else if entry_type.is_dir() {
+            EntryKind::Directory
+        } else if entry_type.is_symlink() {
+            EntryKind::Symlink
+        } else if entry_type.is_hard_link() {
+            EntryKind::HardLink
+        } else {
+            EntryKind::Other
+        };
+        let link = entry
+            .link_name()?
+            .map(|link| link.to_string_lossy().into_owned());
+        Ok(Some(Self {
+            path,
+            kind,
+            size: if kind == EntryKind::File {
+                entry.size()
+            } else {
+                0
+            },
+            mode: entry.header().mode()? & 0o7777,
+            mtime: entry.header().mtime()?,
+            link,
+        }))
+    }
+}
+
+/// List the entries of a .pjz file in archive order, without extracting anything
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn list_entries<P: AsRef<Path>>(input_file: P) -> Result<Vec<ArchiveEntry>> {
+    let mut tar_archive = open_payload_only(open_input(input_file.as_ref())?)?;
+    let mut entries = Vec::new();
+    for entry in tar_archive.entries()? {
+        entries.extend(ArchiveEntry::of(&entry?)?);
+    }
+    Ok(entries)
+}
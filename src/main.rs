@@ -1,9 +1,50 @@
 //! Command-line interface for projzst tool
 
-use clap::{Parser, Subcommand};
-use projzst::{info, pack, unpack, IgnoreUnknown, Metadata, ProjzstError, DEFAULT_ZSTD_LEVEL};
-use std::path::PathBuf;
-use std::process::ExitCode;
+use clap::{CommandFactory, Parser, Subcommand};
+use projzst::{
+    add_attachment, append_metadata, attachment, attachments, auto_file_name, bench, build_catalog,
+    bump_version, bundle_members, catalog_dictionaries, checksum_path, comment, diff,
+    diff_archive_metadata, disk_usage, export_archive, extract_member, frames, grep, icon,
+    import_archive, info, lint, list_entries, merge, metadata_frames, metadata_history, migrate,
+    pack_set, parse_extra_pair, parse_metadata_magic, parse_size, preview_entry, provenance, prune,
+    read_metadata, read_metadata_revision, remove_attachment, repair, sbom, search, set_comment,
+    set_icon, sidecar_path, sign_archive, sign_archive_with_key, signature, signed_digest, stat,
+    strip_foreign_frames, sync, sync_from_reader, timestamp, train_catalog_dictionary,
+    unpack_incremental, validate, verify_checksum_file, verify_naming, verify_signature,
+    verify_timestamp, write_checksum_file, write_entry, ArchiveFormat, ArchiveLayout, Attachment,
+    ChecksumAlgorithm, CompatProfile, CompressionProfile, Concurrency, ConflictPolicy,
+    EntryCompression, EntryKind, EntryOrder, ExtraFormat, ExtraSchema, FrameKind, GrepQuery, Icon,
+    IgnoreUnknown, LintConfig, LintPlugin, LintReport, Metadata, MetadataBuilder, MetadataEncoding,
+    MetadataFormat, MetadataPlacement, MetadataPolicy, MetadataQuery, MtimePolicy, OversizePolicy,
+    PackReport, Packer, PathTransform, PayloadCodec, ProjzstError, Provenance, ReadOptions,
+    RetentionPolicy, SbomFormat, SchemaRegistry, SecretPolicy, SetMember, Severity, SpecialPolicy,
+    SyncReport, TarFormat, TemplateVars, Timestamp, VersionPart, WindowsNamePolicy,
+    DEFAULT_DICTIONARY_SIZE, DEFAULT_ZSTD_LEVEL,
+};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, ExitStatus};
+use std::time::Duration;
+
+#[cfg(feature = "tui")]
+mod browse;
+
+mod config;
+
+mod confine;
+
+mod i18n;
+
+mod keys;
+
+mod wizard;
+
+use config::UserConfig;
+use i18n::tr;
+use keys::KeyStore;
 
 #[derive(Parser)]
 #[command(name = "projzst")]
@@ -11,21 +52,59 @@ use std::process::ExitCode;
 #[command(long_about = "A tool for creating and extracting .pjz archives \
     with MessagePack metadata and zstd compression")]
 struct Cli {
+    /// Threads for compression, hashing and S3 uploads (default: multithreaded hashing
+    /// and uploads, single-threaded compression)
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Print the result as one JSON object on stdout (stderr when stdout carries archive
+    /// or file data), errors and warnings included, instead of human-readable text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Log each entry packed or unpacked with its size; twice (`-vv`) adds directories,
+    /// links, exclusions and skipped entries
+    // Not global: `pack -v` is the project version
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Print errors only, no status lines or warnings
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Read defaults from this file instead of `~/.config/projzst/config.toml`
+    // Not global: `lint --config` is the lint configuration
+    #[arg(long, value_name = "FILE", env = "PROJZST_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Language of status lines, warnings and errors, e.g. `zh` (default: from `LC_ALL`,
+    /// `LC_MESSAGES` or `LANG`; always English with `--json`)
+    #[arg(long, global = true, value_name = "LANG", env = "PROJZST_LANG")]
+    lang: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the `Pack` variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Pack a directory into a .pjz file with metadata
     Pack {
-        /// Source directory to pack
-        #[arg(short, long)]
-        input: PathBuf,
+        /// Source directory to pack (or `s3://bucket/prefix/` to read objects); repeat as
+        /// `DIR:PREFIX` to pack several directories, each under its own prefix
+        #[arg(short, long, required_unless_present = "files_from")]
+        input: Vec<PathBuf>,
+
+        /// Pack the files and directories listed in this file, one per line, instead of
+        /// `--input`; a `SRC=DEST` line stores SRC under DEST
+        #[arg(long, value_name = "LIST", conflicts_with = "input")]
+        files_from: Option<PathBuf>,
 
         /// Package name
-        #[arg(short, long)]
-        name: String,
+        #[arg(short, long, required_unless_present_any = ["from_manifest", "interactive"])]
+        name: Option<String>,
 
         /// Author name
         #[arg(short, long)]
@@ -47,119 +126,4071 @@ enum Commands {
         #[arg(short, long)]
         desc: Option<String>,
 
-        /// Path to extra metadata JSON file
+        /// License as an SPDX expression, e.g. `MIT OR Apache-2.0`
+        #[arg(long, value_name = "SPDX")]
+        license: Option<String>,
+
+        /// Project homepage URL
+        #[arg(long, value_name = "URL")]
+        homepage: Option<String>,
+
+        /// Source repository URL
+        #[arg(long, value_name = "URL")]
+        repository: Option<String>,
+
+        /// Keyword for search and classification (repeatable)
+        #[arg(long = "keyword", value_name = "KEYWORD")]
+        keywords: Vec<String>,
+
+        /// Dependency as NAME@REQ, optionally pinned with #sha256:HEX (repeatable)
+        #[arg(long = "dep", value_name = "NAME@REQ")]
+        deps: Vec<String>,
+
+        /// Cargo.toml or package.json to take unset metadata fields from
+        #[arg(long, value_name = "MANIFEST")]
+        from_manifest: Option<PathBuf>,
+
+        /// Path to extra metadata JSON, TOML or YAML file (`-` for stdin)
         #[arg(short = 'x', long)]
         extra: Option<PathBuf>,
 
-        /// Zstd compression level (1-22)
-        #[arg(short, long, default_value_t = DEFAULT_ZSTD_LEVEL)]
-        level: i32,
+        /// Syntax of the extra metadata: json, toml or yaml (default: by file extension)
+        #[arg(long, value_name = "FORMAT", conflicts_with = "extra_json")]
+        extra_format: Option<String>,
 
-        /// Output .pjz file path
+        /// Environment variable holding extra metadata JSON
+        #[arg(long, value_name = "VAR", conflicts_with = "extra")]
+        extra_env: Option<String>,
+
+        /// Extra metadata given inline as a JSON object, e.g. `'{"build": 42}'`
+        #[arg(long, value_name = "JSON", conflicts_with_all = ["extra", "extra_env"])]
+        extra_json: Option<String>,
+
+        /// Extra metadata `key=value` pair merged over the extra file (repeatable)
+        /// Dotted keys nest (`build.commit=abc`); values are typed when valid JSON
+        #[arg(long = "extra-kv", visible_alias = "set", value_name = "KEY=VALUE")]
+        extra_kv: Vec<String>,
+
+        /// Accept an extra file holding an array or scalar instead of an object
+        #[arg(long)]
+        allow_non_object_extra: bool,
+
+        /// Previous .pjz to pack incrementally against (only changed files are stored)
+        #[arg(long, value_name = "ARCHIVE")]
+        base: Option<PathBuf>,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed); 20-22 take up to about 1 GiB of memory per compression thread
+        /// [default: `PROJZST_LEVEL`, else `level` of the configuration file, else 6]
         #[arg(short, long)]
-        output: PathBuf,
+        level: Option<i32>,
+
+        /// Payload codec: zstd, xz, lz4 or brotli (the last three need the feature of the
+        /// same name)
+        #[arg(long, value_name = "CODEC", default_value_t = String::from("zstd"))]
+        codec: String,
+
+        /// Compress with the dictionary trained for this catalog (`index --train-dictionary`);
+        /// unpacking then needs `unpack --catalog` with the same catalog
+        #[arg(long, value_name = "FILE")]
+        catalog: Option<PathBuf>,
+
+        /// Tuned level, window and thread count: fast, balanced, max or archive
+        /// (`--threads` still sets the thread count)
+        #[arg(long, value_name = "PRESET", conflicts_with = "level")]
+        preset: Option<String>,
+
+        /// Split the output into volumes of this size (`out.pjz.001`, ...), e.g. `100M`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        split_size: Option<u64>,
+
+        /// Order of the stored entries: path, extension or size
+        #[arg(long, value_name = "ORDER", default_value_t = String::from("path"))]
+        order: String,
+
+        /// Tar header format: gnu, pax or ustar
+        #[arg(long, value_name = "FORMAT", default_value_t = String::from("gnu"))]
+        tar_format: String,
+
+        /// Store each path of a hard-linked file with its own copy instead of as a link
+        #[arg(long)]
+        hard_dereference: bool,
+
+        /// Store modification times to the nanosecond in PAX records (not with ustar)
+        #[arg(long)]
+        subsecond_mtime: bool,
+
+        /// Print original and compressed size, ratio and the size taken by each extension
+        #[arg(long)]
+        stats: bool,
+
+        /// Store already-compressed files (media, archives) as-is, compressing the rest
+        #[arg(long)]
+        adaptive: bool,
+
+        /// Compress every file in its own frame, indexed for fast single-entry reads
+        #[arg(long)]
+        per_entry_frames: bool,
+
+        /// End every compressed frame with a checksum of its content, checked on unpack
+        /// and verify
+        #[arg(long)]
+        checksum: bool,
+
+        /// Skippable frame magic holding the metadata, for files shared with tools using
+        /// 0x184D2A50 themselves (0x184D2A52, 0x184D2A53 or 0x184D2A58-0x184D2A5E)
+        #[arg(long, value_name = "MAGIC", value_parser = parse_metadata_magic)]
+        metadata_magic: Option<u32>,
+
+        /// Drop the vendor and other foreign frames of the archive being replaced instead
+        /// of carrying them over
+        #[arg(long)]
+        strip_foreign_frames: bool,
+
+        /// Split files into content-defined chunks and store each distinct chunk once
+        /// (much smaller archives of trees with duplicated files; needs projzst to unpack)
+        #[arg(long)]
+        chunked: bool,
+
+        /// Record progress in this file so an interrupted pack resumes where it stopped
+        /// when run again (implies --per-entry-frames)
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<PathBuf>,
+
+        /// Keep running and pack again whenever the sources change
+        #[arg(long, conflicts_with = "files_from")]
+        watch: bool,
+
+        /// Walk the sources and estimate the archive size from a sample, without writing
+        /// anything
+        #[arg(long, conflicts_with_all = ["watch", "checkpoint"])]
+        dry_run: bool,
+
+        /// Ask for the metadata fields and output file not given, defaulting to the
+        /// directory name and the git user, and show the files before writing
+        #[arg(long, conflicts_with_all = ["watch", "dry_run"])]
+        interactive: bool,
+
+        /// With --watch, milliseconds without changes before packing again
+        #[arg(long, value_name = "MS", default_value_t = 500, requires = "watch")]
+        debounce: u64,
+
+        /// Zstd-compress the metadata frame too (for large extra metadata)
+        #[arg(long)]
+        compress_metadata: bool,
+
+        /// Start the archive with a shell script unpacking it (`sh app.pjz DIR`) where
+        /// projzst is not installed, using zstd and tar
+        #[arg(long)]
+        self_extracting: bool,
+
+        /// Sign the archive with an external command, e.g. `cosign sign-blob --key k.key -`:
+        /// it reads the archive digest on stdin and prints the signature stored in the
+        /// signature frame
+        #[arg(long, value_name = "CMD", conflicts_with = "dry_run")]
+        sign_cmd: Option<String>,
+
+        /// Sign the archive with a key pair made by `projzst key generate`
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["dry_run", "sign_cmd"])]
+        sign_key: Option<String>,
+
+        /// Get an RFC 3161 timestamp for the archive from this time-stamping authority,
+        /// e.g. `https://freetsa.org/tsr` (needs the `http` feature)
+        #[arg(long, value_name = "URL", conflicts_with = "dry_run")]
+        timestamp_url: Option<String>,
+
+        /// Also write a checksum file next to the archive (`app.pjz.sha256`), in the
+        /// format `sha256sum -c` reads: sha256 or sha512
+        #[arg(long, value_name = "ALGORITHM", conflicts_with = "dry_run")]
+        emit_checksum: Option<String>,
+
+        /// Record the host name, OS, projzst version, time and git commit in a
+        /// provenance frame (off by default so builds stay reproducible)
+        #[arg(long)]
+        provenance: bool,
+
+        /// Pad the frames so the payload starts at a multiple of this size, e.g. `4K`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        align: Option<u64>,
+
+        /// Metadata serialization: msgpack, cbor or json
+        #[arg(long, value_name = "ENCODING", default_value_t = String::from("msgpack"))]
+        metadata_encoding: String,
+
+        /// Where the metadata goes: header (start of the file), both (and a trailer copy
+        /// after the payload) or trailer (only after the payload)
+        #[arg(long, value_name = "PLACEMENT", default_value_t = String::from("header"))]
+        metadata_placement: String,
+
+        /// Store every entry under this top-level directory, e.g. `myapp-1.2.0/`
+        #[arg(long, value_name = "DIR")]
+        prefix: Option<String>,
+
+        /// Do not descend into directories on other file systems (mount points)
+        #[arg(long)]
+        one_file_system: bool,
+
+        /// Store entries at most this many levels below each input directory
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+
+        /// Leave out version control directories (.git, .hg, .svn, ...)
+        #[arg(long)]
+        exclude_vcs: bool,
+
+        /// Leave out files and directories whose name starts with a dot
+        #[arg(long)]
+        exclude_hidden: bool,
+
+        /// Leave out files and directories whose name matches this glob (`*`, `?`), e.g.
+        /// `*.tmp`; repeat for several patterns
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Leave out files larger than this size, e.g. `100M`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_file_size: Option<u64>,
+
+        /// What to do with files over --max-file-size: skip or error
+        #[arg(long, value_name = "POLICY", default_value_t = String::from("skip"))]
+        on_oversize: String,
+
+        /// What to do with device nodes, FIFOs and sockets: skip, include or error
+        #[arg(long, value_name = "POLICY", default_value_t = String::from("skip"))]
+        special: String,
+
+        /// Check files for credentials (AWS keys, GitHub tokens, private keys, .env
+        /// files) before packing: off, warn or error
+        #[arg(long, value_name = "POLICY", default_value_t = String::from("off"))]
+        scan_secrets: String,
+
+        /// Flush the archive and its directory to disk before exiting
+        #[arg(long)]
+        sync: bool,
+
+        /// Write buffer in front of the archive, e.g. 1M for network file systems
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        buffer_size: Option<u64>,
+
+        /// Replace the output file if it already exists
+        #[arg(short = 'F', long)]
+        force: bool,
+
+        /// Reject a name unsafe in file names or a version that is not semver
+        #[arg(long)]
+        validate: bool,
+
+        /// Expand ${date}, ${GIT_SHA}, ${env:NAME}, ... in metadata and extra values
+        #[arg(long)]
+        expand: bool,
+
+        /// Template variable as NAME=VALUE, implies --expand (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        vars: Vec<String>,
+
+        /// JSON Schema the extra metadata must match
+        #[arg(long, value_name = "SCHEMA_JSON")]
+        schema: Option<PathBuf>,
+
+        /// Stay readable by an older projzst release (`1.0`); unsupported options are
+        /// dropped with a warning
+        #[arg(long, value_name = "VERSION")]
+        compat: Option<String>,
+
+        /// Output .pjz file path (`-` for stdout)
+        #[arg(short, long, required_unless_present_any = ["auto_output", "interactive"])]
+        output: Option<PathBuf>,
+
+        /// Write `<name>-<ver>.pjz` into this directory (default: current directory)
+        #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = ".", conflicts_with = "output")]
+        auto_output: Option<PathBuf>,
     },
 
     /// Unpack a .pjz file to a directory
     Unpack {
-        /// Input .pjz file path
+        /// Input .pjz file path (`-` for stdin)
         input: PathBuf,
 
-        /// Output directory path
+        /// Output directory path (or `s3://bucket/prefix/` to write objects)
         output: PathBuf,
 
+        /// Base .pjz an incremental archive was packed against
+        #[arg(long, value_name = "ARCHIVE")]
+        base: Option<PathBuf>,
+
+        /// Chunk store a `pack-set` member was packed against
+        #[arg(long, value_name = "ARCHIVE", conflicts_with = "base")]
+        chunk_store: Option<PathBuf>,
+
+        /// Accept a plain .tar.zst without metadata (local output only)
+        #[arg(long)]
+        allow_missing_metadata: bool,
+
+        /// Skippable frame magic the metadata was packed with (`pack --metadata-magic`)
+        #[arg(long, value_name = "MAGIC", value_parser = parse_metadata_magic)]
+        metadata_magic: Option<u32>,
+
+        /// Catalog whose dictionaries the archive may be compressed with (`pack --catalog`)
+        #[arg(long, value_name = "FILE")]
+        catalog: Option<PathBuf>,
+
+        /// Read written files back and compare them with the archive (local output only)
+        #[arg(long, conflicts_with = "base")]
+        post_verify: bool,
+
+        /// JSON Schema the extra metadata must match before anything is extracted
+        /// (local output only)
+        #[arg(long, value_name = "SCHEMA_JSON", conflicts_with = "base")]
+        schema: Option<PathBuf>,
+
+        /// Metadata field that must be set, e.g. `ver` or `extra.build.commit`
+        /// (repeatable, local output only)
+        #[arg(long, value_name = "FIELD", conflicts_with = "base")]
+        require: Vec<String>,
+
+        /// Metadata field that must have this exact value, e.g. `fmt=game`
+        /// (repeatable, local output only)
+        #[arg(long, value_name = "FIELD=VALUE", conflicts_with = "base")]
+        expect: Vec<String>,
+
+        /// Drop this many leading components from entry paths (local output only)
+        #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "base")]
+        strip_components: usize,
+
+        /// Rewrite entry paths with a sed-style `s#PATTERN#REPLACEMENT#[g]`; the pattern
+        /// is literal text, optionally anchored with `^`/`$` (repeatable, local output only)
+        #[arg(long, value_name = "EXPR", conflicts_with = "base")]
+        transform: Vec<String>,
+
+        /// What to do with device nodes and FIFOs: skip, include or error (local output only)
+        #[arg(long, value_name = "POLICY", default_value_t = String::from("skip"))]
+        special: String,
+
+        /// What to do with stored modification times: restore, clamp (future times to
+        /// now) or skip (local output only)
+        #[arg(long, value_name = "POLICY", default_value_t = String::from("restore"), conflicts_with = "base")]
+        mtime: String,
+
+        /// What to do with names Windows cannot create (`CON`, trailing dots, `?`...):
+        /// keep, sanitize or reject (default: sanitize on Windows, keep elsewhere)
+        #[arg(long, value_name = "POLICY", conflicts_with = "base")]
+        windows_names: Option<String>,
+
+        /// Fail before extracting when entries collide on case-insensitive or Unicode
+        /// normalizing file systems, e.g. `README` and `readme` (local input only; on macOS
+        /// and Windows such entries are otherwise reported as warnings)
+        #[arg(long, conflicts_with = "base")]
+        reject_collisions: bool,
+
+        /// Extract even when the output file system looks too small for the archive; by
+        /// default a local input is refused before anything is written when it will not fit
+        #[arg(long, conflicts_with = "base")]
+        no_space_check: bool,
+
+        /// Skip entries that cannot be extracted, with a warning each, and extract the rest;
+        /// the exit status still reports the failures (local output only)
+        #[arg(long, conflicts_with = "base")]
+        keep_going: bool,
+
+        /// Also unpack the .pjz files found in the output, in place (`name.pjz` becomes
+        /// `name/`), and those found in them, down to DEPTH levels [default: 8]
+        #[arg(
+            long,
+            value_name = "DEPTH",
+            num_args = 0..=1,
+            default_missing_value = "8",
+            conflicts_with = "base"
+        )]
+        recursive: Option<usize>,
+
+        /// Largest total size unpacked from nested archives, e.g. 10G [default: 4G]
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "recursive")]
+        nested_size_limit: Option<u64>,
+
+        /// Flush every extracted file to disk before exiting
+        #[arg(long)]
+        sync: bool,
+
+        /// Read buffer feeding the decompressor, e.g. 1M for network file systems
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        buffer_size: Option<u64>,
+
+        /// Write small files in batches through io_uring (needs the `io-uring` feature,
+        /// Linux only; local output only)
+        #[arg(long, conflicts_with = "base")]
+        io_uring: bool,
+
+        /// Create every entry through a handle on the output directory, so that no entry
+        /// path or symlink can lead a write outside of it (needs the `sandbox` feature;
+        /// local output only)
+        #[arg(long, conflicts_with_all = ["base", "post_verify", "io_uring"])]
+        sandbox: bool,
+
+        /// Before decoding anything, confine the process to reading the input's directory
+        /// and writing the output: Landlock and seccomp on Linux, unveil and pledge on
+        /// OpenBSD (local output only)
+        #[arg(long, conflicts_with_all = ["base", "io_uring"])]
+        confine: bool,
+
         /// Ignored Unknown Values or not
         #[arg(short, long, default_value_t = String::from("1"))]
         ignored: String,
     },
 
-    /// Extract metadata info from a .pjz file to JSON
+    /// Update a directory to match a .pjz file, writing only the entries that differ
+    Sync {
+        /// Input .pjz file path (`-` for stdin, or an `http(s)://` URL)
+        input: PathBuf,
+
+        /// Directory to update
+        output: PathBuf,
+
+        /// Also remove files and directories the archive does not have
+        #[arg(long)]
+        delete: bool,
+    },
+
+    /// Show the metadata of a .pjz file, or write it as JSON, YAML, TOML or MessagePack
     Info {
-        /// Input .pjz file path
+        /// Input .pjz file path (`-` for stdin, or an `http(s)://` URL)
+        input: PathBuf,
+
+        /// Output file path (`-` for stdout); without it the metadata is printed as text,
+        /// or under `metadata` with `--json`
+        output: Option<PathBuf>,
+
+        /// Print only this field, e.g. `.extra.build.commit` (strings unquoted, nothing
+        /// for an unset field)
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["output", "format"])]
+        query: Option<String>,
+
+        /// Write the metadata as json, yaml, toml or msgpack (default: by output file
+        /// extension, else json); without an output file, print it to stdout
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<String>,
+
+        /// Accept a plain .tar.zst without metadata (not for URLs)
+        #[arg(long)]
+        allow_missing_metadata: bool,
+
+        /// Skippable frame magic the metadata was packed with (not for URLs)
+        #[arg(long, value_name = "MAGIC", value_parser = parse_metadata_magic)]
+        metadata_magic: Option<u32>,
+
+        /// Ignored Unknown Values or not
+        #[arg(short, long, default_value_t = String::from("1"))]
+        ignored: String,
+    },
+
+    /// Show entries added, removed or modified between two .pjz files
+    Diff {
+        /// Baseline .pjz file path
+        old: PathBuf,
+
+        /// .pjz file path to compare against the baseline
+        new: PathBuf,
+    },
+
+    /// Convert a .pjz file to .tar/.tar.zst/.tar.gz/.zip, or wrap one of those into a .pjz
+    Convert {
+        /// Input file path (format taken from the extension)
         input: PathBuf,
 
-        /// Output JSON file path
+        /// Output file path (format taken from the extension)
         output: PathBuf,
 
+        /// When exporting, write the metadata to `<output>.metadata.json`
+        #[arg(long)]
+        sidecar: bool,
+
+        /// When importing, metadata JSON to embed (defaults to `<input>.metadata.json` if present)
+        #[arg(short, long, value_name = "FILE")]
+        metadata: Option<PathBuf>,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed); 20-22 take up to about 1 GiB of memory per compression thread
+        /// [default: `PROJZST_LEVEL`, else `level` of the configuration file, else 6]
+        #[arg(short, long)]
+        level: Option<i32>,
+
         /// Ignored Unknown Values or not
         #[arg(short, long, default_value_t = String::from("1"))]
         ignored: String,
     },
-}
 
-fn run() -> Result<(), ProjzstError> {
-    let cli = Cli::parse();
+    /// Check that a .pjz file decodes and follows the given conventions
+    Verify {
+        /// Input .pjz file path
+        input: PathBuf,
 
-    match cli.command {
-        Commands::Pack {
-            input,
-            name,
-            auth,
-            fmt,
-            ed,
-            ver,
-            desc,
-            extra,
-            level,
-            output,
-        } => {
-            let metadata = Metadata::new(name, auth, fmt, ed, ver, desc);
-            pack(&input, &output, metadata, extra.as_ref(), level)?;
-            println!("Successfully packed: {}", output.display());
-        }
+        /// Expected file name built from metadata, e.g. `{name}-{ver}.pjz`
+        #[arg(long, value_name = "TEMPLATE")]
+        naming: Option<String>,
 
-        Commands::Unpack {
-            input,
-            output,
-            ignored,
-        } => {
-            let metadata = unpack(&input, &output, IgnoreUnknown::from_str_tmp(ignored)?)?;
-            println!("Successfully unpacked: {}", output.display());
-            println!(
-                "Package: {} v{}",
-                metadata.name.unwrap_or_default(),
-                metadata.ver.unwrap_or_default()
-            );
-        }
+        /// External rule command receiving the manifest JSON on stdin (repeatable)
+        #[arg(long, value_name = "CMD")]
+        rule_cmd: Vec<String>,
 
-        Commands::Info {
-            input,
-            output,
-            ignored,
-        } => {
-            let metadata = info(&input, &output, IgnoreUnknown::from_str_tmp(ignored)?)?;
-            println!("Metadata saved to: {}", output.display());
-            println!("---");
-            if let Some(name) = metadata.name {
-                println!("Name: {}", name);
-            }
-            if let Some(author) = metadata.auth {
-                println!("Author: {}", author);
-            }
-            if let Some(version) = metadata.ver {
-                println!("Version: {}", version);
-            }
-            if let Some(format) = metadata.fmt {
-                match metadata.ed {
-                    Some(edition) => println!("Format: {} ({})", format, edition),
-                    None => println!("Format: {}", format),
-                }
-            }
-            if let Some(description) = metadata.desc {
-                println!("Description: {}", description);
-            }
-        }
-    }
+        /// WebAssembly rule module (repeatable; needs the `wasm` feature)
+        #[arg(long, value_name = "FILE")]
+        rule_wasm: Vec<PathBuf>,
 
-    Ok(())
-}
+        /// Check the archive against a `sha256sum`-style checksum file [default:
+        /// `<INPUT>.sha256`, else `<INPUT>.sha512`]
+        #[arg(long, value_name = "FILE", num_args = 0..=1)]
+        checksum_file: Option<Option<PathBuf>>,
 
-fn main() -> ExitCode {
-    match run() {
-        Ok(()) => ExitCode::SUCCESS,
-        Err(e) => {
-            eprintln!("Error: {e}");
-            ExitCode::FAILURE
-        }
+        /// Check the ed25519 signature against the keys of `projzst key` (trusted keys and
+        /// the user's own)
+        #[arg(long)]
+        check_signature: bool,
+
+        /// Check that the RFC 3161 timestamp covers the archive and print its time
+        #[arg(long)]
+        check_timestamp: bool,
+
+        /// Ignored Unknown Values or not
+        #[arg(short, long, default_value_t = String::from("1"))]
+        ignored: String,
+    },
+
+    /// Salvage a truncated or corrupt .pjz file: its metadata and every entry before the
+    /// damage
+    Repair {
+        /// Damaged .pjz file path
+        input: PathBuf,
+
+        /// Repaired .pjz file path
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Check a .pjz file against packaging best practices
+    Lint {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// JSON lint configuration (`max_file_size`, `max_extra_size`, `severities` by rule id)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Largest single file accepted, e.g. `50M` (overrides the configuration)
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_file_size: Option<u64>,
+
+        /// Largest serialized extra metadata accepted, e.g. `16K` (overrides the configuration)
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_extra_size: Option<u64>,
+
+        /// External rule command receiving the manifest JSON on stdin (repeatable)
+        #[arg(long, value_name = "CMD")]
+        rule_cmd: Vec<String>,
+
+        /// WebAssembly rule module (repeatable; needs the `wasm` feature)
+        #[arg(long, value_name = "FILE")]
+        rule_wasm: Vec<PathBuf>,
+    },
+
+    /// Rewrite a legacy (length-prefixed) archive into the current frame layout
+    Migrate {
+        /// Legacy archive path
+        input: PathBuf,
+
+        /// Output .pjz file path (may be the input to migrate in place)
+        output: PathBuf,
+    },
+
+    /// Merge several .pjz files into one, later inputs layered over earlier ones
+    Merge {
+        /// Input .pjz file paths, lowest layer first
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+
+        /// Output .pjz file path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Handling of files present in several inputs: error, prefer-last or prefix-per-source
+        #[arg(short, long, default_value_t = String::from("error"))]
+        conflict: String,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed); 20-22 take up to about 1 GiB of memory per compression thread
+        /// [default: `PROJZST_LEVEL`, else `level` of the configuration file, else 6]
+        #[arg(short, long)]
+        level: Option<i32>,
+
+        /// Drop the vendor and other foreign frames of the inputs instead of carrying
+        /// them over
+        #[arg(long)]
+        strip_foreign_frames: bool,
+    },
+
+    /// Recompress an archive with another level, codec or dictionary, keeping its entries,
+    /// metadata and attached frames (signatures and timestamps have to be made again)
+    Repack {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Output .pjz file path; the input itself with `--force`
+        output: PathBuf,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed); 20-22 take up to about 1 GiB of memory per compression thread
+        /// [default: `PROJZST_LEVEL`, else `level` of the configuration file, else 6]
+        #[arg(short, long)]
+        level: Option<i32>,
+
+        /// Payload codec: zstd, xz, lz4 or brotli (the last three need the feature of the
+        /// same name)
+        #[arg(long, value_name = "CODEC", default_value_t = String::from("zstd"))]
+        codec: String,
+
+        /// Catalog whose dictionaries the input may be compressed with; the output is
+        /// compressed with its latest one (`index --train-dictionary`)
+        #[arg(long, value_name = "FILE")]
+        catalog: Option<PathBuf>,
+
+        /// Replace the output file if it already exists
+        #[arg(short = 'F', long)]
+        force: bool,
+    },
+
+    /// Rewrite an archive with its entries re-sorted so they compress better, e.g. one
+    /// packed quickly in CI, and report the bytes saved
+    Optimize {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Output .pjz file path (default: rewrite the input in place)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Order of the entries: extension, size or path
+        #[arg(long, default_value_t = String::from("extension"))]
+        order: String,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed); 20-22 take up to about 1 GiB of memory per compression thread
+        /// [default: `PROJZST_LEVEL`, else `level` of the configuration file, else 6]
+        #[arg(short, long)]
+        level: Option<i32>,
+
+        /// Replace the output file if it already exists
+        #[arg(short = 'F', long, requires = "output")]
+        force: bool,
+    },
+
+    /// Pack several variants of a tree into archives sharing one chunk store
+    PackSet {
+        /// Members to pack, as `DIR=OUTPUT` (e.g. `build/linux=app-linux.pjz`)
+        #[arg(required = true, value_name = "DIR=OUTPUT")]
+        members: Vec<String>,
+
+        /// Chunk store archive written for the chunks the members share
+        #[arg(short, long, value_name = "FILE")]
+        store: PathBuf,
+
+        /// Package name of every member
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Author of every member [default: `PROJZST_AUTHOR`, else `author` of the
+        /// configuration file]
+        #[arg(short, long)]
+        auth: Option<String>,
+
+        /// Version of every member
+        #[arg(short, long)]
+        ver: Option<String>,
+
+        /// Description of every member
+        #[arg(short, long)]
+        desc: Option<String>,
+
+        /// Zstd compression level (1-22 or a negative fast level, 0 stores the payload
+        /// uncompressed) [default: `PROJZST_LEVEL`, else `level` of the configuration file,
+        /// else 6]
+        #[arg(short, long)]
+        level: Option<i32>,
+
+        /// Leave out files and directories whose name matches this glob; repeat for
+        /// several patterns
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+    },
+
+    /// Ship several .pjz files as one bundle, list its members or extract one of them
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
+
+    /// List the entries of a .pjz file
+    List {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Also print the type, permissions, size and compressed size of every entry; the
+        /// compressed size of files in a solid payload is an estimate, marked `~`
+        #[arg(short, long)]
+        long: bool,
+    },
+
+    /// Print the content of one file in a .pjz file
+    Cat {
+        /// Input .pjz file path, or an http(s):// URL (`http` feature)
+        input: PathBuf,
+
+        /// Path of the file inside the archive
+        path: String,
+
+        /// Print at most this many bytes, e.g. `4K`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        head: Option<u64>,
+    },
+
+    /// Search the files of a .pjz file for literal text, printing `path:line:text`
+    Grep {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Text searched for, taken literally
+        pattern: String,
+
+        /// Only search files whose path matches this glob; `*` also matches `/`
+        path_glob: Option<String>,
+
+        /// Match regardless of case
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+
+    /// Compress a sample of a directory at several levels and thread counts, to pick a level
+    Bench {
+        /// Source directory to take the sample from
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Zstd levels to try, comma-separated
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            allow_negative_numbers = true,
+            default_values_t = [1, 3, 6, 9, 15, 19]
+        )]
+        levels: Vec<i32>,
+
+        /// Thread counts to try, comma-separated
+        #[arg(long, value_delimiter = ',', default_values_t = [1])]
+        thread_counts: Vec<usize>,
+
+        /// Largest sample taken from the directory, e.g. `16M`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "64M")]
+        sample_size: u64,
+    },
+
+    /// Show the frame layout, sizes and entry count of a .pjz file without extracting it
+    Stat {
+        /// Input .pjz file path
+        input: PathBuf,
+    },
+
+    /// Write a software bill of materials (CycloneDX or SPDX JSON) of a .pjz file: the
+    /// package, every file with its hashes, and its dependencies
+    Sbom {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Output file path (`-` for stdout)
+        #[arg(short, long, default_value = "-")]
+        output: PathBuf,
+
+        /// Document format: cyclonedx or spdx
+        #[arg(long, default_value = "cyclonedx")]
+        format: String,
+    },
+
+    /// Show the uncompressed size of each directory of a .pjz file, like `du`
+    Du {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// List directories at most this many levels deep (0 for the total alone)
+        #[arg(short, long, value_name = "N")]
+        depth: Option<usize>,
+    },
+
+    /// Browse a .pjz file in a terminal UI: entry tree, metadata, file previews, and
+    /// extraction of marked entries (needs the `tui` feature)
+    Browse {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Directory marked entries are extracted to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+
+    /// Mount a .pjz file read-only as a file system until unmounted or interrupted
+    /// (Linux only, not macOS; needs the `fuse` feature)
+    Mount {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Existing directory to mount the archive on
+        mountpoint: PathBuf,
+    },
+
+    /// Upload a .pjz file to a registry as <registry>/<name>/<ver>, from its metadata
+    /// (needs the `http` feature)
+    Push {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Base URL of the registry
+        registry: String,
+
+        /// Token sent as `Authorization: Bearer <token>` [default: $PROJZST_REGISTRY_TOKEN]
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Send the token as-is in this header instead, e.g. `X-Api-Key`
+        #[arg(long, requires = "token")]
+        auth_header: Option<String>,
+    },
+
+    /// Download name@version from a registry, named <name>-<ver>.pjz after its metadata
+    /// (needs the `http` feature)
+    Pull {
+        /// Package to download, as name@version
+        package: String,
+
+        /// Base URL of the registry [default: $PROJZST_REGISTRY]
+        #[arg(short, long)]
+        registry: Option<String>,
+
+        /// Directory to write the archive to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+
+        /// Token sent as `Authorization: Bearer <token>` [default: $PROJZST_REGISTRY_TOKEN]
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Send the token as-is in this header instead, e.g. `X-Api-Key`
+        #[arg(long, requires = "token")]
+        auth_header: Option<String>,
+    },
+
+    /// Write a JSON catalog (name, ver, digest, size, path and metadata) of the .pjz files
+    /// below a directory, reading only their metadata; unchanged archives are not reread
+    Index {
+        /// Directory searched for .pjz files
+        dir: PathBuf,
+
+        /// Catalog file, updated in place when it exists
+        #[arg(short, long, default_value = "index.json")]
+        output: PathBuf,
+
+        /// Also train a compression dictionary on the cataloged archives, stored next to the
+        /// catalog for `pack --catalog`
+        #[arg(long)]
+        train_dictionary: bool,
+
+        /// Largest size of the trained dictionary, e.g. `64K`
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "train_dictionary")]
+        dictionary_size: Option<u64>,
+    },
+
+    /// Delete old archives of a release directory, keeping the newest versions of every
+    /// package by metadata name
+    Prune {
+        /// Directory searched for .pjz files
+        dir: PathBuf,
+
+        /// Keep the N newest versions of every package
+        #[arg(long, value_name = "N", required_unless_present = "keep_per_major")]
+        keep_latest: Option<usize>,
+
+        /// Also keep the N newest versions of every major version
+        #[arg(long, value_name = "N")]
+        keep_per_major: Option<usize>,
+
+        /// List the archives that would be deleted without deleting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Find archives by metadata in a catalog written by `index`, or in a directory
+    Search {
+        /// Catalog file, or directory whose .pjz files are scanned
+        source: PathBuf,
+
+        /// Predicates that must all hold, e.g. 'name=foo ver>=1.2 extra.ci.provider~=git'
+        #[arg(long)]
+        query: String,
+    },
+
+    /// Serve the .pjz files of a directory over HTTP: listing, metadata, downloads and
+    /// single entries (needs the `serve` feature)
+    Serve {
+        /// Directory of .pjz files
+        dir: PathBuf,
+
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+
+    /// List the skippable frames of a .pjz file
+    Frames {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Remove the vendor and other foreign frames in place instead of listing frames
+        #[arg(long)]
+        strip_foreign: bool,
+
+        /// Skippable frame magic the metadata was packed with (`pack --metadata-magic`)
+        #[arg(long, value_name = "MAGIC", value_parser = parse_metadata_magic)]
+        metadata_magic: Option<u32>,
+    },
+
+    /// Inspect and compare archive metadata
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+
+    /// Read or change the human-readable comment of a .pjz file
+    Comment {
+        #[command(subcommand)]
+        command: CommentCommands,
+    },
+
+    /// Read or change the icon attached to a .pjz file
+    Icon {
+        #[command(subcommand)]
+        command: IconCommands,
+    },
+
+    /// Attach small named files (changelog, license, signatures) to a .pjz file
+    Attach {
+        #[command(subcommand)]
+        command: AttachCommands,
+    },
+
+    /// Print the build environment recorded with `pack --provenance`
+    Provenance {
+        /// Input .pjz file path
+        input: PathBuf,
+    },
+
+    /// Print the digest signed by `pack --sign-cmd` and the time of the stored timestamp,
+    /// and write out the signature and timestamp token
+    Signature {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Write the signature to this file, for the signing tool to check
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Write the RFC 3161 timestamp token to this file, for `openssl ts -verify`
+        #[arg(long, value_name = "FILE")]
+        token: Option<PathBuf>,
+    },
+
+    /// Create, export, list and trust the ed25519 keys signing archives
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+
+    /// Print a completion script for a shell, generated from the command-line definition
+    Completions {
+        /// Shell to complete for
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the man page of projzst in roff format, or write the pages of every
+    /// subcommand to a directory
+    Man {
+        /// Directory to write `projzst.1` and one `projzst-<subcommand>.1` page per
+        /// subcommand to, instead of printing the main page
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Any other subcommand runs `projzst-<name>` from PATH
+    #[command(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+impl Commands {
+    /// Whether the command compresses, hashes or uploads on several threads, so the
+    /// thread count of the configuration applies to it
+    fn uses_threads(&self) -> bool {
+        matches!(
+            self,
+            Commands::Pack { .. }
+                | Commands::Unpack { .. }
+                | Commands::Sync { .. }
+                | Commands::Convert { .. }
+                | Commands::Verify { .. }
+                | Commands::Repair { .. }
+                | Commands::Migrate { .. }
+                | Commands::Merge { .. }
+                | Commands::Repack { .. }
+                | Commands::Optimize { .. }
+                | Commands::PackSet { .. }
+                | Commands::Bundle { .. }
+                | Commands::Bench { .. }
+                | Commands::Push { .. }
+                | Commands::Pull { .. }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum MetaCommands {
+    /// Compare the metadata of two .pjz files field by field
+    Diff {
+        /// Baseline .pjz file path
+        old: PathBuf,
+
+        /// .pjz file path to compare against the baseline
+        new: PathBuf,
+
+        /// Ignored Unknown Values or not
+        #[arg(short, long, default_value_t = String::from("1"))]
+        ignored: String,
+    },
+
+    /// Replace the metadata by appending a new revision, without rewriting the file
+    Set {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Metadata JSON file, as written by `info` (`-` for stdin)
+        metadata: PathBuf,
+    },
+
+    /// Increment the semantic version in the metadata, rewriting the metadata frame in place
+    Bump {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+
+        /// Increment the major version: 1.2.3 -> 2.0.0
+        #[arg(long, conflicts_with_all = ["minor", "patch"])]
+        major: bool,
+
+        /// Increment the minor version: 1.2.3 -> 1.3.0
+        #[arg(long, conflicts_with = "patch")]
+        minor: bool,
+
+        /// Increment the patch version: 1.2.3 -> 1.2.4
+        #[arg(long, required_unless_present_any = ["major", "minor", "pre"])]
+        patch: bool,
+
+        /// Make the new version a pre-release, e.g. `rc.1` (alone, bumps the patch of a
+        /// release or moves a pre-release on: 1.3.0-rc.1 -> 1.3.0-rc.2)
+        #[arg(long, value_name = "IDENTIFIERS")]
+        pre: Option<String>,
+    },
+
+    /// List the metadata revisions of a .pjz file, oldest first
+    History {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Ignored Unknown Values or not
+        #[arg(short, long, default_value_t = String::from("1"))]
+        ignored: String,
+    },
+
+    /// Save the metadata frame payloads exactly as stored, undecoded
+    Dump {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Output file path (`-` for stdout)
+        #[arg(short, long, default_value = "-")]
+        output: PathBuf,
+
+        /// Skippable frame magic the metadata was packed with (`pack --metadata-magic`)
+        #[arg(long, value_name = "MAGIC", value_parser = parse_metadata_magic)]
+        metadata_magic: Option<u32>,
+    },
+
+    /// Print one metadata revision as JSON
+    Show {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Revision number, 0 for the metadata written when packing (defaults to the latest)
+        #[arg(short, long)]
+        revision: Option<usize>,
+
+        /// Ignored Unknown Values or not
+        #[arg(short, long, default_value_t = String::from("1"))]
+        ignored: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Create a key pair in the configuration directory
+    Generate {
+        /// Name to store the pair under
+        name: String,
+
+        /// Replace a key pair of the same name
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Print the public key of a key pair, for those checking its signatures
+    Export {
+        /// Name of the key pair
+        name: String,
+
+        /// Write the key to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// List the key pairs and trusted public keys with their identifiers
+    List,
+
+    /// Trust a public key exported by `projzst key export`
+    Trust {
+        /// Public key file
+        file: PathBuf,
+
+        /// Name to trust the key under (default: the file name without `.pub`)
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CommentCommands {
+    /// Print the comment of a .pjz file
+    Get {
+        /// Input .pjz file path
+        input: PathBuf,
+    },
+
+    /// Attach a comment to a .pjz file, replacing any previous one
+    Set {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+
+        /// Comment text (`-` for stdin)
+        text: String,
+    },
+
+    /// Remove the comment of a .pjz file
+    Clear {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum IconCommands {
+    /// Save the icon of a .pjz file
+    Get {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Output image file path (`-` for stdout)
+        output: PathBuf,
+    },
+
+    /// Attach an image (PNG, JPEG, GIF, WebP, ICO or SVG) to a .pjz file
+    Set {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+
+        /// Image file path
+        image: PathBuf,
+    },
+
+    /// Remove the icon of a .pjz file
+    Clear {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum AttachCommands {
+    /// Attach a file, replacing any attachment of the same name
+    Add {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+
+        /// File to attach (`-` for stdin)
+        file: PathBuf,
+
+        /// Name to attach the file under (default: its file name; required for stdin)
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Save an attached file
+    Get {
+        /// Input .pjz file path
+        input: PathBuf,
+
+        /// Name of the attachment
+        name: String,
+
+        /// Output file path (`-` for stdout)
+        #[arg(default_value = "-")]
+        output: PathBuf,
+    },
+
+    /// List the attached files and their sizes
+    List {
+        /// Input .pjz file path
+        input: PathBuf,
+    },
+
+    /// Remove an attached file
+    Remove {
+        /// Input .pjz file path (changed in place)
+        input: PathBuf,
+
+        /// Name of the attachment
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum BundleCommands {
+    /// Pack .pjz files into a bundle with a manifest of their names and versions
+    Create {
+        /// Member .pjz file paths
+        #[arg(required = true)]
+        members: Vec<PathBuf>,
+
+        /// Output bundle path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Bundle (product) name
+        #[arg(short, long)]
+        name: String,
+
+        /// Bundle version
+        #[arg(short, long)]
+        ver: Option<String>,
+
+        /// Bundle description
+        #[arg(short, long)]
+        desc: Option<String>,
+    },
+
+    /// List the members of a bundle from its manifest
+    List {
+        /// Input bundle path
+        input: PathBuf,
+    },
+
+    /// Extract one member without unpacking the rest of the bundle
+    Extract {
+        /// Input bundle path
+        input: PathBuf,
+
+        /// Member file name, package name, or NAME@VER
+        member: String,
+
+        /// Output .pjz file path [default: the member's file name]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Number of files `stat` lists by compressed size
+const STAT_LARGEST_FILES: usize = 10;
+
+/// File descriptor external subcommands read the archive metadata JSON from (Unix only)
+const METADATA_FD: i32 = 3;
+
+/// Run `projzst-<name>` from PATH with the remaining arguments, git/cargo style
+/// Besides `PROJZST` (this executable) and `PROJZST_VERSION`, a first argument naming an
+/// archive whose metadata can be read sets `PROJZST_ARCHIVE`, and on Unix the metadata JSON
+/// is readable on the descriptor given in `PROJZST_METADATA_FD`
+fn run_external(args: Vec<OsString>) -> Result<ExitStatus, ProjzstError> {
+    let (name, args) = args.split_first().expect("clap passes the subcommand name");
+    let program = format!("projzst-{}", name.to_string_lossy());
+
+    let mut command = Command::new(&program);
+    command
+        .args(args)
+        .env("PROJZST_VERSION", env!("CARGO_PKG_VERSION"));
+    if let Ok(exe) = std::env::current_exe() {
+        command.env("PROJZST", exe);
+    }
+
+    // Arguments that are not archives are simply passed on
+    let archive = args
+        .first()
+        .map(PathBuf::from)
+        .filter(|path| path.is_file());
+    let metadata = archive.as_ref().and_then(|path| {
+        ReadOptions::new(IgnoreUnknown::On)
+            .read_metadata(path)
+            .ok()
+            .map(|(metadata, _)| metadata)
+    });
+    let mut metadata_json = None;
+    if let (Some(archive), Some(metadata)) = (&archive, &metadata) {
+        command.env("PROJZST_ARCHIVE", archive);
+        metadata_json = Some(serde_json::to_vec(metadata)?);
+    }
+
+    #[cfg(unix)]
+    let metadata_writer = match metadata_json {
+        Some(json) => Some((metadata_pipe(&mut command)?, json)),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    drop(metadata_json);
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ProjzstError::UnknownSubcommand(program.clone()),
+        _ => e.into(),
+    })?;
+    // The child holds its own copy of the read end now
+    drop(command);
+
+    #[cfg(unix)]
+    let feeder = metadata_writer.map(|(mut writer, json)| {
+        std::thread::spawn(move || {
+            // A subcommand may not care about the metadata and never read it
+            let _ = writer.write_all(&json);
+        })
+    });
+    let status = child.wait()?;
+    #[cfg(unix)]
+    if let Some(feeder) = feeder {
+        feeder.join().expect("metadata writer panicked");
+    }
+
+    Ok(status)
+}
+
+/// Hand the read end of a new pipe to the command as [`METADATA_FD`]
+/// Returns the write end, to be filled once the command is spawned
+#[cfg(unix)]
+fn metadata_pipe(command: &mut Command) -> io::Result<io::PipeWriter> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+
+    let (reader, writer) = io::pipe()?;
+    command.env("PROJZST_METADATA_FD", METADATA_FD.to_string());
+    // SAFETY: only async-signal-safe calls between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            let fd = reader.as_raw_fd();
+            let result = if fd == METADATA_FD {
+                // Already in place, but opened close-on-exec
+                libc::fcntl(fd, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(fd, METADATA_FD)
+            };
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(writer)
+}
+
+/// Whether a path argument is the conventional `-` for stdin/stdout
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Internal helper: split a `DIR:PREFIX` input into the directory and its prefix
+/// Inputs naming an existing path or an `s3://` URL are taken as they are
+fn source_root(input: PathBuf) -> (PathBuf, Option<String>) {
+    if s3_url(&input).is_some() || input.exists() {
+        return (input, None);
+    }
+    match input.to_str().and_then(|s| s.rsplit_once(':')) {
+        Some((dir, prefix)) => (PathBuf::from(dir), Some(prefix.to_string())),
+        None => (input, None),
+    }
+}
+
+/// Internal helper: the entries of a `--files-from` list as paths and optional destinations
+/// Blank lines and lines starting with `#` are skipped
+fn read_file_list(list: &Path) -> Result<Vec<(PathBuf, Option<String>)>, ProjzstError> {
+    Ok(fs::read_to_string(list)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('=') {
+            Some((src, dest)) => (PathBuf::from(src), Some(dest.to_string())),
+            None => (PathBuf::from(line), None),
+        })
+        .collect())
+}
+
+/// The path argument as an `s3://` URL, if it is one
+fn s3_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|s| s.starts_with("s3://"))
+}
+
+/// Pack objects under an S3 prefix, writing the archive to a file or stdout
+#[cfg(feature = "s3")]
+fn pack_from_bucket(url: &str, output: &Path, packer: &Packer) -> Result<PackReport, ProjzstError> {
+    if is_stdio(output) {
+        packer.pack_from_s3_to_writer(url, io::stdout().lock())
+    } else {
+        packer.pack_from_s3(url, output)
+    }
+}
+
+/// Pack from object storage (unavailable without the `s3` feature)
+#[cfg(not(feature = "s3"))]
+fn pack_from_bucket(
+    url: &str,
+    _output: &Path,
+    _packer: &Packer,
+) -> Result<PackReport, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot pack from {url}: projzst was built without the `s3` feature"
+    )))
+}
+
+/// Batch the writes of small extracted files through io_uring
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn with_io_uring(options: ReadOptions, _out: &mut Output) -> ReadOptions {
+    options.io_uring(true)
+}
+
+/// Batched writes (unavailable without the `io-uring` feature)
+#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+fn with_io_uring(options: ReadOptions, out: &mut Output) -> ReadOptions {
+    out.warn(tr!("warning-io-uring-unavailable"));
+    options
+}
+
+/// Confine the process to what unpacking `input` into `output` touches (`--confine`)
+/// Nothing of the archive is read before: the output directory and `metadata.json` are
+/// created first, since neither can be once confined, `metadata.json` staying empty when
+/// the archive turns out to have no metadata
+fn confine_unpack(input: &Path, output: &Path, out: &mut Output) -> Result<(), ProjzstError> {
+    if s3_url(output).is_some() {
+        return Err(ProjzstError::InvalidArgument(
+            "--confine needs a local output directory".to_string(),
+        ));
+    }
+    fs::create_dir_all(output)?;
+    let metadata_json = output
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("metadata.json");
+    if !metadata_json.exists() {
+        fs::write(&metadata_json, "")?;
+    }
+    confine::Confinement {
+        input: (!is_stdio(input)).then_some(input),
+        output,
+        metadata_json: Some(&metadata_json),
+    }
+    .apply(out)
+}
+
+/// Create extracted entries through a handle on the output directory
+#[cfg(feature = "sandbox")]
+fn sandboxed(options: ReadOptions) -> Result<ReadOptions, ProjzstError> {
+    Ok(options.sandboxed(true))
+}
+
+/// Sandboxed unpacks (unavailable without the `sandbox` feature): refused rather than
+/// ignored, since the caller relies on them for untrusted input
+#[cfg(not(feature = "sandbox"))]
+fn sandboxed(_options: ReadOptions) -> Result<ReadOptions, ProjzstError> {
+    Err(ProjzstError::FeatureUnavailable {
+        what: "--sandbox".to_string(),
+        feature: "sandbox".to_string(),
+    })
+}
+
+/// Unpack into object storage, reading the archive from a file or stdin
+#[cfg(feature = "s3")]
+fn unpack_to_bucket(
+    input: &Path,
+    url: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata, ProjzstError> {
+    if is_stdio(input) {
+        projzst::unpack_from_reader_to_s3(io::stdin().lock(), url, ignore_unknown)
+    } else {
+        projzst::unpack_to_s3(input, url, ignore_unknown)
+    }
+}
+
+/// Unpack into object storage (unavailable without the `s3` feature)
+#[cfg(not(feature = "s3"))]
+fn unpack_to_bucket(
+    _input: &Path,
+    url: &str,
+    _ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot unpack to {url}: projzst was built without the `s3` feature"
+    )))
+}
+
+/// The path argument as an `http(s)://` URL, if it is one
+fn http_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// Read metadata of a remote archive with range requests
+#[cfg(feature = "http")]
+fn read_metadata_remote(
+    url: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata, ProjzstError> {
+    projzst::read_metadata_url(url, ignore_unknown)
+}
+
+/// Read metadata of a remote archive (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn read_metadata_remote(
+    url: &str,
+    _ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot read {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Write one file of a remote archive to `writer`, at most `head` bytes of it
+/// Returns the number of bytes written
+#[cfg(feature = "http")]
+fn cat_remote<W: Write>(
+    url: &str,
+    path: &str,
+    head: Option<u64>,
+    mut writer: W,
+) -> Result<u64, ProjzstError> {
+    match head {
+        Some(head) => {
+            let preview = projzst::preview_entry_url(url, path, head as usize)?;
+            writer.write_all(&preview.data)?;
+            writer.flush()?;
+            Ok(preview.data.len() as u64)
+        }
+        None => projzst::write_entry_url(url, path, writer),
+    }
+}
+
+/// Write one file of a remote archive (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn cat_remote<W: Write>(
+    url: &str,
+    _path: &str,
+    _head: Option<u64>,
+    _writer: W,
+) -> Result<u64, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot read {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Update a directory to match a remote archive streamed with range requests
+#[cfg(feature = "http")]
+fn sync_remote(url: &str, output: &Path, delete: bool) -> Result<SyncReport, ProjzstError> {
+    projzst::sync_url(url, output, delete)
+}
+
+/// Update a directory from a remote archive (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn sync_remote(url: &str, _output: &Path, _delete: bool) -> Result<SyncReport, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot read {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Get an RFC 3161 timestamp for an archive from a time-stamping authority
+#[cfg(feature = "http")]
+fn timestamp_remote(input: &Path, url: &str) -> Result<Timestamp, ProjzstError> {
+    projzst::timestamp_archive(input, url)
+}
+
+/// Get a timestamp (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn timestamp_remote(_input: &Path, url: &str) -> Result<Timestamp, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot reach {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Registry URL given on the command line or in `PROJZST_REGISTRY`
+fn registry_url(url: Option<String>) -> Result<String, ProjzstError> {
+    url.or_else(|| {
+        std::env::var("PROJZST_REGISTRY")
+            .ok()
+            .filter(|v| !v.is_empty())
+    })
+    .ok_or_else(|| {
+        ProjzstError::Remote(
+            "no registry given: pass --registry or set PROJZST_REGISTRY".to_string(),
+        )
+    })
+}
+
+/// Authentication header for a registry: the token from the command line or
+/// `PROJZST_REGISTRY_TOKEN`, as a bearer token or as-is in `auth_header`
+fn registry_auth(token: Option<String>, auth_header: Option<String>) -> Option<(String, String)> {
+    let token = token.or_else(|| {
+        std::env::var("PROJZST_REGISTRY_TOKEN")
+            .ok()
+            .filter(|v| !v.is_empty())
+    })?;
+    Some(match auth_header {
+        Some(header) => (header, token),
+        None => ("Authorization".to_string(), format!("Bearer {token}")),
+    })
+}
+
+/// Client for a registry with its authentication
+#[cfg(feature = "http")]
+fn registry_client(url: &str, auth: Option<(String, String)>) -> projzst::Registry {
+    let registry = projzst::Registry::new(url);
+    match auth {
+        Some((header, value)) => registry.auth_header(header, value),
+        None => registry,
+    }
+}
+
+/// Upload an archive to a registry, returning its URL there
+#[cfg(feature = "http")]
+fn push_archive(
+    input: &Path,
+    url: &str,
+    auth: Option<(String, String)>,
+) -> Result<String, ProjzstError> {
+    registry_client(url, auth).push(input)
+}
+
+/// Upload an archive to a registry (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn push_archive(
+    _input: &Path,
+    url: &str,
+    _auth: Option<(String, String)>,
+) -> Result<String, ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot push to {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Download a package version from a registry into `output`
+#[cfg(feature = "http")]
+fn pull_package(
+    package: &str,
+    url: &str,
+    auth: Option<(String, String)>,
+    output: &Path,
+) -> Result<(PathBuf, Metadata), ProjzstError> {
+    let (name, version) = projzst::parse_package_ref(package)?;
+    registry_client(url, auth).pull(name, version, output)
+}
+
+/// Download a package version (unavailable without the `http` feature)
+#[cfg(not(feature = "http"))]
+fn pull_package(
+    package: &str,
+    url: &str,
+    _auth: Option<(String, String)>,
+    _output: &Path,
+) -> Result<(PathBuf, Metadata), ProjzstError> {
+    Err(ProjzstError::Remote(format!(
+        "cannot pull {package} from {url}: projzst was built without the `http` feature"
+    )))
+}
+
+/// Browse an archive in the terminal, returning the number of entries extracted
+#[cfg(feature = "tui")]
+fn browse_archive(input: &Path, output: &Path) -> Result<usize, ProjzstError> {
+    browse::run(input, output)
+}
+
+/// Browse an archive (unavailable without the `tui` feature)
+#[cfg(not(feature = "tui"))]
+fn browse_archive(_input: &Path, _output: &Path) -> Result<usize, ProjzstError> {
+    Err(ProjzstError::FeatureUnavailable {
+        what: "browse".to_string(),
+        feature: "tui".to_string(),
+    })
+}
+
+/// Serve an archive as a read-only file system until it is unmounted or interrupted
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+fn mount_archive(input: &Path, mountpoint: &Path) -> Result<(), ProjzstError> {
+    extern "C" fn interrupted(_: libc::c_int) {}
+    // Ctrl-C then interrupts serving, which unmounts, instead of killing the process and
+    // leaving a dead mount behind; no SA_RESTART, so the wait for requests ends
+    // SAFETY: the handler does nothing, which is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = interrupted as *const () as libc::sighandler_t;
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+    }
+    projzst::mount(input, mountpoint)
+}
+
+/// Serve an archive as a file system (Linux only, with the `fuse` feature)
+#[cfg(not(all(feature = "fuse", target_os = "linux")))]
+fn mount_archive(_input: &Path, _mountpoint: &Path) -> Result<(), ProjzstError> {
+    Err(ProjzstError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "mounting is only supported on Linux, with projzst built with the `fuse` feature",
+    )))
+}
+
+/// Serve a directory of archives over HTTP until the process ends
+#[cfg(feature = "serve")]
+fn serve_directory(dir: &Path, address: &str) -> Result<(), ProjzstError> {
+    projzst::serve(dir, address)
+}
+
+/// Serve a directory of archives (unavailable without the `serve` feature)
+#[cfg(not(feature = "serve"))]
+fn serve_directory(_dir: &Path, _address: &str) -> Result<(), ProjzstError> {
+    Err(ProjzstError::FeatureUnavailable {
+        what: "serve".to_string(),
+        feature: "serve".to_string(),
+    })
+}
+
+/// Where the result of a command goes: human-readable text printed as the command runs,
+/// or with `--json` fields gathered into one object printed when it ends
+struct Output {
+    json: bool,
+    /// Print no status lines or warnings (`--quiet`)
+    quiet: bool,
+    /// Print the JSON object to stderr, stdout carrying archive or file data
+    to_stderr: bool,
+    /// Print no JSON object at all, an external subcommand owning the output
+    silent: bool,
+    fields: serde_json::Map<String, serde_json::Value>,
+    warnings: Vec<String>,
+}
+
+impl Output {
+    fn new(json: bool, quiet: bool) -> Self {
+        Self {
+            json,
+            quiet,
+            to_stderr: false,
+            silent: false,
+            fields: serde_json::Map::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Print a status line to stdout, or to stderr once stdout carries data (nothing
+    /// with `--json`)
+    fn status(&self, line: impl std::fmt::Display) {
+        match (self.json || self.quiet, self.to_stderr) {
+            (true, _) => {}
+            (false, true) => eprintln!("{line}"),
+            (false, false) => println!("{line}"),
+        }
+    }
+
+    /// Report a warning: printed to stderr, or listed under `warnings` with `--json`
+    fn warn(&mut self, message: String) {
+        if self.json {
+            self.warnings.push(message);
+        } else if !self.quiet {
+            eprintln!("{}", tr!("warning", message = message));
+        }
+    }
+
+    /// Set a field of the JSON object (nothing without `--json`)
+    fn set<T: serde::Serialize>(&mut self, key: &str, value: T) -> Result<(), ProjzstError> {
+        if self.json {
+            self.fields
+                .insert(key.to_string(), serde_json::to_value(value)?);
+        }
+        Ok(())
+    }
+
+    /// Print the JSON object, with `ok`, `warnings` and the error of a failed command
+    fn finish(mut self, error: Option<&ProjzstError>) {
+        if !self.json || self.silent {
+            return;
+        }
+        self.fields
+            .insert("ok".to_string(), serde_json::Value::Bool(error.is_none()));
+        if let Some(error) = error {
+            self.fields
+                .insert("error".to_string(), error.to_string().into());
+            self.fields
+                .insert("exit_code".to_string(), error.exit_code().into());
+            self.fields
+                .insert("error_kind".to_string(), error.kind().name().into());
+        }
+        self.fields
+            .insert("warnings".to_string(), self.warnings.into());
+        let object = serde_json::Value::Object(self.fields);
+        let text = serde_json::to_string_pretty(&object).unwrap_or_default();
+        // A reader that stopped early is not worth a panic
+        let _ = if self.to_stderr {
+            writeln!(io::stderr().lock(), "{text}")
+        } else {
+            writeln!(io::stdout().lock(), "{text}")
+        };
+    }
+}
+
+fn run(cli: Cli, out: &mut Output) -> Result<(), ProjzstError> {
+    let config = UserConfig::load(cli.config.as_deref())?;
+    let threads = if cli.command.uses_threads() {
+        config.threads(cli.threads)?
+    } else {
+        cli.threads
+    };
+    if let Some(threads) = threads {
+        Concurrency::threads(threads).set_default();
+    }
+    let configured_level = |level: Option<i32>| {
+        config
+            .level(level)
+            .map(|level| level.unwrap_or(DEFAULT_ZSTD_LEVEL))
+    };
+
+    match cli.command {
+        Commands::Pack {
+            input,
+            name,
+            auth,
+            fmt,
+            ed,
+            ver,
+            desc,
+            license,
+            homepage,
+            repository,
+            keywords,
+            deps,
+            from_manifest,
+            extra,
+            extra_format,
+            extra_env,
+            extra_json,
+            extra_kv,
+            allow_non_object_extra,
+            base,
+            level,
+            codec,
+            catalog,
+            preset,
+            split_size,
+            order,
+            tar_format,
+            hard_dereference,
+            subsecond_mtime,
+            stats,
+            adaptive,
+            per_entry_frames,
+            checksum,
+            metadata_magic,
+            strip_foreign_frames,
+            chunked,
+            checkpoint,
+            watch,
+            dry_run,
+            interactive,
+            debounce,
+            compress_metadata,
+            self_extracting,
+            sign_cmd,
+            sign_key,
+            timestamp_url,
+            emit_checksum,
+            provenance,
+            metadata_encoding,
+            metadata_placement,
+            align,
+            files_from,
+            prefix,
+            one_file_system,
+            max_depth,
+            exclude_vcs,
+            exclude_hidden,
+            exclude,
+            max_file_size,
+            on_oversize,
+            special,
+            scan_secrets,
+            sync,
+            buffer_size,
+            force,
+            validate,
+            expand,
+            vars,
+            schema,
+            compat,
+            output,
+            auto_output,
+        } => {
+            let mut roots = input.into_iter().map(source_root);
+            let (input, input_prefix) = roots.next().unwrap_or_else(|| (PathBuf::from("."), None));
+            let mut roots: Vec<_> = roots.collect();
+            let mut files = Vec::new();
+            if let Some(list) = &files_from {
+                for (path, dest) in read_file_list(list)? {
+                    match dest {
+                        Some(dest) => roots.push((path, Some(dest))),
+                        None => files.push(path),
+                    }
+                }
+            }
+            if s3_url(&input).is_some() && !roots.is_empty() {
+                return Err(ProjzstError::InvalidArgument(
+                    "an s3:// source cannot be combined with other inputs".to_string(),
+                ));
+            }
+
+            let emit_checksum = emit_checksum
+                .map(ChecksumAlgorithm::from_str_tmp)
+                .transpose()?;
+
+            let mut metadata = Metadata::new(name, auth, fmt, ed, ver, desc);
+            if let Some(manifest) = &from_manifest {
+                metadata = metadata.with_defaults(Metadata::from_manifest(manifest)?);
+            }
+            if metadata.auth.is_none() {
+                metadata.auth = config.author.clone();
+            }
+            if interactive {
+                wizard::fill_metadata(&mut metadata, &input)?;
+            }
+            if let Some(license) = license {
+                metadata.set_license(license)?;
+            }
+            if let Some(homepage) = homepage {
+                metadata.set_homepage(homepage)?;
+            }
+            if let Some(repository) = repository {
+                metadata.set_repository(repository)?;
+            }
+            for keyword in keywords {
+                metadata.add_keyword(keyword)?;
+            }
+            for dep in deps {
+                let (dep, digest) = match dep.split_once('#') {
+                    Some((dep, digest)) => (dep, Some(digest.to_string())),
+                    None => (dep.as_str(), None),
+                };
+                let (name, req) = dep.split_once('@').unwrap_or((dep, "*"));
+                metadata.add_dependency(name, req, digest)?;
+            }
+            let mut packer = Packer::new(metadata)
+                .compression_level(configured_level(level)?)
+                .codec(PayloadCodec::from_str_tmp(codec)?)
+                .order(EntryOrder::from_str_tmp(order)?)
+                .tar_format(TarFormat::from_str_tmp(tar_format)?)
+                .hard_dereference(hard_dereference)
+                .subsecond_mtimes(subsecond_mtime)
+                .adaptive_compression(adaptive)
+                .per_entry_frames(per_entry_frames)
+                .content_checksum(checksum)
+                .keep_foreign_frames(!strip_foreign_frames)
+                .chunked(chunked)
+                .compress_metadata(compress_metadata)
+                .self_extracting(self_extracting)
+                .metadata_encoding(MetadataEncoding::from_str_tmp(metadata_encoding)?)
+                .metadata_placement(MetadataPlacement::from_str_tmp(metadata_placement)?)
+                .allow_non_object_extra(allow_non_object_extra)
+                .one_file_system(one_file_system)
+                .exclude_vcs(exclude_vcs)
+                .exclude_hidden(exclude_hidden)
+                .sync(sync)
+                .overwrite(force);
+            for glob in config.exclude.iter().chain(&exclude) {
+                packer = packer.exclude(glob);
+            }
+            if let Some(preset) = preset {
+                packer = packer.profile(CompressionProfile::from_str_tmp(preset)?);
+                if let Some(threads) = threads {
+                    packer = packer.concurrency(Concurrency::threads(threads));
+                }
+            }
+            if let Some(depth) = max_depth {
+                packer = packer.max_depth(depth);
+            }
+            if let Some(size) = buffer_size {
+                packer = packer.buffer_size(size as usize);
+            }
+            if let Some(magic) = metadata_magic {
+                packer = packer.metadata_magic(magic);
+            }
+            if let Some(catalog) = &catalog {
+                if let Some(dictionary) = catalog_dictionaries(catalog)?.pop() {
+                    packer = packer.dictionary(dictionary);
+                }
+            }
+            packer = packer
+                .oversize_policy(OversizePolicy::from_str_tmp(on_oversize)?)
+                .special_entries(SpecialPolicy::from_str_tmp(special)?)
+                .secret_scan(SecretPolicy::from_str_tmp(scan_secrets)?);
+            if let Some(limit) = max_file_size {
+                packer = packer.max_file_size(limit);
+            }
+            // `--prefix` holds every input, each below it under its own prefix
+            let join = |root: Option<String>| match (prefix.as_deref(), root) {
+                (Some(prefix), Some(root)) => format!("{}/{root}", prefix.trim_end_matches('/')),
+                (prefix, root) => root.or(prefix.map(str::to_string)).unwrap_or_default(),
+            };
+            packer = packer.prefix(join(input_prefix));
+            for (dir, root_prefix) in roots {
+                packer = packer.source(dir, join(root_prefix));
+            }
+            if let Some(extra) = &extra {
+                packer = if is_stdio(extra) {
+                    let mut content = String::new();
+                    io::stdin().read_to_string(&mut content)?;
+                    packer.extra_json("<stdin>", content)
+                } else {
+                    packer.extra_file(extra)
+                };
+            }
+            if let Some(var) = extra_env {
+                packer = packer.extra_env(var);
+            }
+            if let Some(json) = extra_json {
+                packer = packer.extra_json("--extra-json", json);
+            }
+            if let Some(format) = extra_format {
+                packer = packer.extra_format(ExtraFormat::from_str_tmp(format)?);
+            }
+            if let Some(base) = &base {
+                packer = packer.base_archive(base);
+            }
+            if let Some(checkpoint) = &checkpoint {
+                packer = packer.checkpoint(checkpoint);
+            }
+            if let Some(alignment) = align {
+                packer = packer.payload_alignment(alignment);
+            }
+            if provenance {
+                packer = packer.provenance(Provenance::collect(&input));
+            }
+            for pair in &extra_kv {
+                let (path, value) = parse_extra_pair(pair)?;
+                packer = packer.extra_value(path, value);
+            }
+            if let Some(size) = split_size {
+                if output.as_deref().is_some_and(is_stdio) || s3_url(&input).is_some() {
+                    return Err(ProjzstError::InvalidArgument(
+                        "--split-size needs a local source directory and an output file"
+                            .to_string(),
+                    ));
+                }
+                packer = packer.split_size(size);
+            }
+            if let Some(schema) = &schema {
+                packer =
+                    packer.schemas(SchemaRegistry::new().fallback(ExtraSchema::from_file(schema)?));
+            }
+            if let Some(compat) = compat {
+                packer = packer.compat(CompatProfile::from_str_tmp(compat.as_str())?);
+                for option in packer.dropped_options() {
+                    out.warn(tr!(
+                        "warning-compat-dropped",
+                        option = option.to_string(),
+                        compat = compat.as_str()
+                    ));
+                }
+            }
+            if expand || !vars.is_empty() {
+                let mut template_vars = TemplateVars::new();
+                if s3_url(&input).is_none() {
+                    template_vars = template_vars.git(&input);
+                }
+                for var in &vars {
+                    let (name, value) = var.split_once('=').ok_or_else(|| {
+                        ProjzstError::InvalidArgument(format!(
+                            "--var expects NAME=VALUE, got {var:?}"
+                        ))
+                    })?;
+                    template_vars = template_vars.set(name, value);
+                }
+                packer = packer.template_vars(template_vars);
+            }
+            if validate {
+                MetadataBuilder::from_metadata(packer.resolved_metadata()?).build()?;
+            }
+            let output = match (output, auto_output) {
+                (Some(output), _) => output,
+                (None, None) if interactive => {
+                    wizard::output(PathBuf::from(auto_file_name(&packer.resolved_metadata()?)?))?
+                }
+                (None, dir) => dir
+                    .unwrap_or_default()
+                    .join(auto_file_name(&packer.resolved_metadata()?)?),
+            };
+
+            if is_stdio(&output) {
+                // Archive goes to stdout, so keep everything else off it
+                out.to_stderr = true;
+            }
+            // Set up before the first pack, so changes made while it runs are seen
+            let watcher = if watch {
+                if s3_url(&input).is_some() || is_stdio(&output) {
+                    return Err(ProjzstError::InvalidArgument(
+                        "--watch needs local sources and an output file".to_string(),
+                    ));
+                }
+                let mut watcher = packer.watcher(&input)?;
+                watcher.ignore(&output);
+                Some(watcher)
+            } else {
+                None
+            };
+            if interactive {
+                if s3_url(&input).is_some() {
+                    return Err(ProjzstError::InvalidArgument(
+                        "--interactive needs local sources".to_string(),
+                    ));
+                }
+                let estimate = if files_from.is_some() {
+                    packer.dry_run_files(&files)?
+                } else {
+                    packer.dry_run(&input)?
+                };
+                if !wizard::confirm(&estimate, &output)? {
+                    out.status(tr!("wizard-cancelled"));
+                    return Ok(());
+                }
+            }
+            if emit_checksum.is_some() && is_stdio(&output) {
+                return Err(ProjzstError::InvalidArgument(
+                    "--emit-checksum needs an output file".to_string(),
+                ));
+            }
+            if (sign_cmd.is_some() || sign_key.is_some() || timestamp_url.is_some())
+                && is_stdio(&output)
+            {
+                return Err(ProjzstError::InvalidArgument(
+                    "signing and timestamping need an output file".to_string(),
+                ));
+            }
+            // Loaded before packing, so a missing key fails early
+            let sign_key = sign_key
+                .map(|name| KeyStore::open()?.secret_key(&name))
+                .transpose()?;
+            let mut estimate = None;
+            let report = if dry_run {
+                if s3_url(&input).is_some() {
+                    return Err(ProjzstError::InvalidArgument(
+                        "--dry-run needs local sources".to_string(),
+                    ));
+                }
+                let dry_run = if files_from.is_some() {
+                    packer.dry_run_files(&files)?
+                } else {
+                    packer.dry_run(&input)?
+                };
+                let report = dry_run.report.clone();
+                estimate = Some(dry_run);
+                report
+            } else if let Some(url) = s3_url(&input) {
+                pack_from_bucket(url, &output, &packer)?
+            } else if files_from.is_some() && is_stdio(&output) {
+                packer.pack_files_to_writer(&files, io::stdout().lock())?
+            } else if files_from.is_some() {
+                packer.pack_files(&files, &output)?
+            } else if is_stdio(&output) {
+                packer.pack_to_writer(&input, io::stdout().lock())?
+            } else {
+                packer.pack(&input, &output)?
+            };
+            for warning in &report.warnings {
+                out.warn(i18n::warning(warning));
+            }
+            if let Some(estimate) = estimate {
+                out.status(tr!(
+                    "estimated",
+                    files = estimate.report.files,
+                    size = format_size(estimate.report.original_size),
+                    path = output.display().to_string(),
+                    estimated = format_size(estimate.estimated_size),
+                    ratio = format!("{:.2}", estimate.report.ratio()),
+                    sampled = format_size(estimate.sampled_size)
+                ));
+                return out.set("estimate", &estimate);
+            }
+            if is_stdio(&output) {
+                out.status(tr!("packed-stdout"));
+                out.set("output", None::<&Path>)?;
+            } else {
+                out.status(tr!("packed", path = output.display().to_string()));
+                out.set("output", &output)?;
+            }
+            // Signed first, so the checksum covers the signature frame
+            if let Some(sign_cmd) = &sign_cmd {
+                sign_archive(&output, &command_argv(sign_cmd))?;
+                out.status(tr!("signed", path = output.display().to_string()));
+            }
+            if let Some(key) = &sign_key {
+                let key_id = sign_archive_with_key(&output, key)?;
+                out.status(tr!(
+                    "signed-with-key",
+                    path = output.display().to_string(),
+                    key = key_id.as_str()
+                ));
+                out.set("key_id", key_id)?;
+            }
+            if let Some(url) = &timestamp_url {
+                let stamp = timestamp_remote(&output, url)?;
+                out.status(tr!(
+                    "timestamped",
+                    path = output.display().to_string(),
+                    time = stamp.time.as_str()
+                ));
+                out.set("timestamp", &stamp)?;
+            }
+            if let Some(algorithm) = emit_checksum {
+                let path = write_checksum_file(&output, algorithm)?;
+                out.status(tr!("wrote", path = path.display().to_string()));
+                out.set("checksum_file", &path)?;
+            }
+            if stats {
+                out.status(format_stats(&report).trim_end());
+            }
+            out.set("report", &report)?;
+
+            if let Some(mut watcher) = watcher {
+                out.status(tr!("watching", path = input.display().to_string()));
+                // Later packs replace the archive of the first one
+                let packer = packer.overwrite(true);
+                loop {
+                    let changed = watcher.wait(Duration::from_millis(debounce))?;
+                    // A broken tree mid-edit is no reason to stop watching
+                    let repacked = packer.pack(&input, &output).and_then(|report| {
+                        if let Some(sign_cmd) = &sign_cmd {
+                            sign_archive(&output, &command_argv(sign_cmd))?;
+                        }
+                        if let Some(key) = &sign_key {
+                            sign_archive_with_key(&output, key)?;
+                        }
+                        if let Some(url) = &timestamp_url {
+                            timestamp_remote(&output, url)?;
+                        }
+                        if let Some(algorithm) = emit_checksum {
+                            write_checksum_file(&output, algorithm)?;
+                        }
+                        Ok(report)
+                    });
+                    match repacked {
+                        Ok(report) => out.status(tr!(
+                            "repacked",
+                            path = output.display().to_string(),
+                            changes = changed.len(),
+                            files = report.files,
+                            size = format_size(report.compressed_size)
+                        )),
+                        Err(e) => out.warn(tr!("warning-watch-failed", error = e.to_string())),
+                    }
+                }
+            }
+        }
+
+        Commands::Unpack {
+            input,
+            output,
+            base,
+            chunk_store,
+            allow_missing_metadata,
+            metadata_magic,
+            catalog,
+            post_verify,
+            schema,
+            require,
+            expect,
+            strip_components,
+            transform,
+            special,
+            mtime,
+            windows_names,
+            reject_collisions,
+            no_space_check,
+            keep_going,
+            recursive,
+            nested_size_limit,
+            sync,
+            buffer_size,
+            io_uring,
+            sandbox,
+            confine,
+            ignored,
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            let mut options = ReadOptions::new(ignore_unknown)
+                .allow_missing_metadata(allow_missing_metadata)
+                .post_verify(post_verify)
+                .strip_components(strip_components)
+                .special_entries(SpecialPolicy::from_str_tmp(special)?)
+                .mtimes(MtimePolicy::from_str_tmp(mtime)?);
+            if let Some(magic) = metadata_magic {
+                options = options.metadata_magic(magic);
+            }
+            if let Some(store) = &chunk_store {
+                options = options.chunk_store(store);
+            }
+            if let Some(catalog) = &catalog {
+                for dictionary in catalog_dictionaries(catalog)? {
+                    options = options.dictionary(dictionary);
+                }
+            }
+            if let Some(policy) = &windows_names {
+                options = options.windows_names(WindowsNamePolicy::from_str_tmp(policy.as_str())?);
+            }
+            for expr in &transform {
+                let transform = PathTransform::parse(expr)?;
+                options = options.transform(move |path| Some(transform.apply(path)));
+            }
+            if let Some(schema) = &schema {
+                options = options
+                    .schemas(SchemaRegistry::new().fallback(ExtraSchema::from_file(schema)?));
+            }
+            if !require.is_empty() || !expect.is_empty() {
+                let mut policy = MetadataPolicy::new();
+                for field in require {
+                    policy = policy.require(field);
+                }
+                for pair in &expect {
+                    let (field, value) = pair.split_once('=').ok_or_else(|| {
+                        ProjzstError::InvalidArgument(format!(
+                            "--expect {pair:?}: expected FIELD=VALUE"
+                        ))
+                    })?;
+                    policy = policy.expect(field, value);
+                }
+                options = options.policy(policy);
+            }
+            options = options
+                .reject_collisions(reject_collisions)
+                .check_free_space(!no_space_check)
+                .keep_going(keep_going)
+                .sync(sync);
+            if let Some(size) = buffer_size {
+                options = options.buffer_size(size as usize);
+            }
+            if let Some(depth) = recursive {
+                options = options.recursive(depth);
+            }
+            if let Some(limit) = nested_size_limit {
+                options = options.nested_size_limit(limit);
+            }
+            if io_uring {
+                options = with_io_uring(options, out);
+            }
+            if sandbox {
+                options = sandboxed(options)?;
+            }
+            // Before anything of the archive is read
+            if confine {
+                confine_unpack(&input, &output, out)?;
+            }
+            if cfg!(any(windows, target_os = "macos"))
+                && !reject_collisions
+                && base.is_none()
+                && !is_stdio(&input)
+            {
+                for collision in options.path_collisions(&input)? {
+                    out.warn(tr!(
+                        "warning-path-collision",
+                        first = collision.first.as_str(),
+                        second = collision.second.as_str(),
+                        kind = collision.kind.name()
+                    ));
+                }
+            }
+            let (metadata, has_metadata, report) = if let Some(base) = &base {
+                let metadata = unpack_incremental(&input, base, &output, ignore_unknown)?;
+                (metadata, true, None)
+            } else if let Some(url) = s3_url(&output) {
+                (unpack_to_bucket(&input, url, ignore_unknown)?, true, None)
+            } else if is_stdio(&input) {
+                let (metadata, has_metadata, report) =
+                    options.unpack_from_reader_with_report(io::stdin().lock(), &output)?;
+                (metadata, has_metadata, Some(report))
+            } else {
+                let (metadata, has_metadata, report) =
+                    options.unpack_with_report(&input, &output)?;
+                (metadata, has_metadata, Some(report))
+            };
+            for warning in report.iter().flat_map(|report| &report.warnings) {
+                out.warn(i18n::warning(warning));
+            }
+            if let Some(report) = report.as_ref().filter(|_| recursive.is_some()) {
+                out.status(tr!("unpacked-nested", count = report.nested.len()));
+                out.set("nested", &report.nested)?;
+            }
+            if let Some(report) = report.filter(|report| !report.is_complete()) {
+                // Each failure was logged as a warning while extracting
+                out.status(tr!(
+                    "unpacked-partially",
+                    path = output.display().to_string(),
+                    count = report.extracted
+                ));
+                out.set("output", &output)?;
+                out.set("report", &report)?;
+                let failures = report.failed.len() + usize::from(report.payload_error.is_some());
+                return Err(ProjzstError::IncompleteExtraction(failures));
+            }
+            out.status(tr!("unpacked", path = output.display().to_string()));
+            out.set("output", &output)?;
+            let verified = post_verify && s3_url(&output).is_none();
+            if verified {
+                out.status(tr!("post-verified"));
+            }
+            out.set("verified", verified)?;
+            if !has_metadata {
+                out.status(tr!("no-metadata"));
+                out.set("metadata", None::<&Metadata>)?;
+                return Ok(());
+            }
+            out.status(tr!(
+                "package",
+                name = metadata.name.as_deref().unwrap_or_default(),
+                version = metadata.ver.as_deref().unwrap_or_default()
+            ));
+            out.set("metadata", &metadata)?;
+        }
+
+        Commands::Sync {
+            input,
+            output,
+            delete,
+        } => {
+            let report = if let Some(url) = http_url(&input) {
+                sync_remote(url, &output, delete)?
+            } else if is_stdio(&input) {
+                sync_from_reader(io::stdin().lock(), &output, delete)?
+            } else {
+                sync(&input, &output, delete)?
+            };
+            out.status(tr!(
+                "synced",
+                path = output.display().to_string(),
+                added = report.added.len(),
+                updated = report.updated.len(),
+                removed = report.removed.len(),
+                unchanged = report.unchanged
+            ));
+            out.set("output", &output)?;
+            out.set("report", &report)?;
+        }
+
+        Commands::Info {
+            input,
+            output,
+            query,
+            format,
+            allow_missing_metadata,
+            metadata_magic,
+            ignored,
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            let format_given = format.is_some();
+            let format = match format {
+                Some(name) => MetadataFormat::from_str_tmp(name)?,
+                None => output
+                    .as_ref()
+                    .map(MetadataFormat::from_path)
+                    .unwrap_or_default(),
+            };
+            let mut options =
+                ReadOptions::new(ignore_unknown).allow_missing_metadata(allow_missing_metadata);
+            if let Some(magic) = metadata_magic {
+                options = options.metadata_magic(magic);
+            }
+            let remote = http_url(&input);
+            let read = || -> Result<Metadata, ProjzstError> {
+                Ok(if let Some(url) = remote {
+                    read_metadata_remote(url, ignore_unknown)?
+                } else if is_stdio(&input) {
+                    options.read_metadata_from(io::stdin().lock())?.0
+                } else {
+                    options.read_metadata(&input)?.0
+                })
+            };
+            if let Some(query) = query {
+                let value = read()?.select(&query)?;
+                if out.json {
+                    return out.set("value", &value);
+                }
+                match value {
+                    Some(serde_json::Value::String(s)) => println!("{s}"),
+                    Some(value @ (serde_json::Value::Array(_) | serde_json::Value::Object(_))) => {
+                        println!("{}", serde_json::to_string_pretty(&value)?)
+                    }
+                    Some(value) => println!("{value}"),
+                    None => {}
+                }
+                return Ok(());
+            }
+            let output = match output {
+                Some(output) => output,
+                // A format without an output file prints the metadata in it
+                None if format_given => PathBuf::from("-"),
+                None => {
+                    let metadata = read()?;
+                    if out.json {
+                        return out.set("metadata", &metadata);
+                    }
+                    return print_metadata(&metadata, true);
+                }
+            };
+            if is_stdio(&input)
+                || is_stdio(&output)
+                || remote.is_some()
+                || allow_missing_metadata
+                || metadata_magic.is_some()
+                || format != MetadataFormat::Json
+            {
+                let metadata = read()?;
+                if is_stdio(&output) && out.json {
+                    // The metadata is part of the JSON result
+                    out.set("output", None::<&Path>)?;
+                    out.set("metadata", &metadata)?;
+                    return Ok(());
+                }
+                let content = metadata.export(format)?;
+                if is_stdio(&output) {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(&content)?;
+                    // YAML and TOML end with a newline already
+                    if format == MetadataFormat::Json {
+                        writeln!(stdout)?;
+                    }
+                    return Ok(());
+                }
+                if let Some(parent) = output.parent() {
+                    if !parent.as_os_str().is_empty() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                }
+                std::fs::write(&output, content)?;
+                print_summary(out, &output, metadata)?;
+            } else {
+                let metadata = info(&input, &output, ignore_unknown)?;
+                print_summary(out, &output, metadata)?;
+            }
+        }
+
+        Commands::Diff { old, new } => {
+            let changes = diff(&old, &new)?;
+            if out.json {
+                return out.set("changes", &changes);
+            }
+            for entry in &changes.added {
+                println!("+ {} ({} bytes)", entry.path, entry.size);
+            }
+            for entry in &changes.removed {
+                println!("- {} ({} bytes)", entry.path, entry.size);
+            }
+            for entry in &changes.modified {
+                println!(
+                    "M {} ({} -> {} bytes)",
+                    entry.path, entry.old.size, entry.new.size
+                );
+            }
+            println!("---");
+            println!(
+                "{}",
+                tr!(
+                    "diff-summary",
+                    added = changes.added.len(),
+                    removed = changes.removed.len(),
+                    modified = changes.modified.len()
+                )
+            );
+        }
+
+        Commands::Convert {
+            input,
+            output,
+            sidecar,
+            metadata,
+            level,
+            ignored,
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            match (
+                ArchiveFormat::from_path(&input),
+                ArchiveFormat::from_path(&output),
+            ) {
+                (None, Some(format)) => {
+                    export_archive(&input, &output, format, sidecar, ignore_unknown)?;
+                }
+                (Some(format), None) => {
+                    let metadata_file = metadata.unwrap_or_else(|| sidecar_path(&input));
+                    let metadata = if metadata_file.is_file() {
+                        serde_json::from_str(&std::fs::read_to_string(&metadata_file)?)?
+                    } else {
+                        Metadata::default()
+                    };
+                    import_archive(&input, format, &output, metadata, configured_level(level)?)?;
+                }
+                _ => {
+                    return Err(ProjzstError::UnsupportedConversion(format!(
+                        "{} -> {}",
+                        input.display(),
+                        output.display()
+                    )))
+                }
+            }
+            out.status(tr!("converted", path = output.display().to_string()));
+            out.set("input", &input)?;
+            out.set("output", &output)?;
+        }
+
+        Commands::Verify {
+            input,
+            naming,
+            rule_cmd,
+            rule_wasm,
+            checksum_file,
+            check_signature,
+            check_timestamp,
+            ignored,
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            if let Some(checksum_file) = checksum_file {
+                let checksum_file = checksum_file.unwrap_or_else(|| {
+                    let sha256 = checksum_path(&input, ChecksumAlgorithm::Sha256);
+                    let sha512 = checksum_path(&input, ChecksumAlgorithm::Sha512);
+                    if !sha256.exists() && sha512.exists() {
+                        sha512
+                    } else {
+                        sha256
+                    }
+                });
+                verify_checksum_file(&input, &checksum_file)?;
+                out.status(tr!(
+                    "checksum-matches",
+                    path = checksum_file.display().to_string()
+                ));
+                out.set("checksum_file", &checksum_file)?;
+            }
+            if check_signature {
+                let key = verify_signature(&input, &KeyStore::open()?.verifying_keys()?)?;
+                out.status(tr!("signed-by-key", key = key.id()));
+                out.set("key_id", key.id())?;
+            }
+            if check_timestamp {
+                let stamp = verify_timestamp(&input)?;
+                out.status(tr!("timestamped-at", time = stamp.time.as_str()));
+                out.set("timestamp", &stamp)?;
+            }
+            if let Some(template) = &naming {
+                verify_naming(&input, template, ignore_unknown)?;
+                out.status(tr!("name-matches", template = template.as_str()));
+                out.set("naming", template)?;
+            } else {
+                read_metadata(&input, ignore_unknown)?;
+            }
+            let plugins = rule_plugins(&rule_cmd, &rule_wasm);
+            if !plugins.is_empty() {
+                let report = lint(&input, &LintConfig::plugins_only(plugins))?;
+                if out.json {
+                    out.set("findings", &report.findings)?;
+                } else {
+                    print_findings(&report);
+                }
+                if report.has_errors() {
+                    return Err(ProjzstError::LintFailed(report.count(Severity::Error)));
+                }
+            }
+            let report = validate(&input)?;
+            if let Some(offset) = report.truncated_at {
+                out.warn(tr!("warning-truncated-frame", offset = offset));
+            }
+            if let Some(entry) = &report.damaged_entry {
+                out.warn(tr!("warning-damaged-entry", entry = entry.as_str()));
+            }
+            report.check()?;
+            out.status(tr!(
+                "verified",
+                path = input.display().to_string(),
+                count = report.entries
+            ));
+            out.set("input", &input)?;
+            out.set("entries", report.entries)?;
+        }
+
+        Commands::Repair { input, output } => {
+            let report = repair(&input, &output)?;
+            if let Some(error) = &report.error {
+                match &report.lost_entry {
+                    Some(entry) => out.warn(tr!(
+                        "warning-lost-entries",
+                        entry = entry.as_str(),
+                        error = error.to_string()
+                    )),
+                    None => out.warn(tr!("warning-payload-damaged", error = error.to_string())),
+                }
+            }
+            out.status(tr!(
+                "recovered",
+                count = report.recovered.len(),
+                size = format_size(report.recovered_bytes),
+                path = output.display().to_string()
+            ));
+            out.set("output", &output)?;
+            out.set("report", &report)?;
+        }
+
+        Commands::Lint {
+            input,
+            config,
+            max_file_size,
+            max_extra_size,
+            rule_cmd,
+            rule_wasm,
+        } => {
+            let mut config: LintConfig = match &config {
+                Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+                None => LintConfig::default(),
+            };
+            config.check()?;
+            if let Some(size) = max_file_size {
+                config.max_file_size = size;
+            }
+            if let Some(size) = max_extra_size {
+                config.max_extra_size = size;
+            }
+            config.plugins.extend(rule_plugins(&rule_cmd, &rule_wasm));
+
+            let report = lint(&input, &config)?;
+            if out.json {
+                out.set("report", &report)?;
+            } else {
+                print_findings(&report);
+                println!(
+                    "{}",
+                    tr!(
+                        "lint-summary",
+                        errors = report.count(Severity::Error),
+                        warnings = report.count(Severity::Warning)
+                    )
+                );
+            }
+            if report.has_errors() {
+                return Err(ProjzstError::LintFailed(report.count(Severity::Error)));
+            }
+        }
+
+        Commands::Migrate { input, output } => {
+            let migrated = migrate(&input, &output)? == ArchiveLayout::Legacy;
+            if migrated {
+                out.status(tr!(
+                    "migrated",
+                    input = input.display().to_string(),
+                    output = output.display().to_string()
+                ));
+            } else {
+                out.status(tr!(
+                    "migrate-copied",
+                    input = input.display().to_string(),
+                    output = output.display().to_string()
+                ));
+            }
+            out.set("input", &input)?;
+            out.set("output", &output)?;
+            out.set("migrated", migrated)?;
+        }
+
+        Commands::Merge {
+            inputs,
+            output,
+            conflict,
+            level,
+            strip_foreign_frames: strip,
+        } => {
+            let policy = ConflictPolicy::from_str_tmp(conflict)?;
+            merge(&inputs, &output, policy, configured_level(level)?)?;
+            if strip {
+                strip_foreign_frames(&output, FrameKind::Metadata.magic())?;
+            }
+            out.status(tr!(
+                "merged",
+                count = inputs.len(),
+                path = output.display().to_string()
+            ));
+            out.set("inputs", &inputs)?;
+            out.set("output", &output)?;
+        }
+
+        Commands::Repack {
+            input,
+            output,
+            level,
+            codec,
+            catalog,
+            force,
+        } => {
+            let mut packer = Packer::default()
+                .compression_level(configured_level(level)?)
+                .codec(PayloadCodec::from_str_tmp(codec)?)
+                .overwrite(force);
+            let mut options = ReadOptions::new(IgnoreUnknown::On);
+            if let Some(catalog) = &catalog {
+                let dictionaries = catalog_dictionaries(catalog)?;
+                if let Some(dictionary) = dictionaries.last() {
+                    packer = packer.dictionary(dictionary.clone());
+                }
+                for dictionary in dictionaries {
+                    options = options.dictionary(dictionary);
+                }
+            }
+            let report = packer.repack(&input, &output, &options)?;
+            out.status(tr!(
+                "recompressed",
+                path = output.display().to_string(),
+                before = format_size(report.input_size),
+                after = format_size(report.output_size)
+            ));
+            out.set("output", &output)?;
+            out.set("report", &report)?;
+        }
+
+        Commands::Optimize {
+            input,
+            output,
+            order,
+            level,
+            force,
+        } => {
+            let overwrite = force || output.is_none();
+            let output = output.unwrap_or_else(|| input.clone());
+            let report = Packer::default()
+                .compression_level(configured_level(level)?)
+                .order(EntryOrder::from_str_tmp(order)?)
+                .overwrite(overwrite)
+                .optimize(&input, &output, &ReadOptions::new(IgnoreUnknown::On))?;
+            let saved = report.input_size as i64 - report.output_size as i64;
+            out.status(tr!(
+                "optimized",
+                path = output.display().to_string(),
+                before = format_size(report.input_size),
+                after = format_size(report.output_size),
+                change = format!(
+                    "{:+.1}",
+                    -100.0 * saved as f64 / report.input_size.max(1) as f64
+                )
+            ));
+            out.set("output", &output)?;
+            out.set("report", &report)?;
+        }
+
+        Commands::PackSet {
+            members,
+            store,
+            name,
+            auth,
+            ver,
+            desc,
+            level,
+            exclude,
+        } => {
+            let members = members
+                .iter()
+                .map(|member| match member.split_once('=') {
+                    Some((source, output)) if !source.is_empty() && !output.is_empty() => {
+                        Ok(SetMember::new(source, output))
+                    }
+                    _ => Err(ProjzstError::InvalidArgument(format!(
+                        "pack-set member must be DIR=OUTPUT, got {member:?}"
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let auth = auth.or_else(|| config.author.clone());
+            let mut packer = Packer::new(Metadata::new(name, auth, None, None, ver, desc))
+                .compression_level(configured_level(level)?);
+            for glob in config.exclude.iter().chain(&exclude) {
+                packer = packer.exclude(glob);
+            }
+            let report = pack_set(&packer, &members, &store)?;
+            out.status(tr!(
+                "packed-set",
+                count = members.len(),
+                chunks = report.shared_chunks,
+                size = format_size(report.store_size),
+                path = store.display().to_string()
+            ));
+            for (member, pack) in members.iter().zip(&report.members) {
+                out.status(format!(
+                    "  {} ({})",
+                    member.output.display(),
+                    format_size(pack.compressed_size)
+                ));
+            }
+            out.set("store", &store)?;
+            out.set("report", &report)?;
+        }
+
+        Commands::Bundle { command } => match command {
+            BundleCommands::Create {
+                members,
+                output,
+                name,
+                ver,
+                desc,
+            } => {
+                let metadata = Metadata::new(name, None, None, None, ver, desc);
+                let report = Packer::new(metadata).pack_bundle(&members, &output)?;
+                out.status(tr!(
+                    "bundled",
+                    count = members.len(),
+                    size = format_size(report.compressed_size),
+                    path = output.display().to_string()
+                ));
+                out.set("output", &output)?;
+                out.set("members", bundle_members(&output)?)?;
+            }
+            BundleCommands::List { input } => {
+                let members = bundle_members(&input)?;
+                if out.json {
+                    return out.set("members", &members);
+                }
+                for member in &members {
+                    println!(
+                        "{}  {} {}  {}",
+                        member.path,
+                        member.name.as_deref().unwrap_or("-"),
+                        member.ver.as_deref().unwrap_or("-"),
+                        format_size(member.size)
+                    );
+                }
+            }
+            BundleCommands::Extract {
+                input,
+                member,
+                output,
+            } => {
+                let output = match output {
+                    Some(output) => output,
+                    None => bundle_members(&input)?
+                        .into_iter()
+                        .find(|found| found.is_named(&member))
+                        .map_or_else(|| PathBuf::from(&member), |found| found.path.into()),
+                };
+                let found = extract_member(&input, &member, &output)?;
+                out.status(tr!(
+                    "extracted-member",
+                    member = found.path.as_str(),
+                    path = output.display().to_string()
+                ));
+                out.set("output", &output)?;
+                out.set("member", &found)?;
+            }
+        },
+
+        Commands::List { input, long } => {
+            let entries = list_entries(&input)?;
+            let shares = if long {
+                stat(&input)?.entry_sizes
+            } else {
+                Vec::new()
+            };
+            if out.json {
+                out.set("entries", &entries)?;
+                if long {
+                    out.set("entry_sizes", &shares)?;
+                }
+                return Ok(());
+            }
+            if !long {
+                for entry in &entries {
+                    println!("{}", entry.path);
+                }
+                return Ok(());
+            }
+            let shares: HashMap<&str, &EntryCompression> = shares
+                .iter()
+                .map(|share| (share.path.as_str(), share))
+                .collect();
+            for entry in &entries {
+                let kind = match entry.kind {
+                    EntryKind::File => '-',
+                    EntryKind::Directory => 'd',
+                    EntryKind::Symlink => 'l',
+                    EntryKind::HardLink => 'h',
+                    _ => '?',
+                };
+                let (compressed, ratio) = match shares.get(entry.path.as_str()) {
+                    Some(share) => (
+                        format!(
+                            "{}{}",
+                            if share.estimated { "~" } else { "" },
+                            format_size(share.compressed_size)
+                        ),
+                        format!("{:.2}x", share.ratio()),
+                    ),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                let path = match &entry.link {
+                    Some(link) => format!("{} -> {link}", entry.path),
+                    None => entry.path.clone(),
+                };
+                println!(
+                    "{kind}{:04o} {:>10} {compressed:>11} {ratio:>7}  {path}",
+                    entry.mode,
+                    format_size(entry.size)
+                );
+            }
+        }
+
+        Commands::Cat { input, path, head } => {
+            // The file content goes to stdout
+            out.to_stderr = true;
+            let mut stdout = io::stdout().lock();
+            let written = if let Some(url) = http_url(&input) {
+                cat_remote(url, &path, head, stdout)?
+            } else {
+                match head {
+                    Some(head) => {
+                        let preview = preview_entry(&input, &path, head as usize)?;
+                        stdout.write_all(&preview.data)?;
+                        stdout.flush()?;
+                        preview.data.len() as u64
+                    }
+                    None => write_entry(&input, &path, stdout)?,
+                }
+            };
+            out.set("path", &path)?;
+            out.set("written", written)?;
+        }
+
+        Commands::Grep {
+            input,
+            pattern,
+            path_glob,
+            ignore_case,
+        } => {
+            let mut query = GrepQuery::new(pattern).ignore_case(ignore_case);
+            if let Some(glob) = path_glob {
+                query = query.paths(glob);
+            }
+            let report = grep(&input, &query)?;
+            if out.json {
+                return out.set("report", &report);
+            }
+            for found in &report.matches {
+                println!("{}:{}:{}", found.path, found.line, found.text);
+            }
+            for path in &report.binary_matches {
+                println!("{}", tr!("binary-match", path = path.as_str()));
+            }
+        }
+
+        Commands::Bench {
+            input,
+            levels,
+            thread_counts,
+            sample_size,
+        } => {
+            let report = bench(&input, &levels, &thread_counts, sample_size)?;
+            if out.json {
+                return out.set("report", &report);
+            }
+            println!(
+                "{}",
+                tr!(
+                    "bench-sample",
+                    size = format_size(report.sample_size),
+                    files = report.files
+                )
+            );
+            println!(
+                "{:>5} {:>7} {:>10} {:>7} {:>10}",
+                "level", "threads", "time", "ratio", "memory"
+            );
+            for result in &report.results {
+                println!(
+                    "{:>5} {:>7} {:>9.3}s {:>6.2}x {:>10}",
+                    result.level,
+                    result.threads,
+                    result.duration.as_secs_f64(),
+                    result.ratio(report.sample_size),
+                    format_size(result.memory as u64)
+                );
+            }
+        }
+
+        Commands::Stat { input } => {
+            let stats = stat(&input)?;
+            if out.json {
+                return out.set("stats", &stats);
+            }
+            let size = format_size(stats.file_size);
+            println!("{}", tr!("stat-file-size", size = size));
+            let size = format_size(stats.metadata_size);
+            println!("{}", tr!("stat-metadata-size", size = size));
+            let count = stats.skippable_frames;
+            println!("{}", tr!("stat-skippable-frames", count = count));
+            let size = format_size(stats.payload_size);
+            println!("{}", tr!("stat-payload-size", size = size));
+            let windows = stats.zstd_frames.iter().map(|frame| frame.window_size);
+            let checksums = stats.zstd_frames.iter().filter(|frame| frame.checksum);
+            println!(
+                "{}",
+                tr!(
+                    "stat-zstd-frames",
+                    count = stats.zstd_frames.len(),
+                    window = format_size(windows.max().unwrap_or(0)),
+                    checksums = checksums.count()
+                )
+            );
+            println!("{}", tr!("stat-entries", count = stats.entries));
+            let size = format_size(stats.uncompressed_size);
+            println!("{}", tr!("stat-uncompressed-size", size = size));
+            let mut largest: Vec<_> = stats.entry_sizes.iter().collect();
+            largest.sort_by_key(|share| std::cmp::Reverse(share.compressed_size));
+            if !largest.is_empty() {
+                if largest.iter().any(|share| share.estimated) {
+                    println!("{}", tr!("stat-largest-estimated"));
+                } else {
+                    println!("{}", tr!("stat-largest"));
+                }
+            }
+            for share in largest.iter().take(STAT_LARGEST_FILES) {
+                println!(
+                    "  {:>10} {:>7.2}x  {}",
+                    format_size(share.compressed_size),
+                    share.ratio(),
+                    share.path
+                );
+            }
+        }
+
+        Commands::Sbom {
+            input,
+            output,
+            format,
+        } => {
+            let format = SbomFormat::from_str_tmp(format)?;
+            let document = sbom(&input, format)?;
+            if is_stdio(&output) {
+                // The document goes to stdout
+                out.to_stderr = true;
+                let mut stdout = io::stdout().lock();
+                serde_json::to_writer_pretty(&mut stdout, &document)?;
+                writeln!(stdout)?;
+            } else {
+                let mut text = serde_json::to_string_pretty(&document)?;
+                text.push('\n');
+                fs::write(&output, text)?;
+                out.status(tr!(
+                    "wrote-sbom",
+                    format = format.name(),
+                    input = input.display().to_string(),
+                    path = output.display().to_string()
+                ));
+                out.set("output", &output)?;
+            }
+            out.set("format", format.name())?;
+        }
+
+        Commands::Du { input, depth } => {
+            let usage = disk_usage(&input, depth)?;
+            if out.json {
+                return out.set("directories", &usage);
+            }
+            for directory in &usage {
+                println!("{:>10}  {}", format_size(directory.size), directory.path);
+            }
+        }
+
+        Commands::Browse { input, output } => {
+            let extracted = browse_archive(&input, &output)?;
+            out.set("extracted", extracted)?;
+        }
+
+        Commands::Mount { input, mountpoint } => {
+            if !out.json {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "mounted",
+                        path = input.display().to_string(),
+                        mountpoint = mountpoint.display().to_string()
+                    )
+                );
+            }
+            mount_archive(&input, &mountpoint)?;
+            out.set("mountpoint", &mountpoint)?;
+        }
+
+        Commands::Push {
+            input,
+            registry,
+            token,
+            auth_header,
+        } => {
+            let url = push_archive(&input, &registry, registry_auth(token, auth_header))?;
+            out.status(tr!(
+                "pushed",
+                path = input.display().to_string(),
+                url = url.to_string()
+            ));
+            out.set("input", &input)?;
+            out.set("url", url)?;
+        }
+
+        Commands::Pull {
+            package,
+            registry,
+            output,
+            token,
+            auth_header,
+        } => {
+            let (path, metadata) = pull_package(
+                &package,
+                &registry_url(registry)?,
+                registry_auth(token, auth_header),
+                &output,
+            )?;
+            out.status(tr!(
+                "pulled",
+                package = package.as_str(),
+                path = path.display().to_string()
+            ));
+            out.set("output", &path)?;
+            out.set("metadata", metadata)?;
+        }
+
+        Commands::Index {
+            dir,
+            output,
+            train_dictionary,
+            dictionary_size,
+        } => {
+            let report = build_catalog(&dir, &output)?;
+            for (path, error) in &report.failed {
+                out.warn(tr!(
+                    "warning-catalog-skipped",
+                    path = path.as_str(),
+                    error = error.to_string()
+                ));
+            }
+            out.status(tr!(
+                "cataloged",
+                count = report.archives,
+                path = output.display().to_string(),
+                read = report.indexed,
+                unchanged = report.reused,
+                removed = report.removed
+            ));
+            out.set("output", &output)?;
+            out.set("report", &report)?;
+            if train_dictionary {
+                let size = dictionary_size.map_or(DEFAULT_DICTIONARY_SIZE, |size| size as usize);
+                let dictionary = train_catalog_dictionary(&dir, &output, size)?;
+                out.status(tr!(
+                    "trained-dictionary",
+                    id = format!("{:08x}", dictionary.id),
+                    size = dictionary.size,
+                    samples = dictionary.samples,
+                    path = dictionary.file.as_str()
+                ));
+                out.set("dictionary", &dictionary)?;
+            }
+        }
+
+        Commands::Prune {
+            dir,
+            keep_latest,
+            keep_per_major,
+            dry_run,
+        } => {
+            let policy =
+                RetentionPolicy::new(keep_latest.unwrap_or(0), keep_per_major.unwrap_or(0));
+            let report = prune(&dir, policy, dry_run)?;
+            for (path, error) in &report.skipped {
+                out.warn(tr!(
+                    "warning-prune-kept",
+                    path = path.as_str(),
+                    error = error.to_string()
+                ));
+            }
+            for path in &report.removed {
+                out.status(if dry_run {
+                    tr!("would-remove", path = path.as_str())
+                } else {
+                    tr!("removed", path = path.as_str())
+                });
+            }
+            let count = report.removed.len();
+            let size = format_size(report.removed_bytes);
+            let kept = report.kept.len() + report.skipped.len();
+            out.status(if dry_run {
+                tr!("would-prune", count = count, size = size, kept = kept)
+            } else {
+                tr!("pruned", count = count, size = size, kept = kept)
+            });
+            out.set("report", &report)?;
+        }
+
+        Commands::Search { source, query } => {
+            let hits = search(&source, &MetadataQuery::parse(&query)?)?;
+            if out.json {
+                return out.set("matches", &hits);
+            }
+            for hit in &hits {
+                let metadata = &hit.metadata;
+                println!(
+                    "{}  {} {}",
+                    hit.path,
+                    metadata.name.as_deref().unwrap_or("-"),
+                    metadata.ver.as_deref().unwrap_or("-")
+                );
+            }
+        }
+
+        Commands::Serve { dir, address } => {
+            if !out.json {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "serving",
+                        path = dir.display().to_string(),
+                        url = format!("http://{address}/archives")
+                    )
+                );
+            }
+            serve_directory(&dir, &address)?;
+        }
+
+        Commands::Frames {
+            input,
+            strip_foreign,
+            metadata_magic,
+        } => {
+            if strip_foreign {
+                let magic = metadata_magic.unwrap_or(FrameKind::Metadata.magic());
+                let removed = strip_foreign_frames(&input, magic)?;
+                out.status(tr!("removed-foreign-frames", count = removed));
+                return out.set("removed", removed);
+            }
+            let frames = frames(&input)?;
+            if out.json {
+                let listing: Vec<serde_json::Value> = frames
+                    .iter()
+                    .map(|frame| {
+                        serde_json::json!({
+                            "kind": frame.kind().name(),
+                            "magic": frame.magic,
+                            "size": frame.payload.len(),
+                        })
+                    })
+                    .collect();
+                out.set("frames", listing)?;
+            } else {
+                for frame in &frames {
+                    println!(
+                        "{:<10} {:#010X} {} bytes",
+                        frame.kind().name(),
+                        frame.magic,
+                        frame.payload.len()
+                    );
+                }
+            }
+        }
+
+        Commands::Provenance { input } => {
+            let recorded = provenance(&input)?;
+            match &recorded {
+                Some(recorded) if !out.json => {
+                    let fields = [
+                        ("Host", recorded.hostname.as_deref()),
+                        ("OS", Some(recorded.os.as_str())),
+                        ("Tool", Some(recorded.tool.as_str())),
+                        ("Packed", Some(recorded.timestamp.as_str())),
+                        ("Commit", recorded.git_commit.as_deref()),
+                    ];
+                    for (label, value) in fields {
+                        println!("{label:<7} {}", value.unwrap_or("-"));
+                    }
+                }
+                None if !out.json => eprintln!(
+                    "{}",
+                    tr!("no-provenance", path = input.display().to_string())
+                ),
+                _ => {}
+            }
+            out.set("provenance", recorded)?;
+        }
+
+        Commands::Signature {
+            input,
+            output,
+            token,
+        } => {
+            let digest = signed_digest(&input)?;
+            let stored = signature(&input)?;
+            let stamp = timestamp(&input)?;
+            if !out.json {
+                println!("sha256:{digest}");
+                if let Some(stamp) = &stamp {
+                    println!("timestamp {}", stamp.time);
+                }
+            }
+            match (&stored, &output) {
+                (Some(stored), Some(path)) => {
+                    fs::write(path, stored)?;
+                    out.status(tr!("wrote", path = path.display().to_string()));
+                }
+                (None, _) => out.warn(tr!("no-signature", path = input.display().to_string())),
+                _ => {}
+            }
+            match (&stamp, &token) {
+                (Some(stamp), Some(path)) => {
+                    fs::write(path, &stamp.token)?;
+                    out.status(tr!("wrote", path = path.display().to_string()));
+                }
+                (None, Some(_)) => {
+                    out.warn(tr!("no-timestamp", path = input.display().to_string()))
+                }
+                _ => {}
+            }
+            out.set("digest", format!("sha256:{digest}"))?;
+            out.set("signed", stored.is_some())?;
+            out.set("timestamp", &stamp)?;
+        }
+
+        Commands::Key { command } => {
+            let store = KeyStore::open()?;
+            match command {
+                KeyCommands::Generate { name, force } => {
+                    let key = store.generate(&name, force)?;
+                    out.status(tr!("generated-key", name = name.as_str(), key = key.id()));
+                    out.set("name", &name)?;
+                    out.set("key_id", key.id())?;
+                }
+                KeyCommands::Export { name, output } => {
+                    let key = store.public_key(&name)?;
+                    match &output {
+                        Some(path) => {
+                            fs::write(path, key.to_text() + "\n")?;
+                            out.status(tr!("wrote", path = path.display().to_string()));
+                        }
+                        None if !out.json => println!("{}", key.to_text()),
+                        None => {}
+                    }
+                    out.set("key", key.to_text())?;
+                    out.set("key_id", key.id())?;
+                }
+                KeyCommands::List => {
+                    let keys = store.list()?;
+                    if !out.json {
+                        for key in &keys {
+                            let kind = if key.own { "own" } else { "trusted" };
+                            println!("{:<8} {} {}", kind, key.id, key.name);
+                        }
+                    }
+                    out.set("keys", &keys)?;
+                }
+                KeyCommands::Trust { file, name } => {
+                    let name = match name {
+                        Some(name) => name,
+                        None => file
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    };
+                    let key = store.trust(&file, &name)?;
+                    out.status(tr!("trusted-key", name = name.as_str(), key = key.id()));
+                    out.set("name", &name)?;
+                    out.set("key_id", key.id())?;
+                }
+            }
+        }
+
+        Commands::Completions { shell } => {
+            // Generated in memory, as the generator panics on a closed stdout
+            let mut script = Vec::new();
+            clap_complete::generate(shell, &mut Cli::command(), "projzst", &mut script);
+            io::stdout().write_all(&script)?;
+        }
+
+        Commands::Man { out_dir } => match out_dir {
+            Some(dir) => {
+                fs::create_dir_all(&dir)?;
+                clap_mangen::generate_to(Cli::command(), &dir)?;
+                out.status(tr!("man-pages-written", path = dir.display().to_string()));
+                out.set("output", &dir)?;
+            }
+            None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?,
+        },
+
+        Commands::External(args) => {
+            // The external subcommand owns the output
+            out.silent = true;
+            let status = run_external(args)?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+
+        Commands::Comment { command } => match command {
+            CommentCommands::Get { input } => {
+                let text = comment(&input)?;
+                match &text {
+                    Some(text) => out.status(text),
+                    None if !out.json => {
+                        eprintln!("{}", tr!("no-comment", path = input.display().to_string()))
+                    }
+                    None => {}
+                }
+                out.set("comment", text)?;
+            }
+            CommentCommands::Set { input, text } => {
+                let text = if text == "-" {
+                    let mut buf = String::new();
+                    io::stdin().read_to_string(&mut buf)?;
+                    buf
+                } else {
+                    text
+                };
+                set_comment(&input, Some(&text))?;
+                out.status(tr!("comment-set", path = input.display().to_string()));
+                out.set("input", &input)?;
+            }
+            CommentCommands::Clear { input } => {
+                set_comment(&input, None)?;
+                out.status(tr!("comment-removed", path = input.display().to_string()));
+                out.set("input", &input)?;
+            }
+        },
+
+        Commands::Icon { command } => match command {
+            IconCommands::Get { input, output } => {
+                let Some(icon) = icon(&input)? else {
+                    if !out.json {
+                        eprintln!("{}", tr!("no-icon", path = input.display().to_string()));
+                    }
+                    return out.set("icon", None::<()>);
+                };
+                if is_stdio(&output) {
+                    // The image goes to stdout
+                    out.to_stderr = true;
+                    io::stdout().lock().write_all(&icon.data)?;
+                } else {
+                    std::fs::write(&output, &icon.data)?;
+                    let media_type = icon
+                        .media_type()
+                        .map_or_else(|| tr!("unknown-media-type"), str::to_string);
+                    out.status(tr!(
+                        "icon-saved",
+                        path = output.display().to_string(),
+                        media_type = media_type
+                    ));
+                }
+                out.set(
+                    "icon",
+                    serde_json::json!({
+                        "output": (!is_stdio(&output)).then_some(&output),
+                        "media_type": icon.media_type(),
+                        "size": icon.data.len(),
+                    }),
+                )?;
+            }
+            IconCommands::Set { input, image } => {
+                let icon = Icon::new(std::fs::read(&image)?)?;
+                set_icon(&input, Some(&icon))?;
+                out.status(tr!("icon-set", path = input.display().to_string()));
+                out.set("input", &input)?;
+            }
+            IconCommands::Clear { input } => {
+                set_icon(&input, None)?;
+                out.status(tr!("icon-removed", path = input.display().to_string()));
+                out.set("input", &input)?;
+            }
+        },
+
+        Commands::Attach { command } => match command {
+            AttachCommands::Add { input, file, name } => {
+                let name = match name {
+                    Some(name) => name,
+                    None if is_stdio(&file) => {
+                        return Err(ProjzstError::InvalidArgument(
+                            "--name is required when attaching stdin".to_string(),
+                        ))
+                    }
+                    None => file
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                };
+                let data = if is_stdio(&file) {
+                    let mut data = Vec::new();
+                    io::stdin().read_to_end(&mut data)?;
+                    data
+                } else {
+                    std::fs::read(&file)?
+                };
+                let attachment = Attachment::new(name, data)?;
+                add_attachment(&input, &attachment)?;
+                out.status(tr!(
+                    "attached",
+                    name = attachment.name.as_str(),
+                    size = attachment.data.len(),
+                    path = input.display().to_string()
+                ));
+                out.set("input", &input)?;
+                out.set("name", &attachment.name)?;
+                out.set("size", attachment.data.len())?;
+            }
+            AttachCommands::Get {
+                input,
+                name,
+                output,
+            } => {
+                let attachment = attachment(&input, &name)?.ok_or_else(|| {
+                    ProjzstError::Io(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("no attachment named {name:?} in {}", input.display()),
+                    ))
+                })?;
+                if is_stdio(&output) {
+                    // The file goes to stdout
+                    out.to_stderr = true;
+                    io::stdout().lock().write_all(&attachment.data)?;
+                } else {
+                    std::fs::write(&output, &attachment.data)?;
+                    out.status(tr!(
+                        "attachment-saved",
+                        path = output.display().to_string(),
+                        size = attachment.data.len()
+                    ));
+                }
+                out.set("output", (!is_stdio(&output)).then_some(&output))?;
+                out.set("size", attachment.data.len())?;
+            }
+            AttachCommands::List { input } => {
+                let attachments: Vec<_> = attachments(&input)?
+                    .into_iter()
+                    .map(|attachment| (attachment.name, attachment.data.len()))
+                    .collect();
+                if out.json {
+                    let list: Vec<_> = attachments
+                        .iter()
+                        .map(|(name, size)| serde_json::json!({ "name": name, "size": size }))
+                        .collect();
+                    return out.set("attachments", list);
+                }
+                for (name, size) in &attachments {
+                    println!("{name} ({})", format_size(*size as u64));
+                }
+            }
+            AttachCommands::Remove { input, name } => {
+                let removed = remove_attachment(&input, &name)?;
+                if removed {
+                    out.status(tr!("attachment-removed", name = name.as_str()));
+                } else {
+                    out.warn(tr!("no-attachment", name = format!("{name:?}")));
+                }
+                out.set("input", &input)?;
+                out.set("removed", removed)?;
+            }
+        },
+
+        Commands::Meta {
+            command: MetaCommands::Diff { old, new, ignored },
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            let changes = diff_archive_metadata(&old, &new, ignore_unknown)?;
+            if out.json {
+                return out.set("changes", &changes);
+            }
+            for change in &changes.changes {
+                match (&change.old, &change.new) {
+                    (None, Some(value)) => println!("+ {}: {}", change.field, value),
+                    (Some(value), None) => println!("- {}: {}", change.field, value),
+                    (Some(old_value), Some(new_value)) => {
+                        println!("~ {}: {} -> {}", change.field, old_value, new_value)
+                    }
+                    (None, None) => {}
+                }
+            }
+            if changes.is_empty() {
+                println!("{}", tr!("metadata-identical"));
+            }
+        }
+
+        Commands::Meta {
+            command: MetaCommands::Set { input, metadata },
+        } => {
+            let content = if is_stdio(&metadata) {
+                let mut content = String::new();
+                io::stdin().read_to_string(&mut content)?;
+                content
+            } else {
+                std::fs::read_to_string(&metadata)?
+            };
+            let metadata: Metadata = serde_json::from_str(&content)?;
+            let revision = append_metadata(&input, &metadata)?;
+            out.status(tr!(
+                "appended-revision",
+                revision = revision,
+                path = input.display().to_string()
+            ));
+            out.set("input", &input)?;
+            out.set("revision", revision)?;
+        }
+
+        Commands::Meta {
+            command:
+                MetaCommands::Bump {
+                    input,
+                    major,
+                    minor,
+                    patch,
+                    pre,
+                },
+        } => {
+            let part = if major {
+                Some(VersionPart::Major)
+            } else if minor {
+                Some(VersionPart::Minor)
+            } else if patch {
+                Some(VersionPart::Patch)
+            } else {
+                None
+            };
+            let (previous, version) = bump_version(&input, part, pre.as_deref())?;
+            out.status(tr!(
+                "bumped",
+                path = input.display().to_string(),
+                previous = previous.to_string(),
+                version = version.to_string()
+            ));
+            out.set("input", &input)?;
+            out.set("previous", previous.to_string())?;
+            out.set("version", version.to_string())?;
+        }
+
+        Commands::Meta {
+            command: MetaCommands::History { input, ignored },
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            let history = metadata_history(&input, ignore_unknown)?;
+            if out.json {
+                return out.set("revisions", &history);
+            }
+            for revision in &history {
+                println!(
+                    "{}: {} v{}",
+                    revision.revision,
+                    revision.metadata.name.as_deref().unwrap_or_default(),
+                    revision.metadata.ver.as_deref().unwrap_or_default()
+                );
+            }
+        }
+
+        Commands::Meta {
+            command:
+                MetaCommands::Dump {
+                    input,
+                    output,
+                    metadata_magic,
+                },
+        } => {
+            let magic = metadata_magic.unwrap_or(FrameKind::Metadata.magic());
+            let frames = metadata_frames(&input, magic)?;
+            let bytes: Vec<u8> = frames
+                .iter()
+                .flat_map(|frame| frame.payload.clone())
+                .collect();
+            if is_stdio(&output) {
+                // The metadata goes to stdout
+                out.to_stderr = true;
+                io::stdout().lock().write_all(&bytes)?;
+            } else {
+                std::fs::write(&output, &bytes)?;
+                out.status(tr!(
+                    "metadata-dumped",
+                    path = output.display().to_string(),
+                    size = bytes.len(),
+                    frames = frames.len()
+                ));
+            }
+            out.set("output", (!is_stdio(&output)).then_some(&output))?;
+            out.set("size", bytes.len())?;
+            out.set("frames", frames.len())?;
+        }
+
+        Commands::Meta {
+            command:
+                MetaCommands::Show {
+                    input,
+                    revision,
+                    ignored,
+                },
+        } => {
+            let ignore_unknown = IgnoreUnknown::from_str_tmp(ignored)?;
+            let metadata = match revision {
+                Some(revision) => read_metadata_revision(&input, revision, ignore_unknown)?,
+                None => read_metadata(&input, ignore_unknown)?,
+            };
+            if out.json {
+                return out.set("metadata", &metadata);
+            }
+            println!("{}", serde_json::to_string_pretty(&metadata)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Arguments of an external command given as one string, split on whitespace
+fn command_argv(cmd: &str) -> Vec<String> {
+    cmd.split_whitespace().map(String::from).collect()
+}
+
+/// Lint plugins given on the command line (commands are split on whitespace)
+fn rule_plugins(rule_cmd: &[String], rule_wasm: &[PathBuf]) -> Vec<LintPlugin> {
+    let commands = rule_cmd
+        .iter()
+        .map(|cmd| LintPlugin::Command(command_argv(cmd)));
+    let modules = rule_wasm.iter().cloned().map(LintPlugin::Wasm);
+    commands.chain(modules).collect()
+}
+
+/// Print one line per lint finding
+fn print_findings(report: &LintReport) {
+    for finding in &report.findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            _ => "info",
+        };
+        match &finding.path {
+            Some(path) => println!(
+                "{}[{}] {}: {}",
+                severity, finding.rule, path, finding.message
+            ),
+            None => println!("{}[{}] {}", severity, finding.rule, finding.message),
+        }
+    }
+}
+
+/// Internal helper: a byte count in the largest binary unit it fills, e.g. `12.3 MiB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Internal helper: the `pack --stats` summary, one line per item
+fn format_stats(report: &PackReport) -> String {
+    let mut stats = tr!(
+        "stats",
+        files = report.files,
+        original = format_size(report.original_size),
+        compressed = format_size(report.compressed_size),
+        ratio = format!("{:.2}", report.ratio())
+    );
+    stats.push('\n');
+    if report.deduplicated_size > 0 {
+        let size = format_size(report.deduplicated_size);
+        stats.push_str(&tr!("stats-deduplicated", size = size));
+        stats.push('\n');
+    }
+    if report.resumed > 0 {
+        stats.push_str(&tr!("stats-resumed", count = report.resumed));
+        stats.push('\n');
+    }
+    for extension in &report.extensions {
+        let name = if extension.extension.is_empty() {
+            "(none)".to_string()
+        } else {
+            format!(".{}", extension.extension)
+        };
+        let share = extension.size as f64 * 100.0 / report.original_size.max(1) as f64;
+        stats.push_str(&format!(
+            "  {name:<12} {:>6} files {:>10} {share:>5.1}%\n",
+            extension.files,
+            format_size(extension.size)
+        ));
+    }
+    stats
+}
+
+/// Print the human-readable summary shown after `info`, or with `--json` set the output
+/// path and metadata
+fn print_summary(out: &mut Output, output: &Path, metadata: Metadata) -> Result<(), ProjzstError> {
+    if out.json {
+        out.set("output", output)?;
+        return out.set("metadata", &metadata);
+    }
+    println!(
+        "{}",
+        tr!("metadata-saved", path = output.display().to_string())
+    );
+    println!("---");
+    print_metadata(&metadata, false)
+}
+
+/// Print the fields of the metadata, one per line; `full` adds the links, keywords,
+/// dependencies and `extra`
+fn print_metadata(metadata: &Metadata, full: bool) -> Result<(), ProjzstError> {
+    if let Some(name) = &metadata.name {
+        println!("{}", tr!("field-name", value = name.as_str()));
+    }
+    if let Some(author) = &metadata.auth {
+        println!("{}", tr!("field-author", value = author.as_str()));
+    }
+    if let Some(version) = &metadata.ver {
+        println!("{}", tr!("field-version", value = version.as_str()));
+    }
+    if let Some(format) = &metadata.fmt {
+        match &metadata.ed {
+            Some(edition) => println!(
+                "{}",
+                tr!(
+                    "field-format-edition",
+                    value = format.as_str(),
+                    edition = edition.as_str()
+                )
+            ),
+            None => println!("{}", tr!("field-format", value = format.as_str())),
+        }
+    }
+    if let Some(description) = &metadata.desc {
+        println!("{}", tr!("field-description", value = description.as_str()));
+    }
+    if !full {
+        return Ok(());
+    }
+    if let Some(license) = &metadata.license {
+        println!("{}", tr!("field-license", value = license.as_str()));
+    }
+    if let Some(homepage) = &metadata.homepage {
+        println!("{}", tr!("field-homepage", value = homepage.as_str()));
+    }
+    if let Some(repository) = &metadata.repository {
+        println!("{}", tr!("field-repository", value = repository.as_str()));
+    }
+    if !metadata.keywords.is_empty() {
+        let keywords = metadata.keywords.join(", ");
+        println!("{}", tr!("field-keywords", value = keywords));
+    }
+    if !metadata.deps.is_empty() {
+        let deps: Vec<String> = metadata
+            .deps
+            .iter()
+            .map(|dep| format!("{} {}", dep.name, dep.req))
+            .collect();
+        println!("{}", tr!("field-dependencies", value = deps.join(", ")));
+    }
+    let empty = match &metadata.extra {
+        serde_json::Value::Null => true,
+        serde_json::Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    };
+    if !empty {
+        let extra = serde_json::to_string_pretty(&metadata.extra)?;
+        println!("{}", tr!("field-extra", value = extra));
+    }
+    Ok(())
+}
+
+/// Exit code of command-line usage errors, as `ProjzstError::exit_code` gives for invalid
+/// option values (clap's own 2 would read as an invalid archive)
+const EXIT_USAGE: u8 = 64;
+
+/// Logger printing the library's per-entry records to stderr, one line each
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                // --help and --version
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, 2) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+    i18n::init(if cli.json {
+        Some("en")
+    } else {
+        cli.lang.as_deref()
+    });
+    let mut out = Output::new(cli.json, cli.quiet);
+    let result = run(cli, &mut out);
+    if let Err(e) = &result {
+        if !out.json || out.silent {
+            eprintln!("{}", i18n::error_line(e));
+        }
+    }
+    out.finish(result.as_ref().err());
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => ExitCode::from(e.exit_code()),
     }
 }
@@ -0,0 +1,133 @@
+//! Memory-mapped archive reading
+//!
+//! A [`MappedArchive`] maps a .pjz file into memory instead of reading it through a
+//! buffer. Frames are handed out as [`FrameSlice`]s borrowing the mapping, so large
+//! frames (icons, indexes, build logs) are never copied, and the whole file is one byte
+//! slice that any reader API accepts, such as
+//! [`ReadOptions::unpack_from_reader`](crate::ReadOptions::unpack_from_reader). Pages are
+//! loaded by the kernel as they are touched and shared with the page cache, so decoding a
+//! very large payload does not buffer it twice.
+
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::builder::read_metadata_from;
+use crate::errors::{ProjzstError, Result};
+use crate::frames::{FrameKind, RawFrame};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::sfx::read_stub;
+
+/// Size of a skippable frame header: magic and payload size
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// A .pjz file mapped into memory
+///
+/// The file must not be modified while mapped: changes made by other processes show up
+/// in the slices handed out, and truncating it makes reading them fault. Only single
+/// files can be mapped, not split archives.
+#[derive(Debug)]
+pub struct MappedArchive {
+    map: Mmap,
+    /// Leading frames: magic and byte range of the payload, never empty
+    frames: Vec<(u32, Range<usize>)>,
+}
+
+/// One skippable frame borrowed from a [`MappedArchive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameSlice<'a> {
+    /// Frame magic number, `0x184D2A50` for metadata
+    pub magic: u32,
+    /// Frame content, in place in the mapping
+    pub payload: &'a [u8],
+}
+
+impl FrameSlice<'_> {
+    /// Kind of the frame according to its magic
+    pub fn kind(&self) -> FrameKind {
+        FrameKind::from_magic(self.magic).expect("frames are read from the skippable range")
+    }
+
+    /// Copy the frame out of the mapping
+    pub fn to_raw(&self) -> RawFrame {
+        RawFrame {
+            magic: self.magic,
+            payload: self.payload.to_vec(),
+        }
+    }
+}
+
+impl MappedArchive {
+    /// Map a .pjz file and locate its leading frames
+    /// Fails with [`ProjzstError::InvalidFileHeader`] when the file does not start with a
+    /// complete skippable frame
+    ///
+    /// # Arguments
+    /// * `input_file` - Path to the .pjz file
+    pub fn open<P: AsRef<Path>>(input_file: P) -> Result<Self> {
+        let file = File::open(input_file.as_ref())?;
+        // SAFETY: the mapping is read-only; the caller keeps the file unchanged while it
+        // is mapped, as documented on the type
+        let map = unsafe { Mmap::map(&file)? };
+        let frames = locate_frames(&map)?;
+        Ok(Self { map, frames })
+    }
+
+    /// Every skippable frame before the payload, in file order
+    pub fn frames(&self) -> Vec<FrameSlice<'_>> {
+        self.frames
+            .iter()
+            .map(|(magic, range)| FrameSlice {
+                magic: *magic,
+                payload: &self.map[range.clone()],
+            })
+            .collect()
+    }
+
+    /// Parse the metadata stored in the mapped frames
+    ///
+    /// # Arguments
+    /// * `ignore_unknown` - Policy for metadata fields this version does not know
+    pub fn metadata(&self, ignore_unknown: IgnoreUnknown) -> Result<Metadata> {
+        read_metadata_from(self.as_bytes(), ignore_unknown)
+    }
+
+    /// The zstd payload: every byte after the leading frames
+    pub fn payload(&self) -> &[u8] {
+        let end = self.frames.last().map_or(0, |(_, range)| range.end);
+        &self.map[end..]
+    }
+
+    /// The whole file, frames and payload
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+/// Internal helper: magic and payload byte range of the leading skippable frames
+fn locate_frames(bytes: &[u8]) -> Result<Vec<(u32, Range<usize>)>> {
+    let mut frames = Vec::new();
+    let (stub, _) = read_stub(&mut &bytes[..])?;
+    let mut offset = stub.len();
+    while let Some(header) = bytes.get(offset..offset + FRAME_HEADER_SIZE) {
+        let magic = u32::from_le_bytes(header[..4].try_into().expect("4-byte slice"));
+        if FrameKind::from_magic(magic).is_none() {
+            break;
+        }
+        let size = u32::from_le_bytes(header[4..].try_into().expect("4-byte slice")) as usize;
+        let start = offset + FRAME_HEADER_SIZE;
+        if bytes.len() - start < size {
+            return Err(ProjzstError::InvalidFileHeader);
+        }
+        frames.push((magic, start..start + size));
+        offset = start + size;
+    }
+
+    if frames.is_empty() {
+        return Err(ProjzstError::InvalidFileHeader);
+    }
+    Ok(frames)
+}
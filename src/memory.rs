@@ -0,0 +1,150 @@
+//! In-memory archives
+//!
+//! [`pack_to_vec`] and [`unpack_from_slice`] build and read a whole .pjz archive held in
+//! memory, without touching the file system or the clock. They are the part of the crate
+//! meant for `wasm32-unknown-unknown`, where a browser can inspect a .pjz file (or build
+//! one) before it is uploaded; [`read_metadata_from`](crate::read_metadata_from) reads the
+//! metadata alone from a byte slice the same way.
+//!
+//! Only regular files travel through memory: directories are implied by the paths of the
+//! files they hold, and links and special entries are left out when unpacking. Chunked
+//! payloads are put back together in memory.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use crate::builder::{encode_metadata, open_archive, write_metadata_frame, METADATA_FRAME_MAGIC};
+use crate::chunking::{chunk_digest, chunk_list};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata, MetadataEncoding};
+use crate::payload::check_compression_level;
+use crate::storage::normalize_path;
+
+/// A file held in memory, as packed by [`pack_to_vec`] or unpacked by [`unpack_from_slice`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemoryFile {
+    /// Path relative to the archive root, `/`-separated
+    pub path: String,
+    /// Content of the file
+    pub contents: Vec<u8>,
+    /// Permission bits, e.g. `0o644`
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: u64,
+}
+
+impl MemoryFile {
+    /// Create a file with mode `0o644` and a modification time of `0`
+    ///
+    /// # Arguments
+    /// * `path` - Path relative to the archive root, `/`-separated
+    /// * `contents` - Content of the file
+    pub fn new(path: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        Self {
+            path: path.into(),
+            contents: contents.into(),
+            mode: 0o644,
+            mtime: 0,
+        }
+    }
+
+    /// Set the permission bits
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the modification time, in seconds since the Unix epoch
+    pub fn mtime(mut self, mtime: u64) -> Self {
+        self.mtime = mtime;
+        self
+    }
+}
+
+/// Pack files held in memory into a .pjz archive, returned as bytes
+/// Metadata is stored as MessagePack; paths that are absolute or climb out of the archive
+/// root fail with [`ProjzstError::InvalidMemoryPath`]
+///
+/// # Arguments
+/// * `files` - Files to store, in archive order
+/// * `metadata` - Metadata to embed
+/// * `compression_level` - Zstd compression level
+pub fn pack_to_vec(
+    files: &[MemoryFile],
+    metadata: &Metadata,
+    compression_level: i32,
+) -> Result<Vec<u8>> {
+    check_compression_level(compression_level)?;
+    let mut archive = Vec::new();
+    let metadata_bytes = encode_metadata(metadata, MetadataEncoding::MessagePack)?;
+    write_metadata_frame(&mut archive, METADATA_FRAME_MAGIC, &metadata_bytes)?;
+
+    let encoder = zstd::stream::Encoder::new(archive, compression_level)?;
+    let mut tar_builder = tar::Builder::new(encoder);
+    for file in files {
+        let path = normalize_path(file.path.as_ref())
+            .ok_or_else(|| ProjzstError::InvalidMemoryPath(file.path.clone()))?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(file.contents.len() as u64);
+        header.set_mode(file.mode);
+        header.set_mtime(file.mtime);
+        tar_builder.append_data(&mut header, path, file.contents.as_slice())?;
+    }
+    let archive = tar_builder.into_inner()?.finish()?;
+    Ok(archive)
+}
+
+/// Unpack a .pjz archive held in memory, returning its metadata and files
+/// Directories, links and special entries are left out
+///
+/// # Arguments
+/// * `archive` - The whole .pjz archive
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn unpack_from_slice(
+    archive: &[u8],
+    ignore_unknown: IgnoreUnknown,
+) -> Result<(Metadata, Vec<MemoryFile>)> {
+    let (metadata, mut tar_archive) = open_archive(archive, ignore_unknown)?;
+    let mut chunks: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut files = Vec::new();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if let Some(digest) = chunk_digest(&entry) {
+            let mut chunk = Vec::new();
+            entry.read_to_end(&mut chunk)?;
+            chunks.insert(digest, chunk);
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        let mode = entry.header().mode()?;
+        let mtime = entry.header().mtime()?;
+        let mut contents = Vec::new();
+        match chunk_list(&mut entry)? {
+            Some(list) => {
+                for digest in &list {
+                    let chunk = chunks
+                        .get(digest)
+                        .ok_or_else(|| ProjzstError::InvalidChunk(digest.clone()))?;
+                    contents.write_all(chunk)?;
+                }
+            }
+            None => {
+                io::copy(&mut entry, &mut contents)?;
+            }
+        }
+        files.push(MemoryFile {
+            path,
+            contents,
+            mode,
+            mtime,
+        });
+    }
+    Ok((metadata, files))
+}
@@ -0,0 +1,236 @@
+//! Merging several archives into one
+//!
+//! Inputs are layered in the order given: entries are copied into a single tar.zst payload
+//! and their metadata is folded into one record (see [`merge`] for the rules).
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+
+use crate::builder::{
+    encode_metadata, open_archive, read_metadata, write_metadata_frame, METADATA_FRAME_MAGIC,
+};
+use crate::concurrency::Concurrency;
+use crate::errors::{ProjzstError, Result};
+use crate::frames::{foreign_frames, RawFrame};
+use crate::incremental::INCREMENTAL_KEY;
+use crate::metadata::{IgnoreUnknown, Metadata, MetadataEncoding};
+use crate::payload::{check_compression_level, PayloadEncoder};
+use crate::storage::normalize_path;
+use crate::string_utils::IntoOpStr;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+
+/// What to do when several inputs contain a file at the same path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ConflictPolicy {
+    /// Fail with [`ProjzstError::MergeConflict`] (default)
+    #[default]
+    Error,
+    /// Keep the entry from the input listed last
+    PreferLast,
+    /// Place each input's entries under a directory named after the input file stem
+    PrefixPerSource,
+}
+
+impl ConflictPolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "error" => Ok(ConflictPolicy::Error),
+            "prefer-last" | "last" => Ok(ConflictPolicy::PreferLast),
+            "prefix-per-source" | "prefix" => Ok(ConflictPolicy::PrefixPerSource),
+            _ => Err(ProjzstError::InvalidConflictPolicy(s.to_string())),
+        }
+    }
+}
+
+/// Merge several .pjz files into a single archive
+/// Metadata fields take the value of the last input that sets them, keywords are
+/// combined, dependencies are merged by name, and `extra` objects are merged recursively
+/// with later inputs winning; directories present in several
+/// inputs are never conflicts. Foreign frames of the inputs (see
+/// [`RawFrame::is_foreign`]) are carried over once each. Incremental archives cannot be
+/// merged.
+///
+/// # Arguments
+/// * `input_files` - Paths to the .pjz files, lowest layer first
+/// * `output_file` - Path to output .pjz file
+/// * `policy` - How to handle files present in more than one input
+/// * `compression_level` - Zstd compression level
+pub fn merge<P1, P2>(
+    input_files: &[P1],
+    output_file: P2,
+    policy: ConflictPolicy,
+    compression_level: i32,
+) -> Result<Metadata>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    check_compression_level(compression_level)?;
+    // Metadata precedes the payload, so every input's metadata is read up front
+    let mut merged = Metadata::default();
+    let mut foreign: Vec<RawFrame> = Vec::new();
+    for input in input_files {
+        let input = input.as_ref();
+        let metadata = read_metadata(input, IgnoreUnknown::On)?;
+        if metadata.extra.get(INCREMENTAL_KEY).is_some() {
+            return Err(ProjzstError::IncrementalMerge(input.display().to_string()));
+        }
+        merge_metadata(&mut merged, metadata);
+        for frame in foreign_frames(input, METADATA_FRAME_MAGIC) {
+            if !foreign.contains(&frame) {
+                foreign.push(frame);
+            }
+        }
+    }
+
+    // The output may be one of the inputs, so it is only replaced once merging is done
+    let (mut output, temp) = temp_file_for(output_file.as_ref())?;
+    write_metadata_frame(
+        &mut output,
+        METADATA_FRAME_MAGIC,
+        &encode_metadata(&merged, MetadataEncoding::MessagePack)?,
+    )?;
+    for frame in &foreign {
+        frame.write_to(&mut output)?;
+    }
+
+    let mut zst_encoder = PayloadEncoder::new(
+        &mut output,
+        compression_level,
+        Concurrency::current_default().compression_workers(),
+    )?;
+    {
+        let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+        let mut written = BTreeSet::new();
+
+        // With prefer-last, the top layer is copied first and shadowed paths are skipped
+        let mut inputs: Vec<&Path> = input_files.iter().map(AsRef::as_ref).collect();
+        if policy == ConflictPolicy::PreferLast {
+            inputs.reverse();
+        }
+
+        for input in inputs {
+            let prefix = match policy {
+                ConflictPolicy::PrefixPerSource => input
+                    .file_stem()
+                    .map(|stem| format!("{}/", stem.to_string_lossy())),
+                _ => None,
+            };
+            copy_entries(
+                &mut tar_builder,
+                input,
+                prefix.as_deref().unwrap_or_default(),
+                policy,
+                &mut written,
+            )?;
+        }
+        tar_builder.finish()?;
+    }
+    zst_encoder.finish()?.flush()?;
+    drop(output);
+    temp.persist(output_file.as_ref())?;
+
+    Ok(merged)
+}
+
+/// Internal helper: copy the entries of one input into the merged tar stream
+/// `written` holds every path already copied, to detect conflicts and shadowed entries
+fn copy_entries<W: Write>(
+    tar_builder: &mut tar::Builder<W>,
+    input: &Path,
+    prefix: &str,
+    policy: ConflictPolicy,
+    written: &mut BTreeSet<String>,
+) -> Result<()> {
+    let (_, mut tar_archive) = open_archive(open_input(input)?, IgnoreUnknown::On)?;
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        let path = format!("{prefix}{relative}");
+        let entry_type = entry.header().entry_type();
+
+        if !written.insert(path.clone()) {
+            match policy {
+                _ if entry_type.is_dir() => continue,
+                ConflictPolicy::PreferLast => continue,
+                _ => return Err(ProjzstError::MergeConflict(path)),
+            }
+        }
+
+        let mut header = entry.header().clone();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let mut target = entry
+                .link_name()?
+                .map(|t| t.into_owned())
+                .unwrap_or_default();
+            // Hard links name an archive path, which moved under the prefix with the rest
+            if entry_type.is_hard_link() {
+                if let Some(linked) = normalize_path(&target) {
+                    target = format!("{prefix}{linked}").into();
+                }
+            }
+            tar_builder.append_link(&mut header, &path, target)?;
+        } else {
+            tar_builder.append_data(&mut header, &path, &mut entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: fold one input's metadata over the merged record
+/// Fields set in `layer` win, keywords are combined and dependencies merged by name
+fn merge_metadata(merged: &mut Metadata, layer: Metadata) {
+    let fields = [
+        (&mut merged.name, layer.name),
+        (&mut merged.auth, layer.auth),
+        (&mut merged.fmt, layer.fmt),
+        (&mut merged.ed, layer.ed),
+        (&mut merged.ver, layer.ver),
+        (&mut merged.desc, layer.desc),
+        (&mut merged.license, layer.license),
+        (&mut merged.homepage, layer.homepage),
+        (&mut merged.repository, layer.repository),
+    ];
+    for (field, value) in fields {
+        if value.is_some() {
+            *field = value;
+        }
+    }
+    for keyword in layer.keywords {
+        if !merged.keywords.contains(&keyword) {
+            merged.keywords.push(keyword);
+        }
+    }
+    for dep in layer.deps {
+        match merged.deps.iter_mut().find(|known| known.name == dep.name) {
+            Some(known) => *known = dep,
+            None => merged.deps.push(dep),
+        }
+    }
+    merge_values(&mut merged.extra, layer.extra);
+}
+
+/// Internal helper: merge `layer` into `base`, recursing where both are objects
+fn merge_values(base: &mut serde_json::Value, layer: serde_json::Value) {
+    match (base, layer) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(layer_map)) => {
+            for (key, value) in layer_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
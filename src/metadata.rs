@@ -1,10 +1,35 @@
+pub(crate) mod ext;
+pub(crate) mod manifest;
+pub(crate) mod validate;
+
+use crate::deps::{Dependency, Version, VersionReq};
 use crate::errors::ProjzstError;
 use crate::errors::Result;
+use crate::metadata::ext::{BuildInfo, CiInfo, ExtraNamespace, VcsInfo};
+use crate::metadata::validate::{keyword_problem, license_problem, url_problem};
 use crate::string_utils::IntoOpStr;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+/// Top-level metadata fields other than `extra`, in declaration order
+/// Diffs, policies and naming templates go by this list, see [`Metadata::field_value`]
+pub(crate) const FIELDS: &[&str] = &[
+    "name",
+    "auth",
+    "fmt",
+    "ed",
+    "ver",
+    "desc",
+    "license",
+    "homepage",
+    "repository",
+    "keywords",
+    "deps",
+];
+
 /// Ignore unknown fields behavior
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
 pub enum IgnoreUnknown {
     /// Silently ignore unknown fields (default)
     #[default]
@@ -29,10 +54,179 @@ impl IgnoreUnknown {
     }
 }
 
+/// Serialization format of the metadata inside its frames
+/// Readers detect the format from the frame payload, so it only matters when packing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MetadataEncoding {
+    /// MessagePack (default)
+    #[default]
+    MessagePack,
+    /// CBOR, tagged with the self-describe tag `0xD9D9F7`
+    Cbor,
+    /// Compact JSON object
+    Json,
+}
+
+impl MetadataEncoding {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "msgpack" | "messagepack" => Ok(MetadataEncoding::MessagePack),
+            "cbor" => Ok(MetadataEncoding::Cbor),
+            "json" => Ok(MetadataEncoding::Json),
+            _ => Err(ProjzstError::InvalidMetadataEncoding(s.to_string())),
+        }
+    }
+}
+
+/// Syntax of an extra metadata file, converted to the JSON value stored as `extra`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ExtraFormat {
+    /// JSON (default)
+    #[default]
+    Json,
+    /// TOML (feature `toml`); datetimes become RFC 3339 strings
+    Toml,
+    /// YAML (feature `yaml`); mapping keys must be strings
+    Yaml,
+}
+
+impl ExtraFormat {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExtraFormat::Json),
+            "toml" => Ok(ExtraFormat::Toml),
+            "yaml" | "yml" => Ok(ExtraFormat::Yaml),
+            _ => Err(ProjzstError::InvalidExtraFormat(s.to_string())),
+        }
+    }
+
+    /// Guess the format from a file extension (`.toml`, `.yaml`, `.yml`), JSON otherwise
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| Self::from_str_tmp(ext).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether this build can parse the format
+    pub fn is_available(self) -> bool {
+        match self {
+            ExtraFormat::Json => true,
+            ExtraFormat::Toml => cfg!(feature = "toml"),
+            ExtraFormat::Yaml => cfg!(feature = "yaml"),
+        }
+    }
+}
+
+/// Syntax metadata is exported in, see [`Metadata::export`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MetadataFormat {
+    /// Pretty-printed JSON (default)
+    #[default]
+    Json,
+    /// YAML (feature `yaml`)
+    Yaml,
+    /// TOML (feature `toml`); unset fields are left out, as TOML has no null
+    Toml,
+    /// MessagePack with named fields, as stored in the archive
+    MessagePack,
+}
+
+impl MetadataFormat {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "json" => Ok(MetadataFormat::Json),
+            "yaml" | "yml" => Ok(MetadataFormat::Yaml),
+            "toml" => Ok(MetadataFormat::Toml),
+            "msgpack" | "messagepack" | "mpk" => Ok(MetadataFormat::MessagePack),
+            _ => Err(ProjzstError::InvalidMetadataFormat(s.to_string())),
+        }
+    }
+
+    /// Guess the format from a file extension (`.yaml`, `.yml`, `.toml`, `.msgpack`,
+    /// `.mpk`), JSON otherwise
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| Self::from_str_tmp(ext).ok())
+            .unwrap_or_default()
+    }
+
+    /// Lowercase name of the format
+    pub fn name(self) -> &'static str {
+        match self {
+            MetadataFormat::Json => "json",
+            MetadataFormat::Yaml => "yaml",
+            MetadataFormat::Toml => "toml",
+            MetadataFormat::MessagePack => "msgpack",
+        }
+    }
+
+    /// Whether this build can write the format
+    pub fn is_available(self) -> bool {
+        match self {
+            MetadataFormat::Json | MetadataFormat::MessagePack => true,
+            MetadataFormat::Yaml => cfg!(feature = "yaml"),
+            MetadataFormat::Toml => cfg!(feature = "toml"),
+        }
+    }
+
+    /// Whether the format is text, as opposed to binary MessagePack
+    pub fn is_text(self) -> bool {
+        self != MetadataFormat::MessagePack
+    }
+}
+
+/// Oldest projzst release the archives written by a pack must stay readable by
+/// Features a profile predates are left out of the archive, see [`crate::Packer::compat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum CompatProfile {
+    /// Every feature of this release (default)
+    #[default]
+    Latest,
+    /// projzst 1.0: one uncompressed MessagePack metadata frame, no auxiliary frames,
+    /// no volumes and no format version stamp
+    V1_0,
+}
+
+impl CompatProfile {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "latest" | "current" => Ok(CompatProfile::Latest),
+            "1.0" | "1" => Ok(CompatProfile::V1_0),
+            _ => Err(ProjzstError::InvalidCompatProfile(s.to_string())),
+        }
+    }
+}
+
 /// Metadata structure stored in .pjz file header
 /// All fields are optional except extra which defaults to empty object
+///
+/// `extra` is arbitrary JSON by default. Downstream crates with a fixed schema can use
+/// their own type instead (`Metadata<MyExtra>`, see [`Metadata::into_typed`]) and get
+/// compile-time-checked access to their fields; archives are always read and written
+/// through the untyped form.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct Metadata {
+#[non_exhaustive]
+pub struct Metadata<E = serde_json::Value> {
     /// Package name
     #[serde(default)]
     pub name: Option<String>,
@@ -57,10 +251,49 @@ pub struct Metadata {
     #[serde(default)]
     pub desc: Option<String>,
 
-    /// Extra metadata (arbitrary JSON structure)
+    /// Extra metadata (arbitrary JSON structure unless a typed `E` is used)
     /// When ignore_unknown = Export, unknown fields are stored in extra.ignored
     #[serde(default)]
-    pub extra: serde_json::Value,
+    pub extra: E,
+
+    /// License of the package as an SPDX expression (`MIT OR Apache-2.0`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+
+    /// Project homepage URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+
+    /// Source repository URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+
+    /// Keywords for search and classification
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+
+    /// Other packages this one needs, see [`Dependency`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deps: Vec<Dependency>,
+
+    /// Format version the archive was written with, see [`crate::FORMAT_VERSION`]
+    /// Stamped when the metadata is encoded and checked (then cleared) when decoded
+    #[serde(
+        default,
+        rename = "pjz_version",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) format_version: Option<u32>,
+
+    /// Codec of the payload when it is not zstd, see [`crate::PayloadCodec`]
+    /// Stamped when packing and taken out (then cleared) when decoded
+    #[serde(default, rename = "pjz_codec", skip_serializing_if = "Option::is_none")]
+    pub(crate) codec: Option<String>,
+
+    /// ID of the dictionary the payload was compressed with, see [`crate::Dictionary`]
+    /// Stamped when packing and taken out (then cleared) when decoded
+    #[serde(default, rename = "pjz_dict", skip_serializing_if = "Option::is_none")]
+    pub(crate) dictionary: Option<u32>,
 }
 
 impl Default for Metadata {
@@ -73,11 +306,104 @@ impl Default for Metadata {
             ver: None,
             desc: None,
             extra: serde_json::Value::Object(serde_json::Map::new()),
+            license: None,
+            homepage: None,
+            repository: None,
+            keywords: Vec::new(),
+            deps: Vec::new(),
+            format_version: None,
+            codec: None,
+            dictionary: None,
+        }
+    }
+}
+
+impl<E> Metadata<E> {
+    /// Replace extra by a value of another type, keeping every other field
+    /// `Metadata::new(..).replace_extra(my_extra)` builds a typed `Metadata<MyExtra>`
+    pub fn replace_extra<F>(self, extra: F) -> Metadata<F> {
+        Metadata {
+            name: self.name,
+            auth: self.auth,
+            fmt: self.fmt,
+            ed: self.ed,
+            ver: self.ver,
+            desc: self.desc,
+            extra,
+            license: self.license,
+            homepage: self.homepage,
+            repository: self.repository,
+            keywords: self.keywords,
+            deps: self.deps,
+            format_version: self.format_version,
+            codec: self.codec,
+            dictionary: self.dictionary,
         }
     }
 }
 
+impl<E: Serialize> Metadata<E> {
+    /// Convert typed metadata back to the untyped form used to pack and edit archives
+    pub fn into_untyped(self) -> Result<Metadata> {
+        let extra = serde_json::to_value(&self.extra)?;
+        Ok(self.replace_extra(extra))
+    }
+}
+
 impl Metadata {
+    /// Convert extra into a typed `E`
+    /// Fails when extra does not deserialize into `E`
+    pub fn into_typed<E: DeserializeOwned>(self) -> Result<Metadata<E>> {
+        let extra = self.read_extra_as()?;
+        Ok(self.replace_extra(extra))
+    }
+
+    /// Deserialize extra into a caller-provided type
+    /// Fails with [`ProjzstError::ExtraMismatch`] naming the type and what did not match
+    pub fn read_extra_as<T: DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(&self.extra).map_err(|source| ProjzstError::ExtraMismatch {
+            type_name: std::any::type_name::<T>(),
+            source,
+        })
+    }
+
+    /// Internal helper: value of one of the [`FIELDS`] as JSON, `None` when unset or empty
+    pub(crate) fn field_value(&self, field: &str) -> Option<serde_json::Value> {
+        // Destructured so that a field added to the struct is not forgotten here
+        let Metadata {
+            name,
+            auth,
+            fmt,
+            ed,
+            ver,
+            desc,
+            extra: _,
+            license,
+            homepage,
+            repository,
+            keywords,
+            deps,
+            format_version: _,
+            codec: _,
+            dictionary: _,
+        } = self;
+        let text = match field {
+            "name" => name,
+            "auth" => auth,
+            "fmt" => fmt,
+            "ed" => ed,
+            "ver" => ver,
+            "desc" => desc,
+            "license" => license,
+            "homepage" => homepage,
+            "repository" => repository,
+            "keywords" if !keywords.is_empty() => return Some(serde_json::json!(keywords)),
+            "deps" if !deps.is_empty() => return serde_json::to_value(deps).ok(),
+            _ => return None,
+        };
+        text.clone().map(serde_json::Value::String)
+    }
+
     /// Create new Metadata with specified fields
     /// All parameters accept types that can be converted to Option<String>
     pub fn new<I1, I2, I3, I4, I5, I6>(
@@ -104,6 +430,107 @@ impl Metadata {
             ver: ver.into_op_str(),
             desc: desc.into_op_str(),
             extra: serde_json::Value::Object(serde_json::Map::new()),
+            license: None,
+            homepage: None,
+            repository: None,
+            keywords: Vec::new(),
+            deps: Vec::new(),
+            format_version: None,
+            codec: None,
+            dictionary: None,
+        }
+    }
+
+    /// Set the license, checking it is a well-formed SPDX expression
+    /// Fails with [`ProjzstError::InvalidMetadata`] otherwise
+    pub fn set_license(&mut self, license: impl Into<String>) -> Result<()> {
+        self.license = Some(validate::checked(
+            "license",
+            license.into(),
+            license_problem,
+        )?);
+        Ok(())
+    }
+
+    /// Set the homepage, checking it is an absolute `http(s)` URL
+    pub fn set_homepage(&mut self, homepage: impl Into<String>) -> Result<()> {
+        self.homepage = Some(validate::checked("homepage", homepage.into(), url_problem)?);
+        Ok(())
+    }
+
+    /// Set the repository, checking it is an absolute `http(s)` URL
+    pub fn set_repository(&mut self, repository: impl Into<String>) -> Result<()> {
+        self.repository = Some(validate::checked(
+            "repository",
+            repository.into(),
+            url_problem,
+        )?);
+        Ok(())
+    }
+
+    /// Add a keyword; it must be non-empty and free of whitespace
+    pub fn add_keyword(&mut self, keyword: impl Into<String>) -> Result<()> {
+        let keyword = validate::checked("keywords", keyword.into(), keyword_problem)?;
+        self.keywords.push(keyword);
+        Ok(())
+    }
+
+    /// Declare a dependency on another package
+    /// Fails when the version requirement or digest is malformed
+    ///
+    /// # Arguments
+    /// * `name` - Name of the package needed
+    /// * `req` - Version requirement, e.g. `^1.2` or `>=1.0, <2.0`
+    /// * `digest` - Optional `sha256:<hex>` digest of the archive
+    pub fn add_dependency<I: IntoOpStr>(
+        &mut self,
+        name: impl Into<String>,
+        req: impl Into<String>,
+        digest: I,
+    ) -> Result<()> {
+        self.deps.push(Dependency::new(name, req, digest)?);
+        Ok(())
+    }
+
+    /// The dependency on the package named `name`, if declared
+    pub fn dependency(&self, name: &str) -> Option<&Dependency> {
+        self.deps.iter().find(|dep| dep.name == name)
+    }
+
+    /// The version as a parsed semantic version
+    /// Fails with [`ProjzstError::InvalidVersion`] when `ver` is missing or not a semantic
+    /// version
+    pub fn version(&self) -> Result<Version> {
+        Version::parse(self.ver.as_deref().unwrap_or_default())
+    }
+
+    /// Whether the version meets a requirement in the Cargo syntax (`^1.2`, `>=1.0, <2.0`)
+    /// Metadata without a semantic version matches nothing; fails with
+    /// [`ProjzstError::InvalidVersionReq`] when `req` cannot be parsed
+    ///
+    /// # Arguments
+    /// * `req` - Version requirement, see [`VersionReq`]
+    pub fn matches_req(&self, req: &str) -> Result<bool> {
+        let req = VersionReq::parse(req)?;
+        Ok(self.version().is_ok_and(|version| req.matches(&version)))
+    }
+
+    /// Whether a reader of format `fmt` understanding editions up to `ed` can use this
+    /// package: `fmt` must be the same, and an edition is accepted when it is not newer
+    /// than `ed`. Editions compare as numbers (`2024`), as semantic versions, or else
+    /// must be equal; metadata without an edition is accepted by any
+    ///
+    /// # Arguments
+    /// * `fmt` - Package format identifier the reader handles
+    /// * `ed` - Newest edition the reader handles, or `None` for any
+    pub fn is_compatible_with(&self, fmt: &str, ed: Option<&str>) -> bool {
+        if self.fmt.as_deref() != Some(fmt) {
+            return false;
+        }
+        match (self.ed.as_deref(), ed) {
+            (Some(edition), Some(newest)) => cmp_editions(edition, newest)
+                .is_some_and(|ordering| ordering != std::cmp::Ordering::Greater),
+            _ => true,
         }
     }
 
@@ -114,6 +541,118 @@ impl Metadata {
         self
     }
 
+    /// Set a value inside extra by dotted path (`build.commit` sets `extra.build.commit`)
+    /// Missing or non-object intermediate values are replaced by objects
+    pub fn set_extra_path(&mut self, path: &str, value: serde_json::Value) {
+        // Ensure extra is an object
+        if !self.extra.is_object() {
+            self.extra = serde_json::Value::Object(serde_json::Map::new());
+        }
+
+        let mut current = &mut self.extra;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            let serde_json::Value::Object(map) = current else {
+                unreachable!("intermediate values are always objects");
+            };
+            if segments.peek().is_none() {
+                map.insert(segment.to_string(), value);
+                return;
+            }
+
+            let next = map
+                .entry(segment.to_string())
+                .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+            if !next.is_object() {
+                *next = serde_json::Value::Object(serde_json::Map::new());
+            }
+            current = next;
+        }
+    }
+
+    /// Read a typed `extra` namespace
+    /// Returns `None` when the namespace is absent
+    pub fn namespace<T: ExtraNamespace>(&self) -> Result<Option<T>> {
+        match self.extra.get(T::KEY) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a typed `extra` namespace, replacing any previous value under its key
+    pub fn set_namespace<T: ExtraNamespace>(&mut self, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.set_extra_path(T::KEY, value);
+        Ok(())
+    }
+
+    /// Build environment from `extra.build`
+    pub fn build(&self) -> Result<Option<BuildInfo>> {
+        self.namespace()
+    }
+
+    /// Store the build environment under `extra.build`
+    pub fn set_build(&mut self, build: &BuildInfo) -> Result<()> {
+        self.set_namespace(build)
+    }
+
+    /// Version control revision from `extra.vcs`
+    pub fn vcs(&self) -> Result<Option<VcsInfo>> {
+        self.namespace()
+    }
+
+    /// Store the version control revision under `extra.vcs`
+    pub fn set_vcs(&mut self, vcs: &VcsInfo) -> Result<()> {
+        self.set_namespace(vcs)
+    }
+
+    /// CI run from `extra.ci`
+    pub fn ci(&self) -> Result<Option<CiInfo>> {
+        self.namespace()
+    }
+
+    /// Store the CI run under `extra.ci`
+    pub fn set_ci(&mut self, ci: &CiInfo) -> Result<()> {
+        self.set_namespace(ci)
+    }
+
+    /// Serialize the metadata in `format`, e.g. to save what `info` prints
+    /// TOML cannot hold a null inside an array of extra, which fails with
+    /// [`ProjzstError::UnrepresentableMetadata`]; a format this build lacks fails with
+    /// [`ProjzstError::FeatureUnavailable`]
+    ///
+    /// # Arguments
+    /// * `format` - Syntax of the bytes returned
+    pub fn export(&self, format: MetadataFormat) -> Result<Vec<u8>> {
+        #[cfg(any(feature = "yaml", feature = "toml"))]
+        let unrepresentable = |message: String| ProjzstError::UnrepresentableMetadata {
+            format: format.name().to_string(),
+            message,
+        };
+        Ok(match format {
+            MetadataFormat::Json => serde_json::to_vec_pretty(self)?,
+            #[cfg(feature = "yaml")]
+            MetadataFormat::Yaml => serde_yaml::to_string(self)
+                .map_err(|e| unrepresentable(e.to_string()))?
+                .into_bytes(),
+            #[cfg(feature = "toml")]
+            MetadataFormat::Toml => {
+                let value = without_nulls(serde_json::to_value(self)?);
+                toml::to_string(&value)
+                    .map_err(|e| unrepresentable(e.to_string()))?
+                    .into_bytes()
+            }
+            MetadataFormat::MessagePack => rmp_serde::to_vec_named(self)?,
+            #[cfg(not(all(feature = "yaml", feature = "toml")))]
+            _ => {
+                return Err(ProjzstError::FeatureUnavailable {
+                    what: format!("{} metadata", format.name()),
+                    feature: format.name().to_string(),
+                })
+            }
+        })
+    }
+
     /// Merge unknown fields into extra.ignored
     /// This is used when ignore_unknown = Export
     pub fn merge_unknown_fields(&mut self, unknown: serde_json::Value) {
@@ -144,3 +683,43 @@ impl Metadata {
         }
     }
 }
+
+/// Parse a `key=value` extra metadata pair as given on the command line
+/// The key may be a dotted path; the value is read as JSON when it parses
+/// (numbers, booleans, null, arrays, objects, quoted strings) and as a plain string otherwise
+pub fn parse_extra_pair(pair: &str) -> Result<(String, serde_json::Value)> {
+    let (key, value) = pair
+        .split_once('=')
+        .filter(|(key, _)| !key.is_empty() && key.split('.').all(|s| !s.is_empty()))
+        .ok_or_else(|| ProjzstError::InvalidExtraPair(pair.to_string()))?;
+
+    let value = serde_json::from_str(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    Ok((key.to_string(), value))
+}
+
+/// Internal helper: drop the null members of every object, which TOML cannot hold
+#[cfg(feature = "toml")]
+fn without_nulls(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect(),
+        serde_json::Value::Array(items) => items.into_iter().map(without_nulls).collect(),
+        value => value,
+    }
+}
+
+/// Internal helper: order two editions as numbers, then as semantic versions; other
+/// editions only compare when equal
+fn cmp_editions(a: &str, b: &str) -> Option<std::cmp::Ordering> {
+    if let (Ok(a), Ok(b)) = (a.parse::<u64>(), b.parse::<u64>()) {
+        return Some(a.cmp(&b));
+    }
+    if let (Ok(a), Ok(b)) = (Version::parse(a), Version::parse(b)) {
+        return Some(a.cmp(&b));
+    }
+    (a == b).then_some(std::cmp::Ordering::Equal)
+}
@@ -0,0 +1,138 @@
+//! Typed views of well-known `extra` namespaces
+//!
+//! `extra` is free-form, but a few namespaces are conventional so tools can share them:
+//!
+//! - `extra.build` - how and where the package was built ([`BuildInfo`])
+//! - `extra.vcs` - the source revision it was built from ([`VcsInfo`])
+//! - `extra.ci` - the CI run that produced it ([`CiInfo`])
+//! - `extra.bundle` - the member archives of a bundle ([`BundleManifest`](crate::BundleManifest))
+//!
+//! Every field is optional and unknown keys are kept in `other`, so reading and writing
+//! a namespace through these types never drops data written by other tools.
+//!
+//! # Examples
+//!
+//! ```
+//! use projzst::{Metadata, VcsInfo};
+//!
+//! let mut metadata = Metadata::default();
+//! metadata
+//!     .set_vcs(&VcsInfo {
+//!         system: Some("git".to_string()),
+//!         revision: Some("3f2a9c1".to_string()),
+//!         ..Default::default()
+//!     })
+//!     .unwrap();
+//!
+//! assert_eq!(metadata.extra["vcs"]["revision"], "3f2a9c1");
+//! assert_eq!(metadata.vcs().unwrap().unwrap().system.as_deref(), Some("git"));
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// A typed `extra` namespace stored under a fixed key
+pub trait ExtraNamespace: Serialize + DeserializeOwned {
+    /// Key of the namespace inside `extra`
+    const KEY: &'static str;
+}
+
+/// Build environment, stored under `extra.build`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Build time (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+
+    /// Host the build ran on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Tool and version that produced the build (e.g. `cargo 1.80.0`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+
+    /// Build profile (e.g. `release`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Target platform (e.g. `x86_64-unknown-linux-gnu`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// Keys not covered by the fields above
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ExtraNamespace for BuildInfo {
+    const KEY: &'static str = "build";
+}
+
+/// Version control revision, stored under `extra.vcs`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VcsInfo {
+    /// Version control system (e.g. `git`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+
+    /// Repository URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+
+    /// Commit hash or revision id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+
+    /// Branch name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+
+    /// Tag pointing at the revision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// Whether the working tree had uncommitted changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<bool>,
+
+    /// Keys not covered by the fields above
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ExtraNamespace for VcsInfo {
+    const KEY: &'static str = "vcs";
+}
+
+/// Continuous integration run, stored under `extra.ci`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CiInfo {
+    /// CI provider (e.g. `github-actions`, `gitlab`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+
+    /// Pipeline or workflow name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+
+    /// Run identifier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+
+    /// Job name within the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+
+    /// Link to the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Keys not covered by the fields above
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ExtraNamespace for CiInfo {
+    const KEY: &'static str = "ci";
+}
@@ -0,0 +1,159 @@
+//! Metadata imported from project manifests
+//!
+//! Name, version, authors, description, license, homepage, repository and keywords are
+//! read from the `[package]` table of a `Cargo.toml` or from a `package.json`. Values the
+//! manifest does not spell out as strings (e.g. `version.workspace = true`) are left unset.
+//! Reading a `Cargo.toml` needs the `toml` feature.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::Metadata;
+
+impl Metadata {
+    /// Read metadata from a `Cargo.toml` or `package.json`, chosen by file name
+    /// Any file other than `*.json` is read as a Cargo manifest
+    ///
+    /// # Arguments
+    /// * `path` - Path to the manifest
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_package_json(path)
+        } else {
+            #[cfg(feature = "toml")]
+            return Self::from_cargo_toml(path);
+            #[cfg(not(feature = "toml"))]
+            Err(ProjzstError::FeatureUnavailable {
+                what: format!("{}: Cargo manifests", path.display()),
+                feature: "toml".to_string(),
+            })
+        }
+    }
+
+    /// Read metadata from the `[package]` table of a `Cargo.toml` (feature `toml`)
+    ///
+    /// # Arguments
+    /// * `path` - Path to the Cargo manifest
+    #[cfg(feature = "toml")]
+    pub fn from_cargo_toml<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest: toml::Table =
+            toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(path, e.message()))?;
+        let package = manifest
+            .get("package")
+            .and_then(toml::Value::as_table)
+            .ok_or_else(|| invalid(path, "no [package] table"))?;
+        let string = |key: &str| {
+            package
+                .get(key)
+                .and_then(toml::Value::as_str)
+                .map(str::to_string)
+        };
+        let strings = |key: &str| -> Vec<String> {
+            package
+                .get(key)
+                .and_then(toml::Value::as_array)
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let authors = strings("authors");
+        let mut metadata = Metadata::new(
+            string("name"),
+            (!authors.is_empty()).then(|| authors.join(", ")),
+            None::<String>,
+            None::<String>,
+            string("version"),
+            string("description"),
+        );
+        metadata.license = string("license");
+        metadata.homepage = string("homepage");
+        metadata.repository = string("repository");
+        metadata.keywords = strings("keywords");
+        Ok(metadata)
+    }
+
+    /// Read metadata from a `package.json`
+    /// `author` and `repository` may be strings or objects (`{"name": ..}`, `{"url": ..}`)
+    ///
+    /// # Arguments
+    /// * `path` - Path to the npm manifest
+    pub fn from_package_json<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| invalid(path, &e.to_string()))?;
+        if !manifest.is_object() {
+            return Err(invalid(path, "not a JSON object"));
+        }
+        let string =
+            |value: &Value, key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let string_or = |key: &str, inner: &str| {
+            let value = manifest.get(key)?;
+            value
+                .as_str()
+                .map(str::to_string)
+                .or_else(|| string(value, inner))
+        };
+
+        let mut metadata = Metadata::new(
+            string(&manifest, "name"),
+            string_or("author", "name"),
+            None::<String>,
+            None::<String>,
+            string(&manifest, "version"),
+            string(&manifest, "description"),
+        );
+        metadata.license = string(&manifest, "license");
+        metadata.homepage = string(&manifest, "homepage");
+        metadata.repository = string_or("repository", "url");
+        metadata.keywords = manifest
+            .get("keywords")
+            .and_then(Value::as_array)
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(metadata)
+    }
+
+    /// Fill every unset field from `defaults`, keeping the values already set
+    /// Used to let command-line values override those imported from a manifest
+    pub fn with_defaults(mut self, defaults: Metadata) -> Self {
+        self.name = self.name.or(defaults.name);
+        self.auth = self.auth.or(defaults.auth);
+        self.fmt = self.fmt.or(defaults.fmt);
+        self.ed = self.ed.or(defaults.ed);
+        self.ver = self.ver.or(defaults.ver);
+        self.desc = self.desc.or(defaults.desc);
+        self.license = self.license.or(defaults.license);
+        self.homepage = self.homepage.or(defaults.homepage);
+        self.repository = self.repository.or(defaults.repository);
+        if self.keywords.is_empty() {
+            self.keywords = defaults.keywords;
+        }
+        if self.deps.is_empty() {
+            self.deps = defaults.deps;
+        }
+        self
+    }
+}
+
+/// Internal helper: the error for a manifest that cannot be read
+fn invalid(path: &Path, message: &str) -> ProjzstError {
+    ProjzstError::InvalidManifest {
+        path: path.display().to_string(),
+        message: message.to_string(),
+    }
+}
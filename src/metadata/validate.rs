@@ -0,0 +1,468 @@
+//! Validating construction of [`Metadata`]
+//!
+//! [`MetadataBuilder`] checks each field as it is set: `name` must be non-empty and usable
+//! as a file name, `ver` must be a semantic version, `license` a well-formed SPDX
+//! expression and `homepage`/`repository` absolute `http(s)` URLs. Problems are collected rather than
+//! returned one by one, so [`MetadataBuilder::build`] reports every bad field at once.
+//!
+//! [`MetadataPolicy`] is the reading side: pipelines that must reject incomplete packages
+//! list the fields they need, and reads fail with every missing or unexpected value.
+//!
+//! # Examples
+//!
+//! ```
+//! use projzst::{MetadataBuilder, ProjzstError};
+//!
+//! let metadata = MetadataBuilder::new()
+//!     .name("my-project")
+//!     .ver("1.2.0-rc.1")
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(metadata.ver.as_deref(), Some("1.2.0-rc.1"));
+//!
+//! let err = MetadataBuilder::new().name("../etc").ver("1.2").build().unwrap_err();
+//! let ProjzstError::InvalidMetadata(errors) = err else { unreachable!() };
+//! assert_eq!(errors.len(), 2);
+//! ```
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{Metadata, FIELDS};
+use crate::naming::placeholder_value;
+
+/// Characters not allowed in a package name, as they are not portable in file names
+const UNSAFE_NAME_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// One metadata field that failed validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct FieldError {
+    /// Field name (`name`, `ver`, ...), or dotted path for a value in extra
+    pub field: String,
+    /// Rejected value, `None` when the field is missing
+    pub value: Option<String>,
+    /// Why the value was rejected
+    pub reason: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} {value:?}: {}", self.field, self.reason),
+            None => write!(f, "{}: {}", self.field, self.reason),
+        }
+    }
+}
+
+/// Builder for [`Metadata`] validating fields as they are set
+#[derive(Debug, Clone, Default)]
+pub struct MetadataBuilder {
+    metadata: Metadata,
+    errors: Vec<FieldError>,
+}
+
+impl MetadataBuilder {
+    /// Start from empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from existing metadata, validating the fields it already has
+    pub fn from_metadata(metadata: Metadata) -> Self {
+        let mut builder = Self {
+            metadata,
+            errors: Vec::new(),
+        };
+        if let Some(name) = builder.metadata.name.clone() {
+            builder.check("name", &name, name_problem(&name));
+        }
+        if let Some(ver) = builder.metadata.ver.clone() {
+            builder.check("ver", &ver, ver_problem(&ver));
+        }
+        if let Some(license) = builder.metadata.license.clone() {
+            builder.check("license", &license, license_problem(&license));
+        }
+        for (field, url) in [
+            ("homepage", builder.metadata.homepage.clone()),
+            ("repository", builder.metadata.repository.clone()),
+        ] {
+            if let Some(url) = url {
+                builder.check(field, &url, url_problem(&url));
+            }
+        }
+        for keyword in builder.metadata.keywords.clone() {
+            if let Some(reason) = keyword_problem(&keyword) {
+                builder.errors.push(FieldError {
+                    field: "keywords".to_string(),
+                    value: Some(keyword),
+                    reason,
+                });
+            }
+        }
+        builder
+    }
+
+    /// Set the package name; it must be non-empty and safe to use in a file name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.check("name", &name, name_problem(&name));
+        self.metadata.name = Some(name);
+        self
+    }
+
+    /// Set the author name
+    pub fn auth(mut self, auth: impl Into<String>) -> Self {
+        self.metadata.auth = Some(auth.into());
+        self
+    }
+
+    /// Set the package format identifier
+    pub fn fmt(mut self, fmt: impl Into<String>) -> Self {
+        self.metadata.fmt = Some(fmt.into());
+        self
+    }
+
+    /// Set the format edition
+    pub fn ed(mut self, ed: impl Into<String>) -> Self {
+        self.metadata.ed = Some(ed.into());
+        self
+    }
+
+    /// Set the project version; it must be a semantic version (`1.2.3`, `2.0.0-rc.1`)
+    pub fn ver(mut self, ver: impl Into<String>) -> Self {
+        let ver = ver.into();
+        self.check("ver", &ver, ver_problem(&ver));
+        self.metadata.ver = Some(ver);
+        self
+    }
+
+    /// Set the package description
+    pub fn desc(mut self, desc: impl Into<String>) -> Self {
+        self.metadata.desc = Some(desc.into());
+        self
+    }
+
+    /// Set the license; it must be a well-formed SPDX expression (`MIT OR Apache-2.0`)
+    /// License identifiers are checked for their syntax, not against the SPDX list
+    pub fn license(mut self, license: impl Into<String>) -> Self {
+        let license = license.into();
+        self.check("license", &license, license_problem(&license));
+        self.metadata.license = Some(license);
+        self
+    }
+
+    /// Set the homepage; it must be an absolute `http(s)` URL
+    pub fn homepage(mut self, homepage: impl Into<String>) -> Self {
+        let homepage = homepage.into();
+        self.check("homepage", &homepage, url_problem(&homepage));
+        self.metadata.homepage = Some(homepage);
+        self
+    }
+
+    /// Set the repository; it must be an absolute `http(s)` URL
+    pub fn repository(mut self, repository: impl Into<String>) -> Self {
+        let repository = repository.into();
+        self.check("repository", &repository, url_problem(&repository));
+        self.metadata.repository = Some(repository);
+        self
+    }
+
+    /// Add a keyword; it must be non-empty and free of whitespace
+    pub fn keyword(mut self, keyword: impl Into<String>) -> Self {
+        let keyword = keyword.into();
+        if let Some(reason) = keyword_problem(&keyword) {
+            self.errors.push(FieldError {
+                field: "keywords".to_string(),
+                value: Some(keyword.clone()),
+                reason,
+            });
+        }
+        self.metadata.keywords.push(keyword);
+        self
+    }
+
+    /// Set extra metadata
+    pub fn extra(mut self, extra: serde_json::Value) -> Self {
+        self.metadata.extra = extra;
+        self
+    }
+
+    /// Set a single extra value by dotted path, see [`Metadata::set_extra_path`]
+    pub fn extra_value(mut self, path: &str, value: serde_json::Value) -> Self {
+        self.metadata.set_extra_path(path, value);
+        self
+    }
+
+    /// Problems found so far, in the order the fields were set
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// Finish the metadata
+    /// Fails with [`ProjzstError::InvalidMetadata`] listing every invalid field; a missing
+    /// name counts as invalid
+    pub fn build(mut self) -> Result<Metadata> {
+        if self.metadata.name.is_none() {
+            self.errors.push(FieldError {
+                field: "name".to_string(),
+                value: None,
+                reason: "is required".to_string(),
+            });
+        }
+        if !self.errors.is_empty() {
+            return Err(ProjzstError::InvalidMetadata(self.errors));
+        }
+        Ok(self.metadata)
+    }
+
+    /// Internal helper: record the outcome of validating `field`, replacing the outcome
+    /// of an earlier value
+    fn check(&mut self, field: &'static str, value: &str, problem: Option<String>) {
+        self.errors.retain(|error| error.field != field);
+        if let Some(reason) = problem {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                value: Some(value.to_string()),
+                reason,
+            });
+        }
+    }
+}
+
+/// Requirements metadata must meet to be accepted when reading
+/// Fields are named as in naming templates: `name`, `ver`, ... or `extra.build.commit`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataPolicy {
+    required: Vec<String>,
+    expected: Vec<(String, String)>,
+    semver: bool,
+}
+
+impl MetadataPolicy {
+    /// Create a policy accepting any metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `field` to be set
+    pub fn require(mut self, field: impl Into<String>) -> Self {
+        self.required.push(field.into());
+        self
+    }
+
+    /// Require `field` to be set to exactly `value`
+    pub fn expect(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.expected.push((field.into(), value.into()));
+        self
+    }
+
+    /// Require `ver`, when set, to be a semantic version
+    pub fn require_semver(mut self, semver: bool) -> Self {
+        self.semver = semver;
+        self
+    }
+
+    /// Check `metadata` against this policy
+    /// Fails with [`ProjzstError::PolicyViolations`] listing every missing or invalid field
+    pub fn check(&self, metadata: &Metadata) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut fail = |field: &str, value: Option<String>, reason: String| {
+            errors.push(FieldError {
+                field: field.to_string(),
+                value,
+                reason,
+            })
+        };
+
+        for field in &self.required {
+            if !is_policy_field(field) {
+                fail(field, None, "is not a metadata field".to_string());
+            } else if placeholder_value(metadata, field).is_none_or(|v| v.is_empty()) {
+                fail(field, None, "is required".to_string());
+            }
+        }
+        for (field, expected) in &self.expected {
+            if !is_policy_field(field) {
+                fail(field, None, "is not a metadata field".to_string());
+                continue;
+            }
+            match placeholder_value(metadata, field) {
+                Some(value) if value == *expected => {}
+                Some(value) => fail(field, Some(value), format!("must be {expected:?}")),
+                None => fail(field, None, format!("is required to be {expected:?}")),
+            }
+        }
+        if let Some(ver) = metadata.ver.as_ref().filter(|_| self.semver) {
+            if let Some(reason) = ver_problem(ver) {
+                fail("ver", Some(ver.clone()), reason);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ProjzstError::PolicyViolations(errors));
+        }
+        Ok(())
+    }
+}
+
+/// Internal helper: whether a policy may name `field`, a top-level field or an `extra.`
+/// path
+fn is_policy_field(field: &str) -> bool {
+    FIELDS.contains(&field) || field.strip_prefix("extra.").is_some_and(|p| !p.is_empty())
+}
+
+/// Internal helper: what makes `name` unusable as a package name, if anything
+fn name_problem(name: &str) -> Option<String> {
+    if name.trim().is_empty() {
+        return Some("must not be empty".to_string());
+    }
+    if name.trim() != name {
+        return Some("must not start or end with whitespace".to_string());
+    }
+    if name == "." || name == ".." {
+        return Some("must not be a relative path component".to_string());
+    }
+    if let Some(c) = name.chars().find(|c| UNSAFE_NAME_CHARS.contains(c)) {
+        return Some(format!("must not contain {c:?}"));
+    }
+    if name.chars().any(char::is_control) {
+        return Some("must not contain control characters".to_string());
+    }
+    None
+}
+
+/// Internal helper: `value` if `problem` finds nothing wrong with it, otherwise
+/// [`ProjzstError::InvalidMetadata`] for `field`
+pub(crate) fn checked(
+    field: &str,
+    value: String,
+    problem: fn(&str) -> Option<String>,
+) -> Result<String> {
+    match problem(&value) {
+        Some(reason) => Err(ProjzstError::InvalidMetadata(vec![FieldError {
+            field: field.to_string(),
+            value: Some(value),
+            reason,
+        }])),
+        None => Ok(value),
+    }
+}
+
+/// Internal helper: what makes `license` an invalid SPDX license expression, if anything
+/// Follows the SPDX 2.3 grammar: identifiers (optionally `+`), `LicenseRef-`/`DocumentRef-`
+/// references, `WITH` exceptions, `AND`/`OR` and parentheses
+pub(crate) fn license_problem(license: &str) -> Option<String> {
+    let spaced = license.replace('(', " ( ").replace(')', " ) ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    if tokens.is_empty() {
+        return Some("must not be empty".to_string());
+    }
+    let mut pos = 0;
+    if let Err(problem) = parse_license_expr(&tokens, &mut pos) {
+        return Some(problem);
+    }
+    if let Some(token) = tokens.get(pos) {
+        return Some(format!("unexpected {token:?} in SPDX expression"));
+    }
+    None
+}
+
+/// Internal helper: `term (("AND" | "OR") term)*`
+fn parse_license_expr(tokens: &[&str], pos: &mut usize) -> std::result::Result<(), String> {
+    parse_license_term(tokens, pos)?;
+    while matches!(tokens.get(*pos), Some(&"AND") | Some(&"OR")) {
+        *pos += 1;
+        parse_license_term(tokens, pos)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: `"(" expr ")" | license ["WITH" exception]`
+fn parse_license_term(tokens: &[&str], pos: &mut usize) -> std::result::Result<(), String> {
+    let Some(&token) = tokens.get(*pos) else {
+        return Err("SPDX expression ends with an operator".to_string());
+    };
+    *pos += 1;
+    if token == "(" {
+        parse_license_expr(tokens, pos)?;
+        if tokens.get(*pos) != Some(&")") {
+            return Err("unbalanced parenthesis in SPDX expression".to_string());
+        }
+        *pos += 1;
+        return Ok(());
+    }
+    if !is_license_ref(token) {
+        return Err(format!("{token:?} is not an SPDX license identifier"));
+    }
+    if tokens.get(*pos) == Some(&"WITH") {
+        *pos += 1;
+        match tokens.get(*pos) {
+            Some(exception) if is_spdx_id(exception) => *pos += 1,
+            _ => return Err("WITH must be followed by an exception identifier".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: a license identifier (`MIT`, `GPL-2.0+`) or reference
+/// (`LicenseRef-x`, `DocumentRef-y:LicenseRef-x`)
+fn is_license_ref(token: &str) -> bool {
+    if let Some((document, license)) = token.split_once(':') {
+        return document
+            .strip_prefix("DocumentRef-")
+            .is_some_and(is_spdx_id)
+            && license.strip_prefix("LicenseRef-").is_some_and(is_spdx_id);
+    }
+    is_spdx_id(token.strip_suffix('+').unwrap_or(token))
+}
+
+/// Internal helper: an SPDX idstring that is not an operator
+fn is_spdx_id(id: &str) -> bool {
+    !id.is_empty()
+        && !matches!(id, "AND" | "OR" | "WITH")
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-')
+}
+
+/// Internal helper: what makes `url` unusable as a homepage or repository, if anything
+pub(crate) fn url_problem(url: &str) -> Option<String> {
+    let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    else {
+        return Some("must be an http:// or https:// URL".to_string());
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if host.is_empty() {
+        return Some("has no host".to_string());
+    }
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Some("must not contain whitespace".to_string());
+    }
+    None
+}
+
+/// Internal helper: what makes `keyword` unusable, if anything
+pub(crate) fn keyword_problem(keyword: &str) -> Option<String> {
+    if keyword.is_empty() {
+        Some("must not be empty".to_string())
+    } else if keyword.chars().any(char::is_whitespace) {
+        Some("must not contain whitespace".to_string())
+    } else {
+        None
+    }
+}
+
+/// Internal helper: what makes `ver` unusable as a project version, if anything
+fn ver_problem(ver: &str) -> Option<String> {
+    (!is_semver(ver)).then(|| "not a semantic version".to_string())
+}
+
+/// Internal helper: whether `version` follows Semantic Versioning 2.0.0
+pub(crate) fn is_semver(version: &str) -> bool {
+    crate::deps::Version::parse(version).is_ok()
+}
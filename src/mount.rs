@@ -0,0 +1,921 @@
+//! Read-only FUSE mount of an archive (Linux, `fuse` feature)
+//!
+//! [`mount`] speaks the FUSE kernel protocol on `/dev/fuse` itself, without libfuse. The
+//! tree comes from the tar headers ([`list_entries`]); an open file is decompressed on a
+//! thread of its own as it is read, straight from its own frame through the entry index
+//! for archives packed with per-entry frames, a few pieces ahead of the reader. Reads are
+//! served by offset: reading on sequentially streams the entry, reading back restarts it.
+//! Root mounts with `mount(2)`, other users through `fusermount3` (or `fusermount`), as
+//! libfuse does. macOS is not supported: macFUSE speaks its own protocol on its own
+//! devices, through its own mount helper.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::entries::{send_content, Piece, PIECES_AHEAD};
+use crate::errors::{ProjzstError, Result};
+use crate::listing::{list_entries, ArchiveEntry, EntryKind};
+use crate::preview::with_entry;
+
+/// Protocol version spoken; the kernel settles on the older of its own and this one
+const KERNEL_VERSION: u32 = 7;
+const KERNEL_MINOR_VERSION: u32 = 31;
+
+/// Node id of the root directory
+const ROOT_ID: u64 = 1;
+
+/// Seconds the kernel may cache names and attributes (the archive does not change)
+const TTL_SECONDS: u64 = 3600;
+
+/// Largest request accepted, in bytes
+const MAX_WRITE: u32 = 128 * 1024;
+
+/// Size of the request buffer: the largest request and its header
+const BUFFER_SIZE: usize = MAX_WRITE as usize + 4096;
+
+/// Size of `fuse_in_header`
+const IN_HEADER_SIZE: usize = 40;
+
+/// `fuse_open_out` flag: the kernel may keep cached pages across opens
+const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+
+/// Opcodes handled, from `fuse_kernel.h`
+const LOOKUP: u32 = 1;
+const FORGET: u32 = 2;
+const GETATTR: u32 = 3;
+const READLINK: u32 = 5;
+const OPEN: u32 = 14;
+const READ: u32 = 15;
+const STATFS: u32 = 17;
+const RELEASE: u32 = 18;
+const INIT: u32 = 26;
+const OPENDIR: u32 = 27;
+const READDIR: u32 = 28;
+const RELEASEDIR: u32 = 29;
+const ACCESS: u32 = 34;
+const INTERRUPT: u32 = 36;
+const DESTROY: u32 = 38;
+const BATCH_FORGET: u32 = 42;
+
+/// One file, directory or symlink of the mounted tree; its node id is its index plus one
+struct Node {
+    /// Entry path in the archive, `""` for the root
+    path: String,
+    kind: EntryKind,
+    size: u64,
+    mode: u32,
+    mtime: u64,
+    link: Option<String>,
+    /// Number of names of the node (hard links included)
+    links: u32,
+    parent: u64,
+    children: BTreeMap<String, u64>,
+}
+
+/// The archive being served and the files open in it
+struct Filesystem {
+    input: PathBuf,
+    nodes: Vec<Node>,
+    uid: u32,
+    gid: u32,
+    /// Open files, by handle
+    handles: HashMap<u64, OpenFile>,
+    next_handle: u64,
+}
+
+/// An open file, its content decoded on a thread of its own as it is read
+struct OpenFile {
+    path: String,
+    /// Offset in the file of the first byte of `piece`
+    position: u64,
+    /// Content decoded ahead, up to [`PIECES_AHEAD`] pieces; `None` until the first read
+    pieces: Option<Receiver<Piece>>,
+    piece: Vec<u8>,
+}
+
+impl OpenFile {
+    /// Internal helper: up to `size` bytes of the file from `offset`, fewer at its end
+    fn read(&mut self, input: &Path, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        if self.pieces.is_none() || offset < self.position {
+            self.restart(input);
+        }
+        let mut out = Vec::with_capacity(size.min(MAX_WRITE as usize));
+        while out.len() < size {
+            let end = self.position + self.piece.len() as u64;
+            if offset + (out.len() as u64) < end {
+                let start = (offset + out.len() as u64 - self.position) as usize;
+                let len = (size - out.len()).min(self.piece.len() - start);
+                out.extend_from_slice(&self.piece[start..start + len]);
+                continue;
+            }
+            let pieces = self.pieces.as_ref().expect("decoding started above");
+            match pieces.recv() {
+                Ok(Ok(piece)) => {
+                    self.position = end;
+                    self.piece = piece;
+                }
+                Ok(Err(e)) => {
+                    // The next read starts over
+                    self.pieces = None;
+                    return Err(e);
+                }
+                // The whole entry was read
+                Err(_) => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Internal helper: decode the file again from its start
+    fn restart(&mut self, input: &Path) {
+        let (sender, pieces) = mpsc::sync_channel(PIECES_AHEAD);
+        let (input, path) = (input.to_path_buf(), self.path.clone());
+        thread::spawn(move || {
+            let found = with_entry(&input, &path, |entry, _| {
+                send_content(entry, &sender);
+                Ok(())
+            });
+            if let Err(e) = found {
+                let _ = sender.send(Err(io::Error::other(e.to_string())));
+            }
+        });
+        self.position = 0;
+        self.pieces = Some(pieces);
+        self.piece = Vec::new();
+    }
+}
+
+/// Mount a .pjz file read-only at `mountpoint` and serve it on the calling thread until
+/// it is unmounted (`umount` or `fusermount -u`)
+/// A signal interrupting the thread, through a handler installed without `SA_RESTART`,
+/// unmounts the archive and returns as well
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `mountpoint` - Existing directory to mount the archive on
+pub fn mount<P1, P2>(input_file: P1, mountpoint: P2) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let input = input_file.as_ref().canonicalize()?;
+    let mountpoint = mountpoint.as_ref().canonicalize()?;
+    // SAFETY: getuid and getgid cannot fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let mut filesystem = Filesystem {
+        nodes: build_tree(list_entries(&input)?),
+        input,
+        uid,
+        gid,
+        handles: HashMap::new(),
+        next_handle: 1,
+    };
+
+    let mut device = open_device(&mountpoint)?;
+    match filesystem.serve(&mut device) {
+        Ok(true) => Ok(()),
+        Ok(false) => unmount(&mountpoint),
+        Err(e) => {
+            // The error that stopped serving matters more than a failed unmount
+            let _ = unmount(&mountpoint);
+            Err(e)
+        }
+    }
+}
+
+/// Internal helper: the node tree of `entries`, root first
+/// Directories without an entry of their own are made up; hard links share the node of
+/// their target; device nodes, FIFOs and sockets are left out
+fn build_tree(entries: Vec<ArchiveEntry>) -> Vec<Node> {
+    let mut nodes = vec![directory_node(String::new(), ROOT_ID, 0o755, 0)];
+    for entry in entries {
+        let (parent_path, name) = entry.path.rsplit_once('/').unwrap_or(("", &entry.path));
+        let name = name.to_string();
+        let parent = directory_id(&mut nodes, parent_path);
+        match entry.kind {
+            EntryKind::Directory => {
+                let id = directory_id(&mut nodes, &entry.path);
+                let node = &mut nodes[id as usize - 1];
+                node.mode = entry.mode;
+                node.mtime = entry.mtime;
+            }
+            EntryKind::HardLink => {
+                let target = entry.link.as_deref().and_then(|link| find(&nodes, link));
+                if let Some(target) = target {
+                    nodes[target as usize - 1].links += 1;
+                    nodes[parent as usize - 1].children.insert(name, target);
+                }
+            }
+            EntryKind::File | EntryKind::Symlink => {
+                let id = nodes.len() as u64 + 1;
+                nodes.push(Node {
+                    path: entry.path,
+                    kind: entry.kind,
+                    size: entry.size,
+                    mode: entry.mode,
+                    mtime: entry.mtime,
+                    link: entry.link,
+                    links: 1,
+                    parent,
+                    children: BTreeMap::new(),
+                });
+                nodes[parent as usize - 1].children.insert(name, id);
+            }
+            _ => {}
+        }
+    }
+    nodes
+}
+
+/// Internal helper: an empty directory node
+fn directory_node(path: String, parent: u64, mode: u32, mtime: u64) -> Node {
+    Node {
+        path,
+        kind: EntryKind::Directory,
+        size: 0,
+        mode,
+        mtime,
+        link: None,
+        links: 2,
+        parent,
+        children: BTreeMap::new(),
+    }
+}
+
+/// Internal helper: the node id of the directory at `path`, made up with its parents
+/// when missing
+fn directory_id(nodes: &mut Vec<Node>, path: &str) -> u64 {
+    let mut id = ROOT_ID;
+    if path.is_empty() {
+        return id;
+    }
+    let mut current = String::new();
+    for name in path.split('/') {
+        if !current.is_empty() {
+            current.push('/');
+        }
+        current.push_str(name);
+        let existing = nodes[id as usize - 1].children.get(name).copied();
+        id = match existing.filter(|&child| nodes[child as usize - 1].kind == EntryKind::Directory)
+        {
+            Some(child) => child,
+            None => {
+                let child = nodes.len() as u64 + 1;
+                nodes.push(directory_node(current.clone(), id, 0o755, 0));
+                nodes[id as usize - 1]
+                    .children
+                    .insert(name.to_string(), child);
+                child
+            }
+        };
+    }
+    id
+}
+
+/// Internal helper: the node id at `path`, if any
+fn find(nodes: &[Node], path: &str) -> Option<u64> {
+    path.split('/').try_fold(ROOT_ID, |id, name| {
+        nodes[id as usize - 1].children.get(name).copied()
+    })
+}
+
+impl Filesystem {
+    /// Internal helper: answer requests until the file system is unmounted (`true`) or
+    /// a signal interrupts the wait (`false`)
+    fn serve(&mut self, device: &mut File) -> Result<bool> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let length = match device.read(&mut buffer) {
+                Ok(length) => length,
+                // The request was interrupted before it was read
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => continue,
+                Err(e) if e.raw_os_error() == Some(libc::ENODEV) => return Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(false),
+                Err(e) => return Err(e.into()),
+            };
+            if length < IN_HEADER_SIZE {
+                return Err(ProjzstError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "short FUSE request",
+                )));
+            }
+            let opcode = u32_at(&buffer, 4);
+            let unique = u64_at(&buffer, 8);
+            let node = u64_at(&buffer, 16);
+            let body = &buffer[IN_HEADER_SIZE..length];
+            let result = match opcode {
+                FORGET | BATCH_FORGET | INTERRUPT => continue,
+                DESTROY => {
+                    reply(device, unique, Ok(Vec::new()))?;
+                    return Ok(true);
+                }
+                _ => self.handle(opcode, node, body),
+            };
+            reply(device, unique, result)?;
+        }
+    }
+
+    /// Internal helper: the reply to one request, or an errno
+    fn handle(&mut self, opcode: u32, node: u64, body: &[u8]) -> std::result::Result<Vec<u8>, i32> {
+        match opcode {
+            INIT => {
+                let max_readahead = u32_at(body, 8);
+                let mut out = Vec::with_capacity(64);
+                put_u32(&mut out, KERNEL_VERSION);
+                put_u32(&mut out, KERNEL_MINOR_VERSION);
+                put_u32(&mut out, max_readahead);
+                put_u32(&mut out, 0); // flags
+                out.extend_from_slice(&16u16.to_le_bytes()); // max_background
+                out.extend_from_slice(&12u16.to_le_bytes()); // congestion_threshold
+                put_u32(&mut out, MAX_WRITE);
+                put_u32(&mut out, 1); // time_gran
+                out.resize(64, 0);
+                Ok(out)
+            }
+            LOOKUP => {
+                let name = request_name(body);
+                let child = self.node(node)?.children.get(name).copied();
+                let child = child.ok_or(libc::ENOENT)?;
+                let mut out = Vec::with_capacity(128);
+                put_u64(&mut out, child);
+                put_u64(&mut out, 0); // generation
+                put_u64(&mut out, TTL_SECONDS); // entry_valid
+                put_u64(&mut out, TTL_SECONDS); // attr_valid
+                put_u32(&mut out, 0);
+                put_u32(&mut out, 0);
+                self.put_attr(&mut out, child)?;
+                Ok(out)
+            }
+            GETATTR => {
+                let mut out = Vec::with_capacity(104);
+                put_u64(&mut out, TTL_SECONDS);
+                put_u32(&mut out, 0);
+                put_u32(&mut out, 0);
+                self.put_attr(&mut out, node)?;
+                Ok(out)
+            }
+            READLINK => match self.node(node)? {
+                Node {
+                    kind: EntryKind::Symlink,
+                    link: Some(link),
+                    ..
+                } => Ok(link.clone().into_bytes()),
+                _ => Err(libc::EINVAL),
+            },
+            OPEN => {
+                let file = self.node(node)?;
+                if file.kind != EntryKind::File {
+                    return Err(libc::EISDIR);
+                }
+                if u32_at(body, 0) as i32 & libc::O_ACCMODE != libc::O_RDONLY {
+                    return Err(libc::EROFS);
+                }
+                let file = OpenFile {
+                    path: file.path.clone(),
+                    position: 0,
+                    pieces: None,
+                    piece: Vec::new(),
+                };
+                let handle = self.next_handle;
+                self.next_handle += 1;
+                self.handles.insert(handle, file);
+                Ok(open_out(handle, FOPEN_KEEP_CACHE))
+            }
+            READ => {
+                let handle = u64_at(body, 0);
+                let offset = u64_at(body, 8);
+                let size = u32_at(body, 16) as usize;
+                let file = self.handles.get_mut(&handle).ok_or(libc::EBADF)?;
+                file.read(&self.input, offset, size).map_err(|_| libc::EIO)
+            }
+            RELEASE => {
+                self.handles.remove(&u64_at(body, 0));
+                Ok(Vec::new())
+            }
+            OPENDIR => match self.node(node)?.kind {
+                EntryKind::Directory => Ok(open_out(0, 0)),
+                _ => Err(libc::ENOTDIR),
+            },
+            READDIR => {
+                let offset = u64_at(body, 8) as usize;
+                let size = u32_at(body, 16) as usize;
+                self.read_dir(node, offset, size)
+            }
+            RELEASEDIR => Ok(Vec::new()),
+            STATFS => {
+                let blocks: u64 = self.nodes.iter().map(|n| n.size.div_ceil(512)).sum();
+                let mut out = Vec::with_capacity(80);
+                put_u64(&mut out, blocks);
+                put_u64(&mut out, 0); // bfree
+                put_u64(&mut out, 0); // bavail
+                put_u64(&mut out, self.nodes.len() as u64);
+                put_u64(&mut out, 0); // ffree
+                put_u32(&mut out, 512); // bsize
+                put_u32(&mut out, 255); // namelen
+                put_u32(&mut out, 512); // frsize
+                out.resize(80, 0);
+                Ok(out)
+            }
+            ACCESS => {
+                self.node(node)?;
+                if u32_at(body, 0) as i32 & libc::W_OK != 0 {
+                    return Err(libc::EROFS);
+                }
+                Ok(Vec::new())
+            }
+            _ => Err(libc::ENOSYS),
+        }
+    }
+
+    /// Internal helper: the node with id `id`
+    fn node(&self, id: u64) -> std::result::Result<&Node, i32> {
+        id.checked_sub(1)
+            .and_then(|index| self.nodes.get(index as usize))
+            .ok_or(libc::ENOENT)
+    }
+
+    /// Internal helper: append the `fuse_attr` of node `id`
+    fn put_attr(&self, out: &mut Vec<u8>, id: u64) -> std::result::Result<(), i32> {
+        let node = self.node(id)?;
+        let (file_type, size) = match node.kind {
+            EntryKind::Directory => (libc::S_IFDIR, 0),
+            EntryKind::Symlink => (
+                libc::S_IFLNK,
+                node.link.as_ref().map_or(0, |link| link.len() as u64),
+            ),
+            _ => (libc::S_IFREG, node.size),
+        };
+        put_u64(out, id);
+        put_u64(out, size);
+        put_u64(out, size.div_ceil(512));
+        for _ in 0..3 {
+            put_u64(out, node.mtime); // atime, mtime, ctime
+        }
+        for _ in 0..3 {
+            put_u32(out, 0);
+        }
+        put_u32(out, file_type | node.mode);
+        put_u32(out, node.links);
+        put_u32(out, self.uid);
+        put_u32(out, self.gid);
+        put_u32(out, 0); // rdev
+        put_u32(out, 4096); // blksize
+        put_u32(out, 0); // flags
+        Ok(())
+    }
+
+    /// Internal helper: `fuse_dirent` records of directory `id` from position `offset`,
+    /// as many as fit in `size` bytes
+    fn read_dir(&self, id: u64, offset: usize, size: usize) -> std::result::Result<Vec<u8>, i32> {
+        let directory = self.node(id)?;
+        if directory.kind != EntryKind::Directory {
+            return Err(libc::ENOTDIR);
+        }
+        let listing = [(".", id), ("..", directory.parent)].into_iter().chain(
+            directory
+                .children
+                .iter()
+                .map(|(name, &child)| (name.as_str(), child)),
+        );
+        let mut out = Vec::new();
+        for (position, (name, child)) in listing.enumerate().skip(offset) {
+            let record = (24 + name.len()).next_multiple_of(8);
+            if out.len() + record > size {
+                break;
+            }
+            let dirent_type = match self.nodes[child as usize - 1].kind {
+                EntryKind::Directory => libc::DT_DIR,
+                EntryKind::Symlink => libc::DT_LNK,
+                _ => libc::DT_REG,
+            };
+            put_u64(&mut out, child);
+            put_u64(&mut out, position as u64 + 1);
+            put_u32(&mut out, name.len() as u32);
+            put_u32(&mut out, u32::from(dirent_type));
+            out.extend_from_slice(name.as_bytes());
+            out.resize(out.len().next_multiple_of(8), 0);
+        }
+        Ok(out)
+    }
+}
+
+/// Internal helper: write the reply to request `unique` in a single write, as the kernel
+/// requires
+fn reply<W: Write>(
+    device: &mut W,
+    unique: u64,
+    result: std::result::Result<Vec<u8>, i32>,
+) -> io::Result<()> {
+    let (error, data) = match result {
+        Ok(data) => (0, data),
+        Err(errno) => (-errno, Vec::new()),
+    };
+    let mut message = Vec::with_capacity(16 + data.len());
+    put_u32(&mut message, (16 + data.len()) as u32);
+    message.extend_from_slice(&error.to_le_bytes());
+    put_u64(&mut message, unique);
+    message.extend_from_slice(&data);
+    match device.write(&message) {
+        Ok(_) => Ok(()),
+        // The request was interrupted and is gone
+        Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Internal helper: a `fuse_open_out`
+fn open_out(handle: u64, flags: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16);
+    put_u64(&mut out, handle);
+    put_u32(&mut out, flags);
+    put_u32(&mut out, 0);
+    out
+}
+
+/// Internal helper: the `/dev/fuse` descriptor of a new mount at `mountpoint`
+fn open_device(mountpoint: &Path) -> Result<File> {
+    // SAFETY: geteuid cannot fail
+    if unsafe { libc::geteuid() } == 0 {
+        mount_directly(mountpoint)
+    } else {
+        mount_with_fusermount(mountpoint)
+    }
+}
+
+/// Internal helper: mount with `mount(2)`, which needs `CAP_SYS_ADMIN`
+fn mount_directly(mountpoint: &Path) -> Result<File> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/fuse")?;
+    // SAFETY: getuid and getgid cannot fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let options = format!(
+        "fd={},rootmode=40000,user_id={uid},group_id={gid},default_permissions,allow_other",
+        device.as_raw_fd()
+    );
+    let c_string = |bytes: &[u8]| {
+        CString::new(bytes)
+            .map_err(|e| ProjzstError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))
+    };
+    let source = c_string(b"projzst")?;
+    let target = c_string(mountpoint.as_os_str().as_bytes())?;
+    let file_system_type = c_string(b"fuse.projzst")?;
+    let data = c_string(options.as_bytes())?;
+    let flags = libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+    // SAFETY: every pointer is a valid NUL-terminated string living through the call
+    let result = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            file_system_type.as_ptr(),
+            flags,
+            data.as_ptr().cast(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(device)
+}
+
+/// Internal helper: mount through the setuid `fusermount3`/`fusermount` helper, which
+/// sends back the `/dev/fuse` descriptor over a socket
+fn mount_with_fusermount(mountpoint: &Path) -> Result<File> {
+    let (ours, theirs) = UnixStream::pair()?;
+    // The helper inherits its end of the socket
+    // SAFETY: clearing FD_CLOEXEC on a descriptor we own
+    if unsafe { libc::fcntl(theirs.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    for program in ["fusermount3", "fusermount"] {
+        let status = Command::new(program)
+            .args([
+                "-o",
+                "ro,nosuid,nodev,default_permissions,fsname=projzst,subtype=projzst",
+            ])
+            .arg("--")
+            .arg(mountpoint)
+            .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+            .status();
+        match status {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+            Ok(status) if !status.success() => {
+                return Err(ProjzstError::Io(io::Error::other(format!(
+                    "{program} failed to mount {}",
+                    mountpoint.display()
+                ))))
+            }
+            Ok(_) => return Ok(File::from(receive_descriptor(&ours)?)),
+        }
+    }
+    Err(ProjzstError::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        "mounting as a regular user needs fusermount3 or fusermount on PATH",
+    )))
+}
+
+/// Internal helper: receive the descriptor `fusermount` passes with `SCM_RIGHTS`
+fn receive_descriptor(socket: &UnixStream) -> io::Result<OwnedFd> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: byte.len(),
+    };
+    // u64 for the alignment of `cmsghdr`
+    let mut control = [0u64; 8];
+    // SAFETY: an all-zero msghdr is valid
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+    // SAFETY: message points at buffers living through the call
+    if unsafe { libc::recvmsg(socket.as_raw_fd(), &mut message, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the control buffer was filled by recvmsg
+    let header = unsafe { libc::CMSG_FIRSTHDR(&message) };
+    // SAFETY: checked for null before reading the header
+    if header.is_null() || unsafe { (*header).cmsg_type } != libc::SCM_RIGHTS {
+        return Err(io::Error::other("fusermount sent no file descriptor"));
+    }
+    // SAFETY: an SCM_RIGHTS message carries at least one descriptor, now ours
+    let fd: RawFd = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(header).cast()) };
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Internal helper: detach the mount at `mountpoint`
+fn unmount(mountpoint: &Path) -> Result<()> {
+    // SAFETY: geteuid cannot fail
+    if unsafe { libc::geteuid() } == 0 {
+        let target = CString::new(mountpoint.as_os_str().as_bytes())
+            .map_err(|e| ProjzstError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        // SAFETY: target is a valid NUL-terminated string
+        if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        return Ok(());
+    }
+    for program in ["fusermount3", "fusermount"] {
+        match Command::new(program).arg("-uz").arg(mountpoint).status() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+            Ok(_) => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: the NUL-terminated name at the start of `bytes`
+fn request_name(bytes: &[u8]) -> &str {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or_default()
+}
+
+/// Internal helper: little-endian `u32` at `offset`
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap_or_default()))
+}
+
+/// Internal helper: little-endian `u64` at `offset`
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    bytes
+        .get(offset..offset + 8)
+        .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap_or_default()))
+}
+
+/// Internal helper: append a little-endian `u32`
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Internal helper: append a little-endian `u64`
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Metadata, Packer};
+
+    /// Internal helper: a file system serving an archive of `a.txt` and `docs/big.bin`
+    fn filesystem(dir: &Path, big: &[u8]) -> Filesystem {
+        let source = dir.join("source");
+        std::fs::create_dir_all(source.join("docs")).unwrap();
+        std::fs::write(source.join("a.txt"), "alpha").unwrap();
+        std::fs::write(source.join("docs/big.bin"), big).unwrap();
+        let input = dir.join("test.pjz");
+        Packer::new(Metadata::default())
+            .pack(&source, &input)
+            .unwrap();
+        Filesystem {
+            nodes: build_tree(list_entries(&input).unwrap()),
+            input,
+            uid: 0,
+            gid: 0,
+            handles: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Internal helper: a request body of little-endian fields
+    fn body(fields: &[u64], widths: &[usize]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (&field, &width) in fields.iter().zip(widths) {
+            out.extend_from_slice(&field.to_le_bytes()[..width]);
+        }
+        out
+    }
+
+    #[test]
+    fn test_reply_encoding() {
+        let mut message = Vec::new();
+        reply(&mut message, 42, Ok(vec![1, 2, 3])).unwrap();
+        assert_eq!(u32_at(&message, 0), 19);
+        assert_eq!(u32_at(&message, 4), 0);
+        assert_eq!(u64_at(&message, 8), 42);
+        assert_eq!(&message[16..], [1, 2, 3]);
+
+        message.clear();
+        reply(&mut message, 7, Err(libc::ENOENT)).unwrap();
+        assert_eq!(message.len(), 16);
+        assert_eq!(u32_at(&message, 4) as i32, -libc::ENOENT);
+        assert_eq!(u64_at(&message, 8), 7);
+
+        // Truncated requests read as zeros instead of panicking
+        assert_eq!(u32_at(&[1, 2], 0), 0);
+        assert_eq!(u64_at(&message, 12), 0);
+        assert_eq!(request_name(b"docs\0junk"), "docs");
+    }
+
+    #[test]
+    fn test_requests() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let big: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut filesystem = filesystem(temp.path(), &big);
+
+        let init = filesystem
+            .handle(INIT, 0, &body(&[7, 31, 65536, 0], &[4, 4, 4, 4]))
+            .unwrap();
+        assert_eq!(init.len(), 64);
+        assert_eq!(u32_at(&init, 0), KERNEL_VERSION);
+        assert_eq!(u32_at(&init, 4), KERNEL_MINOR_VERSION);
+        assert_eq!(u32_at(&init, 8), 65536);
+        assert_eq!(u32_at(&init, 20), MAX_WRITE);
+
+        let docs = filesystem.handle(LOOKUP, ROOT_ID, b"docs\0").unwrap();
+        let docs = u64_at(&docs, 0);
+        let entry = filesystem.handle(LOOKUP, docs, b"big.bin\0").unwrap();
+        let file = u64_at(&entry, 0);
+        // fuse_attr follows the ids and timeouts: ino, then size
+        assert_eq!(u64_at(&entry, 40), file);
+        assert_eq!(u64_at(&entry, 48), big.len() as u64);
+        assert_eq!(
+            filesystem.handle(LOOKUP, ROOT_ID, b"missing\0"),
+            Err(libc::ENOENT)
+        );
+
+        let listing = filesystem
+            .handle(READDIR, ROOT_ID, &body(&[0, 0, 4096], &[8, 8, 4]))
+            .unwrap();
+        let mut names = Vec::new();
+        let mut offset = 0;
+        while offset < listing.len() {
+            let len = u32_at(&listing, offset + 16) as usize;
+            names.push(request_name(&listing[offset + 24..offset + 24 + len]).to_string());
+            offset += (24 + len).next_multiple_of(8);
+        }
+        assert_eq!(names, [".", "..", "a.txt", "docs"]);
+
+        assert_eq!(filesystem.handle(OPEN, docs, &[0; 8]), Err(libc::EISDIR));
+        let write_only = body(&[libc::O_WRONLY as u64, 0], &[4, 4]);
+        assert_eq!(filesystem.handle(OPEN, file, &write_only), Err(libc::EROFS));
+        let open = filesystem.handle(OPEN, file, &[0; 8]).unwrap();
+        let handle = u64_at(&open, 0);
+        assert_eq!(u32_at(&open, 8), FOPEN_KEEP_CACHE);
+
+        // Reads are served by offset: ahead, then back, then past the end
+        let read = |filesystem: &mut Filesystem, offset: u64, size: u64| {
+            filesystem.handle(READ, file, &body(&[handle, offset, size], &[8, 8, 4]))
+        };
+        assert_eq!(read(&mut filesystem, 0, 4096).unwrap(), big[..4096]);
+        assert_eq!(
+            read(&mut filesystem, 200_000, 131_072).unwrap(),
+            big[200_000..]
+        );
+        assert_eq!(read(&mut filesystem, 10, 5).unwrap(), big[10..15]);
+        assert!(read(&mut filesystem, 400_000, 4096).unwrap().is_empty());
+
+        filesystem
+            .handle(RELEASE, file, &body(&[handle], &[8]))
+            .unwrap();
+        assert_eq!(read(&mut filesystem, 0, 4096), Err(libc::EBADF));
+    }
+
+    #[test]
+    fn test_serve() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut filesystem = filesystem(temp.path(), b"beta");
+
+        // A socket pair keeping message boundaries stands in for /dev/fuse
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors returned
+        let result =
+            unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0, fds.as_mut_ptr()) };
+        assert_eq!(result, 0);
+        // SAFETY: both descriptors were just created and are owned here only
+        let (mut kernel, mut device) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let server = thread::spawn(move || filesystem.serve(&mut device));
+
+        let mut call = |opcode: u32, unique: u64, node: u64, body: &[u8]| {
+            let mut request = Vec::new();
+            put_u32(&mut request, (IN_HEADER_SIZE + body.len()) as u32);
+            put_u32(&mut request, opcode);
+            put_u64(&mut request, unique);
+            put_u64(&mut request, node);
+            request.resize(IN_HEADER_SIZE, 0);
+            request.extend_from_slice(body);
+            kernel.write_all(&request).unwrap();
+            if opcode == FORGET {
+                return None;
+            }
+            let mut message = vec![0; BUFFER_SIZE];
+            let length = kernel.read(&mut message).unwrap();
+            message.truncate(length);
+            assert_eq!(u32_at(&message, 0) as usize, length);
+            assert_eq!(u64_at(&message, 8), unique);
+            Some((u32_at(&message, 4) as i32, message[16..].to_vec()))
+        };
+
+        let (error, init) = call(INIT, 1, 0, &body(&[7, 31, 65536, 0], &[4, 4, 4, 4])).unwrap();
+        assert_eq!((error, u32_at(&init, 0)), (0, KERNEL_VERSION));
+        let (_, docs) = call(LOOKUP, 2, ROOT_ID, b"docs\0").unwrap();
+        let (_, entry) = call(LOOKUP, 3, u64_at(&docs, 0), b"big.bin\0").unwrap();
+        let file = u64_at(&entry, 0);
+        let (_, open) = call(OPEN, 4, file, &[0; 8]).unwrap();
+        let read = body(&[u64_at(&open, 0), 0, 4096], &[8, 8, 4]);
+        assert_eq!(call(READ, 5, file, &read).unwrap(), (0, b"beta".to_vec()));
+        // Forgetting is not answered; errors are, as negated errnos
+        assert_eq!(call(FORGET, 6, file, &body(&[1], &[8])), None);
+        let (error, _) = call(LOOKUP, 7, ROOT_ID, b"missing\0").unwrap();
+        assert_eq!(error, -libc::ENOENT);
+
+        assert_eq!(call(DESTROY, 8, 0, &[]).unwrap().0, 0);
+        assert!(server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_mount() {
+        // Mounting needs /dev/fuse and, without fusermount, root
+        // SAFETY: geteuid cannot fail
+        let root = unsafe { libc::geteuid() } == 0;
+        let device = OpenOptions::new().read(true).write(true).open("/dev/fuse");
+        if !root || device.is_err() {
+            // Not mountable here; test_serve still covers the requests
+            return;
+        }
+        let temp = tempfile::TempDir::new().unwrap();
+        let input = filesystem(temp.path(), b"beta").input;
+        let mountpoint = temp.path().join("mnt");
+        std::fs::create_dir(&mountpoint).unwrap();
+
+        let server = {
+            let mountpoint = mountpoint.clone();
+            thread::spawn(move || mount(input, mountpoint))
+        };
+        let file = mountpoint.join("docs/big.bin");
+        for _ in 0..100 {
+            if file.exists() || server.is_finished() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let listed = std::fs::read_dir(&mountpoint)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap());
+        let mut listed: Vec<String> = listed.collect();
+        listed.sort();
+        let content = std::fs::read(&file);
+        // Unmounted whatever the reads found, so a failure leaves no mount behind
+        unmount(&mountpoint).unwrap();
+        assert!(server.join().unwrap().is_ok());
+        assert_eq!(listed, ["a.txt", "docs"]);
+        assert_eq!(content.unwrap(), b"beta");
+        assert_eq!(std::fs::read_dir(&mountpoint).unwrap().count(), 0);
+    }
+}
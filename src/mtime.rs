@@ -0,0 +1,116 @@
+//! Modification times of packed and extracted entries
+//!
+//! Tar headers hold whole seconds. [`Packer::subsecond_mtimes`](crate::Packer::subsecond_mtimes)
+//! adds a PAX `mtime` record with the nanoseconds as well, which extraction restores.
+//! [`MtimePolicy`] decides what extraction does with the stored times: restore them, pull
+//! those in the future back to the time of extraction, or leave every entry with the time
+//! it was written at.
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use filetime::FileTime;
+
+use crate::errors::{ProjzstError, Result};
+use crate::string_utils::IntoOpStr;
+
+/// What extraction does with the modification times stored in the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MtimePolicy {
+    /// Restore stored times, to the nanosecond when a PAX `mtime` record has them (default)
+    #[default]
+    Restore,
+    /// Restore stored times, but set those later than the time of extraction to it
+    Clamp,
+    /// Leave entries with the time they were extracted at
+    Skip,
+}
+
+impl MtimePolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "restore" => Ok(MtimePolicy::Restore),
+            "clamp" => Ok(MtimePolicy::Clamp),
+            "skip" | "none" => Ok(MtimePolicy::Skip),
+            _ => Err(ProjzstError::InvalidMtimePolicy(s.to_string())),
+        }
+    }
+}
+
+/// Internal helper: the PAX `mtime` value of a file, seconds with a nanosecond fraction;
+/// `None` for times before the epoch or unknown
+pub(crate) fn pax_mtime(info: &fs::Metadata) -> Option<String> {
+    let since_epoch = info.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "{}.{:09}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    ))
+}
+
+/// Internal helper: the modification time of an entry, from its PAX `mtime` record when
+/// it has one, else from its header
+pub(crate) fn entry_mtime<R: std::io::Read>(entry: &mut tar::Entry<'_, R>) -> Result<FileTime> {
+    if let Some(extensions) = entry.pax_extensions()? {
+        for extension in extensions {
+            let extension = extension?;
+            if extension.key() != Ok("mtime") {
+                continue;
+            }
+            if let Some(time) = extension.value().ok().and_then(parse_pax_time) {
+                return Ok(time);
+            }
+        }
+    }
+    Ok(FileTime::from_unix_time(entry.header().mtime()? as i64, 0))
+}
+
+/// Internal helper: set the modification time of an extracted entry at `target` as
+/// `policy` says, `mtime` being the stored one
+/// Symlinks are left alone, as by the tar crate
+pub(crate) fn apply(policy: MtimePolicy, target: &Path, mtime: FileTime) -> Result<()> {
+    let Some(mtime) = resolve(policy, mtime) else {
+        return Ok(());
+    };
+    let info = target.symlink_metadata()?;
+    if info.file_type().is_symlink() {
+        return Ok(());
+    }
+    // Not `set_file_mtime`, which opens the file and would block on a FIFO
+    let atime = FileTime::from_last_access_time(&info);
+    filetime::set_symlink_file_times(target, atime, mtime)?;
+    Ok(())
+}
+
+/// Internal helper: the time `policy` gives an entry stored with `mtime`, `None` to leave
+/// it with the time it was written at
+pub(crate) fn resolve(policy: MtimePolicy, mtime: FileTime) -> Option<FileTime> {
+    match policy {
+        MtimePolicy::Skip => None,
+        MtimePolicy::Restore => Some(mtime),
+        MtimePolicy::Clamp => Some(mtime.min(FileTime::from_system_time(SystemTime::now()))),
+    }
+}
+
+/// Internal helper: parse a PAX time, `seconds[.fraction]`
+fn parse_pax_time(value: &str) -> Option<FileTime> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let seconds: i64 = seconds.parse().ok()?;
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Nanoseconds: the first nine digits, padded with zeros
+    let nanos: u32 = format!("{:0<9}", &fraction[..fraction.len().min(9)])
+        .parse()
+        .ok()?;
+    // The fraction of a negative time counts away from zero too
+    if value.starts_with('-') && nanos > 0 {
+        return Some(FileTime::from_unix_time(seconds - 1, 1_000_000_000 - nanos));
+    }
+    Some(FileTime::from_unix_time(seconds, nanos))
+}
@@ -0,0 +1,155 @@
+//! File naming conventions
+//!
+//! A naming template such as `{name}-{ver}.pjz` describes how archives should be named
+//! after their metadata. Placeholders are the metadata fields (`{name}`, `{ver}`,
+//! `{license}`, ...; keywords and dependencies joined by commas) and scalar `extra` values
+//! by dotted path (`{extra.build.target}`); `{{` and `}}` stand for literal braces.
+
+use std::path::Path;
+
+use crate::builder::read_metadata;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata, FIELDS};
+use crate::volume::base_path;
+
+/// Render a naming template with the values of `metadata`
+///
+/// # Arguments
+/// * `template` - Naming template, e.g. `{name}-{ver}.pjz`
+/// * `metadata` - Metadata providing the placeholder values
+pub fn expected_file_name(template: &str, metadata: &Metadata) -> Result<String> {
+    let invalid = || ProjzstError::InvalidNamingTemplate(template.to_string());
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find(['{', '}']) {
+        rendered.push_str(&rest[..idx]);
+        let tail = &rest[idx..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            rendered.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('}') {
+            return Err(invalid());
+        }
+
+        let end = tail.find('}').ok_or_else(invalid)?;
+        let placeholder = &tail[1..end];
+        let value = placeholder_value(metadata, placeholder).ok_or_else(|| {
+            ProjzstError::NamingFieldMissing {
+                field: placeholder.to_string(),
+                template: template.to_string(),
+            }
+        })?;
+        rendered.push_str(&value);
+        rest = &tail[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+/// Derive an archive file name from metadata: `<name>-<ver>.pjz`, or `<name>.pjz` without
+/// a version. Characters other than ASCII letters, digits, `.`, `-`, `_` and `+` become
+/// `_`, and leading dots are dropped, so the result is a plain file name on any system
+///
+/// # Arguments
+/// * `metadata` - Metadata providing the name and version
+pub fn auto_file_name(metadata: &Metadata) -> Result<String> {
+    let name = metadata
+        .name
+        .as_deref()
+        .map(sanitize)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| ProjzstError::NamingFieldMissing {
+            field: "name".to_string(),
+            template: AUTO_NAME_TEMPLATE.to_string(),
+        })?;
+    Ok(match metadata.ver.as_deref().map(sanitize) {
+        Some(ver) if !ver.is_empty() => format!("{name}-{ver}.pjz"),
+        _ => format!("{name}.pjz"),
+    })
+}
+
+/// Check that a .pjz file is named as its metadata says it should be
+/// Only the file name is compared, not its directory; the volumes of a split archive
+/// are checked under their base name (`out.pjz.001` as `out.pjz`)
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+/// * `template` - Naming template, e.g. `{name}-{ver}.pjz`
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn verify_naming<P: AsRef<Path>>(
+    input_file: P,
+    template: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let input_file = input_file.as_ref();
+    let metadata = read_metadata(input_file, ignore_unknown)?;
+
+    let expected = expected_file_name(template, &metadata)?;
+    let actual = base_path(input_file)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if actual != expected {
+        return Err(ProjzstError::NamingMismatch { actual, expected });
+    }
+
+    Ok(metadata)
+}
+
+/// Naming template [`auto_file_name`] follows
+const AUTO_NAME_TEMPLATE: &str = "{name}-{ver}.pjz";
+
+/// Internal helper: replace characters unsafe in file names by `_`, dropping leading dots
+fn sanitize(value: &str) -> String {
+    value
+        .trim_start_matches('.')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Internal helper: string value of a template placeholder (a field name or an
+/// `extra.` path), `None` when unset
+pub(crate) fn placeholder_value(metadata: &Metadata, placeholder: &str) -> Option<String> {
+    if FIELDS.contains(&placeholder) {
+        return match metadata.field_value(placeholder)? {
+            serde_json::Value::String(s) => Some(s),
+            // Keywords, and dependencies as `name@req`
+            serde_json::Value::Array(items) => Some(
+                items
+                    .iter()
+                    .filter_map(|item| match item {
+                        serde_json::Value::String(s) => Some(s.clone()),
+                        _ => Some(format!(
+                            "{}@{}",
+                            item["name"].as_str()?,
+                            item["req"].as_str()?
+                        )),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            _ => None,
+        };
+    }
+    let path = placeholder.strip_prefix("extra.")?;
+    let value = path
+        .split('.')
+        .try_fold(&metadata.extra, |value, key| value.get(key))?;
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
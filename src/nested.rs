@@ -0,0 +1,168 @@
+//! Recursive unpacking of archives inside archives
+//!
+//! With [`ReadOptions::recursive`], an unpack goes on to the .pjz files in the output
+//! directory, recognized by the metadata frame they start with rather than by name. Each
+//! one is unpacked in place, `name.pjz` becoming the directory `name` (`name.d` for a
+//! file without extension) next to `name.metadata.json`, and the files that came out of
+//! it are searched in turn. Three guards keep a hostile archive from running away:
+//!
+//! - a depth limit, the `max_depth` given to [`ReadOptions::recursive`];
+//! - a size budget shared by all nested archives, checked against the sizes in their
+//!   entry headers before anything is written ([`ReadOptions::nested_size_limit`]);
+//! - a cycle check: an archive identical to one it was found in is not unpacked again.
+//!
+//! Archives stopped by a guard stay packed and are listed in
+//! [`UnpackReport::nested_skipped`].
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::builder::{ReadOptions, METADATA_FRAME_MAGIC};
+use crate::catalog::{file_digest, relative_path};
+use crate::errors::Result;
+use crate::listing::list_entries;
+use crate::remap::PathMap;
+use crate::report::UnpackReport;
+
+/// Default of [`ReadOptions::nested_size_limit`]: 4 GiB
+pub(crate) const DEFAULT_NESTED_SIZE_LIMIT: u64 = 4 << 30;
+
+/// An archive found among extracted files
+struct Nested {
+    file: PathBuf,
+    /// Digests of the archives it was found in, outermost first
+    ancestors: Vec<String>,
+    depth: usize,
+}
+
+/// Unpack the archives found below `output_dir` in place, then those found in them, down
+/// to the depth of [`ReadOptions::recursive`]
+/// Paths in `report` are relative to `output_dir`
+pub(crate) fn unpack_nested(
+    options: &ReadOptions,
+    output_dir: &Path,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    // Nested archives keep their own layout: path options only apply to the outer one
+    let inner = ReadOptions {
+        path_map: PathMap {
+            strip_components: 0,
+            transforms: Vec::new(),
+            filter: None,
+            ..options.path_map.clone()
+        },
+        ..options.clone()
+    };
+    let magic = options.metadata_magic.unwrap_or(METADATA_FRAME_MAGIC);
+    let mut budget = options.nested_size_limit;
+    let mut pending: Vec<Nested> = find_archives(output_dir, magic)?
+        .into_iter()
+        .map(|file| Nested {
+            file,
+            ancestors: Vec::new(),
+            depth: 1,
+        })
+        .collect();
+
+    while let Some(nested) = pending.pop() {
+        let path = relative_path(output_dir, &nested.file);
+        if nested.depth > options.recursive_depth {
+            report.skip_nested(path, "deeper than the depth limit");
+            continue;
+        }
+        let digest = file_digest(&nested.file)?;
+        if nested.ancestors.contains(&digest) {
+            report.skip_nested(path, "a copy of an archive it is nested in");
+            continue;
+        }
+        let target = match nested.file.extension() {
+            Some(_) => nested.file.with_extension(""),
+            None => nested.file.with_extension("d"),
+        };
+        if fs::symlink_metadata(&target).is_ok() {
+            report.skip_nested(path, "no free directory name to unpack it to");
+            continue;
+        }
+        let size: u64 = match list_entries(&nested.file) {
+            Ok(entries) => entries.iter().map(|entry| entry.size).sum(),
+            Err(e) => {
+                report.entry_failed(path, e, options.keep_going)?;
+                continue;
+            }
+        };
+        if size > budget {
+            report.skip_nested(path, "over the nested size limit");
+            continue;
+        }
+        budget -= size;
+
+        let (metadata, has_metadata, unpacked) =
+            match inner.unpack_nested_file(&nested.file, &target) {
+                Ok(unpacked) => unpacked,
+                Err(e) => {
+                    if target.is_dir() {
+                        fs::remove_dir_all(&target)?;
+                    }
+                    report.entry_failed(path, e, options.keep_going)?;
+                    continue;
+                }
+            };
+        if has_metadata {
+            let mut metadata_file = target.clone().into_os_string();
+            metadata_file.push(".metadata.json");
+            fs::write(metadata_file, serde_json::to_string_pretty(&metadata)?)?;
+        }
+        fs::remove_file(&nested.file)?;
+        log::info!("{path}: unpacked {} nested entries", unpacked.extracted);
+        report.extracted += unpacked.extracted;
+        for (failed, reason) in unpacked.failed {
+            report.failed.push((format!("{path}/{failed}"), reason));
+        }
+        report.nested.push(path);
+
+        let mut ancestors = nested.ancestors;
+        ancestors.push(digest);
+        for file in find_archives(&target, magic)? {
+            pending.push(Nested {
+                file,
+                ancestors: ancestors.clone(),
+                depth: nested.depth + 1,
+            });
+        }
+    }
+    report.nested.sort();
+    report.nested_skipped.sort();
+    Ok(())
+}
+
+/// Internal helper: the regular files below `dir` starting with a metadata frame, without
+/// following symlinks; sorted in reverse so they are unpacked in path order
+fn find_archives(dir: &Path, magic: u32) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() && starts_with_metadata(&entry.path(), magic)? {
+                found.push(entry.path());
+            }
+        }
+    }
+    found.sort_by(|a, b| b.cmp(a));
+    Ok(found)
+}
+
+/// Internal helper: whether a file begins with a metadata frame of `magic`
+fn starts_with_metadata(file: &Path, magic: u32) -> Result<bool> {
+    let mut head = [0u8; 4];
+    let mut file = File::open(file)?;
+    match file.read_exact(&mut head) {
+        Ok(()) => Ok(head == magic.to_le_bytes()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,201 @@
+//! Package sets sharing a chunk store
+//!
+//! A release often ships several variants of the same tree (per platform, per edition)
+//! that differ in a handful of files. [`pack_set`] packs them in one go: their files are
+//! cut into content-defined chunks as with [`Packer::chunked`], the chunks found in more
+//! than one member go once into a separate chunk store archive, and each member stores
+//! only the chunks of its own. Members record the store in `extra.chunk_store` and are
+//! unpacked with [`ReadOptions::chunk_store`](crate::ReadOptions::chunk_store) pointing at
+//! it; the store itself is a .pjz holding the shared chunks under `.pjz-chunks/`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::builder::{encode_metadata, write_metadata_frame, Packer, METADATA_FRAME_MAGIC};
+use crate::chunking::{append_chunk, each_chunk};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{Metadata, MetadataEncoding};
+use crate::payload::PayloadEncoder;
+use crate::report::PackReport;
+use crate::temp::temp_file_for;
+use crate::walk::walk_source;
+
+/// Key of `extra` naming the chunk store a member was packed against
+pub(crate) const CHUNK_STORE_KEY: &str = "chunk_store";
+
+/// One archive of a package set
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SetMember {
+    /// Directory (or file) to pack
+    pub source: PathBuf,
+    /// .pjz file to write
+    pub output: PathBuf,
+    /// Metadata of this member, instead of the packer's
+    pub metadata: Option<Metadata>,
+}
+
+impl SetMember {
+    /// Pack `source` into `output` with the packer's metadata
+    pub fn new<P1: AsRef<Path>, P2: AsRef<Path>>(source: P1, output: P2) -> Self {
+        Self {
+            source: source.as_ref().to_path_buf(),
+            output: output.as_ref().to_path_buf(),
+            metadata: None,
+        }
+    }
+
+    /// Embed `metadata` in this member instead of the packer's
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
+/// Summary of a [`pack_set`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PackSetReport {
+    /// Distinct chunks found in more than one member, written to the store
+    pub shared_chunks: usize,
+    /// Size of the chunk store archive, in bytes
+    pub store_size: u64,
+    /// Report of each member, in the order given
+    pub members: Vec<PackReport>,
+}
+
+/// Pack several related trees into archives sharing one chunk store
+/// Every member is packed by `packer` (with its own metadata when it has some) against the
+/// store written first to `store_file`; see the [module documentation](self). Members
+/// sharing nothing still pack, chunked, with an empty store
+///
+/// # Arguments
+/// * `packer` - Options of every member; [`Packer::chunked`] is implied
+/// * `members` - Trees to pack and the archives to write
+/// * `store_file` - Path of the chunk store archive to write
+pub fn pack_set<P: AsRef<Path>>(
+    packer: &Packer,
+    members: &[SetMember],
+    store_file: P,
+) -> Result<PackSetReport> {
+    let store_file = store_file.as_ref();
+    if members
+        .iter()
+        .any(|member| member.output.as_path() == store_file)
+    {
+        return Err(ProjzstError::InvalidArgument(
+            "the chunk store cannot be one of the members".to_string(),
+        ));
+    }
+
+    // Chunks are counted once per member they appear in
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for member in members {
+        let mut seen = HashSet::new();
+        each_member_chunk(packer, member, |digest, _| {
+            seen.insert(digest);
+            Ok(())
+        })?;
+        for digest in seen {
+            *counts.entry(digest).or_default() += 1;
+        }
+    }
+    let shared: HashSet<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(digest, _)| digest)
+        .collect();
+    let store_size = write_store(packer, members, store_file, &shared)?;
+
+    let mut report = PackSetReport {
+        shared_chunks: shared.len(),
+        store_size,
+        members: Vec::new(),
+    };
+    for member in members {
+        let mut member_packer = packer.clone().chunk_store(store_file);
+        if let Some(metadata) = &member.metadata {
+            member_packer.metadata = metadata.clone();
+        }
+        report
+            .members
+            .push(member_packer.pack(&member.source, &member.output)?);
+    }
+    Ok(report)
+}
+
+/// Internal helper: write the chunk store holding the `shared` chunks of the members
+/// Returns the size of the file written
+fn write_store(
+    packer: &Packer,
+    members: &[SetMember],
+    store_file: &Path,
+    shared: &HashSet<String>,
+) -> Result<u64> {
+    let names: Vec<String> = members
+        .iter()
+        .filter_map(|member| member.output.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let metadata = Metadata {
+        extra: serde_json::json!({ "pack_set": names }),
+        ..Metadata::default()
+    };
+
+    if let Some(parent) = store_file.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let (mut output, temp) = temp_file_for(store_file)?;
+    write_metadata_frame(
+        &mut output,
+        METADATA_FRAME_MAGIC,
+        &encode_metadata(&metadata, MetadataEncoding::MessagePack)?,
+    )?;
+    let mut zst_encoder = PayloadEncoder::new(
+        &mut output,
+        packer.compression_level,
+        packer.effective_concurrency().compression_workers(),
+    )?;
+    {
+        let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+        let mut written = HashSet::new();
+        for member in members {
+            each_member_chunk(packer, member, |digest, chunk| {
+                if shared.contains(&digest) && !written.contains(&digest) {
+                    append_chunk(&mut tar_builder, packer.walk.format, &digest, chunk)?;
+                    written.insert(digest);
+                }
+                Ok(())
+            })?;
+        }
+        tar_builder.finish()?;
+    }
+    zst_encoder.finish()?.flush()?;
+    drop(output);
+    temp.persist(store_file)?;
+    Ok(fs::metadata(store_file)?.len())
+}
+
+/// Internal helper: call `f` with every chunk of the regular files a member stores
+fn each_member_chunk<F>(packer: &Packer, member: &SetMember, mut f: F) -> Result<()>
+where
+    F: FnMut(String, &[u8]) -> Result<()>,
+{
+    for root in packer.source_roots(&member.source)? {
+        let walk = walk_source(&root.path, root.prefix.as_deref(), &packer.walk)?;
+        for (_, path, info) in walk.entries {
+            if !info.is_file() {
+                continue;
+            }
+            let file = fs::File::open(&path).map_err(|e| ProjzstError::from(e).packing(&path))?;
+            each_chunk(file, &mut f).map_err(|e| e.packing(&path))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,560 @@
+//! Payload compression
+//!
+//! The tar stream following the skippable frames is written as a zstd frame. Level `0`
+//! stores it instead: the frame holds raw blocks only, which costs no CPU on content that
+//! is already compressed and is still read by any zstd decoder.
+//!
+//! In adaptive mode, files that look already compressed (by magic number, or because a
+//! sample of them does not shrink) go into stored frames and the rest into compressed
+//! ones. Zstd decoders read concatenated frames as one stream, so readers see no change.
+//!
+//! In per-entry mode, every file starts a new frame and an [`EntryIndex`] of the frames is
+//! written after the payload, so a single file can be decoded without the ones before it.
+//! Every frame boundary is also a point where an interrupted pack can resume, see
+//! [`PayloadEncoder::resume`].
+//!
+//! With another [`PayloadCodec`], the payload is a single stream of that codec instead,
+//! and the modes above do not apply.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::codec::PayloadCodec;
+#[cfg(feature = "brotli")]
+use crate::codec::{BROTLI_BUFFER_SIZE, BROTLI_WINDOW};
+use crate::dictionary::Dictionary;
+use crate::errors::{ProjzstError, Result};
+use crate::index::{EntryIndex, IndexedEntry};
+
+/// Compression level storing the payload uncompressed
+pub(crate) const STORE_LEVEL: i32 = 0;
+
+/// Largest zstd block (128 KiB)
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Zstd frame magic number (little-endian on disk)
+pub(crate) const ZSTD_FRAME_MAGIC: u32 = 0xFD2FB528;
+
+/// Frame header descriptor: no single segment, checksum, dictionary or content size
+pub(crate) const FRAME_HEADER_DESCRIPTOR: u8 = 0x00;
+
+/// Window descriptor for a 128 KiB window (exponent 7, mantissa 0), one block's worth
+const WINDOW_DESCRIPTOR: u8 = 7 << 3;
+
+/// Bytes of a file compressed to decide whether it is worth compressing
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Files smaller than this are always compressed: sampling would cost more than it saves
+const MIN_SAMPLED_SIZE: u64 = 4096;
+
+/// A sample compressing to more than this share of its size counts as incompressible
+const INCOMPRESSIBLE_RATIO: f64 = 0.95;
+
+/// Leading bytes of formats that are already compressed
+const COMPRESSED_MAGICS: &[&[u8]] = &[
+    b"\x28\xB5\x2F\xFD",   // zstd
+    b"\x1F\x8B",           // gzip
+    b"\xFD7zXZ\x00",       // xz
+    b"BZh",                // bzip2
+    b"PK\x03\x04",         // zip, jar, docx, apk
+    b"7z\xBC\xAF\x27\x1C", // 7-zip
+    b"\xFF\xD8\xFF",       // jpeg
+    b"\x89PNG",            // png
+    b"GIF8",               // gif
+    b"OggS",               // ogg
+    b"fLaC",               // flac
+    b"ID3",                // mp3
+];
+
+/// Largest window log zstd decoders accept without being told to (128 MiB)
+pub(crate) const MAX_WINDOW_LOG: u32 = 27;
+
+/// Smallest window log zstd accepts (1 KiB)
+const MIN_WINDOW_LOG: u32 = 10;
+
+/// Internal helper: fail with [`ProjzstError::InvalidCompressionLevel`] unless `level` is a
+/// zstd level (`1..=22` or a negative fast level) or [`STORE_LEVEL`]
+/// Ultra levels (20-22) are accepted as they are: the windows they pick stay within
+/// [`MAX_WINDOW_LOG`], so only compression pays for them in memory
+pub(crate) fn check_compression_level(level: i32) -> Result<()> {
+    if level != STORE_LEVEL
+        && !(zstd::zstd_safe::min_c_level()..=zstd::zstd_safe::max_c_level()).contains(&level)
+    {
+        return Err(ProjzstError::InvalidCompressionLevel(level));
+    }
+    Ok(())
+}
+
+/// Internal helper: fail with [`ProjzstError::InvalidWindowLog`] unless `window_log` is
+/// a window readers accept
+pub(crate) fn check_window_log(window_log: u32) -> Result<()> {
+    if !(MIN_WINDOW_LOG..=MAX_WINDOW_LOG).contains(&window_log) {
+        return Err(ProjzstError::InvalidWindowLog(window_log));
+    }
+    Ok(())
+}
+
+/// Writer compressing the payload at a zstd level, or storing it at [`STORE_LEVEL`]
+pub(crate) struct PayloadEncoder<W: Write> {
+    /// Frame being written; only `None` while switching frames
+    frame: Option<Frame<Counted<W>>>,
+    codec: PayloadCodec,
+    level: i32,
+    workers: u32,
+    adaptive: bool,
+    checksum: bool,
+    /// Window log of compressed frames, `None` for the default of the level
+    window_log: Option<u32>,
+    long_distance: bool,
+    /// Dictionary of compressed frames
+    dictionary: Option<Dictionary>,
+    /// Frames started so far in per-entry mode, `None` otherwise
+    index: Option<EntryIndex>,
+    /// Payload offset of the frame being written
+    frame_start: u64,
+}
+
+/// One zstd frame of the payload
+enum Frame<W: Write> {
+    /// Regular zstd compression
+    Compressed(zstd::stream::Encoder<'static, W>),
+    /// Raw blocks in an uncompressed frame
+    Stored(StoredFrame<W>),
+    /// The whole payload as one xz stream
+    #[cfg(feature = "xz")]
+    Xz(xz2::write::XzEncoder<W>),
+    /// The whole payload as one LZ4 frame
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+    /// The whole payload as one brotli stream
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::CompressorWriter<W>>),
+}
+
+impl<W: Write> PayloadEncoder<W> {
+    /// Start a payload frame on `output` at `level`, compressed by `workers` zstd worker
+    /// threads (`0` for none)
+    pub(crate) fn new(output: W, level: i32, workers: u32) -> io::Result<Self> {
+        Self::with_codec(output, PayloadCodec::Zstd, level, workers)
+    }
+
+    /// Start a payload on `output` compressed with `codec` at `level`, mapped onto the
+    /// levels of the codec; `workers` only applies to zstd
+    pub(crate) fn with_codec(
+        output: W,
+        codec: PayloadCodec,
+        level: i32,
+        workers: u32,
+    ) -> io::Result<Self> {
+        Self::starting_at(output, codec, level, workers, 0, None)
+    }
+
+    /// Start a zstd payload on `output` at `level`, compressing every frame with
+    /// `dictionary`
+    pub(crate) fn with_dictionary(
+        output: W,
+        level: i32,
+        workers: u32,
+        dictionary: Dictionary,
+    ) -> io::Result<Self> {
+        Self::starting_at(
+            output,
+            PayloadCodec::Zstd,
+            level,
+            workers,
+            0,
+            Some(dictionary),
+        )
+    }
+
+    /// Continue a per-entry payload cut short after the frames of `entries`, the next frame
+    /// starting at payload offset `offset`; `output` must be positioned there
+    pub(crate) fn resume(
+        output: W,
+        level: i32,
+        workers: u32,
+        offset: u64,
+        entries: Vec<IndexedEntry>,
+        dictionary: Option<Dictionary>,
+    ) -> io::Result<Self> {
+        let mut encoder = Self::starting_at(
+            output,
+            PayloadCodec::Zstd,
+            level,
+            workers,
+            offset,
+            dictionary,
+        )?;
+        encoder.index = Some(EntryIndex {
+            entries,
+            ..EntryIndex::default()
+        });
+        Ok(encoder)
+    }
+
+    /// Internal helper: start a payload frame at payload offset `offset`
+    fn starting_at(
+        output: W,
+        codec: PayloadCodec,
+        level: i32,
+        workers: u32,
+        offset: u64,
+        dictionary: Option<Dictionary>,
+    ) -> io::Result<Self> {
+        let output = Counted {
+            inner: output,
+            written: offset,
+        };
+        Ok(Self {
+            frame: Some(Frame::new(
+                output,
+                codec,
+                level,
+                level == STORE_LEVEL,
+                workers,
+                false,
+                dictionary.as_ref(),
+            )?),
+            codec,
+            level,
+            workers,
+            adaptive: false,
+            checksum: false,
+            window_log: None,
+            long_distance: false,
+            dictionary,
+            index: None,
+            frame_start: offset,
+        })
+    }
+
+    /// Store files that look already compressed instead of compressing them
+    /// See [`PayloadEncoder::begin_file`]
+    pub(crate) fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// End every compressed frame with a checksum of its content, which decoders check
+    /// when they reach the end of the frame; stored frames carry none
+    pub(crate) fn content_checksum(mut self, checksum: bool) -> io::Result<Self> {
+        self.checksum = checksum;
+        if let Frame::Compressed(encoder) = self.frame() {
+            encoder.include_checksum(checksum)?;
+        }
+        Ok(self)
+    }
+
+    /// Compress with a window of `2^window_log` bytes instead of the default of the level,
+    /// with long-distance matching finding repeats across all of it if `long_distance`
+    pub(crate) fn window(
+        mut self,
+        window_log: Option<u32>,
+        long_distance: bool,
+    ) -> io::Result<Self> {
+        self.window_log = window_log;
+        self.long_distance = long_distance;
+        self.tune_frame()?;
+        Ok(self)
+    }
+
+    /// Internal helper: apply the window settings to the frame being written, if compressed
+    fn tune_frame(&mut self) -> io::Result<()> {
+        let (window_log, long_distance) = (self.window_log, self.long_distance);
+        if let Frame::Compressed(encoder) = self.frame() {
+            if let Some(window_log) = window_log {
+                encoder.window_log(window_log)?;
+            }
+            if long_distance {
+                encoder.long_distance_matching(true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Give every file a frame of its own and write an [`EntryIndex`] after the payload
+    /// See [`PayloadEncoder::begin_file`]
+    pub(crate) fn per_entry(mut self, per_entry: bool) -> Self {
+        let index = self.index.take();
+        self.index = per_entry.then(|| index.unwrap_or_default());
+        self
+    }
+
+    /// Pick the frame for the file at `path`, about to be appended as `name`
+    /// Does nothing unless the encoder is adaptive or per-entry
+    pub(crate) fn begin_file(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        if self.codec != PayloadCodec::Zstd {
+            return Ok(());
+        }
+        let stored = matches!(self.frame, Some(Frame::Stored(_)));
+        let store = if self.adaptive && self.level != STORE_LEVEL {
+            is_incompressible(path)?
+        } else {
+            stored
+        };
+        if store == stored && self.index.is_none() {
+            return Ok(());
+        }
+        let output = self.frame.take().expect("payload frame").finish()?;
+        self.frame_start = output.written;
+        if let Some(index) = &mut self.index {
+            index.entries.push(IndexedEntry {
+                path: name.to_string(),
+                offset: output.written,
+                length: 0,
+            });
+        }
+        self.frame = Some(Frame::new(
+            output,
+            self.codec,
+            self.level,
+            store,
+            self.workers,
+            self.checksum,
+            self.dictionary.as_ref(),
+        )?);
+        self.tune_frame()
+    }
+
+    /// Pass what the frames before the current one wrote on to the underlying writer
+    /// Returns the payload offset of the current frame, where everything before is complete
+    pub(crate) fn flush_finished(&mut self) -> io::Result<u64> {
+        match self.frame() {
+            Frame::Compressed(encoder) => encoder.get_mut().flush()?,
+            Frame::Stored(frame) => frame.output.flush()?,
+            #[cfg(feature = "xz")]
+            Frame::Xz(encoder) => encoder.get_mut().flush()?,
+            #[cfg(feature = "lz4")]
+            Frame::Lz4(encoder) => encoder.get_mut().flush()?,
+            #[cfg(feature = "brotli")]
+            Frame::Brotli(encoder) => encoder.get_mut().flush()?,
+        }
+        Ok(self.frame_start)
+    }
+
+    /// End the frame, write the entry index in per-entry mode and return the underlying
+    /// writer
+    pub(crate) fn finish(self) -> io::Result<W> {
+        Ok(self.finish_counted()?.0)
+    }
+
+    /// End the payload like [`PayloadEncoder::finish`], also returning the size of its
+    /// frames (the entry index left out)
+    pub(crate) fn finish_counted(mut self) -> io::Result<(W, u64)> {
+        let mut output = self.frame.take().expect("payload frame").finish()?;
+        if let Some(mut index) = self.index.take() {
+            // Each frame ends where the next one starts
+            let mut end = output.written;
+            for entry in index.entries.iter_mut().rev() {
+                entry.length = end - entry.offset;
+                end = entry.offset;
+            }
+            index.payload_size = output.written;
+            index.write_to(&mut output.inner)?;
+        }
+        Ok((output.inner, output.written))
+    }
+
+    /// Internal helper: the frame being written
+    fn frame(&mut self) -> &mut Frame<Counted<W>> {
+        self.frame.as_mut().expect("payload frame")
+    }
+}
+
+impl<W: Write> Frame<W> {
+    /// Internal helper: start a stored frame, or a compressed one at `level`, with a
+    /// content checksum if `checksum` is set and with `dictionary` when given; other codecs
+    /// than zstd start their stream
+    fn new(
+        output: W,
+        codec: PayloadCodec,
+        level: i32,
+        store: bool,
+        workers: u32,
+        checksum: bool,
+        dictionary: Option<&Dictionary>,
+    ) -> io::Result<Self> {
+        #[allow(unreachable_patterns)]
+        match codec {
+            PayloadCodec::Zstd => {}
+            #[cfg(feature = "xz")]
+            PayloadCodec::Xz => {
+                let level = level.clamp(0, 9) as u32;
+                return Ok(Self::Xz(xz2::write::XzEncoder::new(output, level)));
+            }
+            #[cfg(feature = "lz4")]
+            PayloadCodec::Lz4 => {
+                // Cheap next to the decoding time lz4 is picked for
+                let info = lz4_flex::frame::FrameInfo::new().content_checksum(true);
+                return Ok(Self::Lz4(lz4_flex::frame::FrameEncoder::with_frame_info(
+                    info, output,
+                )));
+            }
+            #[cfg(feature = "brotli")]
+            PayloadCodec::Brotli => {
+                return Ok(Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                    output,
+                    BROTLI_BUFFER_SIZE,
+                    level.clamp(0, 11) as u32,
+                    BROTLI_WINDOW,
+                ))));
+            }
+            codec => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("payload codec {} is not built in", codec.name()),
+                ))
+            }
+        }
+        if store {
+            return Ok(Self::Stored(StoredFrame::new(output)?));
+        }
+        let dictionary = dictionary.map_or(&[][..], Dictionary::as_bytes);
+        let mut encoder = zstd::stream::Encoder::with_dictionary(output, level, dictionary)?;
+        encoder.include_checksum(checksum)?;
+        // Single-threaded on wasm32, which zstd is built without thread support for
+        #[cfg(not(target_arch = "wasm32"))]
+        if workers > 0 {
+            encoder.multithread(workers)?;
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = workers;
+        Ok(Self::Compressed(encoder))
+    }
+
+    /// Internal helper: end the frame and return the underlying writer
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Compressed(encoder) => encoder.finish(),
+            Self::Stored(frame) => frame.finish(),
+            #[cfg(feature = "xz")]
+            Self::Xz(encoder) => encoder.finish(),
+            #[cfg(feature = "lz4")]
+            Self::Lz4(encoder) => Ok(encoder.finish()?),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(mut encoder) => {
+                // Ending the stream swallows errors: those of the data before show here
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for PayloadEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.frame() {
+            Frame::Compressed(encoder) => encoder.write(buf),
+            Frame::Stored(frame) => frame.write(buf),
+            #[cfg(feature = "xz")]
+            Frame::Xz(encoder) => encoder.write(buf),
+            #[cfg(feature = "lz4")]
+            Frame::Lz4(encoder) => encoder.write(buf),
+            #[cfg(feature = "brotli")]
+            Frame::Brotli(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.frame() {
+            Frame::Compressed(encoder) => encoder.flush(),
+            Frame::Stored(frame) => frame.flush(),
+            #[cfg(feature = "xz")]
+            Frame::Xz(encoder) => encoder.flush(),
+            #[cfg(feature = "lz4")]
+            Frame::Lz4(encoder) => encoder.flush(),
+            #[cfg(feature = "brotli")]
+            Frame::Brotli(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writer keeping count of the bytes written, for the offsets of the entry index
+struct Counted<W: Write> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Internal helper: whether the file at `path` looks already compressed
+/// Checks known magic numbers (and the `ftyp` box of mp4/mov/heic), then compresses a
+/// sample of the file at level 1 to see whether it shrinks
+fn is_incompressible(path: &Path) -> io::Result<bool> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() < MIN_SAMPLED_SIZE {
+        return Ok(false);
+    }
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    file.take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+    if COMPRESSED_MAGICS
+        .iter()
+        .any(|magic| sample.starts_with(magic))
+        || sample.get(4..8) == Some(&b"ftyp"[..])
+        || (sample.starts_with(b"RIFF") && sample.get(8..12) == Some(&b"WEBP"[..]))
+    {
+        return Ok(true);
+    }
+    let compressed = zstd::bulk::compress(&sample, 1)?;
+    Ok(compressed.len() as f64 > sample.len() as f64 * INCOMPRESSIBLE_RATIO)
+}
+
+/// Zstd frame made of raw (uncompressed) blocks
+struct StoredFrame<W: Write> {
+    output: W,
+    block: Vec<u8>,
+}
+
+impl<W: Write> StoredFrame<W> {
+    /// Internal helper: write the frame header and start buffering the first block
+    fn new(mut output: W) -> io::Result<Self> {
+        output.write_all(&ZSTD_FRAME_MAGIC.to_le_bytes())?;
+        output.write_all(&[FRAME_HEADER_DESCRIPTOR, WINDOW_DESCRIPTOR])?;
+        Ok(Self {
+            output,
+            block: Vec::with_capacity(MAX_BLOCK_SIZE),
+        })
+    }
+
+    /// Internal helper: write the buffered bytes as one raw block
+    fn write_block(&mut self, last: bool) -> io::Result<()> {
+        // 3-byte header: last-block flag, block type 0 (raw), then the size
+        let header = (self.block.len() as u32) << 3 | u32::from(last);
+        self.output.write_all(&header.to_le_bytes()[..3])?;
+        self.output.write_all(&self.block)?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Internal helper: write the last block and return the underlying writer
+    fn finish(mut self) -> io::Result<W> {
+        self.write_block(true)?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> Write for StoredFrame<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.block.len() == MAX_BLOCK_SIZE {
+            self.write_block(false)?;
+        }
+        let len = buf.len().min(MAX_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
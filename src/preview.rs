@@ -0,0 +1,207 @@
+//! Reading single entries without unpacking
+//!
+//! [`preview_entry`] reads at most a given number of bytes of one file and tells text from
+//! binary content, so viewers can show any archive without risking multi-gigabyte reads.
+//! Text in UTF-16 or Latin-1 is recognized as well, see [`TextEncoding`].
+//! Archives packed with per-entry frames are read straight from the frame of the entry.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::open_archive;
+use crate::codec::zstd_decoder;
+use crate::encoding::TextEncoding;
+use crate::errors::{ProjzstError, Result};
+use crate::index::EntryIndex;
+use crate::metadata::IgnoreUnknown;
+use crate::storage::normalize_path;
+use crate::volume::{last_volume, open_input};
+
+/// Whether entry content looks like text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ContentKind {
+    /// Text in one of the detected encodings (empty content counts as text)
+    Text,
+    /// Anything else
+    Binary,
+}
+
+/// The first bytes of an archive entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct EntryPreview {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Full size of the entry in bytes
+    pub size: u64,
+    /// At most `max_bytes` bytes from the start of the entry
+    pub data: Vec<u8>,
+    /// Whether `data` stops before the end of the entry
+    pub truncated: bool,
+    /// Classification of `data`
+    pub kind: ContentKind,
+    /// Character encoding of `data`, `None` for binary content
+    pub encoding: Option<TextEncoding>,
+}
+
+impl EntryPreview {
+    /// Internal helper: classify the first `data` bytes of an entry of `size` bytes
+    pub(crate) fn new(path: String, size: u64, data: Vec<u8>) -> Self {
+        let truncated = (data.len() as u64) < size;
+        let encoding = TextEncoding::detect(&data, truncated);
+        Self {
+            path,
+            size,
+            data,
+            truncated,
+            kind: match encoding {
+                Some(_) => ContentKind::Text,
+                None => ContentKind::Binary,
+            },
+            encoding,
+        }
+    }
+
+    /// `data` decoded as text, `None` for binary content
+    pub fn text(&self) -> Option<String> {
+        self.encoding.map(|encoding| encoding.decode(&self.data))
+    }
+}
+
+/// Read the beginning of one file in a .pjz file
+/// Entries are decompressed up to the requested one (only that one with per-entry frames),
+/// but no more than `max_bytes` of it is kept in memory
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `entry_path` - Path of the file inside the archive, e.g. `docs/readme.md`
+/// * `max_bytes` - Largest number of bytes to read from the entry
+pub fn preview_entry<P: AsRef<Path>>(
+    input_file: P,
+    entry_path: &str,
+    max_bytes: usize,
+) -> Result<EntryPreview> {
+    let mut data = Vec::new();
+    let (path, size) = with_entry(input_file.as_ref(), entry_path, |entry, _| {
+        entry.take(max_bytes as u64).read_to_end(&mut data)?;
+        Ok(())
+    })?;
+
+    Ok(EntryPreview::new(path, size, data))
+}
+
+/// Copy the whole content of one file in a .pjz file to a writer
+/// Returns the number of bytes written
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `entry_path` - Path of the file inside the archive
+/// * `writer` - Destination of the content (e.g. stdout)
+pub fn write_entry<P, W>(input_file: P, entry_path: &str, mut writer: W) -> Result<u64>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let (_, size) = with_entry(input_file.as_ref(), entry_path, |entry, _| {
+        io::copy(entry, &mut writer)?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(size)
+}
+
+/// Internal helper: find a regular file entry and hand its content and size to `read`
+/// Returns the normalized path and the size of the entry. Of a path stored more than once,
+/// the last entry is read, the one unpacking leaves in place
+pub(crate) fn with_entry<F>(input_file: &Path, entry_path: &str, read: F) -> Result<(String, u64)>
+where
+    F: FnOnce(&mut dyn Read, u64) -> Result<()>,
+{
+    let wanted = wanted_path(entry_path)?;
+    let size = match indexed_frame(input_file, &wanted)? {
+        Some(frame) => {
+            let mut tar_archive = tar::Archive::new(zstd_decoder(frame)?);
+            read_entry(tar_archive.entries()?.take(1), &wanted, 0, read)?
+        }
+        None => {
+            // Without an index, a first pass over the headers finds the last entry
+            let (_, mut tar_archive) = open_archive(open_input(input_file)?, IgnoreUnknown::On)?;
+            let mut stored: usize = 0;
+            for entry in tar_archive.entries()? {
+                if normalize_path(&entry?.path()?).as_deref() == Some(wanted.as_str()) {
+                    stored += 1;
+                }
+            }
+            let (_, mut tar_archive) = open_archive(open_input(input_file)?, IgnoreUnknown::On)?;
+            read_entry(
+                tar_archive.entries()?,
+                &wanted,
+                stored.saturating_sub(1),
+                read,
+            )?
+        }
+    };
+    size.map(|size| (wanted, size))
+        .ok_or_else(|| ProjzstError::EntryNotFound(entry_path.to_string()))
+}
+
+/// Internal helper: the normalized archive path of `entry_path`
+pub(crate) fn wanted_path(entry_path: &str) -> Result<String> {
+    normalize_path(Path::new(entry_path.trim_start_matches('/')))
+        .ok_or_else(|| ProjzstError::EntryNotFound(entry_path.to_string()))
+}
+
+/// Internal helper: hand the content of the regular file `wanted` among `entries` to `read`,
+/// passing over the first `skip` entries of that path
+/// Returns its size, `None` when there is no such file
+pub(crate) fn read_entry<'a, R, I, F>(
+    entries: I,
+    wanted: &str,
+    mut skip: usize,
+    read: F,
+) -> Result<Option<u64>>
+where
+    R: Read + 'a,
+    I: Iterator<Item = io::Result<tar::Entry<'a, R>>>,
+    F: FnOnce(&mut dyn Read, u64) -> Result<()>,
+{
+    for entry in entries {
+        let mut entry = entry?;
+        if normalize_path(&entry.path()?).as_deref() != Some(wanted) {
+            continue;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            return Ok(None);
+        }
+        let size = entry.header().size()?;
+        read(&mut entry, size)?;
+        return Ok(Some(size));
+    }
+    Ok(None)
+}
+
+/// Internal helper: the zstd frame holding `wanted`, for archives packed with per-entry
+/// frames; `None` otherwise, and for split archives, which are read as one stream
+fn indexed_frame(input_file: &Path, wanted: &str) -> Result<Option<io::Take<File>>> {
+    if !input_file.is_file() || last_volume(input_file) != input_file {
+        return Ok(None);
+    }
+    let mut file = File::open(input_file)?;
+    let Some(index) = EntryIndex::read_from(&mut file)? else {
+        return Ok(None);
+    };
+    let Some(entry) = index.find(wanted) else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::Start(entry.offset))?;
+    Ok(Some(file.take(entry.length)))
+}
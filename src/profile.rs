@@ -0,0 +1,89 @@
+//! Named compression presets
+//!
+//! A [`CompressionProfile`] bundles a zstd level with the window, long-distance matching
+//! and compression threads that suit it, for [`Packer::profile`](crate::Packer::profile)
+//! and `pack --preset`. Windows stay at 128 MiB or less, the largest zstd decoders accept
+//! without being told to, so archives packed with any preset unpack everywhere.
+
+use std::thread;
+
+use crate::errors::{ProjzstError, Result};
+use crate::payload::MAX_WINDOW_LOG;
+use crate::string_utils::IntoOpStr;
+
+/// Tuned combination of compression settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum CompressionProfile {
+    /// Level 1 on every core: packs as fast as the disk allows
+    Fast,
+    /// The default level with the default thread count (default)
+    #[default]
+    Balanced,
+    /// Level 19 with a 128 MiB long-distance window on every core
+    Max,
+    /// Level 22 with a 128 MiB long-distance window on a single thread: the smallest
+    /// archives, for files written once and kept for long
+    Archive,
+}
+
+impl CompressionProfile {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "fast" => Ok(CompressionProfile::Fast),
+            "balanced" | "default" => Ok(CompressionProfile::Balanced),
+            "max" => Ok(CompressionProfile::Max),
+            "archive" => Ok(CompressionProfile::Archive),
+            _ => Err(ProjzstError::InvalidCompressionProfile(s.to_string())),
+        }
+    }
+
+    /// Lowercase name of the preset
+    pub fn name(self) -> &'static str {
+        match self {
+            CompressionProfile::Fast => "fast",
+            CompressionProfile::Balanced => "balanced",
+            CompressionProfile::Max => "max",
+            CompressionProfile::Archive => "archive",
+        }
+    }
+
+    /// Zstd compression level
+    pub fn level(self) -> i32 {
+        match self {
+            CompressionProfile::Fast => 1,
+            CompressionProfile::Balanced => crate::DEFAULT_ZSTD_LEVEL,
+            CompressionProfile::Max => 19,
+            CompressionProfile::Archive => 22,
+        }
+    }
+
+    /// Base-2 logarithm of the window size, `None` for the default of the level
+    pub fn window_log(self) -> Option<u32> {
+        match self {
+            CompressionProfile::Fast | CompressionProfile::Balanced => None,
+            CompressionProfile::Max | CompressionProfile::Archive => Some(MAX_WINDOW_LOG),
+        }
+    }
+
+    /// Whether long-distance matching finds repeats across the whole window
+    pub fn long_distance(self) -> bool {
+        matches!(self, CompressionProfile::Max | CompressionProfile::Archive)
+    }
+
+    /// Zstd compression workers (`0` compresses on the calling thread), `None` to keep
+    /// the configured [`Concurrency`](crate::Concurrency)
+    pub fn compression_workers(self) -> Option<u32> {
+        match self {
+            CompressionProfile::Fast | CompressionProfile::Max => {
+                Some(thread::available_parallelism().map_or(1, |n| n.get() as u32))
+            }
+            CompressionProfile::Balanced => None,
+            // Workers split the input into jobs, which costs a little ratio
+            CompressionProfile::Archive => Some(0),
+        }
+    }
+}
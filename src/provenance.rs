@@ -0,0 +1,82 @@
+//! Build-environment provenance
+//!
+//! With [`Packer::provenance`](crate::Packer::provenance), the packer records where an
+//! archive was built (host name, operating system, projzst version, time and git commit)
+//! as JSON in a `provenance` frame next to the metadata. It is off by default: two packs of
+//! the same tree on different machines would otherwise never produce the same bytes.
+
+use std::env;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::Result;
+use crate::frames::{frames, FrameKind};
+use crate::template::TemplateVars;
+
+/// Build environment recorded in a `provenance` frame
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Provenance {
+    /// Host name of the machine that packed the archive
+    pub hostname: Option<String>,
+    /// Operating system and architecture, e.g. `linux-x86_64`
+    pub os: String,
+    /// Tool that packed the archive, e.g. `projzst 1.2.0`
+    pub tool: String,
+    /// Pack time in UTC (`2024-05-01T12:00:00Z`), taken from `SOURCE_DATE_EPOCH` when set
+    pub timestamp: String,
+    /// Commit checked out in the git repository containing the source, if any
+    pub git_commit: Option<String>,
+}
+
+impl Provenance {
+    /// Describe the current build environment
+    /// The git commit is looked up from `source_dir`; it is left unset outside a repository
+    pub fn collect<P: AsRef<Path>>(source_dir: P) -> Self {
+        let vars = TemplateVars::new().git(source_dir);
+        Self {
+            hostname: hostname(),
+            os: format!("{}-{}", env::consts::OS, env::consts::ARCH),
+            tool: format!("projzst {}", env!("CARGO_PKG_VERSION")),
+            timestamp: vars.get("datetime").unwrap_or_default().to_string(),
+            git_commit: vars.get("GIT_SHA").map(str::to_string),
+        }
+    }
+}
+
+/// Read the build provenance recorded in a .pjz file, if any
+/// Fields added by newer versions are ignored, missing ones are left empty
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn provenance<P: AsRef<Path>>(input_file: P) -> Result<Option<Provenance>> {
+    frames(input_file)?
+        .into_iter()
+        .find(|frame| frame.kind() == FrameKind::Provenance)
+        .map(|frame| Ok(serde_json::from_slice(&frame.payload)?))
+        .transpose()
+}
+
+/// Internal helper: host name of this machine, `None` when it cannot be told
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length; its last byte stays 0
+    let status = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) };
+    if status != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Internal helper: host name of this machine, `None` when it cannot be told
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
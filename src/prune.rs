@@ -0,0 +1,137 @@
+//! Retention of release directories
+//!
+//! [`prune`] reads the metadata of every .pjz file below a directory, groups the archives
+//! by package name and orders each group by semantic version. A [`RetentionPolicy`] picks
+//! the archives to keep: the newest versions overall and the newest of every major
+//! version. The rest are deleted, or only listed for a dry run. Archives whose metadata
+//! cannot be read or whose version does not parse are never deleted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::builder::read_metadata;
+use crate::catalog::{find_archives, relative_path};
+use crate::deps::Version;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::IgnoreUnknown;
+
+/// Which archives of a package [`prune`] keeps
+/// An archive is kept when any of the rules keeps it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RetentionPolicy {
+    /// Number of newest versions kept
+    pub keep_latest: usize,
+    /// Number of newest versions kept of every major version
+    pub keep_per_major: usize,
+}
+
+impl RetentionPolicy {
+    /// Create a policy; `0` turns a rule off
+    ///
+    /// # Arguments
+    /// * `keep_latest` - Number of newest versions kept
+    /// * `keep_per_major` - Number of newest versions kept of every major version
+    pub fn new(keep_latest: usize, keep_per_major: usize) -> Self {
+        Self {
+            keep_latest,
+            keep_per_major,
+        }
+    }
+}
+
+/// What [`prune`] kept and deleted
+/// Paths are relative to the directory, with `/` separators, and sorted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PruneReport {
+    /// Archives kept by the policy
+    pub kept: Vec<String>,
+    /// Archives deleted, or that would be on a dry run
+    pub removed: Vec<String>,
+    /// Bytes of the removed archives
+    pub removed_bytes: u64,
+    /// Archives left alone because their metadata or version could not be read, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+/// An archive with a readable version
+struct Release {
+    path: String,
+    file: PathBuf,
+    version: Version,
+}
+
+/// Delete the .pjz files below a directory that a retention policy does not keep
+/// Archives are grouped by metadata name and ordered by version; unreadable ones are
+/// listed in [`PruneReport::skipped`] and kept. A policy keeping nothing is refused
+///
+/// # Arguments
+/// * `dir` - Directory searched for .pjz files at any depth (symlinks are not followed)
+/// * `policy` - Archives to keep of every package
+/// * `dry_run` - Only report what would be deleted
+pub fn prune<P: AsRef<Path>>(
+    dir: P,
+    policy: RetentionPolicy,
+    dry_run: bool,
+) -> Result<PruneReport> {
+    if policy.keep_latest == 0 && policy.keep_per_major == 0 {
+        return Err(ProjzstError::InvalidArgument(
+            "the retention policy keeps no archive".to_string(),
+        ));
+    }
+    let dir = dir.as_ref();
+    let mut files = Vec::new();
+    find_archives(dir, &mut files)?;
+    files.sort();
+
+    let mut report = PruneReport::default();
+    let mut packages: HashMap<Option<String>, Vec<Release>> = HashMap::new();
+    for file in files {
+        let path = relative_path(dir, &file);
+        let release = read_metadata(&file, IgnoreUnknown::On).and_then(|metadata| {
+            let version = match metadata.ver.as_deref() {
+                Some(ver) => Some(Version::parse(ver)?),
+                None => None,
+            };
+            Ok((metadata.name, version))
+        });
+        match release {
+            Ok((name, Some(version))) => packages.entry(name).or_default().push(Release {
+                path,
+                file,
+                version,
+            }),
+            Ok((_, None)) => report.skipped.push((path, "no version".to_string())),
+            Err(e) => report.skipped.push((path, e.to_string())),
+        }
+    }
+
+    for releases in packages.values_mut() {
+        // Newest first; copies of one version keep their path order
+        releases.sort_by(|a, b| b.version.cmp(&a.version).then(a.path.cmp(&b.path)));
+        let mut per_major: BTreeMap<u64, usize> = BTreeMap::new();
+        for (position, release) in releases.iter().enumerate() {
+            let seen = per_major.entry(release.version.major).or_default();
+            *seen += 1;
+            if position < policy.keep_latest || *seen <= policy.keep_per_major {
+                report.kept.push(release.path.clone());
+                continue;
+            }
+            report.removed_bytes += fs::metadata(&release.file)?.len();
+            if dry_run {
+                log::info!("would remove {} ({})", release.path, release.version);
+            } else {
+                log::info!("removed {} ({})", release.path, release.version);
+                fs::remove_file(&release.file)?;
+            }
+            report.removed.push(release.path.clone());
+        }
+    }
+    report.kept.sort();
+    report.removed.sort();
+    Ok(report)
+}
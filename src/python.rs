@@ -0,0 +1,243 @@
+//! Python bindings (`python` feature)
+//!
+//! A `projzst` extension module built with pyo3, for scripts that handle .pjz files
+//! without shelling out to the command-line tool. Metadata crosses the boundary as a
+//! `dict` holding the same fields as `metadata.json`; paths are `str` or `os.PathLike`.
+//! The GIL is released while an archive is packed or read, and failures raise
+//! `projzst.ProjzstError`, whose `kind` attribute names the [`ErrorKind`](crate::ErrorKind).
+//!
+//! ```python
+//! import projzst
+//!
+//! projzst.pack("./data", "data.pjz", {"name": "data", "ver": "1.0.0"}, level=9)
+//! meta = projzst.read_metadata("data.pjz")
+//! projzst.unpack("data.pjz", "./restored")
+//! ```
+
+use std::path::PathBuf;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString};
+use serde_json::Value;
+
+use crate::errors;
+use crate::metadata::{IgnoreUnknown, Metadata};
+
+create_exception!(
+    projzst,
+    ProjzstError,
+    PyException,
+    "A projzst operation failed"
+);
+
+/// Pack a directory into a .pjz file
+///
+/// # Arguments
+/// * `source_dir` - Directory to pack
+/// * `output_file` - Path of the .pjz file to write
+/// * `metadata` - Metadata fields (`name`, `auth`, `fmt`, `ed`, `ver`, `desc`, `extra`, ...)
+/// * `level` - Zstd compression level, 1-22 (0 stores the payload uncompressed)
+#[pyfunction]
+#[pyo3(signature = (source_dir, output_file, metadata = None, level = crate::DEFAULT_ZSTD_LEVEL))]
+fn pack(
+    py: Python<'_>,
+    source_dir: PathBuf,
+    output_file: PathBuf,
+    metadata: Option<&Bound<'_, PyDict>>,
+    level: i32,
+) -> PyResult<()> {
+    let metadata = match metadata {
+        Some(dict) => serde_json::from_value(to_json(dict.as_any())?)
+            .map_err(|e| raise(errors::ProjzstError::from(e)))?,
+        None => Metadata::default(),
+    };
+    py.allow_threads(|| crate::pack(source_dir, output_file, metadata, None::<&str>, level))
+        .map_err(raise)
+}
+
+/// Unpack a .pjz file into a directory, writing `metadata.json` next to it, and return
+/// the metadata; unknown metadata fields are ignored
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `output_dir` - Directory to extract to, created if needed
+#[pyfunction]
+fn unpack(py: Python<'_>, input_file: PathBuf, output_dir: PathBuf) -> PyResult<PyObject> {
+    let metadata = py
+        .allow_threads(|| crate::unpack(input_file, output_dir, IgnoreUnknown::On))
+        .map_err(raise)?;
+    metadata_to_py(py, &metadata)
+}
+
+/// Read the metadata of a .pjz file; unknown metadata fields are ignored
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+#[pyfunction]
+fn read_metadata(py: Python<'_>, input_file: PathBuf) -> PyResult<PyObject> {
+    let metadata = py
+        .allow_threads(|| crate::read_metadata(input_file, IgnoreUnknown::On))
+        .map_err(raise)?;
+    metadata_to_py(py, &metadata)
+}
+
+/// The `projzst` Python module
+#[pymodule]
+fn projzst(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ProjzstError", m.py().get_type::<ProjzstError>())?;
+    m.add("FORMAT_VERSION", crate::FORMAT_VERSION)?;
+    m.add_function(wrap_pyfunction!(pack, m)?)?;
+    m.add_function(wrap_pyfunction!(unpack, m)?)?;
+    m.add_function(wrap_pyfunction!(read_metadata, m)?)?;
+    Ok(())
+}
+
+/// Internal helper: a `ProjzstError` exception for a library error
+fn raise(e: errors::ProjzstError) -> PyErr {
+    Python::with_gil(|py| {
+        let err = ProjzstError::new_err(e.to_string());
+        // Only fails when out of memory, and the message still gets through then
+        let _ = err.value(py).setattr("kind", e.kind().name());
+        err
+    })
+}
+
+/// Internal helper: metadata as a Python `dict`
+fn metadata_to_py(py: Python<'_>, metadata: &Metadata) -> PyResult<PyObject> {
+    let value = serde_json::to_value(metadata).map_err(|e| raise(e.into()))?;
+    to_py(py, &value)
+}
+
+/// Internal helper: a JSON value as the Python object `json.loads` would give
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any().unbind(),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_pyobject(py)?.into_any().unbind(),
+            (None, Some(u)) => u.into_pyobject(py)?.into_any().unbind(),
+            (None, None) => n
+                .as_f64()
+                .unwrap_or(f64::NAN)
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+        },
+        Value::String(s) => PyString::new(py, s).into_any().unbind(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new(py);
+            for (key, item) in fields {
+                dict.set_item(key, to_py(py, item)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    })
+}
+
+/// Internal helper: a Python object made of `dict`, `list`, `str`, `int`, `float`, `bool`
+/// and `None` as a JSON value; anything else raises `TypeError`
+fn to_json(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if object.is_none() {
+        return Ok(Value::Null);
+    }
+    // Before integers, as `bool` is a subclass of `int`
+    if let Ok(b) = object.downcast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if object.is_instance_of::<PyInt>() {
+        return match object.extract::<i64>() {
+            Ok(i) => Ok(i.into()),
+            Err(_) => Ok(object.extract::<u64>()?.into()),
+        };
+    }
+    if object.is_instance_of::<PyFloat>() {
+        let f = object.extract::<f64>()?;
+        return serde_json::Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(|| {
+                pyo3::exceptions::PyValueError::new_err(format!("{f} is not valid JSON"))
+            });
+    }
+    if let Ok(s) = object.downcast::<PyString>() {
+        return Ok(Value::String(s.to_str()?.to_string()));
+    }
+    if let Ok(list) = object.downcast::<PyList>() {
+        return list.iter().map(|item| to_json(&item)).collect();
+    }
+    if let Ok(dict) = object.downcast::<PyDict>() {
+        let mut fields = serde_json::Map::new();
+        for (key, item) in dict.iter() {
+            let key = key.downcast::<PyString>()?.to_str()?.to_string();
+            fields.insert(key, to_json(&item)?);
+        }
+        return Ok(Value::Object(fields));
+    }
+    Err(pyo3::exceptions::PyTypeError::new_err(format!(
+        "{} cannot be stored in metadata",
+        object.get_type().name()?
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::ffi::c_str;
+
+    #[test]
+    fn test_module() {
+        let temp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp.path().join("source")).unwrap();
+        std::fs::write(temp.path().join("source/a.txt"), "alpha").unwrap();
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "projzst").unwrap();
+            projzst(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("projzst", module).unwrap();
+            locals.set_item("dir", temp.path()).unwrap();
+            py.run(
+                c_str!(
+                    r#"
+import os
+
+meta = {"name": "demo", "ver": "1.0.0", "extra": {"n": 1, "ok": True, "tags": ["a", None]}}
+archive = os.path.join(dir, "demo.pjz")
+projzst.pack(os.path.join(dir, "source"), archive, meta, level=3)
+
+read = projzst.read_metadata(archive)
+assert read["name"] == "demo" and read["ver"] == "1.0.0", read
+assert read["extra"] == meta["extra"], read
+assert projzst.unpack(archive, os.path.join(dir, "out")) == read
+with open(os.path.join(dir, "out", "a.txt")) as f:
+    assert f.read() == "alpha"
+
+try:
+    projzst.read_metadata(os.path.join(dir, "missing.pjz"))
+    raise AssertionError("no error raised")
+except projzst.ProjzstError as e:
+    assert e.kind == "io", e.kind
+
+try:
+    projzst.pack(os.path.join(dir, "source"), archive, {"extra": {"when": object()}})
+    raise AssertionError("no error raised")
+except TypeError:
+    pass
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,387 @@
+//! Zero-copy extraction of stored entries
+//!
+//! In an archive packed with per-entry frames, a file stored rather than compressed (with
+//! [`Packer::store_only`](crate::Packer::store_only) or adaptive compression) sits in the
+//! archive as it is, split into raw zstd blocks. When such an archive file is unpacked,
+//! [`FramedPayload`] reads the payload frame by frame: stored frames straight from the
+//! file, compressed ones through a zstd decoder. The content of a regular file in a stored
+//! frame is then copied from the archive to its target block by block without passing
+//! through the process: on Linux with `copy_file_range`, which copies inside the kernel and
+//! shares extents instead where the filesystem can (btrfs, XFS), elsewhere with plain reads.
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::codec::{zstd_decoder, ZstdDecoder};
+use crate::errors::Result;
+use crate::frames::frames;
+use crate::index::EntryIndex;
+use crate::payload::{FRAME_HEADER_DESCRIPTOR, ZSTD_FRAME_MAGIC};
+use crate::volume::last_volume;
+
+/// Size of the frame header of a stored frame: magic, descriptor and window descriptor
+const STORED_HEADER_SIZE: u64 = 6;
+
+/// Size of a zstd block header
+const BLOCK_HEADER_SIZE: u64 = 3;
+
+/// The payload of a per-entry archive file, read frame by frame
+/// Used through `&FramedPayload`, so the tar reader and the extraction share it
+pub(crate) struct FramedPayload {
+    inner: RefCell<Reader>,
+}
+
+/// State of a [`FramedPayload`]
+struct Reader {
+    file: File,
+    /// Byte ranges of the payload frames in the file, in order
+    frames: Vec<FrameRange>,
+    /// Index of the next frame to start
+    next: usize,
+    current: Option<Current>,
+    /// Bytes of the decoded payload read or skipped so far
+    position: u64,
+}
+
+/// Byte range of one zstd frame of the payload
+#[derive(Clone, Copy)]
+struct FrameRange {
+    start: u64,
+    end: u64,
+    /// Whether its header is that of a stored frame, whose blocks may all be raw
+    stored: bool,
+}
+
+/// The frame being read
+enum Current {
+    /// Raw blocks read in place, in payload order
+    Stored(Vec<RawBlock>),
+    /// Anything else, through a zstd decoder
+    Compressed(ZstdDecoder<io::BufReader<io::Take<File>>>),
+}
+
+/// Content of one raw block
+#[derive(Clone, Copy)]
+struct RawBlock {
+    /// Offset in the decoded payload
+    position: u64,
+    /// Offset in the file
+    offset: u64,
+    len: u64,
+}
+
+impl FramedPayload {
+    /// Open the payload of an archive file packed with per-entry frames
+    /// `None` for other archives, split archives, and payloads without stored frames
+    pub(crate) fn open(input_file: &Path) -> Result<Option<Self>> {
+        if !input_file.is_file() || last_volume(input_file) != input_file {
+            return Ok(None);
+        }
+        let mut file = File::open(input_file)?;
+        let Ok(Some(index)) = EntryIndex::read_from(&mut file) else {
+            return Ok(None);
+        };
+        let header_len: u64 = frames(input_file)?
+            .iter()
+            .map(|frame| 8 + frame.payload.len() as u64)
+            .sum();
+        if header_len != index.payload_start {
+            return Ok(None);
+        }
+
+        // Each frame ends where the next one starts
+        let mut bounds = vec![index.payload_start];
+        bounds.extend(index.entries.iter().map(|entry| entry.offset));
+        bounds.push(index.payload_start + index.payload_size);
+        let mut frame_ranges = Vec::with_capacity(bounds.len() - 1);
+        for pair in bounds.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if end <= start {
+                return Ok(None);
+            }
+            let mut header = [0u8; 5];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut header)?;
+            let stored = header[..4] == ZSTD_FRAME_MAGIC.to_le_bytes()
+                && header[4] == FRAME_HEADER_DESCRIPTOR;
+            frame_ranges.push(FrameRange { start, end, stored });
+        }
+        if !frame_ranges.iter().any(|frame| frame.stored) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            inner: RefCell::new(Reader {
+                file,
+                frames: frame_ranges,
+                next: 0,
+                current: None,
+                position: 0,
+            }),
+        }))
+    }
+
+    /// Whether the `size` bytes at `position` of the decoded payload can be copied with
+    /// [`FramedPayload::copy_to`]: they lie in the stored frame being read, unread yet
+    pub(crate) fn is_stored(&self, position: u64, size: u64) -> bool {
+        self.inner.borrow().stored_ranges(position, size).is_some()
+    }
+
+    /// Write the `size` bytes at `position` of the decoded payload to a new file at
+    /// `target` with permissions `mode`, copying them from the archive file
+    /// The reader skips them afterwards instead of reading them
+    pub(crate) fn copy_to(&self, position: u64, size: u64, target: &Path, mode: u32) -> Result<()> {
+        let inner = self.inner.borrow();
+        let ranges = inner
+            .stored_ranges(position, size)
+            .ok_or_else(|| io::Error::other("entry content is not stored"))?;
+        if target.symlink_metadata().is_ok() {
+            fs::remove_file(target)?;
+        }
+        let mut output = File::options().write(true).create_new(true).open(target)?;
+        for (offset, len) in ranges {
+            copy_range(&inner.file, offset, &mut output, len)?;
+        }
+        set_mode(&output, mode)?;
+        Ok(())
+    }
+}
+
+impl Reader {
+    /// Internal helper: file ranges of the `size` bytes at `position` of the decoded
+    /// payload, when they lie in the stored frame being read and were not read yet
+    fn stored_ranges(&self, position: u64, size: u64) -> Option<Vec<(u64, u64)>> {
+        let Some(Current::Stored(blocks)) = &self.current else {
+            return None;
+        };
+        let end = position + size;
+        let frame_end = blocks.last().map_or(0, |block| block.position + block.len);
+        if position < self.position || end > frame_end {
+            return None;
+        }
+        Some(
+            blocks
+                .iter()
+                .filter(|block| block.position < end && block.position + block.len > position)
+                .map(|block| {
+                    let from = position.max(block.position);
+                    let to = end.min(block.position + block.len);
+                    (block.offset + from - block.position, to - from)
+                })
+                .collect(),
+        )
+    }
+
+    /// Internal helper: start the next frame, `false` at the end of the payload
+    fn start_frame(&mut self) -> io::Result<bool> {
+        let Some(frame) = self.frames.get(self.next).copied() else {
+            return Ok(false);
+        };
+        self.next += 1;
+        let blocks = if frame.stored {
+            self.raw_blocks(frame)?
+        } else {
+            None
+        };
+        self.current = Some(match blocks {
+            Some(blocks) => Current::Stored(blocks),
+            None => {
+                self.file.seek(SeekFrom::Start(frame.start))?;
+                let range = self.file.try_clone()?.take(frame.end - frame.start);
+                Current::Compressed(zstd_decoder(range)?)
+            }
+        });
+        Ok(true)
+    }
+
+    /// Internal helper: the blocks of a stored frame, `None` unless all of them are raw
+    /// and the last one ends the frame
+    fn raw_blocks(&mut self, frame: FrameRange) -> io::Result<Option<Vec<RawBlock>>> {
+        let mut blocks = Vec::new();
+        let mut offset = frame.start + STORED_HEADER_SIZE;
+        let mut position = self.position;
+        loop {
+            if offset + BLOCK_HEADER_SIZE > frame.end {
+                return Ok(None);
+            }
+            let mut header = [0u8; 4];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut header[..3])?;
+            // Last-block flag, block type (0 for raw), then the size
+            let header = u32::from_le_bytes(header);
+            let len = u64::from(header >> 3);
+            offset += BLOCK_HEADER_SIZE;
+            if (header >> 1) & 3 != 0 || offset + len > frame.end {
+                return Ok(None);
+            }
+            blocks.push(RawBlock {
+                position,
+                offset,
+                len,
+            });
+            position += len;
+            offset += len;
+            if header & 1 == 1 {
+                return Ok((offset == frame.end).then_some(blocks));
+            }
+        }
+    }
+
+    /// Internal helper: read decoded payload bytes
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.current.is_none() && !self.start_frame()? {
+                return Ok(0);
+            }
+            let read = match self.current.as_mut().expect("frame started") {
+                Current::Compressed(decoder) => decoder.read(buf)?,
+                Current::Stored(blocks) => {
+                    let position = self.position;
+                    match blocks
+                        .iter()
+                        .find(|block| block.position + block.len > position)
+                    {
+                        None => 0,
+                        Some(block) => {
+                            let skip = position - block.position;
+                            let len = buf.len().min((block.len - skip) as usize);
+                            self.file.seek(SeekFrom::Start(block.offset + skip))?;
+                            match self.file.read(&mut buf[..len])? {
+                                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                                read => read,
+                            }
+                        }
+                    }
+                }
+            };
+            if read == 0 {
+                self.current = None;
+                continue;
+            }
+            self.position += read as u64;
+            return Ok(read);
+        }
+    }
+
+    /// Internal helper: skip decoded payload bytes up to `target`, without reading
+    /// stored content
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(target) => Some(target),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        }
+        .filter(|&target| target >= self.position)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "payload only seeks forward"))?;
+        while self.position < target {
+            if self.current.is_none() && !self.start_frame()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let skipped = match self.current.as_mut().expect("frame started") {
+                Current::Compressed(decoder) => io::copy(
+                    &mut decoder.by_ref().take(target - self.position),
+                    &mut io::sink(),
+                )?,
+                Current::Stored(blocks) => {
+                    let frame_end = blocks.last().map_or(0, |block| block.position + block.len);
+                    frame_end.min(target).saturating_sub(self.position)
+                }
+            };
+            if skipped == 0 {
+                self.current = None;
+            }
+            self.position += skipped;
+        }
+        Ok(self.position)
+    }
+}
+
+impl Read for &FramedPayload {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.borrow_mut().read(buf)
+    }
+}
+
+impl Seek for &FramedPayload {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.borrow_mut().seek(pos)
+    }
+}
+
+/// Internal helper: append `len` bytes at `offset` of `input` to `output`
+/// `copy_file_range` copies inside the kernel, falling back to plain reads when the
+/// kernel or the filesystems involved cannot
+#[cfg(target_os = "linux")]
+pub(crate) fn copy_range(input: &File, offset: u64, output: &mut File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut offset_in = offset as libc::loff_t;
+    let mut remaining = len;
+    while remaining > 0 {
+        // SAFETY: both descriptors stay open for the call and `offset_in` outlives it;
+        // a null output offset appends at the output's file position
+        let copied = unsafe {
+            libc::copy_file_range(
+                input.as_raw_fd(),
+                &mut offset_in,
+                output.as_raw_fd(),
+                std::ptr::null_mut(),
+                remaining as usize,
+                0,
+            )
+        };
+        match copied {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            copied if copied > 0 => remaining -= copied as u64,
+            _ => {
+                let e = io::Error::last_os_error();
+                return match e.raw_os_error() {
+                    Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL) => {
+                        copy_range_buffered(input, offset_in as u64, output, remaining)
+                    }
+                    _ => Err(e),
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: append `len` bytes at `offset` of `input` to `output`
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn copy_range(input: &File, offset: u64, output: &mut File, len: u64) -> io::Result<()> {
+    copy_range_buffered(input, offset, output, len)
+}
+
+/// Internal helper: append `len` bytes at `offset` of `input` to `output` through a buffer
+fn copy_range_buffered(
+    mut input: &File,
+    offset: u64,
+    output: &mut File,
+    len: u64,
+) -> io::Result<()> {
+    input.seek(SeekFrom::Start(offset))?;
+    if io::copy(&mut input.take(len), output)? != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Internal helper: set the permission bits of an extracted file, as the tar crate does
+#[cfg(unix)]
+pub(crate) fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))
+}
+
+/// Internal helper: permission bits do not apply on this platform
+#[cfg(not(unix))]
+pub(crate) fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Package registry client (feature `http`)
+//!
+//! A registry is any HTTP(S) server storing archives under their metadata: an archive
+//! named `name` at version `ver` lives at `<registry>/<name>/<ver>`. [`Registry::push`]
+//! uploads it there with `PUT`, [`Registry::pull`] downloads it with `GET`. Requests
+//! carry an optional authentication header, a bearer token by default.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::builder::read_metadata;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::naming::auto_file_name;
+use crate::temp::temp_file_for;
+
+/// Client for one registry, with its authentication
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Registry {
+    /// Base URL, without a trailing `/`
+    base_url: String,
+    /// Header name and value sent with every request
+    auth: Option<(String, String)>,
+}
+
+impl Registry {
+    /// Create a client for the registry at `base_url`, without authentication
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            auth: None,
+        }
+    }
+
+    /// Authenticate with `Authorization: Bearer <token>`
+    pub fn token(self, token: &str) -> Self {
+        self.auth_header("Authorization", format!("Bearer {token}"))
+    }
+
+    /// Authenticate with any header, e.g. `X-Api-Key`, replacing a token set before
+    pub fn auth_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.auth = Some((name.into(), value.into()));
+        self
+    }
+
+    /// URL of a package version: `<registry>/<name>/<version>`, both percent-encoded
+    pub fn package_url(&self, name: &str, version: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.base_url,
+            percent_encode(name),
+            percent_encode(version)
+        )
+    }
+
+    /// Upload a .pjz file under the name and version in its metadata
+    /// Returns the URL the archive was uploaded to
+    ///
+    /// # Arguments
+    /// * `input_file` - Path to the .pjz file; its metadata needs `name` and `ver`
+    pub fn push<P: AsRef<Path>>(&self, input_file: P) -> Result<String> {
+        let input_file = input_file.as_ref();
+        let metadata = read_metadata(input_file, IgnoreUnknown::On)?;
+        let (name, version) = package_id(&metadata)?;
+        let url = self.package_url(name, version);
+
+        let file = File::open(input_file)?;
+        let length = file.metadata()?.len();
+        let request = self
+            .request("PUT", &url)
+            .set("Content-Type", "application/octet-stream")
+            .set("Content-Length", &length.to_string());
+        request
+            .send(file)
+            .map_err(|e| registry_error("PUT", &url, e))?;
+        Ok(url)
+    }
+
+    /// Download a package version into a directory, named after its metadata
+    /// (`<name>-<ver>.pjz`); fails if the archive served is another package or version
+    /// Returns the path written and the metadata of the archive
+    ///
+    /// # Arguments
+    /// * `name` - Package name
+    /// * `version` - Package version
+    /// * `output_dir` - Existing directory to write the archive to
+    pub fn pull<P: AsRef<Path>>(
+        &self,
+        name: &str,
+        version: &str,
+        output_dir: P,
+    ) -> Result<(PathBuf, Metadata)> {
+        let url = self.package_url(name, version);
+        let response = self
+            .request("GET", &url)
+            .call()
+            .map_err(|e| registry_error("GET", &url, e))?;
+
+        let output_dir = output_dir.as_ref();
+        let (mut file, temp) = temp_file_for(&output_dir.join("pull.pjz"))?;
+        io::copy(&mut response.into_reader(), &mut file)?;
+        file.sync_all()?;
+        drop(file);
+
+        let metadata = read_metadata(temp.path(), IgnoreUnknown::On)?;
+        let served = package_id(&metadata)?;
+        if served != (name, version) {
+            return Err(ProjzstError::Remote(format!(
+                "GET {url} returned {}@{} instead of {name}@{version}",
+                served.0, served.1
+            )));
+        }
+        let output_file = output_dir.join(auto_file_name(&metadata)?);
+        temp.persist(&output_file)?;
+        Ok((output_file, metadata))
+    }
+
+    /// Internal helper: a request carrying the authentication header
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = ureq::request(method, url);
+        match &self.auth {
+            Some((name, value)) => request.set(name, value),
+            None => request,
+        }
+    }
+}
+
+/// Split a `name@version` package reference
+/// The last `@` separates the two, so names may contain `@` themselves
+pub fn parse_package_ref(reference: &str) -> Result<(&str, &str)> {
+    reference
+        .rsplit_once('@')
+        .filter(|(name, version)| !name.is_empty() && !version.is_empty())
+        .ok_or_else(|| ProjzstError::InvalidPackageRef(reference.to_string()))
+}
+
+/// Internal helper: the name and version addressing an archive in a registry
+fn package_id(metadata: &Metadata) -> Result<(&str, &str)> {
+    Ok((
+        required_field(&metadata.name, "name")?,
+        required_field(&metadata.ver, "ver")?,
+    ))
+}
+
+/// Internal helper: a metadata field registries need, unless unset or empty
+fn required_field<'a>(value: &'a Option<String>, field: &str) -> Result<&'a str> {
+    value
+        .as_deref()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ProjzstError::RegistryFieldMissing(field.to_string()))
+}
+
+/// Internal helper: describe a failed registry request, with the body of error responses
+fn registry_error(method: &str, url: &str, error: ureq::Error) -> ProjzstError {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let detail = response.into_string().unwrap_or_default();
+            let detail = detail.trim();
+            if detail.is_empty() {
+                ProjzstError::Remote(format!("{method} {url} returned HTTP {code}"))
+            } else {
+                ProjzstError::Remote(format!("{method} {url} returned HTTP {code}: {detail}"))
+            }
+        }
+        e => ProjzstError::Remote(format!("{method} {url} failed: {e}")),
+    }
+}
+
+/// Internal helper: percent-encode a path segment, keeping unreserved characters
+fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~' | b'+') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
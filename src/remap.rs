@@ -0,0 +1,731 @@
+//! Path remapping on extraction
+//!
+//! [`ReadOptions::strip_components`](crate::ReadOptions::strip_components) drops leading
+//! path components like `tar --strip-components`, and
+//! [`ReadOptions::transform`](crate::ReadOptions::transform) rewrites what is left, so an
+//! archive with an embedded top-level directory can be flattened. Entries mapped to an
+//! empty path are skipped; mapped paths must stay inside the output directory.
+//! Device nodes and FIFOs are extracted or not as
+//! [`ReadOptions::special_entries`](crate::ReadOptions::special_entries) says, and
+//! [`ReadOptions::entry_filter`](crate::ReadOptions::entry_filter) lets the caller skip
+//! entries or stop early with an [`EntryAction`].
+//!
+//! Names Windows cannot create (`CON`, `aux.txt`, trailing dots or spaces, `<>:"|?*`) are
+//! handled by [`WindowsNamePolicy`], by default sanitized on Windows and kept elsewhere.
+//! On Windows, targets past `MAX_PATH` are written through `\\?\` extended-length paths.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use filetime::FileTime;
+
+use crate::chunking::{chunk_digest, chunk_list, ChunkStore};
+use crate::errors::{ProjzstError, Result};
+use crate::listing::ArchiveEntry;
+use crate::mtime::{self, MtimePolicy};
+use crate::reflink::FramedPayload;
+use crate::report::{SpecialKind, UnpackReport, Warning};
+use crate::storage::normalize_path;
+use crate::string_utils::IntoOpStr;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::uring::UringWriter;
+use crate::walk::SpecialPolicy;
+
+/// Device names Windows reserves in every directory, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows does not allow in file names
+const INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Longest path the classic Windows APIs accept
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// What to do with entry names Windows cannot create when unpacking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WindowsNamePolicy {
+    /// Extract names as they are (default on other systems)
+    Keep,
+    /// Rename offending components: `_` prefixed to reserved names, `_` in place of invalid
+    /// characters and of trailing dots and spaces (default on Windows)
+    Sanitize,
+    /// Fail with [`ProjzstError::UnportableEntryPath`], before anything is extracted when
+    /// unpacking a file (streams are only read once, and fail at the entry)
+    Reject,
+}
+
+impl Default for WindowsNamePolicy {
+    fn default() -> Self {
+        if cfg!(windows) {
+            WindowsNamePolicy::Sanitize
+        } else {
+            WindowsNamePolicy::Keep
+        }
+    }
+}
+
+impl WindowsNamePolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(WindowsNamePolicy::Keep),
+            "sanitize" => Ok(WindowsNamePolicy::Sanitize),
+            "reject" => Ok(WindowsNamePolicy::Reject),
+            _ => Err(ProjzstError::InvalidWindowsNamePolicy(s.to_string())),
+        }
+    }
+}
+
+/// Caller-provided rewrite of entry paths, `None` skipping the entry
+pub(crate) type TransformFn = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Caller-provided decision on each entry before it is extracted
+pub(crate) type FilterFn = dyn Fn(&ArchiveEntry) -> EntryAction + Send + Sync;
+
+/// What to do with an entry, as decided by
+/// [`ReadOptions::entry_filter`](crate::ReadOptions::entry_filter)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EntryAction {
+    /// Extract the entry
+    Extract,
+    /// Leave the entry out and go on with the next one
+    Skip,
+    /// Leave this entry and every one after it out; the unpack still succeeds
+    Stop,
+}
+
+/// How entry paths are mapped to paths under the output directory
+#[derive(Clone, Default)]
+pub(crate) struct PathMap {
+    pub(crate) strip_components: usize,
+    pub(crate) transforms: Vec<Arc<TransformFn>>,
+    /// Decision on each entry, before its path is mapped
+    pub(crate) filter: Option<Arc<FilterFn>>,
+    /// Handling of device nodes and FIFOs
+    pub(crate) special: SpecialPolicy,
+    /// Handling of names Windows cannot create
+    pub(crate) windows_names: WindowsNamePolicy,
+    /// Handling of stored modification times
+    pub(crate) mtime: MtimePolicy,
+    /// Flush extracted files to disk
+    pub(crate) sync: bool,
+    /// Batch the writes of small files through io_uring
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub(crate) io_uring: bool,
+    /// Chunk store archive holding the chunks a package set member leaves out
+    pub(crate) chunk_store: Option<PathBuf>,
+}
+
+/// Where the content of regular files is written other than by the tar crate
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Writers<'a> {
+    /// Payload to copy stored files from
+    pub(crate) stored: Option<&'a FramedPayload>,
+    /// Chunks to assemble chunked files from
+    pub(crate) chunks: Option<&'a ChunkStore>,
+    /// Batch to queue small files in
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub(crate) uring: Option<&'a UringWriter>,
+}
+
+impl Writers<'_> {
+    /// Internal helper: whether the file extracted as `path` waits in an io_uring batch
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn is_pending(&self, path: &str) -> bool {
+        self.uring.is_some_and(|uring| uring.is_pending(path))
+    }
+
+    /// Internal helper: files are never queued without io_uring
+    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+    fn is_pending(&self, _path: &str) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for PathMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PathMap")
+            .field("strip_components", &self.strip_components)
+            .field("transforms", &self.transforms.len())
+            .field("filter", &self.filter.is_some())
+            .field("special", &self.special)
+            .field("windows_names", &self.windows_names)
+            .field("mtime", &self.mtime)
+            .field("sync", &self.sync)
+            .finish()
+    }
+}
+
+impl PathMap {
+    /// Whether entries are extracted under their own paths
+    pub(crate) fn is_identity(&self) -> bool {
+        self.strip_components == 0
+            && self.transforms.is_empty()
+            && self.windows_names == WindowsNamePolicy::Keep
+    }
+
+    /// Internal helper: what the entry filter decides for `entry`, extracting everything
+    /// without one
+    pub(crate) fn action<R: Read>(&self, entry: &tar::Entry<'_, R>) -> Result<EntryAction> {
+        let Some(filter) = &self.filter else {
+            return Ok(EntryAction::Extract);
+        };
+        Ok(ArchiveEntry::of(entry)?.map_or(EntryAction::Skip, |info| filter(&info)))
+    }
+
+    /// Internal helper: the mapped, normalized path of an entry, `None` to skip it
+    /// Fails with [`ProjzstError::UnsafeEntryPath`] when a transform leaves the output,
+    /// and with [`ProjzstError::UnportableEntryPath`] for names Windows cannot create
+    /// under [`WindowsNamePolicy::Reject`]
+    pub(crate) fn map(&self, path: &Path) -> Result<Option<String>> {
+        let Some(mapped) = self.map_unchecked(path)? else {
+            return Ok(None);
+        };
+        match self.windows_names {
+            WindowsNamePolicy::Keep => Ok(Some(mapped)),
+            WindowsNamePolicy::Sanitize => Ok(Some(
+                mapped
+                    .split('/')
+                    .map(windows_name)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )),
+            WindowsNamePolicy::Reject => {
+                if mapped.split('/').all(|name| windows_name(name) == name) {
+                    Ok(Some(mapped))
+                } else {
+                    Err(ProjzstError::UnportableEntryPath(mapped))
+                }
+            }
+        }
+    }
+
+    /// Internal helper: [`PathMap::map`] without the [`WindowsNamePolicy`]
+    fn map_unchecked(&self, path: &Path) -> Result<Option<String>> {
+        let Some(path) = normalize_path(path) else {
+            return Ok(None);
+        };
+        let stripped: Vec<&str> = path.split('/').skip(self.strip_components).collect();
+        if stripped.is_empty() {
+            return Ok(None);
+        }
+        let mut mapped = stripped.join("/");
+        for transform in &self.transforms {
+            match transform(&mapped) {
+                Some(next) => mapped = next,
+                None => return Ok(None),
+            }
+        }
+        if mapped.trim_matches('/').is_empty() {
+            return Ok(None);
+        }
+        if Path::new(&mapped).is_absolute() {
+            return Err(ProjzstError::UnsafeEntryPath(mapped));
+        }
+        normalize_path(Path::new(&mapped))
+            .map(Some)
+            .ok_or(ProjzstError::UnsafeEntryPath(mapped))
+    }
+
+    /// Extract one entry under its mapped path in `output_dir`, with its modification
+    /// time as the [`MtimePolicy`] says; regular files may go through `writers`
+    /// Returns the mapped path, or `None` when the entry was skipped
+    pub(crate) fn unpack_entry<R: Read>(
+        &self,
+        entry: &mut tar::Entry<'_, R>,
+        output_dir: &Path,
+        writers: Writers<'_>,
+    ) -> Result<Option<String>> {
+        // The tar crate restores whole seconds only, and future times as they are
+        let mtime = mtime::entry_mtime(entry)?;
+        entry.set_preserve_mtime(self.mtime != MtimePolicy::Skip);
+        let unpacked = self
+            .unpack_mapped_entry(entry, output_dir, writers)
+            .map_err(|e| e.extracting(String::from_utf8_lossy(&entry.path_bytes())))?;
+        match &unpacked {
+            Some(path) if entry.header().entry_type().is_file() => {
+                log::info!("{path} ({} bytes)", entry.size());
+            }
+            Some(path) if entry.header().entry_type().is_dir() => log::debug!("{path}/"),
+            Some(path) => log::debug!("{path}"),
+            None => log::debug!("skipped {}", String::from_utf8_lossy(&entry.path_bytes())),
+        }
+        // Queued files get their time when written
+        if let Some(path) = unpacked.as_ref().filter(|path| !writers.is_pending(path)) {
+            let target = extended_length(output_dir.join(path));
+            mtime::apply(self.mtime, &target, mtime).map_err(|e| e.extracting(path.as_str()))?;
+            if self.sync && entry.header().entry_type().is_file() {
+                File::open(&target)
+                    .and_then(|file| file.sync_all())
+                    .map_err(|e| ProjzstError::from(e).extracting(path.as_str()))?;
+            }
+        }
+        Ok(unpacked)
+    }
+
+    /// Internal helper: [`PathMap::unpack_entry`], counting the entry in `report` along
+    /// with the warnings it gives
+    /// With `keep_going`, a failure is recorded there instead of returned
+    pub(crate) fn unpack_reported<R: Read>(
+        &self,
+        entry: &mut tar::Entry<'_, R>,
+        output_dir: &Path,
+        writers: Writers<'_>,
+        keep_going: bool,
+        report: &mut UnpackReport,
+    ) -> Result<Option<String>> {
+        let special = SpecialKind::of_entry(entry.header().entry_type());
+        let clamped = self.mtime == MtimePolicy::Clamp
+            && mtime::entry_mtime(entry)
+                .is_ok_and(|mtime| mtime > FileTime::from_system_time(SystemTime::now()));
+        match self.unpack_entry(entry, output_dir, writers) {
+            Ok(unpacked) => {
+                report.extracted += usize::from(unpacked.is_some());
+                match (&unpacked, special) {
+                    (None, Some(kind)) if self.special == SpecialPolicy::Skip => {
+                        report.warnings.push(Warning::SpecialEntry {
+                            path: entry.path()?.to_string_lossy().into_owned(),
+                            entry: kind,
+                        });
+                    }
+                    (Some(mapped), _) => {
+                        if self.windows_names == WindowsNamePolicy::Sanitize {
+                            if let Some(path) = self
+                                .map_unchecked(&entry.path()?)?
+                                .filter(|path| path != mapped)
+                            {
+                                report.warnings.push(Warning::SanitizedPath {
+                                    path,
+                                    sanitized: mapped.clone(),
+                                });
+                            }
+                        }
+                        if clamped {
+                            report.warnings.push(Warning::ClampedMtime {
+                                path: mapped.clone(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(unpacked)
+            }
+            Err(e) => {
+                let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                report.entry_failed(path, e, keep_going)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Internal helper: extract one entry under its mapped path in `output_dir`
+    fn unpack_mapped_entry<R: Read>(
+        &self,
+        entry: &mut tar::Entry<'_, R>,
+        output_dir: &Path,
+        writers: Writers<'_>,
+    ) -> Result<Option<String>> {
+        let special = SpecialKind::of_entry(entry.header().entry_type());
+        if let Some(kind) = special {
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if !self.special.keeps(&path, kind)? {
+                return Ok(None);
+            }
+        }
+        let is_file = entry.header().entry_type().is_file();
+        let chunks = if is_file { chunk_list(entry)? } else { None };
+        let is_whole = is_file && chunks.is_none();
+        let stored = writers.stored.filter(|payload| {
+            is_whole && payload.is_stored(entry.raw_file_position(), entry.size())
+        });
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        let uring = writers
+            .uring
+            .filter(|uring| is_whole && stored.is_none() && uring.takes(entry.size()));
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        let uring: Option<()> = None;
+        // The tar crate would write special entries as empty regular files
+        if self.is_identity()
+            && special.is_none()
+            && chunks.is_none()
+            && stored.is_none()
+            && uring.is_none()
+        {
+            let path = normalize_path(&entry.path()?);
+            return Ok(if entry.unpack_in(output_dir)? {
+                path
+            } else {
+                None
+            });
+        }
+        let Some(mapped) = self.map(&entry.path()?)? else {
+            return Ok(None);
+        };
+        let target = extended_length(output_dir.join(&mapped));
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+            // A symlink extracted earlier must not lead the entry out of the output
+            check_inside(parent, output_dir, &mapped)?;
+        }
+
+        if entry.header().entry_type().is_hard_link() {
+            // Hard links name another entry, which was mapped the same way
+            let link = entry.link_name()?.unwrap_or_default();
+            let Some(source) = self.map(&link)? else {
+                return Ok(None);
+            };
+            let source_path = extended_length(output_dir.join(&source));
+            // Nor lead the link to a file outside of it
+            if let Some(parent) = source_path.parent() {
+                check_inside(parent, output_dir, &source)?;
+            }
+            if target.symlink_metadata().is_ok() {
+                fs::remove_file(&target)?;
+            }
+            fs::hard_link(source_path, &target)?;
+            return Ok(Some(mapped));
+        }
+        if let Some(kind) = special {
+            make_special(&target, entry.header(), kind)?;
+            return Ok(Some(mapped));
+        }
+        if let Some(chunks) = chunks {
+            let store = writers
+                .chunks
+                .ok_or_else(|| ProjzstError::InvalidChunk(chunks[0].clone()))?;
+            store.assemble(&chunks, &target, entry.header().mode()?)?;
+            return Ok(Some(mapped));
+        }
+        if let Some(payload) = stored {
+            let mode = entry.header().mode()?;
+            payload.copy_to(entry.raw_file_position(), entry.size(), &target, mode)?;
+            return Ok(Some(mapped));
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(uring) = uring {
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut content)?;
+            if target.symlink_metadata().is_ok() {
+                fs::remove_file(&target)?;
+            }
+            let mtime = mtime::resolve(self.mtime, mtime::entry_mtime(entry)?);
+            let mode = entry.header().mode()?;
+            uring.queue(mapped.clone(), &target, content, mode, mtime)?;
+            return Ok(Some(mapped));
+        }
+        entry.unpack(&target)?;
+        Ok(Some(mapped))
+    }
+}
+
+/// Internal helper: fail with [`ProjzstError::UnsafeEntryPath`] for `name` unless `dir`,
+/// symlinks resolved, is inside `output_dir`
+fn check_inside(dir: &Path, output_dir: &Path, name: &str) -> Result<()> {
+    if dir.canonicalize()?.starts_with(output_dir.canonicalize()?) {
+        Ok(())
+    } else {
+        Err(ProjzstError::UnsafeEntryPath(name.to_string()))
+    }
+}
+
+/// Internal helper: `name` changed into a name Windows can create, the same if it is one
+fn windows_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c < ' ' || INVALID_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let kept = sanitized.trim_end_matches(['.', ' ']).len();
+    let trailing = sanitized.len() - kept;
+    sanitized.truncate(kept);
+    sanitized.extend(std::iter::repeat_n('_', trailing));
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+/// Internal helper: `path` as an extended-length `\\?\` path when it exceeds `MAX_PATH`
+#[cfg(windows)]
+fn extended_length(path: PathBuf) -> PathBuf {
+    if path.as_os_str().len() < MAX_PATH || path.as_os_str().to_string_lossy().starts_with(r"\\?\")
+    {
+        return path;
+    }
+    match std::path::absolute(&path) {
+        Ok(absolute) => {
+            let mut extended = std::ffi::OsString::from(r"\\?\");
+            extended.push(absolute);
+            PathBuf::from(extended)
+        }
+        Err(_) => path,
+    }
+}
+
+/// Internal helper: paths have no length limit to work around on this platform
+#[cfg(not(windows))]
+fn extended_length(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Internal helper: create the device node or FIFO a tar header describes at `target`
+#[cfg(unix)]
+fn make_special(target: &Path, header: &tar::Header, kind: SpecialKind) -> Result<()> {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+
+    if target.symlink_metadata().is_ok() {
+        fs::remove_file(target)?;
+    }
+    let path = CString::new(target.as_os_str().as_bytes())
+        .map_err(|_| ProjzstError::UnsafeEntryPath(target.display().to_string()))?;
+    let file_type = match kind {
+        SpecialKind::BlockDevice => libc::S_IFBLK,
+        SpecialKind::CharDevice => libc::S_IFCHR,
+        _ => libc::S_IFIFO,
+    };
+    let mode = file_type | (header.mode()? & 0o7777) as libc::mode_t;
+    let major = header.device_major()?.unwrap_or_default();
+    let minor = header.device_minor()?.unwrap_or_default();
+    let device = libc::makedev(major as _, minor as _);
+    // SAFETY: `path` is a NUL-terminated string that outlives the call
+    if unsafe { libc::mknod(path.as_ptr(), mode, device) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Internal helper: special entries cannot be created on this platform
+#[cfg(not(unix))]
+fn make_special(target: &Path, _header: &tar::Header, kind: SpecialKind) -> Result<()> {
+    Err(ProjzstError::SpecialEntry {
+        path: target.display().to_string(),
+        kind: kind.name().to_string(),
+    })
+}
+
+/// Internal helper: extract a tar stream with every entry path mapped by `map`
+/// Directories go last, as in [`tar::Archive::unpack`]; with `keep_going`, entries that
+/// cannot be extracted are recorded in `report` instead of failing
+pub(crate) fn unpack_mapped<R: Read>(
+    payload: R,
+    output_dir: &Path,
+    map: &PathMap,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    let mut tar_archive = tar::Archive::new(payload);
+    if unpack_entries(
+        tar_archive.entries()?,
+        output_dir,
+        map,
+        None,
+        keep_going,
+        report,
+    )? {
+        finish_payload(tar_archive.into_inner(), keep_going, report)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: [`unpack_mapped`] on the payload of a per-entry archive file, with
+/// the stored files copied from the file rather than read
+pub(crate) fn unpack_framed(
+    payload: &FramedPayload,
+    output_dir: &Path,
+    map: &PathMap,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    let mut tar_archive = tar::Archive::new(payload);
+    // Content copied from the file is skipped by seeking past it
+    let entries = tar_archive.entries_with_seek()?;
+    if unpack_entries(entries, output_dir, map, Some(payload), keep_going, report)? {
+        finish_payload(tar_archive.into_inner(), keep_going, report)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: read the rest of a payload whose entries were all read, so the zstd
+/// decoder gets to the end of the last frame and checks its content checksum
+pub(crate) fn finish_payload<R: Read>(
+    mut payload: R,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    if let Err(e) = io::copy(&mut payload, &mut io::sink()) {
+        report.payload_failed(e.into(), keep_going)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: extract the entries of a tar stream, see [`unpack_mapped`]
+/// Returns whether the end of the archive was reached, rather than a stop of the entry
+/// filter or a payload error
+fn unpack_entries<R: Read>(
+    entries: tar::Entries<'_, R>,
+    output_dir: &Path,
+    map: &PathMap,
+    stored: Option<&FramedPayload>,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<bool> {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    let uring = map.io_uring.then(|| UringWriter::new(map.sync)).flatten();
+    let chunks = ChunkStore::with_store(output_dir, map.chunk_store.as_deref())?;
+    let writers = Writers {
+        stored,
+        chunks: Some(&chunks),
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        uring: uring.as_ref(),
+    };
+    let mut directories = Vec::new();
+    let mut finished = true;
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.payload_failed(e.into(), keep_going)?;
+                finished = false;
+                break;
+            }
+        };
+        if let Some(digest) = chunk_digest(&entry) {
+            if let Err(e) = chunks.insert(digest, &mut entry) {
+                let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                report.entry_failed(path, e, keep_going)?;
+            }
+            continue;
+        }
+        match map.action(&entry)? {
+            EntryAction::Extract => {}
+            EntryAction::Skip => continue,
+            EntryAction::Stop => {
+                finished = false;
+                break;
+            }
+        }
+        if entry.header().entry_type().is_dir() {
+            directories.push(entry);
+            continue;
+        }
+        // Links and the like may refer to queued files: write those first
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(uring) = &uring {
+            if uring.is_full()
+                || !entry.header().entry_type().is_file()
+                || !uring.takes(entry.size())
+            {
+                write_batch(uring, keep_going, report)?;
+            }
+        }
+        map.unpack_reported(&mut entry, output_dir, writers, keep_going, report)?;
+    }
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if let Some(uring) = &uring {
+        write_batch(uring, keep_going, report)?;
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut directory in directories {
+        let writers = Writers::default();
+        map.unpack_reported(&mut directory, output_dir, writers, keep_going, report)?;
+    }
+    Ok(finished)
+}
+
+/// Internal helper: write the queued files, recording those that failed in `report`
+/// as extraction failures
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn write_batch(uring: &UringWriter, keep_going: bool, report: &mut UnpackReport) -> Result<()> {
+    for (path, e) in uring.flush() {
+        report.extracted -= 1;
+        report.entry_failed(path.clone(), e.extracting(path), keep_going)?;
+    }
+    Ok(())
+}
+
+/// A sed-style substitution on entry paths: `s/PATTERN/REPLACEMENT/[g]`
+/// Any character can stand in for `/`. The pattern is literal text, optionally anchored
+/// with a leading `^` and/or trailing `$`; `g` replaces every match instead of the first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTransform {
+    pattern: String,
+    replacement: String,
+    anchored_start: bool,
+    anchored_end: bool,
+    global: bool,
+}
+
+impl PathTransform {
+    /// Parse a substitution such as `s#^pkg/##`
+    /// Fails with [`ProjzstError::InvalidTransform`] otherwise
+    pub fn parse(expr: &str) -> Result<Self> {
+        let invalid = || ProjzstError::InvalidTransform(expr.to_string());
+        let rest = expr.strip_prefix('s').ok_or_else(invalid)?;
+        let delimiter = rest.chars().next().ok_or_else(invalid)?;
+        let parts: Vec<&str> = rest[delimiter.len_utf8()..].split(delimiter).collect();
+        let [pattern, replacement, flags] = parts[..] else {
+            return Err(invalid());
+        };
+        if !flags.chars().all(|c| c == 'g') {
+            return Err(invalid());
+        }
+        let (anchored_start, pattern) = match pattern.strip_prefix('^') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let (anchored_end, pattern) = match pattern.strip_suffix('$') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() && !anchored_start && !anchored_end {
+            return Err(invalid());
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            anchored_start,
+            anchored_end,
+            global: !flags.is_empty(),
+        })
+    }
+
+    /// Apply the substitution to `path`
+    pub fn apply(&self, path: &str) -> String {
+        match (self.anchored_start, self.anchored_end) {
+            (true, true) if path == self.pattern => self.replacement.clone(),
+            (true, false) => match path.strip_prefix(self.pattern.as_str()) {
+                Some(rest) => format!("{}{rest}", self.replacement),
+                None => path.to_string(),
+            },
+            (false, true) => match path.strip_suffix(self.pattern.as_str()) {
+                Some(rest) => format!("{rest}{}", self.replacement),
+                None => path.to_string(),
+            },
+            (false, false) if self.global => path.replace(&self.pattern, &self.replacement),
+            (false, false) => path.replacen(&self.pattern, &self.replacement, 1),
+            _ => path.to_string(),
+        }
+    }
+}
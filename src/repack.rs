@@ -0,0 +1,259 @@
+//! Recompressing archives with other settings
+//!
+//! [`Packer::repack`] decodes the payload of a .pjz file and feeds the tar stream straight
+//! into a new encoder, nothing being extracted on the way: the entries come out byte for
+//! byte as they went in, under the compression level, codec, dictionary and thread count
+//! of the packer. The metadata of the input (its latest appended revision, folded into
+//! the header) and its frames are kept: foreign frames, comments, icons, attachments and
+//! provenance. A signature or timestamp no longer matches the new file and has to be
+//! made again.
+//!
+//! [`Packer::optimize`] does the same with the entries re-sorted in the packer's
+//! [`EntryOrder`], which lets zstd find more matches in archives packed quickly in path
+//! order. The tar stream is spooled to a temporary file next to the output for that.
+//! Directories, and the chunks of a chunked archive, stay ahead of the files in their
+//! original order, and hard links after them, so every entry still comes after what it
+//! refers to.
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{open_payload_with, Packer, ReadOptions, METADATA_FRAME_MAGIC};
+use crate::chunking::CHUNK_DIR;
+use crate::errors::Result;
+use crate::frames::{frames, rewrite_frames, FrameKind, RawFrame};
+use crate::revisions::latest_revision;
+use crate::temp::temp_file_for;
+use crate::volume::{open_input, volume_files};
+use crate::walk::EntryOrder;
+
+/// Size of a tar block, which entries are padded to
+const BLOCK_SIZE: u64 = 512;
+
+/// Sizes before and after [`Packer::repack`] or [`Packer::optimize`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct RepackReport {
+    /// Bytes of the input on disk, every volume included
+    pub input_size: u64,
+    /// Bytes of the new archive on disk, every volume included
+    pub output_size: u64,
+}
+
+impl Packer {
+    /// Recompress a .pjz file with the compression settings of this packer
+    /// The tar stream is copied as it is, entries are not extracted. The metadata and
+    /// `extra` options of the packer are not used: the archive keeps its own latest
+    /// revision and the frames attached to it, but a signature or timestamp no longer
+    /// matches. Like other packs, an existing output is replaced unless
+    /// [`Packer::overwrite`] is off; the output may be the input itself
+    ///
+    /// # Arguments
+    /// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+    /// * `output_file` - Output .pjz file path
+    /// * `options` - How to read the input: unknown fields, metadata magic, dictionaries
+    pub fn repack<P1, P2>(
+        &self,
+        input_file: P1,
+        output_file: P2,
+        options: &ReadOptions,
+    ) -> Result<RepackReport>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        self.rewrite_payload(input_file.as_ref(), output_file.as_ref(), options, None)
+    }
+
+    /// Recompress a .pjz file like [`Packer::repack`], with its entries re-sorted in the
+    /// order set with [`Packer::order`]
+    /// Meant for archives packed quickly (in CI, at a low level) and squeezed afterwards;
+    /// the report tells how much was saved. The output may be larger than the input, when
+    /// the input was packed at a higher level than this packer's
+    ///
+    /// # Arguments
+    /// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+    /// * `output_file` - Output .pjz file path
+    /// * `options` - How to read the input: unknown fields, metadata magic, dictionaries
+    pub fn optimize<P1, P2>(
+        &self,
+        input_file: P1,
+        output_file: P2,
+        options: &ReadOptions,
+    ) -> Result<RepackReport>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let order = self.walk.order;
+        self.rewrite_payload(
+            input_file.as_ref(),
+            output_file.as_ref(),
+            options,
+            Some(order),
+        )
+    }
+
+    /// Internal helper: recompress an archive, re-sorting its entries when `order` is given
+    fn rewrite_payload(
+        &self,
+        input_file: &Path,
+        output_file: &Path,
+        options: &ReadOptions,
+        order: Option<EntryOrder>,
+    ) -> Result<RepackReport> {
+        self.check_overwrite(output_file)?;
+        let input_size = disk_size(input_file)?;
+
+        let mut reader = open_input(input_file)?;
+        let (header, payload_head) = options.read_header(&mut reader)?;
+        let metadata = latest_revision(input_file, options.ignore_unknown)?.or(header);
+        options.check_metadata(metadata.as_ref())?;
+        let input_magic = options.metadata_magic.unwrap_or(METADATA_FRAME_MAGIC);
+        let (foreign, attached): (Vec<RawFrame>, Vec<RawFrame>) = frames(input_file)?
+            .into_iter()
+            .filter(|frame| frame.is_foreign(input_magic) || self.carries(frame))
+            .partition(|frame| frame.is_foreign(input_magic));
+
+        let mut packer = self.clone().keep_foreign_frames(false).foreign_frames(
+            foreign
+                .into_iter()
+                .filter(|frame| frame.is_foreign(self.metadata_magic))
+                .collect(),
+        );
+        packer.metadata = metadata.unwrap_or_default();
+        let metadata_bytes = packer.encoded_metadata()?;
+
+        // The output may be the input, so it is only replaced once the payload is copied
+        let (mut output, temp) = temp_file_for(output_file)?;
+        packer.write_header(&mut output, &metadata_bytes)?;
+        let mut tar_stream =
+            open_payload_with(payload_head, reader, &options.dictionaries)?.into_inner();
+        let mut zst_encoder = packer.payload_encoder(&mut output)?;
+        match order {
+            Some(order) => copy_sorted(&mut tar_stream, &mut zst_encoder, order, output_file)?,
+            None => {
+                io::copy(&mut tar_stream, &mut zst_encoder)?;
+            }
+        }
+        zst_encoder.finish()?.flush()?;
+        packer.write_trailer(&mut output, &metadata_bytes)?;
+        packer.persist(output, temp, output_file)?;
+
+        if !attached.is_empty() && !packer.is_v1_0() {
+            let magic = packer.metadata_magic;
+            rewrite_frames(output_file, |frames| {
+                let position = frames
+                    .iter()
+                    .take_while(|frame| frame.magic == magic)
+                    .count();
+                frames.splice(position..position, attached);
+            })?;
+        }
+
+        Ok(RepackReport {
+            input_size,
+            output_size: disk_size(output_file)?,
+        })
+    }
+
+    /// Internal helper: whether a frame describing the archive rather than its payload
+    /// (comment, icon, attachment, provenance) is carried over by [`Packer::repack`]
+    /// A provenance set on the packer replaces the one of the input
+    fn carries(&self, frame: &RawFrame) -> bool {
+        match frame.kind() {
+            FrameKind::Comment | FrameKind::Icon | FrameKind::Attachment => true,
+            FrameKind::Provenance => self.provenance.is_none(),
+            _ => false,
+        }
+    }
+}
+
+/// Internal helper: copy a tar stream with its entries sorted in `order`
+/// The stream is spooled next to `output_file`; each entry is copied with the extension
+/// headers in front of it, byte for byte, and the end-of-archive blocks last
+fn copy_sorted<R: Read, W: Write>(
+    tar_stream: &mut R,
+    output: &mut W,
+    order: EntryOrder,
+    output_file: &Path,
+) -> Result<()> {
+    let (mut spool, temp) = temp_file_for(output_file)?;
+    let spooled = io::copy(tar_stream, &mut spool)?;
+    drop(spool);
+    let mut reader = File::open(temp.path())?;
+
+    // Entries as (rank, key, start, end): an entry starts where the previous one ended,
+    // so the PAX and GNU long name headers before it belong to it
+    let mut entries = Vec::new();
+    let mut end = 0;
+    {
+        let mut tar_archive = tar::Archive::new(&mut reader);
+        for entry in tar_archive.entries_with_seek()? {
+            let entry = entry?;
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            let path = entry.path()?;
+            let rank = if entry_type.is_hard_link() {
+                2
+            } else if entry_type.is_dir()
+                || entry_type.is_pax_global_extensions()
+                || path.starts_with(CHUNK_DIR)
+            {
+                0
+            } else {
+                1
+            };
+            let size = header.entry_size()?;
+            let key = match order {
+                EntryOrder::Path => (path.to_string_lossy().into_owned(), 0),
+                EntryOrder::Extension => (
+                    path.extension()
+                        .map(|ext| ext.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    0,
+                ),
+                EntryOrder::Size => (String::new(), size),
+            };
+            let start = end;
+            end = entry.raw_file_position() + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+            entries.push((rank, key, start, end));
+        }
+    }
+    // Stable, so entries of equal keys keep their order; directories and chunks keep the
+    // original one, where a parent comes before what it holds whatever the keys
+    entries.sort_by(|a, b| {
+        a.0.cmp(&b.0).then_with(|| {
+            if a.0 == 0 {
+                Ordering::Equal
+            } else {
+                a.1.cmp(&b.1)
+            }
+        })
+    });
+
+    for (_, _, start, end) in entries {
+        copy_range(&mut reader, output, start, end)?;
+    }
+    copy_range(&mut reader, output, end, spooled)
+}
+
+/// Internal helper: copy the bytes `start..end` of a file
+fn copy_range<W: Write>(reader: &mut File, output: &mut W, start: u64, end: u64) -> Result<()> {
+    reader.seek(SeekFrom::Start(start))?;
+    io::copy(&mut reader.take(end - start), output)?;
+    Ok(())
+}
+
+/// Internal helper: bytes an archive takes on disk, every volume included
+fn disk_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for file in volume_files(path) {
+        size += fs::metadata(file)?.len();
+    }
+    Ok(size)
+}
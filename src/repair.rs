@@ -0,0 +1,189 @@
+//! Salvage of truncated or corrupt archives
+//!
+//! [`repair`] copies the leading frames of a damaged .pjz file (metadata, comment, icon,
+//! ...) as they are, then decodes the payload entry by entry. Every tar entry decoded in
+//! full goes into the repaired archive in a zstd frame of its own; the entry cut short by
+//! the corruption and everything after it are lost. The repaired payload ends with a
+//! proper end-of-archive marker, so every reader accepts it.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::codec::zstd_decoder;
+use crate::errors::Result;
+use crate::frames::frames;
+use crate::stat::Position;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+use crate::DEFAULT_ZSTD_LEVEL;
+
+/// Size of a tar block
+const BLOCK_SIZE: u64 = 512;
+
+/// What [`repair`] salvaged from a damaged archive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct RepairReport {
+    /// Leading frames copied (metadata, comment, icon, ...)
+    pub frames: usize,
+    /// Paths of the entries copied in full, in archive order
+    pub recovered: Vec<String>,
+    /// Content bytes of the recovered entries
+    pub recovered_bytes: u64,
+    /// Entry cut short by the corruption, if its header could still be read
+    pub lost_entry: Option<String>,
+    /// Why decoding stopped early, `None` when the payload was intact
+    pub error: Option<String>,
+}
+
+impl RepairReport {
+    /// Whether the whole payload decoded, so nothing was lost
+    pub fn is_intact(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Write a readable copy of a damaged .pjz file, keeping its leading frames and every
+/// entry decoded in full before the corruption point
+/// The frames ahead of the payload must be intact; frames after it (such as metadata
+/// revisions appended later) are not salvaged
+///
+/// # Arguments
+/// * `input_file` - Path to the damaged .pjz file (or the first volume of a split archive)
+/// * `output_file` - Path of the repaired .pjz file, written only once salvage is done
+pub fn repair<P1, P2>(input_file: P1, output_file: P2) -> Result<RepairReport>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let input_file = input_file.as_ref();
+    let frames = frames(input_file)?;
+    let (mut output, temp) = temp_file_for(output_file.as_ref())?;
+    for frame in &frames {
+        frame.write_to(&mut output)?;
+    }
+    let mut report = RepairReport {
+        frames: frames.len(),
+        ..RepairReport::default()
+    };
+
+    // The decoder skips the leading frames on its own
+    let mut payload = Position {
+        inner: zstd_decoder(open_input(input_file)?)?,
+        position: 0,
+    };
+    let mut tar_archive = tar::Archive::new(&mut payload);
+    // Long names and PAX records are copied along with the entry they describe
+    let mut extensions = Vec::new();
+    let mut extension_path = None;
+    let mut entries_end = 0;
+    let mut entries = tar_archive.entries()?.raw(true);
+    let failure = loop {
+        let mut entry = match entries.next() {
+            None => break None,
+            Some(Ok(entry)) => entry,
+            Some(Err(e)) => break Some(e),
+        };
+        let header = entry.header().clone();
+        let size = entry.size();
+        let entry_type = header.entry_type();
+        if entry_type.is_gnu_longname()
+            || entry_type.is_gnu_longlink()
+            || entry_type.is_pax_local_extensions()
+            || entry_type.is_pax_global_extensions()
+        {
+            let mut data = Vec::new();
+            match entry.read_to_end(&mut data) {
+                Ok(_) if data.len() as u64 == size => {}
+                Ok(_) => break Some(io::ErrorKind::UnexpectedEof.into()),
+                Err(e) => break Some(e),
+            }
+            if entry_type.is_gnu_longname() {
+                let name = data.split(|&b| b == 0).next().unwrap_or_default();
+                extension_path = Some(String::from_utf8_lossy(name).into_owned());
+            } else if entry_type.is_pax_local_extensions() {
+                extension_path = pax_path(&data).or(extension_path);
+            }
+            extensions.extend_from_slice(header.as_bytes());
+            extensions.extend_from_slice(&data);
+            extensions.resize(extensions.len() + padding(size), 0);
+            continue;
+        }
+
+        let path = extension_path
+            .take()
+            .unwrap_or_else(|| String::from_utf8_lossy(&header.path_bytes()).into_owned());
+        let start = output.stream_position()?;
+        if let Err(e) = copy_entry(&mut output, &extensions, &header, &mut entry, size) {
+            // Drop the partial frame of the entry
+            output.set_len(start)?;
+            output.seek(SeekFrom::Start(start))?;
+            report.lost_entry = Some(path);
+            break Some(e);
+        }
+        log::info!("{path}");
+        entries_end = entry.raw_file_position() + size + padding(size) as u64;
+        extensions.clear();
+        report.recovered_bytes += size;
+        report.recovered.push(path);
+    };
+
+    let payload = tar_archive.into_inner();
+    report.error = match failure {
+        Some(e) => Some(e.to_string()),
+        // The tar stream stopped at an entry boundary instead of its end marker
+        None if payload.position < entries_end + BLOCK_SIZE => {
+            Some("payload ends without an end-of-archive marker".to_string())
+        }
+        // Damage after the end marker costs no entry, but is reported all the same
+        None => io::copy(payload, &mut io::sink())
+            .err()
+            .map(|e| e.to_string()),
+    };
+
+    let end_marker = [0u8; 2 * BLOCK_SIZE as usize];
+    zstd::stream::copy_encode(&end_marker[..], &mut output, DEFAULT_ZSTD_LEVEL)?;
+    output.sync_all()?;
+    drop(output);
+    temp.persist(output_file.as_ref())?;
+    Ok(report)
+}
+
+/// Internal helper: write one entry, with the extension entries before it, as a zstd frame
+/// Fails if the content ends before the size in its header
+fn copy_entry<R: Read>(
+    output: &mut File,
+    extensions: &[u8],
+    header: &tar::Header,
+    content: &mut R,
+    size: u64,
+) -> io::Result<()> {
+    let mut encoder = zstd::stream::Encoder::new(output, DEFAULT_ZSTD_LEVEL)?;
+    encoder.write_all(extensions)?;
+    encoder.write_all(header.as_bytes())?;
+    if io::copy(content, &mut encoder)? != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "entry content ends early",
+        ));
+    }
+    encoder.write_all(&vec![0; padding(size)])?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Internal helper: the `path` record of a PAX extended header
+fn pax_path(data: &[u8]) -> Option<String> {
+    tar::PaxExtensions::new(data)
+        .filter_map(|extension| extension.ok())
+        .find(|extension| extension.key() == Ok("path"))
+        .map(|extension| String::from_utf8_lossy(extension.value_bytes()).into_owned())
+}
+
+/// Internal helper: zeros completing `size` bytes of content to a whole tar block
+fn padding(size: u64) -> usize {
+    ((BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE) as usize
+}
@@ -0,0 +1,333 @@
+//! Outcome of packing a directory and of unpacking an archive
+//!
+//! [`Packer::pack`](crate::Packer::pack) and the other directory packs return a
+//! [`PackReport`] describing what went into the archive and what was left out, with
+//! the size of the stored files before and after compression.
+//! [`ReadOptions::unpack_with_report`](crate::ReadOptions::unpack_with_report) returns an
+//! [`UnpackReport`] listing the entries extracted and those that failed.
+//!
+//! Both collect the non-fatal conditions met on the way as [`Warning`]s, in the order
+//! they happened, so callers can show them or act on them instead of reading the log.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::errors::{ProjzstError, Result};
+use crate::secrets::{SecretFinding, SecretKind};
+
+/// Summary of a directory pack
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PackReport {
+    /// Number of regular files stored
+    pub files: usize,
+    /// Number of files stored as hard links to a file stored earlier
+    pub hard_links: usize,
+    /// Files left out for exceeding [`Packer::max_file_size`](crate::Packer::max_file_size)
+    pub oversized: Vec<SkippedFile>,
+    /// Device nodes, FIFOs and sockets left out, see
+    /// [`Packer::special_entries`](crate::Packer::special_entries)
+    pub special: Vec<SpecialEntry>,
+    /// Files stored although they look like they contain credentials, see
+    /// [`Packer::secret_scan`](crate::Packer::secret_scan)
+    pub secrets: Vec<SecretFinding>,
+    /// Total size of the stored files in bytes
+    pub original_size: u64,
+    /// Bytes of file content left out as already stored in an identical chunk, see
+    /// [`Packer::chunked`](crate::Packer::chunked)
+    pub deduplicated_size: u64,
+    /// Size of the compressed payload in bytes, tar headers included
+    pub compressed_size: u64,
+    /// Entries stored before an interruption and taken over by this pack, see
+    /// [`Packer::checkpoint`](crate::Packer::checkpoint)
+    pub resumed: usize,
+    /// Stored files by extension, largest total size first
+    pub extensions: Vec<ExtensionStats>,
+    /// Non-fatal conditions met while packing, in order: files left out, possible secrets
+    pub warnings: Vec<Warning>,
+}
+
+impl PackReport {
+    /// Original size divided by compressed size, `0` for an empty payload
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_size == 0 {
+            return 0.0;
+        }
+        self.original_size as f64 / self.compressed_size as f64
+    }
+
+    /// Internal helper: count a stored file of `size` bytes at archive path `path`
+    pub(crate) fn add_file(&mut self, path: &str, size: u64) {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        // Dot files such as `.gitignore` have no extension
+        let extension = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => extension.to_lowercase(),
+            _ => String::new(),
+        };
+        self.files += 1;
+        self.original_size += size;
+        match self
+            .extensions
+            .iter_mut()
+            .find(|stats| stats.extension == extension)
+        {
+            Some(stats) => {
+                stats.files += 1;
+                stats.size += size;
+            }
+            None => self.extensions.push(ExtensionStats {
+                extension,
+                files: 1,
+                size,
+            }),
+        }
+    }
+
+    /// Internal helper: record files left out by the walk of a source directory
+    pub(crate) fn skipped(&mut self, oversized: Vec<SkippedFile>, special: Vec<SpecialEntry>) {
+        for file in &oversized {
+            self.warnings.push(Warning::OversizedFile {
+                path: file.path.clone(),
+                size: file.size,
+            });
+        }
+        for entry in &special {
+            self.warnings.push(Warning::SpecialEntry {
+                path: entry.path.clone(),
+                entry: entry.kind,
+            });
+        }
+        self.oversized.extend(oversized);
+        self.special.extend(special);
+    }
+
+    /// Internal helper: record the files stored although they look like they contain
+    /// credentials
+    pub(crate) fn set_secrets(&mut self, secrets: Vec<SecretFinding>) {
+        for finding in &secrets {
+            self.warnings.push(Warning::PossibleSecret {
+                path: finding.path.clone(),
+                line: finding.line,
+                secret: finding.kind,
+            });
+        }
+        self.secrets = secrets;
+    }
+
+    /// Internal helper: record the payload size and order the extensions by size
+    pub(crate) fn finish(&mut self, compressed_size: u64) {
+        self.compressed_size = compressed_size;
+        self.extensions.sort_by(|a, b| {
+            b.size
+                .cmp(&a.size)
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+    }
+}
+
+/// Summary of an unpack
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct UnpackReport {
+    /// Number of entries extracted
+    pub extracted: usize,
+    /// Entries that could not be extracted, with the reason; only filled with
+    /// [`ReadOptions::keep_going`](crate::ReadOptions::keep_going), which otherwise fails
+    /// on the first one
+    pub failed: Vec<(String, String)>,
+    /// Why reading the payload stopped before its end; the entries after that point are
+    /// missing from the output
+    pub payload_error: Option<String>,
+    /// Nested archives unpacked in place with
+    /// [`ReadOptions::recursive`](crate::ReadOptions::recursive), sorted
+    pub nested: Vec<String>,
+    /// Nested archives left packed, with the reason, sorted
+    pub nested_skipped: Vec<(String, String)>,
+    /// Non-fatal conditions met while unpacking, in order: unknown metadata fields
+    /// ignored, entries left out or renamed, modification times clamped
+    pub warnings: Vec<Warning>,
+}
+
+impl UnpackReport {
+    /// Whether every entry of the archive was extracted or skipped by the options
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.payload_error.is_none()
+    }
+
+    /// Internal helper: record an entry that could not be extracted, or fail with the
+    /// error unless `keep_going` is set
+    pub(crate) fn entry_failed(
+        &mut self,
+        path: String,
+        error: ProjzstError,
+        keep_going: bool,
+    ) -> Result<()> {
+        if !keep_going {
+            return Err(error);
+        }
+        log::warn!(
+            "entry not extracted: path={path:?} error={:?}",
+            error.to_string()
+        );
+        self.failed.push((path, error.to_string()));
+        Ok(())
+    }
+
+    /// Internal helper: record the unknown metadata fields ignored when reading the archive
+    pub(crate) fn ignored_fields(&mut self, fields: Vec<String>) {
+        for field in fields {
+            self.warnings.push(Warning::UnknownField { field });
+        }
+    }
+
+    /// Internal helper: record a nested archive left packed
+    pub(crate) fn skip_nested(&mut self, path: String, reason: &str) {
+        log::info!("{path}: left packed, {reason}");
+        self.warnings.push(Warning::NestedLeftPacked {
+            path: path.clone(),
+            reason: reason.to_string(),
+        });
+        self.nested_skipped.push((path, reason.to_string()));
+    }
+
+    /// Internal helper: record why the payload could not be read to its end, or fail with
+    /// the error unless `keep_going` is set
+    pub(crate) fn payload_failed(&mut self, error: ProjzstError, keep_going: bool) -> Result<()> {
+        if !keep_going {
+            return Err(error);
+        }
+        log::warn!("payload not read to its end: error={:?}", error.to_string());
+        self.payload_error = Some(error.to_string());
+        Ok(())
+    }
+}
+
+/// Stored files sharing an extension
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ExtensionStats {
+    /// Lowercase extension without the dot, empty for files without one
+    pub extension: String,
+    /// Number of files
+    pub files: usize,
+    /// Total size of the files in bytes
+    pub size: u64,
+}
+
+/// A source file left out of an archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SkippedFile {
+    /// Path the file would have had in the archive
+    pub path: String,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+/// A device node, FIFO or socket met while packing or unpacking
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SpecialEntry {
+    /// Path of the entry in the archive
+    pub path: String,
+    /// What the entry is
+    pub kind: SpecialKind,
+}
+
+/// Kinds of entries that are neither files, directories nor links
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SpecialKind {
+    /// Block device node
+    BlockDevice,
+    /// Character device node
+    CharDevice,
+    /// Named pipe
+    Fifo,
+    /// Unix domain socket, which tar cannot store
+    Socket,
+}
+
+impl SpecialKind {
+    /// Kind of a tar entry, `None` for files, directories and links
+    pub(crate) fn of_entry(entry_type: tar::EntryType) -> Option<Self> {
+        match entry_type {
+            tar::EntryType::Block => Some(SpecialKind::BlockDevice),
+            tar::EntryType::Char => Some(SpecialKind::CharDevice),
+            tar::EntryType::Fifo => Some(SpecialKind::Fifo),
+            _ => None,
+        }
+    }
+
+    /// Human-readable name, e.g. `block device`
+    pub fn name(self) -> &'static str {
+        match self {
+            SpecialKind::BlockDevice => "block device",
+            SpecialKind::CharDevice => "character device",
+            SpecialKind::Fifo => "named pipe",
+            SpecialKind::Socket => "socket",
+        }
+    }
+}
+
+/// A non-fatal condition met while packing or unpacking, see [`PackReport::warnings`] and
+/// [`UnpackReport::warnings`]
+/// Serialized with the variant as `kind`; displayed as a one-line message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Warning {
+    /// A file left out for exceeding [`Packer::max_file_size`](crate::Packer::max_file_size)
+    OversizedFile { path: String, size: u64 },
+    /// A device node, FIFO or socket left out, see
+    /// [`Packer::special_entries`](crate::Packer::special_entries)
+    SpecialEntry { path: String, entry: SpecialKind },
+    /// A file stored although it looks like it contains credentials
+    PossibleSecret {
+        path: String,
+        line: Option<usize>,
+        secret: SecretKind,
+    },
+    /// A metadata field this release does not know, ignored under [`IgnoreUnknown::On`]
+    ///
+    /// [`IgnoreUnknown::On`]: crate::IgnoreUnknown::On
+    UnknownField { field: String },
+    /// An entry extracted under a name changed for Windows, see
+    /// [`WindowsNamePolicy::Sanitize`](crate::WindowsNamePolicy::Sanitize)
+    SanitizedPath { path: String, sanitized: String },
+    /// A modification time in the future, set to the time of extraction under
+    /// [`MtimePolicy::Clamp`](crate::MtimePolicy::Clamp)
+    ClampedMtime { path: String },
+    /// A nested archive left packed, with the reason
+    NestedLeftPacked { path: String, reason: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::OversizedFile { path, size } => {
+                write!(f, "skipped {path} ({size} bytes): over the size limit")
+            }
+            Warning::SpecialEntry { path, entry } => write!(f, "skipped {} {path}", entry.name()),
+            Warning::PossibleSecret { path, line, secret } => match line {
+                Some(line) => write!(f, "possible secret in {path}:{line}: {}", secret.name()),
+                None => write!(f, "possible secret in {path}: {}", secret.name()),
+            },
+            Warning::UnknownField { field } => {
+                write!(f, "unknown metadata field {field:?} ignored")
+            }
+            Warning::SanitizedPath { path, sanitized } => {
+                write!(f, "{path} extracted as {sanitized}")
+            }
+            Warning::ClampedMtime { path } => {
+                write!(
+                    f,
+                    "{path} has a modification time in the future, set to now"
+                )
+            }
+            Warning::NestedLeftPacked { path, reason } => write!(f, "{path} left packed: {reason}"),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Metadata revisions appended after the payload
+//!
+//! [`append_metadata`] edits metadata without rewriting the file: the new metadata goes
+//! into a metadata frame appended at the very end, which zstd decoders skip like any
+//! skippable frame. For reads by path, the latest revision supersedes the metadata at the
+//! start of the file; earlier ones stay available through [`metadata_history`] and
+//! [`read_metadata_revision`]. Streams (e.g. stdin) only show the original metadata.
+//!
+//! The payload of an appended frame is the encoded metadata followed by the payload size
+//! as a little-endian `u32`, so revisions are found walking backwards from the end.
+//!
+//! Packs can also write the metadata in a trailer frame right after the payload (see
+//! [`MetadataPlacement`]), as a copy of the header or in its place for writers that stream
+//! the payload before the metadata is final. The trailer is laid out like a revision with
+//! `PJZT` in front of the metadata, so revisions appended later are still found past it.
+//! Reading a file that starts right away with the payload, the trailer stands in for the
+//! header; streams cannot seek to it and need the metadata at the start.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{
+    decode_metadata, decompress_metadata, encode_metadata, read_metadata_from,
+    write_metadata_frame, MAX_METADATA_SIZE, METADATA_FRAME_MAGIC, SKIPPABLE_FRAME_MAGIC_MAX,
+    SKIPPABLE_FRAME_MAGIC_MIN,
+};
+use crate::errors::{ProjzstError, Result};
+use crate::frames::write_frame_unchecked;
+use crate::metadata::{IgnoreUnknown, Metadata, MetadataEncoding};
+use crate::string_utils::IntoOpStr;
+use crate::volume::{last_volume, open_input};
+
+/// First bytes of a trailer frame payload, which no metadata encoding starts with
+const TRAILER_TAG: &[u8; 4] = b"PJZT";
+
+/// Where a pack writes the metadata of an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MetadataPlacement {
+    /// In frames at the start of the file (default)
+    #[default]
+    Header,
+    /// At the start, and a copy in a trailer frame after the payload
+    Both,
+    /// Only in a trailer frame after the payload; the file starts with the payload
+    Trailer,
+}
+
+impl MetadataPlacement {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "header" | "start" => Ok(MetadataPlacement::Header),
+            "both" => Ok(MetadataPlacement::Both),
+            "trailer" | "end" => Ok(MetadataPlacement::Trailer),
+            _ => Err(ProjzstError::InvalidMetadataPlacement(s.to_string())),
+        }
+    }
+
+    /// Name of the placement, as accepted by [`MetadataPlacement::from_str_tmp`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataPlacement::Header => "header",
+            MetadataPlacement::Both => "both",
+            MetadataPlacement::Trailer => "trailer",
+        }
+    }
+
+    /// Internal helper: whether the metadata frames start the file
+    pub(crate) fn in_header(self) -> bool {
+        self != MetadataPlacement::Trailer
+    }
+
+    /// Internal helper: whether a trailer frame follows the payload
+    pub(crate) fn in_trailer(self) -> bool {
+        self != MetadataPlacement::Header
+    }
+}
+
+/// One version of the metadata of an archive
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct MetadataRevision {
+    /// 0 for the metadata written when packing, then 1, 2, ... in append order
+    pub revision: usize,
+    /// The metadata of this revision
+    pub metadata: Metadata,
+}
+
+/// Replace the metadata of a .pjz file by appending a new revision to its end
+/// Nothing already in the file is rewritten; returns the number of the new revision
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `metadata` - Metadata superseding the current one
+pub fn append_metadata<P: AsRef<Path>>(input_file: P, metadata: &Metadata) -> Result<usize> {
+    let input_file = input_file.as_ref();
+    read_metadata_from(open_input(input_file)?, IgnoreUnknown::On)?;
+
+    let path = last_volume(input_file);
+    let revision = revision_payloads(&mut File::open(&path)?)?.len() + 1;
+
+    let mut payload = encode_metadata(metadata, MetadataEncoding::MessagePack)?;
+    let size = u32::try_from(payload.len() + 4)
+        .map_err(|_| ProjzstError::FrameTooLarge(payload.len() + 4))?;
+    payload.extend_from_slice(&size.to_le_bytes());
+
+    let mut file = OpenOptions::new().append(true).open(&path)?;
+    write_frame_unchecked(&mut file, METADATA_FRAME_MAGIC, &payload)?;
+    file.sync_all()?;
+    Ok(revision)
+}
+
+/// Read every metadata revision of a .pjz file, oldest first
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn metadata_history<P: AsRef<Path>>(
+    input_file: P,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Vec<MetadataRevision>> {
+    let input_file = input_file.as_ref();
+    let original = read_metadata_from(open_input(input_file)?, ignore_unknown)?;
+    let mut payloads = revision_payloads(&mut File::open(last_volume(input_file))?)?;
+    payloads.reverse();
+
+    let mut history = vec![MetadataRevision {
+        revision: 0,
+        metadata: original,
+    }];
+    for (index, payload) in payloads.iter().enumerate() {
+        history.push(MetadataRevision {
+            revision: index + 1,
+            metadata: decode_metadata(payload, ignore_unknown)?,
+        });
+    }
+    Ok(history)
+}
+
+/// Read one metadata revision of a .pjz file
+/// Fails with [`ProjzstError::RevisionNotFound`] past the latest revision
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `revision` - Revision number, 0 for the metadata written when packing
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn read_metadata_revision<P: AsRef<Path>>(
+    input_file: P,
+    revision: usize,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    metadata_history(input_file, ignore_unknown)?
+        .into_iter()
+        .nth(revision)
+        .map(|revision| revision.metadata)
+        .ok_or(ProjzstError::RevisionNotFound(revision))
+}
+
+/// Internal helper: the latest appended revision of an archive file, if any
+pub(crate) fn latest_revision(
+    input_file: &Path,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Option<Metadata>> {
+    let Ok(mut file) = File::open(last_volume(input_file)) else {
+        return Ok(None);
+    };
+    match revision_payloads(&mut file)?.first() {
+        Some(payload) => Ok(Some(decode_metadata(payload, ignore_unknown)?)),
+        None => Ok(None),
+    }
+}
+
+/// Read the metadata trailer of a .pjz file, if it was packed with one
+/// Unlike [`read_metadata`](crate::read_metadata), gives the trailer even when the header
+/// has the metadata too, so both ends can be compared
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn trailing_metadata<P: AsRef<Path>>(
+    input_file: P,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Option<Metadata>> {
+    match trailer_bytes(input_file.as_ref())? {
+        Some(bytes) => Ok(Some(decode_metadata(
+            &decompress_metadata(bytes)?,
+            ignore_unknown,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// Internal helper: write the trailer frame holding `metadata_bytes`, encoded as in the
+/// header
+pub(crate) fn write_trailer<W: Write>(output: &mut W, metadata_bytes: &[u8]) -> Result<()> {
+    let mut payload = TRAILER_TAG.to_vec();
+    payload.extend_from_slice(metadata_bytes);
+    let size = u32::try_from(payload.len() + 4)
+        .map_err(|_| ProjzstError::FrameTooLarge(payload.len() + 4))?;
+    payload.extend_from_slice(&size.to_le_bytes());
+    write_frame_unchecked(output, METADATA_FRAME_MAGIC, &payload)
+}
+
+/// Internal helper: `reader` over the archive at `path`, with metadata frames made from
+/// its trailer put in front when the file starts right away with the payload
+/// Files starting with a frame, or without a trailer, read as they are
+pub(crate) fn restore_header(
+    path: &Path,
+    mut reader: Box<dyn Read + Send>,
+) -> Result<Box<dyn Read + Send>> {
+    let mut head = Vec::new();
+    reader.by_ref().take(4).read_to_end(&mut head)?;
+    let mut header = Vec::new();
+    if let Ok(magic) = <[u8; 4]>::try_from(head.as_slice()) {
+        let magic = u32::from_le_bytes(magic);
+        if !(SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&magic) {
+            if let Some(bytes) = trailer_bytes(path)? {
+                write_metadata_frame(&mut header, METADATA_FRAME_MAGIC, &bytes)?;
+            }
+        }
+    }
+    Ok(Box::new(
+        Cursor::new(header).chain(Cursor::new(head)).chain(reader),
+    ))
+}
+
+/// Internal helper: the metadata bytes of the trailer of an archive file, if any
+fn trailer_bytes(input_file: &Path) -> Result<Option<Vec<u8>>> {
+    let path = last_volume(input_file);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(trailing_payloads(&mut File::open(path)?)?
+        .into_iter()
+        .find_map(|payload| payload.strip_prefix(TRAILER_TAG).map(<[u8]>::to_vec)))
+}
+
+/// Internal helper: encoded metadata of the revision frames at the end of a file,
+/// newest first
+fn revision_payloads(file: &mut File) -> Result<Vec<Vec<u8>>> {
+    let mut payloads = trailing_payloads(file)?;
+    payloads.retain(|payload| !payload.starts_with(TRAILER_TAG));
+    Ok(payloads)
+}
+
+/// Internal helper: payloads of the metadata frames at the end of a file, revisions and
+/// trailer, newest first and without their size
+fn trailing_payloads(file: &mut File) -> Result<Vec<Vec<u8>>> {
+    let mut payloads = Vec::new();
+    let mut end = file.metadata()?.len();
+
+    while end >= 12 {
+        let mut size_buf = [0u8; 4];
+        file.seek(SeekFrom::Start(end - 4))?;
+        file.read_exact(&mut size_buf)?;
+        let size = u64::from(u32::from_le_bytes(size_buf));
+        if size < 4 || size - 4 > (MAX_METADATA_SIZE + TRAILER_TAG.len()) as u64 || size + 8 > end {
+            break;
+        }
+
+        // The frame header in front must agree, or this is the end of the payload
+        let start = end - 8 - size;
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut header)?;
+        if header[..4] != METADATA_FRAME_MAGIC.to_le_bytes() || header[4..] != size_buf {
+            break;
+        }
+
+        let mut payload = vec![0u8; size as usize - 4];
+        file.read_exact(&mut payload)?;
+        payloads.push(payload);
+        end = start;
+    }
+    Ok(payloads)
+}
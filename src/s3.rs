@@ -0,0 +1,1033 @@
+//! Minimal S3-compatible object storage client (feature `s3`)
+//!
+//! Only the handful of calls projzst needs are implemented (PutObject, the multipart
+//! upload family, ListObjectsV2 and GetObject), signed with AWS Signature Version 4.
+//! On top of them, objects under a prefix are packed into an archive, archives are
+//! unpacked into tagged objects, and [`S3Storage`] serves a bucket as a storage backend.
+//! Credentials and endpoint come from the standard AWS environment variables.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::builder::{open_archive, packer_for, Packer};
+use crate::concurrency::Concurrency;
+use crate::date::{civil_from_days, days_from_civil};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::report::PackReport;
+use crate::storage::{normalize_path, sibling_path, Storage, StorageEntry};
+use crate::string_utils::to_hex;
+use crate::tar_format::append_exact;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+
+/// URL scheme recognized as an S3 location
+const S3_SCHEME: &str = "s3://";
+
+/// Maximum number of tags S3 accepts on a single object
+const MAX_OBJECT_TAGS: usize = 10;
+/// Maximum length of a single tag value
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// Bucket and key prefix parsed from an `s3://bucket/prefix/` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct S3Location {
+    /// Bucket name
+    pub bucket: String,
+    /// Key prefix (empty, or ending with `/`)
+    pub prefix: String,
+}
+
+impl S3Location {
+    /// Whether a string looks like an `s3://` URL
+    pub fn is_s3_url(s: &str) -> bool {
+        s.starts_with(S3_SCHEME)
+    }
+
+    /// Parse an `s3://bucket/prefix/` URL
+    /// A missing trailing slash is added so the prefix always names a "directory"
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix(S3_SCHEME)
+            .ok_or_else(|| ProjzstError::Remote(format!("not an s3:// URL: {url}")))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(ProjzstError::Remote(format!(
+                "missing bucket in URL: {url}"
+            )));
+        }
+
+        let mut prefix = prefix.trim_start_matches('/').to_string();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix,
+        })
+    }
+
+    /// Object key for a path relative to the prefix
+    pub fn key(&self, relative: &str) -> String {
+        format!("{}{}", self.prefix, relative)
+    }
+
+    /// Object key for a file placed next to the prefix rather than inside it
+    /// (the object storage counterpart of "parent directory of the output")
+    pub fn sibling_key(&self, name: &str) -> String {
+        sibling_path(&self.prefix, name)
+    }
+}
+
+/// Map the well-known metadata fields to object tags
+/// Values are sanitized to the character set S3 accepts and truncated to its length limit
+fn metadata_tags(metadata: &Metadata) -> Vec<(String, String)> {
+    let fields = [
+        ("pjz-name", &metadata.name),
+        ("pjz-auth", &metadata.auth),
+        ("pjz-fmt", &metadata.fmt),
+        ("pjz-ed", &metadata.ed),
+        ("pjz-ver", &metadata.ver),
+        ("pjz-desc", &metadata.desc),
+    ];
+
+    fields
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value: String = value
+                .as_deref()?
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || " +-=._:/@".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .take(MAX_TAG_VALUE_LEN)
+                .collect();
+            Some((key.to_string(), value))
+        })
+        .take(MAX_OBJECT_TAGS)
+        .collect()
+}
+
+/// One entry of an object listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct S3Object {
+    /// Full object key
+    pub key: String,
+    /// Object size in bytes
+    pub size: u64,
+    /// Last modification time as Unix seconds
+    pub last_modified: u64,
+}
+
+/// S3 client configured from the environment
+///
+/// Recognized variables: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+/// `AWS_SESSION_TOKEN`, `AWS_REGION` / `AWS_DEFAULT_REGION` and
+/// `AWS_ENDPOINT_URL_S3` / `AWS_ENDPOINT_URL` (switches to path-style addressing,
+/// for MinIO and other S3-compatible services)
+#[derive(Debug, Clone)]
+pub struct S3Client {
+    agent: ureq::Agent,
+    endpoint: Option<String>,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Client {
+    /// Build a client from the standard AWS environment variables
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        let access_key = var("AWS_ACCESS_KEY_ID")
+            .ok_or_else(|| ProjzstError::Remote("AWS_ACCESS_KEY_ID is not set".to_string()))?;
+        let secret_key = var("AWS_SECRET_ACCESS_KEY")
+            .ok_or_else(|| ProjzstError::Remote("AWS_SECRET_ACCESS_KEY is not set".to_string()))?;
+
+        Ok(Self {
+            agent: ureq::Agent::new(),
+            endpoint: var("AWS_ENDPOINT_URL_S3")
+                .or_else(|| var("AWS_ENDPOINT_URL"))
+                .map(|e| e.trim_end_matches('/').to_string()),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            access_key,
+            secret_key,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    /// Upload a whole object in a single request
+    pub fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: &[u8],
+        tags: &[(String, String)],
+    ) -> Result<()> {
+        self.send("PUT", bucket, key, &[], &tagging_header(tags), body)?;
+        Ok(())
+    }
+
+    /// Start a multipart upload and return its upload id
+    pub fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        tags: &[(String, String)],
+    ) -> Result<String> {
+        let query = [("uploads".to_string(), String::new())];
+        let body = self.send("POST", bucket, key, &query, &tagging_header(tags), &[])?;
+        let response = body.into_string()?;
+        xml_element(&response, "UploadId")
+            .map(str::to_string)
+            .ok_or_else(|| ProjzstError::Remote(format!("no UploadId in response for {key}")))
+    }
+
+    /// Upload one part of a multipart upload and return its ETag
+    pub fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: &[u8],
+    ) -> Result<String> {
+        let query = [
+            ("partNumber".to_string(), part_number.to_string()),
+            ("uploadId".to_string(), upload_id.to_string()),
+        ];
+        let response = self.send("PUT", bucket, key, &query, &[], body)?;
+        response
+            .header("ETag")
+            .map(str::to_string)
+            .ok_or_else(|| ProjzstError::Remote(format!("no ETag for part {part_number} of {key}")))
+    }
+
+    /// Finish a multipart upload from `(part number, ETag)` pairs
+    pub fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (number, etag) in parts {
+            body.push_str(&format!(
+                "<Part><PartNumber>{number}</PartNumber><ETag>{etag}</ETag></Part>"
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let query = [("uploadId".to_string(), upload_id.to_string())];
+        self.send("POST", bucket, key, &query, &[], body.as_bytes())?;
+        Ok(())
+    }
+
+    /// Abandon a multipart upload so its parts stop accruing storage
+    pub fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        let query = [("uploadId".to_string(), upload_id.to_string())];
+        self.send("DELETE", bucket, key, &query, &[], &[])?;
+        Ok(())
+    }
+
+    /// List every object under a prefix, following continuation tokens
+    pub fn list_objects(&self, bucket: &str, prefix: &str) -> Result<Vec<S3Object>> {
+        let mut objects = Vec::new();
+        let mut continuation = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_string()),
+            ];
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token".to_string(), token));
+            }
+
+            let response = self.send("GET", bucket, "", &query, &[], &[])?;
+            let listing = response.into_string()?;
+
+            for contents in xml_elements(&listing, "Contents") {
+                let key = xml_element(contents, "Key").map(xml_unescape);
+                let size = xml_element(contents, "Size").and_then(|s| s.parse().ok());
+                if let (Some(key), Some(size)) = (key, size) {
+                    objects.push(S3Object {
+                        key,
+                        size,
+                        last_modified: xml_element(contents, "LastModified")
+                            .and_then(parse_timestamp)
+                            .unwrap_or(0),
+                    });
+                }
+            }
+
+            if xml_element(&listing, "IsTruncated") != Some("true") {
+                break;
+            }
+            continuation = xml_element(&listing, "NextContinuationToken").map(xml_unescape);
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Download an object as a stream
+    pub fn get_object(&self, bucket: &str, key: &str) -> Result<impl Read + Send> {
+        Ok(self.send("GET", bucket, key, &[], &[], &[])?.into_reader())
+    }
+
+    /// Internal helper: sign and send a request, mapping HTTP failures to errors
+    fn send(
+        &self,
+        method: &str,
+        bucket: &str,
+        key: &str,
+        query: &[(String, String)],
+        extra_headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<ureq::Response> {
+        let (base, host, path) = match &self.endpoint {
+            Some(endpoint) => {
+                let host = endpoint
+                    .split_once("://")
+                    .map_or(endpoint.as_str(), |(_, h)| h);
+                (
+                    endpoint.clone(),
+                    host.to_string(),
+                    format!("/{}/{}", bucket, uri_encode(key, false)),
+                )
+            }
+            None => {
+                let host = format!("{}.s3.{}.amazonaws.com", bucket, self.region);
+                (
+                    format!("https://{host}"),
+                    host,
+                    format!("/{}", uri_encode(key, false)),
+                )
+            }
+        };
+
+        let mut sorted_query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        sorted_query.sort();
+        let query_string = sorted_query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let (amz_date, date) = amz_timestamps(SystemTime::now());
+        let payload_hash = to_hex(&Sha256::digest(body));
+
+        let mut headers: Vec<(String, String)> = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        for (name, value) in extra_headers {
+            headers.push((name.to_lowercase(), value.trim().to_string()));
+        }
+        headers.sort();
+
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{k}:{v}\n")).collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{method}\n{path}\n{query_string}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            to_hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let k_date = hmac_sha256(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        let k_region = hmac_sha256(&k_date, self.region.as_bytes());
+        let k_service = hmac_sha256(&k_region, b"s3");
+        let k_signing = hmac_sha256(&k_service, b"aws4_request");
+        let signature = to_hex(&hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let url = if query_string.is_empty() {
+            format!("{base}{path}")
+        } else {
+            format!("{base}{path}?{query_string}")
+        };
+
+        let mut request = self
+            .agent
+            .request(method, &url)
+            .set("Authorization", &authorization);
+        for (name, value) in &headers {
+            if name != "host" {
+                request = request.set(name, value);
+            }
+        }
+
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(code, response)) => {
+                let detail = response.into_string().unwrap_or_default();
+                Err(ProjzstError::Remote(format!(
+                    "{method} s3://{bucket}/{key} returned HTTP {code}: {}",
+                    xml_element(&detail, "Message").unwrap_or(detail.trim())
+                )))
+            }
+            Err(e) => Err(ProjzstError::Remote(format!(
+                "{method} s3://{bucket}/{key} failed: {e}"
+            ))),
+        }
+    }
+}
+
+/// Internal helper: build the `x-amz-tagging` header for a set of tags
+fn tagging_header(tags: &[(String, String)]) -> Vec<(String, String)> {
+    if tags.is_empty() {
+        return Vec::new();
+    }
+    let value = tags
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true)))
+        .collect::<Vec<_>>()
+        .join("&");
+    vec![("x-amz-tagging".to_string(), value)]
+}
+
+/// Internal helper: extract the text of the first `<name>` element of an XML document
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+/// Internal helper: the text of every `<name>` element of an XML document
+fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(element) = xml_element(rest, name) {
+        found.push(element);
+        let consumed = element.as_ptr() as usize - rest.as_ptr() as usize + element.len();
+        rest = &rest[consumed..];
+    }
+    found
+}
+
+/// Internal helper: decode the predefined XML entities
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Internal helper: parse an ISO 8601 UTC timestamp (`2024-01-31T12:00:00.000Z`) to Unix seconds
+fn parse_timestamp(s: &str) -> Option<u64> {
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.split('-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: u8 = date.next()?.parse().ok()?;
+    let day: u8 = date.next()?.parse().ok()?;
+    let mut time = time.trim_end_matches('Z').split(':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: f64 = time.next()?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second as i64;
+    u64::try_from(secs).ok()
+}
+
+/// Internal helper: percent-encode per the SigV4 rules (`/` kept unless `encode_slash`)
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+/// Internal helper: HMAC-SHA256
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Internal helper: `(YYYYMMDDTHHMMSSZ, YYYYMMDD)` timestamps in UTC
+fn amz_timestamps(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+
+    let date = format!("{year:04}{month:02}{day:02}");
+    let stamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        rem / 3_600,
+        (rem % 3_600) / 60,
+        rem % 60
+    );
+    (stamp, date)
+}
+
+/// Objects larger than this are uploaded in parts of this size
+const S3_PART_SIZE: usize = 8 * 1024 * 1024;
+/// Number of parts of one multipart upload in flight at once
+const S3_UPLOAD_CONCURRENCY: usize = 8;
+
+/// Objects up to this size are downloaded in parallel batches; larger ones are streamed
+const S3_BUFFERED_OBJECT_SIZE: u64 = 8 * 1024 * 1024;
+/// Number of downloads in flight at once
+const S3_DOWNLOAD_CONCURRENCY: usize = 8;
+
+/// Pack every object under an S3 prefix into a .pjz file
+/// Object keys relative to the prefix become archive paths
+///
+/// # Arguments
+/// * `source_url` - Source as `s3://bucket/prefix/`
+/// * `output_file` - Output .pjz file path
+/// * `metadata` - Metadata to embed
+/// * `extra_file` - Optional extra metadata JSON file
+/// * `compression_level` - Zstd compression level
+pub fn pack_from_s3<P1, P2>(
+    source_url: &str,
+    output_file: P1,
+    metadata: Metadata,
+    extra_file: Option<P2>,
+    compression_level: i32,
+) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    packer_for(metadata, extra_file, compression_level)
+        .pack_from_s3(source_url, output_file)
+        .map(drop)
+}
+
+/// Pack every object under an S3 prefix into any writer (e.g. stdout)
+/// Behaves exactly like [`pack_from_s3`], but streams the archive to `writer`
+///
+/// # Arguments
+/// * `source_url` - Source as `s3://bucket/prefix/`
+/// * `writer` - Destination of the .pjz stream
+/// * `metadata` - Metadata to embed
+/// * `extra_file` - Optional extra metadata JSON file
+/// * `compression_level` - Zstd compression level
+pub fn pack_from_s3_to_writer<P, W>(
+    source_url: &str,
+    writer: W,
+    metadata: Metadata,
+    extra_file: Option<P>,
+    compression_level: i32,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    packer_for(metadata, extra_file, compression_level)
+        .pack_from_s3_to_writer(source_url, writer)
+        .map(drop)
+}
+
+impl Packer {
+    /// Pack every object under an S3 prefix (`s3://bucket/prefix/`) into a .pjz file;
+    /// returns what was stored
+    pub fn pack_from_s3<P: AsRef<Path>>(
+        &self,
+        source_url: &str,
+        output_file: P,
+    ) -> Result<PackReport> {
+        let output_file = output_file.as_ref();
+        self.check_overwrite(output_file)?;
+        if let Some(parent) = output_file.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        // Only a complete archive replaces the output file
+        let (mut output, temp) = temp_file_for(output_file)?;
+        let report = self.pack_from_s3_to_writer(source_url, &mut output)?;
+        self.persist(output, temp, output_file)?;
+        Ok(report)
+    }
+
+    /// Pack every object under an S3 prefix into any writer (e.g. stdout); returns what
+    /// was stored
+    pub fn pack_from_s3_to_writer<W: Write>(
+        &self,
+        source_url: &str,
+        mut writer: W,
+    ) -> Result<PackReport> {
+        let location = S3Location::parse(source_url)?;
+        let client = S3Client::from_env()?;
+
+        let objects: Vec<S3Object> = client
+            .list_objects(&location.bucket, &location.prefix)?
+            .into_iter()
+            // Zero-byte "directory" markers have no content of their own
+            .filter(|object| !object.key.ends_with('/'))
+            .collect();
+        if objects.is_empty() {
+            return Err(ProjzstError::SourceNotFound(source_url.to_string()));
+        }
+
+        let prefix = self.entry_prefix()?;
+        let prefix = prefix.as_deref();
+        let metadata_bytes = self.encoded_metadata()?;
+        self.write_header(&mut writer, &metadata_bytes)?;
+
+        let mut report = PackReport::default();
+        let mut zst_encoder = self.payload_encoder(&mut writer)?;
+        {
+            let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+            let mut pending: Vec<&S3Object> = Vec::new();
+
+            for object in &objects {
+                if object.size <= S3_BUFFERED_OBJECT_SIZE {
+                    pending.push(object);
+                    if pending.len() == S3_DOWNLOAD_CONCURRENCY {
+                        append_batch(
+                            &client,
+                            &location,
+                            prefix,
+                            &mut tar_builder,
+                            &pending,
+                            &mut report,
+                        )?;
+                        pending.clear();
+                    }
+                    continue;
+                }
+
+                // Keep archive order equal to listing order
+                append_batch(
+                    &client,
+                    &location,
+                    prefix,
+                    &mut tar_builder,
+                    &pending,
+                    &mut report,
+                )?;
+                pending.clear();
+                let body = client.get_object(&location.bucket, &object.key)?;
+                append_object(
+                    &location,
+                    prefix,
+                    &mut tar_builder,
+                    object,
+                    body,
+                    &mut report,
+                )?;
+            }
+            append_batch(
+                &client,
+                &location,
+                prefix,
+                &mut tar_builder,
+                &pending,
+                &mut report,
+            )?;
+            tar_builder.finish()?;
+        }
+        let (writer, compressed_size) = zst_encoder.finish_counted()?;
+        self.write_trailer(writer, &metadata_bytes)?;
+        writer.flush()?;
+        report.finish(compressed_size);
+
+        Ok(report)
+    }
+}
+
+/// Internal helper: download small objects concurrently, then append them in order
+fn append_batch<W: Write>(
+    client: &S3Client,
+    location: &S3Location,
+    prefix: Option<&str>,
+    tar_builder: &mut tar::Builder<W>,
+    batch: &[&S3Object],
+    report: &mut PackReport,
+) -> Result<()> {
+    let bodies: Vec<Result<Vec<u8>>> = thread::scope(|scope| {
+        let handles: Vec<_> = batch
+            .iter()
+            .map(|object| {
+                scope.spawn(move || -> Result<Vec<u8>> {
+                    let mut body = Vec::with_capacity(object.size as usize);
+                    client
+                        .get_object(&location.bucket, &object.key)?
+                        .read_to_end(&mut body)?;
+                    Ok(body)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("download worker panicked"))
+            .collect()
+    });
+
+    for (object, body) in batch.iter().zip(bodies) {
+        append_object(
+            location,
+            prefix,
+            tar_builder,
+            object,
+            body?.as_slice(),
+            report,
+        )?;
+    }
+    Ok(())
+}
+
+/// Internal helper: append one object as a regular file entry, under `prefix` when given,
+/// counting it in `report`
+fn append_object<W: Write, R: Read>(
+    location: &S3Location,
+    prefix: Option<&str>,
+    tar_builder: &mut tar::Builder<W>,
+    object: &S3Object,
+    body: R,
+    report: &mut PackReport,
+) -> Result<()> {
+    let relative = object
+        .key
+        .strip_prefix(&location.prefix)
+        .unwrap_or(&object.key);
+    let name = match prefix {
+        Some(prefix) => format!("{prefix}/{relative}"),
+        None => relative.to_string(),
+    };
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(object.size);
+    header.set_mode(0o644);
+    header.set_mtime(object.last_modified);
+    append_exact(tar_builder, &mut header, &name, body)?;
+    report.add_file(&name, object.size);
+    Ok(())
+}
+
+/// Work item handed to the upload workers
+enum UploadJob {
+    /// Whole object in a single PutObject call
+    Object { key: String, body: Vec<u8> },
+    /// One part of a multipart upload; the ETag is reported back on `done`
+    Part {
+        key: String,
+        upload_id: String,
+        number: u32,
+        body: Vec<u8>,
+        done: mpsc::Sender<Result<(u32, String)>>,
+    },
+}
+
+/// Unpack a .pjz file into an S3 bucket, one object per file entry
+/// Objects are tagged with the archive metadata and `metadata.json` is written
+/// next to the prefix, mirroring what [`crate::unpack`] does on disk; up to
+/// [`Concurrency::extraction_writers`] uploads are in flight at once
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+/// * `dest_url` - Destination as `s3://bucket/prefix/`
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn unpack_to_s3<P: AsRef<Path>>(
+    input_file: P,
+    dest_url: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let file = open_input(input_file.as_ref())?;
+    unpack_from_reader_to_s3(file, dest_url, ignore_unknown)
+}
+
+/// Unpack a .pjz stream (e.g. stdin) into an S3 bucket
+/// Behaves exactly like [`unpack_to_s3`], but reads the archive from `reader`
+///
+/// # Arguments
+/// * `reader` - Source of the .pjz stream
+/// * `dest_url` - Destination as `s3://bucket/prefix/`
+/// * `ignore_unknown` - How to handle unknown fields in metadata
+pub fn unpack_from_reader_to_s3<R: Read>(
+    reader: R,
+    dest_url: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let location = S3Location::parse(dest_url)?;
+    let client = S3Client::from_env()?;
+
+    let (metadata, mut tar_archive) = open_archive(reader, ignore_unknown)?;
+    let tags = metadata_tags(&metadata);
+
+    let writers = Concurrency::current_default().extraction_writers();
+    let (job_tx, job_rx) = mpsc::sync_channel::<UploadJob>(writers * 2);
+    let job_rx = Mutex::new(job_rx);
+    let first_error: Mutex<Option<ProjzstError>> = Mutex::new(None);
+
+    let result = thread::scope(|scope| -> Result<()> {
+        for _ in 0..writers {
+            scope.spawn(|| loop {
+                // Hold the lock only while receiving so workers run uploads concurrently
+                let job = match job_rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                match job {
+                    UploadJob::Object { key, body } => {
+                        if let Err(e) = client.put_object(&location.bucket, &key, &body, &tags) {
+                            first_error.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                    UploadJob::Part {
+                        key,
+                        upload_id,
+                        number,
+                        body,
+                        done,
+                    } => {
+                        let etag =
+                            client.upload_part(&location.bucket, &key, &upload_id, number, &body);
+                        let _ = done.send(etag.map(|etag| (number, etag)));
+                    }
+                }
+            });
+        }
+
+        // Dropping the sender on every exit path lets the workers drain and stop
+        let job_tx = job_tx;
+        for entry in tar_archive.entries()? {
+            if first_error.lock().unwrap().is_some() {
+                break;
+            }
+
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let Some(relative) = normalize_path(&entry.path()?) else {
+                continue;
+            };
+            let key = location.key(&relative);
+
+            if entry.size() as usize <= S3_PART_SIZE {
+                let mut body = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut body)?;
+                send_job(&job_tx, UploadJob::Object { key, body })?;
+                continue;
+            }
+
+            let upload_id = client.create_multipart_upload(&location.bucket, &key, &tags)?;
+            let (done_tx, done_rx) = mpsc::channel();
+            let mut number = 0;
+            let sent = loop {
+                let mut body = Vec::with_capacity(S3_PART_SIZE);
+                if let Err(e) = (&mut entry)
+                    .take(S3_PART_SIZE as u64)
+                    .read_to_end(&mut body)
+                {
+                    break Err(e.into());
+                }
+                if body.is_empty() {
+                    break Ok(());
+                }
+                number += 1;
+                let job = UploadJob::Part {
+                    key: key.clone(),
+                    upload_id: upload_id.clone(),
+                    number,
+                    body,
+                    done: done_tx.clone(),
+                };
+                if let Err(e) = send_job(&job_tx, job) {
+                    break Err(e);
+                }
+            };
+            drop(done_tx);
+
+            let parts = sent.and_then(|()| done_rx.iter().collect::<Result<Vec<_>>>());
+            let completed = parts.and_then(|mut parts| {
+                parts.sort();
+                client.complete_multipart_upload(&location.bucket, &key, &upload_id, &parts)
+            });
+            if let Err(e) = completed {
+                let _ = client.abort_multipart_upload(&location.bucket, &key, &upload_id);
+                return Err(e);
+            }
+        }
+        Ok(())
+    });
+
+    result?;
+    if let Some(e) = first_error.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    let json_content = serde_json::to_string_pretty(&metadata)?;
+    client.put_object(
+        &location.bucket,
+        &location.sibling_key("metadata.json"),
+        json_content.as_bytes(),
+        &[],
+    )?;
+
+    Ok(metadata)
+}
+
+/// Internal helper: queue an upload job, failing if the workers are gone
+fn send_job(job_tx: &mpsc::SyncSender<UploadJob>, job: UploadJob) -> Result<()> {
+    job_tx
+        .send(job)
+        .map_err(|_| ProjzstError::Remote("upload workers stopped unexpectedly".to_string()))
+}
+
+/// [`Storage`] backend over one S3 bucket
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    client: S3Client,
+    bucket: String,
+}
+
+impl S3Storage {
+    /// Create a backend for `bucket` using an existing client
+    pub fn new(client: S3Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+        }
+    }
+
+    /// Create a backend for `bucket` with a client configured from the environment
+    pub fn from_env(bucket: impl Into<String>) -> Result<Self> {
+        Ok(Self::new(S3Client::from_env()?, bucket))
+    }
+}
+
+impl Storage for S3Storage {
+    fn open(&self, path: &str) -> Result<Box<dyn Read + Send + '_>> {
+        Ok(Box::new(self.client.get_object(&self.bucket, path)?))
+    }
+
+    fn write(&self, path: &str, data: &mut dyn Read) -> Result<()> {
+        let first = read_part(data)?;
+        if first.len() < S3_PART_SIZE {
+            return self.client.put_object(&self.bucket, path, &first, &[]);
+        }
+
+        // Large stream: multipart upload with a bounded window of parts in flight
+        let upload_id = self
+            .client
+            .create_multipart_upload(&self.bucket, path, &[])?;
+        let upload_id = upload_id.as_str();
+        let uploaded = thread::scope(|scope| -> Result<Vec<(u32, String)>> {
+            let mut in_flight: VecDeque<thread::ScopedJoinHandle<'_, Result<(u32, String)>>> =
+                VecDeque::new();
+            let mut parts = Vec::new();
+            let mut body = first;
+            let mut number = 0;
+
+            while !body.is_empty() {
+                if in_flight.len() == S3_UPLOAD_CONCURRENCY {
+                    let handle = in_flight.pop_front().unwrap();
+                    parts.push(handle.join().expect("upload worker panicked")?);
+                }
+                number += 1;
+                let part_number = number;
+                in_flight.push_back(scope.spawn(move || {
+                    self.client
+                        .upload_part(&self.bucket, path, upload_id, part_number, &body)
+                        .map(|etag| (part_number, etag))
+                }));
+                body = read_part(data)?;
+            }
+
+            for handle in in_flight {
+                parts.push(handle.join().expect("upload worker panicked")?);
+            }
+            Ok(parts)
+        });
+
+        let completed = uploaded.and_then(|parts| {
+            self.client
+                .complete_multipart_upload(&self.bucket, path, upload_id, &parts)
+        });
+        if completed.is_err() {
+            let _ = self
+                .client
+                .abort_multipart_upload(&self.bucket, path, upload_id);
+        }
+        completed
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<StorageEntry>> {
+        let mut entries: Vec<StorageEntry> = self
+            .client
+            .list_objects(&self.bucket, prefix)?
+            .into_iter()
+            .filter(|object| !object.key.ends_with('/'))
+            .map(|object| StorageEntry {
+                path: object.key,
+                size: object.size,
+                mtime: object.last_modified,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+}
+
+/// Internal helper: read up to one part's worth of bytes from a stream
+fn read_part(data: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut part = Vec::with_capacity(S3_PART_SIZE);
+    data.take(S3_PART_SIZE as u64).read_to_end(&mut part)?;
+    Ok(part)
+}
@@ -0,0 +1,211 @@
+//! Sandboxed extraction
+//!
+//! With [`ReadOptions::sandboxed`](crate::ReadOptions::sandboxed), the output directory is
+//! opened once as a capability (a [`cap_std::fs::Dir`]) and every entry is created relative
+//! to that handle. Path resolution cannot leave the directory: on Linux the kernel resolves
+//! each path with `openat2` and `RESOLVE_BENEATH`, elsewhere `cap-std` walks it component by
+//! component under the handle. A `..`, an absolute path or a symlink planted by an earlier
+//! entry makes the entry fail instead of writing outside the output, whatever the path
+//! checks before it let through.
+//!
+//! Files are written from the decompressed stream; device nodes and FIFOs are never
+//! created, even under [`SpecialPolicy::Include`](crate::SpecialPolicy::Include).
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use cap_std::ambient_authority;
+use cap_std::fs::{Dir, OpenOptions};
+use filetime::FileTime;
+
+use crate::chunking::{chunk_digest, chunk_list, ChunkStore};
+use crate::errors::Result;
+use crate::mtime;
+use crate::reflink::set_mode;
+use crate::remap::{finish_payload, EntryAction, PathMap};
+use crate::report::{SpecialKind, UnpackReport};
+
+/// A directory created while unpacking, given its permissions and time at the end
+struct Directory {
+    path: String,
+    mode: u32,
+    mtime: Option<FileTime>,
+}
+
+/// Internal helper: extract a tar stream under a handle on `output_dir`, every entry path
+/// mapped by `map`; with `keep_going`, entries that cannot be extracted are recorded in
+/// `report` instead of failing
+pub(crate) fn unpack_sandboxed<R: Read>(
+    payload: R,
+    output_dir: &Path,
+    map: &PathMap,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    let root = Dir::open_ambient_dir(output_dir, ambient_authority())?;
+    let chunks = ChunkStore::with_store(output_dir, map.chunk_store.as_deref())?;
+    let mut tar_archive = tar::Archive::new(payload);
+    let mut directories = Vec::new();
+    let mut finished = true;
+    for entry in tar_archive.entries()? {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.payload_failed(e.into(), keep_going)?;
+                finished = false;
+                break;
+            }
+        };
+        let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        if let Some(digest) = chunk_digest(&entry) {
+            if let Err(e) = chunks.insert(digest, &mut entry) {
+                report.entry_failed(path, e, keep_going)?;
+            }
+            continue;
+        }
+        match map.action(&entry)? {
+            EntryAction::Extract => {}
+            EntryAction::Skip => continue,
+            EntryAction::Stop => {
+                finished = false;
+                break;
+            }
+        }
+        match unpack_entry(&root, &mut entry, map, &chunks, &mut directories) {
+            Ok(extracted) => report.extracted += usize::from(extracted),
+            Err(e) => {
+                let e = e.extracting(path.as_str());
+                report.entry_failed(path, e, keep_going)?;
+            }
+        }
+    }
+
+    // Deepest first, so setting a time is not undone by changes to a subdirectory
+    directories.sort_by(|a, b| b.path.cmp(&a.path));
+    for directory in directories {
+        if let Err(e) = finish_directory(&root, &directory) {
+            let e = e.extracting(directory.path.as_str());
+            report.entry_failed(directory.path, e, keep_going)?;
+        }
+    }
+    if finished {
+        finish_payload(tar_archive.into_inner(), keep_going, report)?;
+    }
+    Ok(())
+}
+
+/// Internal helper: create one entry under `root`, returning whether it was extracted
+/// Directories are created right away and queued in `directories` for their permissions
+fn unpack_entry<R: Read>(
+    root: &Dir,
+    entry: &mut tar::Entry<'_, R>,
+    map: &PathMap,
+    chunks: &ChunkStore,
+    directories: &mut Vec<Directory>,
+) -> Result<bool> {
+    let entry_type = entry.header().entry_type();
+    if let Some(kind) = SpecialKind::of_entry(entry_type) {
+        let path = entry.path()?.to_string_lossy().into_owned();
+        if map.special.keeps(&path, kind)? {
+            log::warn!("{path}: a {} is not created when sandboxed", kind.name());
+        }
+        return Ok(false);
+    }
+    let Some(mapped) = map.map(&entry.path()?)? else {
+        return Ok(false);
+    };
+    let mode = entry.header().mode()?;
+    let mtime = mtime::resolve(map.mtime, mtime::entry_mtime(entry)?);
+    if let Some(parent) = Path::new(&mapped)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        root.create_dir_all(parent)?;
+    }
+
+    if entry_type.is_dir() {
+        log::debug!("{mapped}/");
+        root.create_dir_all(&mapped)?;
+        directories.push(Directory {
+            path: mapped,
+            mode,
+            mtime,
+        });
+        return Ok(true);
+    }
+    if !(entry_type.is_file() || entry_type.is_symlink() || entry_type.is_hard_link()) {
+        log::debug!("skipped {mapped}");
+        return Ok(false);
+    }
+    // Replaced, never written through: the existing entry may be a symlink
+    if root
+        .symlink_metadata(&mapped)
+        .is_ok_and(|info| !info.is_dir())
+    {
+        root.remove_file(&mapped)?;
+    }
+
+    if entry_type.is_hard_link() {
+        // Hard links name another entry, which was mapped the same way
+        let link = entry.link_name()?.unwrap_or_default();
+        let Some(source) = map.map(&link)? else {
+            return Ok(false);
+        };
+        log::debug!("{mapped}");
+        root.hard_link(source, root, &mapped)?;
+        return Ok(true);
+    }
+    if entry_type.is_symlink() {
+        let target = entry.link_name()?.unwrap_or_default();
+        log::debug!("{mapped}");
+        symlink(root, &target, &mapped)?;
+        return Ok(true);
+    }
+
+    log::info!("{mapped} ({} bytes)", entry.size());
+    let mut file = root
+        .open_with(&mapped, OpenOptions::new().write(true).create_new(true))?
+        .into_std();
+    match chunk_list(entry)? {
+        Some(list) => chunks.write_into(&list, &mut file)?,
+        None => {
+            io::copy(entry, &mut file)?;
+        }
+    }
+    set_mode(&file, mode)?;
+    if let Some(mtime) = mtime {
+        filetime::set_file_handle_times(&file, None, Some(mtime))?;
+    }
+    if map.sync {
+        file.sync_all()?;
+    }
+    Ok(true)
+}
+
+/// Internal helper: give a directory its stored permissions and modification time
+fn finish_directory(root: &Dir, directory: &Directory) -> Result<()> {
+    // Opened for reading: `open_dir` handles are path-only on Linux, which cannot change
+    // permissions or times
+    let handle: File = root
+        .open_with(&directory.path, OpenOptions::new().read(true))?
+        .into_std();
+    set_mode(&handle, directory.mode)?;
+    if let Some(mtime) = directory.mtime {
+        filetime::set_file_handle_times(&handle, None, Some(mtime))?;
+    }
+    Ok(())
+}
+
+/// Internal helper: create a symlink under `root`, its target stored as it is
+/// Absolute targets are allowed, since they are never followed under `root`
+#[cfg(unix)]
+fn symlink(root: &Dir, target: &Path, link: &str) -> io::Result<()> {
+    root.symlink_contents(target, link)
+}
+
+/// Internal helper: create a file symlink under `root`
+#[cfg(windows)]
+fn symlink(root: &Dir, target: &Path, link: &str) -> io::Result<()> {
+    root.symlink_file(target, link)
+}
@@ -0,0 +1,493 @@
+//! Software bill of materials export
+//!
+//! [`sbom`] describes an archive as a CycloneDX 1.5 or SPDX 2.3 JSON document: the
+//! package itself from the metadata (with the SHA-256 of the archive file), every file
+//! entry with its hashes, and the dependencies declared in `deps`. The creation time
+//! follows `SOURCE_DATE_EPOCH` when set, so documents can be reproduced.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::builder::{open_archive, read_metadata};
+use crate::catalog::file_digest;
+use crate::concurrency::{pipelined_map, Concurrency};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::storage::normalize_path;
+use crate::string_utils::{to_hex, IntoOpStr};
+use crate::template::TemplateVars;
+use crate::volume::open_input;
+
+/// Document format of a bill of materials
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON (default)
+    #[default]
+    CycloneDx,
+    /// SPDX 2.3 JSON
+    Spdx,
+}
+
+impl SbomFormat {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "cyclonedx" | "cdx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(ProjzstError::InvalidSbomFormat(s.to_string())),
+        }
+    }
+
+    /// Name of the format as written in documents
+    pub fn name(self) -> &'static str {
+        match self {
+            SbomFormat::CycloneDx => "CycloneDX",
+            SbomFormat::Spdx => "SPDX",
+        }
+    }
+}
+
+/// One regular file of the archive, hashed
+struct FileHashes {
+    path: String,
+    size: u64,
+    sha1: String,
+    sha256: String,
+}
+
+/// Build a bill of materials for a .pjz file
+/// Regular files and hard links are listed; directories, symlinks and special entries are
+/// not. Packages without a name are named after the archive file
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file
+/// * `format` - Document format to produce
+pub fn sbom<P: AsRef<Path>>(input_file: P, format: SbomFormat) -> Result<Value> {
+    let input_file = input_file.as_ref();
+    let metadata = read_metadata(input_file, IgnoreUnknown::On)?;
+    let threads = Concurrency::current_default().hashing_threads();
+    // The archive file is hashed while its entries are
+    let (digest, files) = thread::scope(|scope| {
+        let digest = scope.spawn(|| file_digest(input_file));
+        let files = hash_files(input_file, threads);
+        (digest.join().expect("hashing thread panicked"), files)
+    });
+    let (digest, files) = (digest?, files?);
+    let archive_sha256 = digest.trim_start_matches("sha256:");
+    let name = metadata.name.clone().unwrap_or_else(|| {
+        input_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let created = TemplateVars::new()
+        .get("datetime")
+        .unwrap_or_default()
+        .to_string();
+
+    Ok(match format {
+        SbomFormat::CycloneDx => cyclonedx(&metadata, &name, archive_sha256, &files, &created),
+        SbomFormat::Spdx => spdx(&metadata, &name, archive_sha256, &files, &created),
+    })
+}
+
+/// Internal helper: the CycloneDX 1.5 document
+fn cyclonedx(
+    metadata: &Metadata,
+    name: &str,
+    archive_sha256: &str,
+    files: &[FileHashes],
+    created: &str,
+) -> Value {
+    let root_ref = match &metadata.ver {
+        Some(ver) => format!("{name}@{ver}"),
+        None => name.to_string(),
+    };
+    let mut root = json!({
+        "type": "application",
+        "bom-ref": root_ref,
+        "name": name,
+        "hashes": [{ "alg": "SHA-256", "content": archive_sha256 }],
+    });
+    set_some(&mut root, "version", metadata.ver.as_deref());
+    set_some(&mut root, "description", metadata.desc.as_deref());
+    set_some(&mut root, "author", metadata.auth.as_deref());
+    if let Some(license) = &metadata.license {
+        root["licenses"] = json!([{ "expression": license }]);
+    }
+    let references: Vec<Value> = [
+        ("website", &metadata.homepage),
+        ("vcs", &metadata.repository),
+    ]
+    .into_iter()
+    .filter_map(|(kind, url)| url.as_ref().map(|url| json!({ "type": kind, "url": url })))
+    .collect();
+    if !references.is_empty() {
+        root["externalReferences"] = Value::Array(references);
+    }
+
+    let mut components: Vec<Value> = files
+        .iter()
+        .map(|file| {
+            json!({
+                "type": "file",
+                "bom-ref": format!("file:{}", file.path),
+                "name": file.path,
+                "hashes": [
+                    { "alg": "SHA-1", "content": file.sha1 },
+                    { "alg": "SHA-256", "content": file.sha256 },
+                ],
+                "properties": [{ "name": "projzst:size", "value": file.size.to_string() }],
+            })
+        })
+        .collect();
+    let mut depends_on = Vec::new();
+    for dependency in &metadata.deps {
+        let dependency_ref = format!("dep:{}", dependency.name);
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": dependency_ref,
+            "name": dependency.name,
+            "properties": [{ "name": "projzst:requirement", "value": dependency.req }],
+        });
+        if let Some(hex) = dependency
+            .digest
+            .as_deref()
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+        {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": hex }]);
+        }
+        components.push(component);
+        depends_on.push(dependency_ref);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "projzst",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": root,
+        },
+        "components": components,
+        "dependencies": [{ "ref": root_ref, "dependsOn": depends_on }],
+    })
+}
+
+/// Internal helper: the SPDX 2.3 document
+fn spdx(
+    metadata: &Metadata,
+    name: &str,
+    archive_sha256: &str,
+    files: &[FileHashes],
+    created: &str,
+) -> Value {
+    const ROOT_ID: &str = "SPDXRef-Package";
+    let no_assertion =
+        |value: Option<&String>| value.map_or_else(|| "NOASSERTION".to_string(), String::clone);
+    let mut root = json!({
+        "SPDXID": ROOT_ID,
+        "name": name,
+        "downloadLocation": no_assertion(metadata.repository.as_ref()),
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": no_assertion(metadata.license.as_ref()),
+        "copyrightText": "NOASSERTION",
+        "checksums": [{ "algorithm": "SHA256", "checksumValue": archive_sha256 }],
+    });
+    set_some(&mut root, "versionInfo", metadata.ver.as_deref());
+    set_some(&mut root, "description", metadata.desc.as_deref());
+    set_some(&mut root, "homepage", metadata.homepage.as_deref());
+    if let Some(author) = &metadata.auth {
+        root["originator"] = json!(format!("Person: {author}"));
+    }
+
+    let mut packages = vec![root];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": ROOT_ID,
+    })];
+    let spdx_files: Vec<Value> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let id = format!("SPDXRef-File-{}", i + 1);
+            relationships.push(json!({
+                "spdxElementId": ROOT_ID,
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": id,
+            }));
+            json!({
+                "SPDXID": id,
+                "fileName": format!("./{}", file.path),
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": file.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": file.sha256 },
+                ],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            })
+        })
+        .collect();
+    for (i, dependency) in metadata.deps.iter().enumerate() {
+        let id = format!("SPDXRef-Dependency-{}", i + 1);
+        let mut package = json!({
+            "SPDXID": id,
+            "name": dependency.name,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+            "comment": format!("version requirement {}", dependency.req),
+        });
+        if let Some(hex) = dependency
+            .digest
+            .as_deref()
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+        {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": hex }]);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": ROOT_ID,
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": id,
+        }));
+    }
+
+    let document_name = match &metadata.ver {
+        Some(ver) => format!("{name}-{ver}"),
+        None => name.to_string(),
+    };
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": document_name,
+        // Unique per archive content, and stable across runs
+        "documentNamespace": format!("https://spdx.org/spdxdocs/projzst/{document_name}-{archive_sha256}"),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: projzst-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": spdx_files,
+        "relationships": relationships,
+    })
+}
+
+/// Internal helper: set `key` when `value` is present
+fn set_some(object: &mut Value, key: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        object[key] = json!(value);
+    }
+}
+
+/// Internal helper: SHA-1 and SHA-256 of every regular file and hard link, sorted by path
+/// Files are hashed on up to `threads` threads while the payload is decoded, except
+/// those over [`PIPELINED_FILE_SIZE`], hashed as they are decoded
+fn hash_files(input_file: &Path, threads: usize) -> Result<Vec<FileHashes>> {
+    let (_, mut tar_archive) = open_archive(open_input(input_file)?, IgnoreUnknown::On)?;
+    let hashed = pipelined_map(
+        threads,
+        |hash: &mut dyn FnMut(Pending)| {
+            for entry in tar_archive.entries()? {
+                let mut entry = entry?;
+                let entry_type = entry.header().entry_type();
+                let Some(path) = normalize_path(&entry.path()?) else {
+                    continue;
+                };
+                if entry_type.is_hard_link() {
+                    let target = entry.link_name()?.unwrap_or_default();
+                    hash(Pending::Link(
+                        path,
+                        normalize_path(&target).unwrap_or_default(),
+                    ));
+                } else if entry_type.is_file() && entry.size() > PIPELINED_FILE_SIZE {
+                    let mut hashers = (Sha1::default(), Sha256::new());
+                    let size = io::copy(&mut entry, &mut HashWriter(&mut hashers))?;
+                    hash(Pending::Hashed(file_hashes(path, size, hashers)));
+                } else if entry_type.is_file() {
+                    let mut content = Vec::with_capacity(entry.size() as usize);
+                    io::Read::read_to_end(&mut entry, &mut content)?;
+                    hash(Pending::Content(path, content));
+                }
+            }
+            Ok(())
+        },
+        |pending| match pending {
+            Pending::Content(path, content) => {
+                let mut hashers = (Sha1::default(), Sha256::new());
+                hashers.0.update(&content);
+                hashers.1.update(&content);
+                Pending::Hashed(file_hashes(path, content.len() as u64, hashers))
+            }
+            pending => pending,
+        },
+    )?;
+
+    let mut files: Vec<FileHashes> = Vec::new();
+    for pending in hashed {
+        match pending {
+            Pending::Hashed(file) => files.push(file),
+            Pending::Link(path, target) => {
+                // A hard link has the content of the file it names, stored earlier
+                if let Some(linked) = files.iter().find(|file| file.path == target) {
+                    files.push(FileHashes {
+                        path,
+                        size: linked.size,
+                        sha1: linked.sha1.clone(),
+                        sha256: linked.sha256.clone(),
+                    });
+                }
+            }
+            Pending::Content(..) => unreachable!("every content is hashed"),
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Files up to this size are read whole and hashed on the hashing threads
+const PIPELINED_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// An entry on its way through the hashing threads
+enum Pending {
+    /// A file read whole, to hash
+    Content(String, Vec<u8>),
+    /// A file hashed
+    Hashed(FileHashes),
+    /// A hard link and the path of the file it names
+    Link(String, String),
+}
+
+/// Internal helper: the hashes of a file of `size` bytes, from its finished hashers
+fn file_hashes(path: String, size: u64, hashers: (Sha1, Sha256)) -> FileHashes {
+    FileHashes {
+        path,
+        size,
+        sha1: to_hex(&hashers.0.finalize()),
+        sha256: to_hex(&hashers.1.finalize()),
+    }
+}
+
+/// Feeds both hashers at once
+struct HashWriter<'a>(&'a mut (Sha1, Sha256));
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 .0.update(buf);
+        self.0 .1.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// SHA-1 (FIPS 180-4), which SPDX 2.3 requires for every file; not used for security
+#[derive(Clone)]
+struct Sha1 {
+    state: [u32; 5],
+    block: [u8; 64],
+    block_len: usize,
+    length: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self {
+            state: [
+                0x6745_2301,
+                0xefcd_ab89,
+                0x98ba_dcfe,
+                0x1032_5476,
+                0xc3d2_e1f0,
+            ],
+            block: [0; 64],
+            block_len: 0,
+            length: 0,
+        }
+    }
+}
+
+impl Sha1 {
+    fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len == 64 {
+                let block = self.block;
+                self.compress(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    fn finalize(mut self) -> [u8; 20] {
+        let bit_length = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_length.to_be_bytes());
+        let mut digest = [0; 20];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! JSON Schema validation of `extra`
+//!
+//! Format owners describe what `extra` must hold for their `(fmt, ed)` pair with a JSON
+//! Schema. A [`SchemaRegistry`] maps pairs to schemas; [`crate::Packer::schemas`] checks the
+//! metadata before anything is written and [`crate::ReadOptions::schemas`] when reading.
+//!
+//! The validator covers the structural keywords: `type`, `enum`, `const`, `properties`,
+//! `required`, `additionalProperties`, `items`, `minItems`, `maxItems`, `uniqueItems`,
+//! `minLength`, `maxLength`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`,
+//! `allOf`, `anyOf`, `oneOf`, `not` and local `$ref`s (`#/$defs/...`). Other keywords,
+//! such as `pattern` and `format`, are ignored.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::Metadata;
+use crate::string_utils::IntoOpStr;
+
+/// Deepest `$ref` chain followed before a schema is taken for cyclic
+const MAX_REF_DEPTH: usize = 64;
+
+/// One place where `extra` does not match its schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value inside `extra` (empty for `extra` itself)
+    pub path: String,
+    /// What the schema expected
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "extra{}: {}", self.path, self.message)
+    }
+}
+
+/// A JSON Schema for `extra`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraSchema {
+    root: Value,
+}
+
+impl ExtraSchema {
+    /// Use a schema already parsed
+    /// Fails with [`ProjzstError::InvalidSchema`] unless it is an object or a boolean
+    pub fn from_value(schema: Value) -> Result<Self> {
+        if !schema.is_object() && !schema.is_boolean() {
+            return Err(ProjzstError::InvalidSchema(
+                "a schema must be an object or a boolean".to_string(),
+            ));
+        }
+        Ok(Self { root: schema })
+    }
+
+    /// Load a schema from a JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let schema = serde_json::from_str(&content)
+            .map_err(|e| ProjzstError::InvalidSchema(format!("{}: {e}", path.display())))?;
+        Self::from_value(schema)
+    }
+
+    /// Every place where `extra` does not match this schema; empty when it does
+    pub fn validate(&self, extra: &Value) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.check(&self.root, extra, "", 0, &mut violations);
+        violations
+    }
+
+    /// Internal helper: check `value` at `path` against `schema`
+    fn check(
+        &self,
+        schema: &Value,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let mut fail = |message: String| {
+            out.push(SchemaViolation {
+                path: path.to_string(),
+                message,
+            })
+        };
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return fail("no value is allowed here".to_string()),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.resolve(reference) {
+                Some(_) if depth >= MAX_REF_DEPTH => {
+                    fail(format!("$ref {reference:?} nests too deeply"))
+                }
+                Some(target) => self.check(target, value, path, depth + 1, out),
+                None => fail(format!("$ref {reference:?} does not resolve")),
+            }
+            return;
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+                return fail(format!(
+                    "expected {}, found {}",
+                    types.join(" or "),
+                    type_name(value)
+                ));
+            }
+        }
+        if let Some(Value::Array(allowed)) = schema.get("enum") {
+            if !allowed.contains(value) {
+                fail(format!(
+                    "{value} is not one of {}",
+                    Value::Array(allowed.clone())
+                ));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                fail(format!("expected {expected}, found {value}"));
+            }
+        }
+
+        match value {
+            Value::Object(map) => self.check_object(schema, map, path, depth, out),
+            Value::Array(items) => self.check_array(schema, items, path, depth, out),
+            Value::String(s) => {
+                let len = s.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                    if len < min {
+                        fail(format!("shorter than {min} characters"));
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                    if len > max {
+                        fail(format!("longer than {max} characters"));
+                    }
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or(f64::NAN);
+                let bound = |key: &str| schema.get(key).and_then(Value::as_f64);
+                if bound("minimum").is_some_and(|min| n < min) {
+                    fail(format!("less than the minimum {}", schema["minimum"]));
+                }
+                if bound("maximum").is_some_and(|max| n > max) {
+                    fail(format!("greater than the maximum {}", schema["maximum"]));
+                }
+                if bound("exclusiveMinimum").is_some_and(|min| n <= min) {
+                    fail(format!("not greater than {}", schema["exclusiveMinimum"]));
+                }
+                if bound("exclusiveMaximum").is_some_and(|max| n >= max) {
+                    fail(format!("not less than {}", schema["exclusiveMaximum"]));
+                }
+            }
+            _ => {}
+        }
+
+        self.check_combinators(schema, value, path, depth, out);
+    }
+
+    /// Internal helper: `properties`, `required` and `additionalProperties`
+    fn check_object(
+        &self,
+        schema: &serde_json::Map<String, Value>,
+        map: &serde_json::Map<String, Value>,
+        path: &str,
+        depth: usize,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !map.contains_key(key) {
+                    out.push(SchemaViolation {
+                        path: path.to_string(),
+                        message: format!("missing required property {key:?}"),
+                    });
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let additional = schema.get("additionalProperties");
+        for (key, item) in map {
+            let item_path = format!("{path}/{}", escape_pointer(key));
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => self.check(property, item, &item_path, depth, out),
+                None => match additional {
+                    Some(Value::Bool(false)) => out.push(SchemaViolation {
+                        path: item_path,
+                        message: "property is not allowed".to_string(),
+                    }),
+                    Some(additional) => self.check(additional, item, &item_path, depth, out),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    /// Internal helper: `items`, `minItems`, `maxItems` and `uniqueItems`
+    fn check_array(
+        &self,
+        schema: &serde_json::Map<String, Value>,
+        items: &[Value],
+        path: &str,
+        depth: usize,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let mut fail = |message: String| {
+            out.push(SchemaViolation {
+                path: path.to_string(),
+                message,
+            })
+        };
+        let len = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            if len < min {
+                fail(format!("fewer than {min} items"));
+            }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            if len > max {
+                fail(format!("more than {max} items"));
+            }
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let duplicate = items
+                .iter()
+                .enumerate()
+                .any(|(i, item)| items[..i].contains(item));
+            if duplicate {
+                fail("items are not unique".to_string());
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                self.check(item_schema, item, &format!("{path}/{index}"), depth, out);
+            }
+        }
+    }
+
+    /// Internal helper: `allOf`, `anyOf`, `oneOf` and `not`
+    fn check_combinators(
+        &self,
+        schema: &serde_json::Map<String, Value>,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let matches = |sub: &Value| {
+            let mut violations = Vec::new();
+            self.check(sub, value, path, depth, &mut violations);
+            violations.is_empty()
+        };
+        let fail = |out: &mut Vec<SchemaViolation>, message: &str| {
+            out.push(SchemaViolation {
+                path: path.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for sub in all {
+                self.check(sub, value, path, depth, out);
+            }
+        }
+        if let Some(Value::Array(any)) = schema.get("anyOf") {
+            if !any.iter().any(matches) {
+                fail(out, "matches none of the anyOf schemas");
+            }
+        }
+        if let Some(Value::Array(one)) = schema.get("oneOf") {
+            match one.iter().filter(|sub| matches(sub)).count() {
+                1 => {}
+                0 => fail(out, "matches none of the oneOf schemas"),
+                _ => fail(out, "matches more than one of the oneOf schemas"),
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if matches(not) {
+                fail(out, "matches the schema under not");
+            }
+        }
+    }
+
+    /// Internal helper: the subschema a local `$ref` (`#`, `#/$defs/name`) points to
+    fn resolve(&self, reference: &str) -> Option<&Value> {
+        let pointer = reference.strip_prefix('#')?;
+        self.root.pointer(pointer)
+    }
+}
+
+/// Schemas for `extra`, keyed by the `(fmt, ed)` pair of the metadata
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<(String, Option<String>), ExtraSchema>,
+    fallback: Option<ExtraSchema>,
+}
+
+impl SchemaRegistry {
+    /// Create an empty registry, which accepts any `extra`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the schema for a format edition; with `ed` left out, for every edition
+    /// of `fmt` without a schema of its own
+    pub fn register<I: IntoOpStr>(
+        mut self,
+        fmt: impl Into<String>,
+        ed: I,
+        schema: ExtraSchema,
+    ) -> Self {
+        self.schemas.insert((fmt.into(), ed.into_op_str()), schema);
+        self
+    }
+
+    /// Schema for metadata whose format has no registered schema
+    pub fn fallback(mut self, schema: ExtraSchema) -> Self {
+        self.fallback = Some(schema);
+        self
+    }
+
+    /// The schema that applies to `metadata`: its exact `(fmt, ed)` pair first, then
+    /// its `fmt` for any edition, then the fallback
+    pub fn schema_for(&self, metadata: &Metadata) -> Option<&ExtraSchema> {
+        let fmt = metadata.fmt.clone().unwrap_or_default();
+        self.schemas
+            .get(&(fmt.clone(), metadata.ed.clone()))
+            .or_else(|| self.schemas.get(&(fmt, None)))
+            .or(self.fallback.as_ref())
+    }
+
+    /// Check the `extra` of `metadata` against the schema that applies to it
+    /// Fails with [`ProjzstError::SchemaViolations`] listing every mismatch
+    pub fn validate(&self, metadata: &Metadata) -> Result<()> {
+        let Some(schema) = self.schema_for(metadata) else {
+            return Ok(());
+        };
+        let violations = schema.validate(&metadata.extra);
+        if !violations.is_empty() {
+            return Err(ProjzstError::SchemaViolations(violations));
+        }
+        Ok(())
+    }
+}
+
+/// Internal helper: whether `value` is of the JSON Schema type `name`
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        _ => type_name(value) == name,
+    }
+}
+
+/// Internal helper: JSON Schema type name of a value
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Internal helper: escape a key for use in a JSON pointer
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
@@ -0,0 +1,320 @@
+//! Metadata search across archives
+//!
+//! A [`MetadataQuery`] is a list of predicates on metadata fields, all of which must
+//! hold: `name=foo ver>=1.2 extra.build.ci.provider~=github`. [`search`] applies it to
+//! every archive of a catalog written by [`build_catalog`](crate::build_catalog), or of
+//! a directory tree scanned on the spot.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::builder::read_metadata;
+use crate::catalog::{find_archives, relative_path, Catalog};
+use crate::deps::Version;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+
+/// Comparison operators, longest first so `>=` is not read as `>`
+const OPERATORS: [(&str, Operator); 7] = [
+    ("!=", Operator::NotEqual),
+    (">=", Operator::GreaterOrEqual),
+    ("<=", Operator::LessOrEqual),
+    ("~=", Operator::Contains),
+    ("=", Operator::Equal),
+    (">", Operator::Greater),
+    ("<", Operator::Less),
+];
+
+/// Metadata fields a query path can start with
+const FIELDS: [&str; 12] = [
+    "name",
+    "auth",
+    "fmt",
+    "ed",
+    "ver",
+    "desc",
+    "extra",
+    "license",
+    "homepage",
+    "repository",
+    "keywords",
+    "deps",
+];
+
+/// Predicates on metadata fields, all of which must hold for a match
+///
+/// The query is a whitespace-separated list of `field OP value`, where `field` is a
+/// metadata field (`name`, `ver`, `license`, `keywords`, ...) or a dotted path below one,
+/// such as `extra.build.ci.provider`, `extra.tags.0` or `deps.0.name`. Operators:
+///
+/// * `=` / `!=` - equal / not equal
+/// * `>=`, `<=`, `>`, `<` - ordering: `ver` by Semantic Versioning (missing minor and
+///   patch numbers count as `0`), other fields as numbers when both sides are numbers,
+///   as text otherwise
+/// * `~=` - contains, ignoring case
+///
+/// A field alone (`extra.ci`) only requires it to be set. Values with spaces are
+/// double-quoted: `desc~="command line"`. A predicate on an array (`keywords=cli`)
+/// holds when it holds for any of its items; unset fields and empty arrays
+/// only satisfy `!=`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataQuery {
+    predicates: Vec<Predicate>,
+}
+
+/// One `field OP value` of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Predicate {
+    field: String,
+    /// Operator and value, `None` for a presence check
+    test: Option<(Operator, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Contains,
+}
+
+/// An archive matching a query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct SearchHit {
+    /// Archive path relative to the catalog's directory or the directory scanned
+    pub path: String,
+    /// Digest of the archive file (`sha256:<hex>`), known when searching a catalog
+    pub digest: Option<String>,
+    /// Latest metadata of the archive
+    pub metadata: Metadata,
+}
+
+impl MetadataQuery {
+    /// Parse a query such as `name=foo ver>=1.2`
+    /// Fails with [`ProjzstError::InvalidQuery`] on an unknown field or an empty value
+    pub fn parse(query: &str) -> Result<Self> {
+        let invalid = |reason: String| ProjzstError::InvalidQuery {
+            query: query.to_string(),
+            reason,
+        };
+        let predicates = split_terms(query)
+            .map_err(invalid)?
+            .into_iter()
+            .map(|term| {
+                let split = term.find(['=', '!', '<', '>', '~']);
+                let (field, test) = match split {
+                    None => (term.as_str(), None),
+                    Some(position) => {
+                        let (field, rest) = term.split_at(position);
+                        let (symbol, operator) = OPERATORS
+                            .iter()
+                            .find(|(symbol, _)| rest.starts_with(symbol))
+                            .ok_or_else(|| invalid(format!("unknown operator in {term:?}")))?;
+                        (field, Some((*operator, rest[symbol.len()..].to_string())))
+                    }
+                };
+                if !is_field_path(field) {
+                    return Err(invalid(format!("unknown field {field:?}")));
+                }
+                if test.as_ref().is_some_and(|(_, value)| value.is_empty()) {
+                    return Err(invalid(format!("no value in {term:?}")));
+                }
+                Ok(Predicate {
+                    field: field.to_string(),
+                    test,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { predicates })
+    }
+
+    /// Whether `metadata` satisfies every predicate
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let Ok(metadata) = serde_json::to_value(metadata) else {
+            return false;
+        };
+        self.predicates
+            .iter()
+            .all(|predicate| predicate.matches(&metadata))
+    }
+}
+
+impl Predicate {
+    /// Internal helper: test one predicate against the metadata as JSON
+    fn matches(&self, metadata: &Value) -> bool {
+        let value = lookup(metadata, &self.field)
+            .filter(|value| !value.is_null() && value.as_array().is_none_or(|a| !a.is_empty()));
+        let Some((operator, expected)) = &self.test else {
+            return value.is_some();
+        };
+        let Some(value) = value else {
+            return *operator == Operator::NotEqual;
+        };
+        let candidates: Vec<&Value> = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        let test = |candidate: &&Value| {
+            let actual = match candidate {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                // Objects equal no value
+                _ => return *operator == Operator::NotEqual,
+            };
+            let ordering = || compare(&self.field, &actual, expected);
+            match operator {
+                Operator::Equal => actual == *expected,
+                Operator::NotEqual => actual != *expected,
+                Operator::Greater => ordering() == Ordering::Greater,
+                Operator::GreaterOrEqual => ordering() != Ordering::Less,
+                Operator::Less => ordering() == Ordering::Less,
+                Operator::LessOrEqual => ordering() != Ordering::Greater,
+                Operator::Contains => actual.to_lowercase().contains(&expected.to_lowercase()),
+            }
+        };
+        // `!=` must hold for every item, everything else for any
+        if *operator == Operator::NotEqual {
+            candidates.iter().all(test)
+        } else {
+            candidates.iter().any(test)
+        }
+    }
+}
+
+impl Metadata {
+    /// Value of one field, given as a path like the fields of a [`MetadataQuery`]
+    /// (`ver`, `extra.build.commit`, `keywords.0`), `None` when it is unset
+    /// A leading `.`, as jq writes paths, is accepted; fails with
+    /// [`ProjzstError::InvalidQuery`] on an unknown field
+    ///
+    /// # Arguments
+    /// * `path` - Dotted path of the field
+    pub fn select(&self, path: &str) -> Result<Option<Value>> {
+        let field = path.strip_prefix('.').unwrap_or(path);
+        if !is_field_path(field) {
+            return Err(ProjzstError::InvalidQuery {
+                query: path.to_string(),
+                reason: format!("unknown field {field:?}"),
+            });
+        }
+        let metadata = serde_json::to_value(self)?;
+        Ok(lookup(&metadata, field)
+            .filter(|value| !value.is_null())
+            .cloned())
+    }
+}
+
+/// Archives matching a query, in path order
+/// A directory is scanned, reading the metadata of every .pjz file below it (archives
+/// that cannot be read are skipped); any other path is read as a catalog file
+///
+/// # Arguments
+/// * `source` - Catalog file written by [`build_catalog`](crate::build_catalog), or a
+///   directory of archives
+/// * `query` - Predicates the metadata must satisfy
+pub fn search<P: AsRef<Path>>(source: P, query: &MetadataQuery) -> Result<Vec<SearchHit>> {
+    let source = source.as_ref();
+    if !source.is_dir() {
+        return Ok(Catalog::load(source)?
+            .archives
+            .into_iter()
+            .filter(|entry| query.matches(&entry.metadata))
+            .map(|entry| SearchHit {
+                path: entry.path,
+                digest: Some(entry.digest),
+                metadata: entry.metadata,
+            })
+            .collect());
+    }
+
+    let mut files = Vec::new();
+    find_archives(source, &mut files)?;
+    let mut hits = Vec::new();
+    for file in files {
+        let path = relative_path(source, &file);
+        match read_metadata(&file, IgnoreUnknown::On) {
+            Ok(metadata) if query.matches(&metadata) => hits.push(SearchHit {
+                path,
+                digest: None,
+                metadata,
+            }),
+            Ok(_) => {}
+            Err(e) => log::debug!("{path}: skipped, {e}"),
+        }
+    }
+    hits.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(hits)
+}
+
+/// Internal helper: whether `field` is a metadata field or a dotted path below one
+fn is_field_path(field: &str) -> bool {
+    let root = field.split('.').next().unwrap_or_default();
+    FIELDS.contains(&root) && !field.split('.').any(str::is_empty)
+}
+
+/// Internal helper: the value at a dotted path below the metadata as JSON, array items
+/// taken by index
+fn lookup<'a>(metadata: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(metadata, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            value => value.get(key),
+        })
+}
+
+/// Internal helper: split a query on whitespace outside double quotes, dropping quotes
+fn split_terms(query: &str) -> std::result::Result<Vec<String>, String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if quoted {
+        return Err("unmatched double quote".to_string());
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    if terms.is_empty() {
+        return Err("no predicates".to_string());
+    }
+    Ok(terms)
+}
+
+/// Internal helper: order two field values, see [`MetadataQuery`]
+fn compare(field: &str, actual: &str, expected: &str) -> Ordering {
+    if field == "ver" {
+        if let (Some(actual), Some(expected)) = (padded_version(actual), padded_version(expected)) {
+            return actual.cmp(&expected);
+        }
+    }
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(actual), Ok(expected)) => actual.total_cmp(&expected),
+        _ => actual.cmp(expected),
+    }
+}
+
+/// Internal helper: parse a version, completing `1` and `1.2` to `1.0.0` and `1.2.0`
+fn padded_version(version: &str) -> Option<Version> {
+    let split = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(split);
+    let missing = 2usize.checked_sub(core.matches('.').count())?;
+    Version::parse(&format!("{core}{}{suffix}", ".0".repeat(missing))).ok()
+}
@@ -0,0 +1,242 @@
+//! Credential scanning before packing
+//!
+//! With [`Packer::secret_scan`](crate::Packer::secret_scan), every file about to be packed
+//! is checked for common credentials before anything is written: AWS access key ids,
+//! GitHub tokens, PEM private key headers, and `.env` files by name. Findings either fail
+//! the pack ([`SecretPolicy::Error`]) or are listed in
+//! [`PackReport::secrets`](crate::PackReport::secrets) ([`SecretPolicy::Warn`]). The
+//! patterns are deliberately narrow: a hit is nearly always a real credential, at the cost
+//! of missing the formats nobody can recognize without context.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::builder::SourceRoot;
+use crate::concurrency::parallel_map;
+use crate::errors::{ProjzstError, Result};
+use crate::string_utils::IntoOpStr;
+use crate::walk::{walk_source, ContentRewrite, WalkOptions};
+
+/// Largest file scanned; bigger files are data, not configuration
+const MAX_SCANNED_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Prefixes of AWS access key ids (long-term and temporary)
+const AWS_KEY_PREFIXES: &[&str] = &["AKIA", "ASIA"];
+
+/// Prefixes of GitHub personal, OAuth, user-to-server, server-to-server and refresh tokens
+const GITHUB_TOKEN_PREFIXES: &[&str] = &["ghp_", "gho_", "ghu_", "ghs_", "ghr_"];
+
+/// Suffixes of `.env` files meant to be shipped, holding placeholders only
+const ENV_TEMPLATE_SUFFIXES: &[&str] = &[".example", ".sample", ".template", ".dist"];
+
+/// What to do with files that look like they contain credentials when packing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SecretPolicy {
+    /// Do not scan (default)
+    #[default]
+    Off,
+    /// Pack anyway, listing the findings in [`PackReport::secrets`](crate::PackReport::secrets)
+    Warn,
+    /// Fail with [`ProjzstError::SecretsFound`] before anything is written
+    Error,
+}
+
+impl SecretPolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "off" => Ok(SecretPolicy::Off),
+            "warn" => Ok(SecretPolicy::Warn),
+            "error" => Ok(SecretPolicy::Error),
+            _ => Err(ProjzstError::InvalidSecretPolicy(s.to_string())),
+        }
+    }
+}
+
+/// Kinds of credentials recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SecretKind {
+    /// AWS access key id (`AKIA...`, `ASIA...`)
+    AwsAccessKey,
+    /// GitHub token (`ghp_...` and the like)
+    GithubToken,
+    /// PEM private key (`-----BEGIN ... PRIVATE KEY-----`)
+    PrivateKey,
+    /// `.env` file, which holds environment secrets by convention
+    EnvFile,
+}
+
+impl SecretKind {
+    /// Human-readable name, e.g. `AWS access key`
+    pub fn name(self) -> &'static str {
+        match self {
+            SecretKind::AwsAccessKey => "AWS access key",
+            SecretKind::GithubToken => "GitHub token",
+            SecretKind::PrivateKey => "private key",
+            SecretKind::EnvFile => ".env file",
+        }
+    }
+}
+
+/// A file that looks like it contains a credential
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SecretFinding {
+    /// Path the file has in the archive
+    pub path: String,
+    /// What was found
+    pub kind: SecretKind,
+    /// Line of the first match, counted from 1; `None` for findings by file name
+    pub line: Option<usize>,
+}
+
+impl SecretFinding {
+    /// Internal helper: `path:line (kind)`, as listed in errors
+    pub(crate) fn describe(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{line} ({})", self.path, self.kind.name()),
+            None => format!("{} ({})", self.path, self.kind.name()),
+        }
+    }
+}
+
+/// Internal helper: scan every file below the roots on up to `threads` threads
+/// Files are checked as they would be stored, after the rewrite of `options` if any
+pub(crate) fn scan_sources(
+    roots: &[SourceRoot],
+    options: &WalkOptions,
+    threads: usize,
+) -> Result<Vec<SecretFinding>> {
+    let mut files: Vec<(String, PathBuf)> = Vec::new();
+    for root in roots {
+        for (relative, path, info) in
+            walk_source(&root.path, root.prefix.as_deref(), options)?.entries
+        {
+            if info.is_file() {
+                files.push((relative, path));
+            }
+        }
+    }
+    let rewrite = options.rewrite.as_ref();
+    let mut findings = Vec::new();
+    for found in parallel_map(&files, threads, |(relative, path)| {
+        scan_file(relative, path, rewrite)
+    }) {
+        findings.extend(found?);
+    }
+    Ok(findings)
+}
+
+/// Internal helper: the credentials in one file, at most one finding per kind
+fn scan_file(
+    relative: &str,
+    path: &Path,
+    rewrite: Option<&ContentRewrite>,
+) -> Result<Vec<SecretFinding>> {
+    let mut findings = Vec::new();
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    if is_env_file(name) {
+        findings.push(SecretFinding {
+            path: relative.to_string(),
+            kind: SecretKind::EnvFile,
+            line: None,
+        });
+    }
+
+    let file = fs::File::open(path).map_err(|e| ProjzstError::from(e).packing(path))?;
+    let mut content = Vec::new();
+    file.take(MAX_SCANNED_SIZE + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| ProjzstError::from(e).packing(path))?;
+    if content.len() as u64 > MAX_SCANNED_SIZE {
+        return Ok(findings);
+    }
+    if let Some(ContentRewrite(rewrite)) = rewrite {
+        content = rewrite(relative, &content).unwrap_or(content);
+    }
+    for (index, line) in content.split(|&b| b == b'\n').enumerate() {
+        for kind in scan_line(line) {
+            if findings.iter().all(|finding| finding.kind != kind) {
+                findings.push(SecretFinding {
+                    path: relative.to_string(),
+                    kind,
+                    line: Some(index + 1),
+                });
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// Internal helper: whether a file name is that of a `.env` file holding real values
+fn is_env_file(name: &str) -> bool {
+    match name.strip_prefix(".env") {
+        Some("") => true,
+        Some(rest) => {
+            rest.starts_with('.') && !ENV_TEMPLATE_SUFFIXES.iter().any(|s| rest.ends_with(s))
+        }
+        None => false,
+    }
+}
+
+/// Internal helper: the kinds of credentials on one line
+fn scan_line(line: &[u8]) -> Vec<SecretKind> {
+    let mut kinds = Vec::new();
+    if AWS_KEY_PREFIXES.iter().any(|prefix| {
+        has_token(line, prefix.as_bytes(), 16, |b| {
+            b.is_ascii_uppercase() || b.is_ascii_digit()
+        })
+    }) {
+        kinds.push(SecretKind::AwsAccessKey);
+    }
+    if GITHUB_TOKEN_PREFIXES
+        .iter()
+        .any(|prefix| has_token(line, prefix.as_bytes(), 36, |b| b.is_ascii_alphanumeric()))
+    {
+        kinds.push(SecretKind::GithubToken);
+    }
+    if let Some(start) = find(line, b"-----BEGIN ") {
+        if find(&line[start..], b"PRIVATE KEY").is_some() {
+            kinds.push(SecretKind::PrivateKey);
+        }
+    }
+    kinds
+}
+
+/// Internal helper: whether `line` holds `prefix` followed by exactly `len` bytes
+/// accepted by `accepts`, as a word of its own
+fn has_token(line: &[u8], prefix: &[u8], len: usize, accepts: impl Fn(u8) -> bool) -> bool {
+    let is_word = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+    let mut offset = 0;
+    while let Some(found) = find(&line[offset..], prefix) {
+        let start = offset + found;
+        let body = start + prefix.len();
+        let end = body + len;
+        let bounded_before = start == 0 || !is_word(&line[start - 1]);
+        let bounded_after = line.get(end).is_none_or(|b| !is_word(b));
+        if bounded_before
+            && bounded_after
+            && line.len() >= end
+            && line[body..end].iter().all(|&b| accepts(b))
+        {
+            return true;
+        }
+        offset = start + 1;
+    }
+    false
+}
+
+/// Internal helper: position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,247 @@
+//! Write verification for unpacking
+//!
+//! With [`ReadOptions::post_verify`](crate::ReadOptions::post_verify), the digest of every
+//! file is taken from the payload while it is extracted, and the written tree is hashed
+//! again afterwards. A mismatch means the bytes on disk are not the bytes in the archive
+//! (bit-rot on write, a filesystem silently truncating files), which a successful unpack
+//! alone would not show.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+use sha2::{Digest, Sha256};
+
+use crate::chunking::{chunk_digest, chunk_list, ChunkStore};
+use crate::concurrency::parallel_map;
+use crate::errors::{ProjzstError, Result};
+use crate::remap::{finish_payload, EntryAction, PathMap, Writers};
+use crate::report::UnpackReport;
+use crate::string_utils::to_hex;
+
+/// Expected content of one extracted entry
+enum Expected {
+    /// Regular file with its size and hex SHA-256
+    File { size: u64, sha256: String },
+    /// Symbolic link with its target
+    Symlink(String),
+}
+
+/// Byte range of the decompressed tar stream being hashed
+#[derive(Default)]
+struct Capture {
+    start: u64,
+    end: u64,
+    hashed: u64,
+    hasher: Sha256,
+}
+
+impl Capture {
+    /// Start hashing the stream bytes in `start..end`
+    fn arm(&mut self, start: u64, end: u64) {
+        *self = Capture {
+            start,
+            end,
+            ..Capture::default()
+        };
+    }
+
+    /// Stop hashing; returns the hex digest if the whole range went through
+    fn finish(&mut self) -> Option<String> {
+        let capture = std::mem::take(self);
+        (capture.hashed == capture.end - capture.start).then(|| to_hex(&capture.hasher.finalize()))
+    }
+}
+
+/// Reader passing the tar stream through while hashing the armed range
+struct CaptureReader<R> {
+    inner: R,
+    position: u64,
+    capture: Rc<RefCell<Capture>>,
+}
+
+impl<R: Read> Read for CaptureReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let (from, to) = (self.position, self.position + read as u64);
+        self.position = to;
+
+        let mut capture = self.capture.borrow_mut();
+        let (start, end) = (from.max(capture.start), to.min(capture.end));
+        if start < end {
+            let bytes = &buf[(start - from) as usize..(end - from) as usize];
+            capture.hasher.update(bytes);
+            capture.hashed += bytes.len() as u64;
+        }
+        Ok(read)
+    }
+}
+
+/// Internal helper: extract a tar stream like [`tar::Archive::unpack`], then check the
+/// written files and symlinks against the content of the stream
+/// Entry paths are mapped by `map` like in any unpack; written files are read back on up
+/// to `threads` threads. With `keep_going`, entries that cannot be extracted are recorded
+/// in `report` instead of failing, and not checked
+pub(crate) fn unpack_verified<R: Read>(
+    payload: R,
+    output_dir: &Path,
+    map: &PathMap,
+    threads: usize,
+    keep_going: bool,
+    report: &mut UnpackReport,
+) -> Result<()> {
+    let output_dir = &output_dir
+        .canonicalize()
+        .unwrap_or_else(|_| output_dir.to_path_buf());
+    let capture = Rc::new(RefCell::new(Capture::default()));
+    let mut tar_archive = tar::Archive::new(CaptureReader {
+        inner: payload,
+        position: 0,
+        capture: Rc::clone(&capture),
+    });
+
+    // Directories go last, as in `tar::Archive::unpack`, so their permissions do not
+    // get in the way of their content
+    let mut expected = BTreeMap::new();
+    let mut directories = Vec::new();
+    let chunks = ChunkStore::with_store(output_dir, map.chunk_store.as_deref())?;
+    let writers = Writers {
+        chunks: Some(&chunks),
+        ..Writers::default()
+    };
+    let mut finished = true;
+    for entry in tar_archive.entries()? {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.payload_failed(e.into(), keep_going)?;
+                finished = false;
+                break;
+            }
+        };
+        if let Some(digest) = chunk_digest(&entry) {
+            if let Err(e) = chunks.insert(digest, &mut entry) {
+                let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                report.entry_failed(path, e, keep_going)?;
+            }
+            continue;
+        }
+        match map.action(&entry)? {
+            EntryAction::Extract => {}
+            EntryAction::Skip => continue,
+            EntryAction::Stop => {
+                finished = false;
+                break;
+            }
+        }
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            directories.push(entry);
+            continue;
+        }
+
+        if let Some(list) = chunk_list(&mut entry)?.filter(|_| entry_type.is_file()) {
+            // Chunks were checked against their digests when read
+            let path = map.unpack_reported(&mut entry, output_dir, writers, keep_going, report)?;
+            if let Some(path) = path {
+                let (size, sha256) = chunks.digest(&list)?;
+                expected.insert(path, Expected::File { size, sha256 });
+            }
+        } else if entry_type == tar::EntryType::Regular {
+            let start = entry.raw_file_position();
+            let size = entry.header().entry_size()?;
+            capture.borrow_mut().arm(start, start + size);
+            let path = map.unpack_reported(
+                &mut entry,
+                output_dir,
+                Writers::default(),
+                keep_going,
+                report,
+            )?;
+            let sha256 = capture.borrow_mut().finish();
+            if let (Some(path), Some(sha256)) = (path, sha256) {
+                expected.insert(path, Expected::File { size, sha256 });
+            }
+        } else if entry_type.is_symlink() {
+            let target = entry.link_name()?.unwrap_or_default();
+            let target = target.to_string_lossy().into_owned();
+            if let Some(path) = map.unpack_reported(
+                &mut entry,
+                output_dir,
+                Writers::default(),
+                keep_going,
+                report,
+            )? {
+                expected.insert(path, Expected::Symlink(target));
+            }
+        } else {
+            map.unpack_reported(
+                &mut entry,
+                output_dir,
+                Writers::default(),
+                keep_going,
+                report,
+            )?;
+        }
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut directory in directories {
+        map.unpack_reported(
+            &mut directory,
+            output_dir,
+            Writers::default(),
+            keep_going,
+            report,
+        )?;
+    }
+    if finished {
+        finish_payload(tar_archive.into_inner(), keep_going, report)?;
+    }
+
+    let expected: Vec<(String, Expected)> = expected.into_iter().collect();
+    let mismatches: Vec<String> = parallel_map(&expected, threads, |(path, expected)| {
+        check_entry(&output_dir.join(path), expected).map(|problem| format!("{path} ({problem})"))
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    if !mismatches.is_empty() {
+        return Err(ProjzstError::ExtractionMismatch(mismatches.join(", ")));
+    }
+    Ok(())
+}
+
+/// Internal helper: compare one written entry with its expected content
+/// Returns what is wrong with it, `None` when it matches
+fn check_entry(path: &Path, expected: &Expected) -> Option<String> {
+    match expected {
+        Expected::File { size, sha256 } => {
+            let actual = match hash_file(path) {
+                Ok(actual) => actual,
+                Err(e) => return Some(format!("cannot read back: {e}")),
+            };
+            if actual.0 != *size {
+                Some(format!("{} bytes written, {size} expected", actual.0))
+            } else if actual.1 != *sha256 {
+                Some("content differs".to_string())
+            } else {
+                None
+            }
+        }
+        Expected::Symlink(target) => match fs::read_link(path) {
+            Ok(actual) if actual.to_string_lossy() == target.as_str() => None,
+            Ok(actual) => Some(format!("links to {}", actual.display())),
+            Err(e) => Some(format!("cannot read link: {e}")),
+        },
+    }
+}
+
+/// Internal helper: size and hex SHA-256 of a file on disk
+fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((size, to_hex(&hasher.finalize())))
+}
@@ -0,0 +1,245 @@
+//! Minimal HTTP package endpoint over a directory of archives (`serve` feature)
+//!
+//! [`serve`] answers `GET` requests for the .pjz files directly inside one directory:
+//!
+//! | Path | Response |
+//! |------|----------|
+//! | `/archives` | JSON list of the archives with their sizes |
+//! | `/archives/{file}` | The archive itself |
+//! | `/archives/{file}/metadata` | Latest metadata as JSON |
+//! | `/archives/{file}/entries` | JSON list of the entries ([`list_entries`]) |
+//! | `/archives/{file}/entries/{path}` | Content of one file entry, streamed |
+//!
+//! Errors are JSON objects with an `error` message. Nothing is cached: every request
+//! reads the archive again, so archives can be replaced while serving.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::builder::read_metadata;
+use crate::errors::{ProjzstError, Result};
+use crate::listing::list_entries;
+use crate::metadata::IgnoreUnknown;
+use crate::preview::with_entry;
+
+/// Requests handled at once
+const WORKERS: usize = 4;
+
+/// File extension of the archives served
+const EXTENSION: &str = "pjz";
+
+/// Response with any body: a buffer, a file or a pipe
+type Reply = Response<Box<dyn Read + Send>>;
+
+/// One archive of `/archives`
+#[derive(Serialize)]
+struct ArchiveListing {
+    name: String,
+    size: u64,
+}
+
+/// Serve the .pjz files of a directory over HTTP until the process ends
+///
+/// # Arguments
+/// * `archive_dir` - Directory whose .pjz files are served (subdirectories are not)
+/// * `address` - Address to listen on, e.g. `127.0.0.1:8080`
+pub fn serve<P: AsRef<Path>>(archive_dir: P, address: &str) -> Result<()> {
+    let server = Server::http(address).map_err(|e| ProjzstError::Io(io::Error::other(e)))?;
+    let archive_dir = archive_dir.as_ref();
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                while let Ok(request) = server.recv() {
+                    respond(archive_dir, request);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Internal helper: answer one request, logging it
+fn respond(archive_dir: &Path, request: Request) {
+    let reply = if *request.method() == Method::Get {
+        route(archive_dir, request.url()).unwrap_or_else(|e| {
+            let status = match &e {
+                ProjzstError::EntryNotFound(_) => 404,
+                ProjzstError::Io(io) if io.kind() == io::ErrorKind::NotFound => 404,
+                _ => 500,
+            };
+            error_reply(status, &e.to_string())
+        })
+    } else {
+        error_reply(405, "only GET is supported")
+    };
+    log::info!(
+        "{} {} {}",
+        request.method(),
+        request.url(),
+        reply.status_code().0
+    );
+    // The client may be gone already; nothing to do about it
+    let _ = request.respond(reply);
+}
+
+/// Internal helper: dispatch a request path to its handler
+fn route(archive_dir: &Path, url: &str) -> Result<Reply> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = path.trim_matches('/');
+    if path == "archives" {
+        return list_archives(archive_dir);
+    }
+    let Some(rest) = path.strip_prefix("archives/") else {
+        return Ok(error_reply(404, "no such route"));
+    };
+    let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let Some(archive) = archive_path(archive_dir, &percent_decode(name)) else {
+        return Ok(error_reply(400, "not an archive name"));
+    };
+    match rest.split_once('/').unwrap_or((rest, "")) {
+        ("", _) => {
+            let file = File::open(&archive)?;
+            let size = file.metadata()?.len();
+            Ok(reply(
+                200,
+                "application/octet-stream",
+                Box::new(file),
+                usize::try_from(size).ok(),
+            ))
+        }
+        ("metadata", "") => json_reply(&read_metadata(&archive, IgnoreUnknown::On)?),
+        ("entries", "") => json_reply(&list_entries(&archive)?),
+        ("entries", entry) => stream_entry(archive, percent_decode(entry)),
+        _ => Ok(error_reply(404, "no such route")),
+    }
+}
+
+/// Internal helper: the .pjz files directly inside `archive_dir`, sorted by name
+fn list_archives(archive_dir: &Path) -> Result<Reply> {
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(archive_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != EXTENSION)
+        {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        archives.push(ArchiveListing {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: metadata.len(),
+        });
+    }
+    archives.sort_by(|a, b| a.name.cmp(&b.name));
+    json_reply(&archives)
+}
+
+/// Internal helper: path of the archive `name` names, if it is a plain .pjz file name
+fn archive_path(archive_dir: &Path, name: &str) -> Option<PathBuf> {
+    let plain = !name.is_empty() && !name.contains(['/', '\\']) && !name.starts_with('.');
+    (plain && Path::new(name).extension().is_some_and(|e| e == EXTENSION))
+        .then(|| archive_dir.join(name))
+}
+
+/// Internal helper: stream one file entry from a decompressing thread
+/// The thread reports the entry size once the entry is found, so a missing entry
+/// is still answered with 404 instead of an empty body
+fn stream_entry(archive: PathBuf, entry: String) -> Result<Reply> {
+    let (reader, mut writer) = io::pipe()?;
+    let (found, size) = mpsc::channel();
+    thread::spawn(move || {
+        let started = found.clone();
+        let result = with_entry(&archive, &entry, |content, size| {
+            let _ = started.send(Ok(size));
+            // Fails once the client hangs up, which ends decompression early
+            io::copy(content, &mut writer)?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            let _ = found.send(Err(e));
+        }
+    });
+    let size = size
+        .recv()
+        .map_err(|_| ProjzstError::Io(io::Error::other("entry reader stopped")))??;
+    Ok(reply(
+        200,
+        "application/octet-stream",
+        Box::new(reader),
+        usize::try_from(size).ok(),
+    ))
+}
+
+/// Internal helper: a response with a body and its content type
+fn reply(
+    status: u16,
+    content_type: &str,
+    body: Box<dyn Read + Send>,
+    length: Option<usize>,
+) -> Reply {
+    let header = Header::from_bytes("Content-Type", content_type)
+        .expect("content types are valid header values");
+    Response::new(StatusCode(status), vec![header], body, length, None)
+}
+
+/// Internal helper: a 200 response with a value as JSON
+fn json_reply<T: Serialize + ?Sized>(value: &T) -> Result<Reply> {
+    let body = serde_json::to_vec_pretty(value)?;
+    let length = body.len();
+    Ok(reply(
+        200,
+        "application/json",
+        Box::new(io::Cursor::new(body)),
+        Some(length),
+    ))
+}
+
+/// Internal helper: an error response, `{"error": message}`
+fn error_reply(status: u16, message: &str) -> Reply {
+    let body = serde_json::json!({ "error": message })
+        .to_string()
+        .into_bytes();
+    let length = body.len();
+    reply(
+        status,
+        "application/json",
+        Box::new(io::Cursor::new(body)),
+        Some(length),
+    )
+}
+
+/// Internal helper: decode `%XX` escapes in a path segment; invalid escapes stay as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
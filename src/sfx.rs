@@ -0,0 +1,83 @@
+//! Self-extracting archives
+//!
+//! With [`Packer::self_extracting`](crate::Packer::self_extracting), the archive starts
+//! with a POSIX shell script, so `sh app.pjz [DIR]` unpacks it on a machine without
+//! projzst installed. The script runs `projzst unpack` when the tool is on the `PATH`, and
+//! otherwise cuts the archive out of itself with `tail` and pipes it through `zstd -d` and
+//! `tar`, which skip the metadata frames. That fallback does not put chunked payloads back
+//! together and needs a zstd payload.
+//!
+//! The script ends with a `__PROJZST_ARCHIVE__` line, right before the first frame.
+//! Readers skip everything up to that line in files starting with `#!`, so the archive
+//! reads as if the script were not there.
+
+use std::io::{self, Read};
+
+use crate::errors::{ProjzstError, Result};
+
+/// Line ending the script, right before the archive
+const ARCHIVE_MARKER: &[u8] = b"\n__PROJZST_ARCHIVE__\n";
+
+/// Largest script skipped in front of an archive
+const MAX_STUB_SIZE: usize = 64 * 1024;
+
+/// Script extracting the archive after it, with `{skip}` standing for its own length
+const STUB_TEMPLATE: &str = r#"#!/bin/sh
+# Self-extracting projzst archive. Run `sh FILE [DIR]` to unpack it into DIR (default:
+# the current directory). projzst is used when installed; otherwise zstd and tar are, and
+# no metadata.json is written.
+set -e
+dir=${1:-.}
+mkdir -p "$dir"
+if command -v projzst >/dev/null 2>&1; then
+    exec projzst unpack "$0" "$dir"
+fi
+if ! command -v zstd >/dev/null 2>&1; then
+    echo "$0: needs projzst, or zstd and tar, to unpack" >&2
+    exit 1
+fi
+tail -c +{skip} "$0" | zstd -dcq | tar -xf - -C "$dir"
+exit 0
+__PROJZST_ARCHIVE__
+"#;
+
+/// Internal helper: the script written in front of a self-extracting archive
+pub(crate) fn stub() -> Vec<u8> {
+    // `tail -c +N` starts at byte N counting from 1, and N is part of the script
+    let mut skip = STUB_TEMPLATE.len();
+    loop {
+        let stub = STUB_TEMPLATE.replace("{skip}", &(skip + 1).to_string());
+        if stub.len() == skip {
+            return stub.into_bytes();
+        }
+        skip = stub.len();
+    }
+}
+
+/// Internal helper: read the script at the start of `reader`, if it starts with `#!`
+/// Returns the script, empty when there is none, and the bytes read that are not part of
+/// it; fails with [`ProjzstError::InvalidFileHeader`] on a script without the archive
+/// marker
+pub(crate) fn read_stub<R: Read + ?Sized>(reader: &mut R) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut head = Vec::new();
+    (&mut *reader).take(2).read_to_end(&mut head)?;
+    if head != b"#!" {
+        return Ok((Vec::new(), head));
+    }
+    // Byte by byte, so nothing past the marker is consumed
+    let mut stub = head;
+    let mut byte = [0u8; 1];
+    while !stub.ends_with(ARCHIVE_MARKER) {
+        if stub.len() >= MAX_STUB_SIZE {
+            return Err(ProjzstError::InvalidFileHeader);
+        }
+        match reader.read_exact(&mut byte) {
+            Ok(()) => stub.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(ProjzstError::InvalidFileHeader)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok((stub, Vec::new()))
+}
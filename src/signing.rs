@@ -0,0 +1,292 @@
+//! Archive signatures
+//!
+//! An archive carries one `signature` frame, made in one of two ways:
+//!
+//! - [`sign_archive`] pipes the archive digest to an external command (an HSM or KMS
+//!   client, `cosign sign-blob`, `openssl pkeyutl`, ...) and stores what it prints,
+//!   unchanged; projzst holds no keys and checking is left to the same tool.
+//! - [`sign_archive_with_key`] signs it with an ed25519 [`SecretKey`] and stores a JSON
+//!   object `{"algorithm": "ed25519", "key_id": ..., "signature": ...}` (hex signature),
+//!   checked by [`verify_signature`] against the [`PublicKey`]s trusted. Keys need the
+//!   `crypto` feature.
+//!
+//! The signed digest is the lowercase hex SHA-256 of the archive without its signature
+//! and padding frames (and without the script of a self-extracting archive), as 64 ASCII
+//! digits without a newline; [`signed_digest`] gives it back. Signing rewrites the leading
+//! frames in place, so it comes after every other edit (comments, attachments, appended
+//! metadata), which would change the digest.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+#[cfg(feature = "crypto")]
+use ed25519_dalek::{Signer, Verifier};
+#[cfg(feature = "crypto")]
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{ProjzstError, Result};
+use crate::frames::{frames, read_frames, rewrite_frames, FrameKind, RawFrame, MAX_FRAME_SIZE};
+#[cfg(feature = "crypto")]
+use crate::string_utils::from_hex;
+use crate::string_utils::to_hex;
+use crate::timestamp::is_timestamp_frame;
+use crate::volume::open_raw_input;
+
+/// Prefix of a [`PublicKey`] in text form
+#[cfg(feature = "crypto")]
+const PUBLIC_KEY_PREFIX: &str = "ed25519:";
+
+/// Prefix of a [`SecretKey`] in text form
+#[cfg(feature = "crypto")]
+const SECRET_KEY_PREFIX: &str = "ed25519-secret:";
+
+/// Signature algorithm of key signatures
+#[cfg(feature = "crypto")]
+const KEY_ALGORITHM: &str = "ed25519";
+
+/// An ed25519 key signing archives
+/// Its text form, kept in key files, is `ed25519-secret:` and the 32-byte seed in hex
+#[cfg(feature = "crypto")]
+#[derive(Clone)]
+pub struct SecretKey(ed25519_dalek::SigningKey);
+
+#[cfg(feature = "crypto")]
+impl SecretKey {
+    /// Key of a 32-byte seed, which must come from a secure random source
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        SecretKey(ed25519_dalek::SigningKey::from_bytes(&seed))
+    }
+
+    /// Parse the text form, see [`SecretKey::to_text`]
+    pub fn from_text(text: &str) -> Result<Self> {
+        let seed = text
+            .trim()
+            .strip_prefix(SECRET_KEY_PREFIX)
+            .and_then(from_hex::<32>)
+            .ok_or_else(|| ProjzstError::InvalidKey("not an ed25519-secret: key".to_string()))?;
+        Ok(Self::from_seed(seed))
+    }
+
+    /// Text form of the key: `ed25519-secret:<64 hex digits>`
+    pub fn to_text(&self) -> String {
+        format!("{SECRET_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+
+    /// Public half of the key, checking its signatures
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key())
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never the seed, which would end up in logs
+        f.debug_tuple("SecretKey")
+            .field(&self.public_key().id())
+            .finish()
+    }
+}
+
+/// Public half of an ed25519 [`SecretKey`], checking the signatures it makes
+/// Its text form is `ed25519:` and the 32-byte key in hex
+#[cfg(feature = "crypto")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(ed25519_dalek::VerifyingKey);
+
+#[cfg(feature = "crypto")]
+impl PublicKey {
+    /// Parse the text form, see [`PublicKey::to_text`]
+    pub fn from_text(text: &str) -> Result<Self> {
+        text.trim()
+            .strip_prefix(PUBLIC_KEY_PREFIX)
+            .and_then(from_hex::<32>)
+            .and_then(|bytes| ed25519_dalek::VerifyingKey::from_bytes(&bytes).ok())
+            .map(PublicKey)
+            .ok_or_else(|| ProjzstError::InvalidKey("not an ed25519: public key".to_string()))
+    }
+
+    /// Text form of the key: `ed25519:<64 hex digits>`
+    pub fn to_text(&self) -> String {
+        format!("{PUBLIC_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+
+    /// Short identifier of the key recorded with its signatures: the first 8 bytes of the
+    /// SHA-256 of the key, in hex
+    pub fn id(&self) -> String {
+        to_hex(&Sha256::digest(self.0.as_bytes())[..8])
+    }
+}
+
+/// Signature frame written by [`sign_archive_with_key`]
+#[cfg(feature = "crypto")]
+#[derive(Debug, Serialize, Deserialize)]
+struct KeySignature {
+    algorithm: String,
+    key_id: String,
+    signature: String,
+}
+
+/// Digest signed for a .pjz file, as hex SHA-256
+/// Covers the archive as stored, except signature and padding frames and the script of a
+/// self-extracting archive, so signing does not change it
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn signed_digest<P: AsRef<Path>>(input_file: P) -> Result<String> {
+    let mut reader = open_raw_input(input_file.as_ref())?;
+    let (frames, payload_head) = read_frames(&mut reader)?;
+    let mut hasher = Sha256::new();
+    for frame in frames
+        .iter()
+        .filter(|frame| !matches!(frame.kind(), FrameKind::Signature | FrameKind::Padding))
+    {
+        frame.write_to(&mut hasher)?;
+    }
+    hasher.update(&payload_head);
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Sign a .pjz file in place with an external command, replacing any signature
+/// The command gets the [`signed_digest`] on stdin and prints the signature on stdout,
+/// which is stored as it is; its stderr is left on the terminal, for signers asking for
+/// a PIN or a login. Returns the signature. Fails with [`ProjzstError::SigningFailed`]
+/// when the command cannot be run, exits with an error or prints nothing. Archives whose
+/// metadata is stored under the signature magic (see
+/// [`Packer::metadata_magic`](crate::Packer::metadata_magic)) cannot be signed
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `command` - Executable and its arguments, e.g. `["cosign", "sign-blob", "--key", "k", "-"]`
+pub fn sign_archive<P: AsRef<Path>>(input_file: P, command: &[String]) -> Result<Vec<u8>> {
+    let input_file = input_file.as_ref();
+    let digest = signed_digest(input_file)?;
+    let signature = run_signer(command, digest.into_bytes())?;
+    store_signature(input_file, signature.clone())?;
+    Ok(signature)
+}
+
+/// Read the signature stored in a .pjz file, if it has one
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn signature<P: AsRef<Path>>(input_file: P) -> Result<Option<Vec<u8>>> {
+    Ok(frames(input_file)?
+        .into_iter()
+        .find(is_signature_frame)
+        .map(|frame| frame.payload))
+}
+
+/// Sign a .pjz file in place with an ed25519 key, replacing any signature
+/// Returns the identifier of the key, see [`PublicKey::id`]
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `key` - Key to sign with
+#[cfg(feature = "crypto")]
+pub fn sign_archive_with_key<P: AsRef<Path>>(input_file: P, key: &SecretKey) -> Result<String> {
+    let input_file = input_file.as_ref();
+    let digest = signed_digest(input_file)?;
+    let key_id = key.public_key().id();
+    let frame = KeySignature {
+        algorithm: KEY_ALGORITHM.to_string(),
+        key_id: key_id.clone(),
+        signature: to_hex(&key.0.sign(digest.as_bytes()).to_bytes()),
+    };
+    store_signature(input_file, serde_json::to_vec(&frame)?)?;
+    Ok(key_id)
+}
+
+/// Check the ed25519 signature of a .pjz file against trusted keys
+/// Returns the key that made it. Fails with [`ProjzstError::SignatureInvalid`] when the
+/// archive is not signed with a key, when no trusted key has the recorded identifier or
+/// when the signature does not match the archive
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `trusted` - Keys whose signatures are accepted
+#[cfg(feature = "crypto")]
+pub fn verify_signature<P: AsRef<Path>>(input_file: P, trusted: &[PublicKey]) -> Result<PublicKey> {
+    let input_file = input_file.as_ref();
+    let invalid = |message: &str| ProjzstError::SignatureInvalid(message.to_string());
+    let stored = signature(input_file)?.ok_or_else(|| invalid("the archive is not signed"))?;
+    let frame: KeySignature = serde_json::from_slice(&stored)
+        .ok()
+        .filter(|frame: &KeySignature| frame.algorithm == KEY_ALGORITHM)
+        .ok_or_else(|| invalid("the archive is not signed with an ed25519 key"))?;
+    let key = trusted
+        .iter()
+        .find(|key| key.id() == frame.key_id)
+        .ok_or_else(|| {
+            ProjzstError::SignatureInvalid(format!("key {} is not trusted", frame.key_id))
+        })?;
+    let bytes = from_hex::<64>(&frame.signature).ok_or_else(|| invalid("malformed signature"))?;
+    let digest = signed_digest(input_file)?;
+    key.0
+        .verify(
+            digest.as_bytes(),
+            &ed25519_dalek::Signature::from_bytes(&bytes),
+        )
+        .map_err(|_| invalid("the signature does not match the archive"))?;
+    Ok(*key)
+}
+
+/// Internal helper: replace the signature frame of an archive, keeping its timestamp
+fn store_signature(input_file: &Path, payload: Vec<u8>) -> Result<()> {
+    rewrite_frames(input_file, |frames| {
+        let position = frames
+            .iter()
+            .position(is_signature_frame)
+            .unwrap_or(frames.len());
+        frames.retain(|frame| !is_signature_frame(frame));
+        let frame = RawFrame {
+            magic: FrameKind::Signature.magic(),
+            payload,
+        };
+        frames.insert(position.min(frames.len()), frame);
+    })
+}
+
+/// Internal helper: whether a frame holds the signature, not a timestamp token
+fn is_signature_frame(frame: &RawFrame) -> bool {
+    frame.kind() == FrameKind::Signature && !is_timestamp_frame(frame)
+}
+
+/// Internal helper: run a signing command on `input` and collect what it prints
+fn run_signer(command: &[String], input: Vec<u8>) -> Result<Vec<u8>> {
+    let name = command.join(" ");
+    let fail = |message: String| ProjzstError::SigningFailed(format!("{name}: {message}"));
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| ProjzstError::SigningFailed("empty command".to_string()))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| fail(e.to_string()))?;
+
+    // Fed from another thread, like lint plugins, so a signer printing first cannot block
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let feeder = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output()?;
+    feeder.join().expect("signer stdin writer panicked");
+
+    if !output.status.success() {
+        return Err(fail(output.status.to_string()));
+    }
+    if output.stdout.is_empty() {
+        return Err(fail("no signature printed".to_string()));
+    }
+    if output.stdout.len() > MAX_FRAME_SIZE {
+        return Err(fail("signature larger than a frame".to_string()));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,70 @@
+//! Free space checks before unpacking
+//!
+//! With [`ReadOptions::check_free_space`](crate::ReadOptions::check_free_space), the
+//! entries an unpack would write are added up from their tar headers, each file rounded
+//! up to whole [`SPACE_BLOCK`]s, and compared with the space left to unprivileged users on
+//! the file system of the output directory. An unpack that cannot fit fails before it
+//! writes anything instead of halfway through with `ENOSPC`. Only Unix systems report
+//! free space; elsewhere the check passes.
+
+use std::io;
+use std::path::Path;
+
+use crate::errors::{ProjzstError, Result};
+
+/// Allocation unit files are rounded up to, the block size of common file systems
+pub(crate) const SPACE_BLOCK: u64 = 4096;
+
+/// Internal helper: bytes a file of `size` bytes takes on disk, in whole blocks
+pub(crate) fn blocks_for(size: u64) -> u64 {
+    size.div_ceil(SPACE_BLOCK).max(1) * SPACE_BLOCK
+}
+
+/// Internal helper: fail with [`ProjzstError::InsufficientSpace`] when `needed` bytes do
+/// not fit on the file system of `output_dir` (or of its nearest existing parent)
+pub(crate) fn ensure_free_space(output_dir: &Path, needed: u64) -> Result<()> {
+    let Some(available) = free_space(output_dir)? else {
+        return Ok(());
+    };
+    log::debug!(
+        "{}: {needed} bytes needed, {available} available",
+        output_dir.display()
+    );
+    if needed > available {
+        return Err(ProjzstError::InsufficientSpace {
+            dir: output_dir.display().to_string(),
+            needed,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Internal helper: bytes available to unprivileged users on the file system holding
+/// `dir`, `None` where the platform does not tell
+#[cfg(unix)]
+fn free_space(dir: &Path) -> io::Result<Option<u64>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // The output directory is created by the unpack, so ask about what exists of it
+    let existing = dir
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let path = CString::new(existing.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid statvfs to fill in
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+/// Internal helper: free space is not checked on this platform
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
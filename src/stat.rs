@@ -0,0 +1,372 @@
+//! Archive statistics without extraction
+//!
+//! [`stat`] walks the frames of a .pjz file by their headers: skippable frames are
+//! counted and skipped, zstd frames are measured block by block and their header
+//! parameters read. The payload is then decompressed once, without writing anything, to
+//! count the entries and the uncompressed size.
+//!
+//! Each file is also attributed its share of the compressed payload. With per-entry frames
+//! the share is exact, the size of the frame the index lists for it. In a solid payload,
+//! where files share frames, every file is compressed on its own while the payload is read
+//! and the results are scaled to add up to the payload size: an estimate that still tells
+//! which files take the room.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{SKIPPABLE_FRAME_MAGIC_MAX, SKIPPABLE_FRAME_MAGIC_MIN, ZSTD_FRAME_MAGIC};
+use crate::codec::zstd_decoder;
+use crate::errors::{ProjzstError, Result};
+use crate::frames::FrameKind;
+use crate::index::EntryIndex;
+use crate::storage::normalize_path;
+use crate::volume::{last_volume, open_input, open_raw_input};
+
+/// Block type of a compressed zstd block whose size is the regenerated size (RLE)
+const RLE_BLOCK: u8 = 1;
+
+/// Block type reserved by the zstd format, a sign of corruption
+const RESERVED_BLOCK: u8 = 3;
+
+/// Level files of a solid payload are compressed at to estimate their share: the level
+/// archives are packed at unless told otherwise
+const ESTIMATE_LEVEL: i32 = crate::DEFAULT_ZSTD_LEVEL;
+
+/// Header parameters and size of one zstd frame of the payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ZstdFrameStats {
+    /// Offset of the frame from the start of the file
+    pub offset: u64,
+    /// Size of the frame in bytes
+    pub size: u64,
+    /// Window size the decoder needs, in bytes
+    pub window_size: u64,
+    /// Whether the frame ends with a content checksum
+    pub checksum: bool,
+    /// Decompressed size recorded in the header, if any
+    pub content_size: Option<u64>,
+}
+
+/// Share of the compressed payload taken by one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct EntryCompression {
+    /// Entry path relative to the archive root
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u64,
+    /// Compressed bytes attributed to the file, its tar header included
+    pub compressed_size: u64,
+    /// Whether `compressed_size` is an estimate (solid payloads) rather than the size of
+    /// the file's own frame
+    pub estimated: bool,
+}
+
+impl EntryCompression {
+    /// Uncompressed size divided by compressed size, `0` when nothing is attributed
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_size == 0 {
+            return 0.0;
+        }
+        self.size as f64 / self.compressed_size as f64
+    }
+}
+
+/// Layout and content statistics of a .pjz file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ArchiveStats {
+    /// Size of the file in bytes (all volumes of a split archive)
+    pub file_size: u64,
+    /// Payload bytes of the metadata frames, revisions included
+    pub metadata_size: u64,
+    /// Number of skippable frames (metadata, index, comment, ...)
+    pub skippable_frames: usize,
+    /// Bytes of zstd frames
+    pub payload_size: u64,
+    /// The zstd frames of the payload, in file order
+    pub zstd_frames: Vec<ZstdFrameStats>,
+    /// Number of tar entries
+    pub entries: usize,
+    /// Size of the decompressed tar stream in bytes
+    pub uncompressed_size: u64,
+    /// Compressed share of every regular file, in archive order
+    pub entry_sizes: Vec<EntryCompression>,
+}
+
+/// Report the frame layout and content statistics of a .pjz file without extracting it
+/// The compressed share of each file is exact for archives packed with per-entry frames
+/// and estimated otherwise, which costs compressing every file once more
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn stat<P: AsRef<Path>>(input_file: P) -> Result<ArchiveStats> {
+    let input_file = input_file.as_ref();
+    let mut stats = ArchiveStats {
+        file_size: 0,
+        metadata_size: 0,
+        skippable_frames: 0,
+        payload_size: 0,
+        zstd_frames: Vec::new(),
+        entries: 0,
+        uncompressed_size: 0,
+        entry_sizes: Vec::new(),
+    };
+
+    let mut reader = Position {
+        inner: open_raw_input(input_file)?,
+        position: 0,
+    };
+    while let Some(magic) = reader.read_magic()? {
+        let offset = reader.position - 4;
+        if (SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&magic) {
+            let size = u64::from(reader.read_u32()?);
+            reader.skip(size)?;
+            stats.skippable_frames += 1;
+            if FrameKind::from_magic(magic) == Some(FrameKind::Metadata) {
+                stats.metadata_size += size;
+            }
+        } else if magic == ZSTD_FRAME_MAGIC {
+            let mut frame = read_zstd_frame(&mut reader)?;
+            frame.offset = offset;
+            frame.size = reader.position - offset;
+            stats.payload_size += frame.size;
+            stats.zstd_frames.push(frame);
+        } else {
+            return Err(ProjzstError::InvalidFrameMagic(magic));
+        }
+    }
+    stats.file_size = reader.position;
+
+    // The decoder skips the skippable frames on its own
+    let mut payload = Position {
+        inner: zstd_decoder(open_input(input_file)?)?,
+        position: 0,
+    };
+    let frame_sizes = frame_sizes(input_file)?;
+    {
+        let mut tar_archive = tar::Archive::new(&mut payload);
+        for entry in tar_archive.entries()? {
+            let mut entry = entry?;
+            stats.entries += 1;
+            if let Some(share) = entry_share(&mut entry, frame_sizes.as_ref())? {
+                stats.entry_sizes.push(share);
+            }
+        }
+    }
+    // Past the end-of-archive marker, to the end of the stream
+    io::copy(&mut payload, &mut io::sink())?;
+    stats.uncompressed_size = payload.position;
+
+    // Scale the estimates to the part of the payload no frame of the index accounts for
+    let (estimated, exact) = stats
+        .entry_sizes
+        .iter()
+        .fold((0, 0), |(estimated, exact), share| {
+            if share.estimated {
+                (estimated + share.compressed_size, exact)
+            } else {
+                (estimated, exact + share.compressed_size)
+            }
+        });
+    if estimated > 0 {
+        let scale = stats.payload_size.saturating_sub(exact) as f64 / estimated as f64;
+        for share in stats.entry_sizes.iter_mut().filter(|share| share.estimated) {
+            share.compressed_size = (share.compressed_size as f64 * scale).round() as u64;
+        }
+    }
+    Ok(stats)
+}
+
+/// Internal helper: frame sizes by path from the entry index, `None` for solid payloads
+/// and split archives, which are read as one stream
+fn frame_sizes(input_file: &Path) -> Result<Option<HashMap<String, u64>>> {
+    if !input_file.is_file() || last_volume(input_file) != input_file {
+        return Ok(None);
+    }
+    let index = EntryIndex::read_from(&mut File::open(input_file)?)?;
+    Ok(index.map(|index| {
+        index
+            .entries
+            .into_iter()
+            .map(|entry| (entry.path, entry.length))
+            .collect()
+    }))
+}
+
+/// Internal helper: the compressed share of a regular file entry, `None` for other
+/// entries; the size of its frame when indexed, else its size compressed on its own
+fn entry_share<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    frame_sizes: Option<&HashMap<String, u64>>,
+) -> Result<Option<EntryCompression>> {
+    if !entry.header().entry_type().is_file() {
+        return Ok(None);
+    }
+    let Some(path) = normalize_path(&entry.path()?) else {
+        return Ok(None);
+    };
+    let size = entry.size();
+    if let Some(&length) = frame_sizes.and_then(|sizes| sizes.get(&path)) {
+        return Ok(Some(EntryCompression {
+            path,
+            size,
+            compressed_size: length,
+            estimated: false,
+        }));
+    }
+    let mut counter = ByteCount(0);
+    zstd::stream::copy_encode(entry, &mut counter, ESTIMATE_LEVEL)?;
+    Ok(Some(EntryCompression {
+        path,
+        size,
+        compressed_size: counter.0,
+        estimated: true,
+    }))
+}
+
+/// Writer counting the bytes written to it
+struct ByteCount(u64);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Internal helper: read the rest of a zstd frame whose magic was just read
+/// The offset and size are left for the caller to fill in
+pub(crate) fn read_zstd_frame<R: Read>(reader: &mut Position<R>) -> Result<ZstdFrameStats> {
+    let descriptor = reader.read_u8()?;
+    let content_size_flag = descriptor >> 6;
+    let single_segment = descriptor & 0x20 != 0;
+    let checksum = descriptor & 0x04 != 0;
+    let dictionary_id_size = [0, 1, 2, 4][usize::from(descriptor & 0x03)];
+
+    let window_descriptor = if single_segment {
+        None
+    } else {
+        Some(reader.read_u8()?)
+    };
+    reader.skip(dictionary_id_size)?;
+    let content_size_bytes = match content_size_flag {
+        0 if single_segment => 1,
+        0 => 0,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let content_size = if content_size_bytes == 0 {
+        None
+    } else {
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes[..content_size_bytes])?;
+        let value = u64::from_le_bytes(bytes);
+        // The two-byte field is stored minus 256
+        Some(if content_size_bytes == 2 {
+            value + 256
+        } else {
+            value
+        })
+    };
+    let window_size = match window_descriptor {
+        Some(descriptor) => {
+            let exponent = u32::from(descriptor >> 3);
+            let mantissa = u64::from(descriptor & 0x07);
+            let base = 1u64 << (10 + exponent);
+            base + (base / 8) * mantissa
+        }
+        // Single-segment frames are decoded in one buffer of the content size
+        None => content_size.unwrap_or(0),
+    };
+
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header[..3])?;
+        let header = u32::from_le_bytes(header);
+        let last = header & 1 != 0;
+        let block_type = ((header >> 1) & 0x03) as u8;
+        let block_size = u64::from(header >> 3);
+        match block_type {
+            RLE_BLOCK => reader.skip(1)?,
+            RESERVED_BLOCK => return Err(ProjzstError::InvalidFileHeader),
+            _ => reader.skip(block_size)?,
+        }
+        if last {
+            break;
+        }
+    }
+    if checksum {
+        reader.skip(4)?;
+    }
+    Ok(ZstdFrameStats {
+        offset: 0,
+        size: 0,
+        window_size,
+        checksum,
+        content_size,
+    })
+}
+
+/// Reader keeping count of the bytes read through it
+pub(crate) struct Position<R> {
+    pub(crate) inner: R,
+    pub(crate) position: u64,
+}
+
+impl<R: Read> Position<R> {
+    /// Internal helper: the magic of the next frame, `None` at the end of the file
+    pub(crate) fn read_magic(&mut self) -> io::Result<Option<u32>> {
+        let mut magic = [0u8; 4];
+        let mut filled = 0;
+        while filled < magic.len() {
+            match self.read(&mut magic[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+        Ok(Some(u32::from_le_bytes(magic)))
+    }
+
+    /// Internal helper: read a little-endian `u32`
+    pub(crate) fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Internal helper: read one byte
+    pub(crate) fn read_u8(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
+        self.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Internal helper: skip `count` bytes, failing if the input ends first
+    pub(crate) fn skip(&mut self, count: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut self.by_ref().take(count), &mut io::sink())?;
+        if skipped < count {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Position<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
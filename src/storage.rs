@@ -0,0 +1,390 @@
+//! Pluggable storage backends
+//!
+//! The [`Storage`] trait abstracts where archives and their contents live, so the same
+//! pack/unpack/info logic runs against the local filesystem, S3 buckets or HTTP servers.
+//! Paths handed to a backend are `/`-separated and relative to the backend root.
+//!
+//! [`pack`](crate::pack), [`unpack`](crate::unpack), [`info`](crate::info) and
+//! [`Packer::pack`] take any [`Location`]: a local path, or a path on a backend given with
+//! [`Storage::at`]. When either side is on a backend, local paths are used through a
+//! [`LocalStorage`] rooted at their parent directory.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+use crate::builder::{open_archive, read_metadata_from_reader, Packer};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::{IgnoreUnknown, Metadata};
+use crate::report::PackReport;
+use crate::tar_format::append_exact;
+use crate::temp::temp_file_for;
+
+/// A file or object listed by a [`Storage`] backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageEntry {
+    /// Path relative to the backend root
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time as Unix seconds (0 when unknown)
+    pub mtime: u64,
+}
+
+/// A place archives and extracted files can be read from and written to
+pub trait Storage: Send + Sync {
+    /// Open a file for streaming reads
+    fn open(&self, path: &str) -> Result<Box<dyn Read + Send + '_>>;
+
+    /// Create or replace a file with everything `data` yields
+    fn write(&self, path: &str, data: &mut dyn Read) -> Result<()>;
+
+    /// List every file under `prefix` (recursively), sorted by path
+    fn list(&self, prefix: &str) -> Result<Vec<StorageEntry>>;
+
+    /// Read a whole file into memory
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// The file or directory at `path` on this backend, to pass where a [`Location`] is
+    /// expected
+    fn at<'a>(&'a self, path: &'a str) -> StoragePath<'a>
+    where
+        Self: Sized,
+    {
+        StoragePath::new(self, path)
+    }
+}
+
+/// Where a file or directory is, see [`Location`]
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Place<'a> {
+    /// A local path
+    Local(&'a Path),
+    /// A path on a storage backend
+    Backend {
+        storage: &'a dyn Storage,
+        path: &'a str,
+    },
+}
+
+/// A file or directory to pack, unpack or read: any local path (`&str`, `PathBuf`, ...)
+/// or a [`StoragePath`] on a backend
+pub trait Location {
+    /// Where the file or directory is
+    fn place(&self) -> Place<'_>;
+}
+
+impl<P: AsRef<Path>> Location for P {
+    fn place(&self) -> Place<'_> {
+        Place::Local(self.as_ref())
+    }
+}
+
+/// A path on a storage backend, made with [`Storage::at`] or [`StoragePath::new`]
+#[derive(Clone, Copy)]
+pub struct StoragePath<'a> {
+    storage: &'a dyn Storage,
+    path: &'a str,
+}
+
+impl<'a> StoragePath<'a> {
+    /// The file or directory at `path` on `storage`
+    pub fn new(storage: &'a dyn Storage, path: &'a str) -> Self {
+        Self { storage, path }
+    }
+}
+
+impl Location for StoragePath<'_> {
+    fn place(&self) -> Place<'_> {
+        Place::Backend {
+            storage: self.storage,
+            path: self.path,
+        }
+    }
+}
+
+/// Internal helper: call `f` with the backend and backend path of `place`; a local path
+/// is given as a [`LocalStorage`] rooted at its parent and its file name
+pub(crate) fn with_backend<T>(
+    place: Place<'_>,
+    f: impl FnOnce(&dyn Storage, &str) -> Result<T>,
+) -> Result<T> {
+    match place {
+        Place::Backend { storage, path } => f(storage, path),
+        Place::Local(path) => match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                f(&LocalStorage::new(parent), &name.to_string_lossy())
+            }
+            _ => f(&LocalStorage::new(path), ""),
+        },
+    }
+}
+
+/// Local filesystem backend rooted at a directory
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Create a backend whose paths resolve under `root`
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Internal helper: resolve a backend path, refusing to escape the root
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let relative = normalize_path(Path::new(path))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, path.to_string()))?;
+        Ok(self.root.join(relative))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn open(&self, path: &str) -> Result<Box<dyn Read + Send + '_>> {
+        Ok(Box::new(File::open(self.resolve(path)?)?))
+    }
+
+    fn write(&self, path: &str, data: &mut dyn Read) -> Result<()> {
+        let target = self.resolve(path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replaced only once complete, so an interrupted write leaves the old file
+        let (mut file, temp) = temp_file_for(&target)?;
+        io::copy(data, &mut file)?;
+        drop(file);
+        temp.persist(&target)
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<StorageEntry>> {
+        let base = if prefix.is_empty() {
+            self.root.clone()
+        } else {
+            self.resolve(prefix)?
+        };
+        if !base.exists() {
+            return Err(ProjzstError::SourceNotFound(base.display().to_string()));
+        }
+
+        let mut entries = Vec::new();
+        let mut pending = vec![base];
+        while let Some(dir) = pending.pop() {
+            for item in fs::read_dir(&dir)? {
+                let item = item?;
+                let file_type = item.file_type()?;
+                if file_type.is_dir() {
+                    pending.push(item.path());
+                } else if file_type.is_file() {
+                    let info = item.metadata()?;
+                    let Some(path) = item
+                        .path()
+                        .strip_prefix(&self.root)
+                        .ok()
+                        .and_then(normalize_path)
+                    else {
+                        continue;
+                    };
+                    entries.push(StorageEntry {
+                        path,
+                        size: info.len(),
+                        mtime: info
+                            .modified()
+                            .ok()
+                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                            .map_or(0, |d| d.as_secs()),
+                    });
+                }
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+}
+
+impl Packer {
+    /// Internal helper: pack every file under `source_prefix` of one backend into an
+    /// archive on another
+    /// Only regular files are stored; paths in the archive are relative to the prefix.
+    /// Exclusions, the depth and file size limits and the overwrite check apply as they do
+    /// to a local pack
+    pub(crate) fn pack_backend(
+        &self,
+        source: &dyn Storage,
+        source_prefix: &str,
+        output: &dyn Storage,
+        output_path: &str,
+    ) -> Result<PackReport> {
+        self.check_overwrite_with(Path::new(output_path), |path| {
+            output.open(&path.to_string_lossy()).is_ok()
+        })?;
+        let metadata_bytes = self.encoded_metadata()?;
+        let prefix = directory_prefix(source_prefix);
+        let entry_prefix = self.entry_prefix()?;
+
+        // The same filters as a walk of a local directory, on the listed paths
+        let mut oversized = Vec::new();
+        let mut entries = Vec::new();
+        for entry in source.list(source_prefix)? {
+            let relative = entry.path.strip_prefix(&prefix).unwrap_or(&entry.path);
+            if !self.walk.keeps_listed(relative) {
+                log::debug!("excluded {}", entry.path);
+                continue;
+            }
+            let name = match &entry_prefix {
+                Some(entry_prefix) => format!("{entry_prefix}/{relative}"),
+                None => relative.to_string(),
+            };
+            match self.walk.oversized(&name, entry.size)? {
+                Some(skipped) => oversized.push(skipped),
+                None => entries.push((name, entry)),
+            }
+        }
+
+        // The archive is produced on one side of a pipe and consumed by the backend on the other
+        let (mut pipe_reader, pipe_writer) = io::pipe()?;
+        thread::scope(|scope| {
+            let producer = scope.spawn(move || -> Result<PackReport> {
+                let mut report = PackReport::default();
+                report.skipped(oversized, Vec::new());
+                let mut pipe_writer = pipe_writer;
+                self.write_header(&mut pipe_writer, &metadata_bytes)?;
+
+                let mut zst_encoder = self.payload_encoder(pipe_writer)?;
+                {
+                    let mut tar_builder = tar::Builder::new(&mut zst_encoder);
+                    for (name, entry) in &entries {
+                        let mut header = tar::Header::new_gnu();
+                        header.set_entry_type(tar::EntryType::Regular);
+                        header.set_size(entry.size);
+                        header.set_mode(0o644);
+                        header.set_mtime(entry.mtime);
+                        let body = source.open(&entry.path)?;
+                        append_exact(&mut tar_builder, &mut header, name, body)?;
+                        report.add_file(name, entry.size);
+                    }
+                    tar_builder.finish()?;
+                }
+                let (mut pipe_writer, compressed_size) = zst_encoder.finish_counted()?;
+                self.write_trailer(&mut pipe_writer, &metadata_bytes)?;
+                pipe_writer.flush()?;
+                report.finish(compressed_size);
+                Ok(report)
+            });
+
+            let written = output.write(output_path, &mut pipe_reader);
+            // Unblock the producer if the backend stopped reading early
+            drop(pipe_reader);
+            let produced = producer.join().expect("archive producer panicked");
+
+            // A producer failure explains a short write better than the write error does
+            let report = produced?;
+            written.map(|()| report)
+        })
+    }
+}
+
+/// Internal helper: unpack an archive from one backend into files on another
+/// Regular files are written under `output_prefix` and `metadata.json` next to it,
+/// mirroring what [`crate::unpack`] does on disk
+pub(crate) fn unpack_backend(
+    input: &dyn Storage,
+    input_path: &str,
+    output: &dyn Storage,
+    output_prefix: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let reader = input.open(input_path)?;
+    let (metadata, mut tar_archive) = open_archive(reader, ignore_unknown)?;
+
+    let prefix = directory_prefix(output_prefix);
+
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(relative) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        output.write(&format!("{prefix}{relative}"), &mut entry)?;
+    }
+
+    let json_content = serde_json::to_string_pretty(&metadata)?;
+    output.write(
+        &sibling_path(&prefix, "metadata.json"),
+        &mut json_content.as_bytes(),
+    )?;
+
+    Ok(metadata)
+}
+
+/// Internal helper: extract metadata from an archive on one backend and save it as JSON
+/// on another
+pub(crate) fn info_backend(
+    input: &dyn Storage,
+    input_path: &str,
+    output: &dyn Storage,
+    output_path: &str,
+    ignore_unknown: IgnoreUnknown,
+) -> Result<Metadata> {
+    let mut reader = input.open(input_path)?;
+    let (metadata, _) = read_metadata_from_reader(&mut reader, ignore_unknown)?;
+
+    let json_content = serde_json::to_string_pretty(&metadata)?;
+    output.write(output_path, &mut json_content.as_bytes())?;
+
+    Ok(metadata)
+}
+
+/// Internal helper: turn an archive or backend path into a `/`-separated relative path
+/// Paths escaping their root (`..`, absolute paths) yield `None`
+pub(crate) fn normalize_path(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("/"))
+    }
+}
+
+/// Internal helper: `prefix` as a directory (empty, or ending with `/`)
+fn directory_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{trimmed}/")
+    }
+}
+
+/// Internal helper: path of a file placed next to a directory prefix rather than inside it
+/// (the backend counterpart of "parent directory of the output")
+pub(crate) fn sibling_path(prefix: &str, name: &str) -> String {
+    let trimmed = prefix.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(idx) => format!("{}/{}", &trimmed[..idx], name),
+        None => name.to_string(),
+    }
+}
@@ -105,3 +105,60 @@ impl IntoOpStr for Option<String> {
 pub fn convert<T: IntoOpStr>(value: T) -> Option<String> {
     value.into_op_str()
 }
+
+/// Lowercase hexadecimal encoding of a byte slice (digests, checksums)
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode exactly `N` bytes of hex, `None` on any other input
+#[cfg(any(feature = "crypto", feature = "http"))]
+pub(crate) fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != 2 * N || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Parse a byte size such as `4096`, `512K`, `100M` or `2GiB`
+///
+/// Suffixes are binary multiples (`K` = 1024) and case-insensitive; a trailing `B`
+/// or `iB` is accepted. Zero is rejected.
+///
+/// # Examples
+///
+/// ```
+/// use projzst::parse_size;
+///
+/// assert_eq!(parse_size("100M").unwrap(), 100 * 1024 * 1024);
+/// assert_eq!(parse_size("4kib").unwrap(), 4096);
+/// assert!(parse_size("0").is_err());
+/// ```
+pub fn parse_size(s: &str) -> crate::Result<u64> {
+    let invalid = || crate::ProjzstError::InvalidSize(s.to_string());
+
+    let trimmed = s.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (digits, unit) = trimmed.split_at(split);
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(invalid)
+}
@@ -0,0 +1,281 @@
+//! Bringing an extracted tree up to date with an archive
+//!
+//! [`sync`] decodes an archive once and writes only the entries that differ from what is
+//! already in the output directory, rsync-style. A regular file of the right size is
+//! compared byte for byte with its entry as the payload streams by and left alone,
+//! modification time included, when they match; otherwise it is rewritten from the first
+//! difference on. Updating a large deployed tree to a new release therefore writes just
+//! the files that changed and, when asked to, removes those the release no longer has.
+//! Files of chunked archives ([`Packer::chunked`](crate::Packer::chunked)) are compared
+//! with their reassembled content the same way.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::open_archive;
+use crate::chunking::{chunk_digest, chunk_list, ChunkStore};
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::IgnoreUnknown;
+use crate::mtime::{self, MtimePolicy};
+use crate::reflink::set_mode;
+use crate::remap::{PathMap, Writers};
+use crate::storage::normalize_path;
+use crate::temp::temp_file_for;
+use crate::volume::open_input;
+
+/// Bytes compared at a time
+const COMPARE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// What [`sync`] changed in the output directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SyncReport {
+    /// Entries written where nothing was, in archive order
+    pub added: Vec<String>,
+    /// Entries rewritten because they differed, in archive order
+    pub updated: Vec<String>,
+    /// Number of entries already up to date
+    pub unchanged: usize,
+    /// Paths removed for not being in the archive, sorted
+    pub removed: Vec<String>,
+}
+
+impl SyncReport {
+    /// Whether the output directory already matched the archive
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// How an entry compared with what is already on disk
+enum Compared {
+    /// Same content; nothing was written
+    Same,
+    /// Different content, written over the file
+    Rewritten,
+    /// Nothing comparable on disk (missing, another type or another size)
+    Absent,
+}
+
+/// Update a directory to match a .pjz file, writing only the entries that differ
+/// Files are compared by content, so unchanged ones keep their modification time; device
+/// nodes and FIFOs are skipped as in [`crate::unpack`]
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `output_dir` - Directory to update, created if missing
+/// * `delete` - Also remove files and directories the archive does not have
+pub fn sync<P1, P2>(input_file: P1, output_dir: P2, delete: bool) -> Result<SyncReport>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    sync_from_reader(open_input(input_file.as_ref())?, output_dir, delete)
+}
+
+/// Update a directory to match a .pjz stream, see [`sync`]
+///
+/// # Arguments
+/// * `reader` - The .pjz stream, read once from start to end
+/// * `output_dir` - Directory to update, created if missing
+/// * `delete` - Also remove files and directories the archive does not have
+pub fn sync_from_reader<R, P>(reader: R, output_dir: P, delete: bool) -> Result<SyncReport>
+where
+    R: Read,
+    P: AsRef<Path>,
+{
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir)?;
+    let output_dir = &output_dir.canonicalize()?;
+    let (_, mut tar_archive) = open_archive(reader, IgnoreUnknown::On)?;
+    let map = PathMap::default();
+    let chunks = ChunkStore::new(output_dir);
+    let writers = Writers {
+        chunks: Some(&chunks),
+        ..Writers::default()
+    };
+
+    let mut report = SyncReport::default();
+    let mut kept = BTreeSet::new();
+    let mut directories = Vec::new();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if let Some(digest) = chunk_digest(&entry) {
+            chunks.insert(digest, &mut entry)?;
+            continue;
+        }
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        let mut ancestor = path.as_str();
+        while kept.insert(ancestor.to_string()) {
+            match ancestor.rsplit_once('/') {
+                Some((parent, _)) => ancestor = parent,
+                None => break,
+            }
+        }
+        let entry_type = entry.header().entry_type();
+        let target = output_dir.join(&path);
+        if entry_type.is_dir() {
+            if fs::symlink_metadata(&target).is_ok_and(|info| !info.is_dir()) {
+                fs::remove_file(&target)?;
+            }
+            // Last, as in `tar::Archive::unpack`, so their permissions do not get in the way
+            directories.push((entry, target.is_dir()));
+            continue;
+        }
+
+        check_parent(&target, output_dir, &path)?;
+        let existing = fs::symlink_metadata(&target).ok();
+        let compared = if entry_type == tar::EntryType::Regular {
+            match chunk_list(&mut entry)? {
+                Some(list) if existing.as_ref().is_some_and(fs::Metadata::is_file) => {
+                    if chunks.matches(&list, &target)? {
+                        Compared::Same
+                    } else {
+                        Compared::Absent
+                    }
+                }
+                Some(_) => Compared::Absent,
+                None => sync_file(&mut entry, &target, existing.as_ref())
+                    .map_err(|e| e.extracting(path.as_str()))?,
+            }
+        } else if entry_type.is_symlink() {
+            let link = entry.link_name()?.unwrap_or_default();
+            match fs::read_link(&target) {
+                Ok(current) if current == link => Compared::Same,
+                _ => Compared::Absent,
+            }
+        } else {
+            Compared::Absent
+        };
+
+        match compared {
+            Compared::Same => report.unchanged += 1,
+            Compared::Rewritten => {
+                log::info!("{path} ({} bytes, updated)", entry.size());
+                report.updated.push(path);
+            }
+            Compared::Absent => {
+                match &existing {
+                    Some(info) if info.is_dir() => fs::remove_dir_all(&target)?,
+                    Some(_) => fs::remove_file(&target)?,
+                    None => {}
+                }
+                if map.unpack_entry(&mut entry, output_dir, writers)?.is_some() {
+                    match existing {
+                        Some(_) => report.updated.push(path),
+                        None => report.added.push(path),
+                    }
+                }
+            }
+        }
+    }
+    directories.sort_by(|(a, _), (b, _)| b.path_bytes().cmp(&a.path_bytes()));
+    for (mut directory, existed) in directories {
+        if let Some(path) = map.unpack_entry(&mut directory, output_dir, Writers::default())? {
+            if existed {
+                report.unchanged += 1;
+            } else {
+                report.added.push(path);
+            }
+        }
+    }
+    // The chunk spool lives in the output directory until here
+    drop(chunks);
+
+    if delete {
+        remove_extraneous(output_dir, "", &kept, &mut report.removed)?;
+        report.removed.sort();
+    }
+    Ok(report)
+}
+
+/// Internal helper: compare a regular file entry with `target` while reading it, writing
+/// the entry over `target` from the first difference on
+/// `existing` is what is at `target` now; only a regular file of the entry's size is compared
+fn sync_file<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    target: &Path,
+    existing: Option<&fs::Metadata>,
+) -> Result<Compared> {
+    let size = entry.size();
+    if !existing.is_some_and(|info| info.is_file() && info.len() == size) {
+        return Ok(Compared::Absent);
+    }
+    let mtime = mtime::entry_mtime(entry)?;
+    let mode = entry.header().mode()?;
+    let mut local = File::open(target)?;
+    let mut archived = vec![0; COMPARE_BLOCK_SIZE];
+    let mut current = vec![0; COMPARE_BLOCK_SIZE];
+    let mut offset = 0;
+    while offset < size {
+        let len = COMPARE_BLOCK_SIZE.min((size - offset) as usize);
+        entry.read_exact(&mut archived[..len])?;
+        local.read_exact(&mut current[..len])?;
+        if archived[..len] != current[..len] {
+            // Equal so far: keep that part, take the rest from the archive
+            let (mut output, temp) = temp_file_for(target)?;
+            io::copy(&mut File::open(target)?.take(offset), &mut output)?;
+            output.write_all(&archived[..len])?;
+            io::copy(entry, &mut output)?;
+            set_mode(&output, mode)?;
+            drop(output);
+            temp.persist(target)?;
+            mtime::apply(MtimePolicy::default(), target, mtime)?;
+            return Ok(Compared::Rewritten);
+        }
+        offset += len as u64;
+    }
+    Ok(Compared::Same)
+}
+
+/// Internal helper: fail with [`ProjzstError::UnsafeEntryPath`] when a symlink leads
+/// the parent of `target` out of `output_dir`
+fn check_parent(target: &Path, output_dir: &Path, path: &str) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+        if !parent.canonicalize()?.starts_with(output_dir) {
+            return Err(ProjzstError::UnsafeEntryPath(path.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Internal helper: remove what is below `dir` (at `relative` in the archive) and not in
+/// `kept`, adding the removed paths to `removed`
+fn remove_extraneous(
+    dir: &Path,
+    relative: &str,
+    kept: &BTreeSet<String>,
+    removed: &mut Vec<String>,
+) -> Result<()> {
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{relative}/{name}")
+        };
+        let file_type = item.file_type()?;
+        if kept.contains(&path) {
+            if file_type.is_dir() {
+                remove_extraneous(&item.path(), &path, kept, removed)?;
+            }
+            continue;
+        }
+        log::info!("removed {path}");
+        if file_type.is_dir() {
+            fs::remove_dir_all(item.path())?;
+        } else {
+            fs::remove_file(item.path())?;
+        }
+        removed.push(path);
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//! Tar header formats
+//!
+//! Packs write GNU headers by default, with GNU extension entries for paths and link
+//! targets over 100 bytes. [`TarFormat::Pax`] writes POSIX ustar headers and puts what does
+//! not fit (long or non-ASCII paths, files of 8 GiB and more) into PAX extended headers;
+//! [`TarFormat::Ustar`] writes plain ustar headers and refuses entries they cannot hold.
+
+use std::io::{self, Read, Write};
+
+use crate::errors::{ProjzstError, Result};
+use crate::string_utils::IntoOpStr;
+
+/// Largest size an octal ustar size field holds (8 GiB - 1)
+const MAX_USTAR_SIZE: u64 = 0o77777777777;
+
+/// Header format of the entries of a pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TarFormat {
+    /// GNU headers with GNU long-name entries (default)
+    #[default]
+    Gnu,
+    /// POSIX.1-2001 ustar headers with PAX extended headers where needed
+    Pax,
+    /// POSIX.1-1988 ustar headers only: paths up to 255 bytes, files under 8 GiB
+    Ustar,
+}
+
+impl TarFormat {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "gnu" => Ok(TarFormat::Gnu),
+            "pax" | "posix" => Ok(TarFormat::Pax),
+            "ustar" => Ok(TarFormat::Ustar),
+            _ => Err(ProjzstError::InvalidTarFormat(s.to_string())),
+        }
+    }
+
+    /// Lowercase name of the format
+    pub fn name(self) -> &'static str {
+        match self {
+            TarFormat::Gnu => "gnu",
+            TarFormat::Pax => "pax",
+            TarFormat::Ustar => "ustar",
+        }
+    }
+
+    /// Internal helper: an empty header of this format
+    pub(crate) fn header(self) -> tar::Header {
+        match self {
+            TarFormat::Gnu => tar::Header::new_gnu(),
+            TarFormat::Pax | TarFormat::Ustar => tar::Header::new_ustar(),
+        }
+    }
+}
+
+/// Append an entry named `path`, linking to `link` if given, with `header` (made by
+/// [`TarFormat::header`]) carrying everything else
+/// `mtime`, a PAX time, goes into a PAX extended header unless the format is
+/// [`TarFormat::Ustar`]
+pub(crate) fn append_entry<W: Write, R: Read>(
+    tar_builder: &mut tar::Builder<W>,
+    format: TarFormat,
+    header: tar::Header,
+    path: &str,
+    link: Option<&str>,
+    mtime: Option<&str>,
+    data: R,
+) -> Result<()> {
+    let mtime = mtime.filter(|_| format != TarFormat::Ustar);
+    let records: Vec<(&str, &str)> = mtime.map(|mtime| ("mtime", mtime)).into_iter().collect();
+    append_entry_with(tar_builder, format, header, path, link, &records, data)
+}
+
+/// Append an entry like [`append_entry`], with the PAX `records` (key and value) given
+/// Fails with [`ProjzstError::TarFormatLimit`] if there are any and the format is
+/// [`TarFormat::Ustar`]
+pub(crate) fn append_entry_with<W: Write, R: Read>(
+    tar_builder: &mut tar::Builder<W>,
+    format: TarFormat,
+    mut header: tar::Header,
+    path: &str,
+    link: Option<&str>,
+    records: &[(&str, &str)],
+    data: R,
+) -> Result<()> {
+    if format == TarFormat::Ustar && !records.is_empty() {
+        return Err(ProjzstError::TarFormatLimit {
+            path: path.to_string(),
+            format: format.name().to_string(),
+            reason: "entry needs PAX records".to_string(),
+        });
+    }
+    let records = records
+        .iter()
+        .fold(Vec::new(), |mut records, (key, value)| {
+            pax_record(&mut records, key, value);
+            records
+        });
+    if format != TarFormat::Gnu {
+        return append_ustar(tar_builder, format, header, path, link, records, data);
+    }
+    // GNU readers take PAX headers too; the long-name entries still carry the paths
+    append_pax_header(tar_builder, path, &records)?;
+    match link {
+        Some(link) => tar_builder.append_link(&mut header, path, link)?,
+        None => tar_builder.append_data(&mut header, path, data)?,
+    }
+    Ok(())
+}
+
+/// Internal helper: append an entry in a ustar-based format
+/// What the header cannot hold goes into a PAX extended header ([`TarFormat::Pax`]) or
+/// fails with [`ProjzstError::TarFormatLimit`] ([`TarFormat::Ustar`])
+fn append_ustar<W: Write, R: Read>(
+    tar_builder: &mut tar::Builder<W>,
+    format: TarFormat,
+    mut header: tar::Header,
+    path: &str,
+    link: Option<&str>,
+    mut records: Vec<u8>,
+    data: R,
+) -> Result<()> {
+    let limit = |reason: &str| ProjzstError::TarFormatLimit {
+        path: path.to_string(),
+        format: format.name().to_string(),
+        reason: reason.to_string(),
+    };
+    // PAX paths are UTF-8 by definition, header paths are whatever bytes the reader assumes
+    if header.set_path(path).is_err() || (format == TarFormat::Pax && !path.is_ascii()) {
+        if format == TarFormat::Ustar {
+            return Err(limit("path does not fit in 255 bytes"));
+        }
+        pax_record(&mut records, "path", path);
+        header.set_path(fallback_name(path))?;
+    }
+    if let Some(link) = link {
+        if header.set_link_name(link).is_err() || (format == TarFormat::Pax && !link.is_ascii()) {
+            if format == TarFormat::Ustar {
+                return Err(limit("link target does not fit in 100 bytes"));
+            }
+            pax_record(&mut records, "linkpath", link);
+            header.set_link_name(fallback_name(link))?;
+        }
+    }
+    let size = header.size()?;
+    if size > MAX_USTAR_SIZE {
+        if format == TarFormat::Ustar {
+            return Err(limit("file is 8 GiB or larger"));
+        }
+        pax_record(&mut records, "size", &size.to_string());
+    }
+
+    append_pax_header(tar_builder, path, &records)?;
+    header.set_cksum();
+    tar_builder.append(&header, data)?;
+    Ok(())
+}
+
+/// Internal helper: append `body` as the content of a regular file entry `name` whose
+/// size `header` gives, failing with [`ProjzstError::SourceSizeChanged`] when `body` ends
+/// early or goes on after it (a file that changed since it was listed)
+pub(crate) fn append_exact<W: Write, R: Read>(
+    tar_builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    name: &str,
+    body: R,
+) -> Result<()> {
+    let expected = header.entry_size()?;
+    let mut body = ExactReader {
+        inner: body,
+        remaining: expected,
+        ended: false,
+    };
+    let appended = tar_builder.append_data(header, name, &mut body);
+    let changed = || ProjzstError::SourceSizeChanged {
+        path: name.to_string(),
+        expected,
+    };
+    if body.ended {
+        return Err(changed());
+    }
+    appended?;
+    if body.inner.read(&mut [0])? != 0 {
+        return Err(changed());
+    }
+    Ok(())
+}
+
+/// Reader of the first `remaining` bytes of `inner`, failing if it ends before
+struct ExactReader<R> {
+    inner: R,
+    remaining: u64,
+    /// Whether `inner` ended early
+    ended: bool,
+}
+
+impl<R: Read> Read for ExactReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let len = self.remaining.min(buf.len() as u64) as usize;
+        let read = self.inner.read(&mut buf[..len])?;
+        if read == 0 && len > 0 {
+            self.ended = true;
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "source ended before its listed size",
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Internal helper: append the PAX extended header of the entry at `path`, if it has
+/// any records
+fn append_pax_header<W: Write>(
+    tar_builder: &mut tar::Builder<W>,
+    path: &str,
+    records: &[u8],
+) -> Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let mut pax_header = tar::Header::new_ustar();
+    pax_header.set_path(format!("PaxHeaders/{}", fallback_name(path)))?;
+    pax_header.set_entry_type(tar::EntryType::XHeader);
+    pax_header.set_mode(0o644);
+    pax_header.set_size(records.len() as u64);
+    pax_header.set_cksum();
+    tar_builder.append(&pax_header, records)?;
+    Ok(())
+}
+
+/// Internal helper: add a `LEN key=value\n` record, `LEN` counting the whole record
+fn pax_record(records: &mut Vec<u8>, key: &str, value: &str) {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + rest.to_string().len();
+    if len.to_string().len() > rest.to_string().len() {
+        len += 1;
+    }
+    records.extend_from_slice(format!("{len} {key}={value}\n").as_bytes());
+}
+
+/// Internal helper: an ASCII name of at most 99 bytes standing in for `path` in the
+/// ustar header, for readers that ignore PAX headers
+fn fallback_name(path: &str) -> String {
+    let name = path
+        .rsplit('/')
+        .find(|part| !part.is_empty())
+        .unwrap_or(path);
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    name[name.len().saturating_sub(99)..].to_string()
+}
@@ -0,0 +1,112 @@
+//! Temporary files for intermediate output
+//!
+//! Intermediate files are created next to their final destination, so moving them into
+//! place is a rename within one filesystem. Names are randomized per call, so operations
+//! running concurrently in one process (or in several) never pick the same name, and a
+//! guard removes whatever an interrupted operation leaves behind.
+
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::errors::Result;
+
+/// Names tried before giving up on finding an unused one
+const MAX_ATTEMPTS: u32 = 16;
+
+/// Temporary names handed out so far in this process, mixed into every new name
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Guard for a temporary file, removed on drop unless persisted
+#[derive(Debug)]
+pub(crate) struct TempPath {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl TempPath {
+    /// Path of the temporary file
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the file into place at `target`, replacing any file already there
+    pub(crate) fn persist(mut self, target: &Path) -> Result<()> {
+        fs::rename(&self.path, target)?;
+        self.armed = false;
+        Ok(())
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.armed {
+            // Best effort: the operation that failed reports its own error
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Create a new empty file in the directory of `target`, to be persisted as `target`
+/// The name is `.<target name>.<random>.tmp`
+pub(crate) fn temp_file_for(target: &Path) -> io::Result<(File, TempPath)> {
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = target
+        .file_name()
+        .map_or_else(|| "projzst".into(), |name| name.to_string_lossy());
+    create_in(dir, &format!(".{name}"))
+}
+
+/// Create a new empty file in the system temporary directory, for intermediate output
+/// that has no destination on disk
+/// The name is `projzst.<random>.tmp`
+pub(crate) fn temp_file() -> io::Result<(File, TempPath)> {
+    create_in(&std::env::temp_dir(), "projzst")
+}
+
+/// Internal helper: create a new file named `<stem>.<random>.tmp` in `dir`
+fn create_in(dir: &Path, stem: &str) -> io::Result<(File, TempPath)> {
+    for _ in 0..MAX_ATTEMPTS {
+        let path = dir.join(format!("{stem}.{:016x}.tmp", random_suffix()));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, TempPath { path, armed: true })),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no unused temporary name in {}", dir.display()),
+    ))
+}
+
+/// Flush the directory holding `path` to disk, making a rename into it durable
+/// Directories cannot be opened for syncing on Windows, where this does nothing
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Internal helper: random 64 bits, distinct for every call in this process
+fn random_suffix() -> u64 {
+    // Every RandomState is seeded with fresh random keys
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
@@ -0,0 +1,168 @@
+//! Metadata templates expanded at pack time
+//!
+//! Metadata values and `extra` strings may hold `${NAME}` placeholders, replaced from a
+//! [`TemplateVars`] when packing. Only the variables it defines expand: the built-in
+//! `${date}` (`2024-05-01`), `${datetime}` (`2024-05-01T12:00:00Z`) and `${timestamp}`
+//! (Unix seconds, all UTC and taken from `SOURCE_DATE_EPOCH` when set), `${GIT_SHA}` and
+//! `${GIT_BRANCH}` once [`TemplateVars::git`] found a repository, variables set with
+//! [`TemplateVars::set`], and `${env:NAME}` for environment variables. Anything else is
+//! an error rather than being left in place; `$$` stands for a literal `$`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use crate::date::civil_from_days;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::Metadata;
+
+/// Variables available to metadata templates
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    vars: BTreeMap<String, String>,
+}
+
+impl TemplateVars {
+    /// Create a variable set holding the built-in date variables
+    pub fn new() -> Self {
+        let secs = env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs())
+            });
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let rem = secs % 86_400;
+        let date = format!("{year:04}-{month:02}-{day:02}");
+        let datetime = format!(
+            "{date}T{:02}:{:02}:{:02}Z",
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60
+        );
+        Self::default()
+            .set("date", date)
+            .set("datetime", datetime)
+            .set("timestamp", secs.to_string())
+    }
+
+    /// Add `GIT_SHA` and `GIT_BRANCH` from the git repository containing `dir`
+    /// Leaves them undefined when `dir` is not in a repository or git is not installed
+    pub fn git<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(dir.as_ref())
+                .args(args)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+            (!value.is_empty()).then_some(value)
+        };
+        if let Some(sha) = git(&["rev-parse", "HEAD"]) {
+            self.vars.insert("GIT_SHA".to_string(), sha);
+        }
+        if let Some(branch) = git(&["rev-parse", "--abbrev-ref", "HEAD"]) {
+            self.vars.insert("GIT_BRANCH".to_string(), branch);
+        }
+        self
+    }
+
+    /// Define a variable, replacing any previous value
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// The value of a variable, if defined
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    /// Expand every placeholder in `text`
+    /// Fails with [`ProjzstError::UndefinedTemplateVariable`] for a variable not in the set
+    /// and [`ProjzstError::InvalidTemplate`] for an unterminated `${`
+    pub fn expand(&self, text: &str) -> Result<String> {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(idx) = rest.find('$') {
+            expanded.push_str(&rest[..idx]);
+            let tail = &rest[idx..];
+            if let Some(after) = tail.strip_prefix("$$") {
+                expanded.push('$');
+                rest = after;
+                continue;
+            }
+            let Some(body) = tail.strip_prefix("${") else {
+                expanded.push('$');
+                rest = &tail[1..];
+                continue;
+            };
+            let end = body
+                .find('}')
+                .ok_or_else(|| ProjzstError::InvalidTemplate(text.to_string()))?;
+            let name = &body[..end];
+            let value = match name.strip_prefix("env:") {
+                Some(var) => env::var(var).ok(),
+                None => self.vars.get(name).cloned(),
+            };
+            expanded.push_str(
+                &value.ok_or_else(|| ProjzstError::UndefinedTemplateVariable(name.to_string()))?,
+            );
+            rest = &body[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    /// Expand the placeholders of every metadata field, keyword and `extra` string
+    /// Object keys in `extra` are left as they are
+    pub fn expand_metadata(&self, metadata: &mut Metadata) -> Result<()> {
+        for field in [
+            &mut metadata.name,
+            &mut metadata.auth,
+            &mut metadata.fmt,
+            &mut metadata.ed,
+            &mut metadata.ver,
+            &mut metadata.desc,
+            &mut metadata.license,
+            &mut metadata.homepage,
+            &mut metadata.repository,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *field = self.expand(field)?;
+        }
+        for keyword in &mut metadata.keywords {
+            *keyword = self.expand(keyword)?;
+        }
+        self.expand_value(&mut metadata.extra)
+    }
+
+    /// Internal helper: expand the strings inside a JSON value in place
+    fn expand_value(&self, value: &mut Value) -> Result<()> {
+        match value {
+            Value::String(text) => *text = self.expand(text)?,
+            Value::Array(items) => {
+                for item in items {
+                    self.expand_value(item)?;
+                }
+            }
+            Value::Object(map) => {
+                for item in map.values_mut() {
+                    self.expand_value(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+//! RFC 3161 trusted timestamps
+//!
+//! A time-stamping authority (TSA) signs a token stating that a digest existed at a given
+//! time. [`timestamp_archive`] (feature `http`) asks a TSA for a token over the
+//! [`signed_digest`] of an archive and stores it in a `signature` frame of its own,
+//! tagged `PJTS`, next to the signature: the digest leaves both out, so an archive can be
+//! signed and stamped in either order and re-signed without losing its timestamp.
+//!
+//! [`verify_timestamp`] checks that the token covers the archive as it is now and gives
+//! the time. It does not check the TSA's own signature over the token, which needs the
+//! TSA certificate chain: export the token with [`Timestamp::token`] and run
+//! `openssl ts -verify -digest <signed digest> -in token.tsr -token_in -CAfile tsa.pem`.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::errors::{ProjzstError, Result};
+#[cfg(feature = "http")]
+use crate::frames::rewrite_frames;
+use crate::frames::{frames, FrameKind, RawFrame};
+use crate::signing::signed_digest;
+#[cfg(feature = "http")]
+use crate::string_utils::from_hex;
+use crate::string_utils::to_hex;
+
+/// Tag starting the payload of the signature frame holding a timestamp token
+const TIMESTAMP_TAG: &[u8; 4] = b"PJTS";
+
+/// DER content of the object identifier of SHA-256 (2.16.840.1.101.3.4.2.1)
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+/// DER content of the object identifier of CMS signed data (1.2.840.113549.1.7.2)
+const SIGNED_DATA_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+
+/// DER content of the object identifier of TSTInfo (1.2.840.113549.1.9.16.1.4)
+const TST_INFO_OID: &[u8] = &[
+    0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x10, 0x01, 0x04,
+];
+
+/// DER tags read and written here
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const BOOLEAN: u8 = 0x01;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_ID: u8 = 0x06;
+#[cfg(feature = "http")]
+const NULL: u8 = 0x05;
+const GENERALIZED_TIME: u8 = 0x18;
+const EXPLICIT_0: u8 = 0xA0;
+
+/// A trusted timestamp stored in an archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Timestamp {
+    /// Time the TSA vouches for, in UTC, e.g. `2026-10-16T15:13:00Z`
+    pub time: String,
+    /// Hex SHA-256 the token covers: the [`signed_digest`] of the archive when stamped
+    pub digest: String,
+    /// The DER `TimeStampToken` as the TSA returned it
+    #[serde(skip)]
+    pub token: Vec<u8>,
+}
+
+impl Timestamp {
+    /// Internal helper: read the time and digest of a DER token, `None` when it is not a
+    /// SHA-256 `TimeStampToken`
+    fn from_token(token: Vec<u8>) -> Option<Self> {
+        let info = tst_info(&token)?;
+        Some(Self {
+            time: info.time,
+            digest: to_hex(&info.digest),
+            token,
+        })
+    }
+}
+
+/// Fields of a `TSTInfo` checked here
+struct TstInfo {
+    digest: Vec<u8>,
+    time: String,
+    /// Only compared with the request, when there is one
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    nonce: Option<Vec<u8>>,
+}
+
+/// Get a timestamp token for a .pjz file from a TSA and store it in place
+/// Replaces any earlier timestamp and keeps the signature. Fails with
+/// [`ProjzstError::Remote`] when the TSA cannot be reached or refuses, and with
+/// [`ProjzstError::TimestampInvalid`] when its token is not for the request sent
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `tsa_url` - URL of the TSA, taking `application/timestamp-query` POST requests
+#[cfg(feature = "http")]
+pub fn timestamp_archive<P: AsRef<Path>>(input_file: P, tsa_url: &str) -> Result<Timestamp> {
+    use std::io::Read;
+
+    let input_file = input_file.as_ref();
+    let digest = from_hex::<32>(&signed_digest(input_file)?).expect("signed digests are hex");
+    let nonce = nonce(&digest);
+    let response = ureq::post(tsa_url)
+        .set("Content-Type", "application/timestamp-query")
+        .send_bytes(&timestamp_request(&digest, &nonce))
+        .map_err(|e| ProjzstError::Remote(format!("POST {tsa_url} failed: {e}")))?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(crate::frames::MAX_FRAME_SIZE as u64)
+        .read_to_end(&mut body)?;
+
+    let token = response_token(&body).map_err(|message| {
+        ProjzstError::Remote(format!("{tsa_url} returned no timestamp: {message}"))
+    })?;
+    let invalid = |message: &str| ProjzstError::TimestampInvalid(format!("{tsa_url}: {message}"));
+    let info = tst_info(&token).ok_or_else(|| invalid("malformed token"))?;
+    if info.digest != digest {
+        return Err(invalid("the token is for another digest"));
+    }
+    if info.nonce.as_deref() != Some(&nonce[..]) {
+        return Err(invalid("the token does not answer this request"));
+    }
+
+    let mut payload = TIMESTAMP_TAG.to_vec();
+    payload.extend_from_slice(&token);
+    rewrite_frames(input_file, |frames| {
+        let position = frames
+            .iter()
+            .position(is_timestamp_frame)
+            .or_else(|| {
+                frames
+                    .iter()
+                    .rposition(|frame| frame.kind() == FrameKind::Signature)
+                    .map(|last| last + 1)
+            })
+            .unwrap_or(frames.len());
+        frames.retain(|frame| !is_timestamp_frame(frame));
+        let frame = RawFrame {
+            magic: FrameKind::Signature.magic(),
+            payload,
+        };
+        frames.insert(position.min(frames.len()), frame);
+    })?;
+    Ok(Timestamp {
+        time: info.time,
+        digest: to_hex(&digest),
+        token,
+    })
+}
+
+/// Read the timestamp stored in a .pjz file, if it has one
+/// Does not check that it covers the archive, see [`verify_timestamp`]; fails with
+/// [`ProjzstError::TimestampInvalid`] on a token that does not parse
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn timestamp<P: AsRef<Path>>(input_file: P) -> Result<Option<Timestamp>> {
+    frames(input_file)?
+        .into_iter()
+        .find(is_timestamp_frame)
+        .map(|frame| {
+            Timestamp::from_token(frame.payload[TIMESTAMP_TAG.len()..].to_vec()).ok_or_else(|| {
+                ProjzstError::TimestampInvalid("malformed timestamp token".to_string())
+            })
+        })
+        .transpose()
+}
+
+/// Check that the timestamp of a .pjz file covers the archive as it is now
+/// Returns the timestamp. Fails with [`ProjzstError::TimestampInvalid`] when there is
+/// none or the archive changed since it was stamped; the TSA's signature over the token
+/// is not checked, see the [module documentation](self)
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn verify_timestamp<P: AsRef<Path>>(input_file: P) -> Result<Timestamp> {
+    let input_file = input_file.as_ref();
+    let stamp = timestamp(input_file)?.ok_or_else(|| {
+        ProjzstError::TimestampInvalid("the archive has no timestamp".to_string())
+    })?;
+    if stamp.digest != signed_digest(input_file)? {
+        return Err(ProjzstError::TimestampInvalid(
+            "the archive changed since it was stamped".to_string(),
+        ));
+    }
+    Ok(stamp)
+}
+
+/// Internal helper: whether a frame holds a timestamp token rather than a signature
+pub(crate) fn is_timestamp_frame(frame: &RawFrame) -> bool {
+    frame.kind() == FrameKind::Signature && frame.payload.starts_with(TIMESTAMP_TAG)
+}
+
+/// Internal helper: DER `TimeStampReq` for a SHA-256 digest, asking for the TSA
+/// certificate in the token
+#[cfg(feature = "http")]
+fn timestamp_request(digest: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let algorithm = der(
+        SEQUENCE,
+        &[der(OBJECT_ID, SHA256_OID), der(NULL, &[])].concat(),
+    );
+    let imprint = der(SEQUENCE, &[algorithm, der(OCTET_STRING, digest)].concat());
+    let fields = [
+        der(INTEGER, &[1]),
+        imprint,
+        der(INTEGER, nonce),
+        der(BOOLEAN, &[0xFF]),
+    ];
+    der(SEQUENCE, &fields.concat())
+}
+
+/// Internal helper: a positive 64-bit nonce, from the digest and the current time
+#[cfg(feature = "http")]
+fn nonce(digest: &[u8]) -> Vec<u8> {
+    use sha2::{Digest, Sha256};
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(digest);
+    hasher.update(now.as_nanos().to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    let mut nonce = hasher.finalize()[..8].to_vec();
+    // Minimal DER for a positive integer: first byte non-zero, high bit clear
+    nonce[0] = (nonce[0] & 0x7F).max(1);
+    nonce
+}
+
+/// Internal helper: the `TimeStampToken` of a DER `TimeStampResp`, or why there is none
+#[cfg(feature = "http")]
+fn response_token(response: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let malformed = || "malformed response".to_string();
+    let (response, _) = der_expect(response, SEQUENCE).ok_or_else(malformed)?;
+    let (status_info, token) = der_expect(response, SEQUENCE).ok_or_else(malformed)?;
+    let (status, _) = der_expect(status_info, INTEGER).ok_or_else(malformed)?;
+    // granted (0) or grantedWithMods (1)
+    if status != [0] && status != [1] {
+        return Err(format!("status {}", status.last().copied().unwrap_or(0)));
+    }
+    let (_, rest) = der_expect(token, SEQUENCE).ok_or_else(malformed)?;
+    Ok(token[..token.len() - rest.len()].to_vec())
+}
+
+/// Internal helper: the digest, time and nonce of the `TSTInfo` in a `TimeStampToken`
+fn tst_info(token: &[u8]) -> Option<TstInfo> {
+    let (content_info, _) = der_expect(token, SEQUENCE)?;
+    let (oid, rest) = der_expect(content_info, OBJECT_ID)?;
+    if oid != SIGNED_DATA_OID {
+        return None;
+    }
+    let (signed_data, _) = der_expect(der_expect(rest, EXPLICIT_0)?.0, SEQUENCE)?;
+    let (_, rest) = der_expect(signed_data, INTEGER)?;
+    let (_, rest) = der_expect(rest, SET)?;
+    let (encapsulated, _) = der_expect(rest, SEQUENCE)?;
+    let (oid, rest) = der_expect(encapsulated, OBJECT_ID)?;
+    if oid != TST_INFO_OID {
+        return None;
+    }
+    let (info, _) = der_expect(der_expect(rest, EXPLICIT_0)?.0, OCTET_STRING)?;
+
+    let (info, _) = der_expect(info, SEQUENCE)?;
+    let (_, rest) = der_expect(info, INTEGER)?;
+    let (_, rest) = der_expect(rest, OBJECT_ID)?;
+    let (imprint, rest) = der_expect(rest, SEQUENCE)?;
+    let (algorithm, hashed) = der_expect(imprint, SEQUENCE)?;
+    if der_expect(algorithm, OBJECT_ID)?.0 != SHA256_OID {
+        return None;
+    }
+    let (digest, _) = der_expect(hashed, OCTET_STRING)?;
+    let (_, rest) = der_expect(rest, INTEGER)?;
+    let (time, mut rest) = der_expect(rest, GENERALIZED_TIME)?;
+    // Optional accuracy and ordering come before the nonce
+    let mut nonce = None;
+    while let Some((tag, content, after)) = der_element(rest) {
+        match tag {
+            SEQUENCE | BOOLEAN => rest = after,
+            INTEGER => {
+                nonce = Some(content.to_vec());
+                break;
+            }
+            _ => break,
+        }
+    }
+    Some(TstInfo {
+        digest: digest.to_vec(),
+        time: rfc3339(std::str::from_utf8(time).ok()?),
+        nonce,
+    })
+}
+
+/// Internal helper: a `GeneralizedTime` (`YYYYMMDDHHMMSS[.f]Z`) in RFC 3339, unchanged
+/// when it has another shape
+fn rfc3339(time: &str) -> String {
+    let digits = time
+        .get(..14)
+        .filter(|d| d.bytes().all(|b| b.is_ascii_digit()));
+    match (digits, time.ends_with('Z')) {
+        (Some(d), true) => format!(
+            "{}-{}-{}T{}:{}:{}{}",
+            &d[..4],
+            &d[4..6],
+            &d[6..8],
+            &d[8..10],
+            &d[10..12],
+            &d[12..14],
+            &time[14..]
+        ),
+        _ => time.to_string(),
+    }
+}
+
+/// Internal helper: DER element of `tag` holding `content`
+#[cfg(feature = "http")]
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        element.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        element.push(0x80 | bytes.len() as u8);
+        element.extend_from_slice(&bytes);
+    }
+    element.extend_from_slice(content);
+    element
+}
+
+/// Internal helper: split the first DER element off `bytes`: its tag, its content and the
+/// bytes after it
+fn der_element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7F);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, &b| len << 8 | usize::from(b));
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Internal helper: the content of the first DER element of `bytes` and the bytes after
+/// it, when its tag is `tag`
+fn der_expect(bytes: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    der_element(bytes)
+        .filter(|(found, _, _)| *found == tag)
+        .map(|(_, content, rest)| (content, rest))
+}
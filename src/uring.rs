@@ -0,0 +1,239 @@
+//! Batched extraction writes through io_uring (Linux, `io-uring` feature)
+//!
+//! With [`ReadOptions::io_uring`](crate::ReadOptions::io_uring), small regular files are
+//! not written one by one as the tar stream is read. Their content is queued, and every
+//! batch goes to the kernel in a few submissions: the opens of all its files, then their
+//! writes (each followed by an fsync under [`ReadOptions::sync`](crate::ReadOptions::sync)),
+//! instead of three to four system calls per file. Archives of many small files extract
+//! faster; larger files are written as usual. A batch is written before any entry that is
+//! not a regular file, so links and directories always find the files they refer to.
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fs::{File, Permissions};
+use std::io;
+use std::os::fd::FromRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use filetime::FileTime;
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::errors::{ProjzstError, Result};
+
+/// Files queued before a batch is written
+const BATCH_FILES: usize = 128;
+
+/// Bytes queued before a batch is written
+const BATCH_BYTES: usize = 8 * 1024 * 1024;
+
+/// Largest file queued; larger ones gain nothing from batching
+const MAX_QUEUED_SIZE: u64 = 1024 * 1024;
+
+/// Submission queue depth: a write and an fsync for every file of a batch
+const RING_ENTRIES: u32 = 2 * BATCH_FILES as u32;
+
+/// Writer batching the files of an extraction through io_uring
+pub(crate) struct UringWriter {
+    ring: RefCell<IoUring>,
+    pending: RefCell<Vec<PendingFile>>,
+    sync: bool,
+}
+
+/// A file queued for the next batch
+struct PendingFile {
+    /// Mapped path of the entry, for reports
+    path: String,
+    target: CString,
+    content: Vec<u8>,
+    mode: u32,
+    mtime: Option<FileTime>,
+}
+
+impl UringWriter {
+    /// Set up a ring, `None` when the kernel does not offer io_uring (or forbids it)
+    /// With `sync`, every file is flushed to disk before its batch completes
+    pub(crate) fn new(sync: bool) -> Option<Self> {
+        match IoUring::new(RING_ENTRIES) {
+            Ok(ring) => Some(Self {
+                ring: RefCell::new(ring),
+                pending: RefCell::new(Vec::new()),
+                sync,
+            }),
+            Err(e) => {
+                log::debug!("io_uring unavailable, writing files directly: {e}");
+                None
+            }
+        }
+    }
+
+    /// Whether a file of `size` bytes is worth queueing
+    pub(crate) fn takes(&self, size: u64) -> bool {
+        size <= MAX_QUEUED_SIZE
+    }
+
+    /// Whether the current batch is complete and should be written
+    pub(crate) fn is_full(&self) -> bool {
+        let pending = self.pending.borrow();
+        pending.len() >= BATCH_FILES
+            || pending.iter().map(|file| file.content.len()).sum::<usize>() >= BATCH_BYTES
+    }
+
+    /// Whether the file extracted as `path` is waiting in the current batch
+    pub(crate) fn is_pending(&self, path: &str) -> bool {
+        self.pending.borrow().iter().any(|file| file.path == path)
+    }
+
+    /// Queue a file for the next batch, replacing one queued for the same target
+    /// The target must not exist; `mode` and `mtime` are set once it is written
+    ///
+    /// # Arguments
+    /// * `path` - Mapped path of the entry, reported on failure
+    /// * `target` - Where to create the file
+    /// * `content` - Content of the file
+    /// * `mode` - Permission bits, as the tar crate sets them
+    /// * `mtime` - Modification time to set, `None` to keep the time of writing
+    pub(crate) fn queue(
+        &self,
+        path: String,
+        target: &Path,
+        content: Vec<u8>,
+        mode: u32,
+        mtime: Option<FileTime>,
+    ) -> Result<()> {
+        let target = CString::new(target.as_os_str().as_bytes())
+            .map_err(|_| ProjzstError::UnsafeEntryPath(path.clone()))?;
+        let mut pending = self.pending.borrow_mut();
+        pending.retain(|file| file.target != target);
+        pending.push(PendingFile {
+            path,
+            target,
+            content,
+            mode,
+            mtime,
+        });
+        Ok(())
+    }
+
+    /// Write the queued files, returning those that failed with the reason
+    pub(crate) fn flush(&self) -> Vec<(String, ProjzstError)> {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        if pending.is_empty() {
+            return Vec::new();
+        }
+        match self.write_batch(&pending) {
+            Ok(failed) => failed,
+            Err(e) => {
+                // Operations may still be in flight and point into the batch: keep it
+                let message = e.to_string();
+                let failed = pending
+                    .iter()
+                    .map(|file| {
+                        let e = io::Error::new(e.kind(), message.clone());
+                        (file.path.clone(), ProjzstError::from(e))
+                    })
+                    .collect();
+                std::mem::forget(pending);
+                failed
+            }
+        }
+    }
+
+    /// Internal helper: open, write and close a batch, returning the files that failed
+    /// Fails as a whole only when the ring itself does
+    fn write_batch(&self, pending: &[PendingFile]) -> io::Result<Vec<(String, ProjzstError)>> {
+        let mut failed = Vec::new();
+        let mut fail = |file: &PendingFile, e: io::Error| {
+            failed.push((file.path.clone(), ProjzstError::from(e)));
+        };
+
+        let opens: Vec<squeue::Entry> = pending
+            .iter()
+            .map(|file| {
+                opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), file.target.as_ptr())
+                    .flags(libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC)
+                    .mode(file.mode & 0o777)
+                    .build()
+            })
+            .collect();
+        let mut opened = Vec::new();
+        for (file, result) in pending.iter().zip(self.run(opens)?) {
+            match result {
+                fd if fd >= 0 => {
+                    // SAFETY: the descriptor was just opened for this file and is owned
+                    // by nothing else
+                    let handle = unsafe { File::from_raw_fd(fd) };
+                    // Open modes are subject to the umask, unlike the tar crate's
+                    match handle.set_permissions(Permissions::from_mode(file.mode & 0o777)) {
+                        Ok(()) => opened.push((file, handle)),
+                        Err(e) => fail(file, e),
+                    }
+                }
+                errno => fail(file, io::Error::from_raw_os_error(-errno)),
+            }
+        }
+
+        let mut writes = Vec::new();
+        for (file, handle) in &opened {
+            let fd = types::Fd(std::os::fd::AsRawFd::as_raw_fd(handle));
+            let write =
+                opcode::Write::new(fd, file.content.as_ptr(), file.content.len() as u32).build();
+            if self.sync {
+                writes.push(write.flags(squeue::Flags::IO_LINK));
+                writes.push(opcode::Fsync::new(fd).build());
+            } else {
+                writes.push(write);
+            }
+        }
+        let results = self.run(writes)?;
+        let per_file = if self.sync { 2 } else { 1 };
+        for ((file, handle), results) in opened.into_iter().zip(results.chunks(per_file)) {
+            let written = results[0];
+            let outcome = if written < 0 {
+                Err(io::Error::from_raw_os_error(-written))
+            } else if written as usize != file.content.len() {
+                Err(io::ErrorKind::WriteZero.into())
+            } else if let Some(&synced) = results.get(1).filter(|&&synced| synced < 0) {
+                Err(io::Error::from_raw_os_error(-synced))
+            } else {
+                match file.mtime {
+                    Some(mtime) => filetime::set_file_handle_times(&handle, None, Some(mtime)),
+                    None => Ok(()),
+                }
+            };
+            if let Err(e) = outcome {
+                fail(file, e);
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Internal helper: submit operations and wait for all of them, returning their
+    /// results in order
+    fn run(&self, operations: Vec<squeue::Entry>) -> io::Result<Vec<i32>> {
+        let mut ring = self.ring.borrow_mut();
+        let count = operations.len();
+        for (i, operation) in operations.into_iter().enumerate() {
+            let operation = operation.user_data(i as u64);
+            // SAFETY: the paths and buffers of the operations belong to the batch, which
+            // outlives their completion (or is leaked when the ring fails)
+            unsafe { ring.submission().push(&operation) }
+                .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+        }
+        let mut results = vec![0; count];
+        let mut done = 0;
+        while done < count {
+            match ring.submit_and_wait(count - done) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            for completion in ring.completion() {
+                results[completion.user_data() as usize] = completion.result();
+                done += 1;
+            }
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,83 @@
+//! Per-directory size breakdown of an archive
+//!
+//! [`disk_usage`] reads the tar headers of the payload, without extracting anything, and
+//! adds the uncompressed size of every file to each directory above it, like `du` does
+//! on a file system.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::open_payload_only;
+use crate::errors::Result;
+use crate::storage::normalize_path;
+use crate::volume::open_input;
+
+/// Path under which the totals of the whole archive are reported
+const ROOT: &str = ".";
+
+/// Size of the files below one directory of an archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct DirectoryUsage {
+    /// Directory path relative to the archive root, `.` for the root itself
+    pub path: String,
+    /// Uncompressed size of the files below the directory, at any depth, in bytes
+    pub size: u64,
+    /// Number of files below the directory, at any depth
+    pub files: usize,
+}
+
+/// Total uncompressed size of the files below each directory of a .pjz file, sorted by
+/// path with the root (`.`) first
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+/// * `depth` - Deepest directories listed, `0` for the root alone; `None` lists all
+pub fn disk_usage<P: AsRef<Path>>(
+    input_file: P,
+    depth: Option<usize>,
+) -> Result<Vec<DirectoryUsage>> {
+    let mut totals: BTreeMap<String, (u64, usize)> = BTreeMap::new();
+    totals.insert(ROOT.to_string(), (0, 0));
+
+    let mut tar_archive = open_payload_only(open_input(input_file.as_ref())?)?;
+    for entry in tar_archive.entries()? {
+        let entry = entry?;
+        let Some(path) = normalize_path(&entry.path()?) else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            // Empty directories are listed too
+            if depth.is_none_or(|depth| path.split('/').count() <= depth) {
+                totals.entry(path).or_default();
+            }
+            continue;
+        }
+        if !entry_type.is_file() {
+            continue;
+        }
+        let size = entry.size();
+        let components: Vec<&str> = path.split('/').collect();
+        let parents = components.len() - 1;
+        let listed = depth.map_or(parents, |depth| parents.min(depth));
+        for level in 0..=listed {
+            let directory = if level == 0 {
+                ROOT.to_string()
+            } else {
+                components[..level].join("/")
+            };
+            let total = totals.entry(directory).or_default();
+            total.0 += size;
+            total.1 += 1;
+        }
+    }
+
+    let root = totals.remove(ROOT).unwrap_or_default();
+    Ok(std::iter::once((ROOT.to_string(), root))
+        .chain(totals)
+        .map(|(path, (size, files))| DirectoryUsage { path, size, files })
+        .collect())
+}
@@ -0,0 +1,192 @@
+//! Decode-only integrity checks
+//!
+//! [`validate`] reads a .pjz file the way an unpack would, without writing anything: the
+//! frames are walked by their headers to the end of the file, the metadata is decoded, and
+//! the payload is decompressed through every tar header and entry to the end of the
+//! stream, so content checksums are checked too. Archives carrying the metadata at both
+//! ends must carry the same metadata twice. Damage does not stop it with an error:
+//! the [`ValidationReport`] tells how far the archive is sound and where it breaks, which
+//! is what services screening uploads need to report back.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::builder::{
+    read_metadata, read_metadata_from, SKIPPABLE_FRAME_MAGIC_MAX, SKIPPABLE_FRAME_MAGIC_MIN,
+    ZSTD_FRAME_MAGIC,
+};
+use crate::codec::zstd_decoder;
+use crate::errors::{ProjzstError, Result};
+use crate::metadata::IgnoreUnknown;
+use crate::revisions::trailing_metadata;
+use crate::stat::{read_zstd_frame, Position};
+use crate::volume::{open_input, open_raw_input};
+
+/// What [`validate`] found in an archive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ValidationReport {
+    /// Bytes walked, the file size when every frame is whole
+    pub file_size: u64,
+    /// Skippable frames seen (metadata, index, comment, ...)
+    pub skippable_frames: usize,
+    /// Zstd frames of the payload seen
+    pub zstd_frames: usize,
+    /// Entries whose header and content decoded in full
+    pub entries: usize,
+    /// Bytes of payload decompressed
+    pub uncompressed_size: u64,
+    /// Offset of the first frame cut short or malformed, `None` when every frame is whole
+    pub truncated_at: Option<u64>,
+    /// Entry being decoded when the payload turned out to be damaged
+    pub damaged_entry: Option<String>,
+    /// The first problem found, `None` for a sound archive
+    pub error: Option<String>,
+}
+
+impl ValidationReport {
+    /// Whether the archive decoded in full without a problem
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Fail with [`ProjzstError::Damaged`] unless the archive is sound
+    pub fn check(&self) -> Result<()> {
+        let Some(error) = &self.error else {
+            return Ok(());
+        };
+        let mut message = error.clone();
+        if let Some(offset) = self.truncated_at {
+            message.push_str(&format!(" (frame at offset {offset})"));
+        }
+        if let Some(entry) = &self.damaged_entry {
+            message.push_str(&format!(" (in {entry})"));
+        }
+        Err(ProjzstError::Damaged(message))
+    }
+
+    /// Internal helper: record a problem, keeping the first one
+    fn fail(&mut self, error: impl ToString) {
+        if self.error.is_none() {
+            self.error = Some(error.to_string());
+        }
+    }
+}
+
+/// Check a .pjz file decodes in full, without writing anything
+/// Only failing to open the file is an error; damage is described in the report, see
+/// [`ValidationReport::check`] to turn it into one
+///
+/// # Arguments
+/// * `input_file` - Path to the .pjz file (or the first volume of a split archive)
+pub fn validate<P: AsRef<Path>>(input_file: P) -> Result<ValidationReport> {
+    let input_file = input_file.as_ref();
+    let mut report = ValidationReport::default();
+
+    let mut reader = Position {
+        inner: open_raw_input(input_file)?,
+        position: 0,
+    };
+    if let Err(e) = walk_frames(&mut reader, &mut report) {
+        report.fail(e);
+    }
+    report.file_size = reader.position;
+
+    if let Err(e) = read_metadata(input_file, IgnoreUnknown::On) {
+        report.fail(e);
+    } else {
+        match trailer_matches(input_file) {
+            Ok(true) => {}
+            Ok(false) => report.fail("metadata trailer does not match the metadata at the start"),
+            Err(e) => report.fail(e),
+        }
+    }
+
+    // The decoder skips the skippable frames on its own
+    let mut payload = Position {
+        inner: zstd_decoder(open_input(input_file)?)?,
+        position: 0,
+    };
+    if let Err(e) = decode_entries(&mut payload, &mut report) {
+        report.fail(e);
+    } else if let Err(e) = io::copy(&mut payload, &mut io::sink()) {
+        // Past the end-of-archive marker, to the end of the stream
+        report.fail(ProjzstError::from(e));
+    }
+    report.uncompressed_size = payload.position;
+    log::info!(
+        "{}: {} frames, {} entries, {}",
+        input_file.display(),
+        report.skippable_frames + report.zstd_frames,
+        report.entries,
+        report.error.as_deref().unwrap_or("valid")
+    );
+    Ok(report)
+}
+
+/// Internal helper: whether the metadata trailer, if any, holds the metadata at the start
+/// of the file, for archives packed with the metadata at both ends
+fn trailer_matches(input_file: &Path) -> Result<bool> {
+    let Some(trailer) = trailing_metadata(input_file, IgnoreUnknown::On)? else {
+        return Ok(true);
+    };
+    // Files starting with the payload read the trailer as their header
+    let header = read_metadata_from(open_input(input_file)?, IgnoreUnknown::On)?;
+    Ok(header == trailer)
+}
+
+/// Internal helper: walk the frames by their headers to the end of the file
+/// Leaves `truncated_at` at the frame that could not be read
+fn walk_frames<R: Read>(reader: &mut Position<R>, report: &mut ValidationReport) -> Result<()> {
+    loop {
+        let offset = reader.position;
+        let walked = match reader.read_magic() {
+            Ok(None) => return Ok(()),
+            Ok(Some(magic))
+                if (SKIPPABLE_FRAME_MAGIC_MIN..=SKIPPABLE_FRAME_MAGIC_MAX).contains(&magic) =>
+            {
+                report.skippable_frames += 1;
+                reader
+                    .read_u32()
+                    .and_then(|size| reader.skip(u64::from(size)))
+                    .map_err(ProjzstError::from)
+            }
+            Ok(Some(ZSTD_FRAME_MAGIC)) => {
+                report.zstd_frames += 1;
+                read_zstd_frame(reader).map(|_| ())
+            }
+            Ok(Some(magic)) => Err(ProjzstError::InvalidFrameMagic(magic)),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = walked {
+            report.truncated_at = Some(offset);
+            return Err(e);
+        }
+    }
+}
+
+/// Internal helper: decode every tar header and entry of the payload
+/// Leaves `damaged_entry` at the entry that could not be read in full
+fn decode_entries<R: Read>(payload: &mut R, report: &mut ValidationReport) -> Result<()> {
+    let mut tar_archive = tar::Archive::new(payload);
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let size = entry.size();
+        let read = match io::copy(&mut entry, &mut io::sink()) {
+            Ok(read) => read,
+            Err(e) => {
+                report.damaged_entry = Some(path);
+                return Err(e.into());
+            }
+        };
+        if read < size {
+            report.damaged_entry = Some(path);
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        report.entries += 1;
+    }
+    Ok(())
+}
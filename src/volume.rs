@@ -0,0 +1,220 @@
+//! Multi-volume archives
+//!
+//! A split archive is an ordinary .pjz byte stream cut into fixed-size parts named
+//! `out.pjz.001`, `out.pjz.002`, ... Reading functions join the parts transparently when
+//! given either the base name (`out.pjz`) or the first part (`out.pjz.001`).
+//!
+//! Volumes are written under temporary names and only renamed into place once the whole
+//! archive is written, the first volume last, so an interrupted pack leaves no partial set.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::Result;
+use crate::revisions::restore_header;
+use crate::sfx::read_stub;
+use crate::temp::{sync_parent_dir, temp_file_for, TempPath};
+
+/// Path of the `index`-th volume (1-based) of a split archive
+pub(crate) fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(format!(".{index:03}"));
+    PathBuf::from(path)
+}
+
+/// Path of the archive a volume belongs to (`out.pjz.001` gives `out.pjz`)
+/// Paths that are not a first volume are returned unchanged
+pub(crate) fn base_path(path: &Path) -> PathBuf {
+    if path.extension().is_some_and(|ext| ext == "001") {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Path of the file holding the end of an archive: the archive itself, or the last
+/// volume if it was split (resolved like [`open_input`])
+pub(crate) fn last_volume(path: &Path) -> PathBuf {
+    let base = if path.extension().is_some_and(|ext| ext == "001") {
+        base_path(path)
+    } else if !path.exists() && volume_path(path, 1).is_file() {
+        path.to_path_buf()
+    } else {
+        return path.to_path_buf();
+    };
+    let mut index = 1;
+    while volume_path(&base, index + 1).is_file() {
+        index += 1;
+    }
+    volume_path(&base, index)
+}
+
+/// Files an archive is stored in, in order: the archive itself, or its volumes if it
+/// was split (resolved like [`open_input`])
+pub(crate) fn volume_files(path: &Path) -> Vec<PathBuf> {
+    let base = if path.extension().is_some_and(|ext| ext == "001") {
+        base_path(path)
+    } else if !path.exists() && volume_path(path, 1).is_file() {
+        path.to_path_buf()
+    } else {
+        return vec![path.to_path_buf()];
+    };
+    (1..)
+        .map(|index| volume_path(&base, index))
+        .take_while(|volume| volume.is_file())
+        .collect()
+}
+
+/// Open an archive for reading, joining its volumes if it was split
+/// An archive whose metadata is only in a trailer frame reads as if the metadata were at
+/// its start (see [`restore_header`]); use [`open_raw_input`] for the bytes of the file
+pub(crate) fn open_input(path: &Path) -> Result<Box<dyn Read + Send>> {
+    restore_header(path, open_raw_input(path)?)
+}
+
+/// Open an archive for reading as it is on disk, joining its volumes if it was split
+/// `out.pjz.001` and a missing `out.pjz` with an `out.pjz.001` next to it both resolve
+/// to the joined volumes; anything else is opened as a plain file. The script of a
+/// self-extracting archive is skipped
+pub(crate) fn open_raw_input(path: &Path) -> Result<Box<dyn Read + Send>> {
+    let mut reader: Box<dyn Read + Send> = if path.extension().is_some_and(|ext| ext == "001") {
+        Box::new(VolumeReader::open(&base_path(path))?)
+    } else if !path.exists() && volume_path(path, 1).is_file() {
+        Box::new(VolumeReader::open(path)?)
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let (_, head) = read_stub(&mut reader)?;
+    Ok(Box::new(io::Cursor::new(head).chain(reader)))
+}
+
+/// Writer spreading its output over volumes of at most `volume_size` bytes
+pub(crate) struct VolumeWriter {
+    base: PathBuf,
+    volume_size: u64,
+    /// Number of the current volume (0 before the first byte is written)
+    index: usize,
+    /// Bytes written to the current volume
+    written: u64,
+    current: Option<File>,
+    /// Volumes written so far, with their final paths
+    pending: Vec<(TempPath, PathBuf)>,
+    /// Flush every volume and the directory to disk when finishing
+    sync: bool,
+}
+
+impl VolumeWriter {
+    pub(crate) fn new(base: &Path, volume_size: u64) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            volume_size,
+            index: 0,
+            written: 0,
+            current: None,
+            pending: Vec::new(),
+            sync: false,
+        }
+    }
+
+    /// Flush each volume to disk once written, and the directory after the renames
+    pub(crate) fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Flush the last volume, move every volume into place and remove leftover volumes
+    /// of an earlier, longer archive with the same name, so they are not joined onto this one,
+    /// and an earlier unsplit archive under the base name, which readers would open instead
+    pub(crate) fn finish(mut self) -> Result<()> {
+        if let Some(mut file) = self.current.take() {
+            file.flush()?;
+            if self.sync {
+                file.sync_all()?;
+            }
+        }
+        // Readers start from the first volume, so it only appears once the rest is there
+        while let Some((temp, target)) = self.pending.pop() {
+            temp.persist(&target)?;
+        }
+        let mut stale = self.index + 1;
+        while volume_path(&self.base, stale).is_file() {
+            fs::remove_file(volume_path(&self.base, stale))?;
+            stale += 1;
+        }
+        if self.base.is_file() {
+            fs::remove_file(&self.base)?;
+        }
+        if self.sync {
+            sync_parent_dir(&self.base)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.current.is_none() || self.written == self.volume_size {
+            self.index += 1;
+            self.written = 0;
+            if let Some(file) = self.current.take().filter(|_| self.sync) {
+                file.sync_all()?;
+            }
+            let target = volume_path(&self.base, self.index);
+            let (file, temp) = temp_file_for(&target)?;
+            self.current = Some(file);
+            self.pending.push((temp, target));
+        }
+        let room = (self.volume_size - self.written).min(buf.len() as u64) as usize;
+        let file = self.current.as_mut().expect("volume is open");
+        let n = file.write(&buf[..room])?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reader joining `base.001`, `base.002`, ... until the first missing volume
+struct VolumeReader {
+    base: PathBuf,
+    index: usize,
+    current: File,
+}
+
+impl VolumeReader {
+    fn open(base: &Path) -> Result<Self> {
+        Ok(Self {
+            base: base.to_path_buf(),
+            index: 1,
+            current: File::open(volume_path(base, 1))?,
+        })
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(out)?;
+            if n > 0 || out.is_empty() {
+                return Ok(n);
+            }
+            match File::open(volume_path(&self.base, self.index + 1)) {
+                Ok(next) => {
+                    self.index += 1;
+                    self.current = next;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
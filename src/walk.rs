@@ -0,0 +1,590 @@
+//! Source directory traversal for packing
+//!
+//! Every pack of a local directory walks it here, so the options limiting what is swept
+//! into the archive (see [`WalkOptions`]) apply alike to full and incremental packs.
+//! Symlinks are followed; entries are stored parents first, in the [`EntryOrder`] chosen.
+//! Files linked more than once are stored once, the other paths as tar hard links to it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::checkpoint::Checkpoint;
+use crate::chunking::append_chunked;
+use crate::errors::{ProjzstError, Result};
+use crate::grep::glob_matches;
+use crate::incremental::IncrementalPlan;
+use crate::mtime::pax_mtime;
+use crate::payload::PayloadEncoder;
+use crate::report::{PackReport, SkippedFile, SpecialEntry, SpecialKind};
+use crate::storage::normalize_path;
+use crate::string_utils::IntoOpStr;
+use crate::tar_format::{append_entry, TarFormat};
+
+/// Names of version control metadata left out by [`WalkOptions::exclude_vcs`]
+const VCS_NAMES: &[&str] = &[".git", ".hg", ".svn", ".bzr", "CVS", "_darcs"];
+
+/// What to do with a file larger than [`Packer::max_file_size`](crate::Packer::max_file_size)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OversizePolicy {
+    /// Leave the file out and list it in [`PackReport::oversized`] (default)
+    #[default]
+    Skip,
+    /// Fail with [`ProjzstError::FileTooLarge`] before anything is written
+    Error,
+}
+
+impl OversizePolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(OversizePolicy::Skip),
+            "error" => Ok(OversizePolicy::Error),
+            _ => Err(ProjzstError::InvalidOversizePolicy(s.to_string())),
+        }
+    }
+}
+
+/// What to do with device nodes, FIFOs and sockets when packing or unpacking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SpecialPolicy {
+    /// Leave the entry out and list it (default)
+    #[default]
+    Skip,
+    /// Store or recreate the entry; sockets, which tar cannot store, are still left out
+    Include,
+    /// Fail with [`ProjzstError::SpecialEntry`]
+    Error,
+}
+
+impl SpecialPolicy {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(SpecialPolicy::Skip),
+            "include" => Ok(SpecialPolicy::Include),
+            "error" => Ok(SpecialPolicy::Error),
+            _ => Err(ProjzstError::InvalidSpecialPolicy(s.to_string())),
+        }
+    }
+
+    /// Internal helper: whether a special entry is kept, failing under
+    /// [`SpecialPolicy::Error`]
+    pub(crate) fn keeps(self, path: &str, kind: SpecialKind) -> Result<bool> {
+        match self {
+            SpecialPolicy::Skip => Ok(false),
+            SpecialPolicy::Include => Ok(kind != SpecialKind::Socket),
+            SpecialPolicy::Error => Err(ProjzstError::SpecialEntry {
+                path: path.to_string(),
+                kind: kind.name().to_string(),
+            }),
+        }
+    }
+}
+
+/// Order in which the entries of a source directory are stored
+/// Grouping similar files lets zstd find more matches between them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum EntryOrder {
+    /// Sorted by path, parents first (default)
+    #[default]
+    Path,
+    /// Directories first, then files grouped by extension
+    Extension,
+    /// Directories first, then files from smallest to largest
+    Size,
+}
+
+impl EntryOrder {
+    /// Create from string parameter
+    pub fn from_str_tmp<I: IntoOpStr>(s: I) -> Result<Self> {
+        let a = s.into_op_str().unwrap_or_default();
+        let s: &str = a.as_ref();
+        match s.to_lowercase().as_str() {
+            "path" => Ok(EntryOrder::Path),
+            "extension" | "ext" => Ok(EntryOrder::Extension),
+            "size" => Ok(EntryOrder::Size),
+            _ => Err(ProjzstError::InvalidEntryOrder(s.to_string())),
+        }
+    }
+}
+
+/// Limits on what is packed from a source directory
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WalkOptions {
+    /// Do not descend into directories on another file system than the source's
+    pub one_file_system: bool,
+    /// Deepest level stored, `1` being the entries directly inside the source
+    pub max_depth: Option<usize>,
+    /// Leave out version control directories such as `.git` and everything inside them
+    pub exclude_vcs: bool,
+    /// Leave out files and directories whose name starts with `.`
+    pub exclude_hidden: bool,
+    /// Globs (`*`, `?`) of the names of files and directories left out
+    pub exclude: Vec<String>,
+    /// Largest file stored, in bytes
+    pub max_file_size: Option<u64>,
+    /// Handling of files larger than `max_file_size`
+    pub oversize_policy: OversizePolicy,
+    /// Order of the entries in the archive
+    pub order: EntryOrder,
+    /// Store every path of a hard-linked file with its own copy of the content
+    pub hard_dereference: bool,
+    /// Handling of device nodes, FIFOs and sockets
+    pub special: SpecialPolicy,
+    /// Header format of the entries
+    pub format: TarFormat,
+    /// Store modification times to the nanosecond in PAX records
+    pub subsecond_mtimes: bool,
+    /// Rewrite of file content before it is stored
+    pub rewrite: Option<ContentRewrite>,
+    /// Store file content as content-defined chunks, each distinct one once
+    pub chunked: bool,
+}
+
+/// Caller-provided rewrite of file content, `None` storing the file as it is
+pub(crate) type RewriteFn = dyn Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync;
+
+/// A [`RewriteFn`] shared between the clones of a packer
+#[derive(Clone)]
+pub(crate) struct ContentRewrite(pub Arc<RewriteFn>);
+
+impl fmt::Debug for ContentRewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ContentRewrite")
+    }
+}
+
+/// First archive path of each hard-linked file stored so far, by device and inode
+pub(crate) type HardLinks = HashMap<(u64, u64), String>;
+
+/// What the entries stored so far leave for later ones to refer to
+#[derive(Debug, Default)]
+pub(crate) struct Appended {
+    /// Hard-linked files stored so far
+    pub links: HardLinks,
+    /// Digests of the chunks stored so far (chunked packs)
+    pub chunks: HashSet<String>,
+    /// Progress of a resumable pack
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl Appended {
+    /// Internal helper: whether `path` was stored before the interruption being resumed
+    fn is_done(&self, path: &str) -> bool {
+        self.checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.is_done(path))
+    }
+
+    /// Internal helper: record an entry in the checkpoint, if any, with the size of
+    /// regular files
+    fn stored(&mut self, path: &str, size: Option<u64>) {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.stored(path, size);
+        }
+    }
+}
+
+/// Entries found below a source, parents first in the chosen [`EntryOrder`]
+pub(crate) struct Walk {
+    /// Archive path, source path and (symlink-following) metadata of each entry
+    pub entries: Vec<(String, PathBuf, fs::Metadata)>,
+    /// Files left out for exceeding the size limit
+    pub oversized: Vec<SkippedFile>,
+    /// Special entries left out
+    pub special: Vec<SpecialEntry>,
+}
+
+impl WalkOptions {
+    /// Internal helper: the PAX `mtime` value stored for an entry, if any
+    fn mtime_record(&self, info: &fs::Metadata) -> Option<String> {
+        self.subsecond_mtimes.then(|| pax_mtime(info)).flatten()
+    }
+
+    /// Internal helper: whether an entry named `name` is left out of the archive
+    pub(crate) fn excludes(&self, name: &str) -> bool {
+        (self.exclude_vcs && VCS_NAMES.contains(&name))
+            || (self.exclude_hidden && name.starts_with('.'))
+            || (!self.exclude.is_empty() && {
+                let name: Vec<char> = name.chars().collect();
+                self.exclude
+                    .iter()
+                    .any(|glob| glob_matches(&glob.chars().collect::<Vec<_>>(), &name))
+            })
+    }
+
+    /// Internal helper: add an entry to `walk`, applying the file size limit and the
+    /// special entry policy
+    fn add_entry(
+        &self,
+        walk: &mut Walk,
+        path: String,
+        source: PathBuf,
+        info: fs::Metadata,
+    ) -> Result<()> {
+        if let Some(kind) = special_kind(&info) {
+            if !self.special.keeps(&path, kind)? {
+                walk.special.push(SpecialEntry { path, kind });
+                return Ok(());
+            }
+        }
+        if info.is_file() {
+            if let Some(skipped) = self.oversized(&path, info.len())? {
+                walk.oversized.push(skipped);
+                return Ok(());
+            }
+        }
+        walk.entries.push((path, source, info));
+        Ok(())
+    }
+
+    /// Internal helper: the file `path` of `size` bytes to list as left out if it is over
+    /// the file size limit, failing instead with [`OversizePolicy::Error`]
+    pub(crate) fn oversized(&self, path: &str, size: u64) -> Result<Option<SkippedFile>> {
+        match self.max_file_size {
+            Some(limit) if size > limit => match self.oversize_policy {
+                OversizePolicy::Skip => Ok(Some(SkippedFile {
+                    path: path.to_string(),
+                    size,
+                })),
+                OversizePolicy::Error => Err(ProjzstError::FileTooLarge {
+                    path: path.to_string(),
+                    size,
+                    limit,
+                }),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Internal helper: whether a file listed by a storage backend at `relative`, a
+    /// `/`-separated path below the source, passes the exclusions and depth limit a walk
+    /// of a local directory applies
+    pub(crate) fn keeps_listed(&self, relative: &str) -> bool {
+        let names: Vec<&str> = relative.split('/').collect();
+        self.max_depth.is_none_or(|max| names.len() <= max)
+            && !names.iter().any(|name| self.excludes(name))
+    }
+}
+
+/// Internal helper: every path below `source_dir` with its (symlink-following) metadata,
+/// as `/`-separated relative paths under `prefix`, parents first in the chosen order
+/// A file source yields itself, named `prefix`
+pub(crate) fn walk_source(
+    source_dir: &Path,
+    prefix: Option<&str>,
+    options: &WalkOptions,
+) -> Result<Walk> {
+    let info = fs::metadata(source_dir)?;
+    let mut walk = Walk {
+        entries: Vec::new(),
+        oversized: Vec::new(),
+        special: Vec::new(),
+    };
+    if !info.is_dir() {
+        let name = prefix.unwrap_or_default().to_string();
+        options.add_entry(&mut walk, name, source_dir.to_path_buf(), info)?;
+        return Ok(walk);
+    }
+    let root_device = device(&info);
+    let mut found = Vec::new();
+    let mut pending = vec![(source_dir.to_path_buf(), 1)];
+    while let Some((dir, depth)) = pending.pop() {
+        if options.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+        let items = fs::read_dir(&dir).map_err(|e| ProjzstError::from(e).packing(&dir))?;
+        for item in items {
+            let item = item.map_err(|e| ProjzstError::from(e).packing(&dir))?;
+            if options.excludes(&item.file_name().to_string_lossy()) {
+                log::debug!("excluded {}", item.path().display());
+                continue;
+            }
+            let path = item.path();
+            let info = fs::metadata(&path).map_err(|e| ProjzstError::from(e).packing(&path))?;
+            let Some(relative) = path.strip_prefix(source_dir).ok().and_then(normalize_path) else {
+                continue;
+            };
+            // A mount point is stored, but not what is mounted on it
+            if info.is_dir() && !(options.one_file_system && device(&info) != root_device) {
+                pending.push((path.clone(), depth + 1));
+            }
+            let relative = match prefix {
+                Some(prefix) => format!("{prefix}/{relative}"),
+                None => relative,
+            };
+            found.push((relative, path, info));
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    match options.order {
+        EntryOrder::Path => {}
+        // Stable sorts keep directories, and files with equal keys, in path order
+        EntryOrder::Extension => found.sort_by_key(|(relative, _, info)| {
+            let file = !info.is_dir();
+            let extension = Path::new(relative)
+                .extension()
+                .filter(|_| file)
+                .map(|ext| ext.to_os_string());
+            (file, extension)
+        }),
+        EntryOrder::Size => found.sort_by_key(|(_, _, info)| {
+            let file = !info.is_dir();
+            (file, if file { info.len() } else { 0 })
+        }),
+    }
+    for (relative, path, info) in found {
+        options.add_entry(&mut walk, relative, path, info)?;
+    }
+    Ok(walk)
+}
+
+/// Append a source directory or file to the tar stream under `prefix` when given,
+/// leaving out the files `plan` finds unchanged (incremental packs)
+/// Files already stored under another path, as recorded in `links`, become hard links to
+/// it; the files stored and left out are added to `report`
+pub(crate) fn append_source<W: io::Write>(
+    tar_builder: &mut tar::Builder<&mut PayloadEncoder<W>>,
+    source_dir: &Path,
+    prefix: Option<&str>,
+    options: &WalkOptions,
+    plan: Option<&IncrementalPlan>,
+    appended: &mut Appended,
+    report: &mut PackReport,
+) -> Result<()> {
+    let format = options.format;
+    if let Some(prefix) = prefix.filter(|prefix| source_dir.is_dir() && !appended.is_done(prefix)) {
+        let info = fs::metadata(source_dir)?;
+        let mtime = options.mtime_record(&info);
+        let header = entry_header(format, &info);
+        append_entry(
+            tar_builder,
+            format,
+            header,
+            prefix,
+            None,
+            mtime.as_deref(),
+            io::empty(),
+        )?;
+        appended.stored(prefix, None);
+    }
+    let walk = walk_source(source_dir, prefix, options)?;
+    for (relative, path, info) in walk.entries {
+        if appended.is_done(&relative) {
+            continue;
+        }
+        let mtime = options.mtime_record(&info);
+        let mtime = mtime.as_deref();
+        let mut header = entry_header(format, &info);
+        if info.is_dir() {
+            log::debug!("{relative}/");
+            append_entry(
+                tar_builder,
+                format,
+                header,
+                &relative,
+                None,
+                mtime,
+                io::empty(),
+            )?;
+            appended.stored(&relative, None);
+        } else if let Some(kind) = special_kind(&info) {
+            log::debug!("{relative} ({})", kind.name());
+            // Kept by the policy; there is no content to read
+            set_special(&mut header, &info, kind)?;
+            append_entry(
+                tar_builder,
+                format,
+                header,
+                &relative,
+                None,
+                mtime,
+                io::empty(),
+            )?;
+            appended.stored(&relative, None);
+        } else if !plan.map_or(Ok(false), |plan| plan.is_unchanged(&relative))? {
+            let key = hard_link_key(&info).filter(|_| !options.hard_dereference);
+            if let Some(target) = key.and_then(|key| appended.links.get(&key)) {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                append_entry(
+                    tar_builder,
+                    format,
+                    header,
+                    &relative,
+                    Some(target),
+                    mtime,
+                    io::empty(),
+                )?;
+                report.hard_links += 1;
+                log::debug!("{relative} (hard link to {target})");
+                appended.stored(&relative, None);
+                continue;
+            }
+            let encoder = tar_builder.get_mut();
+            encoder.begin_file(&path, &relative)?;
+            if let Some(checkpoint) = &mut appended.checkpoint {
+                checkpoint.frame_started(encoder, &relative)?;
+            }
+            let mut file =
+                fs::File::open(&path).map_err(|e| ProjzstError::from(e).packing(&path))?;
+            let mut content = None;
+            if let Some(ContentRewrite(rewrite)) = &options.rewrite {
+                let mut read = Vec::new();
+                file.read_to_end(&mut read)
+                    .map_err(|e| ProjzstError::from(e).packing(&path))?;
+                if let Some(rewritten) = rewrite(&relative, &read) {
+                    log::debug!("{relative} rewritten");
+                    read = rewritten;
+                }
+                header.set_size(read.len() as u64);
+                content = Some(read);
+            }
+            let size = content
+                .as_ref()
+                .map_or(info.len(), |read| read.len() as u64);
+            let data: Box<dyn Read + '_> = match &content {
+                Some(read) => Box::new(read.as_slice()),
+                None => Box::new(file),
+            };
+            if options.chunked {
+                report.deduplicated_size += append_chunked(
+                    tar_builder,
+                    format,
+                    header,
+                    &relative,
+                    mtime,
+                    data,
+                    &mut appended.chunks,
+                )
+                .map_err(|e| e.packing(&path))?;
+            } else {
+                append_entry(tar_builder, format, header, &relative, None, mtime, data)
+                    .map_err(|e| e.packing(&path))?;
+            }
+            report.add_file(&relative, size);
+            appended.stored(&relative, Some(size));
+            log::info!("{relative} ({size} bytes)");
+            if let Some(key) = key {
+                appended.links.insert(key, relative);
+            }
+        } else {
+            log::debug!("{relative} unchanged, left to the base archive");
+        }
+    }
+    report.skipped(walk.oversized, walk.special);
+    Ok(())
+}
+
+/// Internal helper: store a file generated in memory at `path`, readable by everyone and
+/// dated `mtime` (Unix seconds)
+pub(crate) fn append_generated<W: io::Write>(
+    tar_builder: &mut tar::Builder<&mut PayloadEncoder<W>>,
+    format: TarFormat,
+    path: &str,
+    content: &[u8],
+    mtime: u64,
+    report: &mut PackReport,
+) -> Result<()> {
+    let mut header = format.header();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_size(content.len() as u64);
+    append_entry(tar_builder, format, header, path, None, None, content)?;
+    report.add_file(path, content.len() as u64);
+    log::info!("{path} ({} bytes, generated)", content.len());
+    Ok(())
+}
+
+/// Internal helper: the device holding a file, compared for one-file-system traversal
+#[cfg(unix)]
+fn device(info: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    info.dev()
+}
+
+/// Internal helper: the device holding a file (not tracked on this platform)
+#[cfg(not(unix))]
+fn device(_info: &fs::Metadata) -> u64 {
+    0
+}
+
+/// Internal helper: the kind of a device node, FIFO or socket, `None` for anything else
+#[cfg(unix)]
+fn special_kind(info: &fs::Metadata) -> Option<SpecialKind> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = info.file_type();
+    if file_type.is_block_device() {
+        Some(SpecialKind::BlockDevice)
+    } else if file_type.is_char_device() {
+        Some(SpecialKind::CharDevice)
+    } else if file_type.is_fifo() {
+        Some(SpecialKind::Fifo)
+    } else if file_type.is_socket() {
+        Some(SpecialKind::Socket)
+    } else {
+        None
+    }
+}
+
+/// Internal helper: the kind of a special entry (none on this platform)
+#[cfg(not(unix))]
+fn special_kind(_info: &fs::Metadata) -> Option<SpecialKind> {
+    None
+}
+
+/// Internal helper: header of an entry in `format`, with the metadata of its source
+fn entry_header(format: TarFormat, info: &fs::Metadata) -> tar::Header {
+    let mut header = format.header();
+    header.set_metadata(info);
+    header
+}
+
+/// Internal helper: turn a header into that of a device node or FIFO, with its device
+/// numbers (`tar::Builder::append_path_with_name` would name the entry after its source)
+#[cfg(unix)]
+fn set_special(header: &mut tar::Header, info: &fs::Metadata, kind: SpecialKind) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    header.set_size(0);
+    header.set_entry_type(match kind {
+        SpecialKind::BlockDevice => tar::EntryType::Block,
+        SpecialKind::CharDevice => tar::EntryType::Char,
+        _ => tar::EntryType::Fifo,
+    });
+    let device = info.rdev();
+    header.set_device_major(((device >> 32) & 0xffff_f000 | (device >> 8) & 0x0fff) as u32)?;
+    header.set_device_minor(((device >> 12) & 0xffff_ff00 | device & 0xff) as u32)?;
+    Ok(())
+}
+
+/// Internal helper: turn a header into that of a special entry (never met on this platform)
+#[cfg(not(unix))]
+fn set_special(_header: &mut tar::Header, _info: &fs::Metadata, _kind: SpecialKind) -> Result<()> {
+    unreachable!("special entries are only detected on unix")
+}
+
+/// Internal helper: device and inode of a file with more than one hard link
+#[cfg(unix)]
+fn hard_link_key(info: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (info.nlink() > 1).then(|| (info.dev(), info.ino()))
+}
+
+/// Internal helper: device and inode of a file with more than one hard link (not
+/// tracked on this platform)
+#[cfg(not(unix))]
+fn hard_link_key(_info: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
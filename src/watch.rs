@@ -0,0 +1,411 @@
+//! Waiting for changes to the sources of a pack
+//!
+//! [`SourceWatcher`] blocks until files below the sources of a pack change and the tree
+//! has settled, so development loops can repack right after every burst of edits:
+//! `projzst pack --watch` packs once, then again after each one. On Linux, changes come
+//! from inotify, with a watch on every directory (new directories get one as they
+//! appear); elsewhere, or when inotify cannot watch every directory, the tree is scanned
+//! for changed sizes and modification times twice a second. Directories the pack leaves
+//! out by name, such as `.git` with [`Packer::exclude_vcs`], are not watched.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::builder::Packer;
+use crate::errors::Result;
+use crate::walk::WalkOptions;
+
+/// Interval between two scans of the polling fallback
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Waits for changes below the sources of a pack, see [`Packer::watcher`]
+#[derive(Debug)]
+pub struct SourceWatcher {
+    backend: Backend,
+    /// Files whose changes do not count, with their temporary and partial siblings
+    ignored: Vec<PathBuf>,
+}
+
+/// Where changes come from
+#[derive(Debug)]
+enum Backend {
+    /// Kernel notifications
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    /// Scans of the tree
+    Polling(Polling),
+}
+
+impl Packer {
+    /// Watch the sources a pack of `source_dir` would read, together with those added with
+    /// [`Packer::source`], for changes
+    /// Directories left out by [`Packer::exclude_vcs`] and [`Packer::exclude_hidden`] are
+    /// not watched
+    pub fn watcher<P: AsRef<Path>>(&self, source_dir: P) -> Result<SourceWatcher> {
+        let roots: Vec<PathBuf> = self
+            .source_roots(source_dir.as_ref())?
+            .into_iter()
+            .map(|root| root.path)
+            .collect();
+        #[cfg(target_os = "linux")]
+        match inotify::Inotify::new(&roots, &self.walk) {
+            Ok(inotify) => {
+                return Ok(SourceWatcher {
+                    backend: Backend::Inotify(inotify),
+                    ignored: Vec::new(),
+                })
+            }
+            Err(e) => log::debug!("inotify unavailable, scanning for changes: {e}"),
+        }
+        Ok(SourceWatcher {
+            backend: Backend::Polling(Polling::new(roots, self.walk.clone())),
+            ignored: Vec::new(),
+        })
+    }
+}
+
+impl SourceWatcher {
+    /// Leave changes to `path` out, along with those to the temporary and partial files
+    /// written next to it while packing; for an output file inside the sources
+    pub fn ignore<P: AsRef<Path>>(&mut self, path: P) {
+        self.ignored.push(path.as_ref().to_path_buf());
+    }
+
+    /// Block until something below the sources changes, then until `debounce` passes
+    /// without further changes
+    /// Returns the paths that changed, sorted
+    pub fn wait(&mut self, debounce: Duration) -> Result<Vec<PathBuf>> {
+        let mut changed = BTreeSet::new();
+        let mut timeout = None;
+        loop {
+            let Some(paths) = self.backend.changes(timeout)? else {
+                if !changed.is_empty() {
+                    return Ok(changed.into_iter().collect());
+                }
+                continue;
+            };
+            for path in paths {
+                if !self.is_ignored(&path) {
+                    changed.insert(path);
+                    timeout = Some(debounce);
+                }
+            }
+        }
+    }
+
+    /// Internal helper: whether a change to `path` does not count
+    fn is_ignored(&self, path: &Path) -> bool {
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        self.ignored.iter().any(|ignored| {
+            let (Some(name), Some(ignored_name)) = (&name, ignored.file_name()) else {
+                return path == ignored;
+            };
+            let ignored_name = ignored_name.to_string_lossy();
+            path.parent() == ignored.parent()
+                && (*name == ignored_name
+                    || *name == format!("{ignored_name}.part")
+                    || (name.starts_with(&format!(".{ignored_name}.")) && name.ends_with(".tmp")))
+        })
+    }
+}
+
+impl Backend {
+    /// Internal helper: the paths changed within `timeout` (forever when `None`), `None`
+    /// when it passed without a change
+    fn changes(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<PathBuf>>> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Backend::Inotify(inotify) => inotify.changes(timeout),
+            Backend::Polling(polling) => polling.changes(timeout),
+        }
+    }
+}
+
+/// Size, modification time and type of an entry, as compared between scans
+type Stamp = (u64, Option<SystemTime>, bool);
+
+/// Change detection by scanning the tree
+#[derive(Debug)]
+struct Polling {
+    roots: Vec<PathBuf>,
+    options: WalkOptions,
+    snapshot: BTreeMap<PathBuf, Stamp>,
+}
+
+impl Polling {
+    /// Internal helper: take the first scan of the roots
+    fn new(roots: Vec<PathBuf>, options: WalkOptions) -> Self {
+        let mut polling = Self {
+            roots,
+            options,
+            snapshot: BTreeMap::new(),
+        };
+        polling.snapshot = polling.scan();
+        polling
+    }
+
+    /// Internal helper: scan again until something differs or `timeout` passes
+    fn changes(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<PathBuf>>> {
+        let start = Instant::now();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let snapshot = self.scan();
+            let mut changed: BTreeSet<PathBuf> = self
+                .snapshot
+                .iter()
+                .filter(|(path, stamp)| snapshot.get(*path) != Some(stamp))
+                .map(|(path, _)| path.clone())
+                .collect();
+            changed.extend(
+                snapshot
+                    .keys()
+                    .filter(|path| !self.snapshot.contains_key(*path))
+                    .cloned(),
+            );
+            self.snapshot = snapshot;
+            if !changed.is_empty() {
+                return Ok(Some(changed.into_iter().collect()));
+            }
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Internal helper: stamps of every entry below the roots; entries that vanish while
+    /// being scanned are left out
+    fn scan(&self) -> BTreeMap<PathBuf, Stamp> {
+        let mut snapshot = BTreeMap::new();
+        let mut pending = self.roots.clone();
+        while let Some(path) = pending.pop() {
+            let Ok(info) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            let is_dir = info.is_dir();
+            snapshot.insert(path.clone(), (info.len(), info.modified().ok(), is_dir));
+            if !is_dir {
+                continue;
+            }
+            let Ok(items) = fs::read_dir(&path) else {
+                continue;
+            };
+            for item in items.flatten() {
+                if !self.options.excludes(&item.file_name().to_string_lossy()) {
+                    pending.push(item.path());
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+/// Change detection with inotify (Linux)
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::{BTreeSet, HashMap};
+    use std::ffi::{CString, OsStr, OsString};
+    use std::fs;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::errors::Result;
+    use crate::walk::WalkOptions;
+
+    /// Events that mean content or names changed
+    const EVENT_MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF;
+
+    /// Size of `struct inotify_event` before the name
+    const EVENT_HEADER_SIZE: usize = 16;
+
+    /// Size of the event buffer
+    const BUFFER_SIZE: usize = 64 * 1024;
+
+    /// An inotify instance watching every directory of the sources
+    #[derive(Debug)]
+    pub(super) struct Inotify {
+        fd: OwnedFd,
+        options: WalkOptions,
+        /// Watched directory of each watch, with the only name that counts for file roots
+        watches: HashMap<i32, (PathBuf, Option<OsString>)>,
+    }
+
+    impl Inotify {
+        /// Set up watches on the roots and every directory below them
+        pub(super) fn new(roots: &[PathBuf], options: &WalkOptions) -> io::Result<Self> {
+            // SAFETY: plain system call without pointers
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut inotify = Self {
+                // SAFETY: the descriptor was just opened and is owned by nothing else
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                options: options.clone(),
+                watches: HashMap::new(),
+            };
+            for root in roots {
+                if root.is_dir() {
+                    inotify.watch_tree(root)?;
+                } else {
+                    // Files are watched through their directory, which sees them replaced
+                    let parent = match root.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                    inotify.watch(parent, root.file_name().map(OsStr::to_os_string))?;
+                }
+            }
+            Ok(inotify)
+        }
+
+        /// Wait up to `timeout` (forever when `None`) for events, returning the paths they
+        /// name; `None` when the timeout passed
+        pub(super) fn changes(
+            &mut self,
+            timeout: Option<Duration>,
+        ) -> Result<Option<Vec<PathBuf>>> {
+            let mut poll = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = timeout.map_or(-1, |timeout| {
+                i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+            });
+            // SAFETY: `poll` points to one valid pollfd for the duration of the call
+            match unsafe { libc::poll(&mut poll, 1, timeout_ms) } {
+                0 => return Ok(None),
+                ready if ready < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        return Ok(Some(Vec::new()));
+                    }
+                    return Err(e.into());
+                }
+                _ => {}
+            }
+
+            let mut buffer = vec![0u8; BUFFER_SIZE];
+            // SAFETY: the buffer is valid for writes of its whole length
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            if read < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    return Ok(Some(Vec::new()));
+                }
+                return Err(e.into());
+            }
+
+            let mut changed = BTreeSet::new();
+            let mut offset = 0;
+            while offset + EVENT_HEADER_SIZE <= read as usize {
+                let field = |at: usize| {
+                    let start = offset + at;
+                    u32::from_ne_bytes(buffer[start..start + 4].try_into().expect("4 bytes"))
+                };
+                let wd = field(0) as i32;
+                let mask = field(4);
+                let len = field(12) as usize;
+                let name_bytes =
+                    &buffer[offset + EVENT_HEADER_SIZE..offset + EVENT_HEADER_SIZE + len];
+                offset += EVENT_HEADER_SIZE + len;
+                let name =
+                    OsStr::from_bytes(name_bytes.split(|&b| b == 0).next().unwrap_or_default());
+
+                if mask & libc::IN_IGNORED != 0 {
+                    self.watches.remove(&wd);
+                    continue;
+                }
+                if mask & libc::IN_Q_OVERFLOW != 0 {
+                    // Events were lost: every watched directory may have changed
+                    changed.extend(self.watches.values().map(|(dir, _)| dir.clone()));
+                    continue;
+                }
+                let Some((dir, only)) = self.watches.get(&wd) else {
+                    continue;
+                };
+                if only.as_deref().is_some_and(|only| only != name) {
+                    continue;
+                }
+                if self.options.excludes(&name.to_string_lossy()) {
+                    continue;
+                }
+                let path = if name.is_empty() {
+                    dir.clone()
+                } else {
+                    dir.join(name)
+                };
+                if mask & libc::IN_ISDIR != 0 && mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    // Files may already be inside; they show up in the next scan anyway
+                    self.watch_tree(&path)?;
+                }
+                changed.insert(path);
+            }
+            Ok(Some(changed.into_iter().collect()))
+        }
+
+        /// Internal helper: watch `dir` and every directory below it the pack would read
+        fn watch_tree(&mut self, dir: &Path) -> io::Result<()> {
+            let mut pending = vec![dir.to_path_buf()];
+            while let Some(dir) = pending.pop() {
+                match self.watch(&dir, None) {
+                    Ok(true) => {}
+                    // Reached again through a symlink
+                    Ok(false) => continue,
+                    // Gone since it was seen
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                }
+                let Ok(items) = fs::read_dir(&dir) else {
+                    continue;
+                };
+                for item in items.flatten() {
+                    // Symlinks are followed, as packing does
+                    let is_dir = fs::metadata(item.path()).is_ok_and(|info| info.is_dir());
+                    if is_dir && !self.options.excludes(&item.file_name().to_string_lossy()) {
+                        pending.push(item.path());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// Internal helper: add a watch on `dir`, counting only `only` when given
+        /// Returns `false` when the directory was watched already
+        fn watch(&mut self, dir: &Path, only: Option<OsString>) -> io::Result<bool> {
+            let path = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    self.fd.as_raw_fd(),
+                    path.as_ptr(),
+                    EVENT_MASK | libc::IN_ONLYDIR,
+                )
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(self.watches.insert(wd, (dir.to_path_buf(), only)).is_none())
+        }
+    }
+}
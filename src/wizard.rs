@@ -0,0 +1,153 @@
+//! Interactive packs of the command-line tool (`pack --interactive`)
+//!
+//! The metadata fields no flag, manifest or configuration file set are asked for on
+//! stderr, with defaults from the source directory name and `git config user.name`; the
+//! files the pack would store are then shown before anything is written. Answers are
+//! read a line at a time from stdin, an empty one taking the default in brackets.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use projzst::{Metadata, PackEstimate, ProjzstError};
+
+use crate::format_size;
+use crate::i18n::tr;
+
+/// Files listed in the preview, the others only counted
+const PREVIEW_FILES: usize = 20;
+
+/// Ask for the metadata fields left unset
+/// Fails when no package name is given and the directory has none to offer
+///
+/// # Arguments
+/// * `metadata` - Metadata from the flags, completed in place
+/// * `source` - Source directory, naming the package by default
+pub fn fill_metadata(metadata: &mut Metadata, source: &Path) -> Result<(), ProjzstError> {
+    let mut input = io::stdin().lock();
+    if metadata.name.is_none() {
+        let default = fs_name(source);
+        metadata.name = ask(&mut input, &tr!("wizard-name"), default.as_deref())?;
+        if metadata.name.is_none() {
+            return Err(ProjzstError::InvalidArgument(
+                "a package name is required".to_string(),
+            ));
+        }
+    }
+    if metadata.auth.is_none() {
+        let default = git_user(source);
+        metadata.auth = ask(&mut input, &tr!("wizard-author"), default.as_deref())?;
+    }
+    if metadata.ver.is_none() {
+        metadata.ver = ask(&mut input, &tr!("wizard-version"), None)?;
+    }
+    if metadata.fmt.is_none() {
+        metadata.fmt = ask(&mut input, &tr!("wizard-format"), None)?;
+    }
+    if metadata.fmt.is_some() && metadata.ed.is_none() {
+        metadata.ed = ask(&mut input, &tr!("wizard-edition"), None)?;
+    }
+    if metadata.desc.is_none() {
+        metadata.desc = ask(&mut input, &tr!("wizard-description"), None)?;
+    }
+    Ok(())
+}
+
+/// Ask for the output file, offering the name derived from the metadata
+///
+/// # Arguments
+/// * `default` - File written on an empty answer
+pub fn output(default: PathBuf) -> Result<PathBuf, ProjzstError> {
+    let default = default.display().to_string();
+    let answer = ask(
+        &mut io::stdin().lock(),
+        &tr!("wizard-output"),
+        Some(&default),
+    )?;
+    Ok(PathBuf::from(answer.unwrap_or(default)))
+}
+
+/// Show the files a pack would store and ask whether to write it; no answer at all
+/// (end of input) declines
+///
+/// # Arguments
+/// * `estimate` - Dry run of the pack
+/// * `output` - File the pack would write
+pub fn confirm(estimate: &PackEstimate, output: &Path) -> Result<bool, ProjzstError> {
+    let mut stderr = io::stderr().lock();
+    writeln!(
+        stderr,
+        "{}",
+        tr!(
+            "wizard-preview",
+            count = estimate.paths.len(),
+            size = format_size(estimate.report.original_size),
+            estimated = format_size(estimate.estimated_size)
+        )
+    )?;
+    for path in estimate.paths.iter().take(PREVIEW_FILES) {
+        writeln!(stderr, "  {path}")?;
+    }
+    if estimate.paths.len() > PREVIEW_FILES {
+        let more = estimate.paths.len() - PREVIEW_FILES;
+        writeln!(stderr, "  {}", tr!("wizard-more", count = more))?;
+    }
+    write!(
+        stderr,
+        "{} ",
+        tr!("wizard-confirm", path = output.display().to_string())
+    )?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        writeln!(stderr)?;
+        return Ok(false);
+    }
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+/// Internal helper: print a prompt and read the answer, the default when it is empty
+fn ask(
+    input: &mut impl BufRead,
+    label: &str,
+    default: Option<&str>,
+) -> Result<Option<String>, ProjzstError> {
+    let mut stderr = io::stderr().lock();
+    match default {
+        Some(default) => write!(stderr, "{label} [{default}]: ")?,
+        None => write!(stderr, "{label}: ")?,
+    }
+    stderr.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        writeln!(stderr)?;
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.map(str::to_string)
+    } else {
+        Some(answer.to_string())
+    })
+}
+
+/// Internal helper: the name of the source directory, resolved for `.`
+fn fs_name(source: &Path) -> Option<String> {
+    let source = source.canonicalize().ok()?;
+    Some(source.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Internal helper: `user.name` of the git configuration seen from `dir`
+fn git_user(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["config", "user.name"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let name = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!name.is_empty()).then_some(name)
+}